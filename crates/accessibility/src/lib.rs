@@ -0,0 +1,13 @@
+//! Accessibility tree.
+//!
+//! Builds the tree that assistive technology sees from a DOM tree and its
+//! layout: roles from implicit HTML semantics and ARIA, accessible names
+//! and descriptions, states and bounds. It is available as a Rust API and
+//! as a text dump.
+
+pub mod name;
+pub mod role;
+pub mod tree;
+
+pub use role::Role;
+pub use tree::{AccessibilityTree, AccessibleId, AccessibleNode, Checked, States};
@@ -0,0 +1,267 @@
+//! Accessible names and descriptions.
+//!
+//! A simplified form of the accessible name computation: `aria-labelledby`,
+//! then `aria-label`, then the host language (labels, `alt`, legends and
+//! captions), then content for roles that take their name from it, and
+//! finally `title` or `placeholder`.
+
+use crate::role::{element_role, Role};
+use dom::element::ElementData;
+use dom::forms;
+use dom::node::{NodeData, NodeId};
+use dom::tree::DomTree;
+use std::collections::HashSet;
+
+/// Computes names and descriptions, skipping content that is hidden.
+pub(crate) struct Names<'a> {
+    tree: &'a DomTree,
+    hidden: &'a dyn Fn(NodeId) -> bool,
+}
+
+impl<'a> Names<'a> {
+    pub(crate) fn new(tree: &'a DomTree, hidden: &'a dyn Fn(NodeId) -> bool) -> Self {
+        Self { tree, hidden }
+    }
+
+    /// The accessible name of `node`, which has `role`.
+    pub(crate) fn name(&self, node: NodeId, role: Role) -> String {
+        let mut visited = HashSet::new();
+        collapse(&self.text_alternative(node, Some(role), Step::Root, &mut visited))
+    }
+
+    /// The accessible description of `node`; `title` counts only when it
+    /// did not provide the name.
+    pub(crate) fn description(&self, node: NodeId, name: &str) -> String {
+        let Some(element) = self.tree.get_element(node) else {
+            return String::new();
+        };
+        if let Some(ids) = element.get_attribute("aria-describedby") {
+            let mut visited = HashSet::from([node]);
+            let description = collapse(&self.referenced(ids, &mut visited));
+            if !description.is_empty() {
+                return description;
+            }
+        }
+        let attribute = |name| {
+            element
+                .get_attribute(name)
+                .map(collapse)
+                .filter(|text| !text.is_empty())
+        };
+        attribute("aria-description")
+            .or_else(|| attribute("title").filter(|title| title != name))
+            .unwrap_or_default()
+    }
+
+    fn text_alternative(
+        &self,
+        node: NodeId,
+        role: Option<Role>,
+        step: Step,
+        visited: &mut HashSet<NodeId>,
+    ) -> String {
+        if !visited.insert(node) {
+            return String::new();
+        }
+        let element = match &self.tree.get(node).map(|n| &n.data) {
+            Some(NodeData::Text { content }) => return content.clone(),
+            Some(NodeData::Element(element)) => element,
+            _ => return String::new(),
+        };
+        if step == Step::Content && (self.hidden)(node) {
+            return String::new();
+        }
+        let role = role.unwrap_or_else(|| element_role(self.tree, node, element));
+
+        if step != Step::Referenced {
+            if let Some(ids) = element.get_attribute("aria-labelledby") {
+                let text = self.referenced(ids, visited);
+                if !text.trim().is_empty() {
+                    return text;
+                }
+            }
+        }
+        if let Some(label) = element
+            .get_attribute("aria-label")
+            .filter(|label| !label.trim().is_empty())
+        {
+            return label.to_string();
+        }
+        if step == Step::Content {
+            if let Some(value) = embedded_value(self.tree, node, element, role) {
+                return value;
+            }
+        }
+        if let Some(text) = self.native(node, element, visited) {
+            if !text.trim().is_empty() {
+                return text;
+            }
+        }
+        if role.name_from_content() || step != Step::Root {
+            let text = self.content(node, visited);
+            if !text.trim().is_empty() {
+                return text;
+            }
+        }
+        element
+            .get_attribute("title")
+            .or_else(|| {
+                element
+                    .get_attribute("placeholder")
+                    .filter(|_| role.has_value())
+            })
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// The text of the elements with the space-separated `ids`.
+    fn referenced(&self, ids: &str, visited: &mut HashSet<NodeId>) -> String {
+        ids.split_ascii_whitespace()
+            .filter_map(|id| self.tree.find_element_by_id(id))
+            .map(|target| self.text_alternative(target, None, Step::Referenced, visited))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Text from the content of `node`, in flat tree order.
+    fn content(&self, node: NodeId, visited: &mut HashSet<NodeId>) -> String {
+        let mut text = String::new();
+        for child in self.tree.flat_children(node) {
+            let block = self
+                .tree
+                .get_element(child)
+                .is_some_and(|e| e.flags.contains(dom::element::ElementFlags::BLOCK));
+            let part = self.text_alternative(child, None, Step::Content, visited);
+            if block {
+                text.push(' ');
+            }
+            text.push_str(&part);
+            if block {
+                text.push(' ');
+            }
+        }
+        text
+    }
+
+    /// A name from the host language: labels, `alt`, legends and captions.
+    fn native(
+        &self,
+        node: NodeId,
+        element: &ElementData,
+        visited: &mut HashSet<NodeId>,
+    ) -> Option<String> {
+        let child_text = |tag: &str, visited: &mut HashSet<NodeId>| {
+            let child = self.tree.find_child_by_tag(node, tag)?;
+            Some(self.content(child, visited))
+        };
+        match element.tag_name.as_str() {
+            "input" => match forms::input_type(element) {
+                "button" | "reset" | "submit" => {
+                    let default = match forms::input_type(element) {
+                        "reset" => "Reset",
+                        "submit" => "Submit",
+                        _ => "",
+                    };
+                    Some(
+                        element
+                            .get_attribute("value")
+                            .unwrap_or(default)
+                            .to_string(),
+                    )
+                }
+                "image" => Some(element.get_attribute("alt").unwrap_or("Submit").to_string()),
+                _ => self.labels(node, visited),
+            },
+            "select" | "textarea" | "meter" | "output" | "progress" => self.labels(node, visited),
+            "img" | "area" => element.get_attribute("alt").map(str::to_string),
+            "fieldset" => child_text("legend", visited),
+            "figure" => child_text("figcaption", visited),
+            "table" => child_text("caption", visited),
+            "svg" => child_text("title", visited),
+            _ => None,
+        }
+    }
+
+    /// The text of the `<label>`s of a labelable element.
+    fn labels(&self, node: NodeId, visited: &mut HashSet<NodeId>) -> Option<String> {
+        let id = self
+            .tree
+            .get_element(node)
+            .and_then(|e| e.get_attribute("id"));
+        let mut labels: Vec<NodeId> = match id {
+            Some(id) => self
+                .tree
+                .find_elements_by_tag_name("label")
+                .into_iter()
+                .filter(|&label| {
+                    self.tree
+                        .get_element(label)
+                        .and_then(|e| e.get_attribute("for"))
+                        == Some(id)
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        if let Some(ancestor) = self.tree.ancestors(node).find(|&a| {
+            self.tree
+                .get_element(a)
+                .is_some_and(|e| e.tag_name.as_str() == "label" && e.get_attribute("for").is_none())
+        }) {
+            labels.push(ancestor);
+        }
+        if labels.is_empty() {
+            return None;
+        }
+        let texts: Vec<String> = labels
+            .into_iter()
+            .map(|label| self.content(label, visited))
+            .collect();
+        Some(texts.join(" "))
+    }
+}
+
+/// How a node is reached in the computation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// The node being named.
+    Root,
+    /// Through `aria-labelledby` or `aria-describedby`, which includes
+    /// hidden content.
+    Referenced,
+    /// As content of a node being named.
+    Content,
+}
+
+/// The value of a control embedded in content being named.
+fn embedded_value(
+    tree: &DomTree,
+    node: NodeId,
+    element: &ElementData,
+    role: Role,
+) -> Option<String> {
+    match role {
+        Role::Textbox | Role::Searchbox
+            if element.tag_name.as_str() != "input" || forms::input_type(element) != "password" =>
+        {
+            Some(forms::value(tree, node))
+        }
+        Role::Combobox | Role::Listbox if element.tag_name.as_str() == "select" => Some(
+            forms::selected_options(tree, node)
+                .into_iter()
+                .map(|option| tree.get_text_content(option))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        Role::Slider | Role::SpinButton => element
+            .get_attribute("aria-valuetext")
+            .or_else(|| element.get_attribute("aria-valuenow"))
+            .map(str::to_string)
+            .or_else(|| Some(forms::value(tree, node))),
+        _ => None,
+    }
+}
+
+/// Collapse runs of whitespace to single spaces and trim.
+pub(crate) fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,441 @@
+//! Roles: ARIA `role` values and the implicit roles of HTML elements.
+
+use dom::element::ElementData;
+use dom::forms;
+use dom::node::NodeId;
+use dom::tree::DomTree;
+
+/// An accessibility role. Names follow WAI-ARIA; `Document` is the root of
+/// a page and `StaticText` is a run of text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Role {
+    Alert,
+    Article,
+    Banner,
+    Blockquote,
+    Button,
+    Caption,
+    Cell,
+    Checkbox,
+    Code,
+    ColumnHeader,
+    Combobox,
+    Complementary,
+    ContentInfo,
+    Definition,
+    Deletion,
+    Dialog,
+    Document,
+    Emphasis,
+    Figure,
+    Form,
+    Generic,
+    Grid,
+    Group,
+    Heading,
+    Img,
+    Insertion,
+    Link,
+    List,
+    Listbox,
+    ListItem,
+    Main,
+    Math,
+    Menu,
+    MenuItem,
+    Meter,
+    Navigation,
+    None,
+    Option,
+    Paragraph,
+    ProgressBar,
+    Radio,
+    RadioGroup,
+    Region,
+    Row,
+    RowGroup,
+    RowHeader,
+    Search,
+    Searchbox,
+    Separator,
+    Slider,
+    SpinButton,
+    StaticText,
+    Status,
+    Strong,
+    Subscript,
+    Superscript,
+    Switch,
+    Tab,
+    Table,
+    TabList,
+    TabPanel,
+    Term,
+    Textbox,
+    Toolbar,
+    Tooltip,
+    Tree,
+    TreeItem,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Alert => "alert",
+            Role::Article => "article",
+            Role::Banner => "banner",
+            Role::Blockquote => "blockquote",
+            Role::Button => "button",
+            Role::Caption => "caption",
+            Role::Cell => "cell",
+            Role::Checkbox => "checkbox",
+            Role::Code => "code",
+            Role::ColumnHeader => "columnheader",
+            Role::Combobox => "combobox",
+            Role::Complementary => "complementary",
+            Role::ContentInfo => "contentinfo",
+            Role::Definition => "definition",
+            Role::Deletion => "deletion",
+            Role::Dialog => "dialog",
+            Role::Document => "document",
+            Role::Emphasis => "emphasis",
+            Role::Figure => "figure",
+            Role::Form => "form",
+            Role::Generic => "generic",
+            Role::Grid => "grid",
+            Role::Group => "group",
+            Role::Heading => "heading",
+            Role::Img => "img",
+            Role::Insertion => "insertion",
+            Role::Link => "link",
+            Role::List => "list",
+            Role::Listbox => "listbox",
+            Role::ListItem => "listitem",
+            Role::Main => "main",
+            Role::Math => "math",
+            Role::Menu => "menu",
+            Role::MenuItem => "menuitem",
+            Role::Meter => "meter",
+            Role::Navigation => "navigation",
+            Role::None => "none",
+            Role::Option => "option",
+            Role::Paragraph => "paragraph",
+            Role::ProgressBar => "progressbar",
+            Role::Radio => "radio",
+            Role::RadioGroup => "radiogroup",
+            Role::Region => "region",
+            Role::Row => "row",
+            Role::RowGroup => "rowgroup",
+            Role::RowHeader => "rowheader",
+            Role::Search => "search",
+            Role::Searchbox => "searchbox",
+            Role::Separator => "separator",
+            Role::Slider => "slider",
+            Role::SpinButton => "spinbutton",
+            Role::StaticText => "text",
+            Role::Status => "status",
+            Role::Strong => "strong",
+            Role::Subscript => "subscript",
+            Role::Superscript => "superscript",
+            Role::Switch => "switch",
+            Role::Tab => "tab",
+            Role::Table => "table",
+            Role::TabList => "tablist",
+            Role::TabPanel => "tabpanel",
+            Role::Term => "term",
+            Role::Textbox => "textbox",
+            Role::Toolbar => "toolbar",
+            Role::Tooltip => "tooltip",
+            Role::Tree => "tree",
+            Role::TreeItem => "treeitem",
+        }
+    }
+
+    /// Parse one ARIA role token (ASCII case-insensitive). Abstract and
+    /// unknown roles give `None`.
+    pub fn parse(token: &str) -> Option<Self> {
+        Some(match token.to_ascii_lowercase().as_str() {
+            "alert" => Role::Alert,
+            "article" => Role::Article,
+            "banner" => Role::Banner,
+            "blockquote" => Role::Blockquote,
+            "button" => Role::Button,
+            "caption" => Role::Caption,
+            "cell" | "gridcell" => Role::Cell,
+            "checkbox" => Role::Checkbox,
+            "code" => Role::Code,
+            "columnheader" => Role::ColumnHeader,
+            "combobox" => Role::Combobox,
+            "complementary" => Role::Complementary,
+            "contentinfo" => Role::ContentInfo,
+            "definition" => Role::Definition,
+            "deletion" => Role::Deletion,
+            "dialog" | "alertdialog" => Role::Dialog,
+            "document" => Role::Document,
+            "emphasis" => Role::Emphasis,
+            "figure" => Role::Figure,
+            "form" => Role::Form,
+            "generic" => Role::Generic,
+            "grid" => Role::Grid,
+            "group" => Role::Group,
+            "heading" => Role::Heading,
+            "img" | "image" => Role::Img,
+            "insertion" => Role::Insertion,
+            "link" => Role::Link,
+            "list" => Role::List,
+            "listbox" => Role::Listbox,
+            "listitem" => Role::ListItem,
+            "main" => Role::Main,
+            "math" => Role::Math,
+            "menu" | "menubar" => Role::Menu,
+            "menuitem" | "menuitemcheckbox" | "menuitemradio" => Role::MenuItem,
+            "meter" => Role::Meter,
+            "navigation" => Role::Navigation,
+            "none" | "presentation" => Role::None,
+            "option" => Role::Option,
+            "paragraph" => Role::Paragraph,
+            "progressbar" => Role::ProgressBar,
+            "radio" => Role::Radio,
+            "radiogroup" => Role::RadioGroup,
+            "region" => Role::Region,
+            "row" => Role::Row,
+            "rowgroup" => Role::RowGroup,
+            "rowheader" => Role::RowHeader,
+            "search" => Role::Search,
+            "searchbox" => Role::Searchbox,
+            "separator" => Role::Separator,
+            "slider" => Role::Slider,
+            "spinbutton" => Role::SpinButton,
+            "status" => Role::Status,
+            "strong" => Role::Strong,
+            "subscript" => Role::Subscript,
+            "superscript" => Role::Superscript,
+            "switch" => Role::Switch,
+            "tab" => Role::Tab,
+            "table" => Role::Table,
+            "tablist" => Role::TabList,
+            "tabpanel" => Role::TabPanel,
+            "term" => Role::Term,
+            "textbox" => Role::Textbox,
+            "toolbar" => Role::Toolbar,
+            "tooltip" => Role::Tooltip,
+            "tree" => Role::Tree,
+            "treeitem" => Role::TreeItem,
+            _ => return None,
+        })
+    }
+
+    /// Whether the role takes its name from its content when it has no
+    /// label.
+    pub fn name_from_content(&self) -> bool {
+        matches!(
+            self,
+            Role::Button
+                | Role::Caption
+                | Role::Cell
+                | Role::Checkbox
+                | Role::ColumnHeader
+                | Role::Heading
+                | Role::Link
+                | Role::MenuItem
+                | Role::Option
+                | Role::Radio
+                | Role::Row
+                | Role::RowHeader
+                | Role::StaticText
+                | Role::Switch
+                | Role::Tab
+                | Role::Term
+                | Role::Tooltip
+                | Role::TreeItem
+        )
+    }
+
+    /// Whether the role has a checked state.
+    pub fn is_checkable(&self) -> bool {
+        matches!(
+            self,
+            Role::Checkbox | Role::Radio | Role::Switch | Role::MenuItem
+        )
+    }
+
+    /// Whether the role has a value to expose.
+    pub fn has_value(&self) -> bool {
+        matches!(
+            self,
+            Role::Combobox
+                | Role::Meter
+                | Role::ProgressBar
+                | Role::Searchbox
+                | Role::Slider
+                | Role::SpinButton
+                | Role::Textbox
+        )
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The role of an element: the first valid token of its `role` attribute,
+/// or else its implicit role.
+pub fn element_role(tree: &DomTree, node: NodeId, element: &ElementData) -> Role {
+    element
+        .get_attribute("role")
+        .and_then(|roles| roles.split_ascii_whitespace().find_map(Role::parse))
+        .unwrap_or_else(|| implicit_role(tree, node, element))
+}
+
+/// The role an HTML element has without a `role` attribute.
+pub fn implicit_role(tree: &DomTree, node: NodeId, element: &ElementData) -> Role {
+    if element.namespace.is_some() {
+        return match element.tag_name.as_str() {
+            "math" => Role::Math,
+            "svg" => Role::Img,
+            _ => Role::Generic,
+        };
+    }
+    let named = |element: &ElementData| {
+        element
+            .get_attribute("aria-label")
+            .is_some_and(|label| !label.trim().is_empty())
+            || element.get_attribute("aria-labelledby").is_some()
+            || element.get_attribute("title").is_some()
+    };
+    match element.tag_name.as_str() {
+        "a" | "area" if element.get_attribute("href").is_some() => Role::Link,
+        "article" => Role::Article,
+        "aside" => Role::Complementary,
+        "blockquote" => Role::Blockquote,
+        "button" => Role::Button,
+        "caption" => Role::Caption,
+        "code" => Role::Code,
+        "datalist" => Role::Listbox,
+        "dd" => Role::Definition,
+        "del" | "s" => Role::Deletion,
+        "details" | "fieldset" | "optgroup" => Role::Group,
+        "dialog" => Role::Dialog,
+        "dt" | "dfn" => Role::Term,
+        "em" => Role::Emphasis,
+        "figure" => Role::Figure,
+        "figcaption" | "legend" => Role::Caption,
+        "footer" if !in_sectioning_content(tree, node) => Role::ContentInfo,
+        "form" => Role::Form,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Role::Heading,
+        "header" if !in_sectioning_content(tree, node) => Role::Banner,
+        "hr" => Role::Separator,
+        "img" if element.get_attribute("alt") == Some("") => Role::None,
+        "img" => Role::Img,
+        "input" => input_role(element),
+        "ins" => Role::Insertion,
+        "li" => Role::ListItem,
+        "main" => Role::Main,
+        "menu" | "ol" | "ul" => Role::List,
+        "meter" => Role::Meter,
+        "nav" => Role::Navigation,
+        "option" => Role::Option,
+        "output" => Role::Status,
+        "p" => Role::Paragraph,
+        "progress" => Role::ProgressBar,
+        "search" => Role::Search,
+        "section" if named(element) => Role::Region,
+        "select" => {
+            let size = element
+                .get_attribute("size")
+                .and_then(|size| size.trim().parse::<u32>().ok());
+            if element.has_attribute("multiple") || size.is_some_and(|size| size > 1) {
+                Role::Listbox
+            } else {
+                Role::Combobox
+            }
+        }
+        "strong" | "b" => Role::Strong,
+        "sub" => Role::Subscript,
+        "summary" => Role::Button,
+        "sup" => Role::Superscript,
+        "table" => Role::Table,
+        "tbody" | "tfoot" | "thead" => Role::RowGroup,
+        "td" => Role::Cell,
+        "textarea" => Role::Textbox,
+        "th" if element
+            .get_attribute("scope")
+            .is_some_and(|scope| scope.eq_ignore_ascii_case("row")) =>
+        {
+            Role::RowHeader
+        }
+        "th" => Role::ColumnHeader,
+        "tr" => Role::Row,
+        _ => Role::Generic,
+    }
+}
+
+fn input_role(element: &ElementData) -> Role {
+    let has_list = element.get_attribute("list").is_some();
+    match forms::input_type(element) {
+        "button" | "image" | "reset" | "submit" => Role::Button,
+        "checkbox" if element.has_attribute("switch") => Role::Switch,
+        "checkbox" => Role::Checkbox,
+        "radio" => Role::Radio,
+        "range" => Role::Slider,
+        "number" => Role::SpinButton,
+        "search" if !has_list => Role::Searchbox,
+        "color" | "date" | "datetime-local" | "file" | "hidden" | "month" | "time" | "week" => {
+            Role::Generic
+        }
+        _ if has_list => Role::Combobox,
+        _ => Role::Textbox,
+    }
+}
+
+/// Whether `header` or `footer` is scoped to sectioning content, and so is
+/// not a landmark.
+fn in_sectioning_content(tree: &DomTree, node: NodeId) -> bool {
+    tree.ancestors(node).any(|ancestor| {
+        tree.get_element(ancestor).is_some_and(|element| {
+            matches!(
+                element.tag_name.as_str(),
+                "article" | "aside" | "main" | "nav" | "section"
+            )
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::element::TagName;
+
+    #[test]
+    fn test_explicit_and_implicit_roles() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let create =
+            |tree: &mut DomTree, parent: NodeId, tag: &str, attributes: &[(&str, &str)]| {
+                let mut data = ElementData::new(TagName::new(tag));
+                for (name, value) in attributes {
+                    data.set_attribute(name, value);
+                }
+                let node = tree.create_element(data);
+                tree.append_child(parent, node);
+                node
+            };
+        let article = create(&mut tree, root, "article", &[]);
+        let header = create(&mut tree, article, "header", &[]);
+        let banner = create(&mut tree, root, "header", &[]);
+        let tab = create(&mut tree, root, "div", &[("role", "bogus TAB button")]);
+        let select = create(&mut tree, root, "select", &[("size", "3")]);
+        let decorative = create(&mut tree, root, "img", &[("alt", "")]);
+
+        let role = |node| element_role(&tree, node, tree.get_element(node).unwrap());
+        assert_eq!(role(header), Role::Generic);
+        assert_eq!(role(banner), Role::Banner);
+        assert_eq!(role(tab), Role::Tab);
+        assert_eq!(role(select), Role::Listbox);
+        assert_eq!(role(decorative), Role::None);
+        assert_eq!(Role::parse("presentation"), Some(Role::None));
+        assert_eq!(Role::StaticText.to_string(), "text");
+    }
+}
@@ -0,0 +1,585 @@
+//! The accessibility tree.
+
+use crate::name::{collapse, Names};
+use crate::role::{element_role, implicit_role, Role};
+use common::geometry::Rect;
+use dom::element::{ElementData, ElementFlags};
+use dom::node::{NodeData, NodeId};
+use dom::tree::DomTree;
+use dom::{focus, forms};
+use layout::LayoutTree;
+use std::collections::HashMap;
+use std::fmt;
+use style::computed::Visibility;
+
+/// Index of a node in an [`AccessibilityTree`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AccessibleId(usize);
+
+/// Whether a checkable node is checked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checked {
+    False,
+    True,
+    Mixed,
+}
+
+impl Checked {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Checked::False => "false",
+            Checked::True => "true",
+            Checked::Mixed => "mixed",
+        }
+    }
+}
+
+/// States of an accessible node.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct States {
+    /// `None` for roles that cannot be checked.
+    pub checked: Option<Checked>,
+    /// `None` for nodes that do not expand.
+    pub expanded: Option<bool>,
+    pub disabled: bool,
+    pub focusable: bool,
+    pub focused: bool,
+}
+
+/// A node of the accessibility tree.
+#[derive(Clone, Debug)]
+pub struct AccessibleNode {
+    pub id: AccessibleId,
+    /// The DOM node it exposes.
+    pub node: NodeId,
+    pub role: Role,
+    pub name: String,
+    pub description: String,
+    /// Current value of a control, for roles that have one.
+    pub value: Option<String>,
+    /// Heading level.
+    pub level: Option<u32>,
+    pub states: States,
+    /// Border box, or the union of the children's bounds for nodes that
+    /// generate no box.
+    pub bounds: Rect,
+    pub parent: Option<AccessibleId>,
+    pub children: Vec<AccessibleId>,
+}
+
+/// Accessible nodes built from a DOM tree and its layout.
+///
+/// Nodes that are `aria-hidden`, not rendered (`display: none`) or
+/// invisible are pruned; generic and presentational elements are left out
+/// with their children kept in their place.
+#[derive(Clone, Debug, Default)]
+pub struct AccessibilityTree {
+    nodes: Vec<AccessibleNode>,
+    by_node: HashMap<NodeId, AccessibleId>,
+}
+
+impl AccessibilityTree {
+    pub fn build(tree: &DomTree, layout: &LayoutTree) -> Self {
+        let mut boxes: HashMap<NodeId, Vec<Rect>> = HashMap::new();
+        let mut visibility = HashMap::new();
+        for (_, layout_box) in layout.iter() {
+            if let Some(node) = layout_box.node {
+                boxes
+                    .entry(node)
+                    .or_default()
+                    .push(layout_box.dimensions.border_box());
+                visibility.insert(node, layout_box.style.visibility);
+            }
+        }
+        let hidden = |node: NodeId| !is_rendered(tree, &boxes, node) || is_aria_hidden(tree, node);
+        let mut builder = Builder {
+            tree,
+            boxes: &boxes,
+            visibility: &visibility,
+            names: Names::new(tree, &hidden),
+            result: Self::default(),
+        };
+        if let Some(root) = tree.root() {
+            let title = tree
+                .find_elements_by_tag_name("title")
+                .first()
+                .map(|&title| collapse(&tree.get_text_content(title)))
+                .unwrap_or_default();
+            let document = builder.push(root, Role::Document, None);
+            builder.result.nodes[document.0].name = title;
+            builder.children(root, document, true);
+            builder.finish_bounds(document, root);
+        }
+        builder.result
+    }
+
+    /// The node for the document.
+    pub fn root(&self) -> Option<&AccessibleNode> {
+        self.nodes.first()
+    }
+
+    pub fn get(&self, id: AccessibleId) -> Option<&AccessibleNode> {
+        self.nodes.get(id.0)
+    }
+
+    /// The accessible node exposing a DOM node.
+    pub fn find(&self, node: NodeId) -> Option<&AccessibleNode> {
+        self.by_node.get(&node).and_then(|&id| self.get(id))
+    }
+
+    pub fn children(&self, id: AccessibleId) -> impl Iterator<Item = &AccessibleNode> + '_ {
+        self.get(id)
+            .into_iter()
+            .flat_map(|node| node.children.iter())
+            .filter_map(|&child| self.get(child))
+    }
+
+    /// The focused node, if it is exposed.
+    pub fn focused(&self) -> Option<&AccessibleNode> {
+        self.nodes.iter().find(|node| node.states.focused)
+    }
+
+    /// All nodes, in tree order.
+    pub fn iter(&self) -> impl Iterator<Item = &AccessibleNode> {
+        self.nodes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn write_node(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        node: &AccessibleNode,
+        depth: usize,
+    ) -> fmt::Result {
+        write!(f, "{:indent$}{}", "", node.role, indent = depth * 2)?;
+        if !node.name.is_empty() {
+            write!(f, " {:?}", node.name)?;
+        }
+        if let Some(value) = &node.value {
+            write!(f, " value={value:?}")?;
+        }
+        if let Some(level) = node.level {
+            write!(f, " level={level}")?;
+        }
+        if !node.description.is_empty() {
+            write!(f, " description={:?}", node.description)?;
+        }
+        let states = &node.states;
+        if let Some(checked) = states.checked {
+            write!(f, " checked={}", checked.as_str())?;
+        }
+        if let Some(expanded) = states.expanded {
+            write!(f, " expanded={expanded}")?;
+        }
+        for (set, state) in [
+            (states.disabled, "disabled"),
+            (states.focusable, "focusable"),
+            (states.focused, "focused"),
+        ] {
+            if set {
+                write!(f, " {state}")?;
+            }
+        }
+        if f.alternate() {
+            let b = node.bounds;
+            write!(
+                f,
+                " @{},{} {}x{}",
+                b.x.round(),
+                b.y.round(),
+                b.width.round(),
+                b.height.round()
+            )?;
+        }
+        writeln!(f)?;
+        for child in self.children(node.id) {
+            self.write_node(f, child, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// A text dump, one node per line and indented by depth. The alternate
+/// form (`{:#}`) adds bounds.
+impl fmt::Display for AccessibilityTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.root() {
+            Some(root) => self.write_node(f, root, 0),
+            None => Ok(()),
+        }
+    }
+}
+
+struct Builder<'a> {
+    tree: &'a DomTree,
+    boxes: &'a HashMap<NodeId, Vec<Rect>>,
+    visibility: &'a HashMap<NodeId, Visibility>,
+    names: Names<'a>,
+    result: AccessibilityTree,
+}
+
+impl Builder<'_> {
+    fn push(&mut self, node: NodeId, role: Role, parent: Option<AccessibleId>) -> AccessibleId {
+        let id = AccessibleId(self.result.nodes.len());
+        self.result.nodes.push(AccessibleNode {
+            id,
+            node,
+            role,
+            name: String::new(),
+            description: String::new(),
+            value: None,
+            level: None,
+            states: States::default(),
+            bounds: Rect::default(),
+            parent,
+            children: Vec::new(),
+        });
+        if let Some(parent) = parent {
+            self.result.nodes[parent.0].children.push(id);
+        }
+        self.result.by_node.insert(node, id);
+        id
+    }
+
+    /// Add the flat tree children of `node` under `parent`. `visible` is
+    /// the inherited visibility.
+    fn children(&mut self, node: NodeId, parent: AccessibleId, visible: bool) {
+        for child in self.tree.flat_children(node) {
+            self.visit(child, parent, visible);
+        }
+    }
+
+    fn visit(&mut self, node: NodeId, parent: AccessibleId, visible: bool) {
+        let element = match self.tree.get(node).map(|n| &n.data) {
+            Some(NodeData::Text { content }) => {
+                let text = collapse(content);
+                if visible && !text.is_empty() && self.boxes.contains_key(&node) {
+                    let id = self.push(node, Role::StaticText, Some(parent));
+                    self.result.nodes[id.0].name = text;
+                    self.finish_bounds(id, node);
+                }
+                return;
+            }
+            Some(NodeData::Element(element)) => element,
+            _ => return,
+        };
+        if is_aria_hidden(self.tree, node) || !is_rendered(self.tree, self.boxes, node) {
+            return;
+        }
+        let visible = match self.visibility.get(&node) {
+            Some(visibility) => *visibility == Visibility::Visible,
+            None => visible,
+        };
+
+        let focusable = focus::is_focusable_area(self.tree, node);
+        let mut role = element_role(self.tree, node, element);
+        if role == Role::None && (focusable || has_global_aria(element)) {
+            // Presentational roles do not apply to focusable elements.
+            role = implicit_role(self.tree, node, element);
+        }
+        let ignored = !visible
+            || match role {
+                Role::None => true,
+                Role::Generic => !focusable && !has_global_aria(element),
+                _ => false,
+            };
+        if ignored {
+            self.children(node, parent, visible);
+            return;
+        }
+
+        let id = self.push(node, role, Some(parent));
+        let name = self.names.name(node, role);
+        let description = self.names.description(node, &name);
+        let exposed = &mut self.result.nodes[id.0];
+        exposed.description = description;
+        exposed.name = name;
+        exposed.value = value(self.tree, node, element, role);
+        exposed.level = level(element, role);
+        exposed.states = states(self.tree, node, element, role, focusable);
+        if !children_presentational(role) {
+            self.children(node, id, visible);
+        }
+        self.finish_bounds(id, node);
+    }
+
+    /// Bounds from the node's boxes, or else from its children.
+    fn finish_bounds(&mut self, id: AccessibleId, node: NodeId) {
+        let rects: Vec<Rect> = match self.boxes.get(&node) {
+            Some(rects) => rects.clone(),
+            None => self.result.nodes[id.0]
+                .children
+                .iter()
+                .map(|child| self.result.nodes[child.0].bounds)
+                .collect(),
+        };
+        if let Some(bounds) = rects.into_iter().reduce(|a, b| a.union(&b)) {
+            self.result.nodes[id.0].bounds = bounds;
+        }
+    }
+}
+
+/// Whether `node` or anything in its flat tree generates a box.
+/// Elements with `display: contents` and slots have no box of their own.
+fn is_rendered(tree: &DomTree, boxes: &HashMap<NodeId, Vec<Rect>>, node: NodeId) -> bool {
+    boxes.contains_key(&node)
+        || tree
+            .flat_children(node)
+            .into_iter()
+            .any(|child| is_rendered(tree, boxes, child))
+}
+
+/// Whether `node` or an ancestor has `aria-hidden="true"`.
+fn is_aria_hidden(tree: &DomTree, node: NodeId) -> bool {
+    std::iter::once(node)
+        .chain(tree.ancestors(node))
+        .any(|node| {
+            tree.get_element(node)
+                .and_then(|element| element.get_attribute("aria-hidden"))
+                .is_some_and(|hidden| hidden.trim().eq_ignore_ascii_case("true"))
+        })
+}
+
+/// Whether an element has ARIA attributes that keep it in the tree even
+/// without a meaningful role.
+fn has_global_aria(element: &ElementData) -> bool {
+    [
+        "aria-label",
+        "aria-labelledby",
+        "aria-describedby",
+        "aria-live",
+    ]
+    .iter()
+    .any(|name| element.get_attribute(name).is_some())
+}
+
+/// Roles whose content is part of the control rather than separate nodes.
+fn children_presentational(role: Role) -> bool {
+    matches!(
+        role,
+        Role::Button
+            | Role::Checkbox
+            | Role::Img
+            | Role::Meter
+            | Role::ProgressBar
+            | Role::Radio
+            | Role::Searchbox
+            | Role::Separator
+            | Role::Slider
+            | Role::SpinButton
+            | Role::Switch
+            | Role::Textbox
+    )
+}
+
+fn value(tree: &DomTree, node: NodeId, element: &ElementData, role: Role) -> Option<String> {
+    if !role.has_value() {
+        return None;
+    }
+    let attribute = element
+        .get_attribute("aria-valuetext")
+        .or_else(|| element.get_attribute("aria-valuenow"));
+    if let Some(value) = attribute {
+        return Some(value.to_string());
+    }
+    match element.tag_name.as_str() {
+        "input" if forms::input_type(element) == "password" => {
+            Some("\u{2022}".repeat(forms::value(tree, node).chars().count()))
+        }
+        "input" | "textarea" => Some(forms::value(tree, node)),
+        "select" => forms::selected_options(tree, node)
+            .first()
+            .map(|&option| collapse(&tree.get_text_content(option))),
+        "meter" | "progress" => element.get_attribute("value").map(str::to_string),
+        _ => None,
+    }
+}
+
+fn level(element: &ElementData, role: Role) -> Option<u32> {
+    if role != Role::Heading {
+        return None;
+    }
+    let aria_level = element
+        .get_attribute("aria-level")
+        .and_then(|level| level.trim().parse().ok());
+    let tag_level = match element.tag_name.as_str() {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    };
+    aria_level
+        .filter(|&level| level > 0)
+        .or(tag_level)
+        .or(Some(2))
+}
+
+fn states(
+    tree: &DomTree,
+    node: NodeId,
+    element: &ElementData,
+    role: Role,
+    focusable: bool,
+) -> States {
+    let aria_true = |node: NodeId, name: &str| {
+        tree.get_element(node)
+            .and_then(|element| element.get_attribute(name))
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+    };
+    let checked = match element
+        .get_attribute("aria-checked")
+        .map(|value| value.trim().to_ascii_lowercase())
+    {
+        Some(value) if role.is_checkable() => Some(match value.as_str() {
+            "true" => Checked::True,
+            "mixed" => Checked::Mixed,
+            _ => Checked::False,
+        }),
+        _ if role.is_checkable() && role != Role::MenuItem => {
+            Some(if forms::is_checked(tree, node) {
+                Checked::True
+            } else {
+                Checked::False
+            })
+        }
+        _ => None,
+    };
+    let expanded = match element
+        .get_attribute("aria-expanded")
+        .map(|value| value.trim().to_ascii_lowercase())
+    {
+        Some(value) if value == "true" || value == "false" => Some(value == "true"),
+        _ if element.tag_name.as_str() == "summary" => tree
+            .parent(node)
+            .and_then(|parent| tree.get_element(parent))
+            .filter(|details| details.tag_name.as_str() == "details")
+            .map(|details| details.has_attribute("open")),
+        _ => None,
+    };
+    States {
+        checked,
+        expanded,
+        disabled: forms::is_disabled(tree, node)
+            || std::iter::once(node)
+                .chain(tree.ancestors(node))
+                .any(|node| aria_true(node, "aria-disabled")),
+        focusable,
+        focused: element.flags.contains(ElementFlags::FOCUSED),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::element::TagName;
+    use dom::Document;
+    use layout::LayoutEngine;
+    use style::StyleResolver;
+
+    fn element(
+        document: &mut Document,
+        parent: NodeId,
+        tag: &str,
+        attributes: &[(&str, &str)],
+    ) -> NodeId {
+        let mut data = ElementData::new(TagName::new(tag));
+        for (name, value) in attributes {
+            data.set_attribute(name, value);
+        }
+        let node = document.tree.create_element(data);
+        document.tree.append_child(parent, node);
+        node
+    }
+
+    fn text(document: &mut Document, parent: NodeId, content: &str) {
+        let node = document.tree.create_text(content.to_string());
+        document.tree.append_child(parent, node);
+    }
+
+    fn build(document: &Document) -> AccessibilityTree {
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        style_resolver.resolve_document(document);
+        let layout = LayoutEngine::new(800.0, 600.0).layout(document, &style_resolver);
+        AccessibilityTree::build(&document.tree, &layout)
+    }
+
+    #[test]
+    fn test_roles_names_and_states() {
+        let mut document = Document::new(url::Url::parse("about:blank").unwrap());
+        let root = document.tree.root().unwrap();
+        let html = element(&mut document, root, "html", &[]);
+        let head = element(&mut document, html, "head", &[]);
+        let title = element(&mut document, head, "title", &[]);
+        text(&mut document, title, "Settings");
+        let body = element(&mut document, html, "body", &[]);
+        let nav = element(&mut document, body, "nav", &[("aria-label", "Main")]);
+        let link = element(&mut document, nav, "a", &[("href", "/home")]);
+        text(&mut document, link, "Home");
+        let h1 = element(&mut document, body, "h1", &[]);
+        text(&mut document, h1, "Account");
+        let div = element(&mut document, body, "div", &[]);
+        let label = element(&mut document, div, "label", &[("for", "news")]);
+        text(&mut document, label, "Newsletter");
+        let checkbox = element(
+            &mut document,
+            div,
+            "input",
+            &[("type", "checkbox"), ("id", "news"), ("checked", "")],
+        );
+        let hint = element(
+            &mut document,
+            div,
+            "span",
+            &[("id", "hint"), ("aria-hidden", "true")],
+        );
+        text(&mut document, hint, "Weekly");
+        let input = element(
+            &mut document,
+            div,
+            "input",
+            &[("aria-describedby", "hint"), ("placeholder", "Email")],
+        );
+        let button = element(&mut document, body, "button", &[("disabled", "")]);
+        text(&mut document, button, "Save");
+        let hidden = element(&mut document, body, "p", &[("hidden", "")]);
+        text(&mut document, hidden, "Gone");
+        forms::set_value(&mut document.tree, input, "me@example.com");
+        document.focus(input, dom::FocusOrigin::Keyboard);
+
+        let a11y = build(&document);
+        assert_eq!(
+            a11y.to_string(),
+            "document \"Settings\"\n\
+             \x20 navigation \"Main\"\n\
+             \x20   link \"Home\" focusable\n\
+             \x20     text \"Home\"\n\
+             \x20 heading \"Account\" level=1\n\
+             \x20   text \"Account\"\n\
+             \x20 text \"Newsletter\"\n\
+             \x20 checkbox \"Newsletter\" checked=true focusable\n\
+             \x20 textbox \"Email\" value=\"me@example.com\" description=\"Weekly\" focusable focused\n\
+             \x20 button \"Save\" disabled\n"
+        );
+        assert_eq!(a11y.focused().map(|node| node.node), Some(input));
+        assert_eq!(
+            a11y.find(checkbox).unwrap().states.checked,
+            Some(Checked::True)
+        );
+        assert!(a11y.find(button).unwrap().states.disabled);
+        let heading = a11y.find(h1).unwrap().bounds;
+        assert!(heading.width > 0.0 && heading.height > 0.0);
+        assert!(a11y.find(hidden).is_none() && a11y.find(hint).is_none());
+        assert!(format!("{a11y:#}").lines().all(|line| line.contains(" @")));
+    }
+}
@@ -0,0 +1,311 @@
+//! Keyboard, pointer and clipboard editing of `contenteditable` content and
+//! text controls.
+
+use crate::selection::{ClipboardContents, SelectionController};
+use dom::document::Document;
+use dom::editing::{self, EditTarget, InputDetail, InputType};
+use dom::events::{Event, EventType};
+use dom::focus::{FocusChange, FocusOrigin};
+use dom::forms;
+use html_parser::{Sanitizer, SanitizerConfig};
+use layout::LayoutTree;
+use web_apis::events::{InputEvent, KeyboardEvent};
+
+/// Turns key presses, text input and input method composition into edits
+/// of the document.
+#[derive(Debug, Default)]
+pub struct EditingController {
+    composing: bool,
+}
+
+impl EditingController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether an input method composition is in progress.
+    pub fn is_composing(&self) -> bool {
+        self.composing
+    }
+
+    /// Primary button pressed: place the caret where the pointer hits
+    /// editable content and focus its editing host. Returns whether it did.
+    pub fn pointer_down(
+        &mut self,
+        document: &mut Document,
+        layout: &LayoutTree,
+        x: f32,
+        y: f32,
+    ) -> bool {
+        let Some(point) = layout.caret_position_from_point(x, y) else {
+            return false;
+        };
+        let Some(host) = editing::editing_host(&document.tree, point.node) else {
+            return false;
+        };
+        if document
+            .selection
+            .lock()
+            .collapse(&document.tree, point.node, point.offset)
+            .is_err()
+        {
+            return false;
+        }
+        if let Some(change) = document.focus(host, FocusOrigin::Pointer) {
+            dispatch_focus_change(document, &change);
+        }
+        true
+    }
+
+    /// A key was pressed and its `keydown` was not canceled. Returns whether
+    /// it was handled as an edit.
+    pub fn key_down(&mut self, document: &mut Document, event: &KeyboardEvent) -> bool {
+        if self.composing || event.is_composing {
+            return false;
+        }
+        let Some(target) = document.edit_target() else {
+            return false;
+        };
+        let command = event.ctrl_key || event.meta_key;
+        let input_type = match (event.key.as_str(), command) {
+            ("Backspace", _) => InputType::DeleteContentBackward,
+            ("Delete", _) => InputType::DeleteContentForward,
+            ("Enter", false) => {
+                if let EditTarget::Control(control) = target {
+                    if document
+                        .tree
+                        .get_element(control)
+                        .is_some_and(|e| e.tag_name.as_str() == "input")
+                    {
+                        return implicit_submission(document, control);
+                    }
+                }
+                if event.shift_key {
+                    InputType::InsertLineBreak
+                } else {
+                    InputType::InsertParagraph
+                }
+            }
+            ("z" | "Z", true) if event.shift_key => InputType::HistoryRedo,
+            ("z", true) => InputType::HistoryUndo,
+            ("y", true) => InputType::HistoryRedo,
+            ("b", true) => InputType::FormatBold,
+            ("i", true) => InputType::FormatItalic,
+            ("u", true) => InputType::FormatUnderline,
+            (key, false) if !event.alt_key && key.chars().count() == 1 => {
+                return document.edit(InputDetail::with_data(InputType::InsertText, key));
+            }
+            _ => return false,
+        };
+        document.edit(InputDetail::new(input_type))
+    }
+
+    /// Text committed by the platform outside of a composition.
+    pub fn text_input(&mut self, document: &mut Document, event: &InputEvent) -> bool {
+        let input_type = InputType::parse(&event.input_type).unwrap_or(InputType::InsertText);
+        let mut input = InputDetail::new(input_type);
+        input.data = event.data.clone();
+        document.edit(input)
+    }
+
+    /// The input method started composing text.
+    pub fn composition_start(&mut self, document: &mut Document) -> bool {
+        let Some(target) = document.edit_target() else {
+            return false;
+        };
+        let mut event = Event::with_options(EventType::CompositionStart, true, true);
+        event.composed = true;
+        event.input = Some(InputDetail::with_data(InputType::InsertCompositionText, ""));
+        self.composing = document.dispatch_event(target.node(), &mut event);
+        self.composing
+    }
+
+    /// The text being composed changed.
+    pub fn composition_update(&mut self, document: &mut Document, data: &str) -> bool {
+        if !self.composing {
+            return false;
+        }
+        let mut input = InputDetail::with_data(InputType::InsertCompositionText, data);
+        input.is_composing = true;
+        if let Some(target) = document.edit_target() {
+            let mut event = Event::with_options(EventType::CompositionUpdate, true, false);
+            event.composed = true;
+            event.input = Some(input.clone());
+            document.dispatch_event(target.node(), &mut event);
+        }
+        document.edit(input)
+    }
+
+    /// The input method committed `data`, ending the composition.
+    pub fn composition_end(&mut self, document: &mut Document, data: &str) -> bool {
+        if !std::mem::take(&mut self.composing) {
+            return false;
+        }
+        let edited = document.edit(InputDetail::with_data(
+            InputType::InsertCompositionText,
+            data,
+        ));
+        document.editor.end_composition();
+        if let Some(target) = document.edit_target() {
+            let mut event = Event::with_options(EventType::CompositionEnd, true, false);
+            event.composed = true;
+            event.input = Some(InputDetail::with_data(
+                InputType::InsertCompositionText,
+                data,
+            ));
+            document.dispatch_event(target.node(), &mut event);
+        }
+        edited
+    }
+
+    /// Paste clipboard contents at the selection. Markup is sanitized and
+    /// pasted into rich content; text controls and `plaintext-only` hosts
+    /// take the text.
+    pub fn paste(&mut self, document: &mut Document, contents: &ClipboardContents) -> bool {
+        let Some(target) = document.edit_target() else {
+            return false;
+        };
+        let mut input = InputDetail::with_data(InputType::InsertFromPaste, contents.text.clone());
+        if let EditTarget::Content(host) = target {
+            let context = document.tree.get_element(host).cloned();
+            let rich = context
+                .as_ref()
+                .is_some_and(|e| e.get_attribute("contenteditable") != Some("plaintext-only"));
+            if let Some(context) = context.filter(|_| rich && !contents.html.is_empty()) {
+                let sanitizer = Sanitizer::new(SanitizerConfig::default());
+                input.fragment =
+                    Some(sanitizer.sanitize_fragment(&mut document.tree, &contents.html, &context));
+            }
+        }
+        document.edit(input)
+    }
+
+    /// Remove the selection for a cut, returning what it held.
+    pub fn cut(
+        &mut self,
+        document: &mut Document,
+        selection: &SelectionController,
+    ) -> Option<ClipboardContents> {
+        let contents = match document.edit_target()? {
+            EditTarget::Control(control) => {
+                let (start, end) = forms::selection_range(&document.tree, control);
+                let text = forms::value(&document.tree, control)[start..end].to_string();
+                ClipboardContents {
+                    text,
+                    html: String::new(),
+                }
+            }
+            EditTarget::Content(_) => selection.copy(document)?,
+        };
+        if contents.text.is_empty() || !document.edit(InputDetail::new(InputType::DeleteByCut)) {
+            return None;
+        }
+        Some(contents)
+    }
+}
+
+/// Submit the form of a single-line control, as Enter does.
+fn implicit_submission(document: &mut Document, control: dom::NodeId) -> bool {
+    match forms::form_owner(&document.tree, control) {
+        Some(form) => {
+            document.request_submit(form, None);
+            true
+        }
+        None => false,
+    }
+}
+
+fn dispatch_focus_change(document: &Document, change: &FocusChange) {
+    for (node, mut event) in change.events() {
+        document.dispatch_event(node, &mut event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::events::EventListenerOptions;
+    use layout::LayoutEngine;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+    use style::query::QuerySelector;
+    use style::StyleResolver;
+    use url::Url;
+
+    #[test]
+    fn test_typing_into_editing_host() {
+        let mut document = html_parser::parse_html(
+            "<html><body><div contenteditable><p>Hi</p></div></body></html>",
+            Url::parse("about:blank").unwrap(),
+        );
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        style_resolver.resolve_document(&document);
+        let layout = LayoutEngine::new(800.0, 600.0).layout(&document, &style_resolver);
+
+        let host = document.tree.query_selector("div").unwrap().unwrap();
+        let p = document.tree.query_selector("p").unwrap().unwrap();
+        let text = document.tree.first_child(p).unwrap();
+        let rect = layout
+            .get(layout.find_by_node(text).unwrap())
+            .unwrap()
+            .content_rect();
+
+        let inputs = Arc::new(Mutex::new(Vec::new()));
+        let log = inputs.clone();
+        document.events.lock().add_listener(
+            host,
+            "input",
+            Arc::new(move |event: &mut Event| {
+                log.lock()
+                    .push(event.input.as_ref().unwrap().input_type.as_str());
+            }),
+            EventListenerOptions::default(),
+        );
+
+        let mut controller = EditingController::new();
+        assert!(controller.pointer_down(
+            &mut document,
+            &layout,
+            rect.x + rect.width + 1.0,
+            rect.y + 1.0
+        ));
+        assert_eq!(document.focus.focused(), Some(host));
+        assert!(controller.key_down(&mut document, &KeyboardEvent::new("keydown", "!", "Digit1")));
+        assert_eq!(document.tree.get_text_content(p), "Hi!");
+
+        assert!(controller.key_down(
+            &mut document,
+            &KeyboardEvent::new("keydown", "Enter", "Enter")
+        ));
+        assert!(controller.composition_start(&mut document));
+        assert!(controller.composition_update(&mut document, "y"));
+        assert!(controller.composition_end(&mut document, "yo"));
+        assert_eq!(document.tree.get_text_content(host), "Hi!yo");
+        assert_eq!(document.tree.children(host).count(), 2);
+
+        let mut undo = KeyboardEvent::new("keydown", "z", "KeyZ");
+        undo.ctrl_key = true;
+        assert!(controller.key_down(&mut document, &undo));
+        assert_eq!(document.tree.get_text_content(host), "Hi!");
+        assert_eq!(
+            *inputs.lock(),
+            [
+                "insertText",
+                "insertParagraph",
+                "insertCompositionText",
+                "insertCompositionText",
+                "historyUndo"
+            ]
+        );
+
+        let pasted = ClipboardContents {
+            text: "bold".to_string(),
+            html: "<b>bold</b><script>x()</script>".to_string(),
+        };
+        assert!(controller.paste(&mut document, &pasted));
+        let bold = document.tree.query_selector("b").unwrap().unwrap();
+        assert!(document.tree.is_inclusive_ancestor(host, bold));
+        assert!(document.tree.query_selector("script").unwrap().is_none());
+    }
+}
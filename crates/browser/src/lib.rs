@@ -15,11 +15,16 @@ pub mod engine;
 pub mod page;
 pub mod pipeline;
 pub mod config;
+pub mod selection;
+pub mod editing;
+pub mod stylesheets;
 
 pub use engine::BrowserEngine;
 pub use page::Page;
 pub use pipeline::RenderPipeline;
 pub use config::BrowserConfig;
+pub use selection::{ClipboardContents, SelectionController};
+pub use editing::EditingController;
 
 /// Browser version.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -1,7 +1,8 @@
 //! Oxide Browser - A high-performance web browser written in Rust.
 
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use html_parser::{parse_html, serialize_html_with_options, SerializeOptions};
 use tracing::{info, Level};
 use tracing_subscriber::FmtSubscriber;
 
@@ -47,15 +48,42 @@ struct Args {
     #[arg(short, long)]
     verbose: bool,
 
-    /// Dump DOM tree
-    #[arg(long)]
-    dump_dom: bool,
+    /// Dump DOM tree, optionally as pretty, canonical or minified HTML
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "standard"
+    )]
+    dump_dom: Option<DumpMode>,
 
     /// Take screenshot and save to file
     #[arg(long)]
     screenshot: Option<String>,
 }
 
+/// Serialization used by `--dump-dom`.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum DumpMode {
+    Standard,
+    Pretty,
+    Canonical,
+    Minify,
+}
+
+impl DumpMode {
+    fn options(self) -> SerializeOptions {
+        let options = SerializeOptions::new();
+        match self {
+            DumpMode::Standard => options,
+            DumpMode::Pretty => options.pretty(),
+            DumpMode::Canonical => options.canonical(),
+            DumpMode::Minify => options.minify(),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
@@ -104,9 +132,18 @@ async fn main() -> Result<()> {
         info!("Page loaded: {}", page.title());
 
         // Dump DOM if requested
-        if args.dump_dom {
+        if let Some(mode) = args.dump_dom {
+            let dom = match mode {
+                // The page content is already the parser's serialization.
+                DumpMode::Standard => page.content(),
+                _ => {
+                    let url = page.url().unwrap_or_else(|| url::Url::parse("about:blank").unwrap());
+                    let document = parse_html(&page.content(), url);
+                    serialize_html_with_options(&document, &mode.options())
+                }
+            };
             println!("DOM Tree:");
-            println!("{}", page.content());
+            println!("{}", dom);
         }
 
         // Take screenshot if requested
@@ -156,4 +193,18 @@ mod tests {
         let args = Args::parse_from(["oxide-browser", "--headless"]);
         assert!(args.headless);
     }
+
+    #[test]
+    fn test_args_dump_dom() {
+        let args = Args::parse_from(["oxide-browser"]);
+        assert_eq!(args.dump_dom, None);
+
+        let args = Args::parse_from(["oxide-browser", "--dump-dom", "https://example.com"]);
+        assert_eq!(args.dump_dom, Some(DumpMode::Standard));
+        assert_eq!(args.url, "https://example.com");
+
+        let args = Args::parse_from(["oxide-browser", "--dump-dom=minify"]);
+        assert_eq!(args.dump_dom, Some(DumpMode::Minify));
+        assert!(!args.dump_dom.unwrap().options().pretty);
+    }
 }
@@ -1,11 +1,16 @@
 //! Browser page implementation.
 
 use std::sync::Arc;
-use parking_lot::RwLock;
+use dom::FormSubmission;
+use html_parser::view_source::{render_view_source, VIEW_SOURCE_SCHEME};
+use networking::{HttpClient, Request, ResourceLoader};
+use parking_lot::{RwLock, RwLockReadGuard};
+use style::resolver::StyleResolver;
 use url::Url;
 
 use crate::config::BrowserConfig;
 use crate::pipeline::RenderPipeline;
+use crate::stylesheets::{load_document_stylesheets, StylesheetFetcher};
 
 /// A browser page (tab).
 pub struct Page {
@@ -25,8 +30,12 @@ pub struct Page {
     history: RwLock<NavigationHistory>,
     /// Page content (raw HTML).
     content: RwLock<String>,
+    /// Default and author styles of the content.
+    styles: RwLock<StyleResolver>,
     /// Security state.
     security_state: RwLock<SecurityState>,
+    /// HTTP client, created on first use.
+    client: RwLock<Option<Arc<HttpClient>>>,
 }
 
 impl Page {
@@ -41,17 +50,27 @@ impl Page {
             pipeline: RwLock::new(None),
             history: RwLock::new(NavigationHistory::new()),
             content: RwLock::new(String::new()),
+            styles: RwLock::new(StyleResolver::new()),
             security_state: RwLock::new(SecurityState::Unknown),
+            client: RwLock::new(None),
         }
     }
 
     /// Navigate to a URL.
     pub async fn navigate(&self, url: &str) -> anyhow::Result<()> {
-        let parsed_url = if url.contains("://") {
-            Url::parse(url)?
-        } else {
-            Url::parse(&format!("https://{}", url))?
+        // Schemes are case-insensitive.
+        let view_source = url
+            .split_once(':')
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case(VIEW_SOURCE_SCHEME))
+            .map(|(_, target)| target);
+        let parsed_url = match view_source {
+            Some(target) => {
+                let target = parse_navigation_url(target)?;
+                Url::parse(&format!("{}:{}", VIEW_SOURCE_SCHEME, target))?
+            }
+            None => parse_navigation_url(url)?,
         };
+        let target_url = view_source_target(&parsed_url).unwrap_or_else(|| parsed_url.clone());
 
         // Start loading
         *self.loading.write() = true;
@@ -64,7 +83,7 @@ impl Page {
         self.history.write().push(parsed_url.clone());
 
         // Update security state
-        *self.security_state.write() = if parsed_url.scheme() == "https" {
+        *self.security_state.write() = if target_url.scheme() == "https" {
             SecurityState::Secure
         } else {
             SecurityState::Insecure
@@ -83,9 +102,97 @@ impl Page {
         Ok(())
     }
 
+    /// Navigate by submitting a form: `GET` the action with the entries as
+    /// its query, or `POST` the encoded entries to it.
+    pub async fn submit_form(&self, submission: &FormSubmission) -> anyhow::Result<()> {
+        let request = form_request(submission);
+        let target_url = request.url.clone();
+
+        *self.loading.write() = true;
+        *self.progress.write() = 0.0;
+        let previous_url = self.url.write().replace(target_url.clone());
+        let previous_security_state = std::mem::replace(
+            &mut *self.security_state.write(),
+            if target_url.scheme() == "https" {
+                SecurityState::Secure
+            } else {
+                SecurityState::Insecure
+            },
+        );
+
+        let result = async {
+            let response = self.client()?.execute(request).await?;
+            *self.progress.write() = 0.5;
+            let final_url = response.url().clone();
+            let html = response.text()?;
+            Ok::<_, anyhow::Error>((final_url, html))
+        }
+        .await;
+        *self.loading.write() = false;
+        let (final_url, html) = match result {
+            Ok(response) => response,
+            Err(error) => {
+                // The previous document is still shown.
+                *self.url.write() = previous_url;
+                *self.security_state.write() = previous_security_state;
+                return Err(error);
+            }
+        };
+
+        tracing::info!("Submitted form to: {}", final_url);
+        *self.url.write() = Some(final_url.clone());
+        self.history.write().push(final_url);
+        self.set_content(&html);
+        let loader = ResourceLoader::new(self.client()?);
+        self.load_stylesheets(&loader).await;
+        *self.progress.write() = 1.0;
+
+        Ok(())
+    }
+
+    /// The page's HTTP client.
+    fn client(&self) -> anyhow::Result<Arc<HttpClient>> {
+        if let Some(client) = self.client.read().as_ref() {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(HttpClient::new()?);
+        *self.client.write() = Some(client.clone());
+        Ok(client)
+    }
+
     /// Set page content directly (for testing).
+    ///
+    /// On a `view-source:` page the content is the raw response body, which
+    /// is rendered as highlighted source.
     pub fn set_content(&self, html: &str) {
-        *self.content.write() = html.to_string();
+        let content = match self.view_source_url() {
+            Some(target) => render_view_source(html, &target),
+            None => html.to_string(),
+        };
+        *self.content.write() = content;
+    }
+
+    /// Style the content afresh: the default styles, then its `<style>` and
+    /// `<link rel=stylesheet>` sheets with what they import.
+    pub async fn load_stylesheets<F: StylesheetFetcher>(&self, fetcher: &F) {
+        let Some(url) = self.url() else {
+            return;
+        };
+        let document = html_parser::parse_html(&self.content(), url);
+        let mut styles = StyleResolver::new();
+        styles.add_default_styles();
+        load_document_stylesheets(fetcher, &document, &mut styles).await;
+        *self.styles.write() = styles;
+    }
+
+    /// The styles of the content.
+    pub fn styles(&self) -> RwLockReadGuard<'_, StyleResolver> {
+        self.styles.read()
+    }
+
+    /// The URL whose source is shown, if this is a `view-source:` page.
+    pub fn view_source_url(&self) -> Option<Url> {
+        self.url.read().as_ref().and_then(view_source_target)
     }
 
     /// Get current URL.
@@ -186,6 +293,36 @@ impl Page {
     }
 }
 
+/// Parse a user-typed URL, defaulting to HTTPS when no scheme is given.
+fn parse_navigation_url(url: &str) -> anyhow::Result<Url> {
+    if url.contains("://") {
+        Ok(Url::parse(url)?)
+    } else {
+        Ok(Url::parse(&format!("https://{}", url))?)
+    }
+}
+
+/// The request that performs a form submission.
+fn form_request(submission: &FormSubmission) -> Request {
+    match submission.body() {
+        Some((content_type, body)) => Request::new(http::Method::POST, submission.url())
+            .header("Content-Type", content_type)
+            .body(body),
+        None => Request::new(http::Method::GET, submission.url()),
+    }
+}
+
+/// Extract the inner URL of a `view-source:` URL.
+fn view_source_target(url: &Url) -> Option<Url> {
+    if url.scheme() != VIEW_SOURCE_SCHEME {
+        return None;
+    }
+    url.as_str()
+        .strip_prefix(VIEW_SOURCE_SCHEME)
+        .and_then(|rest| rest.strip_prefix(':'))
+        .and_then(|target| Url::parse(target).ok())
+}
+
 /// Navigation history.
 #[derive(Debug)]
 pub struct NavigationHistory {
@@ -306,6 +443,56 @@ mod tests {
         assert!(page.security_state().is_secure());
     }
 
+    #[tokio::test]
+    async fn test_view_source_navigation() {
+        let page = Page::new(BrowserConfig::default());
+
+        page.navigate("view-source:example.com/index.html").await.unwrap();
+
+        assert_eq!(
+            page.url().unwrap().as_str(),
+            "view-source:https://example.com/index.html"
+        );
+        assert!(page.security_state().is_secure());
+        assert_eq!(
+            page.view_source_url().unwrap().as_str(),
+            "https://example.com/index.html"
+        );
+
+        page.set_content("<a href=\"/about\">About</a>");
+        let content = page.content();
+        assert!(content.contains("<td class=\"line-number\">1</td>"));
+        assert!(content.contains("href=\"https://example.com/about\""));
+
+        page.navigate("VIEW-SOURCE:example.com/").await.unwrap();
+        assert_eq!(page.view_source_url().unwrap().as_str(), "https://example.com/");
+    }
+
+    #[tokio::test]
+    async fn test_page_stylesheets() {
+        let page = Page::new(BrowserConfig::default());
+        page.navigate("https://example.com/index.html").await.unwrap();
+        let html = "<link rel=stylesheet href=site.css><p>text</p>";
+        page.set_content(html);
+        let sources = std::collections::HashMap::from([
+            (
+                Url::parse("https://example.com/site.css").unwrap(),
+                "@import \"theme.css\";".to_string(),
+            ),
+            (
+                Url::parse("https://example.com/theme.css").unwrap(),
+                "p { margin-top: 5px }".to_string(),
+            ),
+        ]);
+        page.load_stylesheets(&sources).await;
+
+        let document = html_parser::parse_html(html, page.url().unwrap());
+        let p = document.tree.find_elements_by_tag_name("p")[0];
+        let mut styles = page.styles.write();
+        styles.resolve_document(&document);
+        assert_eq!(styles.get_style(p).unwrap().margin.top.resolve(0.0), 5.0);
+    }
+
     #[test]
     fn test_navigation_history() {
         let mut history = NavigationHistory::new();
@@ -320,4 +507,31 @@ mod tests {
         history.back();
         assert!(history.can_go_forward());
     }
+
+    #[test]
+    fn test_form_request() {
+        use dom::{FormEntry, FormMethod};
+
+        let mut submission = FormSubmission {
+            action: Url::parse("https://example.com/search#results").unwrap(),
+            method: FormMethod::Get,
+            enctype: Default::default(),
+            target: String::new(),
+            entries: vec![FormEntry::text("q", "a b"), FormEntry::text("lang", "en")],
+        };
+        let request = form_request(&submission);
+        assert_eq!(request.method, http::Method::GET);
+        assert_eq!(request.url.as_str(), "https://example.com/search?q=a+b&lang=en#results");
+        assert!(request.body.is_none());
+
+        submission.method = FormMethod::Post;
+        let request = form_request(&submission);
+        assert_eq!(request.method, http::Method::POST);
+        assert_eq!(request.url.as_str(), "https://example.com/search#results");
+        assert_eq!(
+            request.headers.get("Content-Type").map(String::as_str),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(request.body.as_deref(), Some(&b"q=a+b&lang=en"[..]));
+    }
 }
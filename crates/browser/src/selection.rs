@@ -0,0 +1,165 @@
+//! Mouse-driven text selection.
+
+use common::geometry::Rect;
+use dom::document::Document;
+use html_parser::serialize_range;
+use layout::LayoutTree;
+
+/// Selected content in the formats offered to the clipboard.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClipboardContents {
+    /// `text/plain`.
+    pub text: String,
+    /// `text/html`.
+    pub html: String,
+}
+
+/// Turns pointer drags over a laid-out page into the document's selection.
+#[derive(Debug, Default)]
+pub struct SelectionController {
+    dragging: bool,
+}
+
+impl SelectionController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a drag is in progress.
+    pub fn is_dragging(&self) -> bool {
+        self.dragging
+    }
+
+    /// Primary button pressed: place a caret, or extend the selection to the
+    /// pointer when `extend` (shift) is held. Returns whether the selection
+    /// changed.
+    pub fn pointer_down(
+        &mut self,
+        document: &Document,
+        layout: &LayoutTree,
+        x: f32,
+        y: f32,
+        extend: bool,
+    ) -> bool {
+        let point = match layout.caret_position_from_point(x, y) {
+            Some(point) => point,
+            None => return false,
+        };
+        self.dragging = true;
+        let mut selection = document.selection.lock();
+        if extend && selection.range().is_some() {
+            selection
+                .extend(&document.tree, point.node, point.offset)
+                .is_ok()
+        } else {
+            selection
+                .collapse(&document.tree, point.node, point.offset)
+                .is_ok()
+        }
+    }
+
+    /// Pointer moved: extend the selection while dragging.
+    pub fn pointer_move(
+        &mut self,
+        document: &Document,
+        layout: &LayoutTree,
+        x: f32,
+        y: f32,
+    ) -> bool {
+        if !self.dragging {
+            return false;
+        }
+        match layout.caret_position_from_point(x, y) {
+            Some(point) => document
+                .selection
+                .lock()
+                .extend(&document.tree, point.node, point.offset)
+                .is_ok(),
+            None => false,
+        }
+    }
+
+    /// Primary button released.
+    pub fn pointer_up(&mut self) {
+        self.dragging = false;
+    }
+
+    /// Select the whole body (Ctrl+A).
+    pub fn select_all(&mut self, document: &Document) -> bool {
+        let target = match document.body.or(document.tree.root()) {
+            Some(target) => target,
+            None => return false,
+        };
+        document
+            .selection
+            .lock()
+            .select_all_children(&document.tree, target)
+            .is_ok()
+    }
+
+    /// Rects to paint as the selection highlight.
+    pub fn highlight_rects(&self, document: &Document, layout: &LayoutTree) -> Vec<Rect> {
+        match document.selection.lock().range() {
+            Some(range) if !range.collapsed() => layout.range_rects(&document.tree, range),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The selected content for a copy, or `None` when nothing is selected.
+    pub fn copy(&self, document: &mut Document) -> Option<ClipboardContents> {
+        let range = document.selection.lock().range().cloned()?;
+        if range.collapsed() {
+            return None;
+        }
+        Some(ClipboardContents {
+            text: range.to_string(&document.tree),
+            html: serialize_range(&mut document.tree, &range),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use layout::LayoutEngine;
+    use style::query::QuerySelector;
+    use style::StyleResolver;
+    use url::Url;
+
+    #[test]
+    fn test_drag_selects_and_copies() {
+        let mut document = html_parser::parse_html(
+            "<html><body><p>Hello <b>bold</b></p></body></html>",
+            Url::parse("about:blank").unwrap(),
+        );
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        style_resolver.resolve_document(&document);
+        let layout = LayoutEngine::new(800.0, 600.0).layout(&document, &style_resolver);
+
+        let p = document.tree.query_selector("p").unwrap().unwrap();
+        let hello = document.tree.first_child(p).unwrap();
+        let rect = layout
+            .get(layout.find_by_node(hello).unwrap())
+            .unwrap()
+            .content_rect();
+        let y = rect.y + rect.height / 2.0;
+
+        let mut controller = SelectionController::new();
+        assert!(controller.pointer_down(&document, &layout, rect.x + rect.width - 1.0, y, false));
+        assert!(document.selection.lock().is_collapsed());
+        assert!(controller.pointer_move(&document, &layout, rect.x + 1.0, y));
+        controller.pointer_up();
+        assert!(!controller.pointer_move(&document, &layout, rect.x + 500.0, y));
+
+        assert_eq!(controller.highlight_rects(&document, &layout).len(), 1);
+        let copied = controller.copy(&mut document).unwrap();
+        assert_eq!(copied.text, "Hello ");
+        assert_eq!(copied.html, "Hello ");
+
+        controller.select_all(&document);
+        let copied = controller.copy(&mut document).unwrap();
+        assert_eq!(copied.text, "Hello bold");
+        assert_eq!(copied.html, "<p>Hello <b>bold</b></p>");
+    }
+}
@@ -0,0 +1,192 @@
+//! Stylesheet loading.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+
+use css_parser::import::{resolve_imports, LoadedStylesheet, StylesheetLoader};
+use css_parser::Stylesheet;
+use dom::Document;
+use networking::ResourceLoader;
+use style::resolver::StyleResolver;
+use url::Url;
+
+/// Fetches stylesheets over the network, or from fixed sources in tests.
+pub trait StylesheetFetcher {
+    /// Fetch `urls` in parallel, giving `None` for each that failed.
+    fn fetch_all(&self, urls: &[Url]) -> impl Future<Output = Vec<Option<LoadedStylesheet>>>;
+}
+
+impl StylesheetFetcher for ResourceLoader {
+    async fn fetch_all(&self, urls: &[Url]) -> Vec<Option<LoadedStylesheet>> {
+        let urls: Vec<&str> = urls.iter().map(Url::as_str).collect();
+        self.load_all(&urls)
+            .await
+            .into_iter()
+            .map(|result| {
+                result.ok().map(|resource| LoadedStylesheet {
+                    url: resource.url,
+                    css: String::from_utf8_lossy(&resource.data).into_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Stylesheet sources by URL.
+impl StylesheetFetcher for HashMap<Url, String> {
+    async fn fetch_all(&self, urls: &[Url]) -> Vec<Option<LoadedStylesheet>> {
+        urls.iter()
+            .map(|url| StylesheetLoader::load(self, url))
+            .collect()
+    }
+}
+
+/// Parse the stylesheet `css` found at `url` and fetch what it imports.
+///
+/// Imports are resolved against the stylesheets fetched so far; each round
+/// fetches the ones that were missing in parallel, until the imports of
+/// every fetched stylesheet have been tried.
+pub async fn load_stylesheet<F: StylesheetFetcher>(fetcher: &F, css: &str, url: Url) -> Stylesheet {
+    let mut sheet = css_parser::parse_css(css, url);
+    let mut fetched = FetchedStylesheets::default();
+    loop {
+        resolve_imports(&mut sheet, &fetched);
+        let missing = fetched.missing.take();
+        if missing.is_empty() {
+            return sheet;
+        }
+        let results = fetcher.fetch_all(&missing).await;
+        fetched.sheets.extend(missing.into_iter().zip(results));
+    }
+}
+
+/// Add the author stylesheets of `document` to `resolver` in tree order:
+/// the contents of `<style>` elements and the targets of
+/// `<link rel=stylesheet>`, each with its imports. Linked stylesheets that
+/// fail to load are skipped.
+pub async fn load_document_stylesheets<F: StylesheetFetcher>(
+    fetcher: &F,
+    document: &Document,
+    resolver: &mut StyleResolver,
+) {
+    enum Source {
+        Inline(String),
+        Linked(Url),
+    }
+
+    let tree = &document.tree;
+    let sources: Vec<Source> = tree
+        .root()
+        .into_iter()
+        .flat_map(|root| tree.descendants(root))
+        .filter_map(|node| {
+            let element = tree.get_element(node).filter(|e| e.namespace.is_none())?;
+            match element.tag_name.as_str() {
+                "style" => Some(Source::Inline(tree.get_text_content(node))),
+                "link" => {
+                    let rel = element.get_attribute("rel")?;
+                    if !rel
+                        .split_ascii_whitespace()
+                        .any(|token| token.eq_ignore_ascii_case("stylesheet"))
+                    {
+                        return None;
+                    }
+                    let href = element.get_attribute("href")?;
+                    document.base_url.join(href).ok().map(Source::Linked)
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    let linked: Vec<Url> = sources
+        .iter()
+        .filter_map(|source| match source {
+            Source::Linked(url) => Some(url.clone()),
+            Source::Inline(_) => None,
+        })
+        .collect();
+    let mut fetched = fetcher.fetch_all(&linked).await.into_iter();
+    for source in sources {
+        let sheet = match source {
+            Source::Inline(css) => load_stylesheet(fetcher, &css, document.base_url.clone()).await,
+            Source::Linked(_) => match fetched.next().flatten() {
+                Some(loaded) => load_stylesheet(fetcher, &loaded.css, loaded.url).await,
+                None => continue,
+            },
+        };
+        resolver.add_stylesheet(sheet);
+    }
+}
+
+/// Stylesheets fetched so far, recording the URLs asked for that have not
+/// been fetched yet.
+#[derive(Default)]
+struct FetchedStylesheets {
+    /// Fetched stylesheets by requested URL; `None` if the fetch failed.
+    sheets: HashMap<Url, Option<LoadedStylesheet>>,
+    missing: RefCell<Vec<Url>>,
+}
+
+impl StylesheetLoader for FetchedStylesheets {
+    fn load(&self, url: &Url) -> Option<LoadedStylesheet> {
+        match self.sheets.get(url) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let mut missing = self.missing.borrow_mut();
+                if !missing.contains(url) {
+                    missing.push(url.clone());
+                }
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_imported_rules_reach_the_cascade() {
+        let url = |path: &str| {
+            Url::parse("https://example.com/")
+                .unwrap()
+                .join(path)
+                .unwrap()
+        };
+        let sources = HashMap::from([
+            (
+                url("/css/site.css"),
+                "@import url(\"base.css\"); p { margin-left: 2px }".to_string(),
+            ),
+            (
+                url("/css/base.css"),
+                "p { margin-top: 1px; margin-left: 1px }".to_string(),
+            ),
+            (
+                url("/inline.css"),
+                "p { margin-bottom: 3px; margin-left: 3px }".to_string(),
+            ),
+        ]);
+        let document = html_parser::parse_html(
+            "<link rel=\"preload stylesheet\" href=\"css/site.css\">\
+             <link rel=\"stylesheet\" href=\"missing.css\">\
+             <style>@import \"inline.css\"; p { margin-right: 4px }</style><p>text</p>",
+            url("/page.html"),
+        );
+
+        let mut resolver = StyleResolver::new();
+        load_document_stylesheets(&sources, &document, &mut resolver).await;
+        resolver.resolve_document(&document);
+        let p = document.tree.find_elements_by_tag_name("p")[0];
+        let style = resolver.get_style(p).unwrap();
+        // Imported rules come before the rules of the importing sheet, and
+        // linked sheets before later `<style>` elements.
+        assert_eq!(style.margin.top.resolve(0.0), 1.0);
+        assert_eq!(style.margin.bottom.resolve(0.0), 3.0);
+        assert_eq!(style.margin.right.resolve(0.0), 4.0);
+        assert_eq!(style.margin.left.resolve(0.0), 3.0);
+    }
+}
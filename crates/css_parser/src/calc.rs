@@ -0,0 +1,557 @@
+//! Math functions: `calc()`, `min()`, `max()` and `clamp()`.
+//!
+//! Expressions are parsed into a typed tree and simplified: numbers fold,
+//! terms with the same unit combine and absolute units are converted to
+//! their canonical unit (px, deg, s, dppx). Relative units are resolved at
+//! computed-value time and percentages, when they depend on layout, at
+//! used-value time.
+
+use crate::parser::CssParseError;
+use crate::values::{format_number, CssValue};
+use cssparser::{ParseError, Parser, Token};
+use std::fmt;
+
+/// The type of a math expression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    /// A mix of lengths and percentages.
+    LengthPercentage,
+    Angle,
+    Time,
+    Resolution,
+}
+
+impl CalcType {
+    /// The type of a sum, or of a comparison function, of two types.
+    fn add(self, other: CalcType) -> Option<CalcType> {
+        use CalcType::*;
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (Length | Percentage | LengthPercentage, Length | Percentage | LengthPercentage) => {
+                Some(LengthPercentage)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A node of a math expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CalcNode {
+    Number(f32),
+    Percentage(f32),
+    /// A dimension, with absolute units in their canonical unit.
+    Dimension(f32, String),
+    Sum(Vec<CalcNode>),
+    /// A node scaled by a number.
+    Product(Box<CalcNode>, f32),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    /// `clamp(min, value, max)`.
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+/// Whether `name` is a math function.
+pub fn is_math_function(name: &str) -> bool {
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "calc" | "min" | "max" | "clamp"
+    )
+}
+
+/// The type of values with the given unit, if it is a known unit.
+pub fn unit_type(unit: &str) -> Option<CalcType> {
+    unit_info(&unit.to_ascii_lowercase()).map(|(ty, _)| ty)
+}
+
+/// The type of a unit and, for absolute units, its factor to the canonical
+/// unit of that type.
+fn unit_info(unit: &str) -> Option<(CalcType, Option<f32>)> {
+    use CalcType::*;
+    let info = match unit {
+        "px" => (Length, Some(1.0)),
+        "cm" => (Length, Some(96.0 / 2.54)),
+        "mm" => (Length, Some(96.0 / 25.4)),
+        "q" => (Length, Some(96.0 / 101.6)),
+        "in" => (Length, Some(96.0)),
+        "pt" => (Length, Some(96.0 / 72.0)),
+        "pc" => (Length, Some(16.0)),
+        "em" | "rem" | "ex" | "rex" | "ch" | "rch" | "cap" | "ic" | "lh" | "rlh" | "vw" | "vh"
+        | "vi" | "vb" | "vmin" | "vmax" | "svw" | "svh" | "lvw" | "lvh" | "dvw" | "dvh" | "cqw"
+        | "cqh" | "cqi" | "cqb" | "cqmin" | "cqmax" => (Length, None),
+        "deg" => (Angle, Some(1.0)),
+        "grad" => (Angle, Some(0.9)),
+        "rad" => (Angle, Some(180.0 / std::f32::consts::PI)),
+        "turn" => (Angle, Some(360.0)),
+        "s" => (Time, Some(1.0)),
+        "ms" => (Time, Some(0.001)),
+        "dppx" | "x" => (Resolution, Some(1.0)),
+        "dpi" => (Resolution, Some(1.0 / 96.0)),
+        "dpcm" => (Resolution, Some(2.54 / 96.0)),
+        _ => return None,
+    };
+    Some(info)
+}
+
+fn canonical_unit(ty: CalcType) -> &'static str {
+    match ty {
+        CalcType::Angle => "deg",
+        CalcType::Time => "s",
+        CalcType::Resolution => "dppx",
+        _ => "px",
+    }
+}
+
+impl CalcNode {
+    /// A dimension in its canonical unit when the unit is absolute.
+    pub fn dimension(value: f32, unit: &str) -> Option<CalcNode> {
+        let unit = unit.to_ascii_lowercase();
+        let (ty, factor) = unit_info(&unit)?;
+        Some(match factor {
+            Some(factor) => CalcNode::Dimension(value * factor, canonical_unit(ty).to_string()),
+            None => CalcNode::Dimension(value, unit),
+        })
+    }
+
+    /// The type of the expression, `None` if it mixes incompatible types.
+    pub fn ty(&self) -> Option<CalcType> {
+        match self {
+            CalcNode::Number(_) => Some(CalcType::Number),
+            CalcNode::Percentage(_) => Some(CalcType::Percentage),
+            CalcNode::Dimension(_, unit) => unit_info(unit).map(|(ty, _)| ty),
+            CalcNode::Product(node, _) => node.ty(),
+            CalcNode::Sum(nodes) | CalcNode::Min(nodes) | CalcNode::Max(nodes) => {
+                let mut nodes = nodes.iter();
+                let first = nodes.next()?.ty()?;
+                nodes.try_fold(first, |ty, node| ty.add(node.ty()?))
+            }
+            CalcNode::Clamp(min, value, max) => min.ty()?.add(value.ty()?)?.add(max.ty()?),
+        }
+    }
+
+    /// Whether the expression contains percentages.
+    pub fn has_percentage(&self) -> bool {
+        match self {
+            CalcNode::Percentage(_) => true,
+            CalcNode::Number(_) | CalcNode::Dimension(..) => false,
+            CalcNode::Product(node, _) => node.has_percentage(),
+            CalcNode::Sum(nodes) | CalcNode::Min(nodes) | CalcNode::Max(nodes) => {
+                nodes.iter().any(CalcNode::has_percentage)
+            }
+            CalcNode::Clamp(min, value, max) => {
+                min.has_percentage() || value.has_percentage() || max.has_percentage()
+            }
+        }
+    }
+
+    /// Value and unit of a leaf; numbers have an empty unit and percentages
+    /// `%`.
+    fn leaf(&self) -> Option<(f32, &str)> {
+        match self {
+            CalcNode::Number(n) => Some((*n, "")),
+            CalcNode::Percentage(p) => Some((*p, "%")),
+            CalcNode::Dimension(v, unit) => Some((*v, unit)),
+            _ => None,
+        }
+    }
+
+    fn from_leaf(value: f32, unit: &str) -> CalcNode {
+        match unit {
+            "" => CalcNode::Number(value),
+            "%" => CalcNode::Percentage(value),
+            unit => CalcNode::Dimension(value, unit.to_string()),
+        }
+    }
+
+    /// Fold numbers and combine terms that have the same unit.
+    pub fn simplify(self) -> CalcNode {
+        match self {
+            CalcNode::Product(node, factor) => match node.simplify() {
+                CalcNode::Product(node, inner) => {
+                    CalcNode::Product(node, inner * factor).simplify()
+                }
+                CalcNode::Sum(terms) => CalcNode::Sum(
+                    terms
+                        .into_iter()
+                        .map(|term| CalcNode::Product(Box::new(term), factor))
+                        .collect(),
+                )
+                .simplify(),
+                node => match node.leaf() {
+                    Some((value, unit)) => CalcNode::from_leaf(value * factor, unit),
+                    None if factor == 1.0 => node,
+                    None => CalcNode::Product(Box::new(node), factor),
+                },
+            },
+            CalcNode::Sum(terms) => {
+                let mut result: Vec<CalcNode> = Vec::new();
+                let mut flat = Vec::new();
+                for term in terms {
+                    match term.simplify() {
+                        CalcNode::Sum(inner) => flat.extend(inner),
+                        term => flat.push(term),
+                    }
+                }
+                for term in flat {
+                    let merged = term.leaf().and_then(|(value, unit)| {
+                        let existing = result
+                            .iter_mut()
+                            .find(|t| t.leaf().is_some_and(|(_, u)| u == unit))?;
+                        let (sum, unit) =
+                            existing.leaf().map(|(v, u)| (v + value, u.to_string()))?;
+                        *existing = CalcNode::from_leaf(sum, &unit);
+                        Some(())
+                    });
+                    if merged.is_none() {
+                        result.push(term);
+                    }
+                }
+                if result.len() == 1 {
+                    result.pop().unwrap()
+                } else {
+                    CalcNode::Sum(result)
+                }
+            }
+            CalcNode::Min(args) => fold(args, f32::min).unwrap_or_else(CalcNode::Min),
+            CalcNode::Max(args) => fold(args, f32::max).unwrap_or_else(CalcNode::Max),
+            CalcNode::Clamp(min, value, max) => {
+                let (min, value, max) = (min.simplify(), value.simplify(), max.simplify());
+                match (min.leaf(), value.leaf(), max.leaf()) {
+                    (Some((lo, a)), Some((v, b)), Some((hi, c))) if a == b && b == c => {
+                        CalcNode::from_leaf(v.min(hi).max(lo), a)
+                    }
+                    _ => CalcNode::Clamp(Box::new(min), Box::new(value), Box::new(max)),
+                }
+            }
+            leaf => leaf,
+        }
+    }
+
+    /// Resolve relative units with `to_px`, e.g. at computed-value time,
+    /// and simplify the result. Units it does not resolve are kept.
+    pub fn compute(&self, to_px: &mut dyn FnMut(f32, &str) -> Option<f32>) -> CalcNode {
+        self.map_leaves(&mut |node| match node {
+            CalcNode::Dimension(value, unit)
+                if unit != "px"
+                    && unit_info(unit).is_some_and(|(ty, _)| ty == CalcType::Length) =>
+            {
+                to_px(*value, unit).map(|px| CalcNode::Dimension(px, "px".to_string()))
+            }
+            _ => None,
+        })
+        .simplify()
+    }
+
+    fn map_leaves(&self, f: &mut dyn FnMut(&CalcNode) -> Option<CalcNode>) -> CalcNode {
+        let map = |nodes: &[CalcNode], f: &mut dyn FnMut(&CalcNode) -> Option<CalcNode>| {
+            nodes.iter().map(|node| node.map_leaves(f)).collect()
+        };
+        match self {
+            CalcNode::Sum(nodes) => CalcNode::Sum(map(nodes, f)),
+            CalcNode::Min(nodes) => CalcNode::Min(map(nodes, f)),
+            CalcNode::Max(nodes) => CalcNode::Max(map(nodes, f)),
+            CalcNode::Product(node, factor) => {
+                CalcNode::Product(Box::new(node.map_leaves(f)), *factor)
+            }
+            CalcNode::Clamp(min, value, max) => CalcNode::Clamp(
+                Box::new(min.map_leaves(f)),
+                Box::new(value.map_leaves(f)),
+                Box::new(max.map_leaves(f)),
+            ),
+            leaf => f(leaf).unwrap_or_else(|| leaf.clone()),
+        }
+    }
+
+    /// Evaluate the expression, with percentages of `percentage_basis`, in
+    /// the canonical unit of its type. Returns `None` if relative units are
+    /// left.
+    pub fn resolve(&self, percentage_basis: f32) -> Option<f32> {
+        let all = |nodes: &[CalcNode]| -> Option<Vec<f32>> {
+            nodes
+                .iter()
+                .map(|node| node.resolve(percentage_basis))
+                .collect()
+        };
+        match self {
+            CalcNode::Number(n) => Some(*n),
+            CalcNode::Percentage(p) => Some(percentage_basis * p / 100.0),
+            CalcNode::Dimension(value, unit) => {
+                let (ty, factor) = unit_info(unit)?;
+                (factor.is_some() && unit == canonical_unit(ty)).then_some(*value)
+            }
+            CalcNode::Sum(nodes) => Some(all(nodes)?.into_iter().sum()),
+            CalcNode::Product(node, factor) => Some(node.resolve(percentage_basis)? * factor),
+            CalcNode::Min(nodes) => all(nodes)?.into_iter().reduce(f32::min),
+            CalcNode::Max(nodes) => all(nodes)?.into_iter().reduce(f32::max),
+            CalcNode::Clamp(min, value, max) => {
+                let (min, value, max) = (
+                    min.resolve(percentage_basis)?,
+                    value.resolve(percentage_basis)?,
+                    max.resolve(percentage_basis)?,
+                );
+                Some(value.min(max).max(min))
+            }
+        }
+    }
+
+    /// Convert to a plain value when the expression simplified to one.
+    pub fn into_value(self) -> CssValue {
+        match self {
+            CalcNode::Number(n) => CssValue::Number(n),
+            CalcNode::Percentage(p) => CssValue::Percentage(p),
+            CalcNode::Dimension(value, unit) => CssValue::Dimension(value, unit),
+            node => CssValue::Calc(Box::new(node)),
+        }
+    }
+
+    fn write_inner(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcNode::Sum(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    let negated = match term {
+                        CalcNode::Product(node, factor) if *factor < 0.0 && i > 0 => {
+                            Some(CalcNode::Product(node.clone(), -factor).simplify())
+                        }
+                        term if i > 0 => term
+                            .leaf()
+                            .filter(|(v, _)| *v < 0.0)
+                            .map(|(v, u)| CalcNode::from_leaf(-v, u)),
+                        _ => None,
+                    };
+                    match (i, negated) {
+                        (0, _) => term.write_inner(f)?,
+                        (_, Some(term)) => {
+                            write!(f, " - ")?;
+                            term.write_inner(f)?;
+                        }
+                        (_, None) => {
+                            write!(f, " + ")?;
+                            term.write_inner(f)?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            CalcNode::Product(node, factor) => {
+                write!(f, "{} * ", format_number(*factor))?;
+                match node.as_ref() {
+                    CalcNode::Sum(_) => {
+                        write!(f, "(")?;
+                        node.write_inner(f)?;
+                        write!(f, ")")
+                    }
+                    node => node.write_inner(f),
+                }
+            }
+            CalcNode::Number(n) => write!(f, "{}", format_number(*n)),
+            CalcNode::Percentage(p) => write!(f, "{}%", format_number(*p)),
+            CalcNode::Dimension(value, unit) => write!(f, "{}{}", format_number(*value), unit),
+            node => write!(f, "{}", node),
+        }
+    }
+}
+
+impl fmt::Display for CalcNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, args): (&str, Vec<&CalcNode>) = match self {
+            CalcNode::Min(nodes) => ("min", nodes.iter().collect()),
+            CalcNode::Max(nodes) => ("max", nodes.iter().collect()),
+            CalcNode::Clamp(min, value, max) => ("clamp", vec![min, value, max]),
+            node => ("calc", vec![node]),
+        };
+        write!(f, "{}(", name)?;
+        for (i, arg) in args.into_iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            arg.write_inner(f)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Fold a comparison function whose arguments all simplified to leaves
+/// with the same unit; returns the simplified arguments otherwise.
+fn fold(args: Vec<CalcNode>, op: fn(f32, f32) -> f32) -> Result<CalcNode, Vec<CalcNode>> {
+    let args: Vec<CalcNode> = args.into_iter().map(CalcNode::simplify).collect();
+    let unit = args
+        .first()
+        .and_then(|arg| arg.leaf())
+        .map(|(_, unit)| unit.to_string());
+    let folded = unit.as_deref().and_then(|unit| {
+        args.iter()
+            .map(|arg| arg.leaf().filter(|(_, u)| *u == unit).map(|(v, _)| v))
+            .collect::<Option<Vec<f32>>>()?
+            .into_iter()
+            .reduce(op)
+            .map(|value| CalcNode::from_leaf(value, unit))
+    });
+    folded.ok_or(args)
+}
+
+type CalcResult<'i> = Result<CalcNode, ParseError<'i, CssParseError<'i>>>;
+
+/// Parse the arguments of the math function `name`, type-check them and
+/// simplify the result.
+pub(crate) fn parse_function<'i>(name: &str, input: &mut Parser<'i, '_>) -> CalcResult<'i> {
+    let node = parse_arguments(name, input)?;
+    if node.ty().is_none() {
+        return Err(input.new_custom_error(CssParseError::InvalidValue));
+    }
+    Ok(node.simplify())
+}
+
+fn parse_arguments<'i>(name: &str, input: &mut Parser<'i, '_>) -> CalcResult<'i> {
+    match name.to_ascii_lowercase().as_str() {
+        "calc" => parse_sum(input),
+        "min" => Ok(CalcNode::Min(input.parse_comma_separated(parse_sum)?)),
+        "max" => Ok(CalcNode::Max(input.parse_comma_separated(parse_sum)?)),
+        "clamp" => {
+            let mut args = input.parse_comma_separated(parse_sum)?;
+            if args.len() != 3 {
+                return Err(input.new_custom_error(CssParseError::InvalidValue));
+            }
+            let max = args.pop().unwrap();
+            let value = args.pop().unwrap();
+            let min = args.pop().unwrap();
+            Ok(CalcNode::Clamp(
+                Box::new(min),
+                Box::new(value),
+                Box::new(max),
+            ))
+        }
+        _ => Err(input.new_custom_error(CssParseError::InvalidValue)),
+    }
+}
+
+fn parse_sum<'i>(input: &mut Parser<'i, '_>) -> CalcResult<'i> {
+    let mut terms = vec![parse_product(input)?];
+    loop {
+        let state = input.state();
+        match input.next() {
+            Ok(Token::Delim('+')) => terms.push(parse_product(input)?),
+            Ok(Token::Delim('-')) => {
+                terms.push(CalcNode::Product(Box::new(parse_product(input)?), -1.0));
+            }
+            Ok(_) => return Err(input.new_custom_error(CssParseError::InvalidValue)),
+            Err(_) => {
+                input.reset(&state);
+                break;
+            }
+        }
+    }
+    Ok(if terms.len() == 1 {
+        terms.pop().unwrap()
+    } else {
+        CalcNode::Sum(terms)
+    })
+}
+
+fn parse_product<'i>(input: &mut Parser<'i, '_>) -> CalcResult<'i> {
+    let mut node = parse_value(input)?;
+    loop {
+        let state = input.state();
+        let multiply = match input.next() {
+            Ok(Token::Delim('*')) => true,
+            Ok(Token::Delim('/')) => false,
+            _ => {
+                input.reset(&state);
+                return Ok(node);
+            }
+        };
+        let rhs = parse_value(input)?.simplify();
+        node = match (node.simplify(), rhs) {
+            (CalcNode::Number(factor), rhs) if multiply => CalcNode::Product(Box::new(rhs), factor),
+            (lhs, CalcNode::Number(factor)) if multiply => CalcNode::Product(Box::new(lhs), factor),
+            (lhs, CalcNode::Number(divisor)) if !multiply => {
+                CalcNode::Product(Box::new(lhs), 1.0 / divisor)
+            }
+            _ => return Err(input.new_custom_error(CssParseError::InvalidValue)),
+        };
+    }
+}
+
+fn parse_value<'i>(input: &mut Parser<'i, '_>) -> CalcResult<'i> {
+    let location = input.current_source_location();
+    let invalid = || location.new_custom_error(CssParseError::InvalidValue);
+    match input.next()?.clone() {
+        Token::Number { value, .. } => Ok(CalcNode::Number(value)),
+        Token::Percentage { unit_value, .. } => Ok(CalcNode::Percentage(unit_value * 100.0)),
+        Token::Dimension { value, unit, .. } => {
+            CalcNode::dimension(value, &unit).ok_or_else(invalid)
+        }
+        Token::Ident(name) => match name.to_ascii_lowercase().as_str() {
+            "pi" => Ok(CalcNode::Number(std::f32::consts::PI)),
+            "e" => Ok(CalcNode::Number(std::f32::consts::E)),
+            "infinity" => Ok(CalcNode::Number(f32::INFINITY)),
+            "-infinity" => Ok(CalcNode::Number(f32::NEG_INFINITY)),
+            _ => Err(invalid()),
+        },
+        Token::ParenthesisBlock => input.parse_nested_block(parse_sum),
+        Token::Function(name) if is_math_function(&name) => {
+            input.parse_nested_block(|input| parse_arguments(&name, input))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cssparser::ParserInput;
+
+    fn parse(css: &str) -> Option<CalcNode> {
+        let mut input = ParserInput::new(css);
+        let mut parser = Parser::new(&mut input);
+        let name = parser.expect_function().ok()?.to_string();
+        parser
+            .parse_nested_block(|input| parse_function(&name, input))
+            .ok()
+    }
+
+    #[test]
+    fn test_parse_and_simplify() {
+        assert_eq!(
+            parse("calc(1in + 2 * 8px - 12px)"),
+            Some(CalcNode::Dimension(100.0, "px".to_string()))
+        );
+        assert_eq!(parse("calc((2 + 3) * 2 / 4)"), Some(CalcNode::Number(2.5)));
+        assert_eq!(
+            parse("max(10px, 1in, 2pt)"),
+            Some(CalcNode::Dimension(96.0, "px".to_string()))
+        );
+
+        let mixed = parse("calc(100% - 2rem + 10px - 4px)").unwrap();
+        assert_eq!(mixed.ty(), Some(CalcType::LengthPercentage));
+        assert_eq!(mixed.to_string(), "calc(100% - 2rem + 6px)");
+        let computed = mixed.compute(&mut |value, unit| (unit == "rem").then_some(value * 16.0));
+        assert_eq!(computed.to_string(), "calc(100% - 26px)");
+        assert_eq!(computed.resolve(200.0), Some(174.0));
+        assert_eq!(mixed.resolve(200.0), None);
+
+        let clamp = parse("clamp(1rem, 2.5vw, 40px)").unwrap();
+        assert_eq!(clamp.to_string(), "clamp(1rem, 2.5vw, 40px)");
+        let to_px = &mut |value, unit: &str| {
+            Some(if unit == "vw" {
+                value * 8.0
+            } else {
+                value * 16.0
+            })
+        };
+        assert_eq!(
+            clamp.compute(to_px),
+            CalcNode::Dimension(20.0, "px".to_string())
+        );
+
+        // Type errors and invalid syntax make the whole value invalid.
+        assert_eq!(parse("calc(1px + 2)"), None);
+        assert_eq!(parse("calc(1px * 2px)"), None);
+        assert_eq!(parse("calc(4px / 2px)"), None);
+        assert_eq!(parse("calc(1px +2px)"), None);
+        assert_eq!(parse("clamp(1px, 2px)"), None);
+        assert_eq!(parse("min(1px, 10deg)"), None);
+    }
+}
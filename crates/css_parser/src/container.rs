@@ -0,0 +1,385 @@
+//! Container queries.
+//!
+//! An `@container` rule applies to an element depending on the size of its
+//! nearest ancestor query container. Container sizes come from layout, so
+//! conditions are evaluated against a `ContainerSize` the caller provides.
+//! Queries a container cannot answer are unknown, and unknown conditions
+//! do not match.
+
+use crate::calc;
+use crate::parser::CssParseError;
+use crate::values::CssValue;
+use cssparser::{ParseError, Parser, Token};
+
+/// Content-box size of a query container. Axes the container does not
+/// establish size containment in are `None`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContainerSize {
+    pub width: Option<f32>,
+    pub height: Option<f32>,
+    /// Computed font size of the container, for `em` in queries.
+    pub font_size: f32,
+}
+
+/// A comparison in a size feature, as `feature <op> value`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+impl Comparison {
+    /// The comparison with its operands swapped.
+    fn flip(self) -> Self {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Eq => Comparison::Eq,
+            Comparison::Ge => Comparison::Le,
+            Comparison::Gt => Comparison::Lt,
+        }
+    }
+
+    fn test(self, actual: f32, expected: f32) -> bool {
+        match self {
+            Comparison::Lt => actual < expected,
+            Comparison::Le => actual <= expected,
+            Comparison::Eq => actual == expected,
+            Comparison::Ge => actual >= expected,
+            Comparison::Gt => actual > expected,
+        }
+    }
+}
+
+/// A size feature such as `(min-width: 400px)` or `(400px < width <= 800px)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizeFeature {
+    /// Feature name, without `min-`/`max-` prefix.
+    pub name: String,
+    /// Comparisons the feature value must all pass; empty in boolean
+    /// context.
+    pub comparisons: Vec<(Comparison, CssValue)>,
+}
+
+impl SizeFeature {
+    fn evaluate(&self, size: &ContainerSize) -> Option<bool> {
+        if self.name == "orientation" {
+            let portrait = size.height? >= size.width?;
+            return match self.comparisons.as_slice() {
+                [] => Some(true),
+                [(Comparison::Eq, CssValue::Ident(value))] => match value.as_str() {
+                    "portrait" => Some(portrait),
+                    "landscape" => Some(!portrait),
+                    _ => None,
+                },
+                _ => None,
+            };
+        }
+
+        let ratio = self.name == "aspect-ratio";
+        let actual = match self.name.as_str() {
+            "width" | "inline-size" => size.width?,
+            "height" | "block-size" => size.height?,
+            "aspect-ratio" => size.width? / size.height?,
+            _ => return None,
+        };
+        if self.comparisons.is_empty() {
+            return Some(actual != 0.0);
+        }
+        let mut result = true;
+        for (comparison, value) in &self.comparisons {
+            let expected = if ratio {
+                value.as_number()?
+            } else {
+                length(value, size)?
+            };
+            result &= comparison.test(actual, expected);
+        }
+        Some(result)
+    }
+
+    fn queries_block_axis(&self) -> bool {
+        matches!(
+            self.name.as_str(),
+            "height" | "block-size" | "aspect-ratio" | "orientation"
+        )
+    }
+}
+
+/// A `<container-condition>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ContainerCondition {
+    Feature(SizeFeature),
+    Not(Box<ContainerCondition>),
+    And(Vec<ContainerCondition>),
+    Or(Vec<ContainerCondition>),
+    /// A query this engine does not understand, which is always unknown.
+    Unknown,
+}
+
+impl ContainerCondition {
+    /// Whether the condition matches a container of the given size.
+    pub fn matches(&self, size: &ContainerSize) -> bool {
+        self.evaluate(size) == Some(true)
+    }
+
+    /// Evaluate the condition with three-valued logic; `None` is unknown.
+    pub fn evaluate(&self, size: &ContainerSize) -> Option<bool> {
+        match self {
+            ContainerCondition::Feature(feature) => feature.evaluate(size),
+            ContainerCondition::Not(condition) => condition.evaluate(size).map(|result| !result),
+            ContainerCondition::And(conditions) => {
+                let mut result = Some(true);
+                for condition in conditions {
+                    match condition.evaluate(size) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            ContainerCondition::Or(conditions) => {
+                let mut result = Some(false);
+                for condition in conditions {
+                    match condition.evaluate(size) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            ContainerCondition::Unknown => None,
+        }
+    }
+
+    /// Whether the condition queries the block axis, which only containers
+    /// with `container-type: size` can answer.
+    pub fn queries_block_axis(&self) -> bool {
+        match self {
+            ContainerCondition::Feature(feature) => feature.queries_block_axis(),
+            ContainerCondition::Not(condition) => condition.queries_block_axis(),
+            ContainerCondition::And(conditions) | ContainerCondition::Or(conditions) => conditions
+                .iter()
+                .any(ContainerCondition::queries_block_axis),
+            ContainerCondition::Unknown => false,
+        }
+    }
+}
+
+fn length(value: &CssValue, size: &ContainerSize) -> Option<f32> {
+    match value {
+        CssValue::Dimension(n, unit) if unit.eq_ignore_ascii_case("em") => Some(n * size.font_size),
+        CssValue::Dimension(n, unit) if unit.eq_ignore_ascii_case("rem") => Some(n * 16.0),
+        value => value.as_px(),
+    }
+}
+
+type ConditionResult<'i, T> = Result<T, ParseError<'i, CssParseError<'i>>>;
+
+/// Parse a `<container-condition>`.
+pub(crate) fn parse_condition<'i>(
+    input: &mut Parser<'i, '_>,
+) -> ConditionResult<'i, ContainerCondition> {
+    if input.try_parse(|i| i.expect_ident_matching("not")).is_ok() {
+        return Ok(ContainerCondition::Not(Box::new(parse_in_parens(input)?)));
+    }
+
+    let mut operands = vec![parse_in_parens(input)?];
+    let mut operator: Option<String> = None;
+    loop {
+        let state = input.state();
+        let ident = match input.next() {
+            Ok(Token::Ident(ident)) => ident.to_ascii_lowercase(),
+            _ => {
+                input.reset(&state);
+                break;
+            }
+        };
+        // `and` and `or` cannot be mixed without parentheses.
+        if !matches!(ident.as_str(), "and" | "or")
+            || operator.as_ref().is_some_and(|op| *op != ident)
+        {
+            return Err(input.new_custom_error(CssParseError::InvalidValue));
+        }
+        operator = Some(ident);
+        operands.push(parse_in_parens(input)?);
+    }
+
+    Ok(match operator.as_deref() {
+        Some("and") => ContainerCondition::And(operands),
+        Some(_) => ContainerCondition::Or(operands),
+        None => operands.pop().unwrap(),
+    })
+}
+
+fn parse_in_parens<'i>(input: &mut Parser<'i, '_>) -> ConditionResult<'i, ContainerCondition> {
+    let location = input.current_source_location();
+    match input.next()?.clone() {
+        Token::ParenthesisBlock => input.parse_nested_block(|input| {
+            let condition: ConditionResult<'i, _> = input.try_parse(|i| {
+                let condition = parse_condition(i)?;
+                i.expect_exhausted()?;
+                Ok(condition)
+            });
+            if let Ok(condition) = condition {
+                return Ok(condition);
+            }
+            let feature: ConditionResult<'i, _> = input.try_parse(|i| {
+                let feature = parse_size_feature(i)?;
+                i.expect_exhausted()?;
+                Ok(feature)
+            });
+            if let Ok(feature) = feature {
+                return Ok(ContainerCondition::Feature(feature));
+            }
+            while input.next().is_ok() {}
+            Ok(ContainerCondition::Unknown)
+        }),
+        // `style()` queries and other general-enclosed functions.
+        Token::Function(_) => input.parse_nested_block(|input| {
+            while input.next().is_ok() {}
+            Ok(ContainerCondition::Unknown)
+        }),
+        _ => Err(location.new_custom_error(CssParseError::InvalidValue)),
+    }
+}
+
+fn parse_size_feature<'i>(input: &mut Parser<'i, '_>) -> ConditionResult<'i, SizeFeature> {
+    let location = input.current_source_location();
+    let known = |name: &str| {
+        matches!(
+            name,
+            "width" | "height" | "inline-size" | "block-size" | "aspect-ratio" | "orientation"
+        )
+    };
+
+    if let Ok(name) = input.try_parse(|i| i.expect_ident().map(|name| name.to_ascii_lowercase())) {
+        let feature = if input.try_parse(|i| i.expect_colon()).is_ok() {
+            let (name, comparison) = if let Some(name) = name.strip_prefix("min-") {
+                (name.to_string(), Comparison::Ge)
+            } else if let Some(name) = name.strip_prefix("max-") {
+                (name.to_string(), Comparison::Le)
+            } else {
+                (name, Comparison::Eq)
+            };
+            SizeFeature {
+                name,
+                comparisons: vec![(comparison, parse_feature_value(input)?)],
+            }
+        } else if input.is_exhausted() {
+            SizeFeature {
+                name,
+                comparisons: Vec::new(),
+            }
+        } else {
+            let comparison = parse_comparison(input)?;
+            SizeFeature {
+                name,
+                comparisons: vec![(comparison, parse_feature_value(input)?)],
+            }
+        };
+        if !known(&feature.name) {
+            return Err(location.new_custom_error(CssParseError::InvalidValue));
+        }
+        return Ok(feature);
+    }
+
+    // Range syntax with the value first: `value op name [op value]`.
+    let value = parse_feature_value(input)?;
+    let comparison = parse_comparison(input)?;
+    let name = input.expect_ident()?.to_ascii_lowercase();
+    if !known(&name) {
+        return Err(location.new_custom_error(CssParseError::InvalidValue));
+    }
+    let mut comparisons = vec![(comparison.flip(), value)];
+    if !input.is_exhausted() {
+        let second = parse_comparison(input)?;
+        let less = |c: Comparison| matches!(c, Comparison::Lt | Comparison::Le);
+        let greater = |c: Comparison| matches!(c, Comparison::Gt | Comparison::Ge);
+        if !(less(comparison) && less(second) || greater(comparison) && greater(second)) {
+            return Err(input.new_custom_error(CssParseError::InvalidValue));
+        }
+        comparisons.push((second, parse_feature_value(input)?));
+    }
+    Ok(SizeFeature { name, comparisons })
+}
+
+fn parse_comparison<'i>(input: &mut Parser<'i, '_>) -> ConditionResult<'i, Comparison> {
+    let location = input.current_source_location();
+    let (strict, inclusive) = match input.next()? {
+        Token::Delim('<') => (Comparison::Lt, Comparison::Le),
+        Token::Delim('>') => (Comparison::Gt, Comparison::Ge),
+        Token::Delim('=') => return Ok(Comparison::Eq),
+        _ => return Err(location.new_custom_error(CssParseError::InvalidValue)),
+    };
+    if input.try_parse(|i| i.expect_delim('=')).is_ok() {
+        Ok(inclusive)
+    } else {
+        Ok(strict)
+    }
+}
+
+/// Parse a feature value: a length, a ratio, or a keyword.
+fn parse_feature_value<'i>(input: &mut Parser<'i, '_>) -> ConditionResult<'i, CssValue> {
+    let location = input.current_source_location();
+    match input.next()?.clone() {
+        Token::Number { value, .. } => {
+            if input.try_parse(|i| i.expect_delim('/')).is_ok() {
+                let denominator = input.expect_number()?;
+                return Ok(CssValue::Number(value / denominator));
+            }
+            Ok(CssValue::Number(value))
+        }
+        Token::Dimension { value, unit, .. } => Ok(CssValue::Dimension(value, unit.to_string())),
+        Token::Ident(ident) => Ok(CssValue::Ident(ident.to_ascii_lowercase())),
+        Token::Function(name) if calc::is_math_function(&name) => Ok(input
+            .parse_nested_block(|input| calc::parse_function(&name, input))?
+            .into_value()),
+        _ => Err(location.new_custom_error(CssParseError::InvalidValue)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cssparser::ParserInput;
+
+    fn parse(css: &str) -> ContainerCondition {
+        let mut input = ParserInput::new(css);
+        parse_condition(&mut Parser::new(&mut input)).unwrap()
+    }
+
+    #[test]
+    fn test_evaluate_conditions() {
+        let inline = ContainerSize {
+            width: Some(500.0),
+            height: None,
+            font_size: 20.0,
+        };
+        let size = ContainerSize {
+            height: Some(300.0),
+            ..inline
+        };
+
+        assert!(parse("(min-width: 400px)").matches(&inline));
+        assert!(!parse("(width > 500px)").matches(&inline));
+        assert!(parse("(400px < width <= 500px)").matches(&inline));
+        assert!(parse("(width >= 600px) or (inline-size < 30em)").matches(&inline));
+        assert!(parse("not (max-width: 100px)").matches(&inline));
+
+        // Block-axis queries are unknown on inline-size containers.
+        let landscape = parse("(orientation: landscape) and (aspect-ratio > 1/1)");
+        assert!(landscape.queries_block_axis());
+        assert_eq!(landscape.evaluate(&inline), None);
+        assert!(landscape.matches(&size));
+        assert!(!parse("not (height < 100px)").matches(&inline));
+
+        assert_eq!(parse("(foo: bar)"), ContainerCondition::Unknown);
+        assert_eq!(parse("style(--theme: dark)").evaluate(&size), None);
+    }
+}
@@ -0,0 +1,181 @@
+//! `@import` resolution.
+//!
+//! Imported stylesheets are fetched through a [`StylesheetLoader`], parsed
+//! against their own URL and attached to their `@import` rules, which the
+//! cascade walks in place of the rule. Media conditions can change while a
+//! page is shown, so stylesheets are fetched whatever their media list;
+//! an import whose `supports()` condition fails is never fetched.
+
+use crate::parser::parse_css;
+use crate::stylesheet::{CssRule, Stylesheet};
+use std::collections::HashMap;
+use std::sync::Arc;
+use url::Url;
+
+/// Maximum nesting of imports below a stylesheet.
+pub const MAX_IMPORT_DEPTH: usize = 16;
+
+/// A fetched stylesheet.
+#[derive(Clone, Debug)]
+pub struct LoadedStylesheet {
+    /// Final URL, after redirects, which relative URLs resolve against.
+    pub url: Url,
+    /// Stylesheet source.
+    pub css: String,
+}
+
+/// Fetches imported stylesheets.
+pub trait StylesheetLoader {
+    /// Fetch the stylesheet at `url`, or `None` if it cannot be loaded.
+    fn load(&self, url: &Url) -> Option<LoadedStylesheet>;
+}
+
+/// Stylesheet sources by URL, for already fetched or inline stylesheets.
+impl StylesheetLoader for HashMap<Url, String> {
+    fn load(&self, url: &Url) -> Option<LoadedStylesheet> {
+        self.get(url).map(|css| LoadedStylesheet {
+            url: url.clone(),
+            css: css.clone(),
+        })
+    }
+}
+
+/// Fetch and attach the stylesheets imported by `sheet`, recursively.
+///
+/// An import of a stylesheet that is already being imported, or one nested
+/// deeper than [`MAX_IMPORT_DEPTH`], is left without a stylesheet.
+pub fn resolve_imports(sheet: &mut Stylesheet, loader: &dyn StylesheetLoader) {
+    let mut chain = vec![sheet.url.clone()];
+    resolve_rules(&mut sheet.rules, loader, &mut chain);
+}
+
+fn resolve_rules(rules: &mut [CssRule], loader: &dyn StylesheetLoader, chain: &mut Vec<Url>) {
+    // `@import` is only valid at the top of a stylesheet.
+    for rule in rules {
+        let CssRule::Import(import) = rule else {
+            continue;
+        };
+        import.stylesheet = None;
+        let Some(url) = import.resolved_url.clone() else {
+            continue;
+        };
+        if import
+            .supports
+            .as_ref()
+            .is_some_and(|condition| !condition.matches())
+        {
+            continue;
+        }
+        if chain.len() > MAX_IMPORT_DEPTH {
+            tracing::warn!("@import of {} nested too deeply", url);
+            continue;
+        }
+        if chain.contains(&url) {
+            tracing::warn!("@import cycle at {}", url);
+            continue;
+        }
+        let Some(loaded) = loader.load(&url) else {
+            tracing::warn!("Failed to load @import {}", url);
+            continue;
+        };
+        if loaded.url != url && chain.contains(&loaded.url) {
+            tracing::warn!("@import cycle at {}", loaded.url);
+            continue;
+        }
+
+        let mut imported = parse_css(&loaded.css, loaded.url);
+        chain.push(url);
+        resolve_rules(&mut imported.rules, loader, chain);
+        chain.pop();
+        import.stylesheet = Some(Arc::new(imported));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loader(sources: &[(&str, &str)]) -> HashMap<Url, String> {
+        sources
+            .iter()
+            .map(|(url, css)| (Url::parse(url).unwrap(), css.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_imports() {
+        let loader = loader(&[
+            (
+                "https://example.com/css/base.css",
+                "@import 'fonts/type.css'; p { color: red }",
+            ),
+            (
+                "https://example.com/css/fonts/type.css",
+                "p { font-size: 12px }",
+            ),
+            ("https://example.com/css/grid.css", "div { display: grid }"),
+            ("https://example.com/css/old.css", "div { float: left }"),
+            ("https://example.com/css/late.css", "a { color: blue }"),
+        ]);
+        let mut sheet = parse_css(
+            "@import url(base.css) screen; @import 'grid.css' supports(display: grid); \
+             @import 'old.css' supports(not (display: grid)); @import 'missing.css'; \
+             a { color: red } @import 'late.css';",
+            Url::parse("https://example.com/css/main.css").unwrap(),
+        );
+        resolve_imports(&mut sheet, &loader);
+
+        let imports = sheet.imports();
+        assert_eq!(imports.len(), 4);
+        let base = imports[0].stylesheet.as_ref().unwrap();
+        assert_eq!(base.style_rules().len(), 2);
+        assert!(imports[1].stylesheet.is_some());
+        assert!(imports[2].stylesheet.is_none());
+        assert!(imports[3].stylesheet.is_none());
+        // Imported rules come before the importing sheet's own.
+        let selectors: Vec<String> = sheet
+            .style_rules()
+            .iter()
+            .map(|r| r.selectors.to_css_string())
+            .collect();
+        assert_eq!(selectors, ["p", "p", "div", "a"]);
+    }
+
+    #[test]
+    fn test_import_cycles_and_depth() {
+        let mut sources = vec![
+            (
+                "https://example.com/a.css".to_string(),
+                "@import 'b.css'; a { color: red }".to_string(),
+            ),
+            (
+                "https://example.com/b.css".to_string(),
+                "@import 'a.css'; b { color: red }".to_string(),
+            ),
+        ];
+        for depth in 0..MAX_IMPORT_DEPTH + 4 {
+            sources.push((
+                format!("https://example.com/deep/{}.css", depth),
+                format!("@import '{}.css'; i{} {{ color: red }}", depth + 1, depth),
+            ));
+        }
+        let loader: HashMap<Url, String> = sources
+            .into_iter()
+            .map(|(url, css)| (Url::parse(&url).unwrap(), css))
+            .collect();
+
+        let mut sheet = parse_css(
+            "@import 'a.css';",
+            Url::parse("https://example.com/main.css").unwrap(),
+        );
+        resolve_imports(&mut sheet, &loader);
+        assert_eq!(sheet.style_rules().len(), 2);
+
+        let mut sheet = parse_css(
+            "@import '0.css';",
+            Url::parse("https://example.com/deep/main.css").unwrap(),
+        );
+        resolve_imports(&mut sheet, &loader);
+        assert_eq!(sheet.style_rules().len(), MAX_IMPORT_DEPTH);
+    }
+}
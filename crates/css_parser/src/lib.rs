@@ -7,10 +7,16 @@ pub mod stylesheet;
 pub mod selector;
 pub mod values;
 pub mod properties;
+pub mod shorthand;
 pub mod media;
 pub mod color;
+pub mod calc;
+pub mod import;
+pub mod container;
+pub mod supports;
+pub mod variables;
 
-pub use parser::{parse_css, parse_style_attribute, CssParser};
+pub use parser::{parse_css, parse_style_attribute, parse_value, CssParser};
 pub use stylesheet::{Stylesheet, StyleRule, CssRule};
 pub use selector::{Selector, SelectorList, Specificity};
 pub use values::{CssValue, CssValueList};
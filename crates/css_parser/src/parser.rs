@@ -1,17 +1,19 @@
 //! CSS Parser.
 
+use crate::container::ContainerCondition;
 use crate::media::{MediaQuery, MediaQueryList};
 use crate::properties::{PropertyDeclaration, PropertyId};
-use crate::selector::{Selector, SelectorList};
+use crate::selector::{PseudoClass, PseudoClassArgs, RelativeSelector, Selector, SelectorList};
+use crate::supports::SupportsCondition;
 use crate::stylesheet::{
-    CssRule, FontFaceRule, ImportRule, KeyframeRule, KeyframesRule, MediaRule, StyleRule,
-    Stylesheet, SupportsRule,
+    ContainerRule, CssRule, FontFaceRule, ImportRule, KeyframeRule, KeyframesRule, LayerBlockRule, LayerName,
+    LayerStatementRule, MediaRule, StyleRule, Stylesheet, SupportsRule,
 };
 use crate::values::CssValue;
 use cssparser::{
-    BasicParseError, BasicParseErrorKind, CowRcStr, DeclarationParser,
+    BasicParseError, BasicParseErrorKind, CowRcStr, DeclarationParser, Delimiter,
     ParseError, Parser, ParserInput, ParserState, RuleBodyItemParser, RuleBodyParser,
-    StyleSheetParser, ToCss, Token,
+    StyleSheetParser, Token,
 };
 use std::sync::Arc;
 use url::Url;
@@ -35,6 +37,7 @@ impl CssParser {
 
         let mut rule_parser = TopLevelRuleParser {
             base_url: &self.base_url,
+            imports_allowed: true,
         };
 
         for result in StyleSheetParser::new(&mut parser, &mut rule_parser) {
@@ -76,9 +79,19 @@ pub fn parse_style_attribute(css: &str) -> Vec<PropertyDeclaration> {
     CssParser::new(Url::parse("about:blank").unwrap()).parse_style_attribute(css)
 }
 
+/// Parse a single property value, e.g. one produced by `var()`
+/// substitution.
+pub fn parse_value(css: &str) -> Option<CssValue> {
+    let mut input = ParserInput::new(css);
+    parse_component_value(&mut Parser::new(&mut input)).ok()
+}
+
 /// Top-level rule parser.
 struct TopLevelRuleParser<'a> {
     base_url: &'a Url,
+    /// Whether `@import` may still appear: only `@charset` and `@layer`
+    /// statements may come before it.
+    imports_allowed: bool,
 }
 
 impl<'i> cssparser::QualifiedRuleParser<'i> for TopLevelRuleParser<'_> {
@@ -99,11 +112,8 @@ impl<'i> cssparser::QualifiedRuleParser<'i> for TopLevelRuleParser<'_> {
         _start: &ParserState,
         input: &mut Parser<'i, 't>,
     ) -> Result<Self::QualifiedRule, ParseError<'i, Self::Error>> {
-        let declarations = parse_declaration_block(input, self.base_url);
-        Ok(CssRule::Style(StyleRule {
-            selectors: prelude,
-            declarations,
-        }))
+        self.imports_allowed = false;
+        Ok(CssRule::Style(parse_style_rule(input, self.base_url, prelude)))
     }
 }
 
@@ -118,22 +128,65 @@ impl<'i> cssparser::AtRuleParser<'i> for TopLevelRuleParser<'_> {
         input: &mut Parser<'i, 't>,
     ) -> Result<Self::Prelude, ParseError<'i, Self::Error>> {
         match &*name {
-            "import" => {
+            "import" if self.imports_allowed => {
                 let url = input.expect_url_or_string()?.as_ref().to_string();
+                let layer = if input.try_parse(|i| i.expect_ident_matching("layer")).is_ok() {
+                    Some(LayerName::anonymous())
+                } else {
+                    input
+                        .try_parse(|i| {
+                            i.expect_function_matching("layer")?;
+                            i.parse_nested_block(parse_layer_name)
+                        })
+                        .ok()
+                };
+                let supports = input
+                    .try_parse(|i| {
+                        i.expect_function_matching("supports")?;
+                        i.parse_nested_block(crate::supports::parse_condition_or_declaration)
+                    })
+                    .ok();
                 let media = parse_media_query_list(input);
-                Ok(AtRulePrelude::Import(url, media))
+                Ok(AtRulePrelude::Import(url, layer, supports, media))
+            }
+            "import" => Err(input.new_custom_error(CssParseError::InvalidAtRule)),
+            "layer" => {
+                let names = if input.is_exhausted() {
+                    Vec::new()
+                } else {
+                    input.parse_comma_separated(parse_layer_name)?
+                };
+                Ok(AtRulePrelude::Layer(names))
             }
             "media" => {
                 let media = parse_media_query_list(input);
                 Ok(AtRulePrelude::Media(media))
             }
+            "container" => {
+                let name = input
+                    .try_parse(|i| -> Result<String, ParseError<'i, CssParseError<'i>>> {
+                        let location = i.current_source_location();
+                        let name = i.expect_ident()?;
+                        match name.to_ascii_lowercase().as_str() {
+                            "not" | "and" | "or" | "none" => {
+                                Err(location.new_custom_error(CssParseError::InvalidAtRule))
+                            }
+                            _ => Ok(name.to_string()),
+                        }
+                    })
+                    .ok();
+                let condition = crate::container::parse_condition(input)?;
+                input.expect_exhausted()?;
+                Ok(AtRulePrelude::Container(name, condition))
+            }
             "font-face" => Ok(AtRulePrelude::FontFace),
             "keyframes" | "-webkit-keyframes" => {
                 let name = input.expect_ident_or_string()?.as_ref().to_string();
                 Ok(AtRulePrelude::Keyframes(name))
             }
             "supports" => {
-                let condition = parse_supports_condition(input)?;
+                let condition = crate::supports::parse_condition(input)?;
+                input.expect_exhausted()?;
                 Ok(AtRulePrelude::Supports(condition))
             }
             "charset" => {
@@ -159,17 +212,23 @@ impl<'i> cssparser::AtRuleParser<'i> for TopLevelRuleParser<'_> {
         _start: &ParserState,
     ) -> Result<Self::AtRule, ()> {
         match prelude {
-            AtRulePrelude::Import(url, media) => {
+            AtRulePrelude::Import(url, layer, supports, media) => {
                 let resolved_url = self.base_url.join(&url).ok();
                 Ok(CssRule::Import(ImportRule {
                     url,
                     resolved_url,
                     media,
+                    layer,
+                    supports,
                     stylesheet: None,
                 }))
             }
+            AtRulePrelude::Layer(names) if !names.is_empty() => {
+                Ok(CssRule::LayerStatement(LayerStatementRule { names }))
+            }
             AtRulePrelude::Charset => Ok(CssRule::Charset),
             AtRulePrelude::Namespace(prefix, url) => {
+                self.imports_allowed = false;
                 Ok(CssRule::Namespace { prefix, url })
             }
             _ => Err(()),
@@ -182,11 +241,16 @@ impl<'i> cssparser::AtRuleParser<'i> for TopLevelRuleParser<'_> {
         _start: &ParserState,
         input: &mut Parser<'i, 't>,
     ) -> Result<Self::AtRule, ParseError<'i, Self::Error>> {
+        let imports_allowed = std::mem::replace(&mut self.imports_allowed, false);
         match prelude {
             AtRulePrelude::Media(media) => {
                 let rules = parse_rule_list(input, self.base_url);
                 Ok(CssRule::Media(MediaRule { media, rules }))
             }
+            AtRulePrelude::Container(name, condition) => {
+                let rules = parse_rule_list(input, self.base_url);
+                Ok(CssRule::Container(ContainerRule { name, condition, rules }))
+            }
             AtRulePrelude::FontFace => {
                 let declarations = parse_declaration_block(input, self.base_url);
                 Ok(CssRule::FontFace(FontFaceRule { declarations }))
@@ -203,21 +267,166 @@ impl<'i> cssparser::AtRuleParser<'i> for TopLevelRuleParser<'_> {
                 let declarations = parse_declaration_block(input, self.base_url);
                 Ok(CssRule::Page { selector, declarations })
             }
-            _ => Err(input.new_custom_error(CssParseError::InvalidAtRule)),
+            AtRulePrelude::Layer(mut names) if names.len() <= 1 => {
+                let name = names.pop().unwrap_or_else(LayerName::anonymous);
+                let rules = parse_rule_list(input, self.base_url);
+                Ok(CssRule::LayerBlock(LayerBlockRule { name, rules }))
+            }
+            _ => {
+                self.imports_allowed = imports_allowed;
+                Err(input.new_custom_error(CssParseError::InvalidAtRule))
+            }
         }
     }
 }
 
 /// At-rule prelude variants.
 enum AtRulePrelude {
-    Import(String, MediaQueryList),
+    Import(String, Option<LayerName>, Option<SupportsCondition>, MediaQueryList),
     Media(MediaQueryList),
+    Container(Option<String>, ContainerCondition),
     FontFace,
     Keyframes(String),
-    Supports(String),
+    Supports(SupportsCondition),
     Charset,
     Namespace(Option<String>, String),
     Page(Option<String>),
+    Layer(Vec<LayerName>),
+}
+
+/// An item of the body of a style rule.
+enum StyleRuleItem {
+    Declaration(PropertyDeclaration),
+    Rule(CssRule),
+}
+
+/// Parser for the body of a style rule: declarations, nested style rules
+/// and nested group rules.
+struct StyleRuleBodyParser<'a> {
+    base_url: &'a Url,
+    /// Selectors of the enclosing style rule, with `&` resolved.
+    parent: &'a SelectorList,
+}
+
+impl<'i> DeclarationParser<'i> for StyleRuleBodyParser<'_> {
+    type Declaration = StyleRuleItem;
+    type Error = CssParseError<'i>;
+
+    fn parse_value<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::Declaration, ParseError<'i, Self::Error>> {
+        // A `{}` block makes `a:hover { ... }` a nested rule rather than a
+        // declaration, except in custom properties.
+        if !name.starts_with("--") {
+            let state = input.state();
+            while let Ok(token) = input.next() {
+                if matches!(token, Token::CurlyBracketBlock) {
+                    return Err(input.new_custom_error(CssParseError::InvalidValue));
+                }
+            }
+            input.reset(&state);
+        }
+        PropertyDeclarationParser { base_url: self.base_url }
+            .parse_value(name, input)
+            .map(StyleRuleItem::Declaration)
+    }
+}
+
+impl<'i> cssparser::QualifiedRuleParser<'i> for StyleRuleBodyParser<'_> {
+    type Prelude = SelectorList;
+    type QualifiedRule = StyleRuleItem;
+    type Error = CssParseError<'i>;
+
+    fn parse_prelude<'t>(
+        &mut self,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::Prelude, ParseError<'i, Self::Error>> {
+        parse_nested_selector_list(input, self.parent)
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        prelude: Self::Prelude,
+        _start: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::QualifiedRule, ParseError<'i, Self::Error>> {
+        Ok(StyleRuleItem::Rule(CssRule::Style(parse_style_rule(input, self.base_url, prelude))))
+    }
+}
+
+impl<'i> cssparser::AtRuleParser<'i> for StyleRuleBodyParser<'_> {
+    type Prelude = AtRulePrelude;
+    type AtRule = StyleRuleItem;
+    type Error = CssParseError<'i>;
+
+    fn parse_prelude<'t>(
+        &mut self,
+        name: CowRcStr<'i>,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::Prelude, ParseError<'i, Self::Error>> {
+        // Only conditional group rules and layers nest in style rules.
+        match name.to_ascii_lowercase().as_str() {
+            "media" | "supports" | "container" | "layer" => {
+                TopLevelRuleParser { base_url: self.base_url, imports_allowed: false }.parse_prelude(name, input)
+            }
+            _ => Err(input.new_custom_error(CssParseError::UnknownAtRule(name.to_string()))),
+        }
+    }
+
+    fn rule_without_block(
+        &mut self,
+        prelude: Self::Prelude,
+        start: &ParserState,
+    ) -> Result<Self::AtRule, ()> {
+        TopLevelRuleParser { base_url: self.base_url, imports_allowed: false }
+            .rule_without_block(prelude, start)
+            .map(StyleRuleItem::Rule)
+    }
+
+    fn parse_block<'t>(
+        &mut self,
+        prelude: Self::Prelude,
+        _start: &ParserState,
+        input: &mut Parser<'i, 't>,
+    ) -> Result<Self::AtRule, ParseError<'i, Self::Error>> {
+        // Declarations directly inside the group rule apply to the
+        // elements the enclosing style rule matches.
+        let (declarations, mut rules) = parse_style_rule_body(input, self.base_url, self.parent);
+        if !declarations.is_empty() {
+            rules.insert(
+                0,
+                CssRule::Style(StyleRule {
+                    selectors: self.parent.clone(),
+                    declarations,
+                    rules: Vec::new(),
+                }),
+            );
+        }
+        let rule = match prelude {
+            AtRulePrelude::Media(media) => CssRule::Media(MediaRule { media, rules }),
+            AtRulePrelude::Supports(condition) => CssRule::Supports(SupportsRule { condition, rules }),
+            AtRulePrelude::Container(name, condition) => {
+                CssRule::Container(ContainerRule { name, condition, rules })
+            }
+            AtRulePrelude::Layer(mut names) if names.len() <= 1 => {
+                let name = names.pop().unwrap_or_else(LayerName::anonymous);
+                CssRule::LayerBlock(LayerBlockRule { name, rules })
+            }
+            _ => return Err(input.new_custom_error(CssParseError::InvalidAtRule)),
+        };
+        Ok(StyleRuleItem::Rule(rule))
+    }
+}
+
+impl<'i> RuleBodyItemParser<'i, StyleRuleItem, CssParseError<'i>> for StyleRuleBodyParser<'_> {
+    fn parse_declarations(&self) -> bool {
+        true
+    }
+    fn parse_qualified(&self) -> bool {
+        true
+    }
 }
 
 /// Style attribute parser (for parsing inline styles).
@@ -345,7 +554,7 @@ impl<'i> From<BasicParseError<'i>> for CssParseError<'i> {
 }
 
 /// Parse selector list.
-fn parse_selector_list<'i, 't>(
+pub(crate) fn parse_selector_list<'i, 't>(
     input: &mut Parser<'i, 't>,
 ) -> Result<SelectorList, ParseError<'i, CssParseError<'i>>> {
     let mut selectors = Vec::new();
@@ -368,6 +577,144 @@ fn parse_selector_list<'i, 't>(
     Ok(SelectorList { selectors })
 }
 
+/// Parse a forgiving selector list, dropping the selectors that fail to
+/// parse, and report whether any were dropped.
+pub(crate) fn parse_forgiving_selector_list(
+    input: &mut Parser<'_, '_>,
+) -> (SelectorList, bool) {
+    let mut selectors = Vec::new();
+    let mut dropped = false;
+    loop {
+        let selector = input.parse_until_before(Delimiter::Comma, |input| {
+            let selector = parse_selector(input)?;
+            input.expect_exhausted()?;
+            Ok::<_, ParseError<'_, CssParseError<'_>>>(selector)
+        });
+        match selector {
+            Ok(selector) => selectors.push(selector),
+            Err(_) => dropped = true,
+        }
+        if input.next().is_err() {
+            break;
+        }
+    }
+    (SelectorList { selectors }, dropped)
+}
+
+/// Parse a relative selector list, where each selector may start with a
+/// combinator and is otherwise a descendant of the anchor.
+pub(crate) fn parse_relative_selector_list<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<Vec<RelativeSelector>, ParseError<'i, CssParseError<'i>>> {
+    let mut selectors = Vec::new();
+    loop {
+        let combinator = input
+            .try_parse(parse_leading_combinator)
+            .unwrap_or(crate::selector::Combinator::Descendant);
+        let selector = parse_selector(input)?;
+        selectors.push(RelativeSelector { combinator, selector });
+
+        if input.try_parse(|i| i.expect_comma()).is_err() {
+            break;
+        }
+    }
+    Ok(selectors)
+}
+
+/// Parse the combinator a relative selector starts with, and the
+/// whitespace after it.
+fn parse_leading_combinator<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<crate::selector::Combinator, ParseError<'i, CssParseError<'i>>> {
+    use crate::selector::Combinator;
+
+    let combinator = match input.next()? {
+        Token::Delim('>') => Combinator::Child,
+        Token::Delim('+') => Combinator::NextSibling,
+        Token::Delim('~') => Combinator::SubsequentSibling,
+        _ => return Err(input.new_custom_error(CssParseError::InvalidSelector)),
+    };
+    while input.try_parse(|i| i.expect_whitespace()).is_ok() {}
+    Ok(combinator)
+}
+
+/// Parse the selectors of a nested style rule and resolve `&` against the
+/// selectors of the enclosing rule. Selectors without `&`, including those
+/// starting with a combinator, are relative to it.
+fn parse_nested_selector_list<'i, 't>(
+    input: &mut Parser<'i, 't>,
+    parent: &SelectorList,
+) -> Result<SelectorList, ParseError<'i, CssParseError<'i>>> {
+    use crate::selector::Combinator;
+
+    let mut selectors = Vec::new();
+    loop {
+        let combinator = input.try_parse(parse_leading_combinator).ok();
+        let selector = parse_selector(input)?;
+        let mut selector = match combinator {
+            None if selector.has_nesting() => selector,
+            combinator => Selector {
+                nesting: true,
+                combinator: Some(combinator.unwrap_or(Combinator::Descendant)),
+                next: Some(Box::new(selector)),
+                ..Selector::default()
+            },
+        };
+        selector.resolve_nesting(parent);
+        selectors.push(selector);
+
+        if input.try_parse(|i| i.expect_comma()).is_err() {
+            break;
+        }
+    }
+
+    Ok(SelectorList { selectors })
+}
+
+/// Read the arguments of a functional pseudo-class or pseudo-element as
+/// raw text, nested blocks included.
+fn parse_raw_arguments<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<String, ParseError<'i, CssParseError<'i>>> {
+    input.parse_nested_block(|input| {
+        let start = input.position();
+        while input.next_including_whitespace_and_comments().is_ok() {}
+        Ok(input.slice_from(start).trim().to_string())
+    })
+}
+
+/// Parse the arguments of the functional pseudo-class `name`, selector
+/// lists included.
+fn parse_pseudo_class_arguments<'i, 't>(
+    input: &mut Parser<'i, 't>,
+    name: &str,
+) -> Result<PseudoClassArgs, ParseError<'i, CssParseError<'i>>> {
+    if name.starts_with("nth-") {
+        // `of <selector>` is not implemented, so only An+B is valid.
+        let args = parse_raw_arguments(input)?;
+        return match crate::selector::parse_nth(&args) {
+            Some(_) => Ok(PseudoClassArgs::Raw(args)),
+            None => Err(input.new_custom_error(CssParseError::InvalidSelector)),
+        };
+    }
+    if !matches!(name, "is" | "where" | "not" | "has" | "host" | "host-context") {
+        return Err(input.new_custom_error(CssParseError::InvalidSelector));
+    }
+    input.parse_nested_block(|input| {
+        // Unlike `:is()` and `:where()`, the others take unforgiving lists.
+        let args = match name {
+            "is" | "where" => {
+                let (selectors, dropped) = parse_forgiving_selector_list(input);
+                PseudoClassArgs::Forgiving(selectors, dropped)
+            }
+            "has" => PseudoClassArgs::Relative(parse_relative_selector_list(input)?),
+            _ => PseudoClassArgs::Selectors(parse_selector_list(input)?),
+        };
+        input.expect_exhausted()?;
+        Ok(args)
+    })
+}
+
 /// Parse a single selector.
 fn parse_selector<'i, 't>(
     input: &mut Parser<'i, 't>,
@@ -402,6 +749,10 @@ fn parse_selector<'i, 't>(
                     selector.universal = true;
                     has_content = true;
                 }
+                Token::Delim('&') => {
+                    selector.nesting = true;
+                    has_content = true;
+                }
                 Token::SquareBracketBlock => {
                     let attr = input.parse_nested_block(|input| {
                         parse_attribute_selector(input)
@@ -412,33 +763,61 @@ fn parse_selector<'i, 't>(
                 Token::Colon => {
                     // Pseudo-class or pseudo-element
                     if input.try_parse(|i| i.expect_colon()).is_ok() {
-                        // Pseudo-element (::)
-                        let name = input.expect_ident()?.to_string();
-                        selector.pseudo_elements.push(name);
+                        // Pseudo-element (::), possibly functional like `::slotted()`
+                        let token = input.next_including_whitespace()?.clone();
+                        match token {
+                            Token::Ident(name) => selector.pseudo_elements.push(name.to_string()),
+                            Token::Function(name) => {
+                                let args = parse_raw_arguments(input)?;
+                                selector.pseudo_elements.push(format!("{}({})", name, args));
+                            }
+                            _ => return Err(input.new_custom_error(CssParseError::InvalidSelector)),
+                        }
                     } else {
-                        // Pseudo-class (:)
-                        let name = input.expect_ident()?.to_string();
-
-                        // Handle functional pseudo-classes
-                        if input.try_parse(|i| i.expect_parenthesis_block()).is_ok() {
-                            let args = input.parse_nested_block(|input| {
-                                let mut args = String::new();
-                                while let Ok(token) = input.next() {
-                                    args.push_str(&token.to_css_string());
-                                }
-                                Ok::<_, ParseError<'_, CssParseError<'_>>>(args)
-                            })?;
-                            selector.pseudo_classes.push((name, Some(args)));
-                        } else {
-                            selector.pseudo_classes.push((name, None));
+                        // Pseudo-class (:), possibly functional
+                        let token = input.next_including_whitespace()?.clone();
+                        let name = match &token {
+                            Token::Ident(name) | Token::Function(name) => name.to_ascii_lowercase(),
+                            _ => return Err(input.new_custom_error(CssParseError::InvalidSelector)),
+                        };
+                        if !crate::selector::PSEUDO_CLASSES.contains(&name.as_str()) {
+                            return Err(input.new_custom_error(CssParseError::InvalidSelector));
+                        }
+                        match token {
+                            Token::Ident(_) => {
+                                selector.pseudo_classes.push(PseudoClass::new(&name));
+                            }
+                            Token::Function(_) => {
+                                let args = parse_pseudo_class_arguments(input, &name)?;
+                                selector.pseudo_classes.push(PseudoClass::with_args(&name, args));
+                            }
+                            _ => return Err(input.new_custom_error(CssParseError::InvalidSelector)),
                         }
                     }
                     has_content = true;
                 }
                 Token::WhiteSpace(_) => {
                     if has_content {
-                        // Descendant combinator
-                        selector.combinator = Some(crate::selector::Combinator::Descendant);
+                        // Whitespace is a descendant combinator unless an
+                        // explicit combinator, a comma or the end follows.
+                        let state = input.state();
+                        match input.next() {
+                            Ok(&Token::Delim('>')) => {
+                                selector.combinator = Some(crate::selector::Combinator::Child);
+                            }
+                            Ok(&Token::Delim('+')) => {
+                                selector.combinator = Some(crate::selector::Combinator::NextSibling);
+                            }
+                            Ok(&Token::Delim('~')) => {
+                                selector.combinator =
+                                    Some(crate::selector::Combinator::SubsequentSibling);
+                            }
+                            Ok(&Token::Comma) | Err(_) => input.reset(&state),
+                            Ok(_) => {
+                                input.reset(&state);
+                                selector.combinator = Some(crate::selector::Combinator::Descendant);
+                            }
+                        }
                         break;
                     }
                 }
@@ -476,9 +855,7 @@ fn parse_selector<'i, 't>(
         // Skip whitespace after combinator
         while input.try_parse(|i| i.expect_whitespace()).is_ok() {}
 
-        if let Ok(next) = parse_selector(input) {
-            selector.next = Some(Box::new(next));
-        }
+        selector.next = Some(Box::new(parse_selector(input)?));
     }
 
     Ok(selector)
@@ -493,16 +870,18 @@ fn parse_attribute_selector<'i, 't>(
     let (operator, value, case_sensitivity) = if input.is_exhausted() {
         (None, None, crate::selector::CaseSensitivity::Default)
     } else {
-        let op = input.expect_delim('=').ok().map(|_| "=".to_string()).or_else(|| {
-            input.try_parse(|i| -> Result<String, ParseError<'_, CssParseError<'_>>> {
-                let c = match i.next()? {
-                    Token::Delim(c) => *c,
-                    _ => return Err(i.new_custom_error(CssParseError::InvalidSelector)),
-                };
-                i.expect_delim('=')?;
-                Ok(format!("{}=", c))
-            }).ok()
-        });
+        let op = input.try_parse(|i| -> Result<String, ParseError<'_, CssParseError<'_>>> {
+            let op = match i.next()? {
+                Token::Delim('=') => "=",
+                Token::IncludeMatch => "~=",
+                Token::DashMatch => "|=",
+                Token::PrefixMatch => "^=",
+                Token::SuffixMatch => "$=",
+                Token::SubstringMatch => "*=",
+                _ => return Err(i.new_custom_error(CssParseError::InvalidSelector)),
+            };
+            Ok(op.to_string())
+        }).ok();
 
         if let Some(op) = op {
             let value = input.expect_ident_or_string()?.as_ref().to_string();
@@ -543,9 +922,56 @@ fn parse_declaration_block(input: &mut Parser<'_, '_>, base_url: &Url) -> Vec<Pr
     declarations
 }
 
+/// Parse the body of a style rule with selectors `selectors`.
+fn parse_style_rule(input: &mut Parser<'_, '_>, base_url: &Url, selectors: SelectorList) -> StyleRule {
+    let (declarations, rules) = parse_style_rule_body(input, base_url, &selectors);
+    StyleRule {
+        selectors,
+        declarations,
+        rules,
+    }
+}
+
+/// Parse the declarations and nested rules of a style rule whose selectors
+/// are `parent`. Declarations that follow a nested rule keep their place
+/// in a rule with the same selectors.
+fn parse_style_rule_body(
+    input: &mut Parser<'_, '_>,
+    base_url: &Url,
+    parent: &SelectorList,
+) -> (Vec<PropertyDeclaration>, Vec<CssRule>) {
+    let mut parser = StyleRuleBodyParser { base_url, parent };
+    let mut declarations = Vec::new();
+    let mut rules = Vec::new();
+    let mut pending = Vec::new();
+    let flush = |pending: &mut Vec<PropertyDeclaration>, rules: &mut Vec<CssRule>| {
+        if !pending.is_empty() {
+            rules.push(CssRule::Style(StyleRule {
+                selectors: parent.clone(),
+                declarations: std::mem::take(pending),
+                rules: Vec::new(),
+            }));
+        }
+    };
+    for item in RuleBodyParser::new(input, &mut parser).flatten() {
+        match item {
+            StyleRuleItem::Declaration(declaration) if rules.is_empty() => {
+                declarations.push(declaration);
+            }
+            StyleRuleItem::Declaration(declaration) => pending.push(declaration),
+            StyleRuleItem::Rule(rule) => {
+                flush(&mut pending, &mut rules);
+                rules.push(rule);
+            }
+        }
+    }
+    flush(&mut pending, &mut rules);
+    (declarations, rules)
+}
+
 /// Parse rule list (for nested rules).
 fn parse_rule_list(input: &mut Parser<'_, '_>, base_url: &Url) -> Vec<CssRule> {
-    let mut rule_parser = TopLevelRuleParser { base_url };
+    let mut rule_parser = TopLevelRuleParser { base_url, imports_allowed: false };
     let list_parser = StyleSheetParser::new(input, &mut rule_parser);
     let mut rules = Vec::new();
     for result in list_parser {
@@ -556,6 +982,17 @@ fn parse_rule_list(input: &mut Parser<'_, '_>, base_url: &Url) -> Vec<CssRule> {
     rules
 }
 
+/// Parse a cascade layer name: identifiers separated by `.`.
+fn parse_layer_name<'i, 't>(
+    input: &mut Parser<'i, 't>,
+) -> Result<LayerName, ParseError<'i, CssParseError<'i>>> {
+    let mut segments = vec![input.expect_ident()?.to_string()];
+    while input.try_parse(|i| i.expect_delim('.')).is_ok() {
+        segments.push(input.expect_ident()?.to_string());
+    }
+    Ok(LayerName(segments))
+}
+
 /// Parse media query list.
 fn parse_media_query_list(input: &mut Parser<'_, '_>) -> MediaQueryList {
     let mut queries = Vec::new();
@@ -627,20 +1064,6 @@ fn parse_media_feature<'i, 't>(
     Ok(crate::media::MediaFeature { name, value })
 }
 
-/// Parse supports condition.
-fn parse_supports_condition<'i, 't>(
-    input: &mut Parser<'i, 't>,
-) -> Result<String, ParseError<'i, CssParseError<'i>>> {
-    // Simple implementation - collect condition as string
-    let mut condition = String::new();
-
-    while let Ok(token) = input.next() {
-        condition.push_str(&token.to_css_string());
-    }
-
-    Ok(condition)
-}
-
 /// Parse keyframes.
 fn parse_keyframes(input: &mut Parser<'_, '_>, base_url: &Url) -> Vec<KeyframeRule> {
     let mut keyframes = Vec::new();
@@ -698,13 +1121,25 @@ fn parse_keyframe_selectors<'i, 't>(
     }
 }
 
-/// Parse property value.
-fn parse_property_value<'i, 't>(
+/// Parse property value. Custom properties and values with `var()`
+/// references are kept as raw text, up to `!important`.
+pub(crate) fn parse_property_value<'i, 't>(
     input: &mut Parser<'i, 't>,
-    _property: &PropertyId,
+    property: &PropertyId,
     base_url: &Url,
 ) -> Result<CssValue, ParseError<'i, CssParseError<'i>>> {
-    parse_component_value(input)
+    let state = input.state();
+    let unparsed = property.is_custom_property() || crate::variables::has_var(input);
+    input.reset(&state);
+    if !unparsed {
+        return input.parse_until_before(Delimiter::Bang, parse_component_value);
+    }
+    let start = input.position();
+    input.parse_until_before(Delimiter::Bang, |input| {
+        while input.next().is_ok() {}
+        Ok::<_, ParseError<'i, CssParseError<'i>>>(())
+    })?;
+    Ok(CssValue::Unparsed(input.slice_from(start).trim().to_string()))
 }
 
 /// Parse a single component value.
@@ -725,6 +1160,12 @@ fn parse_component_value<'i, 't>(
             Token::Hash(s) | Token::IDHash(s) => {
                 CssValue::Color(format!("#{}", s))
             }
+            Token::Function(name) if crate::calc::is_math_function(name) => {
+                let name = name.to_string();
+                input
+                    .parse_nested_block(|input| crate::calc::parse_function(&name, input))?
+                    .into_value()
+            }
             Token::Function(name) => {
                 let name = name.to_string();
                 let args = input.parse_nested_block(|input| {
@@ -773,6 +1214,109 @@ mod tests {
         let style = "color: red; font-size: 16px;";
         let declarations = parse_style_attribute(style);
         assert_eq!(declarations.len(), 2);
+
+        let declarations = parse_style_attribute("color: red !important");
+        assert_eq!(declarations[0].value, CssValue::Ident("red".to_string()));
+        assert!(declarations[0].important);
+    }
+
+    #[test]
+    fn test_parse_custom_properties() {
+        let declarations = parse_style_attribute("--Gap: { 1px } ; margin: var(--Gap, 2px) !important");
+        assert_eq!(declarations[0].property, PropertyId::Custom("--Gap".to_string()));
+        assert_eq!(declarations[0].value, CssValue::Unparsed("{ 1px }".to_string()));
+        assert_eq!(declarations[1].value, CssValue::Unparsed("var(--Gap, 2px)".to_string()));
+        assert!(declarations[1].important);
+        assert_eq!(parse_value("4px 2px"), Some(CssValue::List(vec![
+            CssValue::Dimension(4.0, "px".to_string()),
+            CssValue::Dimension(2.0, "px".to_string()),
+        ])));
+    }
+
+    #[test]
+    fn test_parse_layers() {
+        let css = "@layer reset, framework.base; @import url(theme.css) layer(theme); @import 'x.css' layer; \
+                   @layer { a { color: red } } @layer framework { @layer base { a { color: blue } } } \
+                   @layer a, b { }";
+        let stylesheet = parse_css(css, Url::parse("https://example.com/").unwrap());
+        let rules = &stylesheet.rules;
+        assert!(matches!(&rules[0], CssRule::LayerStatement(statement)
+            if statement.names.iter().map(|n| n.to_string()).collect::<Vec<_>>() == ["reset", "framework.base"]));
+        assert!(matches!(&rules[3], CssRule::LayerBlock(block) if block.name.is_anonymous() && block.rules.len() == 1));
+        assert!(matches!(&rules[4], CssRule::LayerBlock(block)
+            if block.name.to_string() == "framework" && matches!(&block.rules[0], CssRule::LayerBlock(_))));
+        let imports = stylesheet.imports();
+        assert_eq!(imports[0].layer.as_ref().map(|l| l.to_string()).as_deref(), Some("theme"));
+        assert!(imports[1].layer.as_ref().is_some_and(LayerName::is_anonymous));
+        // A block may name at most one layer.
+        assert_eq!(rules.len(), 5);
+    }
+
+    #[test]
+    fn test_parse_container_rule() {
+        let css = "@container card (min-width: 400px) { .title { font-size: 2cqi } } \
+                   @container (width > 30em) and (orientation: landscape) { a { color: red } } \
+                   @container not (height) { } @container card { } @container none (width) { }";
+        let stylesheet = parse_css(css, Url::parse("about:blank").unwrap());
+        let rules = &stylesheet.rules;
+        assert_eq!(rules.len(), 3);
+        assert!(matches!(&rules[0], CssRule::Container(rule)
+            if rule.name.as_deref() == Some("card") && !rule.condition.queries_block_axis()));
+        assert!(matches!(&rules[1], CssRule::Container(rule)
+            if rule.name.is_none() && rule.condition.queries_block_axis()));
+        assert_eq!(stylesheet.style_rules().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_nested_rules() {
+        let css = ".card { color: red; &:hover { color: blue } > p, .x & { margin: 0 } a:hover { color: green } \
+                   @media (min-width: 100px) { padding: 1px; .t { padding: 2px } } font-size: 2px }";
+        let stylesheet = parse_css(css, Url::parse("about:blank").unwrap());
+        let CssRule::Style(card) = &stylesheet.rules[0] else {
+            panic!("expected a style rule");
+        };
+        assert_eq!(card.declarations.len(), 1);
+        assert_eq!(card.rules.len(), 5);
+        let selectors = |rule: &CssRule| match rule {
+            CssRule::Style(style) => style.selectors.to_css_string(),
+            _ => String::new(),
+        };
+        assert_eq!(selectors(&card.rules[0]), ":is(.card):hover");
+        // `&` holds the parent's selectors rather than their text.
+        let CssRule::Style(hover) = &card.rules[0] else {
+            panic!("expected a style rule");
+        };
+        let nesting = &hover.selectors.selectors[0].pseudo_classes[0];
+        assert!(matches!(
+            &nesting.args,
+            Some(PseudoClassArgs::Selectors(list)) if list.selectors[0].classes == ["card"]
+        ));
+        assert_eq!(selectors(&card.rules[1]), ":is(.card) > p, .x :is(.card)");
+        assert_eq!(selectors(&card.rules[2]), ":is(.card) a:hover");
+        let CssRule::Media(media) = &card.rules[3] else {
+            panic!("expected a media rule");
+        };
+        assert_eq!(media.rules.iter().map(selectors).collect::<Vec<_>>(), [".card", ":is(.card) .t"]);
+        // Trailing declarations keep their place after the nested rules.
+        assert_eq!(selectors(&card.rules[4]), ".card");
+        assert_eq!(stylesheet.style_rules().len(), 7);
+    }
+
+    #[test]
+    fn test_parse_supports_rule() {
+        let css = "@supports (display: grid) and selector(a:has(b)) { a { display: grid } } \
+                   @supports not (display: grid) { a { float: left } } \
+                   @supports (display: grid) foo { } @supports display: grid { }";
+        let stylesheet = parse_css(css, Url::parse("about:blank").unwrap());
+        let matches: Vec<bool> = stylesheet
+            .rules
+            .iter()
+            .filter_map(|rule| match rule {
+                CssRule::Supports(rule) => Some(rule.matches()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(matches, [true, false]);
     }
 
     #[test]
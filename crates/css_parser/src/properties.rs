@@ -1,5 +1,6 @@
 //! CSS Properties.
 
+use crate::calc::CalcType;
 use crate::values::CssValue;
 use std::fmt;
 
@@ -206,6 +207,11 @@ pub enum PropertyId {
     CounterReset,
     CounterIncrement,
 
+    // Containment
+    Container,
+    ContainerType,
+    ContainerName,
+
     // Misc
     ObjectFit,
     ObjectPosition,
@@ -233,6 +239,10 @@ pub enum PropertyId {
 impl PropertyId {
     /// Get property from name string.
     pub fn from_name(name: &str) -> Self {
+        // Custom property names are case-sensitive.
+        if name.starts_with("--") {
+            return PropertyId::Custom(name.to_string());
+        }
         match name.to_ascii_lowercase().as_str() {
             // Display and positioning
             "display" => PropertyId::Display,
@@ -434,6 +444,11 @@ impl PropertyId {
             "counter-reset" => PropertyId::CounterReset,
             "counter-increment" => PropertyId::CounterIncrement,
 
+            // Containment
+            "container" => PropertyId::Container,
+            "container-type" => PropertyId::ContainerType,
+            "container-name" => PropertyId::ContainerName,
+
             // Misc
             "object-fit" => PropertyId::ObjectFit,
             "object-position" => PropertyId::ObjectPosition,
@@ -499,12 +514,20 @@ impl PropertyId {
             PropertyId::FontFamily => "font-family",
             PropertyId::FontSize => "font-size",
             PropertyId::FontWeight => "font-weight",
+            PropertyId::Container => "container",
+            PropertyId::ContainerType => "container-type",
+            PropertyId::ContainerName => "container-name",
             PropertyId::Custom(s) => s,
             // ... add all other properties
             _ => "unknown",
         }
     }
 
+    /// Check if this is a custom property (`--*`).
+    pub fn is_custom_property(&self) -> bool {
+        matches!(self, PropertyId::Custom(name) if name.starts_with("--"))
+    }
+
     /// Check if this is a shorthand property.
     pub fn is_shorthand(&self) -> bool {
         matches!(
@@ -538,6 +561,7 @@ impl PropertyId {
                 | PropertyId::PlaceItems
                 | PropertyId::PlaceSelf
                 | PropertyId::Gap
+                | PropertyId::Container
         )
     }
 
@@ -578,7 +602,368 @@ impl PropertyId {
                 | PropertyId::Fill
                 | PropertyId::Stroke
                 | PropertyId::StrokeWidth
-        )
+        ) || self.is_custom_property()
+    }
+}
+
+/// Value grammar of a property, as far as this engine checks it.
+enum Grammar {
+    /// One of the keywords.
+    Keyword(&'static [&'static str]),
+    /// Space-separated keywords from the list.
+    Keywords(&'static [&'static str]),
+    /// A length or percentage, or one of the keywords.
+    LengthPercentage(&'static [&'static str]),
+    /// A number, a length or percentage, or one of the keywords.
+    NumberLengthPercentage(&'static [&'static str]),
+    /// A number, or one of the keywords.
+    Number(&'static [&'static str]),
+    /// An integer, or one of the keywords.
+    Integer(&'static [&'static str]),
+    /// A number or percentage.
+    Alpha,
+    Color,
+    /// Space-separated lengths or percentages, or keywords from the list.
+    LengthPercentages(&'static [&'static str]),
+    /// Functions named in the first list, or keywords from the second.
+    Functions(&'static [&'static str], &'static [&'static str]),
+    /// Any identifiers, such as names.
+    Idents,
+    /// Identifiers and integers, as for grid lines and counters.
+    IdentsIntegers,
+    /// Font family names or generic families.
+    Families,
+    /// Grid track sizes, or `none`.
+    Tracks,
+    /// Times, such as durations and delays.
+    Times,
+    /// Shadows, or `none`.
+    Shadows,
+    /// Strings, as for grid template areas, or `none`.
+    Strings,
+    /// Not supported: no value is accepted.
+    Unsupported,
+}
+
+const SIZING: &[&str] = &["auto", "min-content", "max-content", "fit-content"];
+const MAX_SIZING: &[&str] = &["none", "min-content", "max-content", "fit-content"];
+const LINE_STYLES: &[&str] = &[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+const LINE_WIDTHS: &[&str] = &["thin", "medium", "thick"];
+const OVERFLOW: &[&str] = &["visible", "hidden", "clip", "scroll", "auto"];
+const POSITIONS: &[&str] = &["left", "right", "top", "bottom", "center"];
+const IMAGES: &[&str] = &[
+    "url", "image", "image-set", "linear-gradient", "radial-gradient", "conic-gradient",
+    "repeating-linear-gradient", "repeating-radial-gradient", "repeating-conic-gradient",
+];
+const TRANSFORMS: &[&str] = &[
+    "matrix", "matrix3d", "translate", "translate3d", "translatex", "translatey", "translatez",
+    "scale", "scale3d", "scalex", "scaley", "scalez", "rotate", "rotate3d", "rotatex", "rotatey",
+    "rotatez", "skew", "skewx", "skewy", "perspective",
+];
+const FILTERS: &[&str] = &[
+    "url", "blur", "brightness", "contrast", "drop-shadow", "grayscale", "hue-rotate", "invert",
+    "opacity", "saturate", "sepia",
+];
+const TIMING_FUNCTIONS: &[&str] = &[
+    "ease", "linear", "ease-in", "ease-out", "ease-in-out", "step-start", "step-end",
+];
+const ALIGNMENT: &[&str] = &[
+    "normal", "stretch", "center", "start", "end", "flex-start", "flex-end", "self-start",
+    "self-end", "left", "right", "baseline", "first", "last", "safe", "unsafe", "space-between",
+    "space-around", "space-evenly", "auto", "legacy",
+];
+
+impl PropertyId {
+    fn grammar(&self) -> Grammar {
+        use Grammar::*;
+        use PropertyId::*;
+        match self {
+            Display => Keywords(&[
+                "block", "inline", "inline-block", "flex", "inline-flex", "grid", "inline-grid",
+                "flow", "flow-root", "none", "contents", "list-item", "table", "inline-table",
+                "table-row", "table-cell", "table-row-group", "table-header-group",
+                "table-footer-group", "table-column", "table-column-group", "table-caption",
+            ]),
+            Position => Keyword(&["static", "relative", "absolute", "fixed", "sticky"]),
+            Top | Right | Bottom | Left => LengthPercentage(&["auto"]),
+            Float => Keyword(&["left", "right", "none", "inline-start", "inline-end"]),
+            Clear => Keyword(&["left", "right", "both", "none", "inline-start", "inline-end"]),
+            ZIndex => Integer(&["auto"]),
+            Visibility => Keyword(&["visible", "hidden", "collapse"]),
+            Overflow => Keywords(OVERFLOW),
+            OverflowX | OverflowY => Keyword(OVERFLOW),
+            Width | Height | MinWidth | MinHeight | FlexBasis => LengthPercentage(SIZING),
+            MaxWidth | MaxHeight => LengthPercentage(MAX_SIZING),
+            MarginTop | MarginRight | MarginBottom | MarginLeft => LengthPercentage(&["auto"]),
+            PaddingTop | PaddingRight | PaddingBottom | PaddingLeft | TextIndent => {
+                LengthPercentage(&[])
+            }
+            BorderTopWidth | BorderRightWidth | BorderBottomWidth | BorderLeftWidth
+            | OutlineWidth => LengthPercentage(LINE_WIDTHS),
+            BorderStyle => Keywords(LINE_STYLES),
+            BorderTopStyle | BorderRightStyle | BorderBottomStyle | BorderLeftStyle => {
+                Keyword(LINE_STYLES)
+            }
+            OutlineStyle => Keyword(&[
+                "auto", "none", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset",
+                "outset",
+            ]),
+            PropertyId::Color | BackgroundColor | BorderTopColor | BorderRightColor | BorderBottomColor
+            | BorderLeftColor | OutlineColor | TextDecorationColor => Grammar::Color,
+            BoxSizing => Keyword(&["content-box", "border-box"]),
+            FontSize => LengthPercentage(&[
+                "xx-small", "x-small", "small", "medium", "large", "x-large", "xx-large",
+                "xxx-large", "smaller", "larger",
+            ]),
+            FontWeight => Number(&["normal", "bold", "bolder", "lighter"]),
+            FontStyle => Keyword(&["normal", "italic", "oblique"]),
+            LineHeight => NumberLengthPercentage(&["normal"]),
+            LetterSpacing | WordSpacing => LengthPercentage(&["normal"]),
+            TextAlign => Keyword(&[
+                "left", "right", "center", "justify", "start", "end", "match-parent",
+            ]),
+            TextTransform => Keyword(&["none", "capitalize", "uppercase", "lowercase", "full-width"]),
+            TextOverflow => Keyword(&["clip", "ellipsis"]),
+            WhiteSpace => Keyword(&[
+                "normal", "nowrap", "pre", "pre-wrap", "pre-line", "break-spaces",
+            ]),
+            WordBreak => Keyword(&["normal", "break-all", "keep-all", "break-word"]),
+            WordWrap | OverflowWrap => Keyword(&["normal", "break-word", "anywhere"]),
+            FlexDirection => Keyword(&["row", "row-reverse", "column", "column-reverse"]),
+            FlexWrap => Keyword(&["nowrap", "wrap", "wrap-reverse"]),
+            JustifyContent | AlignItems | AlignContent | AlignSelf | JustifyItems | JustifySelf => {
+                Keywords(ALIGNMENT)
+            }
+            Order => Integer(&[]),
+            FlexGrow | FlexShrink => Number(&[]),
+            RowGap | ColumnGap => LengthPercentage(&["normal"]),
+            TransformStyle => Keyword(&["flat", "preserve-3d"]),
+            Backface => Keyword(&["visible", "hidden"]),
+            Opacity => Alpha,
+            MixBlendMode => Keyword(&[
+                "normal", "multiply", "screen", "overlay", "darken", "lighten", "color-dodge",
+                "color-burn", "hard-light", "soft-light", "difference", "exclusion", "hue",
+                "saturation", "color", "luminosity", "plus-lighter",
+            ]),
+            ListStylePosition => Keyword(&["inside", "outside"]),
+            BorderCollapse => Keyword(&["collapse", "separate"]),
+            TableLayout => Keyword(&["auto", "fixed"]),
+            CaptionSide => Keyword(&["top", "bottom"]),
+            EmptyCells => Keyword(&["show", "hide"]),
+            Resize => Keyword(&["none", "both", "horizontal", "vertical", "block", "inline"]),
+            UserSelect => Keyword(&["auto", "text", "none", "contain", "all"]),
+            PointerEvents => Keyword(&[
+                "auto", "none", "visiblepainted", "visiblefill", "visiblestroke", "visible",
+                "painted", "fill", "stroke", "all",
+            ]),
+            ContainerType => Keyword(&["normal", "size", "inline-size"]),
+            ObjectFit => Keyword(&["fill", "contain", "cover", "none", "scale-down"]),
+            Direction => Keyword(&["ltr", "rtl"]),
+            WritingMode => Keyword(&[
+                "horizontal-tb", "vertical-rl", "vertical-lr", "sideways-rl", "sideways-lr",
+            ]),
+            BorderTopLeftRadius | BorderTopRightRadius | BorderBottomRightRadius
+            | BorderBottomLeftRadius | BorderSpacing => LengthPercentages(&[]),
+            BackgroundImage | ListStyleImage => Functions(IMAGES, &["none"]),
+            BackgroundPosition | BackgroundPositionX | BackgroundPositionY | ObjectPosition
+            | TransformOrigin | PerspectiveOrigin => LengthPercentages(POSITIONS),
+            BackgroundSize => LengthPercentages(&["auto", "cover", "contain"]),
+            BackgroundRepeat => {
+                Keywords(&["repeat", "repeat-x", "repeat-y", "no-repeat", "space", "round"])
+            }
+            BackgroundAttachment => Keyword(&["scroll", "fixed", "local"]),
+            BackgroundOrigin => Keyword(&["border-box", "padding-box", "content-box"]),
+            BackgroundClip => Keyword(&["border-box", "padding-box", "content-box", "text"]),
+            FontFamily => Families,
+            FontVariant => Keywords(&["normal", "none", "small-caps", "all-small-caps"]),
+            FontStretch => LengthPercentage(&[
+                "normal", "ultra-condensed", "extra-condensed", "condensed", "semi-condensed",
+                "semi-expanded", "expanded", "extra-expanded", "ultra-expanded",
+            ]),
+            TextDecorationLine => Keywords(&["none", "underline", "overline", "line-through"]),
+            TextDecorationStyle => Keyword(&["solid", "double", "dotted", "dashed", "wavy"]),
+            TextShadow | BoxShadow => Shadows,
+            GridTemplateColumns | GridTemplateRows | GridAutoColumns | GridAutoRows => Tracks,
+            GridTemplateAreas => Strings,
+            GridAutoFlow => Keywords(&["row", "column", "dense"]),
+            GridRowStart | GridRowEnd | GridColumnStart | GridColumnEnd | CounterReset
+            | CounterIncrement => IdentsIntegers,
+            Transform => Functions(TRANSFORMS, &["none"]),
+            Perspective => LengthPercentage(&["none"]),
+            TransitionProperty | AnimationName | ContainerName | ListStyleType => Idents,
+            TransitionDuration | TransitionDelay | AnimationDuration | AnimationDelay => Times,
+            TransitionTimingFunction | AnimationTimingFunction => {
+                Functions(&["cubic-bezier", "steps", "linear"], TIMING_FUNCTIONS)
+            }
+            AnimationIterationCount => Number(&["infinite"]),
+            AnimationDirection => {
+                Keywords(&["normal", "reverse", "alternate", "alternate-reverse"])
+            }
+            AnimationFillMode => Keywords(&["none", "forwards", "backwards", "both"]),
+            AnimationPlayState => Keywords(&["running", "paused"]),
+            Filter | BackdropFilter => Functions(FILTERS, &["none"]),
+            Cursor => Keyword(&[
+                "auto", "default", "none", "pointer", "text", "move", "wait", "progress", "help",
+                "crosshair", "not-allowed", "grab", "grabbing", "context-menu", "cell", "copy",
+                "alias", "no-drop", "vertical-text", "all-scroll", "zoom-in", "zoom-out",
+                "col-resize", "row-resize", "n-resize", "e-resize", "s-resize", "w-resize",
+                "ne-resize", "nw-resize", "se-resize", "sw-resize", "ew-resize", "ns-resize",
+                "nesw-resize", "nwse-resize",
+            ]),
+            OutlineOffset => LengthPercentage(&[]),
+            VerticalAlign => LengthPercentage(&[
+                "baseline", "sub", "super", "text-top", "text-bottom", "middle", "top", "bottom",
+            ]),
+            UnicodeBidi => Keyword(&[
+                "normal", "embed", "isolate", "bidi-override", "isolate-override", "plaintext",
+            ]),
+            TextOrientation => Keyword(&["mixed", "upright", "sideways"]),
+            ImageRendering => {
+                Keyword(&["auto", "smooth", "high-quality", "crisp-edges", "pixelated"])
+            }
+            PageBreakBefore | PageBreakAfter => {
+                Keyword(&["auto", "always", "avoid", "left", "right"])
+            }
+            PageBreakInside => Keyword(&["auto", "avoid"]),
+            StrokeWidth => LengthPercentage(&[]),
+            _ => Unsupported,
+        }
+    }
+
+    /// Whether `value` is valid for the property. Values with `var()` are
+    /// only checked once substituted. Shorthands are checked through the
+    /// longhands they expand to, and properties without a grammar here
+    /// accept nothing.
+    pub fn accepts(&self, value: &CssValue) -> bool {
+        if let PropertyId::Custom(name) = self {
+            return name.starts_with("--");
+        }
+        if value.is_css_wide_keyword()
+            || matches!(value, CssValue::Unparsed(_))
+            || ["initial", "inherit", "unset", "revert", "revert-layer"]
+                .iter()
+                .any(|keyword| value.is_keyword(keyword))
+        {
+            return true;
+        }
+
+        if self.is_shorthand() {
+            let longhands = crate::shorthand::expand_shorthand(self, value);
+            return !longhands.is_empty()
+                && longhands.iter().all(|(longhand, value)| {
+                    longhand != self && (*value == CssValue::Initial || longhand.accepts(value))
+                });
+        }
+
+        let keyword = |keywords: &[&str]| match value {
+            CssValue::Ident(ident) => keywords.iter().any(|k| ident.eq_ignore_ascii_case(k)),
+            _ => false,
+        };
+        let length_percentage = is_length_percentage(value);
+        let items = |accepts: &dyn Fn(&CssValue) -> bool| match value {
+            CssValue::List(values) => values.iter().all(accepts),
+            value => accepts(value),
+        };
+        match self.grammar() {
+            Grammar::Keyword(keywords) => keyword(keywords),
+            Grammar::Keywords(keywords) => match value {
+                CssValue::List(values) => values.iter().all(|value| {
+                    matches!(value, CssValue::Ident(ident)
+                        if keywords.iter().any(|k| ident.eq_ignore_ascii_case(k)))
+                }),
+                _ => keyword(keywords),
+            },
+            Grammar::LengthPercentage(keywords) => length_percentage || keyword(keywords),
+            Grammar::NumberLengthPercentage(keywords) => {
+                length_percentage || value_type(value) == Some(CalcType::Number) || keyword(keywords)
+            }
+            Grammar::Number(keywords) => {
+                value_type(value) == Some(CalcType::Number) || keyword(keywords)
+            }
+            Grammar::Integer(keywords) => {
+                matches!(value, CssValue::Number(n) if n.fract() == 0.0)
+                    || matches!(value, CssValue::Calc(calc) if calc.ty() == Some(CalcType::Number))
+                    || keyword(keywords)
+            }
+            Grammar::Alpha => {
+                matches!(value_type(value), Some(CalcType::Number | CalcType::Percentage))
+            }
+            Grammar::Color => {
+                value.as_color().is_some()
+                    || keyword(&["currentcolor", "transparent"])
+                    || matches!(value, CssValue::Ident(name)
+                        if crate::color::system_color(name).is_some())
+            }
+            Grammar::LengthPercentages(keywords) => items(&|item| {
+                is_length_percentage(item)
+                    || matches!(item, CssValue::Ident(ident)
+                        if keywords.iter().any(|k| ident.eq_ignore_ascii_case(k)))
+            }),
+            Grammar::Functions(functions, keywords) => items(&|item| match item {
+                CssValue::Function(name, _) => {
+                    functions.iter().any(|f| name.eq_ignore_ascii_case(f))
+                }
+                CssValue::Url(_) => functions.contains(&"url"),
+                CssValue::Ident(ident) => keywords.iter().any(|k| ident.eq_ignore_ascii_case(k)),
+                _ => false,
+            }),
+            Grammar::Idents => items(&|item| matches!(item, CssValue::Ident(_))),
+            Grammar::IdentsIntegers => items(&|item| {
+                matches!(item, CssValue::Ident(_))
+                    || matches!(item, CssValue::Number(n) if n.fract() == 0.0)
+            }),
+            Grammar::Families => {
+                items(&|item| matches!(item, CssValue::Ident(_) | CssValue::String(_)))
+            }
+            // Subgrid and masonry tracks are not implemented.
+            Grammar::Tracks => {
+                keyword(&["none"])
+                    || items(&|item| match item {
+                        CssValue::Dimension(_, unit) if unit.eq_ignore_ascii_case("fr") => true,
+                        CssValue::Function(name, _) => ["repeat", "minmax", "fit-content"]
+                            .iter()
+                            .any(|f| name.eq_ignore_ascii_case(f)),
+                        CssValue::Ident(ident) => ["auto", "min-content", "max-content"]
+                            .iter()
+                            .any(|k| ident.eq_ignore_ascii_case(k)),
+                        item => is_length_percentage(item),
+                    })
+            }
+            Grammar::Times => items(&|item| value_type(item) == Some(CalcType::Time)),
+            Grammar::Shadows => {
+                keyword(&["none"])
+                    || items(&|item| {
+                        is_length_percentage(item)
+                            || item.is_keyword("inset")
+                            || PropertyId::Color.accepts(item)
+                    })
+            }
+            Grammar::Strings => {
+                keyword(&["none"]) || items(&|item| matches!(item, CssValue::String(_)))
+            }
+            Grammar::Unsupported => false,
+        }
+    }
+}
+
+/// Whether `value` is a length or percentage, unitless zero included.
+fn is_length_percentage(value: &CssValue) -> bool {
+    matches!(
+        value_type(value),
+        Some(CalcType::Length | CalcType::Percentage | CalcType::LengthPercentage)
+    ) || matches!(value, CssValue::Number(n) if *n == 0.0)
+}
+
+/// The type of a single numeric value.
+fn value_type(value: &CssValue) -> Option<CalcType> {
+    match value {
+        CssValue::Number(_) => Some(CalcType::Number),
+        CssValue::Percentage(_) => Some(CalcType::Percentage),
+        CssValue::Dimension(_, unit) => crate::calc::unit_type(unit),
+        CssValue::Calc(calc) => calc.ty(),
+        _ => None,
     }
 }
 
@@ -652,6 +1037,8 @@ mod tests {
         assert!(PropertyId::FontFamily.inherited());
         assert!(!PropertyId::Width.inherited());
         assert!(!PropertyId::Margin.inherited());
+        assert!(PropertyId::from_name("--Accent").inherited());
+        assert_eq!(PropertyId::from_name("--Accent").name(), "--Accent");
     }
 
     #[test]
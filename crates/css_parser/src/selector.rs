@@ -2,6 +2,17 @@
 
 use std::cmp::Ordering;
 
+/// Pseudo-classes selector matching implements; selectors naming any
+/// other pseudo-class are invalid. Matching in `style` handles each name
+/// in this list and matches no other.
+pub const PSEUDO_CLASSES: &[&str] = &[
+    "active", "any-link", "checked", "defined", "disabled", "empty", "enabled", "first-child",
+    "first-of-type", "focus", "focus-visible", "focus-within", "has", "host", "host-context",
+    "hover", "invalid", "is", "last-child", "last-of-type", "link", "not", "nth-child",
+    "nth-last-child", "nth-last-of-type", "nth-of-type", "only-child", "only-of-type", "optional",
+    "required", "root", "scope", "target", "valid", "visited", "where",
+];
+
 /// List of selectors (comma-separated).
 #[derive(Clone, Debug, Default)]
 pub struct SelectorList {
@@ -50,11 +61,14 @@ pub struct Selector {
     /// Attribute selectors.
     pub attributes: Vec<AttributeSelector>,
     /// Pseudo-classes with optional arguments.
-    pub pseudo_classes: Vec<(String, Option<String>)>,
+    pub pseudo_classes: Vec<PseudoClass>,
     /// Pseudo-elements.
     pub pseudo_elements: Vec<String>,
     /// Universal selector (*).
     pub universal: bool,
+    /// Nesting selector (&) not resolved against an enclosing rule, which
+    /// behaves as `:scope`.
+    pub nesting: bool,
     /// Combinator to next selector.
     pub combinator: Option<Combinator>,
     /// Next selector in chain.
@@ -108,12 +122,34 @@ impl Selector {
         }
 
         // Class selectors, attribute selectors, pseudo-classes
+        if self.nesting {
+            spec.b += 1;
+        }
         spec.b += self.classes.len() as u32;
         spec.b += self.attributes.len() as u32;
-        spec.b += self.pseudo_classes
+        spec.b += self
+            .pseudo_classes
             .iter()
-            .filter(|(name, _)| !matches!(name.as_str(), "not" | "is" | "where" | "has"))
+            .filter(|pseudo| !matches!(pseudo.name.as_str(), "not" | "is" | "where" | "has"))
             .count() as u32;
+        // `:is()`, `:not()` and `:has()` take the specificity of their most
+        // specific argument, `:where()` adds none.
+        for pseudo in &self.pseudo_classes {
+            let max = match pseudo.name.as_str() {
+                "is" | "not" => pseudo
+                    .selectors()
+                    .map(SelectorList::max_specificity)
+                    .unwrap_or_default(),
+                "has" => pseudo
+                    .relative_selectors()
+                    .and_then(|list| list.iter().map(|r| r.selector.specificity()).max())
+                    .unwrap_or_default(),
+                _ => continue,
+            };
+            spec.a += max.a;
+            spec.b += max.b;
+            spec.c += max.c;
+        }
 
         // Type selectors, pseudo-elements
         if self.tag.is_some() && !self.universal {
@@ -132,6 +168,25 @@ impl Selector {
         spec
     }
 
+    /// Whether the selector contains an unresolved nesting selector.
+    pub fn has_nesting(&self) -> bool {
+        self.nesting || self.next.as_ref().is_some_and(|next| next.has_nesting())
+    }
+
+    /// Resolve `&` to `:is()` of the selectors of the enclosing rule.
+    pub fn resolve_nesting(&mut self, parent: &SelectorList) {
+        if self.nesting {
+            self.nesting = false;
+            self.pseudo_classes.insert(
+                0,
+                PseudoClass::with_args("is", PseudoClassArgs::Selectors(parent.clone())),
+            );
+        }
+        if let Some(next) = &mut self.next {
+            next.resolve_nesting(parent);
+        }
+    }
+
     /// Check if selector matches element.
     pub fn matches(&self, element: &dom::element::ElementData) -> bool {
         // Check tag
@@ -164,8 +219,8 @@ impl Selector {
         }
 
         // Check pseudo-classes (simplified)
-        for (pseudo, args) in &self.pseudo_classes {
-            if !self.matches_pseudo_class(element, pseudo, args.as_deref()) {
+        for pseudo in &self.pseudo_classes {
+            if !self.matches_pseudo_class(element, &pseudo.name, pseudo.raw_args()) {
                 return false;
             }
         }
@@ -244,6 +299,10 @@ impl Selector {
     pub fn to_css_string(&self) -> String {
         let mut result = String::new();
 
+        if self.nesting {
+            result.push('&');
+        }
+
         if self.universal && self.tag.is_none() {
             result.push('*');
         }
@@ -266,14 +325,8 @@ impl Selector {
             result.push_str(&attr.to_css_string());
         }
 
-        for (pseudo, args) in &self.pseudo_classes {
-            result.push(':');
-            result.push_str(pseudo);
-            if let Some(args) = args {
-                result.push('(');
-                result.push_str(args);
-                result.push(')');
-            }
+        for pseudo in &self.pseudo_classes {
+            result.push_str(&pseudo.to_css_string());
         }
 
         for pseudo in &self.pseudo_elements {
@@ -293,6 +346,115 @@ impl Selector {
     }
 }
 
+/// A pseudo-class, such as `:hover` or `:not(.a)`.
+#[derive(Clone, Debug)]
+pub struct PseudoClass {
+    /// Lowercase name.
+    pub name: String,
+    /// Arguments, for a functional pseudo-class.
+    pub args: Option<PseudoClassArgs>,
+}
+
+/// Arguments of a functional pseudo-class, parsed with the selector.
+#[derive(Clone, Debug)]
+pub enum PseudoClassArgs {
+    /// Selector list of `:not()`, `:host()` or `:host-context()`.
+    Selectors(SelectorList),
+    /// Forgiving selector list of `:is()` or `:where()`, and whether
+    /// invalid selectors were dropped from it.
+    Forgiving(SelectorList, bool),
+    /// Relative selectors of `:has()`.
+    Relative(Vec<RelativeSelector>),
+    /// Arguments of other pseudo-classes as written, such as the `An+B`
+    /// of `:nth-child()`.
+    Raw(String),
+}
+
+/// Parse the `An+B` argument of an `:nth-*()` pseudo-class into `(A, B)`.
+pub fn parse_nth(args: &str) -> Option<(i32, i32)> {
+    let args: String = args.split_whitespace().collect::<String>().to_ascii_lowercase();
+    match args.as_str() {
+        "odd" => return Some((2, 1)),
+        "even" => return Some((2, 0)),
+        _ => {}
+    }
+    let Some((a, b)) = args.split_once('n') else {
+        return args.parse().ok().map(|b| (0, b));
+    };
+    let a = match a {
+        "" | "+" => 1,
+        "-" => -1,
+        a => a.parse().ok()?,
+    };
+    let b = match b {
+        "" => 0,
+        b if b.starts_with(['+', '-']) => b.parse().ok()?,
+        _ => return None,
+    };
+    Some((a, b))
+}
+
+impl PseudoClass {
+    /// Create a pseudo-class without arguments.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_ascii_lowercase(),
+            args: None,
+        }
+    }
+
+    /// Create a functional pseudo-class.
+    pub fn with_args(name: &str, args: PseudoClassArgs) -> Self {
+        Self {
+            name: name.to_ascii_lowercase(),
+            args: Some(args),
+        }
+    }
+
+    /// Selector list argument, forgiving or not.
+    pub fn selectors(&self) -> Option<&SelectorList> {
+        match &self.args {
+            Some(PseudoClassArgs::Selectors(list) | PseudoClassArgs::Forgiving(list, _)) => {
+                Some(list)
+            }
+            _ => None,
+        }
+    }
+
+    /// Relative selector arguments.
+    pub fn relative_selectors(&self) -> Option<&[RelativeSelector]> {
+        match &self.args {
+            Some(PseudoClassArgs::Relative(list)) => Some(list),
+            _ => None,
+        }
+    }
+
+    /// Arguments that are not selectors, as written.
+    pub fn raw_args(&self) -> Option<&str> {
+        match &self.args {
+            Some(PseudoClassArgs::Raw(args)) => Some(args),
+            _ => None,
+        }
+    }
+
+    /// Convert to CSS string.
+    pub fn to_css_string(&self) -> String {
+        let args = match &self.args {
+            None => return format!(":{}", self.name),
+            Some(PseudoClassArgs::Selectors(list) | PseudoClassArgs::Forgiving(list, _)) => {
+                list.to_css_string()
+            }
+            Some(PseudoClassArgs::Relative(list)) => list
+                .iter()
+                .map(|r| r.to_css_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Some(PseudoClassArgs::Raw(args)) => args.clone(),
+        };
+        format!(":{}({})", self.name, args)
+    }
+}
+
 /// Selector combinator.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Combinator {
@@ -445,6 +607,59 @@ pub fn parse_selector(css: &str) -> Result<Selector, String> {
         .ok_or_else(|| "Invalid selector".to_string())
 }
 
+/// Parse a comma-separated selector list, as accepted by `querySelectorAll`.
+pub fn parse_selector_list(css: &str) -> Result<SelectorList, String> {
+    let mut input = cssparser::ParserInput::new(css);
+    let mut parser = cssparser::Parser::new(&mut input);
+
+    let list = crate::parser::parse_selector_list(&mut parser)
+        .map_err(|_| format!("'{}' is not a valid selector", css))?;
+    if !parser.is_exhausted() {
+        return Err(format!("'{}' is not a valid selector", css));
+    }
+    Ok(list)
+}
+
+/// Parse a forgiving selector list, as taken by `:is()` and `:where()`:
+/// invalid selectors are dropped rather than invalidating the list.
+pub fn parse_forgiving_selector_list(css: &str) -> SelectorList {
+    let mut input = cssparser::ParserInput::new(css);
+    let mut parser = cssparser::Parser::new(&mut input);
+    crate::parser::parse_forgiving_selector_list(&mut parser).0
+}
+
+/// A selector relative to an anchor element, as taken by `:has()`.
+#[derive(Clone, Debug)]
+pub struct RelativeSelector {
+    /// How the first compound relates to the anchor.
+    pub combinator: Combinator,
+    pub selector: Selector,
+}
+
+impl RelativeSelector {
+    /// Convert to CSS string.
+    pub fn to_css_string(&self) -> String {
+        let combinator = match self.combinator {
+            Combinator::Descendant => "",
+            combinator => combinator.as_str().trim_start(),
+        };
+        format!("{}{}", combinator, self.selector.to_css_string())
+    }
+}
+
+/// Parse a relative selector list such as `> img, + .caption`.
+pub fn parse_relative_selector_list(css: &str) -> Result<Vec<RelativeSelector>, String> {
+    let mut input = cssparser::ParserInput::new(css);
+    let mut parser = cssparser::Parser::new(&mut input);
+
+    let list = crate::parser::parse_relative_selector_list(&mut parser)
+        .map_err(|_| format!("'{}' is not a valid relative selector", css))?;
+    if !parser.is_exhausted() {
+        return Err(format!("'{}' is not a valid relative selector", css));
+    }
+    Ok(list)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -464,6 +679,19 @@ mod tests {
         assert_eq!(tag.specificity(), Specificity::new(0, 0, 1));
     }
 
+    #[test]
+    fn test_functional_pseudo_class_specificity() {
+        let specificity = |css: &str| parse_selector_list(css).unwrap().max_specificity();
+        assert_eq!(specificity(":is(.a, #b) p"), Specificity::new(1, 0, 1));
+        assert_eq!(specificity(":where(.a, #b) p"), Specificity::new(0, 0, 1));
+        assert_eq!(specificity("p:not(.a, div.b)"), Specificity::new(0, 1, 2));
+        assert_eq!(specificity("div:has(> img, + #c)"), Specificity::new(1, 0, 1));
+        // Invalid selectors are dropped from `:is()` but invalidate `:not()`.
+        assert_eq!(specificity(":is(!!, .a)"), Specificity::new(0, 1, 0));
+        assert!(parse_selector_list(":not(!!, .a)").is_err());
+        assert!(parse_selector_list(":has()").is_err());
+    }
+
     #[test]
     fn test_specificity_comparison() {
         let a = Specificity::new(1, 0, 0);
@@ -488,4 +716,43 @@ mod tests {
         assert!(css.contains(".container"));
         assert!(css.contains(".main"));
     }
+
+    #[test]
+    fn test_parse_selector_list() {
+        let list = parse_selector_list("ul > li:not(.x) a[href^=http], #main").unwrap();
+        assert_eq!(list.selectors.len(), 2);
+
+        let ul = &list.selectors[0];
+        assert_eq!(ul.tag.as_deref(), Some("ul"));
+        assert_eq!(ul.combinator, Some(Combinator::Child));
+        let li = ul.next.as_deref().unwrap();
+        assert_eq!(li.pseudo_classes.len(), 1);
+        assert_eq!(li.pseudo_classes[0].name, "not");
+        assert_eq!(li.pseudo_classes[0].selectors().unwrap().selectors[0].classes, ["x"]);
+        assert_eq!(li.combinator, Some(Combinator::Descendant));
+        let a = li.next.as_deref().unwrap();
+        assert_eq!(a.attributes[0].operator.as_deref(), Some("^="));
+        assert!(a.combinator.is_none());
+
+        assert_eq!(list.selectors[1].id.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_parse_shadow_selectors() {
+        let list = parse_selector_list(":host(.dark) ::slotted(p.note), slot::slotted(*)").unwrap();
+        let host = &list.selectors[0];
+        assert_eq!(host.pseudo_classes.len(), 1);
+        assert_eq!(host.pseudo_classes[0].to_css_string(), ":host(.dark)");
+        let slotted = host.next.as_deref().unwrap();
+        assert_eq!(slotted.pseudo_elements, vec!["slotted(p.note)".to_string()]);
+        assert_eq!(list.selectors[1].to_css_string(), "slot::slotted(*)");
+    }
+
+    #[test]
+    fn test_parse_selector_list_invalid() {
+        assert!(parse_selector_list("div >").is_err());
+        assert!(parse_selector_list("").is_err());
+        assert!(parse_selector_list("a {").is_err());
+        assert!(parse_selector_list(" p  ~  span ").is_ok());
+    }
 }
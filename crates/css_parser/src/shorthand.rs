@@ -0,0 +1,324 @@
+//! Shorthand property expansion.
+
+use crate::properties::PropertyId;
+use crate::values::CssValue;
+
+/// Expand shorthand property into longhands.
+///
+/// Properties that are not shorthands, and shorthands this engine does not
+/// expand, come back unchanged. A value with too many components for the
+/// shorthand expands to nothing.
+pub fn expand_shorthand(property: &PropertyId, value: &CssValue) -> Vec<(PropertyId, CssValue)> {
+    use PropertyId::*;
+
+    match property {
+        Margin => expand_four_sides(value, [MarginTop, MarginRight, MarginBottom, MarginLeft]),
+        Padding => expand_four_sides(
+            value,
+            [PaddingTop, PaddingRight, PaddingBottom, PaddingLeft],
+        ),
+        BorderWidth => expand_four_sides(
+            value,
+            [
+                BorderTopWidth,
+                BorderRightWidth,
+                BorderBottomWidth,
+                BorderLeftWidth,
+            ],
+        ),
+        BorderStyle => expand_four_sides(
+            value,
+            [
+                BorderTopStyle,
+                BorderRightStyle,
+                BorderBottomStyle,
+                BorderLeftStyle,
+            ],
+        ),
+        BorderColor => expand_four_sides(
+            value,
+            [
+                BorderTopColor,
+                BorderRightColor,
+                BorderBottomColor,
+                BorderLeftColor,
+            ],
+        ),
+        // Corners go clockwise from the top left one, like sides.
+        BorderRadius => expand_four_sides(
+            value,
+            [
+                BorderTopLeftRadius,
+                BorderTopRightRadius,
+                BorderBottomRightRadius,
+                BorderBottomLeftRadius,
+            ],
+        ),
+        Border => [
+            [BorderTopWidth, BorderTopStyle, BorderTopColor],
+            [BorderRightWidth, BorderRightStyle, BorderRightColor],
+            [BorderBottomWidth, BorderBottomStyle, BorderBottomColor],
+            [BorderLeftWidth, BorderLeftStyle, BorderLeftColor],
+        ]
+        .iter()
+        .flat_map(|side| expand_components(value, side, None))
+        .collect(),
+        BorderTop => expand_components(
+            value,
+            &[BorderTopWidth, BorderTopStyle, BorderTopColor],
+            None,
+        ),
+        BorderRight => expand_components(
+            value,
+            &[BorderRightWidth, BorderRightStyle, BorderRightColor],
+            None,
+        ),
+        BorderBottom => expand_components(
+            value,
+            &[BorderBottomWidth, BorderBottomStyle, BorderBottomColor],
+            None,
+        ),
+        BorderLeft => expand_components(
+            value,
+            &[BorderLeftWidth, BorderLeftStyle, BorderLeftColor],
+            None,
+        ),
+        Outline => expand_components(value, &[OutlineWidth, OutlineStyle, OutlineColor], None),
+        Background => expand_components(
+            value,
+            &[
+                BackgroundColor,
+                BackgroundImage,
+                BackgroundRepeat,
+                BackgroundAttachment,
+                BackgroundPosition,
+            ],
+            Some(BackgroundSize),
+        ),
+        Font => expand_components(
+            value,
+            &[FontStyle, FontWeight, FontSize, FontFamily],
+            Some(LineHeight),
+        ),
+        TextDecoration => expand_components(
+            value,
+            &[TextDecorationLine, TextDecorationStyle, TextDecorationColor],
+            None,
+        ),
+        ListStyle => expand_components(
+            value,
+            &[ListStylePosition, ListStyleImage, ListStyleType],
+            None,
+        ),
+        FlexFlow => expand_components(value, &[FlexDirection, FlexWrap], None),
+        Flex => expand_flex(value),
+        Gap => expand_pair(value, RowGap, ColumnGap),
+        PlaceContent => expand_pair(value, AlignContent, JustifyContent),
+        PlaceItems => expand_pair(value, AlignItems, JustifyItems),
+        PlaceSelf => expand_pair(value, AlignSelf, JustifySelf),
+        Container => expand_container(value),
+        _ => vec![(property.clone(), value.clone())],
+    }
+}
+
+/// The space-separated components of a value.
+fn components(value: &CssValue) -> &[CssValue] {
+    match value {
+        CssValue::List(list) => list,
+        value => std::slice::from_ref(value),
+    }
+}
+
+/// Expand one to four values over the sides `top right bottom left`.
+fn expand_four_sides(value: &CssValue, sides: [PropertyId; 4]) -> Vec<(PropertyId, CssValue)> {
+    let values = components(value);
+    let indices = match values.len() {
+        1 => [0, 0, 0, 0],
+        2 => [0, 1, 0, 1],
+        3 => [0, 1, 2, 1],
+        4 => [0, 1, 2, 3],
+        _ => return Vec::new(),
+    };
+    sides
+        .into_iter()
+        .zip(indices)
+        .map(|(side, i)| (side, values[i].clone()))
+        .collect()
+}
+
+/// Expand one or two values over a pair of longhands.
+fn expand_pair(
+    value: &CssValue,
+    first: PropertyId,
+    second: PropertyId,
+) -> Vec<(PropertyId, CssValue)> {
+    match components(value) {
+        [one] => vec![(first, one.clone()), (second, one.clone())],
+        [one, two] => vec![(first, one.clone()), (second, two.clone())],
+        _ => Vec::new(),
+    }
+}
+
+/// Expand a shorthand whose components may come in any order: each goes
+/// to the first of `longhands` that accepts it, and the one after a `/`
+/// to `after_slash`. Longhands without components are reset to their
+/// initial values; a component no longhand accepts expands to nothing.
+fn expand_components(
+    value: &CssValue,
+    longhands: &[PropertyId],
+    after_slash: Option<PropertyId>,
+) -> Vec<(PropertyId, CssValue)> {
+    let mut assigned: Vec<Vec<CssValue>> = vec![Vec::new(); longhands.len()];
+    let mut slashed = None;
+    let mut values = components(value).iter();
+    while let Some(component) = values.next() {
+        if matches!(component, CssValue::Operator(op) if op == "/") {
+            match (&after_slash, values.next()) {
+                (Some(_), Some(next)) if slashed.is_none() => slashed = Some(next.clone()),
+                _ => return Vec::new(),
+            }
+            continue;
+        }
+        let longhand = (0..longhands.len()).find(|&i| {
+            let mut values = assigned[i].clone();
+            values.push(component.clone());
+            longhands[i].accepts(&list_value(values))
+        });
+        match longhand {
+            Some(index) => assigned[index].push(component.clone()),
+            None => return Vec::new(),
+        }
+    }
+
+    let mut expanded: Vec<(PropertyId, CssValue)> = longhands
+        .iter()
+        .cloned()
+        .zip(assigned)
+        .map(|(longhand, values)| match values.is_empty() {
+            true => (longhand, CssValue::Initial),
+            false => (longhand, list_value(values)),
+        })
+        .collect();
+    if let Some(after_slash) = after_slash {
+        expanded.push((after_slash, slashed.unwrap_or(CssValue::Initial)));
+    }
+    expanded
+}
+
+/// A single value, or a list of several.
+fn list_value(mut values: Vec<CssValue>) -> CssValue {
+    match values.len() {
+        1 => values.pop().unwrap(),
+        _ => CssValue::List(values),
+    }
+}
+
+fn expand_container(value: &CssValue) -> Vec<(PropertyId, CssValue)> {
+    // `container: <name> [/ <type>]`
+    let mut parts = components(value).split(|v| matches!(v, CssValue::Operator(op) if op == "/"));
+    let names = parts.next().unwrap_or_default().to_vec();
+    let container_type = parts
+        .next()
+        .and_then(|part| part.first().cloned())
+        .unwrap_or_else(|| CssValue::Ident("normal".to_string()));
+    vec![
+        (PropertyId::ContainerName, CssValue::List(names)),
+        (PropertyId::ContainerType, container_type),
+    ]
+}
+
+fn expand_flex(value: &CssValue) -> Vec<(PropertyId, CssValue)> {
+    let flex = |grow: CssValue, shrink: CssValue, basis: CssValue| {
+        vec![
+            (PropertyId::FlexGrow, grow),
+            (PropertyId::FlexShrink, shrink),
+            (PropertyId::FlexBasis, basis),
+        ]
+    };
+    let auto = || CssValue::Ident("auto".to_string());
+
+    match value {
+        CssValue::Ident(s) if s == "none" => {
+            flex(CssValue::Number(0.0), CssValue::Number(0.0), auto())
+        }
+        CssValue::Ident(s) if s == "auto" => {
+            flex(CssValue::Number(1.0), CssValue::Number(1.0), auto())
+        }
+        CssValue::Number(n) => flex(
+            CssValue::Number(*n),
+            CssValue::Number(1.0),
+            CssValue::Number(0.0),
+        ),
+        CssValue::List(list) => match list.as_slice() {
+            [grow, shrink @ CssValue::Number(_)] => {
+                flex(grow.clone(), shrink.clone(), CssValue::Number(0.0))
+            }
+            [grow, basis] => flex(grow.clone(), CssValue::Number(1.0), basis.clone()),
+            [grow, shrink, basis] => flex(grow.clone(), shrink.clone(), basis.clone()),
+            _ => Vec::new(),
+        },
+        // `flex: <flex-basis>`
+        basis => flex(CssValue::Number(1.0), CssValue::Number(1.0), basis.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_value;
+
+    #[test]
+    fn test_expand_shorthands() {
+        let expand = |property: &str, css: &str| {
+            expand_shorthand(&PropertyId::from_name(property), &parse_value(css).unwrap())
+        };
+        let margin = expand("margin", "1px 2px 3px");
+        assert_eq!(
+            margin[3],
+            (
+                PropertyId::MarginLeft,
+                CssValue::Dimension(2.0, "px".to_string())
+            )
+        );
+        assert!(expand("margin", "1px 2px 3px 4px 5px").is_empty());
+
+        let border = expand("border-top", "solid 2px");
+        assert_eq!(
+            border[0],
+            (
+                PropertyId::BorderTopWidth,
+                CssValue::Dimension(2.0, "px".to_string())
+            )
+        );
+        assert_eq!(
+            border[1],
+            (
+                PropertyId::BorderTopStyle,
+                CssValue::Ident("solid".to_string())
+            )
+        );
+        assert_eq!(border[2], (PropertyId::BorderTopColor, CssValue::Initial));
+        assert!(expand("border", "solid banana").is_empty());
+
+        let font = expand("font", "italic 12px/1.5 Georgia, serif");
+        assert_eq!(
+            font[0],
+            (PropertyId::FontStyle, CssValue::Ident("italic".to_string()))
+        );
+        assert_eq!(
+            font[2],
+            (
+                PropertyId::FontSize,
+                CssValue::Dimension(12.0, "px".to_string())
+            )
+        );
+        assert_eq!(font[4], (PropertyId::LineHeight, CssValue::Number(1.5)));
+        assert_eq!(
+            font[3].1,
+            CssValue::List(vec![
+                CssValue::Ident("Georgia".to_string()),
+                CssValue::Ident("serif".to_string())
+            ])
+        );
+    }
+}
@@ -1,8 +1,12 @@
 //! CSS Stylesheet structure.
 
+use crate::container::ContainerCondition;
 use crate::media::MediaQueryList;
 use crate::properties::PropertyDeclaration;
 use crate::selector::SelectorList;
+use crate::supports::SupportsCondition;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use url::Url;
 
@@ -39,9 +43,19 @@ impl Stylesheet {
     fn collect_style_rules<'a>(&'a self, rules: &'a [CssRule], out: &mut Vec<&'a StyleRule>) {
         for rule in rules {
             match rule {
-                CssRule::Style(style) => out.push(style),
+                CssRule::Style(style) => {
+                    out.push(style);
+                    self.collect_style_rules(&style.rules, out);
+                }
                 CssRule::Media(media) => self.collect_style_rules(&media.rules, out),
                 CssRule::Supports(supports) => self.collect_style_rules(&supports.rules, out),
+                CssRule::LayerBlock(layer) => self.collect_style_rules(&layer.rules, out),
+                CssRule::Container(container) => self.collect_style_rules(&container.rules, out),
+                CssRule::Import(import) => {
+                    if let Some(sheet) = &import.stylesheet {
+                        self.collect_style_rules(&sheet.rules, out);
+                    }
+                }
                 _ => {}
             }
         }
@@ -96,10 +110,16 @@ pub enum CssRule {
     Keyframes(KeyframesRule),
     /// @supports rule.
     Supports(SupportsRule),
+    /// @container rule.
+    Container(ContainerRule),
     /// @charset rule.
     Charset,
     /// @namespace rule.
     Namespace { prefix: Option<String>, url: String },
+    /// @layer block.
+    LayerBlock(LayerBlockRule),
+    /// @layer statement, declaring the order of layers.
+    LayerStatement(LayerStatementRule),
     /// @page rule.
     Page {
         selector: Option<String>,
@@ -114,6 +134,8 @@ pub struct StyleRule {
     pub selectors: SelectorList,
     /// Property declarations.
     pub declarations: Vec<PropertyDeclaration>,
+    /// Nested rules, with `&` resolved against `selectors`.
+    pub rules: Vec<CssRule>,
 }
 
 impl StyleRule {
@@ -137,10 +159,22 @@ pub struct ImportRule {
     pub resolved_url: Option<Url>,
     /// Media query list.
     pub media: MediaQueryList,
-    /// Imported stylesheet (loaded later).
+    /// Cascade layer from `layer` or `layer(name)`.
+    pub layer: Option<LayerName>,
+    /// Condition from `supports(...)`.
+    pub supports: Option<SupportsCondition>,
+    /// Imported stylesheet, set by `import::resolve_imports`.
     pub stylesheet: Option<Arc<Stylesheet>>,
 }
 
+impl ImportRule {
+    /// Check if the media and supports conditions match.
+    pub fn matches(&self, context: &crate::media::MediaContext) -> bool {
+        self.media.matches(context)
+            && self.supports.as_ref().map_or(true, SupportsCondition::matches)
+    }
+}
+
 /// @media rule.
 #[derive(Clone, Debug)]
 pub struct MediaRule {
@@ -300,11 +334,54 @@ impl KeyframeRule {
     }
 }
 
+/// Name of a cascade layer, e.g. `framework.base`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LayerName(pub Vec<String>);
+
+impl LayerName {
+    /// A new anonymous layer, distinct from every other layer.
+    pub fn anonymous() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        // Parsed identifiers never contain NUL, so this can't clash.
+        LayerName(vec![format!("\0{}", NEXT.fetch_add(1, Ordering::Relaxed))])
+    }
+
+    /// Whether this is an anonymous layer.
+    pub fn is_anonymous(&self) -> bool {
+        self.0.iter().any(|segment| segment.starts_with('\0'))
+    }
+}
+
+impl fmt::Display for LayerName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_anonymous() {
+            return Ok(());
+        }
+        write!(f, "{}", self.0.join("."))
+    }
+}
+
+/// @layer block.
+#[derive(Clone, Debug)]
+pub struct LayerBlockRule {
+    /// Layer name, anonymous if none was given.
+    pub name: LayerName,
+    /// Nested rules.
+    pub rules: Vec<CssRule>,
+}
+
+/// @layer statement.
+#[derive(Clone, Debug)]
+pub struct LayerStatementRule {
+    /// Declared layers, in order.
+    pub names: Vec<LayerName>,
+}
+
 /// @supports rule.
 #[derive(Clone, Debug)]
 pub struct SupportsRule {
-    /// Condition string.
-    pub condition: String,
+    /// Condition.
+    pub condition: SupportsCondition,
     /// Nested rules.
     pub rules: Vec<CssRule>,
 }
@@ -312,12 +389,21 @@ pub struct SupportsRule {
 impl SupportsRule {
     /// Check if supports condition is met.
     pub fn matches(&self) -> bool {
-        // Parse and evaluate condition
-        // Simplified - always return true
-        true
+        self.condition.matches()
     }
 }
 
+/// @container rule.
+#[derive(Clone, Debug)]
+pub struct ContainerRule {
+    /// Name the query container must have, if any.
+    pub name: Option<String>,
+    /// Size condition, evaluated against the query container.
+    pub condition: ContainerCondition,
+    /// Nested rules.
+    pub rules: Vec<CssRule>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,326 @@
+//! Feature queries.
+//!
+//! An `@supports` rule applies if this engine supports the declarations,
+//! selectors and font features its condition names. Support does not
+//! change while a page is shown, so conditions are checked as they are
+//! parsed. The same logic backs `CSS.supports()`.
+
+use crate::parser::CssParseError;
+use crate::properties::PropertyId;
+use crate::selector::{parse_selector_list, PseudoClassArgs, Selector, PSEUDO_CLASSES};
+use cssparser::{ParseError, Parser, ParserInput, Token};
+use url::Url;
+
+/// Pseudo-elements selector matching implements.
+const PSEUDO_ELEMENTS: &[&str] = &["slotted"];
+
+/// Font formats `font-format()` knows, and those the font loader reads.
+const FONT_FORMATS: &[&str] = &[
+    "collection",
+    "embedded-opentype",
+    "opentype",
+    "svg",
+    "truetype",
+    "woff",
+    "woff2",
+];
+const SUPPORTED_FONT_FORMATS: &[&str] = &["collection", "opentype", "truetype"];
+
+/// Font technologies `font-tech()` knows.
+const FONT_TECHS: &[&str] = &[
+    "features-opentype",
+    "features-aat",
+    "features-graphite",
+    "variations",
+    "color-colrv0",
+    "color-colrv1",
+    "color-svg",
+    "color-sbix",
+    "color-cbdt",
+    "palettes",
+    "incremental",
+];
+
+/// A `<supports-condition>`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SupportsCondition {
+    /// `(property: value)`.
+    Declaration {
+        declaration: String,
+        supported: bool,
+    },
+    /// `selector(...)`.
+    Selector {
+        selector: String,
+        supported: bool,
+    },
+    /// `font-tech(...)`.
+    FontTech(String),
+    /// `font-format(...)`.
+    FontFormat(String),
+    Not(Box<SupportsCondition>),
+    And(Vec<SupportsCondition>),
+    Or(Vec<SupportsCondition>),
+    /// Anything else in parentheses or a function, which is false.
+    GeneralEnclosed(String),
+}
+
+impl SupportsCondition {
+    /// Whether the engine supports what the condition asks for.
+    pub fn matches(&self) -> bool {
+        match self {
+            SupportsCondition::Declaration { supported, .. }
+            | SupportsCondition::Selector { supported, .. } => *supported,
+            // The font loader implements none of the font technologies.
+            SupportsCondition::FontTech(_) => false,
+            SupportsCondition::FontFormat(format) => {
+                SUPPORTED_FONT_FORMATS.contains(&format.as_str())
+            }
+            SupportsCondition::Not(condition) => !condition.matches(),
+            SupportsCondition::And(conditions) => conditions.iter().all(Self::matches),
+            SupportsCondition::Or(conditions) => conditions.iter().any(Self::matches),
+            SupportsCondition::GeneralEnclosed(_) => false,
+        }
+    }
+}
+
+/// `CSS.supports(conditionText)`: whether `condition` parses as a
+/// supports condition, or as a declaration without its parentheses, and
+/// matches.
+pub fn supports(condition: &str) -> bool {
+    let parse = |css: &str| {
+        let mut input = ParserInput::new(css);
+        let mut parser = Parser::new(&mut input);
+        let condition: ConditionResult<'_, _> = parser.parse_entirely(parse_condition);
+        condition.map(|condition| condition.matches()).ok()
+    };
+    parse(condition)
+        .or_else(|| parse(&format!("({})", condition)))
+        .unwrap_or(false)
+}
+
+/// `CSS.supports(property, value)`: whether `value` is a valid value for
+/// `property`.
+pub fn supports_declaration(property: &str, value: &str) -> bool {
+    let mut input = ParserInput::new(value);
+    let mut parser = Parser::new(&mut input);
+    let supported: ConditionResult<'_, _> =
+        parser.parse_entirely(|input| parse_declaration_value(property, input, false));
+    supported.unwrap_or(false)
+}
+
+type ConditionResult<'i, T> = Result<T, ParseError<'i, CssParseError<'i>>>;
+
+/// Parse a `<supports-condition>`.
+pub(crate) fn parse_condition<'i>(
+    input: &mut Parser<'i, '_>,
+) -> ConditionResult<'i, SupportsCondition> {
+    if input.try_parse(|i| i.expect_ident_matching("not")).is_ok() {
+        return Ok(SupportsCondition::Not(Box::new(parse_in_parens(input)?)));
+    }
+
+    let mut operands = vec![parse_in_parens(input)?];
+    let mut operator: Option<String> = None;
+    loop {
+        let state = input.state();
+        let ident = match input.next() {
+            Ok(Token::Ident(ident)) => ident.to_ascii_lowercase(),
+            _ => {
+                input.reset(&state);
+                break;
+            }
+        };
+        // `and` and `or` cannot be mixed without parentheses.
+        if !matches!(ident.as_str(), "and" | "or")
+            || operator.as_ref().is_some_and(|op| *op != ident)
+        {
+            return Err(input.new_custom_error(CssParseError::InvalidValue));
+        }
+        operator = Some(ident);
+        operands.push(parse_in_parens(input)?);
+    }
+
+    Ok(match operator.as_deref() {
+        Some("and") => SupportsCondition::And(operands),
+        Some(_) => SupportsCondition::Or(operands),
+        None => operands.pop().unwrap(),
+    })
+}
+
+/// Parse the contents of parentheses: a nested condition, or a
+/// declaration.
+pub(crate) fn parse_condition_or_declaration<'i>(
+    input: &mut Parser<'i, '_>,
+) -> ConditionResult<'i, SupportsCondition> {
+    let condition: ConditionResult<'i, _> = input.try_parse(|i| {
+        let condition = parse_condition(i)?;
+        i.expect_exhausted()?;
+        Ok(condition)
+    });
+    if let Ok(condition) = condition {
+        return Ok(condition);
+    }
+    let start = input.position();
+    let supported: ConditionResult<'i, _> = input.try_parse(|i| {
+        let name = i.expect_ident()?.clone();
+        i.expect_colon()?;
+        parse_declaration_value(&name, i, true)
+    });
+    let text = |input: &mut Parser<'i, '_>| {
+        while input.next().is_ok() {}
+        input.slice_from(start).trim().to_string()
+    };
+    Ok(match supported {
+        Ok(supported) => SupportsCondition::Declaration {
+            declaration: text(input),
+            supported,
+        },
+        Err(_) => SupportsCondition::GeneralEnclosed(format!("({})", text(input))),
+    })
+}
+
+fn parse_in_parens<'i>(input: &mut Parser<'i, '_>) -> ConditionResult<'i, SupportsCondition> {
+    let location = input.current_source_location();
+    match input.next()?.clone() {
+        Token::ParenthesisBlock => input.parse_nested_block(parse_condition_or_declaration),
+        Token::Function(name) => {
+            let name = name.to_ascii_lowercase();
+            input.parse_nested_block(|input| {
+                let start = input.position();
+                while input.next().is_ok() {}
+                let argument = input.slice_from(start).trim().to_string();
+                let keyword = argument.to_ascii_lowercase();
+                Ok(match name.as_str() {
+                    "selector" => SupportsCondition::Selector {
+                        supported: supports_selector(&argument),
+                        selector: argument,
+                    },
+                    "font-tech" if FONT_TECHS.contains(&keyword.as_str()) => {
+                        SupportsCondition::FontTech(keyword)
+                    }
+                    "font-format" if FONT_FORMATS.contains(&keyword.as_str()) => {
+                        SupportsCondition::FontFormat(keyword)
+                    }
+                    _ => SupportsCondition::GeneralEnclosed(format!("{}({})", name, argument)),
+                })
+            })
+        }
+        _ => Err(location.new_custom_error(CssParseError::InvalidValue)),
+    }
+}
+
+/// Parse the rest of a declaration for `property`, followed by
+/// `!important` if `important` allows it, and return whether the property
+/// exists and accepts the value.
+fn parse_declaration_value<'i>(
+    property: &str,
+    input: &mut Parser<'i, '_>,
+    important: bool,
+) -> ConditionResult<'i, bool> {
+    let property = PropertyId::from_name(property);
+    // Tokens the value parser skips make the declaration invalid.
+    let state = input.state();
+    while let Ok(token) = input.next() {
+        let stray = match token {
+            Token::Delim(c) => !matches!(c, '/' | '!'),
+            Token::BadUrl(_) | Token::BadString(_) | Token::CurlyBracketBlock => true,
+            Token::Colon | Token::Semicolon => true,
+            _ => false,
+        };
+        if stray && !property.is_custom_property() {
+            return Err(input.new_custom_error(CssParseError::InvalidValue));
+        }
+    }
+    input.reset(&state);
+
+    let base_url = Url::parse("about:blank").unwrap();
+    let value = crate::parser::parse_property_value(input, &property, &base_url)?;
+    if important {
+        let _ = input.try_parse(|i| {
+            i.expect_delim('!')?;
+            i.expect_ident_matching("important")
+        });
+    }
+    input.expect_exhausted()?;
+    Ok(property.accepts(&value))
+}
+
+/// Whether `css` is a single complex selector made of features selector
+/// matching implements.
+fn supports_selector(css: &str) -> bool {
+    match parse_selector_list(css) {
+        Ok(list) => list.selectors.len() == 1 && compounds_supported(&list.selectors[0]),
+        Err(_) => false,
+    }
+}
+
+fn compounds_supported(selector: &Selector) -> bool {
+    // Selectors dropped from a forgiving list are not supported either.
+    let pseudo_classes = selector.pseudo_classes.iter().all(|pseudo| {
+        PSEUDO_CLASSES.contains(&pseudo.name.as_str())
+            && match &pseudo.args {
+                Some(PseudoClassArgs::Forgiving(_, true)) => false,
+                Some(PseudoClassArgs::Relative(list)) => {
+                    list.iter().all(|r| compounds_supported(&r.selector))
+                }
+                _ => pseudo
+                    .selectors()
+                    .map_or(true, |list| list.selectors.iter().all(compounds_supported)),
+            }
+    });
+    let pseudo_elements = selector.pseudo_elements.iter().all(|pseudo| {
+        let name = pseudo.split('(').next().unwrap_or_default();
+        PSEUDO_ELEMENTS.contains(&name.to_ascii_lowercase().as_str())
+    });
+    pseudo_classes && pseudo_elements && selector.next.as_deref().map_or(true, compounds_supported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_supports_conditions() {
+        assert!(supports("(display: grid)"));
+        assert!(supports("display: flex"));
+        assert!(!supports("(display: subgrid)"));
+        assert!(!supports("(colour: red)"));
+        assert!(supports(
+            "(width: calc(100% - 2em)) and (color: rgb(0 0 0 / 50%))"
+        ));
+        assert!(!supports("(width: 10s) or (opacity: red)"));
+        assert!(supports("not (position: -webkit-sticky)"));
+        assert!(supports("(--accent: { anything })"));
+        assert!(supports("(margin: var(--gap))"));
+        assert!(!supports(
+            "(display: grid) and (display: flex) or (color: red)"
+        ));
+        assert!(!supports("(foo) or unknown(bar)"));
+
+        assert!(supports("selector(.card:has(> img))"));
+        assert!(!supports("selector(:blank)"));
+        assert!(!supports("selector(a, b)"));
+        assert!(!supports("selector(::before)"));
+        assert!(!supports("selector(:is(:blank, p))"));
+        assert!(supports("selector(p:nth-child(2n + 1))"));
+        assert!(!supports("selector(p:nth-child(2n of .a))"));
+        assert!(!supports("selector(p:hover(1))"));
+        assert!(supports("(transform: rotate(45deg))"));
+        assert!(supports("(margin: 0 auto)"));
+        assert!(supports("(border: 1px solid red)"));
+        assert!(supports("(grid-template-columns: repeat(3, 1fr))"));
+        assert!(!supports("(margin: red)"));
+        assert!(!supports("(transform: banana)"));
+        assert!(!supports("(background: banana)"));
+        assert!(!supports("(grid-template-columns: subgrid)"));
+        assert!(supports(
+            "font-format(truetype) and (not font-tech(color-colrv1))"
+        ));
+        assert!(!supports("font-format(woff2)"));
+
+        assert!(supports_declaration("z-index", "3"));
+        assert!(!supports_declaration("z-index", "1.5"));
+        assert!(!supports_declaration("z-index", "1 !important"));
+        assert!(!supports_declaration("color", "red; display: none"));
+    }
+}
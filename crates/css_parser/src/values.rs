@@ -1,5 +1,6 @@
 //! CSS Values.
 
+use crate::calc::{CalcNode, CalcType};
 use common::color::Color;
 use ordered_float::OrderedFloat;
 use std::fmt;
@@ -35,6 +36,11 @@ pub enum CssValue {
     Unset,
     /// Revert keyword.
     Revert,
+    /// Math function that did not simplify to a single value.
+    Calc(Box<CalcNode>),
+    /// Raw value of a custom property, or of a declaration containing
+    /// `var()`, resolved at computed-value time.
+    Unparsed(String),
 }
 
 impl CssValue {
@@ -77,6 +83,7 @@ impl CssValue {
                     _ => None, // Relative units need context
                 }
             }
+            CssValue::Calc(calc) if calc.ty() == Some(CalcType::Length) => calc.resolve(0.0),
             _ => None,
         }
     }
@@ -138,6 +145,8 @@ impl CssValue {
             CssValue::Inherit => "inherit".to_string(),
             CssValue::Unset => "unset".to_string(),
             CssValue::Revert => "revert".to_string(),
+            CssValue::Calc(calc) => calc.to_string(),
+            CssValue::Unparsed(css) => css.clone(),
         }
     }
 }
@@ -155,7 +164,7 @@ impl fmt::Display for CssValue {
 }
 
 /// Format number removing unnecessary decimals.
-fn format_number(n: f32) -> String {
+pub(crate) fn format_number(n: f32) -> String {
     if n.fract() == 0.0 {
         format!("{}", n as i32)
     } else {
@@ -165,7 +174,17 @@ fn format_number(n: f32) -> String {
 
 /// Parse color function.
 fn parse_color_function(name: &str, args: &[CssValue]) -> Option<Color> {
-    match name {
+    // Space-separated components parse as one list, with `/` before alpha.
+    let args: Vec<CssValue> = args
+        .iter()
+        .flat_map(|arg| match arg {
+            CssValue::List(values) => values.clone(),
+            value => vec![value.clone()],
+        })
+        .filter(|arg| !matches!(arg, CssValue::Operator(_)))
+        .collect();
+    let args = args.as_slice();
+    match name.to_ascii_lowercase().as_str() {
         "rgb" | "rgba" => {
             let (r, g, b, a) = parse_rgb_args(args)?;
             Some(Color::rgba(r, g, b, a))
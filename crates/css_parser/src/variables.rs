@@ -0,0 +1,147 @@
+//! Custom property references.
+//!
+//! Values that contain `var()` are kept as raw text by the parser and
+//! resolved at computed-value time: every reference is replaced by the
+//! value of the custom property, or its fallback, and the result is parsed
+//! again as an ordinary value.
+
+use cssparser::{ParseError, Parser, ParserInput, Token};
+
+/// Whether `css` contains a `var()` reference.
+pub fn contains_var(css: &str) -> bool {
+    let mut input = ParserInput::new(css);
+    has_var(&mut Parser::new(&mut input))
+}
+
+pub(crate) fn has_var(input: &mut Parser<'_, '_>) -> bool {
+    while let Ok(token) = input.next() {
+        let nested = match token {
+            Token::Function(name) if name.eq_ignore_ascii_case("var") => return true,
+            Token::Function(_)
+            | Token::ParenthesisBlock
+            | Token::SquareBracketBlock
+            | Token::CurlyBracketBlock => true,
+            _ => false,
+        };
+        if nested
+            && input
+                .parse_nested_block(|input| {
+                    let found = has_var(input);
+                    while input.next().is_ok() {}
+                    Ok::<_, ParseError<'_, ()>>(found)
+                })
+                .unwrap_or(false)
+        {
+            return true;
+        }
+    }
+    false
+}
+
+/// Replace the `var()` references in `css`, looking custom properties up
+/// with `lookup`. Returns `None` when a reference has neither a value nor a
+/// fallback, which makes the whole value invalid at computed-value time.
+pub fn substitute(css: &str, lookup: &mut dyn FnMut(&str) -> Option<String>) -> Option<String> {
+    let mut input = ParserInput::new(css);
+    let mut output = String::new();
+    substitute_block(&mut Parser::new(&mut input), lookup, &mut output)?;
+    Some(output.trim().to_string())
+}
+
+fn substitute_block(
+    input: &mut Parser<'_, '_>,
+    lookup: &mut dyn FnMut(&str) -> Option<String>,
+    output: &mut String,
+) -> Option<()> {
+    loop {
+        let start = input.position();
+        let token = match input.next_including_whitespace_and_comments() {
+            Ok(token) => token.clone(),
+            Err(_) => return Some(()),
+        };
+        let close = match &token {
+            Token::Function(name) if name.eq_ignore_ascii_case("var") => {
+                let value = input
+                    .parse_nested_block(|input| {
+                        Ok::<_, ParseError<'_, ()>>(substitute_var(input, lookup))
+                    })
+                    .ok()
+                    .flatten()?;
+                output.push_str(&value);
+                continue;
+            }
+            Token::Function(_) | Token::ParenthesisBlock => ")",
+            Token::SquareBracketBlock => "]",
+            Token::CurlyBracketBlock => "}",
+            _ => {
+                output.push_str(input.slice_from(start));
+                continue;
+            }
+        };
+        output.push_str(input.slice_from(start));
+        input
+            .parse_nested_block(|input| {
+                Ok::<_, ParseError<'_, ()>>(substitute_block(input, lookup, output))
+            })
+            .ok()
+            .flatten()?;
+        output.push_str(close);
+    }
+}
+
+/// Resolve the arguments of one `var()`: a custom property name and an
+/// optional fallback, which may be empty.
+fn substitute_var(
+    input: &mut Parser<'_, '_>,
+    lookup: &mut dyn FnMut(&str) -> Option<String>,
+) -> Option<String> {
+    let name = input.expect_ident().ok()?.to_string();
+    if !name.starts_with("--") {
+        return None;
+    }
+    let fallback = match input.next() {
+        Ok(Token::Comma) => true,
+        Ok(_) => return None,
+        Err(_) => false,
+    };
+    if let Some(value) = lookup(&name) {
+        while input.next().is_ok() {}
+        return Some(value);
+    }
+    if !fallback {
+        return None;
+    }
+    let mut value = String::new();
+    substitute_block(input, lookup, &mut value)?;
+    Some(value.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_with_fallbacks() {
+        let mut lookup = |name: &str| (name == "--gap").then(|| "4px".to_string());
+        assert!(contains_var("calc(1px + var(--gap))"));
+        assert!(!contains_var("1px solid red"));
+        assert_eq!(
+            substitute(
+                "calc(var(--gap) * 2) var(--missing, var(--gap))",
+                &mut lookup
+            )
+            .as_deref(),
+            Some("calc(4px * 2) 4px")
+        );
+        assert_eq!(
+            substitute("var(--missing,)", &mut lookup).as_deref(),
+            Some("")
+        );
+        assert_eq!(
+            substitute("var(--gap, 1px)", &mut lookup).as_deref(),
+            Some("4px")
+        );
+        assert_eq!(substitute("var(--missing) 1px", &mut lookup), None);
+        assert_eq!(substitute("var(gap)", &mut lookup), None);
+    }
+}
@@ -0,0 +1,207 @@
+//! Live collections (`HTMLCollection` and live `NodeList`).
+//!
+//! A live collection reflects the tree at the time it is read. Its members
+//! are cached and recomputed only when the tree's generation changes, so
+//! indexing in a loop stays cheap while mutations in the loop are seen.
+
+use crate::forms;
+use crate::node::NodeId;
+use crate::tree::DomTree;
+
+/// Which nodes a collection holds.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CollectionFilter {
+    /// Descendant elements with a tag name (`getElementsByTagName`);
+    /// `*` matches all.
+    TagName(String),
+    /// Descendant elements with all of the classes
+    /// (`getElementsByClassName`).
+    ClassNames(Vec<String>),
+    /// Child elements (`children`).
+    Children,
+    /// Child nodes (`childNodes`, a `NodeList`).
+    ChildNodes,
+    /// `form` elements (`document.forms`).
+    Forms,
+    /// Controls of a form (`form.elements`).
+    FormElements,
+}
+
+impl CollectionFilter {
+    /// The filter of `getElementsByClassName(names)`.
+    pub fn class_names(names: &str) -> Self {
+        CollectionFilter::ClassNames(names.split_whitespace().map(str::to_string).collect())
+    }
+
+    /// The filter of `getElementsByTagName(name)`.
+    pub fn tag_name(name: &str) -> Self {
+        CollectionFilter::TagName(name.to_ascii_lowercase())
+    }
+
+    fn collect(&self, tree: &DomTree, root: NodeId) -> Vec<NodeId> {
+        let element = |id| tree.get_element(id);
+        match self {
+            CollectionFilter::TagName(name) => tree
+                .descendants(root)
+                .filter(|&id| {
+                    element(id).is_some_and(|e| name == "*" || e.tag_name.as_str() == name)
+                })
+                .collect(),
+            CollectionFilter::ClassNames(classes) if classes.is_empty() => Vec::new(),
+            CollectionFilter::ClassNames(classes) => tree
+                .descendants(root)
+                .filter(|&id| element(id).is_some_and(|e| classes.iter().all(|c| e.has_class(c))))
+                .collect(),
+            CollectionFilter::Children => tree
+                .children(root)
+                .filter(|&id| element(id).is_some())
+                .collect(),
+            CollectionFilter::ChildNodes => tree.children(root).collect(),
+            CollectionFilter::Forms => tree
+                .descendants(root)
+                .filter(|&id| element(id).is_some_and(|e| e.tag_name.as_str() == "form"))
+                .collect(),
+            CollectionFilter::FormElements => forms::form_elements(tree, root),
+        }
+    }
+}
+
+/// A live collection rooted at a node.
+#[derive(Clone, Debug)]
+pub struct LiveCollection {
+    pub root: NodeId,
+    pub filter: CollectionFilter,
+    /// Members, as of a tree generation.
+    cache: Option<(u64, Vec<NodeId>)>,
+}
+
+impl LiveCollection {
+    pub fn new(root: NodeId, filter: CollectionFilter) -> Self {
+        Self {
+            root,
+            filter,
+            cache: None,
+        }
+    }
+
+    /// The members, in tree order.
+    pub fn nodes(&mut self, tree: &DomTree) -> &[NodeId] {
+        let generation = tree.generation();
+        if self
+            .cache
+            .as_ref()
+            .map_or(true, |(cached, _)| *cached != generation)
+        {
+            self.cache = Some((generation, self.filter.collect(tree, self.root)));
+        }
+        &self.cache.as_ref().expect("cache filled above").1
+    }
+
+    pub fn len(&mut self, tree: &DomTree) -> usize {
+        self.nodes(tree).len()
+    }
+
+    pub fn is_empty(&mut self, tree: &DomTree) -> bool {
+        self.nodes(tree).is_empty()
+    }
+
+    pub fn item(&mut self, tree: &DomTree, index: usize) -> Option<NodeId> {
+        self.nodes(tree).get(index).copied()
+    }
+
+    /// The first element whose `id`, or `name` attribute, is `name`.
+    pub fn named_item(&mut self, tree: &DomTree, name: &str) -> Option<NodeId> {
+        if name.is_empty() {
+            return None;
+        }
+        self.nodes(tree).iter().copied().find(|&id| {
+            tree.get_element(id).is_some_and(|e| {
+                e.get_attribute("id") == Some(name) || e.get_attribute("name") == Some(name)
+            })
+        })
+    }
+
+    /// The names `named_item` answers to, in tree order, without
+    /// duplicates.
+    pub fn names(&mut self, tree: &DomTree) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for &id in self.nodes(tree) {
+            let Some(element) = tree.get_element(id) else {
+                continue;
+            };
+            for name in [element.get_attribute("id"), element.get_attribute("name")]
+                .into_iter()
+                .flatten()
+            {
+                if !name.is_empty() && !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{ElementData, TagName};
+
+    #[test]
+    fn test_live_collection_tracks_mutations() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        tree.append_child(root, div);
+        let mut items = Vec::new();
+        for id in ["a", "b", "c"] {
+            let mut data = ElementData::new(TagName::p());
+            data.set_attribute("class", "item");
+            data.set_attribute("id", id);
+            let p = tree.create_element(data);
+            tree.append_child(div, p);
+            items.push(p);
+        }
+
+        let mut by_class = LiveCollection::new(root, CollectionFilter::class_names("item"));
+        let mut children = LiveCollection::new(div, CollectionFilter::Children);
+        assert_eq!(by_class.nodes(&tree), &items[..]);
+        assert_eq!(children.named_item(&tree, "b"), Some(items[1]));
+        assert_eq!(children.names(&tree), vec!["a", "b", "c"]);
+
+        // Removing members while looping over the collection.
+        while let Some(first) = by_class.item(&tree, 0) {
+            tree.get_element_mut(first)
+                .unwrap()
+                .remove_attribute("class");
+        }
+        assert!(by_class.is_empty(&tree));
+        assert_eq!(children.len(&tree), 3);
+
+        tree.remove_from_parent(items[0]);
+        assert_eq!(children.item(&tree, 0), Some(items[1]));
+        let text = tree.create_text("x".to_string());
+        tree.append_child(div, text);
+        assert_eq!(children.len(&tree), 2);
+        assert_eq!(
+            LiveCollection::new(div, CollectionFilter::ChildNodes).len(&tree),
+            3
+        );
+    }
+
+    #[test]
+    fn test_live_collection_without_hooks() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let first = tree.create_element(ElementData::new(TagName::p()));
+        let second = tree.create_element(ElementData::new(TagName::p()));
+        let mut paragraphs = LiveCollection::new(root, CollectionFilter::tag_name("p"));
+        assert_eq!(paragraphs.len(&tree), 0);
+        tree.append_child(root, first);
+        assert_eq!(paragraphs.len(&tree), 1);
+        tree.append_child(root, second);
+        assert_eq!(paragraphs.len(&tree), 2);
+        tree.remove_from_parent(first);
+        assert_eq!(paragraphs.nodes(&tree), &[second]);
+    }
+}
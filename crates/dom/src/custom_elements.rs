@@ -0,0 +1,460 @@
+//! Custom element definitions and reactions.
+//!
+//! The registry records which names are defined and what each definition
+//! observes; the script engine owns the constructors and callbacks. Tree
+//! mutations are turned into [`CustomElementReaction`]s by
+//! [`CustomElementReactions`], for the engine to run.
+
+use crate::element::{CustomElementState, ElementData};
+use crate::mutation::{Mutation, MutationHook, MutationKind};
+use crate::node::NodeId;
+use crate::tree::DomTree;
+use parking_lot::{Mutex, RwLock};
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Names that match the custom element name production but are reserved.
+const RESERVED_NAMES: &[&str] = &[
+    "annotation-xml",
+    "color-profile",
+    "font-face",
+    "font-face-src",
+    "font-face-uri",
+    "font-face-format",
+    "font-face-name",
+    "missing-glyph",
+];
+
+/// Whether `name` is a valid custom element name: a lowercase ASCII
+/// letter, then name characters including at least one hyphen.
+pub fn is_valid_custom_element_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_lowercase()) {
+        return false;
+    }
+    name.contains('-')
+        && !RESERVED_NAMES.contains(&name)
+        && chars.all(|c| {
+            matches!(c, '-' | '.' | '_' | '0'..='9' | 'a'..='z' | '\u{B7}')
+                || matches!(c as u32, 0xC0..=0xD6 | 0xD8..=0xF6 | 0xF8..=0x37D | 0x37F..=0x1FFF
+                    | 0x200C..=0x200D | 0x203F..=0x2040 | 0x2070..=0x218F | 0x2C00..=0x2FEF
+                    | 0x3001..=0xD7FF | 0xF900..=0xFDCF | 0xFDF0..=0xFFFD | 0x10000..=0xEFFFF)
+        })
+}
+
+/// Errors raised by `customElements.define`.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CustomElementError {
+    #[error("'{0}' is not a valid custom element name")]
+    InvalidName(String),
+    #[error("'{0}' has already been defined as a custom element")]
+    AlreadyDefined(String),
+    #[error("'{0}' cannot be extended")]
+    InvalidExtends(String),
+}
+
+/// A defined custom element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomElementDefinition {
+    pub name: String,
+    /// Element name: the custom name, or the extended built-in element.
+    pub local_name: String,
+    /// Attributes that trigger `attributeChangedCallback`.
+    pub observed_attributes: Vec<String>,
+}
+
+impl CustomElementDefinition {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            local_name: name.to_string(),
+            observed_attributes: Vec::new(),
+        }
+    }
+
+    /// Customize the built-in element `local_name` (`{ extends }`).
+    pub fn extends(mut self, local_name: &str) -> Self {
+        self.local_name = local_name.to_ascii_lowercase();
+        self
+    }
+
+    pub fn observed_attributes(mut self, attributes: Vec<String>) -> Self {
+        self.observed_attributes = attributes;
+        self
+    }
+
+    /// Whether `element` is an instance of this definition.
+    pub fn matches(&self, element: &ElementData) -> bool {
+        element.namespace.is_none()
+            && element.tag_name == self.local_name.as_str()
+            && (self.local_name == self.name || element.get_attribute("is") == Some(&self.name))
+    }
+}
+
+/// The custom elements defined for a document (`CustomElementRegistry`).
+#[derive(Debug, Default)]
+pub struct CustomElementRegistry {
+    definitions: HashMap<String, CustomElementDefinition>,
+}
+
+impl CustomElementRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a definition.
+    pub fn define(
+        &mut self,
+        definition: CustomElementDefinition,
+    ) -> Result<(), CustomElementError> {
+        if !is_valid_custom_element_name(&definition.name) {
+            return Err(CustomElementError::InvalidName(definition.name));
+        }
+        if self.definitions.contains_key(&definition.name) {
+            return Err(CustomElementError::AlreadyDefined(definition.name));
+        }
+        if definition.local_name != definition.name
+            && is_valid_custom_element_name(&definition.local_name)
+        {
+            return Err(CustomElementError::InvalidExtends(definition.local_name));
+        }
+        self.definitions.insert(definition.name.clone(), definition);
+        Ok(())
+    }
+
+    /// Definition named `name`.
+    pub fn get(&self, name: &str) -> Option<&CustomElementDefinition> {
+        self.definitions.get(name)
+    }
+
+    /// Definition `element` is an instance of, if any.
+    pub fn lookup(&self, element: &ElementData) -> Option<&CustomElementDefinition> {
+        let name = element
+            .get_attribute("is")
+            .unwrap_or(element.tag_name.as_str());
+        self.definitions
+            .get(name)
+            .filter(|definition| definition.matches(element))
+    }
+
+    /// Connected elements waiting for `definition`, in shadow-including
+    /// tree order.
+    pub fn upgrade_candidates(
+        &self,
+        tree: &DomTree,
+        definition: &CustomElementDefinition,
+    ) -> Vec<NodeId> {
+        let root = match tree.root() {
+            Some(root) => root,
+            None => return Vec::new(),
+        };
+        shadow_including_inclusive_descendants(tree, root)
+            .into_iter()
+            .filter(|&node| {
+                tree.get_element(node).is_some_and(|element| {
+                    element.custom_state == CustomElementState::Undefined
+                        && definition.matches(element)
+                })
+            })
+            .collect()
+    }
+}
+
+/// A lifecycle callback or upgrade to run for an element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CustomElementReaction {
+    /// A connected element has a definition and is not upgraded yet.
+    Upgrade(NodeId),
+    Connected(NodeId),
+    Disconnected(NodeId),
+    /// The element moved between the document and template contents.
+    Adopted(NodeId),
+    AttributeChanged {
+        element: NodeId,
+        name: String,
+        old_value: Option<String>,
+        new_value: Option<String>,
+    },
+}
+
+/// Mutation hook that queues custom element reactions.
+#[derive(Debug)]
+pub struct CustomElementReactions {
+    registry: Arc<RwLock<CustomElementRegistry>>,
+    queue: Mutex<Vec<CustomElementReaction>>,
+    /// Removed nodes, and whether they were in template contents then.
+    removed: Mutex<HashMap<NodeId, bool>>,
+    /// Nodes dropped since the last [`take_dropped`](Self::take_dropped).
+    dropped: Mutex<Vec<NodeId>>,
+}
+
+impl CustomElementReactions {
+    pub fn new(registry: Arc<RwLock<CustomElementRegistry>>) -> Self {
+        Self {
+            registry,
+            queue: Mutex::new(Vec::new()),
+            removed: Mutex::new(HashMap::new()),
+            dropped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queue a reaction.
+    pub fn push(&self, reaction: CustomElementReaction) {
+        self.queue.lock().push(reaction);
+    }
+
+    /// Take the queued reactions, oldest first.
+    pub fn take(&self) -> Vec<CustomElementReaction> {
+        std::mem::take(&mut *self.queue.lock())
+    }
+
+    /// Take the nodes dropped from the tree since the last call, whose
+    /// element objects can be released.
+    pub fn take_dropped(&self) -> Vec<NodeId> {
+        std::mem::take(&mut *self.dropped.lock())
+    }
+
+    fn inserted(&self, tree: &DomTree, node: NodeId, queue: &mut Vec<CustomElementReaction>) {
+        let was_inert = self.removed.lock().remove(&node);
+        let adopted =
+            was_inert.is_some_and(|was_inert| was_inert != in_template_contents(tree, node));
+        let connected = is_connected(tree, node);
+        let registry = self.registry.read();
+
+        for id in shadow_including_inclusive_descendants(tree, node) {
+            let element = match tree.get_element(id) {
+                Some(element) => element,
+                None => continue,
+            };
+            match element.custom_state {
+                CustomElementState::Custom => {
+                    if adopted {
+                        queue.push(CustomElementReaction::Adopted(id));
+                    }
+                    if connected {
+                        queue.push(CustomElementReaction::Connected(id));
+                    }
+                }
+                CustomElementState::Undefined
+                    if connected && registry.lookup(element).is_some() =>
+                {
+                    queue.push(CustomElementReaction::Upgrade(id));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn removed(
+        &self,
+        tree: &DomTree,
+        parent: NodeId,
+        node: NodeId,
+        queue: &mut Vec<CustomElementReaction>,
+    ) {
+        self.removed
+            .lock()
+            .insert(node, in_template_contents(tree, parent));
+        if !is_connected(tree, parent) {
+            return;
+        }
+        for id in shadow_including_inclusive_descendants(tree, node) {
+            let custom = tree
+                .get_element(id)
+                .is_some_and(|element| element.custom_state == CustomElementState::Custom);
+            if custom {
+                queue.push(CustomElementReaction::Disconnected(id));
+            }
+        }
+    }
+}
+
+impl MutationHook for CustomElementReactions {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        let mut queue = Vec::new();
+        match mutation.kind {
+            MutationKind::ChildList => {
+                for &node in &mutation.removed_nodes {
+                    self.removed(tree, mutation.target, node, &mut queue);
+                }
+                for &node in &mutation.added_nodes {
+                    self.inserted(tree, node, &mut queue);
+                }
+            }
+            MutationKind::Attributes => {
+                let element = match tree.get_element(mutation.target) {
+                    Some(element) if element.custom_state == CustomElementState::Custom => element,
+                    _ => return,
+                };
+                let name = mutation.attribute_name.clone().unwrap_or_default();
+                let observed = self
+                    .registry
+                    .read()
+                    .lookup(element)
+                    .is_some_and(|definition| definition.observed_attributes.contains(&name));
+                if observed {
+                    queue.push(CustomElementReaction::AttributeChanged {
+                        element: mutation.target,
+                        new_value: element.get_attribute(&name).map(str::to_string),
+                        name,
+                        old_value: mutation.old_value.clone(),
+                    });
+                }
+            }
+            MutationKind::CharacterData => {}
+        }
+        self.queue.lock().extend(queue);
+    }
+
+    fn dropped(&self, _tree: &DomTree, nodes: &[NodeId]) {
+        let mut removed = self.removed.lock();
+        for node in nodes {
+            removed.remove(node);
+        }
+        self.dropped.lock().extend_from_slice(nodes);
+    }
+}
+
+/// Whether `node` is in the document, shadow trees included.
+fn is_connected(tree: &DomTree, node: NodeId) -> bool {
+    tree.root()
+        .is_some_and(|root| tree.is_shadow_including_inclusive_ancestor(root, node))
+}
+
+/// `node` and its descendants, shadow trees included, in tree order.
+fn shadow_including_inclusive_descendants(tree: &DomTree, node: NodeId) -> Vec<NodeId> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![node];
+    while let Some(node) = stack.pop() {
+        nodes.push(node);
+        let children: Vec<NodeId> = tree.children(node).collect();
+        stack.extend(children.into_iter().rev());
+        if let Some(shadow) = tree.shadow_root(node) {
+            stack.push(shadow.root);
+        }
+    }
+    nodes
+}
+
+/// Whether `node` is inside the contents of a `<template>`, which belong
+/// to a separate, inert document.
+fn in_template_contents(tree: &DomTree, node: NodeId) -> bool {
+    tree.template_host(tree.tree_root(node)).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::TagName;
+
+    #[test]
+    fn test_custom_element_names() {
+        assert!(is_valid_custom_element_name("my-element"));
+        assert!(is_valid_custom_element_name("x-\u{e9}l\u{e9}ment"));
+        assert!(!is_valid_custom_element_name("myelement"));
+        assert!(!is_valid_custom_element_name("My-element"));
+        assert!(!is_valid_custom_element_name("1-element"));
+        assert!(!is_valid_custom_element_name("font-face"));
+
+        let mut registry = CustomElementRegistry::new();
+        assert!(registry.define(CustomElementDefinition::new("x-a")).is_ok());
+        assert_eq!(
+            registry.define(CustomElementDefinition::new("x-a")),
+            Err(CustomElementError::AlreadyDefined("x-a".to_string()))
+        );
+        assert_eq!(
+            registry.define(CustomElementDefinition::new("x-b").extends("x-a")),
+            Err(CustomElementError::InvalidExtends("x-a".to_string()))
+        );
+        assert!(registry
+            .define(CustomElementDefinition::new("fancy-button").extends("button"))
+            .is_ok());
+
+        let mut button = ElementData::new(TagName::button());
+        assert!(registry.lookup(&button).is_none());
+        button.set_attribute("is", "fancy-button");
+        assert_eq!(
+            registry.lookup(&button).map(|d| d.name.as_str()),
+            Some("fancy-button")
+        );
+    }
+
+    #[test]
+    fn test_reactions() {
+        let registry = Arc::new(RwLock::new(CustomElementRegistry::new()));
+        registry
+            .write()
+            .define(
+                CustomElementDefinition::new("x-a").observed_attributes(vec!["open".to_string()]),
+            )
+            .unwrap();
+        let reactions = Arc::new(CustomElementReactions::new(registry.clone()));
+
+        let mut tree = DomTree::new();
+        tree.add_mutation_hook(reactions.clone());
+        let doc = tree.root().unwrap();
+        let element = tree.create_element(ElementData::new(TagName::new("x-a")));
+        let other = tree.create_element(ElementData::new(TagName::new("x-b")));
+        tree.append_child(element, other);
+        assert!(
+            reactions.take().is_empty(),
+            "disconnected elements are not upgraded"
+        );
+
+        tree.append_child(doc, element);
+        assert_eq!(
+            reactions.take(),
+            vec![CustomElementReaction::Upgrade(element)]
+        );
+        assert_eq!(
+            registry
+                .read()
+                .upgrade_candidates(&tree, registry.read().get("x-a").unwrap()),
+            vec![element]
+        );
+
+        tree.get_element_mut(element).unwrap().custom_state = CustomElementState::Custom;
+        tree.get_element_mut(element)
+            .unwrap()
+            .set_attribute("open", "");
+        tree.get_element_mut(element)
+            .unwrap()
+            .set_attribute("title", "ignored");
+        tree.flush_mutations();
+        assert_eq!(
+            reactions.take(),
+            vec![CustomElementReaction::AttributeChanged {
+                element,
+                name: "open".to_string(),
+                old_value: None,
+                new_value: Some(String::new()),
+            }]
+        );
+
+        tree.remove_from_parent(element);
+        tree.append_child(doc, element);
+        assert_eq!(
+            reactions.take(),
+            vec![
+                CustomElementReaction::Disconnected(element),
+                CustomElementReaction::Connected(element)
+            ]
+        );
+
+        // Template contents belong to another document.
+        let template = tree.create_element(ElementData::new(TagName::new("template")));
+        let contents = tree.template_contents(template).unwrap();
+        assert_eq!(tree.template_host(contents), Some(template));
+        tree.append_child(doc, template);
+        reactions.take();
+        tree.append_child(contents, element);
+        assert_eq!(
+            reactions.take(),
+            vec![
+                CustomElementReaction::Disconnected(element),
+                CustomElementReaction::Adopted(element)
+            ]
+        );
+    }
+}
@@ -1,14 +1,20 @@
 //! DOM Document implementation.
 
 use crate::node::{NodeId, NodeData, DocumentType};
+use crate::editing::{self, EditTarget, Editor, InputDetail};
 use crate::element::{ElementData, TagName};
+use crate::events::{Event, EventManager, EventType};
+use crate::focus::{FocusChange, FocusController, FocusDirection, FocusOrigin};
+use crate::forms::{self, FormMethod, FormSubmission};
+use crate::selection::Selection;
 use crate::tree::DomTree;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use url::Url;
 
 /// Document ready state.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ReadyState {
     Loading,
     Interactive,
@@ -26,7 +32,7 @@ impl ReadyState {
 }
 
 /// Document content type.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ContentType {
     Html,
     Xml,
@@ -34,7 +40,7 @@ pub enum ContentType {
 }
 
 /// Quirks mode for rendering.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum QuirksMode {
     #[default]
     NoQuirks,
@@ -66,8 +72,17 @@ pub struct Document {
     pub head: Option<NodeId>,
     /// Body element.
     pub body: Option<NodeId>,
-    /// Active element (focused).
+    /// Active element (focused), kept in step with `focus`.
     pub active_element: Option<NodeId>,
+    /// Focus controller.
+    pub focus: FocusController,
+    /// The selection, kept live by a mutation hook on `tree`.
+    pub selection: Arc<Mutex<Selection>>,
+    /// Applies user edits and keeps their undo history.
+    pub editor: Editor,
+    /// Event listeners of the document's nodes. Shared so that listeners
+    /// can be invoked without holding the document.
+    pub events: Arc<Mutex<EventManager>>,
     /// Stylesheets.
     pub stylesheets: Vec<StylesheetRef>,
     /// Scripts.
@@ -82,14 +97,22 @@ pub struct Document {
     pub cookie: String,
     /// Domain.
     pub domain: String,
+    /// Navigation planned by a form submission.
+    pub planned_navigation: Option<FormSubmission>,
 }
 
 impl Document {
     pub fn new(url: Url) -> Self {
+        Self::with_tree(url, DomTree::new())
+    }
+
+    /// Create a document around an existing tree.
+    pub fn with_tree(url: Url, mut tree: DomTree) -> Self {
         let base_url = url.clone();
         let domain = url.host_str().unwrap_or("").to_string();
 
-        let mut tree = DomTree::new();
+        let selection = Arc::new(Mutex::new(Selection::new()));
+        tree.add_mutation_hook(selection.clone());
 
         Self {
             tree,
@@ -104,6 +127,10 @@ impl Document {
             head: None,
             body: None,
             active_element: None,
+            focus: FocusController::new(),
+            selection,
+            editor: Editor::new(),
+            events: Arc::new(Mutex::new(EventManager::new())),
             stylesheets: Vec::new(),
             scripts: Vec::new(),
             loading: true,
@@ -111,6 +138,7 @@ impl Document {
             last_modified: None,
             cookie: String::new(),
             domain,
+            planned_navigation: None,
         }
     }
 
@@ -171,16 +199,6 @@ impl Document {
         self.tree.find_elements_by_class_name(class_name)
     }
 
-    /// Query selector.
-    pub fn query_selector(&self, selector: &str) -> Option<NodeId> {
-        self.tree.query_selector(selector)
-    }
-
-    /// Query selector all.
-    pub fn query_selector_all(&self, selector: &str) -> Vec<NodeId> {
-        self.tree.query_selector_all(selector)
-    }
-
     /// Set document title.
     pub fn set_title(&mut self, title: &str) {
         self.title = title.to_string();
@@ -194,6 +212,140 @@ impl Document {
         }
     }
 
+    /// Remove the selected content from the tree (`deleteFromDocument`).
+    pub fn delete_selection(&mut self) {
+        // The selection is not locked while the tree changes, as its hook
+        // locks it to follow the mutations.
+        let range = self.selection.lock().range().cloned();
+        if let Some(mut range) = range {
+            range.delete_contents(&mut self.tree);
+            if let Some(selected) = self.selection.lock().range_mut() {
+                *selected = range;
+            }
+        }
+    }
+
+    /// What the user's edits apply to: the focused text control, or the
+    /// editing host of the selection.
+    pub fn edit_target(&self) -> Option<EditTarget> {
+        let selection = self.selection.lock();
+        editing::edit_target(&self.tree, self.focus.focused(), selection.range())
+    }
+
+    /// Apply an edit to the edit target without firing events, as
+    /// `execCommand` does before its own `input` event.
+    pub fn apply_input(&mut self, input: &InputDetail) -> bool {
+        let Some(target) = self.edit_target() else {
+            return false;
+        };
+        self.editor.apply(&mut self.tree, &self.selection, target, input)
+    }
+
+    /// Apply an edit as the user makes it: fire `beforeinput`, apply the
+    /// edit unless that was canceled, then fire `input`. Returns whether
+    /// anything changed.
+    pub fn edit(&mut self, input: InputDetail) -> bool {
+        let Some(target) = self.edit_target() else {
+            return false;
+        };
+        let mut event = Event::with_options(EventType::BeforeInput, true, input.input_type.cancelable());
+        event.composed = true;
+        event.input = Some(input.clone());
+        if !self.dispatch_event(target.node(), &mut event) {
+            return false;
+        }
+        if !self.editor.apply(&mut self.tree, &self.selection, target, &input) {
+            return false;
+        }
+        let mut event = Event::with_options(EventType::Input, true, false);
+        event.composed = true;
+        event.input = Some(input);
+        self.dispatch_event(target.node(), &mut event);
+        true
+    }
+
+    /// Focus `node`. The returned change's events are for the caller to
+    /// dispatch.
+    pub fn focus(&mut self, node: NodeId, origin: FocusOrigin) -> Option<FocusChange> {
+        let change = self.focus.focus(&mut self.tree, node, origin);
+        self.active_element = self.focus.focused();
+        change
+    }
+
+    /// Unfocus `node` if it has focus.
+    pub fn blur(&mut self, node: NodeId) -> Option<FocusChange> {
+        let change = self.focus.blur(&mut self.tree, node);
+        self.active_element = self.focus.focused();
+        change
+    }
+
+    /// Move focus to the next or previous element in Tab order.
+    pub fn navigate_focus(&mut self, direction: FocusDirection) -> Option<FocusChange> {
+        let change = self.focus.navigate(&mut self.tree, direction);
+        self.active_element = self.focus.focused();
+        change
+    }
+
+    /// The focused element, or the body when nothing is focused
+    /// (`document.activeElement`).
+    pub fn active_element(&mut self) -> Option<NodeId> {
+        self.focus.fixup(&mut self.tree);
+        self.active_element = self.focus.focused();
+        self.active_element.or(self.body)
+    }
+
+    /// Dispatch `event` at `target` to the Rust listeners of the document.
+    /// Returns `false` if the default action was prevented.
+    pub fn dispatch_event(&self, target: NodeId, event: &mut Event) -> bool {
+        self.events.lock().dispatch(&self.tree, target, event)
+    }
+
+    /// Submit `form` as the user or `requestSubmit()` does: run interactive
+    /// validation unless disabled, then fire `submit`. Returns whether a
+    /// navigation was planned.
+    pub fn request_submit(&mut self, form: NodeId, submitter: Option<NodeId>) -> bool {
+        if !forms::novalidate(&self.tree, form, submitter) {
+            let invalid = forms::invalid_controls(&self.tree, form);
+            if !invalid.is_empty() {
+                for control in invalid {
+                    let mut event = Event::with_options(EventType::Invalid, false, true);
+                    self.dispatch_event(control, &mut event);
+                }
+                return false;
+            }
+        }
+        let mut event = Event::with_options(EventType::Submit, true, true);
+        self.dispatch_event(form, &mut event) && self.submit_form(form, submitter)
+    }
+
+    /// Plan the navigation of submitting `form`, without validation or
+    /// events (`form.submit()`).
+    pub fn submit_form(&mut self, form: NodeId, submitter: Option<NodeId>) -> bool {
+        match forms::form_submission(&self.tree, form, submitter, &self.url, &self.base_url) {
+            Ok(submission) if submission.method != FormMethod::Dialog => {
+                self.planned_navigation = Some(submission);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Reset the controls of `form` unless its `reset` event is canceled.
+    pub fn reset_form(&mut self, form: NodeId) -> bool {
+        let mut event = Event::with_options(EventType::Reset, true, true);
+        if !self.dispatch_event(form, &mut event) {
+            return false;
+        }
+        forms::reset(&mut self.tree, form);
+        true
+    }
+
+    /// Take the navigation planned by a form submission, for the embedder
+    /// to perform.
+    pub fn take_planned_navigation(&mut self) -> Option<FormSubmission> {
+        self.planned_navigation.take()
+    }
+
     /// Resolve a URL relative to the document.
     pub fn resolve_url(&self, url: &str) -> Result<Url, url::ParseError> {
         self.base_url.join(url)
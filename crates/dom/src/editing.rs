@@ -0,0 +1,1923 @@
+//! Editing of `contenteditable` content and text controls.
+//!
+//! An edit is described by an [`InputDetail`], the same data `beforeinput`
+//! and `input` events carry, and applied by the document's [`Editor`].
+//! Content edits are recorded as the mutations they made, so undo replays
+//! their inverse; text control edits record the value instead.
+
+use crate::element::{ElementData, TagName};
+use crate::focus;
+use crate::forms;
+use crate::mutation::{Mutation, MutationKind};
+use crate::node::{NodeData, NodeId};
+use crate::range::{BoundaryPoint, Range};
+use crate::selection::Selection;
+use crate::tree::DomTree;
+use parking_lot::Mutex;
+use std::iter;
+use std::sync::Arc;
+
+/// Undo steps kept per document.
+const UNDO_LIMIT: usize = 100;
+
+/// Elements that start a new line, split by Enter and joined by deletion.
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Inline elements removed by `removeFormat`.
+const FORMATTING: &[&str] = &[
+    "b", "big", "del", "em", "font", "i", "mark", "s", "small", "strike", "strong", "sub", "sup",
+    "u",
+];
+
+/// Elements that are content of their own although they have no children.
+const VOID_CONTENT: &[&str] = &["br", "hr", "img", "input", "video", "iframe", "canvas"];
+
+/// The kind of an edit (`InputEvent.inputType`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InputType {
+    InsertText,
+    InsertLineBreak,
+    InsertParagraph,
+    InsertFromPaste,
+    /// Replace the text being composed with an input method.
+    InsertCompositionText,
+    InsertLink,
+    DeleteContentBackward,
+    DeleteContentForward,
+    DeleteByCut,
+    HistoryUndo,
+    HistoryRedo,
+    FormatBold,
+    FormatItalic,
+    FormatUnderline,
+    FormatStrikeThrough,
+    FormatSuperscript,
+    FormatSubscript,
+    FormatRemove,
+}
+
+impl InputType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InputType::InsertText => "insertText",
+            InputType::InsertLineBreak => "insertLineBreak",
+            InputType::InsertParagraph => "insertParagraph",
+            InputType::InsertFromPaste => "insertFromPaste",
+            InputType::InsertCompositionText => "insertCompositionText",
+            InputType::InsertLink => "insertLink",
+            InputType::DeleteContentBackward => "deleteContentBackward",
+            InputType::DeleteContentForward => "deleteContentForward",
+            InputType::DeleteByCut => "deleteByCut",
+            InputType::HistoryUndo => "historyUndo",
+            InputType::HistoryRedo => "historyRedo",
+            InputType::FormatBold => "formatBold",
+            InputType::FormatItalic => "formatItalic",
+            InputType::FormatUnderline => "formatUnderline",
+            InputType::FormatStrikeThrough => "formatStrikeThrough",
+            InputType::FormatSuperscript => "formatSuperscript",
+            InputType::FormatSubscript => "formatSubscript",
+            InputType::FormatRemove => "formatRemove",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            InputType::InsertText,
+            InputType::InsertLineBreak,
+            InputType::InsertParagraph,
+            InputType::InsertFromPaste,
+            InputType::InsertCompositionText,
+            InputType::InsertLink,
+            InputType::DeleteContentBackward,
+            InputType::DeleteContentForward,
+            InputType::DeleteByCut,
+            InputType::HistoryUndo,
+            InputType::HistoryRedo,
+            InputType::FormatBold,
+            InputType::FormatItalic,
+            InputType::FormatUnderline,
+            InputType::FormatStrikeThrough,
+            InputType::FormatSuperscript,
+            InputType::FormatSubscript,
+            InputType::FormatRemove,
+        ]
+        .into_iter()
+        .find(|input_type| input_type.as_str() == value)
+    }
+
+    /// Whether `beforeinput` for this edit can be canceled. Composition
+    /// updates cannot, as the input method has already shown them.
+    pub fn cancelable(&self) -> bool {
+        *self != InputType::InsertCompositionText
+    }
+
+    /// The tags that have this formatting, and the one to apply it with.
+    fn format_tags(&self) -> Option<(&'static [&'static str], &'static str)> {
+        Some(match self {
+            InputType::FormatBold => (&["b", "strong"], "b"),
+            InputType::FormatItalic => (&["i", "em"], "i"),
+            InputType::FormatUnderline => (&["u"], "u"),
+            InputType::FormatStrikeThrough => (&["s", "strike", "del"], "s"),
+            InputType::FormatSuperscript => (&["sup"], "sup"),
+            InputType::FormatSubscript => (&["sub"], "sub"),
+            _ => return None,
+        })
+    }
+
+    /// Whether consecutive edits of this kind undo as one step.
+    fn coalesces(&self) -> bool {
+        matches!(
+            self,
+            InputType::InsertText
+                | InputType::InsertCompositionText
+                | InputType::DeleteContentBackward
+                | InputType::DeleteContentForward
+        )
+    }
+}
+
+/// An edit, as carried by `beforeinput` and `input` events.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputDetail {
+    pub input_type: InputType,
+    /// Text to insert, or the URL of a link.
+    pub data: Option<String>,
+    /// Nodes to insert for a rich paste, as a detached document fragment.
+    pub fragment: Option<NodeId>,
+    pub is_composing: bool,
+}
+
+impl InputDetail {
+    pub fn new(input_type: InputType) -> Self {
+        Self {
+            input_type,
+            data: None,
+            fragment: None,
+            is_composing: false,
+        }
+    }
+
+    pub fn with_data(input_type: InputType, data: impl Into<String>) -> Self {
+        Self {
+            data: Some(data.into()),
+            ..Self::new(input_type)
+        }
+    }
+}
+
+/// What an edit applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditTarget {
+    /// The content of an editing host.
+    Content(NodeId),
+    /// The value of a text `input` or `textarea`.
+    Control(NodeId),
+}
+
+impl EditTarget {
+    /// The node `beforeinput` and `input` are fired at.
+    pub fn node(&self) -> NodeId {
+        match *self {
+            EditTarget::Content(node) | EditTarget::Control(node) => node,
+        }
+    }
+}
+
+/// The `contenteditable` state of an element: `None` when it inherits.
+fn content_editable(element: &ElementData) -> Option<bool> {
+    match element
+        .get_attribute("contenteditable")?
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "" | "true" | "plaintext-only" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// The editing host `node` is editable in: its outermost editable
+/// inclusive ancestor not cut off by `contenteditable="false"`.
+pub fn editing_host(tree: &DomTree, node: NodeId) -> Option<NodeId> {
+    let mut host = None;
+    for id in iter::once(node).chain(tree.ancestors(node)) {
+        match tree.get_element(id).and_then(content_editable) {
+            Some(true) => host = Some(id),
+            Some(false) => break,
+            None => {}
+        }
+    }
+    host
+}
+
+/// Whether `node` is the root of editable content.
+pub fn is_editing_host(tree: &DomTree, node: NodeId) -> bool {
+    editing_host(tree, node) == Some(node)
+}
+
+/// Whether the user can edit the value of `node`: a text control that is
+/// neither disabled nor read-only.
+pub fn is_editable_control(tree: &DomTree, node: NodeId) -> bool {
+    focus::is_text_control(tree, node)
+        && !forms::is_disabled(tree, node)
+        && !tree
+            .get_element(node)
+            .is_some_and(|e| e.has_attribute("readonly"))
+}
+
+/// What typing edits: the focused text control, else the editing host of
+/// the selection or of the focused element.
+pub fn edit_target(
+    tree: &DomTree,
+    focused: Option<NodeId>,
+    range: Option<&Range>,
+) -> Option<EditTarget> {
+    if let Some(control) = focused.filter(|&node| is_editable_control(tree, node)) {
+        return Some(EditTarget::Control(control));
+    }
+    range
+        .and_then(|range| editing_host(tree, range.start().node))
+        .or_else(|| focused.and_then(|node| editing_host(tree, node)))
+        .map(EditTarget::Content)
+}
+
+/// Whether the selected content of `host` all has the formatting of
+/// `input_type` (`queryCommandState`).
+pub fn format_state(tree: &DomTree, host: NodeId, range: &Range, input_type: InputType) -> bool {
+    let Some((tags, _)) = input_type.format_tags() else {
+        return false;
+    };
+    if range.collapsed() {
+        return formatted(tree, host, range.start().node, tags).is_some();
+    }
+    let texts = selected_texts(tree, host, range);
+    !texts.is_empty()
+        && texts
+            .iter()
+            .all(|&text| formatted(tree, host, text, tags).is_some())
+}
+
+/// An edit that can be undone.
+#[derive(Clone, Debug)]
+struct Transaction {
+    target: EditTarget,
+    input_type: InputType,
+    change: Change,
+}
+
+#[derive(Clone, Debug)]
+enum Change {
+    /// Mutations of an editing host, with the selection before and after.
+    Tree {
+        mutations: Vec<Mutation>,
+        before: Option<Range>,
+        after: Option<Range>,
+    },
+    /// A text control's value before and after.
+    Value {
+        before: ValueState,
+        after: ValueState,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ValueState {
+    value: String,
+    selection: (usize, usize),
+}
+
+impl ValueState {
+    fn of(tree: &DomTree, control: NodeId) -> Self {
+        Self {
+            value: forms::value(tree, control),
+            selection: forms::selection_range(tree, control),
+        }
+    }
+
+    fn write(&self, tree: &mut DomTree, control: NodeId) {
+        if let Some(element) = tree.get_element_mut(control) {
+            element.control.value = Some(self.value.clone());
+            element.control.selection = self.selection;
+        }
+    }
+}
+
+/// Text being composed with an input method: `len` bytes at `start` of a
+/// text node, or of a control's value.
+#[derive(Clone, Copy, Debug)]
+struct Composition {
+    target: EditTarget,
+    node: NodeId,
+    start: usize,
+    len: usize,
+}
+
+/// Applies edits and keeps their undo history.
+#[derive(Debug, Default)]
+pub struct Editor {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    composition: Option<Composition>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Whether an input method composition is in progress.
+    pub fn is_composing(&self) -> bool {
+        self.composition.is_some()
+    }
+
+    /// Commit the composition; later text is inserted after it.
+    pub fn end_composition(&mut self) {
+        self.composition = None;
+    }
+
+    /// Apply `input` to `target`, moving the selection along. Returns
+    /// false if there was nothing to do.
+    ///
+    /// The selection must not be locked, as it follows the mutations.
+    pub fn apply(
+        &mut self,
+        tree: &mut DomTree,
+        selection: &Mutex<Selection>,
+        target: EditTarget,
+        input: &InputDetail,
+    ) -> bool {
+        match input.input_type {
+            InputType::HistoryUndo => return self.undo(tree, selection),
+            InputType::HistoryRedo => return self.redo(tree, selection),
+            InputType::InsertCompositionText => {}
+            _ => self.composition = None,
+        }
+        let change = match target {
+            EditTarget::Content(host) => {
+                let current = selection.lock().range().cloned();
+                let range = caret_range(tree, host, current);
+                let (after, mutations) = record(tree, |tree| {
+                    self.edit_content(tree, host, range.clone(), input)
+                });
+                if let Some(after) = &after {
+                    selection.lock().set_range(after.clone());
+                }
+                if mutations.is_empty() {
+                    return false;
+                }
+                Change::Tree {
+                    mutations,
+                    before: Some(range),
+                    after,
+                }
+            }
+            EditTarget::Control(control) => {
+                let before = ValueState::of(tree, control);
+                let Some(after) = self.edit_value(tree, control, &before, input) else {
+                    return false;
+                };
+                after.write(tree, control);
+                Change::Value { before, after }
+            }
+        };
+        self.push(Transaction {
+            target,
+            input_type: input.input_type,
+            change,
+        });
+        true
+    }
+
+    /// Revert the last edit.
+    pub fn undo(&mut self, tree: &mut DomTree, selection: &Mutex<Selection>) -> bool {
+        let Some(transaction) = self.undo.pop() else {
+            return false;
+        };
+        let inverse = self.revert(tree, selection, transaction);
+        self.redo.push(inverse);
+        true
+    }
+
+    /// Apply the last undone edit again.
+    pub fn redo(&mut self, tree: &mut DomTree, selection: &Mutex<Selection>) -> bool {
+        let Some(transaction) = self.redo.pop() else {
+            return false;
+        };
+        let inverse = self.revert(tree, selection, transaction);
+        self.undo.push(inverse);
+        true
+    }
+
+    /// Forget the history, as when the content is replaced wholesale.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.composition = None;
+    }
+
+    /// Record a new edit, merging it into the previous one while typing
+    /// continues where that left off.
+    fn push(&mut self, transaction: Transaction) {
+        self.redo.clear();
+        if let Some(last) = self.undo.last_mut() {
+            if last.target == transaction.target
+                && last.input_type == transaction.input_type
+                && transaction.input_type.coalesces()
+            {
+                match (&mut last.change, transaction.change) {
+                    (
+                        Change::Tree {
+                            mutations, after, ..
+                        },
+                        Change::Tree {
+                            mutations: more,
+                            before: next_before,
+                            after: next_after,
+                        },
+                    ) if *after == next_before => {
+                        mutations.extend(more);
+                        *after = next_after;
+                        return;
+                    }
+                    (
+                        Change::Value { after, .. },
+                        Change::Value {
+                            before,
+                            after: next_after,
+                        },
+                    ) if *after == before => {
+                        *after = next_after;
+                        return;
+                    }
+                    (_, change) => {
+                        self.undo.push(Transaction {
+                            change,
+                            ..transaction
+                        });
+                        self.trim();
+                        return;
+                    }
+                }
+            }
+        }
+        self.undo.push(transaction);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        if self.undo.len() > UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Undo `transaction`, returning the edit that redoes it.
+    fn revert(
+        &mut self,
+        tree: &mut DomTree,
+        selection: &Mutex<Selection>,
+        transaction: Transaction,
+    ) -> Transaction {
+        self.composition = None;
+        let change = match transaction.change {
+            Change::Tree {
+                mutations,
+                before,
+                after,
+            } => {
+                let ((), inverse) = record(tree, |tree| revert_mutations(tree, &mutations));
+                if let Some(before) = &before {
+                    selection.lock().set_range(before.clone());
+                }
+                Change::Tree {
+                    mutations: inverse,
+                    before: after,
+                    after: before,
+                }
+            }
+            Change::Value { before, after } => {
+                before.write(tree, transaction.target.node());
+                Change::Value {
+                    before: after,
+                    after: before,
+                }
+            }
+        };
+        Transaction {
+            change,
+            ..transaction
+        }
+    }
+
+    /// Edit a text control's value, returning the new value and selection.
+    fn edit_value(
+        &mut self,
+        tree: &DomTree,
+        control: NodeId,
+        state: &ValueState,
+        input: &InputDetail,
+    ) -> Option<ValueState> {
+        let single_line = tree
+            .get_element(control)
+            .is_some_and(|e| e.tag_name.as_str() == "input");
+        let (start, end) = state.selection;
+        let value = &state.value;
+        let (span, text) = match input.input_type {
+            InputType::InsertText
+            | InputType::InsertFromPaste
+            | InputType::InsertCompositionText => {
+                let text = input.data.clone().unwrap_or_default();
+                let text = if single_line {
+                    text.replace(['\r', '\n'], "")
+                } else {
+                    text.replace("\r\n", "\n").replace('\r', "\n")
+                };
+                let span = match self.composition {
+                    Some(composition)
+                        if input.input_type == InputType::InsertCompositionText
+                            && composition.target == EditTarget::Control(control) =>
+                    {
+                        (composition.start, composition.start + composition.len)
+                    }
+                    _ => (start, end),
+                };
+                (span, text)
+            }
+            InputType::InsertLineBreak | InputType::InsertParagraph if !single_line => {
+                ((start, end), "\n".to_string())
+            }
+            InputType::DeleteContentBackward if start == end => {
+                let previous = value[..start].chars().next_back()?;
+                ((start - previous.len_utf8(), start), String::new())
+            }
+            InputType::DeleteContentForward if start == end => {
+                let next = value[start..].chars().next()?;
+                ((start, start + next.len_utf8()), String::new())
+            }
+            InputType::DeleteContentBackward
+            | InputType::DeleteContentForward
+            | InputType::DeleteByCut => ((start, end), String::new()),
+            _ => return None,
+        };
+        let (from, to) = (span.0.min(value.len()), span.1.min(value.len()));
+        if from == to && text.is_empty() && input.input_type != InputType::InsertCompositionText {
+            return None;
+        }
+        if input.input_type == InputType::InsertCompositionText {
+            self.composition = Some(Composition {
+                target: EditTarget::Control(control),
+                node: control,
+                start: from,
+                len: text.len(),
+            });
+        }
+        let mut value = value.clone();
+        value.replace_range(from..to, &text);
+        let caret = from + text.len();
+        Some(ValueState {
+            value,
+            selection: (caret, caret),
+        })
+    }
+
+    /// Edit the content of `host` at `range`, returning the selection
+    /// afterwards.
+    fn edit_content(
+        &mut self,
+        tree: &mut DomTree,
+        host: NodeId,
+        range: Range,
+        input: &InputDetail,
+    ) -> Option<Range> {
+        let data = input.data.as_deref().unwrap_or("");
+        let point = match input.input_type {
+            InputType::InsertText => {
+                let point = delete_range(tree, host, &range);
+                if data.is_empty() {
+                    return Some(caret(tree, point));
+                }
+                let (node, offset) = insert_text(tree, point, data);
+                BoundaryPoint::new(node, offset + data.len())
+            }
+            InputType::InsertFromPaste => {
+                let point = delete_range(tree, host, &range);
+                match input.fragment {
+                    Some(fragment) => insert_fragment(tree, point, fragment).unwrap_or(point),
+                    None if data.is_empty() => point,
+                    None => {
+                        let (node, offset) = insert_text(tree, point, data);
+                        BoundaryPoint::new(node, offset + data.len())
+                    }
+                }
+            }
+            InputType::InsertCompositionText => {
+                let range = match self.composition {
+                    Some(composition)
+                        if composition.target == EditTarget::Content(host)
+                            && tree.is_inclusive_ancestor(host, composition.node) =>
+                    {
+                        let mut range = Range::new(composition.node);
+                        range
+                            .set_end(tree, composition.node, composition.start + composition.len)
+                            .ok()?;
+                        range
+                            .set_start(tree, composition.node, composition.start)
+                            .ok()?;
+                        range
+                    }
+                    _ => range,
+                };
+                let point = delete_range(tree, host, &range);
+                let (node, offset) = insert_text(tree, point, data);
+                self.composition = Some(Composition {
+                    target: EditTarget::Content(host),
+                    node,
+                    start: offset,
+                    len: data.len(),
+                });
+                BoundaryPoint::new(node, offset + data.len())
+            }
+            InputType::InsertLineBreak => {
+                let point = delete_range(tree, host, &range);
+                insert_line_break(tree, host, point)?
+            }
+            InputType::InsertParagraph => {
+                let point = delete_range(tree, host, &range);
+                insert_paragraph(tree, host, point)?
+            }
+            InputType::DeleteContentBackward if range.collapsed() => {
+                delete_backward(tree, host, range.start())?
+            }
+            InputType::DeleteContentForward if range.collapsed() => {
+                delete_forward(tree, host, range.start())?
+            }
+            InputType::DeleteContentBackward
+            | InputType::DeleteContentForward
+            | InputType::DeleteByCut => {
+                if range.collapsed() {
+                    return None;
+                }
+                let point = delete_range(tree, host, &range);
+                if let Some(block) = block_of(tree, host, point.node) {
+                    ensure_placeholder(tree, block);
+                }
+                point
+            }
+            InputType::InsertLink => {
+                if data.is_empty() {
+                    return None;
+                }
+                let range = split_selection(tree, &range)?;
+                let texts = selected_texts(tree, host, &range);
+                for &text in &texts {
+                    match formatted(tree, host, text, &["a"]) {
+                        Some(link) => tree.set_attribute(link, "href", data),
+                        None => wrap(tree, text, "a", Some(("href", data))),
+                    }
+                }
+                return text_span(tree, &texts);
+            }
+            InputType::FormatRemove => {
+                let range = split_selection(tree, &range)?;
+                let texts = selected_texts(tree, host, &range);
+                for &text in &texts {
+                    while let Some(element) = formatted(tree, host, text, FORMATTING) {
+                        isolate_and_unwrap(tree, element, text);
+                    }
+                }
+                return text_span(tree, &texts);
+            }
+            InputType::HistoryUndo | InputType::HistoryRedo => return None,
+            input_type => {
+                let (tags, tag) = input_type.format_tags()?;
+                let range = split_selection(tree, &range)?;
+                let texts = selected_texts(tree, host, &range);
+                let on = texts
+                    .iter()
+                    .all(|&text| formatted(tree, host, text, tags).is_some());
+                for &text in &texts {
+                    if on {
+                        while let Some(element) = formatted(tree, host, text, tags) {
+                            isolate_and_unwrap(tree, element, text);
+                        }
+                    } else if formatted(tree, host, text, tags).is_none() {
+                        wrap(tree, text, tag, None);
+                    }
+                }
+                return text_span(tree, &texts);
+            }
+        };
+        Some(caret(tree, point))
+    }
+}
+
+/// Run `edit`, collecting the mutations it makes.
+fn record<R>(tree: &mut DomTree, edit: impl FnOnce(&mut DomTree) -> R) -> (R, Vec<Mutation>) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let sink = log.clone();
+    let hook = tree.add_mutation_hook(Arc::new(move |_: &DomTree, mutation: &Mutation| {
+        sink.lock().push(mutation.clone());
+    }));
+    let result = edit(tree);
+    tree.remove_mutation_hook(hook);
+    let mutations = std::mem::take(&mut *log.lock());
+    (result, mutations)
+}
+
+/// Undo `mutations`, last first.
+fn revert_mutations(tree: &mut DomTree, mutations: &[Mutation]) {
+    for mutation in mutations.iter().rev() {
+        let target = mutation.target;
+        match mutation.kind {
+            MutationKind::ChildList => {
+                for &node in &mutation.added_nodes {
+                    if tree.parent(node) == Some(target) {
+                        tree.remove_from_parent(node);
+                    }
+                }
+                let reference = mutation
+                    .next_sibling
+                    .filter(|&next| tree.parent(next) == Some(target));
+                for &node in &mutation.removed_nodes {
+                    if tree.get(node).is_some() {
+                        tree.insert_before(target, node, reference);
+                    }
+                }
+            }
+            MutationKind::Attributes => {
+                let Some(name) = &mutation.attribute_name else {
+                    continue;
+                };
+                match &mutation.old_value {
+                    Some(value) => tree.set_attribute(target, name, value),
+                    None => {
+                        tree.remove_attribute(target, name);
+                    }
+                }
+            }
+            MutationKind::CharacterData => {
+                tree.set_text_content(target, mutation.old_value.as_deref().unwrap_or(""));
+            }
+        }
+    }
+    tree.flush_mutations();
+}
+
+/// The selection if it lies in `host`, else a caret at its end.
+fn caret_range(tree: &DomTree, host: NodeId, range: Option<Range>) -> Range {
+    range
+        .filter(|range| {
+            tree.is_inclusive_ancestor(host, range.start().node)
+                && tree.is_inclusive_ancestor(host, range.end().node)
+        })
+        .unwrap_or_else(|| caret(tree, BoundaryPoint::new(host, tree.children(host).count())))
+}
+
+/// A collapsed range at `point`.
+fn caret(tree: &DomTree, point: BoundaryPoint) -> Range {
+    let mut range = Range::new(point.node);
+    let _ = range.set_start(tree, point.node, point.offset);
+    range
+}
+
+fn is_text(tree: &DomTree, node: NodeId) -> bool {
+    matches!(tree.get(node).map(|n| &n.data), Some(NodeData::Text { .. }))
+}
+
+fn text_len(tree: &DomTree, node: NodeId) -> usize {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => content.len(),
+        _ => 0,
+    }
+}
+
+fn has_tag(tree: &DomTree, node: NodeId, tags: &[&str]) -> bool {
+    tree.get_element(node)
+        .is_some_and(|e| tags.contains(&e.tag_name.as_str()))
+}
+
+fn is_block(tree: &DomTree, node: NodeId) -> bool {
+    has_tag(tree, node, BLOCKS)
+}
+
+/// Whether `node` shows nothing: no text and no content elements.
+fn is_visually_empty(tree: &DomTree, node: NodeId) -> bool {
+    !iter::once(node)
+        .chain(tree.descendants(node))
+        .any(|id| text_len(tree, id) > 0 || has_tag(tree, id, VOID_CONTENT))
+}
+
+/// The nearest block containing `node`, below `host`.
+fn block_of(tree: &DomTree, host: NodeId, node: NodeId) -> Option<NodeId> {
+    iter::once(node)
+        .chain(tree.ancestors(node))
+        .take_while(|&id| id != host)
+        .find(|&id| is_block(tree, id))
+}
+
+/// The nearest inclusive ancestor of `node` below `host` with one of `tags`.
+fn formatted(tree: &DomTree, host: NodeId, node: NodeId, tags: &[&str]) -> Option<NodeId> {
+    iter::once(node)
+        .chain(tree.ancestors(node))
+        .take_while(|&id| id != host)
+        .find(|&id| has_tag(tree, id, tags))
+}
+
+fn index_of(tree: &DomTree, node: NodeId) -> usize {
+    tree.parent(node)
+        .and_then(|parent| tree.children(parent).position(|child| child == node))
+        .unwrap_or(0)
+}
+
+fn point_before(tree: &DomTree, node: NodeId) -> Option<BoundaryPoint> {
+    Some(BoundaryPoint::new(tree.parent(node)?, index_of(tree, node)))
+}
+
+fn point_after(tree: &DomTree, node: NodeId) -> Option<BoundaryPoint> {
+    Some(BoundaryPoint::new(
+        tree.parent(node)?,
+        index_of(tree, node) + 1,
+    ))
+}
+
+fn create_element(tree: &mut DomTree, tag: &str) -> NodeId {
+    tree.create_element(ElementData::new(TagName::new(tag)))
+}
+
+/// Where a node inserted at `point` goes, splitting a text node if the
+/// point is inside one.
+fn insertion_point(tree: &mut DomTree, point: BoundaryPoint) -> Option<(NodeId, Option<NodeId>)> {
+    if !is_text(tree, point.node) {
+        return Some((point.node, tree.children(point.node).nth(point.offset)));
+    }
+    let parent = tree.parent(point.node)?;
+    let reference = if point.offset == 0 {
+        Some(point.node)
+    } else if point.offset >= text_len(tree, point.node) {
+        tree.next_sibling(point.node)
+    } else {
+        tree.split_text(point.node, point.offset)
+    };
+    Some((parent, reference))
+}
+
+/// The point a range collapses to once its contents are removed.
+fn collapse_point(tree: &DomTree, start: BoundaryPoint, end: BoundaryPoint) -> BoundaryPoint {
+    if tree.is_inclusive_ancestor(start.node, end.node) {
+        return start;
+    }
+    let mut reference = start.node;
+    while let Some(parent) = tree.parent(reference) {
+        if tree.is_inclusive_ancestor(parent, end.node) {
+            break;
+        }
+        reference = parent;
+    }
+    point_after(tree, reference).unwrap_or(start)
+}
+
+/// Remove the contents of `range`, joining the blocks it started and ended
+/// in. Nodes are detached rather than dropped, so that undo can restore them.
+fn delete_range(tree: &mut DomTree, host: NodeId, range: &Range) -> BoundaryPoint {
+    let (start, end) = (range.start(), range.end());
+    if range.collapsed() {
+        return start;
+    }
+    if start.node == end.node && is_text(tree, start.node) {
+        tree.replace_data(start.node, start.offset, end.offset - start.offset, "");
+        return start;
+    }
+
+    let common = range.common_ancestor_container(tree);
+    let contained: Vec<NodeId> = tree
+        .descendants(common)
+        .filter(|&node| {
+            range.contains_node(tree, node)
+                && !tree
+                    .parent(node)
+                    .is_some_and(|parent| range.contains_node(tree, parent))
+        })
+        .collect();
+    let point = collapse_point(tree, start, end);
+
+    if is_text(tree, start.node) {
+        let length = text_len(tree, start.node);
+        tree.replace_data(start.node, start.offset, length - start.offset, "");
+    }
+    for node in contained {
+        tree.remove_from_parent(node);
+    }
+    if is_text(tree, end.node) {
+        tree.replace_data(end.node, 0, end.offset, "");
+    }
+
+    let first = block_of(tree, host, point.node);
+    let second = block_of(tree, host, end.node);
+    if let (Some(first), Some(second)) = (first, second) {
+        if !tree.is_inclusive_ancestor(first, second) && !tree.is_inclusive_ancestor(second, first)
+        {
+            merge_blocks(tree, host, first, second);
+        }
+    }
+    point
+}
+
+/// Insert `text` at `point`, into an adjacent text node where there is
+/// one. Returns the text node and the offset the text starts at.
+fn insert_text(tree: &mut DomTree, point: BoundaryPoint, text: &str) -> (NodeId, usize) {
+    if is_text(tree, point.node) {
+        if !text.is_empty() {
+            tree.replace_data(point.node, point.offset, 0, text);
+        }
+        return (point.node, point.offset);
+    }
+    let before = point
+        .offset
+        .checked_sub(1)
+        .and_then(|index| tree.children(point.node).nth(index))
+        .filter(|&node| is_text(tree, node));
+    if let Some(before) = before {
+        let offset = text_len(tree, before);
+        tree.replace_data(before, offset, 0, text);
+        return (before, offset);
+    }
+    let after = tree.children(point.node).nth(point.offset);
+    if let Some(after) = after.filter(|&node| is_text(tree, node)) {
+        tree.replace_data(after, 0, 0, text);
+        return (after, 0);
+    }
+    let node = tree.create_text(text.to_string());
+    tree.insert_before(point.node, node, after);
+    // Text replaces the line break that kept an empty block open.
+    if let Some(br) =
+        after.filter(|&br| has_tag(tree, br, &["br"]) && tree.next_sibling(br).is_none())
+    {
+        if !text.is_empty() {
+            tree.remove_from_parent(br);
+        }
+    }
+    (node, 0)
+}
+
+/// Insert the children of `fragment` at `point`, returning the point
+/// after them.
+fn insert_fragment(
+    tree: &mut DomTree,
+    point: BoundaryPoint,
+    fragment: NodeId,
+) -> Option<BoundaryPoint> {
+    let last = tree.last_child(fragment)?;
+    let (parent, reference) = insertion_point(tree, point)?;
+    tree.move_children(fragment, parent, reference);
+    point_after(tree, last)
+}
+
+/// Insert a `<br>` at `point`, returning the point after it.
+fn insert_line_break(
+    tree: &mut DomTree,
+    host: NodeId,
+    point: BoundaryPoint,
+) -> Option<BoundaryPoint> {
+    let (parent, reference) = insertion_point(tree, point)?;
+    let br = create_element(tree, "br");
+    tree.insert_before(parent, br, reference);
+    // A break that ends a block shows no line of its own without another.
+    if ends_block(tree, host, br) {
+        let placeholder = create_element(tree, "br");
+        let next = tree.next_sibling(br);
+        tree.insert_before(parent, placeholder, next);
+    }
+    point_after(tree, br)
+}
+
+/// Split the block at `point` in two, returning the start of the second.
+/// Outside a block, or in a table, a line break is inserted instead.
+fn insert_paragraph(
+    tree: &mut DomTree,
+    host: NodeId,
+    point: BoundaryPoint,
+) -> Option<BoundaryPoint> {
+    let block = block_of(tree, host, point.node)
+        .filter(|&block| !has_tag(tree, block, &["table", "td", "th", "tr"]));
+    let Some(block) = block else {
+        return insert_line_break(tree, host, point);
+    };
+    let new_block = split_at(tree, block, point)?;
+    for node in [block, new_block] {
+        prune_empty_inlines(tree, node);
+        ensure_placeholder(tree, node);
+    }
+    // A line break now ending the first block needs a placeholder to show.
+    if let Some(br) = tree
+        .last_child(block)
+        .filter(|&last| has_tag(tree, last, &["br"]))
+    {
+        if tree.prev_sibling(br).is_some() {
+            let placeholder = create_element(tree, "br");
+            tree.append_child(block, placeholder);
+        }
+    }
+    Some(start_of(tree, new_block))
+}
+
+/// Split `ancestor` at `point`, which must be inside it, moving what
+/// follows the point into copies of the elements on the way. Returns the
+/// copy of `ancestor`, inserted after it.
+fn split_at(tree: &mut DomTree, ancestor: NodeId, point: BoundaryPoint) -> Option<NodeId> {
+    let (mut parent, mut index) = if is_text(tree, point.node) {
+        let parent = tree.parent(point.node)?;
+        let index = index_of(tree, point.node);
+        if point.offset == 0 {
+            (parent, index)
+        } else {
+            if point.offset < text_len(tree, point.node) {
+                tree.split_text(point.node, point.offset);
+            }
+            (parent, index + 1)
+        }
+    } else {
+        (point.node, point.offset)
+    };
+    loop {
+        let copy = tree.clone_node(parent, false)?;
+        if let Some(element) = tree.get_element_mut(copy) {
+            element.remove_attribute("id");
+        }
+        let moved: Vec<NodeId> = tree.children(parent).skip(index).collect();
+        for child in moved {
+            tree.append_child(copy, child);
+        }
+        let grandparent = tree.parent(parent)?;
+        let next = tree.next_sibling(parent);
+        tree.insert_before(grandparent, copy, next);
+        if parent == ancestor {
+            return Some(copy);
+        }
+        index = index_of(tree, parent) + 1;
+        parent = grandparent;
+    }
+}
+
+/// Whether nothing visible follows `node` in its block.
+fn ends_block(tree: &DomTree, host: NodeId, node: NodeId) -> bool {
+    let mut node = node;
+    loop {
+        let mut sibling = tree.next_sibling(node);
+        while let Some(next) = sibling {
+            if !is_visually_empty(tree, next) {
+                return false;
+            }
+            sibling = tree.next_sibling(next);
+        }
+        match tree.parent(node) {
+            Some(parent) if parent != host && !is_block(tree, parent) => node = parent,
+            _ => return true,
+        }
+    }
+}
+
+/// Caret position at the start of `node`'s content.
+fn start_of(tree: &DomTree, node: NodeId) -> BoundaryPoint {
+    let mut node = node;
+    loop {
+        match tree.first_child(node) {
+            Some(child) if is_text(tree, child) => return BoundaryPoint::new(child, 0),
+            Some(child)
+                if tree.get_element(child).is_some() && !has_tag(tree, child, VOID_CONTENT) =>
+            {
+                node = child
+            }
+            _ => return BoundaryPoint::new(node, 0),
+        }
+    }
+}
+
+/// Caret position at the end of `node`'s content.
+fn end_of(tree: &DomTree, node: NodeId) -> BoundaryPoint {
+    let mut node = node;
+    loop {
+        match tree.last_child(node) {
+            Some(child) if is_text(tree, child) => {
+                return BoundaryPoint::new(child, text_len(tree, child))
+            }
+            Some(child)
+                if tree.get_element(child).is_some() && !has_tag(tree, child, VOID_CONTENT) =>
+            {
+                node = child
+            }
+            _ => return BoundaryPoint::new(node, tree.children(node).count()),
+        }
+    }
+}
+
+/// Keep an empty block one line tall with a `<br>`.
+fn ensure_placeholder(tree: &mut DomTree, block: NodeId) {
+    if is_visually_empty(tree, block) {
+        let br = create_element(tree, "br");
+        tree.append_child(block, br);
+    }
+}
+
+/// Remove the `<br>` that ends a block, which shows no line of its own.
+fn remove_placeholder(tree: &mut DomTree, block: NodeId) {
+    if let Some(br) = tree
+        .last_child(block)
+        .filter(|&last| has_tag(tree, last, &["br"]))
+    {
+        tree.remove_from_parent(br);
+    }
+}
+
+/// Remove inline elements left empty by splitting.
+fn prune_empty_inlines(tree: &mut DomTree, node: NodeId) {
+    let empty: Vec<NodeId> = tree
+        .descendants(node)
+        .filter(|&id| {
+            tree.get_element(id).is_some()
+                && !is_block(tree, id)
+                && !has_tag(tree, id, VOID_CONTENT)
+                && is_visually_empty(tree, id)
+        })
+        .collect();
+    for id in empty {
+        if tree.ancestors(id).any(|ancestor| ancestor == node) {
+            tree.remove_from_parent(id);
+        }
+    }
+}
+
+/// Move the content of `second` to the end of `first` and remove it,
+/// along with a list or quote it leaves empty.
+fn merge_blocks(tree: &mut DomTree, host: NodeId, first: NodeId, second: NodeId) {
+    let parent = tree.parent(second);
+    // A block holding only its placeholder adds nothing.
+    let content: Vec<NodeId> = tree
+        .children(second)
+        .filter(|&child| !is_skippable(tree, child))
+        .collect();
+    if let [only] = content[..] {
+        if has_tag(tree, only, &["br"]) {
+            tree.remove_from_parent(only);
+        }
+    }
+    let children: Vec<NodeId> = tree.children(second).collect();
+    for child in children {
+        tree.append_child(first, child);
+    }
+    tree.remove_from_parent(second);
+    if let Some(parent) = parent.filter(|&parent| parent != host && is_visually_empty(tree, parent))
+    {
+        tree.remove_from_parent(parent);
+    }
+    ensure_placeholder(tree, first);
+}
+
+/// Replace `node` with its children.
+fn unwrap(tree: &mut DomTree, node: NodeId) {
+    let Some(parent) = tree.parent(node) else {
+        return;
+    };
+    let children: Vec<NodeId> = tree.children(node).collect();
+    for child in children {
+        tree.insert_before(parent, child, Some(node));
+    }
+    tree.remove_from_parent(node);
+}
+
+/// Wrap `node` in a new `tag` element, or in the same element as the
+/// node before it.
+fn wrap(tree: &mut DomTree, node: NodeId, tag: &str, attribute: Option<(&str, &str)>) {
+    let Some(parent) = tree.parent(node) else {
+        return;
+    };
+    let previous = tree.prev_sibling(node).filter(|&previous| {
+        tree.get_element(previous).is_some_and(|e| {
+            e.tag_name.as_str() == tag
+                && attribute.map_or(true, |(name, value)| e.get_attribute(name) == Some(value))
+        })
+    });
+    let wrapper = match previous {
+        Some(previous) => previous,
+        None => {
+            let wrapper = create_element(tree, tag);
+            if let (Some((name, value)), Some(element)) = (attribute, tree.get_element_mut(wrapper))
+            {
+                element.set_attribute(name, value);
+            }
+            tree.insert_before(parent, wrapper, Some(node));
+            wrapper
+        }
+    };
+    tree.append_child(wrapper, node);
+}
+
+/// Take `node` out of `element` by splitting the element around it and
+/// unwrapping the part holding the node.
+fn isolate_and_unwrap(tree: &mut DomTree, element: NodeId, node: NodeId) {
+    if let Some(after) = point_after(tree, node) {
+        if let Some(rest) = split_at(tree, element, after) {
+            remove_if_empty(tree, rest);
+        }
+    }
+    let Some(before) = point_before(tree, node) else {
+        return;
+    };
+    if let Some(middle) = split_at(tree, element, before) {
+        remove_if_empty(tree, element);
+        unwrap(tree, middle);
+    }
+}
+
+fn remove_if_empty(tree: &mut DomTree, node: NodeId) {
+    if is_visually_empty(tree, node) {
+        tree.remove_from_parent(node);
+    }
+}
+
+/// Split the text nodes the range starts and ends inside, so that it
+/// selects whole text nodes. Returns the adjusted range, or `None` for a
+/// caret.
+fn split_selection(tree: &mut DomTree, range: &Range) -> Option<Range> {
+    if range.collapsed() {
+        return None;
+    }
+    let (mut start, mut end) = (range.start(), range.end());
+    if is_text(tree, start.node) && start.offset > 0 && start.offset < text_len(tree, start.node) {
+        let tail = tree.split_text(start.node, start.offset)?;
+        if end.node == start.node {
+            end = BoundaryPoint::new(tail, end.offset - start.offset);
+        }
+        start = BoundaryPoint::new(tail, 0);
+    }
+    if is_text(tree, end.node) && end.offset > 0 && end.offset < text_len(tree, end.node) {
+        tree.split_text(end.node, end.offset);
+    }
+    let mut range = Range::new(start.node);
+    range.set_end(tree, end.node, end.offset).ok()?;
+    range.set_start(tree, start.node, start.offset).ok()?;
+    Some(range)
+}
+
+/// Text nodes of `host` with selected, visible text.
+fn selected_texts(tree: &DomTree, host: NodeId, range: &Range) -> Vec<NodeId> {
+    let (start, end) = (range.start(), range.end());
+    tree.descendants(host)
+        .filter(|&node| {
+            let Some(NodeData::Text { content }) = tree.get(node).map(|n| &n.data) else {
+                return false;
+            };
+            if content.trim().is_empty() || !range.intersects_node(tree, node) {
+                return false;
+            }
+            let from = if node == start.node { start.offset } else { 0 };
+            let to = if node == end.node {
+                end.offset
+            } else {
+                content.len()
+            };
+            from < to
+        })
+        .collect()
+}
+
+/// The range over whole text nodes, from the first to the last.
+fn text_span(tree: &DomTree, texts: &[NodeId]) -> Option<Range> {
+    let (&first, &last) = (texts.first()?, texts.last()?);
+    let mut range = Range::new(first);
+    range.set_end(tree, last, text_len(tree, last)).ok()?;
+    Some(range)
+}
+
+/// Remove the character before `offset` in a text node.
+fn delete_char_before(
+    tree: &mut DomTree,
+    host: NodeId,
+    node: NodeId,
+    offset: usize,
+) -> BoundaryPoint {
+    let len = match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => content[..offset]
+            .chars()
+            .next_back()
+            .map_or(0, char::len_utf8),
+        _ => 0,
+    };
+    tree.replace_data(node, offset - len, len, "");
+    if let Some(block) = block_of(tree, host, node) {
+        ensure_placeholder(tree, block);
+    }
+    BoundaryPoint::new(node, offset - len)
+}
+
+/// Remove the character at `offset` in a text node.
+fn delete_char_after(tree: &mut DomTree, host: NodeId, node: NodeId, offset: usize) {
+    let len = match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => {
+            content[offset..].chars().next().map_or(0, char::len_utf8)
+        }
+        _ => 0,
+    };
+    tree.replace_data(node, offset, len, "");
+    if let Some(block) = block_of(tree, host, node) {
+        ensure_placeholder(tree, block);
+    }
+}
+
+/// Backspace at a caret: remove the character or element before it, or
+/// join its block to the content before.
+fn delete_backward(
+    tree: &mut DomTree,
+    host: NodeId,
+    point: BoundaryPoint,
+) -> Option<BoundaryPoint> {
+    let point = match tree.children(point.node).nth(point.offset.wrapping_sub(1)) {
+        Some(child) if !is_text(tree, point.node) && is_text(tree, child) => {
+            BoundaryPoint::new(child, text_len(tree, child))
+        }
+        _ => point,
+    };
+    if is_text(tree, point.node) && point.offset > 0 {
+        return Some(delete_char_before(tree, host, point.node, point.offset));
+    }
+    let block = block_of(tree, host, point.node);
+    let leaf = previous_leaf(tree, host, point)?;
+    let Some(block) = block.filter(|&block| !tree.is_inclusive_ancestor(block, leaf)) else {
+        if is_text(tree, leaf) {
+            return Some(delete_char_before(tree, host, leaf, text_len(tree, leaf)));
+        }
+        let caret = point_before(tree, leaf)?;
+        tree.remove_from_parent(leaf);
+        if let Some(block) = block {
+            ensure_placeholder(tree, block);
+        }
+        return Some(caret);
+    };
+    match block_of(tree, host, leaf)
+        .filter(|&previous| !tree.is_inclusive_ancestor(previous, block))
+    {
+        Some(previous) => {
+            remove_placeholder(tree, previous);
+            let caret = end_of(tree, previous);
+            merge_blocks(tree, host, previous, block);
+            Some(caret)
+        }
+        None => {
+            let caret = point_before(tree, block)?;
+            unwrap(tree, block);
+            Some(caret)
+        }
+    }
+}
+
+/// Delete at a caret: remove the character or element after it, or join
+/// the content after into its block.
+fn delete_forward(tree: &mut DomTree, host: NodeId, point: BoundaryPoint) -> Option<BoundaryPoint> {
+    let point = match tree.children(point.node).nth(point.offset) {
+        Some(child) if !is_text(tree, point.node) && is_text(tree, child) => {
+            BoundaryPoint::new(child, 0)
+        }
+        _ => point,
+    };
+    if is_text(tree, point.node) && point.offset < text_len(tree, point.node) {
+        delete_char_after(tree, host, point.node, point.offset);
+        return Some(point);
+    }
+    let block = block_of(tree, host, point.node);
+    let leaf = next_leaf(tree, host, point)?;
+    let in_block = block.map_or(true, |block| tree.is_inclusive_ancestor(block, leaf));
+    if in_block
+        && !(block.is_some() && has_tag(tree, leaf, &["br"]) && ends_block(tree, host, leaf))
+    {
+        if is_text(tree, leaf) {
+            delete_char_after(tree, host, leaf, 0);
+        } else {
+            tree.remove_from_parent(leaf);
+            if let Some(block) = block {
+                ensure_placeholder(tree, block);
+            }
+        }
+        return Some(point);
+    }
+    let block = block?;
+    match block_of(tree, host, leaf).filter(|&next| !tree.is_inclusive_ancestor(next, block)) {
+        Some(next) => {
+            remove_placeholder(tree, block);
+            merge_blocks(tree, host, block, next);
+        }
+        None => {
+            // Pull the inline content that follows the block into it.
+            remove_placeholder(tree, block);
+            let mut node = block;
+            while tree.next_sibling(node).is_none() {
+                node = tree.parent(node).filter(|&parent| parent != host)?;
+            }
+            while let Some(sibling) = tree
+                .next_sibling(node)
+                .filter(|&sibling| !is_block(tree, sibling))
+            {
+                tree.append_child(block, sibling);
+            }
+        }
+    }
+    Some(point)
+}
+
+/// Whether a leaf shows nothing, so deletion steps over it.
+fn is_skippable(tree: &DomTree, node: NodeId) -> bool {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => content.is_empty(),
+        Some(NodeData::Element(_)) => {
+            !has_tag(tree, node, VOID_CONTENT) && tree.first_child(node).is_none()
+        }
+        _ => true,
+    }
+}
+
+/// The visible leaf before `point` in tree order, within `host`.
+fn previous_leaf(tree: &DomTree, host: NodeId, point: BoundaryPoint) -> Option<NodeId> {
+    let mut leaf = match tree.children(point.node).nth(point.offset.wrapping_sub(1)) {
+        Some(child) if !is_text(tree, point.node) => Some(last_leaf(tree, child)),
+        _ => preceding(tree, host, point.node),
+    };
+    while let Some(node) = leaf {
+        if !is_skippable(tree, node) {
+            return Some(node);
+        }
+        leaf = preceding(tree, host, node);
+    }
+    None
+}
+
+/// The visible leaf after `point` in tree order, within `host`.
+fn next_leaf(tree: &DomTree, host: NodeId, point: BoundaryPoint) -> Option<NodeId> {
+    let mut leaf = match tree.children(point.node).nth(point.offset) {
+        Some(child) if !is_text(tree, point.node) => Some(first_leaf(tree, child)),
+        _ => following(tree, host, point.node),
+    };
+    while let Some(node) = leaf {
+        if !is_skippable(tree, node) {
+            return Some(node);
+        }
+        leaf = following(tree, host, node);
+    }
+    None
+}
+
+fn preceding(tree: &DomTree, host: NodeId, node: NodeId) -> Option<NodeId> {
+    let mut node = node;
+    while node != host {
+        if let Some(previous) = tree.prev_sibling(node) {
+            return Some(last_leaf(tree, previous));
+        }
+        node = tree.parent(node)?;
+    }
+    None
+}
+
+fn following(tree: &DomTree, host: NodeId, node: NodeId) -> Option<NodeId> {
+    let mut node = node;
+    while node != host {
+        if let Some(next) = tree.next_sibling(node) {
+            return Some(first_leaf(tree, next));
+        }
+        node = tree.parent(node)?;
+    }
+    None
+}
+
+fn last_leaf(tree: &DomTree, node: NodeId) -> NodeId {
+    let mut node = node;
+    while let Some(child) = tree.last_child(node) {
+        node = child;
+    }
+    node
+}
+
+fn first_leaf(tree: &DomTree, node: NodeId) -> NodeId {
+    let mut node = node;
+    while let Some(child) = tree.first_child(node) {
+        node = child;
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tags and text of `node`'s children, for comparing structure.
+    fn markup(tree: &DomTree, node: NodeId) -> String {
+        tree.children(node)
+            .map(|child| match &tree.get(child).unwrap().data {
+                NodeData::Text { content } => content.clone(),
+                _ => {
+                    let tag = tree
+                        .get_element(child)
+                        .unwrap()
+                        .tag_name
+                        .as_str()
+                        .to_string();
+                    if tag == "br" {
+                        return "<br>".to_string();
+                    }
+                    format!("<{tag}>{}</{tag}>", markup(tree, child))
+                }
+            })
+            .collect()
+    }
+
+    fn apply(
+        editor: &mut Editor,
+        tree: &mut DomTree,
+        selection: &Mutex<Selection>,
+        host: NodeId,
+        input: InputDetail,
+    ) {
+        assert!(
+            editor.apply(tree, selection, EditTarget::Content(host), &input),
+            "{input:?}"
+        );
+    }
+
+    #[test]
+    fn test_content_editing_and_undo() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("contenteditable", "");
+        let host = tree.create_element(data);
+        tree.append_child(root, host);
+        let p = create_element(&mut tree, "p");
+        tree.append_child(host, p);
+        let text = tree.create_text("hello".to_string());
+        tree.append_child(p, text);
+        assert_eq!(editing_host(&tree, text), Some(host));
+
+        let selection = Arc::new(Mutex::new(Selection::new()));
+        tree.add_mutation_hook(selection.clone());
+        selection.lock().collapse(&tree, text, 5).unwrap();
+        let mut editor = Editor::new();
+        let editor = &mut editor;
+
+        for ch in [" ", "w", "o", "r", "l", "d"] {
+            apply(
+                editor,
+                &mut tree,
+                &selection,
+                host,
+                InputDetail::with_data(InputType::InsertText, ch),
+            );
+        }
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::InsertParagraph),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p><p><br></p>");
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::with_data(InputType::InsertText, "x"),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p><p>x</p>");
+
+        // Backspace empties the paragraph, then joins it to the first.
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::DeleteContentBackward),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p><p><br></p>");
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::DeleteContentBackward),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p>");
+        assert_eq!(selection.lock().focus(), Some(BoundaryPoint::new(text, 11)));
+
+        // Bold toggles on the selected word.
+        let mut range = Range::new(text);
+        range.set_end(&tree, text, 11).unwrap();
+        range.set_start(&tree, text, 6).unwrap();
+        selection.lock().set_range(range.clone());
+        assert!(!format_state(&tree, host, &range, InputType::FormatBold));
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::FormatBold),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello <b>world</b></p>");
+        let bold = selection.lock().range().cloned().unwrap();
+        assert!(format_state(&tree, host, &bold, InputType::FormatBold));
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::FormatBold),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p>");
+
+        // Undo steps back through the edits; typing undoes as one step.
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::HistoryUndo),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello <b>world</b></p>");
+        for _ in 0..4 {
+            apply(
+                editor,
+                &mut tree,
+                &selection,
+                host,
+                InputDetail::new(InputType::HistoryUndo),
+            );
+        }
+        assert_eq!(markup(&tree, host), "<p>hello world</p>");
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::HistoryUndo),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello</p>");
+        assert!(!editor.can_undo());
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::HistoryRedo),
+        );
+        assert_eq!(markup(&tree, host), "<p>hello world</p>");
+    }
+
+    #[test]
+    fn test_text_control_editing_and_composition() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let input = create_element(&mut tree, "input");
+        tree.append_child(root, input);
+        forms::set_value(&mut tree, input, "ab");
+        assert!(is_editable_control(&tree, input));
+
+        let selection = Mutex::new(Selection::new());
+        let target = EditTarget::Control(input);
+        let mut editor = Editor::new();
+        forms::set_selection_range(&mut tree, input, 1, 1);
+        for data in ["k", "ka", "か"] {
+            let mut detail = InputDetail::with_data(InputType::InsertCompositionText, data);
+            detail.is_composing = true;
+            assert!(editor.apply(&mut tree, &selection, target, &detail));
+        }
+        editor.end_composition();
+        assert_eq!(forms::value(&tree, input), "aかb");
+        assert_eq!(forms::selection_range(&tree, input), (4, 4));
+
+        let newline = InputDetail::new(InputType::InsertLineBreak);
+        assert!(!editor.apply(&mut tree, &selection, target, &newline));
+        let backspace = InputDetail::new(InputType::DeleteContentBackward);
+        assert!(editor.apply(&mut tree, &selection, target, &backspace));
+        assert_eq!(forms::value(&tree, input), "ab");
+
+        assert!(editor.undo(&mut tree, &selection));
+        assert_eq!(forms::value(&tree, input), "aかb");
+        assert!(editor.undo(&mut tree, &selection));
+        assert_eq!(forms::value(&tree, input), "ab");
+        assert_eq!(forms::selection_range(&tree, input), (1, 1));
+    }
+
+    #[test]
+    fn test_deletion_at_node_boundaries() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("contenteditable", "");
+        let host = tree.create_element(data);
+        tree.append_child(root, host);
+        let first = create_element(&mut tree, "p");
+        tree.append_child(host, first);
+        let ab = tree.create_text("ab".to_string());
+        tree.append_child(first, ab);
+        let bold = create_element(&mut tree, "b");
+        tree.append_child(first, bold);
+        let cd = tree.create_text("cd".to_string());
+        tree.append_child(bold, cd);
+        let ef = tree.create_text("ef".to_string());
+        tree.append_child(first, ef);
+        let second = create_element(&mut tree, "p");
+        tree.append_child(host, second);
+        let gh = tree.create_text("gh".to_string());
+        tree.append_child(second, gh);
+
+        let selection = Arc::new(Mutex::new(Selection::new()));
+        tree.add_mutation_hook(selection.clone());
+        let mut editor = Editor::new();
+        let editor = &mut editor;
+        let backward = || InputDetail::new(InputType::DeleteContentBackward);
+        let forward = || InputDetail::new(InputType::DeleteContentForward);
+
+        // Deleting past the end or start of a text node reaches into the
+        // neighbouring inline element.
+        selection.lock().collapse(&tree, ef, 0).unwrap();
+        apply(editor, &mut tree, &selection, host, backward());
+        assert_eq!(markup(&tree, host), "<p>ab<b>c</b>ef</p><p>gh</p>");
+        selection.lock().collapse(&tree, ab, 2).unwrap();
+        apply(editor, &mut tree, &selection, host, forward());
+        assert_eq!(markup(&tree, host), "<p>ab<b></b>ef</p><p>gh</p>");
+        assert_eq!(selection.lock().focus(), Some(BoundaryPoint::new(ab, 2)));
+        // Emptied elements are stepped over.
+        apply(editor, &mut tree, &selection, host, forward());
+        assert_eq!(markup(&tree, host), "<p>ab<b></b>f</p><p>gh</p>");
+
+        // At the end of a block, the next block is joined into it; at the
+        // start of a block, it joins the previous one.
+        selection.lock().collapse(&tree, ef, 1).unwrap();
+        apply(editor, &mut tree, &selection, host, forward());
+        assert_eq!(markup(&tree, host), "<p>ab<b></b>fgh</p>");
+        // Splitting the paragraph again prunes the empty element.
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::InsertParagraph),
+        );
+        assert_eq!(markup(&tree, host), "<p>abf</p><p>gh</p>");
+        apply(editor, &mut tree, &selection, host, backward());
+        assert_eq!(markup(&tree, host), "<p>abfgh</p>");
+
+        // Nothing precedes the start of the host.
+        selection.lock().collapse(&tree, ab, 0).unwrap();
+        assert!(!editor.apply(
+            &mut tree,
+            &selection,
+            EditTarget::Content(host),
+            &backward()
+        ));
+        assert_eq!(markup(&tree, host), "<p>abfgh</p>");
+
+        while editor.undo(&mut tree, &selection) {}
+        assert_eq!(markup(&tree, host), "<p>ab<b>cd</b>ef</p><p>gh</p>");
+    }
+
+    #[test]
+    fn test_undo_coalescing() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("contenteditable", "");
+        let host = tree.create_element(data);
+        tree.append_child(root, host);
+        let text = tree.create_text("ab".to_string());
+        tree.append_child(host, text);
+
+        let selection = Arc::new(Mutex::new(Selection::new()));
+        tree.add_mutation_hook(selection.clone());
+        let mut editor = Editor::new();
+        let editor = &mut editor;
+        let type_text = |editor: &mut Editor, tree: &mut DomTree, data: &str| {
+            for ch in data.chars() {
+                let input = InputDetail::with_data(InputType::InsertText, ch.to_string());
+                apply(editor, tree, &selection, host, input);
+            }
+        };
+
+        selection.lock().collapse(&tree, text, 2).unwrap();
+        type_text(editor, &mut tree, "cd");
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::InsertLineBreak),
+        );
+        type_text(editor, &mut tree, "ef");
+        assert_eq!(markup(&tree, host), "abcd<br>ef");
+        // Typing elsewhere starts a new step, as does another kind of edit.
+        selection.lock().collapse(&tree, text, 0).unwrap();
+        type_text(editor, &mut tree, "xy");
+        apply(
+            editor,
+            &mut tree,
+            &selection,
+            host,
+            InputDetail::new(InputType::DeleteContentBackward),
+        );
+        assert_eq!(markup(&tree, host), "xabcd<br>ef");
+
+        let undo = |editor: &mut Editor, tree: &mut DomTree| {
+            assert!(editor.undo(tree, &selection));
+            markup(tree, host)
+        };
+        assert_eq!(undo(editor, &mut tree), "xyabcd<br>ef");
+        assert_eq!(selection.lock().focus(), Some(BoundaryPoint::new(text, 2)));
+        assert_eq!(undo(editor, &mut tree), "abcd<br>ef");
+        // The line break's placeholder comes back with the text removed.
+        assert_eq!(undo(editor, &mut tree), "abcd<br><br>");
+        assert_eq!(undo(editor, &mut tree), "abcd");
+        assert_eq!(undo(editor, &mut tree), "ab");
+        assert_eq!(selection.lock().focus(), Some(BoundaryPoint::new(text, 2)));
+        assert!(!editor.can_undo());
+
+        assert!(editor.redo(&mut tree, &selection));
+        assert!(editor.redo(&mut tree, &selection));
+        assert_eq!(markup(&tree, host), "abcd<br><br>");
+        // A new edit drops what was undone.
+        type_text(editor, &mut tree, "!");
+        assert!(!editor.can_redo());
+        assert_eq!(markup(&tree, host), "abcd<br>!");
+    }
+
+    #[test]
+    fn test_text_control_selection_clamping() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let textarea = create_element(&mut tree, "textarea");
+        tree.append_child(root, textarea);
+        forms::set_value(&mut tree, textarea, "h\u{e9}llo");
+
+        // Offsets are clamped to the value and back to a character boundary.
+        forms::set_selection_range(&mut tree, textarea, 2, 2);
+        assert_eq!(forms::selection_range(&tree, textarea), (1, 1));
+        forms::set_selection_range(&mut tree, textarea, 4, 1);
+        assert_eq!(forms::selection_range(&tree, textarea), (1, 1));
+        forms::set_selection_range(&mut tree, textarea, 3, 99);
+        assert_eq!(forms::selection_range(&tree, textarea), (3, 6));
+
+        let selection = Mutex::new(Selection::new());
+        let target = EditTarget::Control(textarea);
+        let mut editor = Editor::new();
+        let paste = InputDetail::with_data(InputType::InsertFromPaste, "a\r\nb\rc");
+        assert!(editor.apply(&mut tree, &selection, target, &paste));
+        assert_eq!(forms::value(&tree, textarea), "h\u{e9}a\nb\nc");
+        assert_eq!(forms::selection_range(&tree, textarea), (8, 8));
+
+        forms::set_selection_range(&mut tree, textarea, 99, 99);
+        let forward = InputDetail::new(InputType::DeleteContentForward);
+        assert!(!editor.apply(&mut tree, &selection, target, &forward));
+        let backward = InputDetail::new(InputType::DeleteContentBackward);
+        assert!(editor.apply(&mut tree, &selection, target, &backward));
+        assert_eq!(forms::value(&tree, textarea), "h\u{e9}a\nb\n");
+        forms::set_selection_range(&mut tree, textarea, 3, 3);
+        assert!(editor.apply(&mut tree, &selection, target, &backward));
+        assert_eq!(forms::value(&tree, textarea), "ha\nb\n");
+        assert_eq!(forms::selection_range(&tree, textarea), (1, 1));
+    }
+}
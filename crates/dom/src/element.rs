@@ -1,10 +1,13 @@
 //! DOM Element implementation.
 
 use crate::attributes::AttributeMap;
+use crate::forms::ControlState;
+use crate::node::NodeId;
 use bitflags::bitflags;
 use indexmap::IndexMap;
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -196,6 +199,10 @@ bitflags! {
         const CHECKED = 1 << 11;
         const SELECTED = 1 << 12;
         const EXPANDED = 1 << 13;
+        /// Set by the document's focus controller.
+        const FOCUSED = 1 << 14;
+        const FOCUS_VISIBLE = 1 << 15;
+        const FOCUS_WITHIN = 1 << 16;
     }
 }
 
@@ -218,13 +225,22 @@ pub struct ElementData {
     pub inline_style: Option<String>,
     /// Shadow root (if any).
     pub shadow_root: Option<ShadowRoot>,
+    /// Template contents fragment (`<template>` elements only).
+    pub template_contents: Option<NodeId>,
     /// Custom element state.
     pub custom_state: CustomElementState,
+    /// Form control state (value and dirty flags).
+    pub control: Box<ControlState>,
 }
 
 impl ElementData {
     pub fn new(tag_name: TagName) -> Self {
         let flags = Self::default_flags(&tag_name);
+        let custom_state = if crate::custom_elements::is_valid_custom_element_name(tag_name.as_str()) {
+            CustomElementState::Undefined
+        } else {
+            CustomElementState::Uncustomized
+        };
         Self {
             tag_name,
             namespace: None,
@@ -234,13 +250,16 @@ impl ElementData {
             flags,
             inline_style: None,
             shadow_root: None,
-            custom_state: CustomElementState::Undefined,
+            template_contents: None,
+            custom_state,
+            control: Box::default(),
         }
     }
 
     pub fn with_namespace(tag_name: TagName, namespace: &str) -> Self {
         let mut elem = Self::new(tag_name);
         elem.namespace = Some(Arc::from(namespace));
+        elem.custom_state = CustomElementState::Uncustomized;
         elem
     }
 
@@ -324,10 +343,11 @@ impl ElementData {
             "disabled" => {
                 self.flags.insert(ElementFlags::DISABLED);
             }
-            "checked" => {
+            // Dirty checkedness no longer follows the attribute.
+            "checked" if !self.control.dirty_checkedness => {
                 self.flags.insert(ElementFlags::CHECKED);
             }
-            "selected" => {
+            "selected" if !self.control.dirty_checkedness => {
                 self.flags.insert(ElementFlags::SELECTED);
             }
             _ => {}
@@ -346,8 +366,10 @@ impl ElementData {
             "style" => self.inline_style = None,
             "hidden" => self.flags.remove(ElementFlags::HIDDEN),
             "disabled" => self.flags.remove(ElementFlags::DISABLED),
-            "checked" => self.flags.remove(ElementFlags::CHECKED),
-            "selected" => self.flags.remove(ElementFlags::SELECTED),
+            "checked" if !self.control.dirty_checkedness => self.flags.remove(ElementFlags::CHECKED),
+            "selected" if !self.control.dirty_checkedness => {
+                self.flags.remove(ElementFlags::SELECTED)
+            }
             _ => {}
         }
 
@@ -486,16 +508,53 @@ pub enum SimpleSelector {
 /// Shadow DOM root.
 #[derive(Clone, Debug)]
 pub struct ShadowRoot {
+    /// Document fragment holding the shadow tree.
+    pub root: NodeId,
     pub mode: ShadowRootMode,
     pub delegates_focus: bool,
+    pub clonable: bool,
+    pub serializable: bool,
+}
+
+impl ShadowRoot {
+    pub fn new(root: NodeId, mode: ShadowRootMode) -> Self {
+        Self {
+            root,
+            mode,
+            delegates_focus: false,
+            clonable: false,
+            serializable: false,
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ShadowRootMode {
     Open,
     Closed,
 }
 
+impl ShadowRootMode {
+    /// Parse a `mode` / `shadowrootmode` value (ASCII case-insensitive).
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.eq_ignore_ascii_case("open") {
+            Some(ShadowRootMode::Open)
+        } else if s.eq_ignore_ascii_case("closed") {
+            Some(ShadowRootMode::Closed)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShadowRootMode::Open => "open",
+            ShadowRootMode::Closed => "closed",
+        }
+    }
+}
+
 /// Custom element state.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
 pub enum CustomElementState {
@@ -1,8 +1,11 @@
 //! DOM Events implementation.
 
+use crate::editing::InputDetail;
 use crate::node::NodeId;
+use crate::tree::DomTree;
 use parking_lot::RwLock;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
@@ -40,6 +43,12 @@ pub enum EventType {
     Input,
     Invalid,
 
+    // Editing events
+    BeforeInput,
+    CompositionStart,
+    CompositionUpdate,
+    CompositionEnd,
+
     // Document/Window events
     Load,
     Unload,
@@ -101,6 +110,10 @@ impl EventType {
             "change" => EventType::Change,
             "input" => EventType::Input,
             "invalid" => EventType::Invalid,
+            "beforeinput" => EventType::BeforeInput,
+            "compositionstart" => EventType::CompositionStart,
+            "compositionupdate" => EventType::CompositionUpdate,
+            "compositionend" => EventType::CompositionEnd,
             "load" => EventType::Load,
             "unload" => EventType::Unload,
             "beforeunload" => EventType::BeforeUnload,
@@ -124,7 +137,7 @@ impl EventType {
             "animationend" => EventType::AnimationEnd,
             "animationiteration" => EventType::AnimationIteration,
             "transitionend" => EventType::TransitionEnd,
-            other => EventType::Custom(other.to_string()),
+            _ => EventType::Custom(s.to_string()),
         }
     }
 
@@ -153,6 +166,10 @@ impl EventType {
             EventType::Change => "change",
             EventType::Input => "input",
             EventType::Invalid => "invalid",
+            EventType::BeforeInput => "beforeinput",
+            EventType::CompositionStart => "compositionstart",
+            EventType::CompositionUpdate => "compositionupdate",
+            EventType::CompositionEnd => "compositionend",
             EventType::Load => "load",
             EventType::Unload => "unload",
             EventType::BeforeUnload => "beforeunload",
@@ -204,7 +221,10 @@ impl EventType {
             | EventType::Focus
             | EventType::Blur
             | EventType::MouseEnter
-            | EventType::MouseLeave => false,
+            | EventType::MouseLeave
+            | EventType::Input
+            | EventType::CompositionUpdate
+            | EventType::CompositionEnd => false,
             _ => true,
         }
     }
@@ -246,6 +266,13 @@ pub struct Event {
     pub is_trusted: bool,
     /// Timestamp.
     pub timestamp: f64,
+    /// Propagation path of the current dispatch, target first.
+    pub path: Vec<NodeId>,
+    /// Whether the listener being invoked is passive; `prevent_default`
+    /// has no effect while it is set.
+    pub in_passive_listener: bool,
+    /// The edit of `beforeinput`, `input` and composition events.
+    pub input: Option<InputDetail>,
 }
 
 impl Event {
@@ -271,6 +298,9 @@ impl Event {
             composed: false,
             is_trusted: false,
             timestamp,
+            path: Vec::new(),
+            in_passive_listener: false,
+            input: None,
         }
     }
 
@@ -283,7 +313,7 @@ impl Event {
 
     /// Prevent default action.
     pub fn prevent_default(&mut self) {
-        if self.cancelable {
+        if self.cancelable && !self.in_passive_listener {
             self.default_prevented = true;
         }
     }
@@ -299,13 +329,12 @@ impl Event {
         self.propagation_stopped = true;
     }
 
-    /// Compose path (for shadow DOM).
+    /// Nodes the event propagates through, target first (`composedPath()`).
     pub fn composed_path(&self) -> Vec<NodeId> {
-        // TODO: Implement shadow DOM path
-        match self.target {
-            Some(target) => vec![target],
-            None => Vec::new(),
+        if !self.path.is_empty() {
+            return self.path.clone();
         }
+        self.target.into_iter().collect()
     }
 }
 
@@ -441,6 +470,26 @@ impl Default for WheelEvent {
 /// Event listener callback type.
 pub type EventCallback = Arc<dyn Fn(&mut Event) + Send + Sync>;
 
+/// What an event listener calls.
+#[derive(Clone)]
+pub enum ListenerCallback {
+    /// A Rust callback.
+    Native(EventCallback),
+    /// A script callback, by the handle the script engine keeps it under.
+    Script(u64),
+}
+
+impl ListenerCallback {
+    /// Whether both refer to the same callback.
+    pub fn same(&self, other: &ListenerCallback) -> bool {
+        match (self, other) {
+            (ListenerCallback::Native(a), ListenerCallback::Native(b)) => Arc::ptr_eq(a, b),
+            (ListenerCallback::Script(a), ListenerCallback::Script(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
 /// Event listener options.
 #[derive(Clone, Debug, Default)]
 pub struct EventListenerOptions {
@@ -450,10 +499,33 @@ pub struct EventListenerOptions {
 }
 
 /// Event listener.
+///
+/// Clones share the removed flag, so copies taken for a dispatch in
+/// progress skip listeners removed in the meantime.
 #[derive(Clone)]
 pub struct EventListener {
-    pub callback: EventCallback,
+    pub callback: ListenerCallback,
     pub options: EventListenerOptions,
+    removed: Arc<AtomicBool>,
+}
+
+impl EventListener {
+    pub fn new(callback: ListenerCallback, options: EventListenerOptions) -> Self {
+        Self {
+            callback,
+            options,
+            removed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the listener was removed from its target.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Acquire)
+    }
+
+    fn set_removed(&self) {
+        self.removed.store(true, Ordering::Release);
+    }
 }
 
 /// Event target trait.
@@ -473,7 +545,7 @@ pub trait EventTarget {
     fn dispatch_event(&mut self, event: &mut Event) -> bool;
 }
 
-/// Event manager for handling event dispatch.
+/// Event manager holding the listeners of a document's nodes.
 pub struct EventManager {
     /// Listeners by node and event type.
     listeners: HashMap<NodeId, HashMap<String, Vec<EventListener>>>,
@@ -494,18 +566,80 @@ impl EventManager {
         callback: EventCallback,
         options: EventListenerOptions,
     ) {
+        self.add(node, event_type, ListenerCallback::Native(callback), options);
+    }
+
+    /// Add a script listener for a node. Returns `false` if the handle is
+    /// already registered for the same type and phase.
+    pub fn add_script_listener(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        handle: u64,
+        options: EventListenerOptions,
+    ) -> bool {
+        self.add(node, event_type, ListenerCallback::Script(handle), options)
+    }
+
+    fn add(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        callback: ListenerCallback,
+        options: EventListenerOptions,
+    ) -> bool {
         let node_listeners = self.listeners.entry(node).or_default();
         let type_listeners = node_listeners.entry(event_type.to_string()).or_default();
-        type_listeners.push(EventListener { callback, options });
+        type_listeners.retain(|l| !l.is_removed());
+        let duplicate = type_listeners
+            .iter()
+            .any(|l| l.callback.same(&callback) && l.options.capture == options.capture);
+        if !duplicate {
+            type_listeners.push(EventListener::new(callback, options));
+        }
+        !duplicate
     }
 
     /// Remove event listener for a node.
-    pub fn remove_listener(&mut self, node: NodeId, event_type: &str, _callback: EventCallback) {
-        if let Some(node_listeners) = self.listeners.get_mut(&node) {
-            if let Some(type_listeners) = node_listeners.get_mut(event_type) {
-                // In real implementation, would compare callbacks
-                type_listeners.clear();
+    pub fn remove_listener(&mut self, node: NodeId, event_type: &str, callback: &EventCallback, capture: bool) {
+        let callback = ListenerCallback::Native(callback.clone());
+        self.remove_where(node, event_type, capture, |l| l.same(&callback));
+    }
+
+    /// Remove the first script listener of a node whose handle satisfies
+    /// `matches`, returning its handle.
+    pub fn remove_script_listener(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        capture: bool,
+        matches: impl Fn(u64) -> bool,
+    ) -> Option<u64> {
+        let mut removed = None;
+        self.remove_where(node, event_type, capture, |l| match l {
+            ListenerCallback::Script(handle) if removed.is_none() && matches(*handle) => {
+                removed = Some(*handle);
+                true
             }
+            _ => false,
+        });
+        removed
+    }
+
+    fn remove_where(
+        &mut self,
+        node: NodeId,
+        event_type: &str,
+        capture: bool,
+        mut remove: impl FnMut(&ListenerCallback) -> bool,
+    ) {
+        if let Some(type_listeners) = self.listeners.get_mut(&node).and_then(|n| n.get_mut(event_type)) {
+            type_listeners.retain(|l| {
+                if l.options.capture == capture && !l.is_removed() && remove(&l.callback) {
+                    l.set_removed();
+                }
+                !l.is_removed()
+            });
         }
     }
 
@@ -514,77 +648,180 @@ impl EventManager {
         self.listeners
             .get(&node)
             .and_then(|n| n.get(event_type))
-            .map(|l| l.iter().collect())
+            .map(|l| l.iter().filter(|l| !l.is_removed()).collect())
             .unwrap_or_default()
     }
 
-    /// Dispatch event to target.
-    pub fn dispatch(&mut self, target: NodeId, event: &mut Event, path: &[NodeId]) -> bool {
-        event.target = Some(target);
+    /// Listeners to invoke at `node` in the given phase, for
+    /// [`invoke_listeners`]. `once` listeners stay registered until they
+    /// are invoked.
+    pub fn take_listeners(&mut self, node: NodeId, event_type: &str, capture: bool) -> Vec<EventListener> {
+        let Some(type_listeners) = self.listeners.get_mut(&node).and_then(|n| n.get_mut(event_type)) else {
+            return Vec::new();
+        };
+        type_listeners.retain(|l| !l.is_removed());
+        type_listeners
+            .iter()
+            .filter(|l| l.options.capture == capture)
+            .cloned()
+            .collect()
+    }
+
+    /// Dispatch `event` at `target` to Rust listeners. Script listeners are
+    /// skipped; the script engine dispatches through the same
+    /// [`EventPath`] to reach both.
+    pub fn dispatch(&mut self, tree: &DomTree, target: NodeId, event: &mut Event) -> bool {
         event.is_trusted = true;
+        let event_type = event.event_type.as_str().to_string();
+        EventPath::new(tree, target, event.composed).dispatch(event, |node, event, capture| {
+            let listeners = self.take_listeners(node, &event_type, capture);
+            invoke_listeners(&listeners, event, &mut |_, _| {});
+        })
+    }
 
-        // Capture phase
-        event.phase = EventPhase::Capturing;
-        for &node in path.iter().rev().skip(1) {
-            event.current_target = Some(node);
-            self.invoke_listeners(node, event, true);
-            if event.propagation_stopped {
-                return !event.default_prevented;
-            }
+    /// Remove all listeners for a node.
+    pub fn remove_all(&mut self, node: NodeId) {
+        let removed = self.listeners.remove(&node).into_iter().flat_map(|n| n.into_values());
+        for listener in removed.flatten() {
+            listener.set_removed();
         }
+    }
+}
 
-        // Target phase
-        event.phase = EventPhase::AtTarget;
-        event.current_target = Some(target);
-        self.invoke_listeners(target, event, false);
-        if event.propagation_stopped {
-            return !event.default_prevented;
+/// Call `listeners` in order until immediate propagation is stopped,
+/// skipping those removed since they were taken. A `once` listener is
+/// removed just before it is called. Script callbacks are handed to
+/// `script` by handle.
+pub fn invoke_listeners(
+    listeners: &[EventListener],
+    event: &mut Event,
+    script: &mut dyn FnMut(u64, &mut Event),
+) {
+    for listener in listeners {
+        if event.immediate_propagation_stopped {
+            break;
         }
-
-        // Bubble phase
-        if event.bubbles {
-            event.phase = EventPhase::Bubbling;
-            for &node in path.iter().skip(1) {
-                event.current_target = Some(node);
-                self.invoke_listeners(node, event, false);
-                if event.propagation_stopped {
-                    return !event.default_prevented;
-                }
-            }
+        if listener.is_removed() {
+            continue;
+        }
+        if listener.options.once {
+            listener.set_removed();
         }
+        event.in_passive_listener = listener.options.passive;
+        match &listener.callback {
+            ListenerCallback::Native(callback) => callback(event),
+            ListenerCallback::Script(handle) => script(*handle, event),
+        }
+        event.in_passive_listener = false;
+    }
+}
 
-        event.phase = EventPhase::None;
-        !event.default_prevented
+/// The nodes an event propagates through, each with the target it sees
+/// there.
+#[derive(Clone, Debug)]
+pub struct EventPath {
+    /// `(node, retargeted target)`, target first.
+    entries: Vec<(NodeId, NodeId)>,
+}
+
+impl EventPath {
+    pub fn new(tree: &DomTree, target: NodeId, composed: bool) -> Self {
+        let entries = event_path(tree, target, composed)
+            .into_iter()
+            .map(|node| (node, retarget(tree, target, node)))
+            .collect();
+        Self { entries }
     }
 
-    fn invoke_listeners(&mut self, node: NodeId, event: &mut Event, capture: bool) {
-        let event_type = event.event_type.as_str().to_string();
+    /// The nodes of the path, target first.
+    pub fn nodes(&self) -> Vec<NodeId> {
+        self.entries.iter().map(|&(node, _)| node).collect()
+    }
 
-        if let Some(node_listeners) = self.listeners.get(&node) {
-            if let Some(type_listeners) = node_listeners.get(&event_type) {
-                for listener in type_listeners.iter() {
-                    if listener.options.capture == capture || event.phase == EventPhase::AtTarget {
-                        (listener.callback)(event);
+    /// Run the capture, target and bubble phases, calling
+    /// `invoke(node, event, capture)` at each step. Returns `false` if the
+    /// default action was prevented.
+    pub fn dispatch(&self, event: &mut Event, mut invoke: impl FnMut(NodeId, &mut Event, bool)) -> bool {
+        event.path = self.nodes();
 
-                        if event.immediate_propagation_stopped {
-                            break;
-                        }
-                    }
-                }
+        // Capture phase
+        for &(node, target) in self.entries.iter().rev() {
+            if event.propagation_stopped {
+                break;
+            }
+            event.target = Some(target);
+            event.current_target = Some(node);
+            event.phase = if target == node {
+                EventPhase::AtTarget
+            } else {
+                EventPhase::Capturing
+            };
+            invoke(node, event, true);
+        }
+
+        // Target and bubble phase
+        for &(node, target) in self.entries.iter() {
+            if event.propagation_stopped {
+                break;
+            }
+            event.target = Some(target);
+            event.current_target = Some(node);
+            if target == node {
+                event.phase = EventPhase::AtTarget;
+            } else if event.bubbles {
+                event.phase = EventPhase::Bubbling;
+            } else {
+                continue;
             }
+            invoke(node, event, false);
         }
 
-        // Handle 'once' listeners
-        if let Some(node_listeners) = self.listeners.get_mut(&node) {
-            if let Some(type_listeners) = node_listeners.get_mut(&event_type) {
-                type_listeners.retain(|l| !l.options.once);
+        event.phase = EventPhase::None;
+        event.current_target = None;
+        event.target = self.entries.last().map(|&(_, target)| target);
+        event.propagation_stopped = false;
+        event.immediate_propagation_stopped = false;
+        !event.default_prevented
+    }
+}
+
+/// Propagation path of an event dispatched at `target`, target first.
+///
+/// Slotted nodes propagate through their assigned slot, and shadow roots
+/// to their host; an event that is not composed stops at the shadow root
+/// of the tree it was dispatched in.
+pub fn event_path(tree: &DomTree, target: NodeId, composed: bool) -> Vec<NodeId> {
+    let target_root = tree.tree_root(target);
+    let mut path = vec![target];
+    let mut node = target;
+    loop {
+        let parent = if let Some(slot) = tree.assigned_slot(node) {
+            Some(slot)
+        } else if let Some(host) = tree.shadow_host(node) {
+            (composed || node != target_root).then_some(host)
+        } else {
+            tree.parent(node)
+        };
+        match parent {
+            Some(parent) => {
+                path.push(parent);
+                node = parent;
             }
+            None => return path,
         }
     }
+}
 
-    /// Remove all listeners for a node.
-    pub fn remove_all(&mut self, node: NodeId) {
-        self.listeners.remove(&node);
+/// Retarget `target` against `node`: the closest shadow-including
+/// ancestor of `target` that is in `node`'s tree or one of its ancestors'.
+pub fn retarget(tree: &DomTree, target: NodeId, node: NodeId) -> NodeId {
+    let mut target = target;
+    loop {
+        let root = tree.tree_root(target);
+        match tree.shadow_host(root) {
+            Some(host) if !tree.is_shadow_including_inclusive_ancestor(root, node) => target = host,
+            _ => return target,
+        }
     }
 }
 
@@ -626,4 +863,147 @@ mod tests {
             EventType::Custom("custom-event".to_string())
         );
     }
+
+    #[test]
+    fn test_listener_options_and_propagation() {
+        use crate::element::{ElementData, TagName};
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        let button = tree.create_element(ElementData::new(TagName::button()));
+        tree.append_child(doc, div);
+        tree.append_child(div, button);
+
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let listener = |name: &'static str, f: fn(&mut Event)| -> EventCallback {
+            let seen = seen.clone();
+            Arc::new(move |event: &mut Event| {
+                seen.lock().push(name);
+                f(event);
+            })
+        };
+        let options = |capture, once, passive| EventListenerOptions { capture, once, passive };
+
+        let mut events = EventManager::new();
+        events.add_listener(div, "click", listener("div-capture", |_| {}), options(true, false, false));
+        events.add_listener(button, "click", listener("once", |_| {}), options(false, true, false));
+        events.add_listener(button, "click", listener("passive", Event::prevent_default), options(false, false, true));
+        let stopper = listener("stopper", Event::stop_immediate_propagation);
+        events.add_listener(button, "click", stopper.clone(), options(false, false, false));
+        // Adding the same callback for the same phase again is a no-op.
+        events.add_listener(button, "click", stopper.clone(), options(false, false, false));
+        events.add_listener(button, "click", listener("after", |_| {}), options(false, false, false));
+        events.add_listener(div, "click", listener("div-bubble", Event::prevent_default), options(false, false, false));
+
+        let mut event = Event::new(EventType::Click);
+        assert!(events.dispatch(&tree, button, &mut event));
+        assert_eq!(*seen.lock(), vec!["div-capture", "once", "passive", "stopper"]);
+        assert!(!event.propagation_stopped);
+
+        seen.lock().clear();
+        assert!(events.dispatch(&tree, button, &mut Event::new(EventType::Click)));
+        assert_eq!(*seen.lock(), vec!["div-capture", "passive", "stopper"]);
+
+        seen.lock().clear();
+        events.remove_listener(button, "click", &stopper, false);
+        assert!(!events.dispatch(&tree, button, &mut Event::new(EventType::Click)));
+        assert_eq!(*seen.lock(), vec!["div-capture", "passive", "after", "div-bubble"]);
+    }
+
+    #[test]
+    fn test_once_and_removed_listeners_during_dispatch() {
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let listener = |name: &'static str, f: fn(&mut Event)| -> EventCallback {
+            let seen = seen.clone();
+            Arc::new(move |event: &mut Event| {
+                seen.lock().push(name);
+                f(event);
+            })
+        };
+        let once = EventListenerOptions { once: true, ..Default::default() };
+        let node = DomTree::new().root().unwrap();
+
+        // A `once` listener that never ran because immediate propagation
+        // was stopped before its turn stays registered.
+        let mut events = EventManager::new();
+        let stopper = listener("stopper", Event::stop_immediate_propagation);
+        events.add_listener(node, "click", stopper, Default::default());
+        events.add_listener(node, "click", listener("once", |_| {}), once);
+        let listeners = events.take_listeners(node, "click", false);
+        invoke_listeners(&listeners, &mut Event::new(EventType::Click), &mut |_, _| {});
+        assert_eq!(*seen.lock(), vec!["stopper"]);
+        assert_eq!(events.get_listeners(node, "click").len(), 2);
+
+        seen.lock().clear();
+        let listeners = events.take_listeners(node, "click", false);
+        invoke_listeners(&listeners[1..], &mut Event::new(EventType::Click), &mut |_, _| {});
+        invoke_listeners(&listeners[1..], &mut Event::new(EventType::Click), &mut |_, _| {});
+        assert_eq!(*seen.lock(), vec!["once"]);
+        assert_eq!(events.get_listeners(node, "click").len(), 1);
+
+        // A listener removed after the listeners were taken is skipped.
+        seen.lock().clear();
+        let mut events = EventManager::new();
+        let later = listener("later", |_| {});
+        events.add_listener(node, "click", listener("first", |_| {}), Default::default());
+        events.add_listener(node, "click", later.clone(), Default::default());
+        let listeners = events.take_listeners(node, "click", false);
+        events.remove_listener(node, "click", &later, false);
+        invoke_listeners(&listeners, &mut Event::new(EventType::Click), &mut |_, _| {});
+        assert_eq!(*seen.lock(), vec!["first"]);
+    }
+
+    #[test]
+    fn test_shadow_dispatch_and_retargeting() {
+        use crate::element::{ElementData, ShadowRootMode, TagName};
+        use crate::shadow::ShadowRootInit;
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let host = tree.create_element(ElementData::new(TagName::div()));
+        let light = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(doc, host);
+        tree.append_child(host, light);
+        let shadow = tree.attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Open)).unwrap();
+        let slot = tree.create_element(ElementData::new(TagName::new("slot")));
+        let inner = tree.create_element(ElementData::new(TagName::button()));
+        tree.append_child(shadow, slot);
+        tree.append_child(shadow, inner);
+
+        // Slotted nodes propagate through their slot and shadow root.
+        assert_eq!(event_path(&tree, light, false), vec![light, slot, shadow, host, doc]);
+        // Events from inside the shadow tree only escape when composed.
+        assert_eq!(event_path(&tree, inner, false), vec![inner, shadow]);
+        assert_eq!(event_path(&tree, inner, true), vec![inner, shadow, host, doc]);
+
+        let seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let mut events = EventManager::new();
+        for (node, capture) in [(doc, true), (host, false), (inner, false), (doc, false)] {
+            let seen = seen.clone();
+            events.add_listener(
+                node,
+                "click",
+                Arc::new(move |event: &mut Event| {
+                    seen.lock().push((event.current_target, event.target, event.phase))
+                }),
+                EventListenerOptions { capture, ..Default::default() },
+            );
+        }
+
+        let mut event = Event::new(EventType::Click);
+        event.composed = true;
+        assert!(events.dispatch(&tree, inner, &mut event));
+        assert_eq!(
+            *seen.lock(),
+            vec![
+                (Some(doc), Some(host), EventPhase::Capturing),
+                (Some(inner), Some(inner), EventPhase::AtTarget),
+                (Some(host), Some(host), EventPhase::AtTarget),
+                (Some(doc), Some(host), EventPhase::Bubbling),
+            ]
+        );
+        assert_eq!(event.composed_path(), vec![inner, shadow, host, doc]);
+        assert_eq!(event.target, Some(host));
+    }
 }
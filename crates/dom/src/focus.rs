@@ -0,0 +1,457 @@
+//! Focus management.
+//!
+//! A [`FocusController`] tracks the focused element of a document and
+//! mirrors it onto [`ElementFlags`] so that `:focus`, `:focus-visible` and
+//! `:focus-within` can be matched from the tree alone. Sequential (Tab)
+//! navigation follows `tabindex` order within focus navigation scopes: the
+//! document, each shadow root and each slot, walked in the flat tree.
+
+use crate::editing;
+use crate::element::{ElementData, ElementFlags};
+use crate::events::{Event, EventType};
+use crate::node::NodeId;
+use crate::tree::DomTree;
+
+/// What moved the focus, which decides whether it is focus-visible.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FocusOrigin {
+    Pointer,
+    Keyboard,
+    /// `focus()` from script.
+    Script,
+}
+
+/// Direction of sequential navigation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FocusDirection {
+    Forward,
+    Backward,
+}
+
+/// A change of the focused element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FocusChange {
+    pub blurred: Option<NodeId>,
+    pub focused: Option<NodeId>,
+}
+
+impl FocusChange {
+    /// The `blur`, `focusout`, `focus` and `focusin` events to dispatch,
+    /// in order.
+    pub fn events(&self) -> Vec<(NodeId, Event)> {
+        let event = |event_type, bubbles| {
+            let mut event = Event::with_options(event_type, bubbles, false);
+            event.composed = true;
+            event
+        };
+        let mut events = Vec::new();
+        if let Some(blurred) = self.blurred {
+            events.push((blurred, event(EventType::Blur, false)));
+            events.push((blurred, event(EventType::FocusOut, true)));
+        }
+        if let Some(focused) = self.focused {
+            events.push((focused, event(EventType::Focus, false)));
+            events.push((focused, event(EventType::FocusIn, true)));
+        }
+        events
+    }
+}
+
+/// The focused element of a document.
+#[derive(Clone, Debug, Default)]
+pub struct FocusController {
+    focused: Option<NodeId>,
+    focus_visible: bool,
+    /// Elements carrying focus flags, to clear when focus moves.
+    flagged: Vec<NodeId>,
+}
+
+impl FocusController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The focused element, if any.
+    pub fn focused(&self) -> Option<NodeId> {
+        self.focused
+    }
+
+    /// Whether the focus should be indicated (`:focus-visible`).
+    pub fn is_focus_visible(&self) -> bool {
+        self.focused.is_some() && self.focus_visible
+    }
+
+    /// Focus `node`, or its focus delegate if it is a shadow host with
+    /// `delegatesFocus`. Returns `None` if nothing can take focus or the
+    /// focus does not move.
+    pub fn focus(
+        &mut self,
+        tree: &mut DomTree,
+        node: NodeId,
+        origin: FocusOrigin,
+    ) -> Option<FocusChange> {
+        self.fixup(tree);
+        let target = match tree.shadow_root(node) {
+            Some(shadow) if shadow.delegates_focus => {
+                // Focus already inside the host's shadow tree stays put.
+                let root = shadow.root;
+                if self.focused.is_some_and(|focused| {
+                    tree.is_shadow_including_inclusive_ancestor(root, focused)
+                }) {
+                    return None;
+                }
+                focus_delegate(tree, node)?
+            }
+            _ if is_focusable_area(tree, node) => node,
+            _ => return None,
+        };
+        if self.focused == Some(target) {
+            return None;
+        }
+
+        let visible = match origin {
+            FocusOrigin::Keyboard => true,
+            FocusOrigin::Pointer => is_text_control(tree, target),
+            FocusOrigin::Script => self.is_focus_visible() || is_text_control(tree, target),
+        };
+        Some(self.set_focus(tree, Some(target), visible))
+    }
+
+    /// Remove focus from `node` if it, or the shadow tree it hosts, has it.
+    pub fn blur(&mut self, tree: &mut DomTree, node: NodeId) -> Option<FocusChange> {
+        self.fixup(tree);
+        let focused = self.focused?;
+        let inside = tree.shadow_root(node).is_some_and(|shadow| {
+            tree.is_shadow_including_inclusive_ancestor(shadow.root, focused)
+        });
+        if focused != node && !inside {
+            return None;
+        }
+        Some(self.set_focus(tree, None, false))
+    }
+
+    /// Move focus to the next or previous element in sequential navigation
+    /// order, wrapping around at the ends.
+    pub fn navigate(
+        &mut self,
+        tree: &mut DomTree,
+        direction: FocusDirection,
+    ) -> Option<FocusChange> {
+        self.fixup(tree);
+        let order = sequential_navigation_order(tree);
+        if order.is_empty() {
+            return None;
+        }
+        let current = self
+            .focused
+            .and_then(|focused| order.iter().position(|&node| node == focused));
+        let next = match (direction, current) {
+            (FocusDirection::Forward, Some(index)) => (index + 1) % order.len(),
+            (FocusDirection::Forward, None) => 0,
+            (FocusDirection::Backward, Some(index)) => (index + order.len() - 1) % order.len(),
+            (FocusDirection::Backward, None) => order.len() - 1,
+        };
+        if Some(next) == current {
+            return None;
+        }
+        Some(self.set_focus(tree, Some(order[next]), true))
+    }
+
+    /// Drop the focus, without events, if the focused element left the
+    /// document or can no longer be focused.
+    pub fn fixup(&mut self, tree: &mut DomTree) {
+        if let Some(focused) = self.focused {
+            if !is_connected(tree, focused) || !is_focusable_area(tree, focused) {
+                self.set_focus(tree, None, false);
+            }
+        }
+    }
+
+    fn set_focus(
+        &mut self,
+        tree: &mut DomTree,
+        target: Option<NodeId>,
+        visible: bool,
+    ) -> FocusChange {
+        let change = FocusChange {
+            blurred: self.focused,
+            focused: target,
+        };
+        let focus_flags =
+            ElementFlags::FOCUSED | ElementFlags::FOCUS_VISIBLE | ElementFlags::FOCUS_WITHIN;
+        for node in self.flagged.drain(..) {
+            if let Some(element) = tree.get_element_mut(node) {
+                element.flags.remove(focus_flags);
+            }
+        }
+
+        self.focused = target;
+        self.focus_visible = target.is_some() && visible;
+        let Some(target) = target else {
+            return change;
+        };
+
+        // Shadow hosts match `:focus` when focus is in their shadow tree.
+        let mut hosts = Vec::new();
+        let mut root = tree.tree_root(target);
+        while let Some(host) = tree.shadow_host(root) {
+            hosts.push(host);
+            root = tree.tree_root(host);
+        }
+        let mut current = Some(target);
+        while let Some(node) = current {
+            if let Some(element) = tree.get_element_mut(node) {
+                element.flags.insert(ElementFlags::FOCUS_WITHIN);
+                if node == target || hosts.contains(&node) {
+                    element.flags.insert(ElementFlags::FOCUSED);
+                    if self.focus_visible {
+                        element.flags.insert(ElementFlags::FOCUS_VISIBLE);
+                    }
+                }
+                self.flagged.push(node);
+            }
+            current = tree.flat_parent(node).or_else(|| tree.shadow_host(node));
+        }
+        change
+    }
+}
+
+/// Whether `node` can be focused: a natively focusable element, an
+/// editing host, or any element with a `tabindex`, that is not disabled
+/// or hidden.
+pub fn is_focusable_area(tree: &DomTree, node: NodeId) -> bool {
+    let Some(element) = tree.get_element(node) else {
+        return false;
+    };
+    let focusable = tab_index(element).is_some()
+        || natively_focusable(element)
+        || editing::is_editing_host(tree, node);
+    focusable
+        && !element.flags.contains(ElementFlags::DISABLED)
+        && !tree.ancestors(node).chain(Some(node)).any(|id| {
+            tree.get_element(id)
+                .is_some_and(|e| e.flags.contains(ElementFlags::HIDDEN))
+        })
+}
+
+/// Focusable elements reachable with Tab, in navigation order.
+///
+/// Positive `tabindex` values come first in ascending order, then the
+/// elements with `tabindex="0"` or none, in flat tree order. A negative
+/// `tabindex` removes an element, and any scope it owns, from the order.
+pub fn sequential_navigation_order(tree: &DomTree) -> Vec<NodeId> {
+    tree.root()
+        .map(|root| scope_order(tree, root))
+        .unwrap_or_default()
+}
+
+/// The parsed `tabindex` attribute.
+fn tab_index(element: &ElementData) -> Option<i32> {
+    element.get_attribute("tabindex")?.trim().parse().ok()
+}
+
+fn natively_focusable(element: &ElementData) -> bool {
+    if !element.flags.contains(ElementFlags::FOCUSABLE) {
+        return false;
+    }
+    match element.tag_name.as_str() {
+        "a" => element.has_attribute("href"),
+        "input" => !element
+            .get_attribute("type")
+            .is_some_and(|kind| kind.eq_ignore_ascii_case("hidden")),
+        _ => true,
+    }
+}
+
+/// Whether focus on `node` is always indicated, as for text entry.
+pub fn is_text_control(tree: &DomTree, node: NodeId) -> bool {
+    tree.get_element(node)
+        .is_some_and(|element| match element.tag_name.as_str() {
+            "textarea" => true,
+            "input" => !element.get_attribute("type").is_some_and(|kind| {
+                matches!(
+                    kind.to_ascii_lowercase().as_str(),
+                    "button"
+                        | "checkbox"
+                        | "radio"
+                        | "submit"
+                        | "reset"
+                        | "image"
+                        | "file"
+                        | "color"
+                        | "range"
+                )
+            }),
+            _ => false,
+        })
+}
+
+/// Whether `node` is in the document, possibly inside shadow trees.
+fn is_connected(tree: &DomTree, node: NodeId) -> bool {
+    tree.root()
+        .is_some_and(|root| tree.is_shadow_including_inclusive_ancestor(root, node))
+}
+
+/// Whether `node` owns a focus navigation scope: a shadow host or a slot.
+fn is_scope_owner(tree: &DomTree, node: NodeId) -> bool {
+    tree.shadow_root(node).is_some()
+        || (tree
+            .get_element(node)
+            .is_some_and(|element| element.tag_name == "slot")
+            && tree.containing_shadow_root(node).is_some())
+}
+
+/// Navigation order of the scope owned by `owner`.
+fn scope_order(tree: &DomTree, owner: NodeId) -> Vec<NodeId> {
+    let mut candidates = Vec::new();
+    collect_candidates(tree, owner, &mut candidates);
+
+    let mut candidates: Vec<(NodeId, i32)> = candidates
+        .into_iter()
+        .filter_map(|node| {
+            let element = tree.get_element(node)?;
+            let index = tab_index(element).unwrap_or(0);
+            (index >= 0).then_some((node, index))
+        })
+        .collect();
+    candidates.sort_by_key(|&(_, index)| if index == 0 { i32::MAX } else { index });
+
+    let mut order = Vec::new();
+    for (node, _) in candidates {
+        if is_scope_owner(tree, node) {
+            let delegates = tree
+                .shadow_root(node)
+                .is_some_and(|shadow| shadow.delegates_focus);
+            if !delegates && is_focusable_area(tree, node) {
+                order.push(node);
+            }
+            order.extend(scope_order(tree, node));
+        } else {
+            order.push(node);
+        }
+    }
+    order
+}
+
+/// Focusable areas and scope owners in the scope of `node`, in flat tree
+/// order, without descending into nested scopes.
+fn collect_candidates(tree: &DomTree, node: NodeId, candidates: &mut Vec<NodeId>) {
+    for child in tree.flat_children(node) {
+        if is_scope_owner(tree, child) {
+            candidates.push(child);
+            continue;
+        }
+        if is_focusable_area(tree, child) {
+            candidates.push(child);
+        }
+        collect_candidates(tree, child, candidates);
+    }
+}
+
+/// The element a `delegatesFocus` host passes focus to: the first in its
+/// navigation order, or else the first focusable element of its shadow
+/// tree.
+fn focus_delegate(tree: &DomTree, host: NodeId) -> Option<NodeId> {
+    let root = tree.shadow_root(host)?.root;
+    scope_order(tree, host).into_iter().next().or_else(|| {
+        tree.descendants(root)
+            .find(|&node| is_focusable_area(tree, node))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{ShadowRootMode, TagName};
+    use crate::shadow::ShadowRootInit;
+
+    fn element(
+        tree: &mut DomTree,
+        parent: NodeId,
+        tag: &str,
+        attributes: &[(&str, &str)],
+    ) -> NodeId {
+        let mut data = ElementData::new(TagName::new(tag));
+        for (name, value) in attributes {
+            data.set_attribute(name, value);
+        }
+        let node = tree.create_element(data);
+        tree.append_child(parent, node);
+        node
+    }
+
+    #[test]
+    fn test_tab_order_and_focus_flags() {
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let body = element(&mut tree, doc, "body", &[]);
+        let first = element(&mut tree, body, "input", &[]);
+        let late = element(&mut tree, body, "div", &[("tabindex", "2")]);
+        let early = element(&mut tree, body, "button", &[("tabindex", "1")]);
+        let skipped = element(&mut tree, body, "button", &[("tabindex", "-1")]);
+        element(&mut tree, body, "a", &[]);
+        element(&mut tree, body, "button", &[("disabled", "")]);
+        let host = element(&mut tree, body, "div", &[]);
+        let shadow = tree
+            .attach_shadow(
+                host,
+                ShadowRootInit::new(ShadowRootMode::Open).delegates_focus(),
+            )
+            .unwrap();
+        let inner = element(&mut tree, shadow, "button", &[]);
+        let last = element(&mut tree, body, "textarea", &[]);
+
+        assert_eq!(
+            sequential_navigation_order(&tree),
+            vec![early, late, first, inner, last]
+        );
+
+        let mut focus = FocusController::new();
+        let change = focus.navigate(&mut tree, FocusDirection::Forward).unwrap();
+        assert_eq!(
+            change,
+            FocusChange {
+                blurred: None,
+                focused: Some(early)
+            }
+        );
+        assert!(focus.is_focus_visible());
+        focus.navigate(&mut tree, FocusDirection::Backward).unwrap();
+        assert_eq!(focus.focused(), Some(last));
+
+        // Focusing the host delegates to its shadow tree.
+        let change = focus.focus(&mut tree, host, FocusOrigin::Pointer).unwrap();
+        assert_eq!(
+            change,
+            FocusChange {
+                blurred: Some(last),
+                focused: Some(inner)
+            }
+        );
+        assert!(!focus.is_focus_visible());
+        let flags = |tree: &DomTree, node| tree.get_element(node).unwrap().flags;
+        assert!(flags(&tree, inner).contains(ElementFlags::FOCUSED));
+        assert!(flags(&tree, host).contains(ElementFlags::FOCUSED | ElementFlags::FOCUS_WITHIN));
+        assert!(flags(&tree, body).contains(ElementFlags::FOCUS_WITHIN));
+        assert!(!flags(&tree, body).contains(ElementFlags::FOCUSED));
+        assert!(!flags(&tree, last).intersects(ElementFlags::FOCUSED | ElementFlags::FOCUS_WITHIN));
+        assert!(focus.focus(&mut tree, host, FocusOrigin::Script).is_none());
+
+        assert!(focus
+            .focus(&mut tree, skipped, FocusOrigin::Script)
+            .is_some());
+        assert!(focus.focus(&mut tree, body, FocusOrigin::Script).is_none());
+        let change = focus.blur(&mut tree, skipped).unwrap();
+        assert_eq!(change.events().len(), 2);
+        assert!(!flags(&tree, body).contains(ElementFlags::FOCUS_WITHIN));
+
+        // Removing the focused element drops the focus.
+        focus
+            .focus(&mut tree, first, FocusOrigin::Keyboard)
+            .unwrap();
+        tree.remove_from_parent(first);
+        focus.fixup(&mut tree);
+        assert_eq!(focus.focused(), None);
+        assert!(!flags(&tree, body).contains(ElementFlags::FOCUS_WITHIN));
+    }
+}
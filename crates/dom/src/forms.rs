@@ -0,0 +1,1500 @@
+//! HTML form controls.
+//!
+//! The state of `<input>`, `<textarea>` and `<select>` controls lives on the
+//! elements themselves: values and dirty flags in [`ElementData::control`],
+//! checkedness and option selectedness in the `CHECKED` and `SELECTED`
+//! flags. The value, validation and submission algorithms here work from
+//! the tree alone, so selector matching can use them for `:checked`,
+//! `:disabled` and `:invalid`.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::element::{ElementData, ElementFlags};
+use crate::node::NodeId;
+use crate::tree::DomTree;
+
+/// Form control state that is not reflected by attributes.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlState {
+    /// The value once set by the user or script; `None` while it follows
+    /// the default value (the dirty value flag).
+    pub value: Option<String>,
+    /// Whether checkedness, or an option's selectedness, no longer follows
+    /// the `checked` or `selected` attribute.
+    pub dirty_checkedness: bool,
+    /// Message set by `setCustomValidity()`.
+    pub custom_validity: String,
+    /// Selected part of the value of a text control, as byte offsets.
+    pub selection: (usize, usize),
+}
+
+/// The result of constraint validation (`ValidityState`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidityState {
+    pub value_missing: bool,
+    pub type_mismatch: bool,
+    pub pattern_mismatch: bool,
+    pub range_underflow: bool,
+    pub range_overflow: bool,
+    pub custom_error: bool,
+}
+
+impl ValidityState {
+    /// Whether no constraint is violated.
+    pub fn valid(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Form submission method.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormMethod {
+    #[default]
+    Get,
+    Post,
+    Dialog,
+}
+
+impl FormMethod {
+    /// Parse a `method` attribute; invalid values are `GET`.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "post" => FormMethod::Post,
+            "dialog" => FormMethod::Dialog,
+            _ => FormMethod::Get,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FormMethod::Get => "get",
+            FormMethod::Post => "post",
+            FormMethod::Dialog => "dialog",
+        }
+    }
+}
+
+/// Form data encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FormEnctype {
+    #[default]
+    UrlEncoded,
+    Multipart,
+    TextPlain,
+}
+
+impl FormEnctype {
+    /// Parse an `enctype` attribute; invalid values are urlencoded.
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "multipart/form-data" => FormEnctype::Multipart,
+            "text/plain" => FormEnctype::TextPlain,
+            _ => FormEnctype::UrlEncoded,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FormEnctype::UrlEncoded => "application/x-www-form-urlencoded",
+            FormEnctype::Multipart => "multipart/form-data",
+            FormEnctype::TextPlain => "text/plain",
+        }
+    }
+}
+
+/// A value in a form's entry list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FormValue {
+    Text(String),
+    File {
+        filename: String,
+        content_type: String,
+        data: Vec<u8>,
+    },
+}
+
+/// A name-value pair of a form's entry list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormEntry {
+    pub name: String,
+    pub value: FormValue,
+}
+
+impl FormEntry {
+    pub fn text(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: FormValue::Text(value.to_string()),
+        }
+    }
+}
+
+/// A navigation planned by submitting a form.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormSubmission {
+    pub action: Url,
+    pub method: FormMethod,
+    pub enctype: FormEnctype,
+    pub target: String,
+    pub entries: Vec<FormEntry>,
+}
+
+impl FormSubmission {
+    /// The URL to navigate to: the action, with the entries as its query
+    /// for `GET`.
+    pub fn url(&self) -> Url {
+        let mut url = self.action.clone();
+        if self.method == FormMethod::Get {
+            url.set_query(Some(&urlencode(&self.entries)));
+        }
+        url
+    }
+
+    /// The `Content-Type` and body of a `POST` submission.
+    pub fn body(&self) -> Option<(String, Vec<u8>)> {
+        if self.method != FormMethod::Post {
+            return None;
+        }
+        Some(match self.enctype {
+            FormEnctype::UrlEncoded => (
+                self.enctype.as_str().to_string(),
+                urlencode(&self.entries).into_bytes(),
+            ),
+            FormEnctype::Multipart => {
+                let boundary = multipart_boundary();
+                (
+                    format!("{}; boundary={}", self.enctype.as_str(), boundary),
+                    multipart(&self.entries, &boundary),
+                )
+            }
+            FormEnctype::TextPlain => (
+                "text/plain;charset=UTF-8".to_string(),
+                text_plain(&self.entries).into_bytes(),
+            ),
+        })
+    }
+}
+
+const INPUT_TYPES: &[&str] = &[
+    "hidden",
+    "text",
+    "search",
+    "tel",
+    "url",
+    "email",
+    "password",
+    "date",
+    "month",
+    "week",
+    "time",
+    "datetime-local",
+    "number",
+    "range",
+    "color",
+    "checkbox",
+    "radio",
+    "file",
+    "submit",
+    "image",
+    "reset",
+    "button",
+];
+
+/// The state of an `<input>`'s `type` attribute; invalid values are `text`.
+pub fn input_type(element: &ElementData) -> &'static str {
+    element
+        .get_attribute("type")
+        .and_then(|value| {
+            let value = value.trim();
+            INPUT_TYPES
+                .iter()
+                .find(|known| known.eq_ignore_ascii_case(value))
+        })
+        .copied()
+        .unwrap_or("text")
+}
+
+/// Whether the element can have a form owner and is listed in
+/// `form.elements`.
+pub fn is_listed(element: &ElementData) -> bool {
+    matches!(
+        element.tag_name.as_str(),
+        "button" | "fieldset" | "input" | "object" | "output" | "select" | "textarea"
+    )
+}
+
+/// Whether the element contributes to its form's entry list.
+pub fn is_submittable(element: &ElementData) -> bool {
+    matches!(
+        element.tag_name.as_str(),
+        "button" | "input" | "select" | "textarea"
+    )
+}
+
+/// Whether the element is a submit button.
+pub fn is_submit_button(element: &ElementData) -> bool {
+    match element.tag_name.as_str() {
+        "button" => button_type(element) == "submit",
+        "input" => matches!(input_type(element), "submit" | "image"),
+        _ => false,
+    }
+}
+
+/// The state of a `<button>`'s `type` attribute.
+pub fn button_type(element: &ElementData) -> &'static str {
+    match element
+        .get_attribute("type")
+        .map(|t| t.trim().to_ascii_lowercase())
+    {
+        Some(t) if t == "reset" => "reset",
+        Some(t) if t == "button" => "button",
+        _ => "submit",
+    }
+}
+
+fn is_text_type(input_type: &str) -> bool {
+    matches!(
+        input_type,
+        "text" | "search" | "tel" | "url" | "email" | "password"
+    )
+}
+
+fn is_readonly(element: &ElementData) -> bool {
+    element.has_attribute("readonly")
+        && match element.tag_name.as_str() {
+            "textarea" => true,
+            "input" => !matches!(
+                input_type(element),
+                "hidden"
+                    | "range"
+                    | "color"
+                    | "checkbox"
+                    | "radio"
+                    | "file"
+                    | "submit"
+                    | "image"
+                    | "reset"
+                    | "button"
+            ),
+            _ => false,
+        }
+}
+
+fn tree_root(tree: &DomTree, node: NodeId) -> NodeId {
+    tree.ancestors(node).last().unwrap_or(node)
+}
+
+fn is_element(tree: &DomTree, node: NodeId, tag: &str) -> bool {
+    tree.get_element(node)
+        .is_some_and(|e| e.tag_name.as_str() == tag)
+}
+
+/// The control's current value, as `HTMLInputElement.value` and friends
+/// return it.
+pub fn value(tree: &DomTree, node: NodeId) -> String {
+    let Some(element) = tree.get_element(node) else {
+        return String::new();
+    };
+    let attribute = |name| element.get_attribute(name).unwrap_or_default().to_string();
+    match element.tag_name.as_str() {
+        "input" => match input_type(element) {
+            "checkbox" | "radio" => element.get_attribute("value").unwrap_or("on").to_string(),
+            "file" => String::new(),
+            "hidden" | "submit" | "image" | "reset" | "button" => attribute("value"),
+            ty => match &element.control.value {
+                Some(value) => value.clone(),
+                None => sanitize(
+                    element,
+                    ty,
+                    element.get_attribute("value").unwrap_or_default(),
+                ),
+            },
+        },
+        "textarea" => match &element.control.value {
+            Some(value) => value.clone(),
+            None => tree.get_text_content(node).replace("\r\n", "\n"),
+        },
+        "select" => selected_options(tree, node)
+            .first()
+            .map(|&option| value(tree, option))
+            .unwrap_or_default(),
+        "option" => match element.get_attribute("value") {
+            Some(value) => value.to_string(),
+            None => collapse_whitespace(&tree.get_text_content(node)),
+        },
+        "button" | "data" | "li" | "param" => attribute("value"),
+        "output" => tree.get_text_content(node),
+        _ => String::new(),
+    }
+}
+
+/// Set the control's value, as the `value` setters do.
+pub fn set_value(tree: &mut DomTree, node: NodeId, value: &str) {
+    let Some(element) = tree.get_element(node) else {
+        return;
+    };
+    match element.tag_name.as_str() {
+        "input" => match input_type(element) {
+            "file" => {}
+            "checkbox" | "radio" | "hidden" | "submit" | "image" | "reset" | "button" => {
+                tree.set_attribute(node, "value", value);
+            }
+            ty => {
+                let value = sanitize(element, ty, value);
+                if let Some(element) = tree.get_element_mut(node) {
+                    element.control.selection = (value.len(), value.len());
+                    element.control.value = Some(value);
+                }
+            }
+        },
+        "textarea" => {
+            if let Some(element) = tree.get_element_mut(node) {
+                let value = value.replace("\r\n", "\n").replace('\r', "\n");
+                element.control.selection = (value.len(), value.len());
+                element.control.value = Some(value);
+            }
+        }
+        "select" => {
+            let options = options(tree, node);
+            let mut found = false;
+            for option in options {
+                let selected = !found && self::value(tree, option) == value;
+                found |= selected;
+                set_selectedness(tree, option, selected);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The selection in a text control's value, clamped to the value and to
+/// character boundaries (`selectionStart`, `selectionEnd`).
+pub fn selection_range(tree: &DomTree, node: NodeId) -> (usize, usize) {
+    let value = value(tree, node);
+    let (start, end) = tree
+        .get_element(node)
+        .map_or((0, 0), |e| e.control.selection);
+    let clamp = |offset: usize| {
+        let mut offset = offset.min(value.len());
+        while !value.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    };
+    let end = clamp(end);
+    (clamp(start).min(end), end)
+}
+
+/// Select part of a text control's value (`setSelectionRange`). A start
+/// after the end collapses to the end.
+pub fn set_selection_range(tree: &mut DomTree, node: NodeId, start: usize, end: usize) {
+    if let Some(element) = tree.get_element_mut(node) {
+        element.control.selection = (start.min(end), end);
+    }
+}
+
+/// The value sanitization algorithm of an input type.
+fn sanitize(element: &ElementData, input_type: &str, value: &str) -> String {
+    let strip_newlines = || value.replace(['\n', '\r'], "");
+    match input_type {
+        "url" => strip_newlines().trim().to_string(),
+        "email" if element.has_attribute("multiple") => value
+            .split(',')
+            .map(|v| v.trim())
+            .collect::<Vec<_>>()
+            .join(","),
+        "email" => strip_newlines().trim().to_string(),
+        "number" => match parse_number(value) {
+            Some(_) => value.to_string(),
+            None => String::new(),
+        },
+        "range" => {
+            let min = element
+                .get_attribute("min")
+                .and_then(parse_number)
+                .unwrap_or(0.0);
+            let max = element
+                .get_attribute("max")
+                .and_then(parse_number)
+                .unwrap_or(100.0)
+                .max(min);
+            let number = parse_number(value).unwrap_or(min + (max - min) / 2.0);
+            format!("{}", number.clamp(min, max))
+        }
+        "color" => {
+            let is_color = value.len() == 7
+                && value.starts_with('#')
+                && value[1..].bytes().all(|b| b.is_ascii_hexdigit());
+            if is_color {
+                value.to_ascii_lowercase()
+            } else {
+                "#000000".to_string()
+            }
+        }
+        "date" | "month" | "week" | "time" | "datetime-local" => {
+            match parse_ordinal(input_type, value) {
+                Some(_) => value.to_string(),
+                None => String::new(),
+            }
+        }
+        _ => strip_newlines(),
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_ascii_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parse a valid floating-point number.
+fn parse_number(value: &str) -> Option<f64> {
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let unsigned = value.strip_prefix('-').unwrap_or(value);
+    let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+        Some(i) => (&unsigned[..i], Some(&unsigned[i + 1..])),
+        None => (unsigned, None),
+    };
+    let valid_mantissa = match mantissa.split_once('.') {
+        Some((int, frac)) => (int.is_empty() || digits(int)) && digits(frac),
+        None => digits(mantissa),
+    };
+    let valid_exponent = exponent.map_or(true, |e| digits(e.strip_prefix(['+', '-']).unwrap_or(e)));
+    if !valid_mantissa || !valid_exponent {
+        return None;
+    }
+    value.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Parse a value of a numeric or date/time input type into a number that
+/// orders like the value.
+fn parse_ordinal(input_type: &str, value: &str) -> Option<f64> {
+    match input_type {
+        "number" | "range" => parse_number(value),
+        "date" => parse_date(value).map(|(y, m, d)| ((y * 100 + m) * 100 + d) as f64),
+        "month" => parse_month(value).map(|(y, m)| (y * 100 + m) as f64),
+        "week" => {
+            let (year, week) = value.split_once("-W")?;
+            let year = fixed_digits(year, None).filter(|&y| y > 0)?;
+            let week = fixed_digits(week, Some(2)).filter(|w| (1..=53).contains(w))?;
+            Some((year * 100 + week) as f64)
+        }
+        "time" => parse_time(value),
+        "datetime-local" => {
+            let (date, time) = value.split_once(['T', ' '])?;
+            let (y, m, d) = parse_date(date)?;
+            Some(((y * 100 + m) * 100 + d) as f64 * 1e8 + parse_time(time)?)
+        }
+        _ => None,
+    }
+}
+
+fn fixed_digits(value: &str, len: Option<usize>) -> Option<i64> {
+    let valid = match len {
+        Some(len) => value.len() == len,
+        None => value.len() >= 4,
+    };
+    if !valid || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn parse_month(value: &str) -> Option<(i64, i64)> {
+    let (year, month) = value.rsplit_once('-')?;
+    let year = fixed_digits(year, None).filter(|&y| y > 0)?;
+    let month = fixed_digits(month, Some(2)).filter(|m| (1..=12).contains(m))?;
+    Some((year, month))
+}
+
+fn parse_date(value: &str) -> Option<(i64, i64, i64)> {
+    let (month, day) = value.rsplit_once('-')?;
+    let (year, month) = parse_month(month)?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    let day = fixed_digits(day, Some(2)).filter(|d| (1..=days).contains(d))?;
+    Some((year, month, day))
+}
+
+/// Parse `HH:MM[:SS[.sss]]` into milliseconds.
+fn parse_time(value: &str) -> Option<f64> {
+    let mut parts = value.splitn(3, ':');
+    let hours = fixed_digits(parts.next()?, Some(2)).filter(|h| *h < 24)?;
+    let minutes = fixed_digits(parts.next()?, Some(2)).filter(|m| *m < 60)?;
+    let seconds = match parts.next() {
+        Some(seconds) => {
+            let (whole, frac) = seconds.split_once('.').unwrap_or((seconds, ""));
+            let whole = fixed_digits(whole, Some(2)).filter(|s| *s < 60)?;
+            if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            whole as f64 + format!("0.{}", frac).parse::<f64>().unwrap_or(0.0)
+        }
+        None => 0.0,
+    };
+    Some(((hours * 60 + minutes) * 60) as f64 * 1000.0 + seconds * 1000.0)
+}
+
+/// Whether `value` is a valid email address.
+fn is_valid_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    let local_ok = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c));
+    let label_ok = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    local_ok && domain.split('.').all(label_ok)
+}
+
+/// Whether the whole of `value` matches a `pattern` attribute. Invalid
+/// patterns are ignored.
+fn matches_pattern(pattern: &str, value: &str) -> bool {
+    /// Compiled patterns, `None` where invalid; cleared when full.
+    static PATTERNS: Lazy<Mutex<HashMap<String, Option<regress::Regex>>>> =
+        Lazy::new(|| Mutex::new(HashMap::new()));
+    const MAX_PATTERNS: usize = 64;
+
+    let mut patterns = PATTERNS.lock();
+    if !patterns.contains_key(pattern) {
+        if patterns.len() >= MAX_PATTERNS {
+            patterns.clear();
+        }
+        let regex = regress::Regex::with_flags(&format!("^(?:{})$", pattern), "v").ok();
+        patterns.insert(pattern.to_string(), regex);
+    }
+    match &patterns[pattern] {
+        Some(regex) => regex.find(value).is_some(),
+        None => true,
+    }
+}
+
+/// Whether the element matches `:checked`: a checked checkbox or radio
+/// button, or a selected option.
+pub fn is_checked(tree: &DomTree, node: NodeId) -> bool {
+    let Some(element) = tree.get_element(node) else {
+        return false;
+    };
+    match element.tag_name.as_str() {
+        "input" => {
+            matches!(input_type(element), "checkbox" | "radio")
+                && element.flags.contains(ElementFlags::CHECKED)
+        }
+        "option" => match owning_select(tree, node) {
+            Some(select) => selected_options(tree, select).contains(&node),
+            None => element.flags.contains(ElementFlags::SELECTED),
+        },
+        _ => false,
+    }
+}
+
+/// Set the checkedness of a checkbox or radio button, as script or the
+/// user does. Checking a radio button unchecks the rest of its group.
+pub fn set_checked(tree: &mut DomTree, node: NodeId, checked: bool) {
+    let is_radio = tree
+        .get_element(node)
+        .is_some_and(|e| e.tag_name.as_str() == "input" && input_type(e) == "radio");
+    if checked && is_radio {
+        for other in radio_group(tree, node) {
+            if let Some(element) = tree.get_element_mut(other) {
+                element.flags.remove(ElementFlags::CHECKED);
+            }
+        }
+    }
+    if let Some(element) = tree.get_element_mut(node) {
+        element.flags.set(ElementFlags::CHECKED, checked);
+        element.control.dirty_checkedness = true;
+    }
+}
+
+/// The radio buttons in the same group as `node`, itself included.
+pub fn radio_group(tree: &DomTree, node: NodeId) -> Vec<NodeId> {
+    let name = match tree.get_element(node).and_then(|e| e.get_attribute("name")) {
+        Some(name) if !name.is_empty() => name,
+        _ => return vec![node],
+    };
+    let owner = form_owner(tree, node);
+    tree.descendants(tree_root(tree, node))
+        .filter(|&id| {
+            tree.get_element(id).is_some_and(|e| {
+                e.tag_name.as_str() == "input"
+                    && input_type(e) == "radio"
+                    && e.get_attribute("name") == Some(name)
+            }) && form_owner(tree, id) == owner
+        })
+        .collect()
+}
+
+fn owning_select(tree: &DomTree, option: NodeId) -> Option<NodeId> {
+    let parent = tree.parent(option)?;
+    if is_element(tree, parent, "select") {
+        return Some(parent);
+    }
+    tree.parent(parent).filter(|&grandparent| {
+        is_element(tree, parent, "optgroup") && is_element(tree, grandparent, "select")
+    })
+}
+
+/// The options of a `<select>`, including those in `<optgroup>`s.
+pub fn options(tree: &DomTree, select: NodeId) -> Vec<NodeId> {
+    let mut options = Vec::new();
+    for child in tree.children(select) {
+        if is_element(tree, child, "option") {
+            options.push(child);
+        } else if is_element(tree, child, "optgroup") {
+            options.extend(
+                tree.children(child)
+                    .filter(|&c| is_element(tree, c, "option")),
+            );
+        }
+    }
+    options
+}
+
+fn is_single_select(element: &ElementData) -> bool {
+    !element.has_attribute("multiple")
+        && element
+            .get_attribute("size")
+            .and_then(|size| size.trim().parse::<u32>().ok())
+            .map_or(true, |size| size <= 1)
+}
+
+/// The selected options of a `<select>`. A single-select drop-down box
+/// without a selected option shows its first enabled one.
+pub fn selected_options(tree: &DomTree, select: NodeId) -> Vec<NodeId> {
+    let Some(element) = tree.get_element(select) else {
+        return Vec::new();
+    };
+    let options = options(tree, select);
+    let selected = |&&option: &&NodeId| {
+        tree.get_element(option)
+            .is_some_and(|e| e.flags.contains(ElementFlags::SELECTED))
+    };
+    if !is_single_select(element) {
+        return options.iter().filter(selected).copied().collect();
+    }
+    options
+        .iter()
+        .rfind(selected)
+        .or_else(|| options.iter().find(|&&option| !is_disabled(tree, option)))
+        .map(|&option| vec![option])
+        .unwrap_or_default()
+}
+
+/// The index of the first selected option (`selectedIndex`).
+pub fn selected_index(tree: &DomTree, select: NodeId) -> Option<usize> {
+    let selected = selected_options(tree, select);
+    options(tree, select)
+        .iter()
+        .position(|option| selected.contains(option))
+}
+
+/// Select the option at `index`, deselecting all others.
+pub fn set_selected_index(tree: &mut DomTree, select: NodeId, index: Option<usize>) {
+    for (i, option) in options(tree, select).into_iter().enumerate() {
+        set_selectedness(tree, option, Some(i) == index);
+    }
+}
+
+/// Set an option's selectedness (`option.selected`). Selecting an option
+/// of a single-select deselects the others.
+pub fn set_selected(tree: &mut DomTree, option: NodeId, selected: bool) {
+    let single = owning_select(tree, option)
+        .filter(|&select| tree.get_element(select).is_some_and(is_single_select));
+    if let (true, Some(select)) = (selected, single) {
+        for other in options(tree, select) {
+            set_selectedness(tree, other, false);
+        }
+    }
+    set_selectedness(tree, option, selected);
+}
+
+fn set_selectedness(tree: &mut DomTree, option: NodeId, selected: bool) {
+    if let Some(element) = tree.get_element_mut(option) {
+        element.flags.set(ElementFlags::SELECTED, selected);
+        element.control.dirty_checkedness = true;
+    }
+}
+
+/// Whether the element is disabled, by its own `disabled` attribute or by
+/// a disabled `<fieldset>` or `<optgroup>` around it.
+pub fn is_disabled(tree: &DomTree, node: NodeId) -> bool {
+    let Some(element) = tree.get_element(node) else {
+        return false;
+    };
+    let own = element.flags.contains(ElementFlags::DISABLED);
+    match element.tag_name.as_str() {
+        "button" | "input" | "select" | "textarea" | "fieldset" => {
+            own || tree.ancestors(node).any(|ancestor| {
+                let disabled_fieldset = tree.get_element(ancestor).is_some_and(|e| {
+                    e.tag_name.as_str() == "fieldset" && e.flags.contains(ElementFlags::DISABLED)
+                });
+                // The first legend of a disabled fieldset stays enabled.
+                disabled_fieldset
+                    && !tree
+                        .children(ancestor)
+                        .find(|&child| tree.get_element(child).is_some())
+                        .filter(|&legend| is_element(tree, legend, "legend"))
+                        .is_some_and(|legend| tree.is_inclusive_ancestor(legend, node))
+            })
+        }
+        "option" => {
+            own || tree.parent(node).is_some_and(|parent| {
+                tree.get_element(parent).is_some_and(|e| {
+                    e.tag_name.as_str() == "optgroup" && e.flags.contains(ElementFlags::DISABLED)
+                })
+            })
+        }
+        "optgroup" => own,
+        _ => false,
+    }
+}
+
+/// Whether `:enabled` or `:disabled` applies to the element.
+pub fn can_be_disabled(element: &ElementData) -> bool {
+    matches!(
+        element.tag_name.as_str(),
+        "button" | "input" | "select" | "textarea" | "optgroup" | "option" | "fieldset"
+    )
+}
+
+/// Whether the element matches `:required`.
+pub fn is_required(element: &ElementData) -> bool {
+    element.has_attribute("required")
+        && match element.tag_name.as_str() {
+            "select" | "textarea" => true,
+            "input" => !matches!(
+                input_type(element),
+                "hidden" | "range" | "color" | "submit" | "image" | "reset" | "button"
+            ),
+            _ => false,
+        }
+}
+
+/// The form owner of a listed element: the form named by its `form`
+/// attribute, else its nearest ancestor `<form>`.
+pub fn form_owner(tree: &DomTree, node: NodeId) -> Option<NodeId> {
+    let element = tree.get_element(node)?;
+    if !is_listed(element) {
+        return None;
+    }
+    if let Some(id) = element.get_attribute("form") {
+        return tree
+            .find_element_by_id(id)
+            .filter(|&form| is_element(tree, form, "form"));
+    }
+    tree.ancestors(node)
+        .find(|&ancestor| is_element(tree, ancestor, "form"))
+}
+
+/// The listed elements owned by `form`, in tree order.
+fn owned_elements(tree: &DomTree, form: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+    tree.descendants(tree_root(tree, form)).filter(move |&id| {
+        tree.get_element(id).is_some_and(is_listed) && form_owner(tree, id) == Some(form)
+    })
+}
+
+/// The elements of `form.elements`: the listed elements it owns, except
+/// image buttons.
+pub fn form_elements(tree: &DomTree, form: NodeId) -> Vec<NodeId> {
+    owned_elements(tree, form)
+        .filter(|&id| {
+            tree.get_element(id)
+                .is_some_and(|e| !(e.tag_name.as_str() == "input" && input_type(e) == "image"))
+        })
+        .collect()
+}
+
+/// Whether the element is a candidate for constraint validation
+/// (`willValidate`).
+pub fn will_validate(tree: &DomTree, node: NodeId) -> bool {
+    let Some(element) = tree.get_element(node) else {
+        return false;
+    };
+    let barred = match element.tag_name.as_str() {
+        "input" => {
+            matches!(input_type(element), "hidden" | "reset" | "button") || is_readonly(element)
+        }
+        "button" => !is_submit_button(element),
+        "select" => false,
+        "textarea" => is_readonly(element),
+        _ => return false,
+    };
+    !barred
+        && !is_disabled(tree, node)
+        && !tree
+            .ancestors(node)
+            .any(|ancestor| is_element(tree, ancestor, "datalist"))
+}
+
+/// Run the constraints of a control against its current state.
+pub fn validity(tree: &DomTree, node: NodeId) -> ValidityState {
+    let mut state = ValidityState::default();
+    let Some(element) = tree.get_element(node) else {
+        return state;
+    };
+    state.custom_error = !element.control.custom_validity.is_empty();
+    let required = is_required(element);
+    match element.tag_name.as_str() {
+        "input" => match input_type(element) {
+            "checkbox" => {
+                state.value_missing = required && !element.flags.contains(ElementFlags::CHECKED);
+            }
+            "radio" => {
+                let group = radio_group(tree, node);
+                let any = |flag: &dyn Fn(&ElementData) -> bool| {
+                    group
+                        .iter()
+                        .any(|&id| tree.get_element(id).is_some_and(flag))
+                };
+                state.value_missing =
+                    any(&is_required) && !any(&|e| e.flags.contains(ElementFlags::CHECKED));
+            }
+            // No file selection is kept yet.
+            "file" => state.value_missing = required,
+            ty => {
+                let value = value(tree, node);
+                state.value_missing = required && value.is_empty();
+                if value.is_empty() {
+                    return state;
+                }
+                let values: Vec<&str> = if ty == "email" && element.has_attribute("multiple") {
+                    value.split(',').collect()
+                } else {
+                    vec![&value]
+                };
+                state.type_mismatch = match ty {
+                    "email" => !values.iter().all(|v| is_valid_email(v)),
+                    "url" => Url::parse(&value).is_err(),
+                    _ => false,
+                };
+                if let Some(pattern) = element
+                    .get_attribute("pattern")
+                    .filter(|_| is_text_type(ty))
+                {
+                    state.pattern_mismatch = !values.iter().all(|v| matches_pattern(pattern, v));
+                }
+                if let Some(number) = parse_ordinal(ty, &value) {
+                    let bound = |name| {
+                        element
+                            .get_attribute(name)
+                            .and_then(|v| parse_ordinal(ty, v))
+                    };
+                    state.range_underflow = bound("min").is_some_and(|min| number < min);
+                    state.range_overflow = bound("max").is_some_and(|max| number > max);
+                }
+            }
+        },
+        "textarea" => state.value_missing = required && value(tree, node).is_empty(),
+        "select" => {
+            let selected = selected_options(tree, node);
+            // A leading empty option of a single-select is a placeholder.
+            let placeholder = options(tree, node)
+                .first()
+                .copied()
+                .filter(|&first| tree.parent(first) == Some(node) && value(tree, first).is_empty());
+            let single = is_single_select(element);
+            state.value_missing = required
+                && (selected.is_empty()
+                    || (single && placeholder.is_some_and(|p| selected == [p])));
+        }
+        _ => {}
+    }
+    state
+}
+
+/// Whether the element satisfies its constraints, or is not validated at
+/// all (`checkValidity()` without the event).
+pub fn check_validity(tree: &DomTree, node: NodeId) -> bool {
+    !will_validate(tree, node) || validity(tree, node).valid()
+}
+
+/// Whether the element matches `:valid` (`Some(true)`) or `:invalid`
+/// (`Some(false)`). Forms and fieldsets are invalid if a control in them is.
+pub fn validity_match(tree: &DomTree, node: NodeId) -> Option<bool> {
+    let element = tree.get_element(node)?;
+    match element.tag_name.as_str() {
+        "form" => Some(owned_elements(tree, node).all(|id| check_validity(tree, id))),
+        "fieldset" => Some(tree.descendants(node).all(|id| check_validity(tree, id))),
+        _ if will_validate(tree, node) => Some(validity(tree, node).valid()),
+        _ => None,
+    }
+}
+
+/// The controls of `form` that fail their constraints, in tree order.
+pub fn invalid_controls(tree: &DomTree, form: NodeId) -> Vec<NodeId> {
+    owned_elements(tree, form)
+        .filter(|&id| !check_validity(tree, id))
+        .collect()
+}
+
+/// Reset the controls of `form` to their default values and checkedness.
+pub fn reset(tree: &mut DomTree, form: NodeId) {
+    let controls: Vec<NodeId> = owned_elements(tree, form).collect();
+    for node in controls {
+        let options = options(tree, node);
+        if let Some(element) = tree.get_element_mut(node) {
+            element.control.value = None;
+            element.control.dirty_checkedness = false;
+            let checked = element.has_attribute("checked");
+            if element.tag_name.as_str() == "input" {
+                element.flags.set(ElementFlags::CHECKED, checked);
+            }
+        }
+        for option in options {
+            if let Some(element) = tree.get_element_mut(option) {
+                let selected = element.has_attribute("selected");
+                element.flags.set(ElementFlags::SELECTED, selected);
+                element.control.dirty_checkedness = false;
+            }
+        }
+    }
+}
+
+/// Construct the entry list of `form` as submitted by `submitter`.
+pub fn entry_list(tree: &DomTree, form: NodeId, submitter: Option<NodeId>) -> Vec<FormEntry> {
+    let mut entries = Vec::new();
+    for node in owned_elements(tree, form) {
+        let Some(element) = tree.get_element(node) else {
+            continue;
+        };
+        if !is_submittable(element)
+            || is_disabled(tree, node)
+            || tree
+                .ancestors(node)
+                .any(|ancestor| is_element(tree, ancestor, "datalist"))
+            || (is_submit_button(element) && Some(node) != submitter)
+        {
+            continue;
+        }
+        let tag = element.tag_name.as_str();
+        if tag == "button" && button_type(element) != "submit" {
+            continue;
+        }
+        let ty = if tag == "input" {
+            input_type(element)
+        } else {
+            ""
+        };
+        if matches!(ty, "checkbox" | "radio") && !element.flags.contains(ElementFlags::CHECKED) {
+            continue;
+        }
+        if matches!(ty, "reset" | "button") {
+            continue;
+        }
+        let name = element.get_attribute("name").unwrap_or_default();
+        if ty == "image" {
+            let prefix = if name.is_empty() {
+                String::new()
+            } else {
+                format!("{}.", name)
+            };
+            entries.push(FormEntry::text(&format!("{}x", prefix), "0"));
+            entries.push(FormEntry::text(&format!("{}y", prefix), "0"));
+            continue;
+        }
+        if name.is_empty() {
+            continue;
+        }
+        match (tag, ty) {
+            ("select", _) => {
+                for option in selected_options(tree, node) {
+                    if !is_disabled(tree, option) {
+                        entries.push(FormEntry::text(name, &value(tree, option)));
+                    }
+                }
+            }
+            (_, "file") => entries.push(FormEntry {
+                name: name.to_string(),
+                value: FormValue::File {
+                    filename: String::new(),
+                    content_type: "application/octet-stream".to_string(),
+                    data: Vec::new(),
+                },
+            }),
+            (_, "hidden") if name.eq_ignore_ascii_case("_charset_") => {
+                entries.push(FormEntry::text(name, "UTF-8"));
+            }
+            _ => {
+                entries.push(FormEntry::text(name, &value(tree, node)));
+                if let Some(dirname) = element.get_attribute("dirname").filter(|d| !d.is_empty()) {
+                    if tag == "textarea" || matches!(ty, "text" | "search") {
+                        entries.push(FormEntry::text(dirname, "ltr"));
+                    }
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// Whether submitting `form` by `submitter` skips interactive validation
+/// (`novalidate` or `formnovalidate`).
+pub fn novalidate(tree: &DomTree, form: NodeId, submitter: Option<NodeId>) -> bool {
+    submitter
+        .and_then(|s| tree.get_element(s))
+        .is_some_and(|e| is_submit_button(e) && e.has_attribute("formnovalidate"))
+        || tree
+            .get_element(form)
+            .is_some_and(|e| e.has_attribute("novalidate"))
+}
+
+/// Build the submission of `form` by `submitter`. An empty action submits
+/// to `document_url`; others are resolved against `base_url`.
+pub fn form_submission(
+    tree: &DomTree,
+    form: NodeId,
+    submitter: Option<NodeId>,
+    document_url: &Url,
+    base_url: &Url,
+) -> Result<FormSubmission, url::ParseError> {
+    // A submit button's `formaction` and friends override the form's.
+    let attribute = |name: &str| {
+        submitter
+            .and_then(|s| tree.get_element(s))
+            .filter(|e| is_submit_button(e))
+            .and_then(|e| e.get_attribute(&format!("form{}", name)))
+            .or_else(|| tree.get_element(form).and_then(|e| e.get_attribute(name)))
+            .unwrap_or_default()
+    };
+    let action = match attribute("action").trim() {
+        "" => document_url.clone(),
+        action => base_url.join(action)?,
+    };
+    Ok(FormSubmission {
+        action,
+        method: FormMethod::parse(attribute("method")),
+        enctype: FormEnctype::parse(attribute("enctype")),
+        target: attribute("target").to_string(),
+        entries: entry_list(tree, form, submitter),
+    })
+}
+
+/// Normalize line breaks to CRLF, as entry lists are before encoding.
+fn normalize_newlines(value: &str) -> String {
+    value
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', "\r\n")
+}
+
+fn entry_value(entry: &FormEntry) -> String {
+    match &entry.value {
+        FormValue::Text(value) => normalize_newlines(value),
+        FormValue::File { filename, .. } => filename.clone(),
+    }
+}
+
+/// Serialize an entry list as `application/x-www-form-urlencoded`.
+pub fn urlencode(entries: &[FormEntry]) -> String {
+    fn serialize(value: &str, out: &mut String) {
+        for byte in value.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'*' | b'-' | b'.' | b'_' => {
+                    out.push(byte as char)
+                }
+                b' ' => out.push('+'),
+                _ => out.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+    }
+    let mut out = String::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            out.push('&');
+        }
+        serialize(&normalize_newlines(&entry.name), &mut out);
+        out.push('=');
+        serialize(&entry_value(entry), &mut out);
+    }
+    out
+}
+
+/// Serialize an entry list as `text/plain`.
+pub fn text_plain(entries: &[FormEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "{}={}\r\n",
+                normalize_newlines(&entry.name),
+                entry_value(entry)
+            )
+        })
+        .collect()
+}
+
+/// Serialize an entry list as `multipart/form-data` with `boundary`.
+pub fn multipart(entries: &[FormEntry], boundary: &str) -> Vec<u8> {
+    let escape = |value: &str| {
+        normalize_newlines(value)
+            .replace('\n', "%0A")
+            .replace('\r', "%0D")
+            .replace('"', "%22")
+    };
+    let mut body = Vec::new();
+    for entry in entries {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        let disposition = format!(
+            "Content-Disposition: form-data; name=\"{}\"",
+            escape(&entry.name)
+        );
+        match &entry.value {
+            FormValue::Text(value) => {
+                body.extend_from_slice(format!("{}\r\n\r\n", disposition).as_bytes());
+                body.extend_from_slice(normalize_newlines(value).as_bytes());
+            }
+            FormValue::File {
+                filename,
+                content_type,
+                data,
+            } => {
+                body.extend_from_slice(
+                    format!(
+                        "{}; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                        disposition,
+                        escape(filename),
+                        content_type
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(data);
+            }
+        }
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+/// A fresh `multipart/form-data` boundary: a per-call counter hashed with
+/// keys randomly chosen once per process, so boundaries are distinct within
+/// a process and unpredictable across processes.
+pub fn multipart_boundary() -> String {
+    static KEYS: Lazy<RandomState> = Lazy::new(RandomState::new);
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = KEYS.build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    format!("----OxideFormBoundary{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::TagName;
+
+    fn element(
+        tree: &mut DomTree,
+        parent: NodeId,
+        tag: &str,
+        attributes: &[(&str, &str)],
+    ) -> NodeId {
+        let mut data = ElementData::new(TagName::new(tag));
+        for (name, value) in attributes {
+            data.set_attribute(name, value);
+        }
+        let node = tree.create_element(data);
+        tree.append_child(parent, node);
+        node
+    }
+
+    #[test]
+    fn test_control_state_and_validation() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let form = element(&mut tree, root, "form", &[]);
+        let name = element(
+            &mut tree,
+            form,
+            "input",
+            &[("name", "name"), ("required", "")],
+        );
+        let email = element(
+            &mut tree,
+            form,
+            "input",
+            &[("name", "email"), ("type", "email")],
+        );
+        let age = element(
+            &mut tree,
+            form,
+            "input",
+            &[("name", "age"), ("type", "number"), ("min", "18")],
+        );
+        let code = element(
+            &mut tree,
+            form,
+            "input",
+            &[("name", "code"), ("pattern", "[A-Z]{3}")],
+        );
+        let a = element(
+            &mut tree,
+            form,
+            "input",
+            &[
+                ("type", "radio"),
+                ("name", "r"),
+                ("value", "a"),
+                ("checked", ""),
+            ],
+        );
+        let b = element(
+            &mut tree,
+            form,
+            "input",
+            &[("type", "radio"), ("name", "r"), ("value", "b")],
+        );
+
+        assert_eq!(validity_match(&tree, form), Some(false));
+        assert!(validity(&tree, name).value_missing);
+        set_value(&mut tree, name, "Ada\nLovelace");
+        assert_eq!(value(&tree, name), "AdaLovelace");
+        assert!(check_validity(&tree, name));
+
+        set_value(&mut tree, email, "not an email");
+        assert!(validity(&tree, email).type_mismatch);
+        set_value(&mut tree, email, "ada@example.com");
+        set_value(&mut tree, age, "12");
+        assert!(validity(&tree, age).range_underflow);
+        set_value(&mut tree, age, "abc");
+        assert_eq!(value(&tree, age), "");
+        set_value(&mut tree, code, "abc");
+        assert!(validity(&tree, code).pattern_mismatch);
+        set_value(&mut tree, code, "ABC");
+        assert_eq!(invalid_controls(&tree, form), Vec::<NodeId>::new());
+
+        set_checked(&mut tree, b, true);
+        assert!(!is_checked(&tree, a) && is_checked(&tree, b));
+        // Once dirty, checkedness no longer follows the attribute.
+        tree.get_element_mut(b).unwrap().remove_attribute("checked");
+        assert!(is_checked(&tree, b));
+
+        reset(&mut tree, form);
+        assert!(is_checked(&tree, a) && !is_checked(&tree, b));
+        assert_eq!(value(&tree, name), "");
+    }
+
+    #[test]
+    fn test_select_and_submission() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let form = element(
+            &mut tree,
+            root,
+            "form",
+            &[("action", "/save"), ("method", "post")],
+        );
+        let fieldset = element(&mut tree, form, "fieldset", &[("disabled", "")]);
+        let skipped = element(&mut tree, fieldset, "input", &[("name", "skipped")]);
+        let select = element(&mut tree, form, "select", &[("name", "color")]);
+        let red = element(&mut tree, select, "option", &[]);
+        let text = tree.create_text("Red".to_string());
+        tree.append_child(red, text);
+        element(&mut tree, select, "option", &[("value", "blue")]);
+        let notes = element(&mut tree, form, "textarea", &[("name", "notes")]);
+        let save = element(
+            &mut tree,
+            form,
+            "button",
+            &[("name", "op"), ("value", "save"), ("formmethod", "get")],
+        );
+        element(
+            &mut tree,
+            form,
+            "button",
+            &[("name", "op"), ("value", "delete")],
+        );
+
+        assert!(is_disabled(&tree, skipped));
+        assert_eq!(selected_index(&tree, select), Some(0));
+        assert!(is_checked(&tree, red));
+        set_value(&mut tree, select, "blue");
+        assert_eq!(selected_index(&tree, select), Some(1));
+        set_value(&mut tree, notes, "a b\nc&d");
+
+        let url = Url::parse("https://example.com/form").unwrap();
+        let submission = form_submission(&tree, form, None, &url, &url).unwrap();
+        assert_eq!(submission.action.as_str(), "https://example.com/save");
+        let (content_type, body) = submission.body().unwrap();
+        assert_eq!(content_type, "application/x-www-form-urlencoded");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "color=blue&notes=a+b%0D%0Ac%26d"
+        );
+
+        let submission = form_submission(&tree, form, Some(save), &url, &url).unwrap();
+        assert_eq!(submission.method, FormMethod::Get);
+        assert!(submission.body().is_none());
+        assert_eq!(
+            submission.url().as_str(),
+            "https://example.com/save?color=blue&notes=a+b%0D%0Ac%26d&op=save"
+        );
+
+        let body = multipart(&submission.entries[..1], "XYZ");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--XYZ\r\nContent-Disposition: form-data; name=\"color\"\r\n\r\nblue\r\n--XYZ--\r\n"
+        );
+        assert_eq!(text_plain(&submission.entries[2..]), "op=save\r\n");
+    }
+
+    #[test]
+    fn test_encoders_escape_names_and_values() {
+        let file = FormEntry {
+            name: "up\"load\n".to_string(),
+            value: FormValue::File {
+                filename: "a\"b\r.txt".to_string(),
+                content_type: "text/plain".to_string(),
+                data: b"x\ny".to_vec(),
+            },
+        };
+        let entries = [
+            FormEntry::text("a b", "x\ry\nz\r\n"),
+            FormEntry::text("\u{e9}&=", "*-._~+"),
+            file,
+        ];
+
+        assert_eq!(
+            urlencode(&entries),
+            "a+b=x%0D%0Ay%0D%0Az%0D%0A&%C3%A9%26%3D=*-._%7E%2B&up%22load%0D%0A=a%22b%0D.txt"
+        );
+        assert_eq!(text_plain(&entries[..1]), "a b=x\r\ny\r\nz\r\n\r\n");
+        assert_eq!(
+            String::from_utf8(multipart(&entries[..1], "B")).unwrap(),
+            "--B\r\nContent-Disposition: form-data; name=\"a b\"\r\n\r\nx\r\ny\r\nz\r\n\r\n--B--\r\n"
+        );
+        // Names and filenames are escaped; file contents are sent as is.
+        assert_eq!(
+            String::from_utf8(multipart(&entries[2..], "B")).unwrap(),
+            "--B\r\nContent-Disposition: form-data; name=\"up%22load%0D%0A\"; \
+             filename=\"a%22b%0D%0A.txt\"\r\nContent-Type: text/plain\r\n\r\nx\ny\r\n--B--\r\n"
+        );
+        assert_ne!(multipart_boundary(), multipart_boundary());
+    }
+
+    #[test]
+    fn test_email_and_pattern_matching() {
+        for valid in [
+            "a@b",
+            "first.last+tag@example-site.co.uk",
+            "!#$%&'*/=?^_`{|}~-@x",
+        ] {
+            assert!(is_valid_email(valid), "{}", valid);
+        }
+        for invalid in [
+            "", "a", "@b", "a@", "a b@c", "a@b..c", "a@-b.c", "a@b-.c", "a@b_c", "a@b@c",
+        ] {
+            assert!(!is_valid_email(invalid), "{}", invalid);
+        }
+
+        // Patterns must match the whole value, alternatives included.
+        assert!(matches_pattern("[a-z]+", "abc"));
+        assert!(!matches_pattern("[a-z]+", "abc1"));
+        assert!(!matches_pattern("[a-z]", "abc"));
+        assert!(matches_pattern("a|b", "b"));
+        assert!(!matches_pattern("a|b", "ab"));
+        // Invalid patterns are ignored, also once cached.
+        assert!(matches_pattern("(", "anything"));
+        assert!(matches_pattern("(", "anything"));
+        assert!(matches_pattern("[\\d-a]", "x"));
+    }
+
+    #[test]
+    fn test_validity_flags() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let form = element(&mut tree, root, "form", &[]);
+        let control = |tree: &mut DomTree, attributes: &[(&str, &str)], value: &str| {
+            let node = element(tree, form, "input", attributes);
+            set_value(tree, node, value);
+            validity(tree, node)
+        };
+        let flags = |missing, mismatch, pattern, under, over, custom| ValidityState {
+            value_missing: missing,
+            type_mismatch: mismatch,
+            pattern_mismatch: pattern,
+            range_underflow: under,
+            range_overflow: over,
+            custom_error: custom,
+        };
+
+        let state = control(&mut tree, &[("required", "")], "");
+        assert_eq!(state, flags(true, false, false, false, false, false));
+        let state = control(&mut tree, &[("type", "url")], "not a url");
+        assert_eq!(state, flags(false, true, false, false, false, false));
+        let state = control(
+            &mut tree,
+            &[("type", "email"), ("multiple", ""), ("pattern", "[a-z]@x")],
+            "a@x,b@x",
+        );
+        assert!(state.valid());
+        let state = control(
+            &mut tree,
+            &[("type", "email"), ("multiple", ""), ("pattern", "[a-z]@x")],
+            "a@x,bb@x",
+        );
+        assert_eq!(state, flags(false, false, true, false, false, false));
+        let state = control(&mut tree, &[("pattern", "(")], "ignored");
+        assert!(state.valid());
+        let state = control(&mut tree, &[("type", "number"), ("min", "1")], "0.5");
+        assert_eq!(state, flags(false, false, false, true, false, false));
+        let state = control(
+            &mut tree,
+            &[("type", "date"), ("max", "2024-01-31")],
+            "2024-02-01",
+        );
+        assert_eq!(state, flags(false, false, false, false, true, false));
+        // Empty values only report a missing value.
+        let state = control(
+            &mut tree,
+            &[("type", "url"), ("min", "1"), ("pattern", "x")],
+            "",
+        );
+        assert!(state.valid());
+
+        let checkbox = element(
+            &mut tree,
+            form,
+            "input",
+            &[("type", "checkbox"), ("required", "")],
+        );
+        assert!(validity(&tree, checkbox).value_missing);
+        set_checked(&mut tree, checkbox, true);
+        assert!(validity(&tree, checkbox).valid());
+        tree.get_element_mut(checkbox)
+            .unwrap()
+            .control
+            .custom_validity = "No".to_string();
+        assert_eq!(
+            validity(&tree, checkbox),
+            flags(false, false, false, false, false, true)
+        );
+        assert!(!check_validity(&tree, checkbox));
+
+        let select = element(&mut tree, form, "select", &[("required", "")]);
+        let placeholder = element(&mut tree, select, "option", &[("value", "")]);
+        element(&mut tree, select, "option", &[("value", "x")]);
+        assert!(is_checked(&tree, placeholder));
+        assert!(validity(&tree, select).value_missing);
+        set_selected_index(&mut tree, select, Some(1));
+        assert!(validity(&tree, select).valid());
+    }
+}
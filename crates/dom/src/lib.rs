@@ -11,6 +11,17 @@ pub mod tree;
 pub mod events;
 pub mod attributes;
 pub mod window;
+pub mod mutation;
+pub mod shadow;
+pub mod custom_elements;
+pub mod range;
+pub mod selection;
+pub mod focus;
+pub mod forms;
+pub mod traversal;
+pub mod collection;
+pub mod editing;
+pub mod snapshot;
 
 pub use node::{Node, NodeId, NodeType, NodeData};
 pub use document::Document;
@@ -18,6 +29,20 @@ pub use element::{Element, ElementData, TagName};
 pub use text::Text;
 pub use comment::Comment;
 pub use tree::DomTree;
-pub use events::{Event, EventType, EventTarget, EventPhase};
+pub use events::{Event, EventListenerOptions, EventManager, EventPath, EventPhase, EventTarget, EventType};
 pub use attributes::{Attribute, AttributeMap};
 pub use window::Window;
+pub use mutation::{Mutation, MutationHook, MutationHookId, MutationKind};
+pub use shadow::{ShadowError, ShadowRootInit};
+pub use range::{BoundaryPoint, LiveRanges, Range, RangeError};
+pub use selection::{Selection, SelectionDirection};
+pub use focus::{FocusChange, FocusController, FocusDirection, FocusOrigin};
+pub use forms::{ControlState, FormEnctype, FormEntry, FormMethod, FormSubmission, FormValue, ValidityState};
+pub use traversal::{FilterResult, LiveNodeIterators, NodeIterator, TraversalSource, TreeWalker};
+pub use collection::{CollectionFilter, LiveCollection};
+pub use editing::{EditTarget, Editor, InputDetail, InputType};
+pub use snapshot::{DocumentSnapshot, SnapshotError, TreeSnapshot};
+pub use custom_elements::{
+    CustomElementDefinition, CustomElementError, CustomElementReaction, CustomElementReactions,
+    CustomElementRegistry,
+};
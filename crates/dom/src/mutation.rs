@@ -0,0 +1,144 @@
+//! Mutation notifications.
+//!
+//! A `DomTree` reports every change to hooks registered with
+//! [`DomTree::add_mutation_hook`], in the shape of DOM `MutationRecord`s.
+//! Mutation observers, style invalidation and similar consumers are built
+//! on top of these hooks.
+
+use crate::node::NodeId;
+use crate::tree::DomTree;
+
+/// Kind of change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutationKind {
+    /// Children were added or removed.
+    ChildList,
+    /// An attribute was set, changed or removed.
+    Attributes,
+    /// The data of a text or comment node changed.
+    CharacterData,
+}
+
+impl MutationKind {
+    /// Name used for `MutationRecord.type`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MutationKind::ChildList => "childList",
+            MutationKind::Attributes => "attributes",
+            MutationKind::CharacterData => "characterData",
+        }
+    }
+}
+
+/// A single change to a tree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mutation {
+    pub kind: MutationKind,
+    /// Parent for child list changes, otherwise the changed node.
+    pub target: NodeId,
+    pub added_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    /// Sibling before the added or removed nodes.
+    pub previous_sibling: Option<NodeId>,
+    /// Sibling after the added or removed nodes.
+    pub next_sibling: Option<NodeId>,
+    pub attribute_name: Option<String>,
+    /// Previous attribute value or character data (`None` for a new attribute).
+    pub old_value: Option<String>,
+    /// For the text node added by `DomTree::split_text`, the offset at which
+    /// `previous_sibling` was split.
+    pub split_offset: Option<usize>,
+}
+
+impl Mutation {
+    /// Children of `target` were added and/or removed between two siblings.
+    pub fn child_list(
+        target: NodeId,
+        added_nodes: Vec<NodeId>,
+        removed_nodes: Vec<NodeId>,
+        previous_sibling: Option<NodeId>,
+        next_sibling: Option<NodeId>,
+    ) -> Self {
+        Self {
+            kind: MutationKind::ChildList,
+            target,
+            added_nodes,
+            removed_nodes,
+            previous_sibling,
+            next_sibling,
+            attribute_name: None,
+            old_value: None,
+            split_offset: None,
+        }
+    }
+
+    /// `new_node` was inserted into `target` after `node`, holding the data
+    /// of `node` from `offset` on (`Text.splitText`).
+    pub fn split_text(
+        target: NodeId,
+        node: NodeId,
+        new_node: NodeId,
+        offset: usize,
+        next_sibling: Option<NodeId>,
+    ) -> Self {
+        Self {
+            split_offset: Some(offset),
+            ..Self::child_list(target, vec![new_node], Vec::new(), Some(node), next_sibling)
+        }
+    }
+
+    /// Attribute `name` of `target` changed from `old_value`.
+    pub fn attribute(target: NodeId, name: &str, old_value: Option<String>) -> Self {
+        Self {
+            kind: MutationKind::Attributes,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: Some(name.to_string()),
+            old_value,
+            split_offset: None,
+        }
+    }
+
+    /// The data of `target` changed from `old_value`.
+    pub fn character_data(target: NodeId, old_value: String) -> Self {
+        Self {
+            kind: MutationKind::CharacterData,
+            target,
+            added_nodes: Vec::new(),
+            removed_nodes: Vec::new(),
+            previous_sibling: None,
+            next_sibling: None,
+            attribute_name: None,
+            old_value: Some(old_value),
+            split_offset: None,
+        }
+    }
+}
+
+/// Receives the mutations of a tree as they happen.
+///
+/// Hooks run synchronously after each change, with the tree already
+/// updated, and must not assume any lock on the owning document is free.
+pub trait MutationHook: Send + Sync {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation);
+
+    /// `nodes` were dropped from the tree after being removed; their ids
+    /// no longer refer to anything.
+    fn dropped(&self, _tree: &DomTree, _nodes: &[NodeId]) {}
+}
+
+impl<F> MutationHook for F
+where
+    F: Fn(&DomTree, &Mutation) + Send + Sync,
+{
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        self(tree, mutation)
+    }
+}
+
+/// Handle for removing a hook again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MutationHookId(pub(crate) u64);
@@ -2,7 +2,8 @@
 
 use crate::element::ElementData;
 use parking_lot::RwLock;
-use slotmap::new_key_type;
+use serde::{Deserialize, Serialize};
+use slotmap::{new_key_type, Key, KeyData};
 use smallvec::SmallVec;
 use std::sync::Arc;
 
@@ -11,6 +12,18 @@ new_key_type! {
     pub struct NodeId;
 }
 
+impl NodeId {
+    /// Raw representation, for handing node identity across FFI boundaries.
+    pub fn to_raw(self) -> u64 {
+        self.data().as_ffi()
+    }
+
+    /// Reconstruct a node ID from its raw representation.
+    pub fn from_raw(raw: u64) -> Self {
+        KeyData::from_ffi(raw).into()
+    }
+}
+
 /// Type of DOM node.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeType {
@@ -62,7 +75,7 @@ pub enum NodeData {
 }
 
 /// Document type declaration.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct DocumentType {
     pub name: String,
     pub public_id: String,
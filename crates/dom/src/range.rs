@@ -0,0 +1,1017 @@
+//! DOM ranges.
+//!
+//! A [`Range`] is a pair of boundary points in one tree. Ranges are plain
+//! values: the owner keeps one live by feeding it the tree's mutations
+//! through [`Range::update`], as [`Selection`](crate::selection::Selection)
+//! and [`LiveRanges`] do. Offsets into text are byte offsets, like the rest
+//! of the crate.
+
+use crate::mutation::{Mutation, MutationHook, MutationKind};
+use crate::node::{NodeData, NodeId, NodeType};
+use crate::tree::DomTree;
+use parking_lot::Mutex;
+use std::cmp::Ordering;
+use std::sync::{Arc, Weak};
+use thiserror::Error;
+
+/// Errors raised by range operations, named after their DOM exceptions.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RangeError {
+    #[error("offset {0} is out of range for the node")]
+    IndexSize(usize),
+    #[error("a boundary point cannot be in a doctype or parentless node")]
+    InvalidNodeType,
+    #[error("the operation would yield an incorrect node tree")]
+    HierarchyRequest,
+    #[error("the range is in an invalid state for this operation")]
+    InvalidState,
+}
+
+/// A node and an offset into it.
+///
+/// The offset counts bytes for text, comment and processing instruction
+/// nodes and children for everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoundaryPoint {
+    pub node: NodeId,
+    pub offset: usize,
+}
+
+impl BoundaryPoint {
+    pub fn new(node: NodeId, offset: usize) -> Self {
+        Self { node, offset }
+    }
+}
+
+/// A range between two boundary points, `start` never after `end`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Range {
+    start: BoundaryPoint,
+    end: BoundaryPoint,
+}
+
+/// The first and last partially contained children of a range's common
+/// ancestor, and the fully contained ones between them.
+type SplitChildren = (Option<NodeId>, Option<NodeId>, Vec<NodeId>);
+
+impl Range {
+    /// A collapsed range at the start of `node`.
+    pub fn new(node: NodeId) -> Self {
+        let point = BoundaryPoint::new(node, 0);
+        Self {
+            start: point,
+            end: point,
+        }
+    }
+
+    pub fn start(&self) -> BoundaryPoint {
+        self.start
+    }
+
+    pub fn end(&self) -> BoundaryPoint {
+        self.end
+    }
+
+    /// Whether start and end are the same point.
+    pub fn collapsed(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Set the start, moving the end along if it would come before it.
+    pub fn set_start(
+        &mut self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<(), RangeError> {
+        let point = checked_point(tree, node, offset)?;
+        if root_of(tree, node) != root_of(tree, self.end.node)
+            || compare_points(tree, &point, &self.end) == Ordering::Greater
+        {
+            self.end = point;
+        }
+        self.start = point;
+        Ok(())
+    }
+
+    /// Set the end, moving the start along if it would come after it.
+    pub fn set_end(
+        &mut self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<(), RangeError> {
+        let point = checked_point(tree, node, offset)?;
+        if root_of(tree, node) != root_of(tree, self.start.node)
+            || compare_points(tree, &point, &self.start) == Ordering::Less
+        {
+            self.start = point;
+        }
+        self.end = point;
+        Ok(())
+    }
+
+    pub fn set_start_before(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        let (parent, index) = parent_and_index(tree, node)?;
+        self.set_start(tree, parent, index)
+    }
+
+    pub fn set_start_after(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        let (parent, index) = parent_and_index(tree, node)?;
+        self.set_start(tree, parent, index + 1)
+    }
+
+    pub fn set_end_before(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        let (parent, index) = parent_and_index(tree, node)?;
+        self.set_end(tree, parent, index)
+    }
+
+    pub fn set_end_after(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        let (parent, index) = parent_and_index(tree, node)?;
+        self.set_end(tree, parent, index + 1)
+    }
+
+    /// Collapse to the start or the end.
+    pub fn collapse(&mut self, to_start: bool) {
+        if to_start {
+            self.end = self.start;
+        } else {
+            self.start = self.end;
+        }
+    }
+
+    /// Select `node` itself within its parent.
+    pub fn select_node(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        let (parent, index) = parent_and_index(tree, node)?;
+        self.start = BoundaryPoint::new(parent, index);
+        self.end = BoundaryPoint::new(parent, index + 1);
+        Ok(())
+    }
+
+    /// Select everything inside `node`.
+    pub fn select_node_contents(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        if is_doctype(tree, node) {
+            return Err(RangeError::InvalidNodeType);
+        }
+        self.start = BoundaryPoint::new(node, 0);
+        self.end = BoundaryPoint::new(node, node_length(tree, node));
+        Ok(())
+    }
+
+    /// The deepest node containing both boundary points.
+    pub fn common_ancestor_container(&self, tree: &DomTree) -> NodeId {
+        let mut container = self.start.node;
+        while !tree.is_inclusive_ancestor(container, self.end.node) {
+            match tree.parent(container) {
+                Some(parent) => container = parent,
+                None => break,
+            }
+        }
+        container
+    }
+
+    /// Compare a boundary point of this range with one of `other`
+    /// (`compareBoundaryPoints`, with each side given as start or end).
+    pub fn compare_boundary_points(
+        &self,
+        tree: &DomTree,
+        this_start: bool,
+        other: &Range,
+        other_start: bool,
+    ) -> Result<Ordering, RangeError> {
+        if root_of(tree, self.start.node) != root_of(tree, other.start.node) {
+            return Err(RangeError::InvalidState);
+        }
+        let this_point = if this_start { &self.start } else { &self.end };
+        let other_point = if other_start {
+            &other.start
+        } else {
+            &other.end
+        };
+        Ok(compare_points(tree, this_point, other_point))
+    }
+
+    /// Where a point lies relative to the range (`comparePoint`).
+    pub fn compare_point(
+        &self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<Ordering, RangeError> {
+        if root_of(tree, node) != root_of(tree, self.start.node) {
+            return Err(RangeError::InvalidState);
+        }
+        let point = checked_point(tree, node, offset)?;
+        if compare_points(tree, &point, &self.start) == Ordering::Less {
+            Ok(Ordering::Less)
+        } else if compare_points(tree, &point, &self.end) == Ordering::Greater {
+            Ok(Ordering::Greater)
+        } else {
+            Ok(Ordering::Equal)
+        }
+    }
+
+    /// Whether a point lies within the range, ends included.
+    pub fn is_point_in_range(
+        &self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<bool, RangeError> {
+        if root_of(tree, node) != root_of(tree, self.start.node) {
+            return Ok(false);
+        }
+        self.compare_point(tree, node, offset)
+            .map(|ordering| ordering == Ordering::Equal)
+    }
+
+    /// Whether any part of `node` is within the range.
+    pub fn intersects_node(&self, tree: &DomTree, node: NodeId) -> bool {
+        if root_of(tree, node) != root_of(tree, self.start.node) {
+            return false;
+        }
+        let (parent, index) = match parent_and_index(tree, node) {
+            Ok(position) => position,
+            Err(_) => return true,
+        };
+        compare_points(tree, &BoundaryPoint::new(parent, index), &self.end) == Ordering::Less
+            && compare_points(tree, &BoundaryPoint::new(parent, index + 1), &self.start)
+                == Ordering::Greater
+    }
+
+    /// Whether all of `node` is within the range.
+    pub fn contains_node(&self, tree: &DomTree, node: NodeId) -> bool {
+        root_of(tree, node) == root_of(tree, self.start.node)
+            && compare_points(tree, &BoundaryPoint::new(node, 0), &self.start) == Ordering::Greater
+            && compare_points(
+                tree,
+                &BoundaryPoint::new(node, node_length(tree, node)),
+                &self.end,
+            ) == Ordering::Less
+    }
+
+    /// Whether `node` contains one boundary point but not the other.
+    pub fn partially_contains_node(&self, tree: &DomTree, node: NodeId) -> bool {
+        tree.is_inclusive_ancestor(node, self.start.node)
+            != tree.is_inclusive_ancestor(node, self.end.node)
+    }
+
+    /// The text in the range (`stringifier`).
+    pub fn to_string(&self, tree: &DomTree) -> String {
+        if self.start.node == self.end.node {
+            if let Some(text) = text_data(tree, self.start.node) {
+                return text
+                    .get(self.start.offset..self.end.offset)
+                    .unwrap_or_default()
+                    .to_string();
+            }
+        }
+
+        let mut result = String::new();
+        if let Some(text) = text_data(tree, self.start.node) {
+            result.push_str(text.get(self.start.offset..).unwrap_or_default());
+        }
+        let common = self.common_ancestor_container(tree);
+        for node in tree.descendants(common) {
+            if let Some(text) = text_data(tree, node) {
+                if self.contains_node(tree, node) {
+                    result.push_str(text);
+                }
+            }
+        }
+        if let Some(text) = text_data(tree, self.end.node) {
+            result.push_str(text.get(..self.end.offset).unwrap_or_default());
+        }
+        result
+    }
+
+    /// Copy the range's contents into a new document fragment.
+    pub fn clone_contents(&self, tree: &mut DomTree) -> Result<NodeId, RangeError> {
+        let fragment = tree.create_document_fragment();
+        if self.collapsed() {
+            return Ok(fragment);
+        }
+
+        let (start, end) = (self.start, self.end);
+        if start.node == end.node && is_character_data(tree, start.node) {
+            let clone = clone_data(tree, start.node, start.offset, end.offset);
+            tree.append_child(fragment, clone);
+            return Ok(fragment);
+        }
+
+        let (first, last, contained) = self.split_children(tree)?;
+
+        if let Some(first) = first {
+            if is_character_data(tree, first) {
+                let clone = clone_data(tree, first, start.offset, node_length(tree, first));
+                tree.append_child(fragment, clone);
+            } else if let Some(clone) = tree.clone_node(first, false) {
+                tree.append_child(fragment, clone);
+                let sub = Range {
+                    start,
+                    end: BoundaryPoint::new(first, node_length(tree, first)),
+                };
+                let sub_fragment = sub.clone_contents(tree)?;
+                tree.move_children(sub_fragment, clone, None);
+                tree.remove(sub_fragment);
+            }
+        }
+
+        for child in contained {
+            if let Some(clone) = tree.clone_node(child, true) {
+                tree.append_child(fragment, clone);
+            }
+        }
+
+        if let Some(last) = last {
+            if is_character_data(tree, last) {
+                let clone = clone_data(tree, last, 0, end.offset);
+                tree.append_child(fragment, clone);
+            } else if let Some(clone) = tree.clone_node(last, false) {
+                tree.append_child(fragment, clone);
+                let sub = Range {
+                    start: BoundaryPoint::new(last, 0),
+                    end,
+                };
+                let sub_fragment = sub.clone_contents(tree)?;
+                tree.move_children(sub_fragment, clone, None);
+                tree.remove(sub_fragment);
+            }
+        }
+
+        Ok(fragment)
+    }
+
+    /// Move the range's contents into a new document fragment, splitting
+    /// partially selected nodes, and collapse the range where they were.
+    pub fn extract_contents(&mut self, tree: &mut DomTree) -> Result<NodeId, RangeError> {
+        let fragment = tree.create_document_fragment();
+        if self.collapsed() {
+            return Ok(fragment);
+        }
+
+        let (start, end) = (self.start, self.end);
+        if start.node == end.node && is_character_data(tree, start.node) {
+            let clone = clone_data(tree, start.node, start.offset, end.offset);
+            tree.append_child(fragment, clone);
+            tree.replace_data(start.node, start.offset, end.offset - start.offset, "");
+            self.end = self.start;
+            return Ok(fragment);
+        }
+
+        let (first, last, contained) = self.split_children(tree)?;
+        let collapse_point = self.collapse_point(tree);
+
+        if let Some(first) = first {
+            if is_character_data(tree, first) {
+                let length = node_length(tree, first);
+                let clone = clone_data(tree, first, start.offset, length);
+                tree.append_child(fragment, clone);
+                tree.replace_data(first, start.offset, length - start.offset, "");
+            } else if let Some(clone) = tree.clone_node(first, false) {
+                tree.append_child(fragment, clone);
+                let mut sub = Range {
+                    start,
+                    end: BoundaryPoint::new(first, node_length(tree, first)),
+                };
+                let sub_fragment = sub.extract_contents(tree)?;
+                tree.move_children(sub_fragment, clone, None);
+                tree.remove(sub_fragment);
+            }
+        }
+
+        for child in contained {
+            tree.append_child(fragment, child);
+        }
+
+        if let Some(last) = last {
+            if is_character_data(tree, last) {
+                let clone = clone_data(tree, last, 0, end.offset);
+                tree.append_child(fragment, clone);
+                tree.replace_data(last, 0, end.offset, "");
+            } else if let Some(clone) = tree.clone_node(last, false) {
+                tree.append_child(fragment, clone);
+                let mut sub = Range {
+                    start: BoundaryPoint::new(last, 0),
+                    end,
+                };
+                let sub_fragment = sub.extract_contents(tree)?;
+                tree.move_children(sub_fragment, clone, None);
+                tree.remove(sub_fragment);
+            }
+        }
+
+        self.start = collapse_point;
+        self.end = collapse_point;
+        Ok(fragment)
+    }
+
+    /// Remove the range's contents from the tree and collapse the range.
+    pub fn delete_contents(&mut self, tree: &mut DomTree) {
+        if self.collapsed() {
+            return;
+        }
+
+        let (start, end) = (self.start, self.end);
+        if start.node == end.node && is_character_data(tree, start.node) {
+            tree.replace_data(start.node, start.offset, end.offset - start.offset, "");
+            self.end = self.start;
+            return;
+        }
+
+        let common = self.common_ancestor_container(tree);
+        let to_remove: Vec<NodeId> = tree
+            .descendants(common)
+            .filter(|&node| {
+                self.contains_node(tree, node)
+                    && !tree
+                        .parent(node)
+                        .is_some_and(|parent| self.contains_node(tree, parent))
+            })
+            .collect();
+        let collapse_point = self.collapse_point(tree);
+
+        if is_character_data(tree, start.node) {
+            let length = node_length(tree, start.node);
+            tree.replace_data(start.node, start.offset, length - start.offset, "");
+        }
+        for node in to_remove {
+            tree.remove(node);
+        }
+        if is_character_data(tree, end.node) {
+            tree.replace_data(end.node, 0, end.offset, "");
+        }
+
+        self.start = collapse_point;
+        self.end = collapse_point;
+    }
+
+    /// Insert `node` at the start of the range, splitting a text node if
+    /// the start is inside one.
+    pub fn insert_node(&mut self, tree: &mut DomTree, node: NodeId) -> Result<(), RangeError> {
+        let start = self.start;
+        let start_type = tree.get(start.node).map(|n| n.node_type);
+        let start_is_text = start_type == Some(NodeType::Text);
+        if matches!(
+            start_type,
+            Some(NodeType::Comment) | Some(NodeType::ProcessingInstruction)
+        ) || (start_is_text && tree.parent(start.node).is_none())
+            || start.node == node
+        {
+            return Err(RangeError::HierarchyRequest);
+        }
+
+        let reference = if start_is_text {
+            Some(start.node)
+        } else {
+            tree.children(start.node).nth(start.offset)
+        };
+        let parent = match reference {
+            Some(reference) => tree.parent(reference).ok_or(RangeError::HierarchyRequest)?,
+            None => start.node,
+        };
+        if !can_have_children(tree, parent) || tree.is_inclusive_ancestor(node, parent) {
+            return Err(RangeError::HierarchyRequest);
+        }
+
+        let was_collapsed = self.collapsed();
+        let mut reference = if start_is_text {
+            tree.split_text(start.node, start.offset)
+        } else {
+            reference
+        };
+        if reference == Some(node) {
+            reference = tree.next_sibling(node);
+        }
+        if tree.parent(node).is_some() {
+            tree.remove_from_parent(node);
+        }
+
+        let mut new_offset = match reference {
+            Some(reference) => child_index(tree, reference).unwrap_or(0),
+            None => node_length(tree, parent),
+        };
+        if tree.get(node).map(|n| n.node_type) == Some(NodeType::DocumentFragment) {
+            new_offset += node_length(tree, node);
+            tree.move_children(node, parent, reference);
+        } else {
+            new_offset += 1;
+            tree.insert_before(parent, node, reference);
+        }
+
+        if was_collapsed {
+            self.end = BoundaryPoint::new(parent, new_offset);
+        }
+        Ok(())
+    }
+
+    /// Move the range's contents into `new_parent` and put it in their place.
+    pub fn surround_contents(
+        &mut self,
+        tree: &mut DomTree,
+        new_parent: NodeId,
+    ) -> Result<(), RangeError> {
+        let common = self.common_ancestor_container(tree);
+        let partial_non_text = tree.descendants(common).any(|node| {
+            tree.get(node).map(|n| n.node_type) != Some(NodeType::Text)
+                && self.partially_contains_node(tree, node)
+        });
+        if partial_non_text {
+            return Err(RangeError::InvalidState);
+        }
+        if matches!(
+            tree.get(new_parent).map(|n| n.node_type),
+            Some(NodeType::Document)
+                | Some(NodeType::DocumentType)
+                | Some(NodeType::DocumentFragment)
+        ) {
+            return Err(RangeError::InvalidNodeType);
+        }
+
+        let fragment = self.extract_contents(tree)?;
+        tree.remove_children(new_parent);
+        self.insert_node(tree, new_parent)?;
+        tree.move_children(fragment, new_parent, None);
+        tree.remove(fragment);
+        self.select_node(tree, new_parent)
+    }
+
+    /// Keep the boundary points in place across a mutation of their tree.
+    pub fn update(&mut self, tree: &DomTree, mutation: &Mutation) {
+        match mutation.kind {
+            MutationKind::ChildList => {
+                let parent = mutation.target;
+                let index = mutation
+                    .previous_sibling
+                    .and_then(|prev| child_index(tree, prev))
+                    .map_or(0, |i| i + 1);
+                for &removed in &mutation.removed_nodes {
+                    for point in [&mut self.start, &mut self.end] {
+                        if tree.is_inclusive_ancestor(removed, point.node) {
+                            *point = BoundaryPoint::new(parent, index);
+                        } else if point.node == parent && point.offset > index {
+                            point.offset -= 1;
+                        }
+                    }
+                }
+                // Points in the tail of a split text node follow it into the
+                // new node, and points right after the split node stay after
+                // both halves.
+                let split = mutation.split_offset.zip(mutation.previous_sibling);
+                if let (Some((offset, node)), Some(&new_node)) =
+                    (split, mutation.added_nodes.first())
+                {
+                    for point in [&mut self.start, &mut self.end] {
+                        if point.node == node && point.offset > offset {
+                            *point = BoundaryPoint::new(new_node, point.offset - offset);
+                        }
+                    }
+                }
+                let added = mutation.added_nodes.len();
+                for point in [&mut self.start, &mut self.end] {
+                    if point.node == parent
+                        && (point.offset > index || (split.is_some() && point.offset == index))
+                    {
+                        point.offset += added;
+                    }
+                }
+            }
+            MutationKind::CharacterData => {
+                let node = mutation.target;
+                let (old, new) = match (&mutation.old_value, text_data(tree, node)) {
+                    (Some(old), Some(new)) => (old.as_str(), new),
+                    _ => return,
+                };
+                let (offset, removed, inserted) = changed_span(old, new);
+                for point in [&mut self.start, &mut self.end] {
+                    if point.node != node {
+                        continue;
+                    }
+                    if point.offset > offset + removed {
+                        point.offset = point.offset + inserted - removed;
+                    } else if point.offset > offset {
+                        point.offset = offset;
+                    }
+                    point.offset = point.offset.min(new.len());
+                }
+            }
+            MutationKind::Attributes => {}
+        }
+    }
+
+    /// Split the children of the common ancestor around the range.
+    fn split_children(&self, tree: &DomTree) -> Result<SplitChildren, RangeError> {
+        let common = self.common_ancestor_container(tree);
+        let children: Vec<NodeId> = tree.children(common).collect();
+        let first = if tree.is_inclusive_ancestor(self.start.node, self.end.node) {
+            None
+        } else {
+            children
+                .iter()
+                .copied()
+                .find(|&c| self.partially_contains_node(tree, c))
+        };
+        let last = if tree.is_inclusive_ancestor(self.end.node, self.start.node) {
+            None
+        } else {
+            children
+                .iter()
+                .rev()
+                .copied()
+                .find(|&c| self.partially_contains_node(tree, c))
+        };
+        let contained: Vec<NodeId> = children
+            .into_iter()
+            .filter(|&c| self.contains_node(tree, c))
+            .collect();
+        if contained.iter().any(|&c| is_doctype(tree, c)) {
+            return Err(RangeError::HierarchyRequest);
+        }
+        Ok((first, last, contained))
+    }
+
+    /// Where the range ends up after its contents are removed.
+    fn collapse_point(&self, tree: &DomTree) -> BoundaryPoint {
+        if tree.is_inclusive_ancestor(self.start.node, self.end.node) {
+            return self.start;
+        }
+        let mut reference = self.start.node;
+        while let Some(parent) = tree.parent(reference) {
+            if tree.is_inclusive_ancestor(parent, self.end.node) {
+                return BoundaryPoint::new(parent, child_index(tree, reference).unwrap_or(0) + 1);
+            }
+            reference = parent;
+        }
+        self.start
+    }
+}
+
+/// A set of ranges kept live by registering it as a mutation hook, for
+/// ranges held by scripts.
+///
+/// Ranges are dropped from the set once their last handle is. Handles must
+/// not be locked while the tree is mutated.
+#[derive(Default)]
+pub struct LiveRanges {
+    ranges: Mutex<Vec<Weak<Mutex<Range>>>>,
+}
+
+impl LiveRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start keeping `range` live.
+    pub fn track(&self, range: Range) -> Arc<Mutex<Range>> {
+        let range = Arc::new(Mutex::new(range));
+        self.ranges.lock().push(Arc::downgrade(&range));
+        range
+    }
+
+    /// Number of ranges still held.
+    pub fn len(&self) -> usize {
+        self.ranges
+            .lock()
+            .iter()
+            .filter(|range| range.strong_count() > 0)
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl MutationHook for LiveRanges {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        self.ranges.lock().retain(|range| match range.upgrade() {
+            Some(range) => {
+                range.lock().update(tree, mutation);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// Length of a node for boundary point offsets.
+pub fn node_length(tree: &DomTree, node: NodeId) -> usize {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) | Some(NodeData::Comment { content }) => content.len(),
+        Some(NodeData::ProcessingInstruction { data, .. }) => data.len(),
+        Some(NodeData::DocumentType(_)) | None => 0,
+        Some(_) => tree.children(node).count(),
+    }
+}
+
+/// Order two boundary points in the same tree.
+pub fn compare_points(tree: &DomTree, a: &BoundaryPoint, b: &BoundaryPoint) -> Ordering {
+    if a.node == b.node {
+        return a.offset.cmp(&b.offset);
+    }
+    if tree.compare_tree_order(a.node, b.node) == Ordering::Greater {
+        return compare_points(tree, b, a).reverse();
+    }
+    if tree.is_inclusive_ancestor(a.node, b.node) {
+        let mut child = b.node;
+        while let Some(parent) = tree.parent(child) {
+            if parent == a.node {
+                break;
+            }
+            child = parent;
+        }
+        if child_index(tree, child).is_some_and(|index| index < a.offset) {
+            return Ordering::Greater;
+        }
+    }
+    Ordering::Less
+}
+
+/// Position of `node` among its parent's children.
+fn child_index(tree: &DomTree, node: NodeId) -> Option<usize> {
+    let parent = tree.parent(node)?;
+    tree.children(parent).position(|child| child == node)
+}
+
+fn parent_and_index(tree: &DomTree, node: NodeId) -> Result<(NodeId, usize), RangeError> {
+    let parent = tree.parent(node).ok_or(RangeError::InvalidNodeType)?;
+    Ok((parent, child_index(tree, node).unwrap_or(0)))
+}
+
+fn checked_point(tree: &DomTree, node: NodeId, offset: usize) -> Result<BoundaryPoint, RangeError> {
+    if is_doctype(tree, node) || tree.get(node).is_none() {
+        return Err(RangeError::InvalidNodeType);
+    }
+    if offset > node_length(tree, node)
+        || text_data(tree, node).is_some_and(|text| !text.is_char_boundary(offset))
+    {
+        return Err(RangeError::IndexSize(offset));
+    }
+    Ok(BoundaryPoint::new(node, offset))
+}
+
+fn root_of(tree: &DomTree, node: NodeId) -> NodeId {
+    tree.ancestors(node).last().unwrap_or(node)
+}
+
+fn is_doctype(tree: &DomTree, node: NodeId) -> bool {
+    tree.get(node).map(|n| n.node_type) == Some(NodeType::DocumentType)
+}
+
+fn is_character_data(tree: &DomTree, node: NodeId) -> bool {
+    matches!(
+        tree.get(node).map(|n| &n.data),
+        Some(NodeData::Text { .. })
+            | Some(NodeData::Comment { .. })
+            | Some(NodeData::ProcessingInstruction { .. })
+    )
+}
+
+fn can_have_children(tree: &DomTree, node: NodeId) -> bool {
+    matches!(
+        tree.get(node).map(|n| n.node_type),
+        Some(NodeType::Element) | Some(NodeType::Document) | Some(NodeType::DocumentFragment)
+    )
+}
+
+fn text_data(tree: &DomTree, node: NodeId) -> Option<&str> {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => Some(content),
+        _ => None,
+    }
+}
+
+/// Shallow copy of a character data node keeping `start..end` of its data.
+fn clone_data(tree: &mut DomTree, node: NodeId, start: usize, end: usize) -> NodeId {
+    let clone = tree.clone_node(node, false).unwrap_or(node);
+    if let Some(n) = tree.get_mut(clone) {
+        match &mut n.data {
+            NodeData::Text { content } | NodeData::Comment { content } => {
+                *content = content.get(start..end).unwrap_or_default().to_string();
+            }
+            NodeData::ProcessingInstruction { data, .. } => {
+                *data = data.get(start..end).unwrap_or_default().to_string();
+            }
+            _ => {}
+        }
+    }
+    clone
+}
+
+/// The single edit turning `old` into `new`, as (offset, removed, inserted).
+fn changed_span(old: &str, new: &str) -> (usize, usize, usize) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .scan(0, |total, len| {
+            *total += len;
+            (*total <= max_suffix).then_some(len)
+        })
+        .sum();
+    (
+        prefix,
+        old.len() - prefix - suffix,
+        new.len() - prefix - suffix,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{ElementData, TagName};
+
+    /// `<div><p>Hello <b>bold</b></p><p>world</p></div>`
+    fn sample() -> (DomTree, NodeId, [NodeId; 6]) {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        let p1 = tree.create_element(ElementData::new(TagName::p()));
+        let hello = tree.create_text("Hello ".to_string());
+        let b = tree.create_element(ElementData::new(TagName::new("b")));
+        let bold = tree.create_text("bold".to_string());
+        let p2 = tree.create_element(ElementData::new(TagName::p()));
+        let world = tree.create_text("world".to_string());
+        tree.append_child(root, div);
+        tree.append_child(div, p1);
+        tree.append_child(p1, hello);
+        tree.append_child(p1, b);
+        tree.append_child(b, bold);
+        tree.append_child(div, p2);
+        tree.append_child(p2, world);
+        (tree, div, [p1, hello, b, bold, p2, world])
+    }
+
+    #[test]
+    fn test_range_boundaries_and_text() {
+        let (tree, div, [p1, hello, _, bold, _, world]) = sample();
+        let mut range = Range::new(div);
+        range.set_start(&tree, hello, 2).unwrap();
+        range.set_end(&tree, world, 3).unwrap();
+        assert_eq!(range.to_string(&tree), "llo boldwor");
+        assert_eq!(range.common_ancestor_container(&tree), div);
+        assert!(range.intersects_node(&tree, bold));
+        assert!(range.contains_node(&tree, bold));
+        assert!(range.partially_contains_node(&tree, p1));
+        assert!(range.is_point_in_range(&tree, p1, 1).unwrap());
+        assert!(!range.is_point_in_range(&tree, hello, 1).unwrap());
+        assert_eq!(
+            range.set_start(&tree, hello, 7),
+            Err(RangeError::IndexSize(7))
+        );
+
+        // Setting the start after the end collapses the range there.
+        range.set_start(&tree, div, 2).unwrap();
+        assert!(range.collapsed());
+    }
+
+    #[test]
+    fn test_range_extract_and_clone_contents() {
+        let (mut tree, div, [p1, hello, b, _, p2, world]) = sample();
+        let mut range = Range::new(div);
+        range.set_start(&tree, hello, 2).unwrap();
+        range.set_end(&tree, world, 3).unwrap();
+
+        let copy = range.clone_contents(&mut tree).unwrap();
+        assert_eq!(tree.get_text_content(copy), "llo boldwor");
+        assert_eq!(tree.children(copy).count(), 2);
+        assert_eq!(tree.get_text_content(div), "Hello boldworld");
+
+        let extracted = range.extract_contents(&mut tree).unwrap();
+        assert_eq!(tree.get_text_content(extracted), "llo boldwor");
+        assert_eq!(tree.get_text_content(div), "Held");
+        assert_eq!(tree.parent(b), tree.children(extracted).next());
+        assert_eq!(tree.children(div).collect::<Vec<_>>(), vec![p1, p2]);
+        assert!(range.collapsed());
+        assert_eq!(range.start(), BoundaryPoint::new(div, 1));
+        assert_eq!(tree.get(world).and_then(|n| n.as_text()), Some("ld"));
+    }
+
+    #[test]
+    fn test_range_surround_contents_and_live_updates() {
+        let (mut tree, div, [p1, hello, _, _, _, _]) = sample();
+        let mut range = Range::new(div);
+        range.set_start(&tree, hello, 0).unwrap();
+        range.set_end(&tree, hello, 5).unwrap();
+
+        let em = tree.create_element(ElementData::new(TagName::new("em")));
+        range.surround_contents(&mut tree, em).unwrap();
+        assert_eq!(tree.get_text_content(em), "Hello");
+        assert_eq!(tree.parent(em), Some(p1));
+        assert_eq!(range.to_string(&tree), "Hello");
+        assert_eq!(tree.get_text_content(p1), "Hello bold");
+
+        // Removing an earlier sibling shifts the offsets in the parent.
+        let mut live = range.clone();
+        let first = tree.first_child(p1).unwrap();
+        let mutation = Mutation::child_list(p1, Vec::new(), vec![first], None, Some(em));
+        tree.remove_from_parent(first);
+        live.update(&tree, &mutation);
+        assert_eq!((live.start().offset, live.end().offset), (0, 1));
+
+        let mut partial = Range::new(div);
+        partial.set_start(&tree, em, 0).unwrap();
+        partial.set_end(&tree, div, 2).unwrap();
+        let span = tree.create_element(ElementData::new(TagName::span()));
+        assert_eq!(
+            partial.surround_contents(&mut tree, span),
+            Err(RangeError::InvalidState)
+        );
+    }
+
+    #[test]
+    fn test_live_ranges_follow_inserts_removals_and_splits() {
+        let (mut tree, div, [p1, hello, b, _, p2, world]) = sample();
+        let ranges = Arc::new(LiveRanges::new());
+        tree.add_mutation_hook(ranges.clone());
+        let mut range = Range::new(div);
+        range.set_start(&tree, hello, 4).unwrap();
+        range.set_end(&tree, p1, 2).unwrap();
+        let range = ranges.track(range);
+        let after_hello = ranges.track(Range::new(p1));
+        after_hello.lock().set_start(&tree, p1, 1).unwrap();
+        let in_world = ranges.track(Range::new(world));
+
+        // Inserting before a point's child shifts it; removing shifts it back.
+        let span = tree.create_element(ElementData::new(TagName::span()));
+        tree.insert_before(p1, span, Some(hello));
+        assert_eq!(range.lock().end(), BoundaryPoint::new(p1, 3));
+        tree.remove(span);
+        assert_eq!(range.lock().end(), BoundaryPoint::new(p1, 2));
+
+        // Data inserted before a point in a text node moves it along.
+        tree.replace_data(hello, 0, 0, "Oh ");
+        assert_eq!(range.lock().start(), BoundaryPoint::new(hello, 7));
+
+        // `Oh Hello ` split at 5: the start follows the tail into the new
+        // node, and points after the split node stay after both halves.
+        let tail = tree.split_text(hello, 5).unwrap();
+        assert_eq!(range.lock().start(), BoundaryPoint::new(tail, 2));
+        assert_eq!(range.lock().end(), BoundaryPoint::new(p1, 3));
+        assert_eq!(after_hello.lock().start(), BoundaryPoint::new(p1, 2));
+        assert_eq!(range.lock().to_string(&tree), "o bold");
+        assert_eq!(tree.parent(b), Some(p1));
+
+        // Removing an ancestor of a point moves it to where the node was.
+        tree.remove(p2);
+        assert_eq!(in_world.lock().start(), BoundaryPoint::new(div, 1));
+        assert_eq!(in_world.lock().end(), BoundaryPoint::new(div, 1));
+    }
+
+    #[test]
+    fn test_contents_across_partially_selected_nodes() {
+        let (mut tree, div, [p1, hello, b, bold, p2, world]) = sample();
+        let mut range = Range::new(div);
+        range.set_start(&tree, bold, 2).unwrap();
+        range.set_end(&tree, world, 2).unwrap();
+
+        // Partially selected ancestors are cloned shallowly, down to the
+        // selected part of each text node.
+        let copy = range.clone_contents(&mut tree).unwrap();
+        let parts: Vec<NodeId> = tree.children(copy).collect();
+        assert_eq!(parts.len(), 2);
+        let copied_b = tree.first_child(parts[0]).unwrap();
+        assert_eq!(tree.get_element(copied_b).unwrap().tag_name.as_str(), "b");
+        assert_eq!(tree.children(parts[0]).count(), 1);
+        assert_eq!(tree.get_text_content(copied_b), "ld");
+        assert_eq!(tree.get_text_content(parts[1]), "wo");
+        assert_eq!(tree.get_text_content(div), "Hello boldworld");
+
+        let extracted = range.extract_contents(&mut tree).unwrap();
+        assert_eq!(tree.get_text_content(extracted), "ldwo");
+        assert_eq!(
+            tree.children(tree.first_child(extracted).unwrap()).count(),
+            1
+        );
+        assert_eq!(tree.children(p1).collect::<Vec<_>>(), vec![hello, b]);
+        assert_eq!(tree.get_text_content(b), "bo");
+        assert_eq!(tree.get_text_content(p2), "rld");
+        assert_eq!(tree.first_child(p2), Some(world));
+        assert_eq!(range.start(), BoundaryPoint::new(div, 1));
+        assert!(range.collapsed());
+
+        // Within one text node only the selected data is taken.
+        let mut inner = Range::new(hello);
+        inner.set_start(&tree, hello, 1).unwrap();
+        inner.set_end(&tree, hello, 4).unwrap();
+        let copy = inner.clone_contents(&mut tree).unwrap();
+        assert_eq!(tree.get_text_content(copy), "ell");
+        assert_eq!(tree.get(hello).and_then(|n| n.as_text()), Some("Hello "));
+    }
+
+    #[test]
+    fn test_changed_span() {
+        assert_eq!(changed_span("hello world", "hello"), (5, 6, 0));
+        assert_eq!(changed_span("abc", "aXbc"), (1, 0, 1));
+        assert_eq!(changed_span("aaa", "aa"), (2, 1, 0));
+    }
+}
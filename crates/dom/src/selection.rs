@@ -0,0 +1,246 @@
+//! The document's selection.
+//!
+//! A [`Selection`] holds at most one [`Range`] plus the direction it was
+//! made in, so that the anchor (where a drag started) and the focus (where
+//! it is now) can be told apart. [`Document`](crate::document::Document)
+//! registers its selection as a mutation hook, which keeps the range live.
+
+use crate::mutation::{Mutation, MutationHook};
+use crate::node::NodeId;
+use crate::range::{compare_points, node_length, BoundaryPoint, Range, RangeError};
+use crate::tree::DomTree;
+use parking_lot::Mutex;
+use std::cmp::Ordering;
+
+/// Which end of the range the anchor is at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SelectionDirection {
+    Forwards,
+    Backwards,
+    #[default]
+    Directionless,
+}
+
+impl SelectionDirection {
+    /// Name used for `Selection.direction`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SelectionDirection::Forwards => "forward",
+            SelectionDirection::Backwards => "backward",
+            SelectionDirection::Directionless => "none",
+        }
+    }
+}
+
+/// The user's selection in a document.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    range: Option<Range>,
+    direction: SelectionDirection,
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The selected range, if any.
+    pub fn range(&self) -> Option<&Range> {
+        self.range.as_ref()
+    }
+
+    pub fn range_mut(&mut self) -> Option<&mut Range> {
+        self.range.as_mut()
+    }
+
+    pub fn range_count(&self) -> usize {
+        usize::from(self.range.is_some())
+    }
+
+    pub fn direction(&self) -> SelectionDirection {
+        self.direction
+    }
+
+    /// Where the selection was started.
+    pub fn anchor(&self) -> Option<BoundaryPoint> {
+        self.range.as_ref().map(|range| match self.direction {
+            SelectionDirection::Backwards => range.end(),
+            _ => range.start(),
+        })
+    }
+
+    /// Where the selection was extended to.
+    pub fn focus(&self) -> Option<BoundaryPoint> {
+        self.range.as_ref().map(|range| match self.direction {
+            SelectionDirection::Backwards => range.start(),
+            _ => range.end(),
+        })
+    }
+
+    /// Whether nothing is selected beyond a caret.
+    pub fn is_collapsed(&self) -> bool {
+        match &self.range {
+            Some(range) => range.collapsed(),
+            None => true,
+        }
+    }
+
+    /// Replace the range (`addRange` after `removeAllRanges`).
+    pub fn set_range(&mut self, range: Range) {
+        self.range = Some(range);
+        self.direction = SelectionDirection::Directionless;
+    }
+
+    pub fn remove_all_ranges(&mut self) {
+        self.range = None;
+        self.direction = SelectionDirection::Directionless;
+    }
+
+    /// Place a caret at the given point.
+    pub fn collapse(
+        &mut self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<(), RangeError> {
+        let mut range = Range::new(node);
+        range.set_start(tree, node, offset)?;
+        self.set_range(range);
+        Ok(())
+    }
+
+    pub fn collapse_to_start(&mut self) -> Result<(), RangeError> {
+        let range = self.range.as_mut().ok_or(RangeError::InvalidState)?;
+        range.collapse(true);
+        self.direction = SelectionDirection::Directionless;
+        Ok(())
+    }
+
+    pub fn collapse_to_end(&mut self) -> Result<(), RangeError> {
+        let range = self.range.as_mut().ok_or(RangeError::InvalidState)?;
+        range.collapse(false);
+        self.direction = SelectionDirection::Directionless;
+        Ok(())
+    }
+
+    /// Move the focus, keeping the anchor where it is.
+    pub fn extend(
+        &mut self,
+        tree: &DomTree,
+        node: NodeId,
+        offset: usize,
+    ) -> Result<(), RangeError> {
+        let anchor = self.anchor().ok_or(RangeError::InvalidState)?;
+        self.set_base_and_extent(tree, anchor.node, anchor.offset, node, offset)
+    }
+
+    /// Select from an anchor point to a focus point, in either order.
+    pub fn set_base_and_extent(
+        &mut self,
+        tree: &DomTree,
+        anchor_node: NodeId,
+        anchor_offset: usize,
+        focus_node: NodeId,
+        focus_offset: usize,
+    ) -> Result<(), RangeError> {
+        let anchor = BoundaryPoint::new(anchor_node, anchor_offset);
+        let focus = BoundaryPoint::new(focus_node, focus_offset);
+        let mut range = Range::new(anchor_node);
+        range.set_start(tree, anchor_node, anchor_offset)?;
+        let backwards = compare_points(tree, &focus, &anchor) == Ordering::Less;
+        if backwards {
+            range.set_start(tree, focus_node, focus_offset)?;
+            range.set_end(tree, anchor_node, anchor_offset)?;
+        } else {
+            range.set_end(tree, focus_node, focus_offset)?;
+        }
+        self.range = Some(range);
+        self.direction = if backwards {
+            SelectionDirection::Backwards
+        } else {
+            SelectionDirection::Forwards
+        };
+        Ok(())
+    }
+
+    /// Select the contents of `node`.
+    pub fn select_all_children(&mut self, tree: &DomTree, node: NodeId) -> Result<(), RangeError> {
+        self.set_base_and_extent(tree, node, 0, node, node_length(tree, node))
+    }
+
+    /// Whether any part of `node` is selected (`containsNode` with
+    /// `allowPartialContainment` set), or all of it otherwise.
+    pub fn contains_node(&self, tree: &DomTree, node: NodeId, allow_partial: bool) -> bool {
+        match &self.range {
+            Some(range) if allow_partial => range.intersects_node(tree, node),
+            Some(range) => range.contains_node(tree, node),
+            None => false,
+        }
+    }
+
+    /// The selected text.
+    pub fn to_string(&self, tree: &DomTree) -> String {
+        self.range
+            .as_ref()
+            .map(|range| range.to_string(tree))
+            .unwrap_or_default()
+    }
+
+    /// Keep the range live across a mutation.
+    pub fn update(&mut self, tree: &DomTree, mutation: &Mutation) {
+        if let Some(range) = &mut self.range {
+            range.update(tree, mutation);
+        }
+    }
+}
+
+impl MutationHook for Mutex<Selection> {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        self.lock().update(tree, mutation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{ElementData, TagName};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_selection_direction_and_live_range() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let p = tree.create_element(ElementData::new(TagName::p()));
+        let text = tree.create_text("Hello world".to_string());
+        tree.append_child(root, p);
+        tree.append_child(p, text);
+
+        let selection = Arc::new(Mutex::new(Selection::new()));
+        tree.add_mutation_hook(selection.clone());
+
+        selection
+            .lock()
+            .set_base_and_extent(&tree, text, 8, text, 2)
+            .unwrap();
+        {
+            let selection = selection.lock();
+            assert_eq!(selection.direction(), SelectionDirection::Backwards);
+            assert_eq!(selection.anchor(), Some(BoundaryPoint::new(text, 8)));
+            assert_eq!(selection.focus(), Some(BoundaryPoint::new(text, 2)));
+            assert_eq!(selection.to_string(&tree), "llo wo");
+        }
+
+        tree.replace_data(text, 0, 1, "J");
+        tree.replace_data(text, 0, 0, ">> ");
+        assert_eq!(selection.lock().to_string(&tree), "llo wo");
+
+        selection.lock().extend(&tree, text, 14).unwrap();
+        assert_eq!(selection.lock().to_string(&tree), "rld");
+        assert_eq!(selection.lock().direction(), SelectionDirection::Forwards);
+
+        tree.remove_from_parent(text);
+        let selection = selection.lock();
+        assert!(selection.is_collapsed());
+        assert_eq!(selection.anchor(), Some(BoundaryPoint::new(p, 0)));
+    }
+}
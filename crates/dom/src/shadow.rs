@@ -0,0 +1,585 @@
+//! Shadow DOM: shadow roots, slot assignment and the flat tree.
+//!
+//! A shadow root is a document fragment owned by its host element; the
+//! host's light children are distributed to the `<slot>`s of the shadow
+//! tree by name. The flat tree, which is what gets styled and rendered,
+//! replaces each host's children with its shadow tree and each slot's
+//! children with the nodes assigned to it.
+
+use crate::element::{ElementData, ShadowRoot, ShadowRootMode};
+use crate::events::{Event, EventManager, EventType};
+use crate::mutation::{Mutation, MutationHook, MutationKind};
+use crate::node::{NodeData, NodeId};
+use crate::tree::DomTree;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Elements other than custom elements that may host a shadow root.
+const SHADOW_HOST_ELEMENTS: &[&str] = &[
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "main",
+    "nav",
+    "p",
+    "section",
+    "span",
+];
+
+/// Errors raised by `attachShadow`.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ShadowError {
+    #[error("<{0}> cannot host a shadow root")]
+    NotSupported(String),
+    #[error("element already hosts a shadow root")]
+    AlreadyAttached,
+    #[error("node is not an element")]
+    NotAnElement,
+}
+
+/// Options for [`DomTree::attach_shadow`] (`ShadowRootInit`).
+#[derive(Clone, Debug)]
+pub struct ShadowRootInit {
+    pub mode: ShadowRootMode,
+    pub delegates_focus: bool,
+    pub clonable: bool,
+    pub serializable: bool,
+}
+
+impl ShadowRootInit {
+    pub fn new(mode: ShadowRootMode) -> Self {
+        Self {
+            mode,
+            delegates_focus: false,
+            clonable: false,
+            serializable: false,
+        }
+    }
+
+    pub fn delegates_focus(mut self) -> Self {
+        self.delegates_focus = true;
+        self
+    }
+
+    pub fn clonable(mut self) -> Self {
+        self.clonable = true;
+        self
+    }
+
+    pub fn serializable(mut self) -> Self {
+        self.serializable = true;
+        self
+    }
+}
+
+/// Whether `element` may host a shadow root.
+pub fn is_valid_shadow_host(element: &ElementData) -> bool {
+    if element.namespace.is_some() {
+        return false;
+    }
+    let name = element.tag_name.as_str();
+    SHADOW_HOST_ELEMENTS.contains(&name)
+        || (name.contains('-') && name.starts_with(|c: char| c.is_ascii_lowercase()))
+}
+
+impl DomTree {
+    /// Attach a new, empty shadow root to `host` (`Element.attachShadow`).
+    pub fn attach_shadow(
+        &mut self,
+        host: NodeId,
+        init: ShadowRootInit,
+    ) -> Result<NodeId, ShadowError> {
+        let element = self.get_element(host).ok_or(ShadowError::NotAnElement)?;
+        if !is_valid_shadow_host(element) {
+            return Err(ShadowError::NotSupported(element.tag_name.to_string()));
+        }
+        if element.shadow_root.is_some() {
+            return Err(ShadowError::AlreadyAttached);
+        }
+
+        let root = self.create_document_fragment();
+        self.set_shadow_root(
+            host,
+            ShadowRoot {
+                delegates_focus: init.delegates_focus,
+                clonable: init.clonable,
+                serializable: init.serializable,
+                ..ShadowRoot::new(root, init.mode)
+            },
+        );
+        Ok(root)
+    }
+
+    /// Install `shadow` as the shadow root of `host`, replacing any other.
+    pub fn set_shadow_root(&mut self, host: NodeId, shadow: ShadowRoot) {
+        let root = shadow.root;
+        let previous = match self.get_element_mut(host) {
+            Some(element) => element.shadow_root.replace(shadow),
+            None => return,
+        };
+        if let Some(previous) = previous {
+            self.shadow_hosts.remove(&previous.root);
+        }
+        self.shadow_hosts.insert(root, host);
+    }
+
+    /// Host of the shadow root `root`.
+    pub fn shadow_host(&self, root: NodeId) -> Option<NodeId> {
+        self.shadow_hosts.get(&root).copied()
+    }
+
+    /// Whether `node` is a shadow root.
+    pub fn is_shadow_root(&self, node: NodeId) -> bool {
+        self.shadow_hosts.contains_key(&node)
+    }
+
+    /// Root of the tree containing `node`: the document, a shadow root, or
+    /// the top of a detached subtree.
+    pub fn tree_root(&self, node: NodeId) -> NodeId {
+        self.ancestors(node).last().unwrap_or(node)
+    }
+
+    /// Shadow root whose tree contains `node`, if any.
+    pub fn containing_shadow_root(&self, node: NodeId) -> Option<NodeId> {
+        let root = self.tree_root(node);
+        self.is_shadow_root(root).then_some(root)
+    }
+
+    /// Whether `ancestor` is `node` or contains it, crossing shadow
+    /// boundaries from shadow roots to their hosts.
+    pub fn is_shadow_including_inclusive_ancestor(&self, ancestor: NodeId, node: NodeId) -> bool {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            if self.is_inclusive_ancestor(ancestor, node) {
+                return true;
+            }
+            current = self.shadow_host(self.tree_root(node));
+        }
+        false
+    }
+
+    /// Name a slottable is assigned by: its `slot` attribute, or `""`.
+    fn slottable_name(&self, node: NodeId) -> Option<&str> {
+        match &self.get(node)?.data {
+            NodeData::Element(element) => Some(element.get_attribute("slot").unwrap_or("")),
+            NodeData::Text { .. } => Some(""),
+            _ => None,
+        }
+    }
+
+    /// `<slot>` elements of the shadow tree `root`, in tree order.
+    pub fn slots(&self, root: NodeId) -> Vec<NodeId> {
+        self.descendants(root)
+            .filter(|&node| {
+                self.get_element(node).is_some_and(|element| {
+                    element.tag_name == "slot" && element.namespace.is_none()
+                })
+            })
+            .collect()
+    }
+
+    /// Slot a host child is assigned to: the first slot of the host's
+    /// shadow tree whose name matches (`assignedSlot`).
+    pub fn assigned_slot(&self, node: NodeId) -> Option<NodeId> {
+        let host = self.parent(node)?;
+        let root = self.shadow_root(host)?.root;
+        self.with_slot_assignment(root, |assignment| assignment.slots.get(&node).copied())
+    }
+
+    /// Nodes assigned to `slot`, in tree order (`assignedNodes()`).
+    pub fn assigned_nodes(&self, slot: NodeId) -> Vec<NodeId> {
+        match self.containing_shadow_root(slot) {
+            Some(root) if self.shadow_host(root).is_some() => self
+                .with_slot_assignment(root, |assignment| {
+                    assignment.assigned.get(&slot).cloned().unwrap_or_default()
+                }),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Run `f` on the slot assignment of the shadow root `root`, computing
+    /// it again if the tree changed since it was cached.
+    fn with_slot_assignment<R>(&self, root: NodeId, f: impl FnOnce(&SlotAssignment) -> R) -> R {
+        let generation = self.generation();
+        if let Some(assignment) = self.slot_assignments.read().get(&root) {
+            if assignment.generation == generation {
+                return f(assignment);
+            }
+        }
+        let assignment = self.assign_slots(root, generation);
+        let result = f(&assignment);
+        self.slot_assignments.write().insert(root, assignment);
+        result
+    }
+
+    /// Assign the children of `root`'s host to the slots of its shadow tree.
+    fn assign_slots(&self, root: NodeId, generation: u64) -> SlotAssignment {
+        let mut by_name: HashMap<&str, NodeId> = HashMap::new();
+        for slot in self.slots(root) {
+            let name = self
+                .get_element(slot)
+                .and_then(|e| e.get_attribute("name"))
+                .unwrap_or("");
+            by_name.entry(name).or_insert(slot);
+        }
+        let mut assignment = SlotAssignment {
+            generation,
+            ..SlotAssignment::default()
+        };
+        let host = match self.shadow_host(root) {
+            Some(host) => host,
+            None => return assignment,
+        };
+        for child in self.children(host) {
+            let slot = match self
+                .slottable_name(child)
+                .and_then(|name| by_name.get(name))
+            {
+                Some(&slot) => slot,
+                None => continue,
+            };
+            assignment.slots.insert(child, slot);
+            assignment.assigned.entry(slot).or_default().push(child);
+        }
+        assignment
+    }
+
+    /// Assigned nodes of `slot` with nested slots replaced by their own
+    /// (`assignedNodes({ flatten: true })`). A slot without assigned nodes
+    /// contributes its fallback content.
+    pub fn flattened_assigned_nodes(&self, slot: NodeId) -> Vec<NodeId> {
+        let mut nodes = self.assigned_nodes(slot);
+        if nodes.is_empty() && self.is_shadow_slot(slot) {
+            nodes = self.children(slot).collect();
+        }
+        nodes
+            .into_iter()
+            .flat_map(|node| {
+                if self.is_shadow_slot(node) {
+                    self.flattened_assigned_nodes(node)
+                } else {
+                    vec![node]
+                }
+            })
+            .collect()
+    }
+
+    /// Whether `node` is a slot in a shadow tree.
+    fn is_shadow_slot(&self, node: NodeId) -> bool {
+        self.get_element(node)
+            .is_some_and(|element| element.tag_name == "slot" && element.namespace.is_none())
+            && self.containing_shadow_root(node).is_some()
+    }
+
+    /// Children of `node` in the flat tree: a host's shadow tree, a slot's
+    /// assigned nodes (or its fallback content), otherwise its children.
+    pub fn flat_children(&self, node: NodeId) -> Vec<NodeId> {
+        if let Some(shadow) = self.shadow_root(node) {
+            return self.children(shadow.root).collect();
+        }
+        if self.is_shadow_slot(node) {
+            let assigned = self.assigned_nodes(node);
+            if !assigned.is_empty() {
+                return assigned;
+            }
+        }
+        self.children(node).collect()
+    }
+
+    /// Parent of `node` in the flat tree. Host children that are not
+    /// assigned to a slot are not part of the flat tree.
+    pub fn flat_parent(&self, node: NodeId) -> Option<NodeId> {
+        let parent = self.parent(node)?;
+        if self.shadow_root(parent).is_some() {
+            return self.assigned_slot(node);
+        }
+        if self.is_shadow_root(parent) {
+            return self.shadow_host(parent);
+        }
+        Some(parent)
+    }
+
+    /// Flat-tree descendants of `node` in pre-order, excluding `node`.
+    pub fn flat_descendants(&self, node: NodeId) -> FlatTreeIterator<'_> {
+        FlatTreeIterator {
+            tree: self,
+            stack: self.flat_children(node).into_iter().rev().collect(),
+        }
+    }
+}
+
+/// Slot assignment of a shadow tree, as of a tree generation.
+#[derive(Debug, Default)]
+pub(crate) struct SlotAssignment {
+    generation: u64,
+    /// Slot of each assigned host child.
+    slots: HashMap<NodeId, NodeId>,
+    /// Assigned nodes of each slot, in tree order.
+    assigned: HashMap<NodeId, Vec<NodeId>>,
+}
+
+/// Pre-order iterator over the flat tree.
+pub struct FlatTreeIterator<'a> {
+    tree: &'a DomTree,
+    stack: Vec<NodeId>,
+}
+
+impl<'a> Iterator for FlatTreeIterator<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.stack
+            .extend(self.tree.flat_children(node).into_iter().rev());
+        Some(node)
+    }
+}
+
+/// Mutation hook that notices when the nodes assigned to a slot change,
+/// so that `slotchange` can be fired at it.
+#[derive(Debug, Default)]
+pub struct SlotChangeTracker {
+    state: Mutex<SlotState>,
+}
+
+#[derive(Debug, Default)]
+struct SlotState {
+    /// Last known shadow root and assigned nodes of each slot.
+    assigned: HashMap<NodeId, (NodeId, Vec<NodeId>)>,
+    /// Slots to fire `slotchange` at, in signal order.
+    signaled: Vec<NodeId>,
+}
+
+impl SlotChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the current assignment of every slot in `root`'s shadow
+    /// tree without signaling, e.g. for a tree built before tracking began.
+    pub fn observe_shadow_root(&self, tree: &DomTree, root: NodeId) {
+        let mut state = self.state.lock();
+        for slot in tree.slots(root) {
+            state
+                .assigned
+                .insert(slot, (root, tree.assigned_nodes(slot)));
+        }
+    }
+
+    /// Take the slots whose assigned nodes changed since the last call.
+    pub fn take_signaled(&self) -> Vec<NodeId> {
+        std::mem::take(&mut self.state.lock().signaled)
+    }
+
+    /// Fire `slotchange` at every signaled slot.
+    pub fn dispatch(&self, tree: &DomTree, events: &mut EventManager) {
+        for slot in self.take_signaled() {
+            if tree.get(slot).is_some() {
+                let mut event =
+                    Event::with_options(EventType::Custom("slotchange".into()), true, false);
+                events.dispatch(tree, slot, &mut event);
+            }
+        }
+    }
+
+    /// Shadow root whose slot assignment `mutation` may have changed.
+    fn affected_shadow_root(tree: &DomTree, mutation: &Mutation) -> Option<NodeId> {
+        let target = mutation.target;
+        match mutation.kind {
+            MutationKind::ChildList => tree
+                .shadow_root(target)
+                .map(|shadow| shadow.root)
+                .or_else(|| tree.containing_shadow_root(target)),
+            MutationKind::Attributes => match mutation.attribute_name.as_deref() {
+                Some("slot") => tree
+                    .parent(target)
+                    .and_then(|host| tree.shadow_root(host))
+                    .map(|shadow| shadow.root),
+                Some("name") if tree.is_shadow_slot(target) => tree.containing_shadow_root(target),
+                _ => None,
+            },
+            MutationKind::CharacterData => None,
+        }
+    }
+}
+
+impl MutationHook for SlotChangeTracker {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        let root = match Self::affected_shadow_root(tree, mutation) {
+            Some(root) => root,
+            None => return,
+        };
+        let mut state = self.state.lock();
+        let SlotState { assigned, signaled } = &mut *state;
+
+        let slots = tree.slots(root);
+        for &slot in &slots {
+            let nodes = tree.assigned_nodes(slot);
+            let previous = assigned.insert(slot, (root, nodes.clone()));
+            let changed = previous.map_or(!nodes.is_empty(), |(_, previous)| previous != nodes);
+            if changed && !signaled.contains(&slot) {
+                signaled.push(slot);
+            }
+        }
+
+        // Slots that left the shadow tree lose their assigned nodes.
+        let removed: Vec<NodeId> = assigned
+            .iter()
+            .filter(|(slot, (slot_root, _))| *slot_root == root && !slots.contains(slot))
+            .map(|(&slot, _)| slot)
+            .collect();
+        for slot in removed {
+            let had_nodes = assigned
+                .remove(&slot)
+                .is_some_and(|(_, nodes)| !nodes.is_empty());
+            if had_nodes && tree.get(slot).is_some() && !signaled.contains(&slot) {
+                signaled.push(slot);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::TagName;
+    use std::sync::Arc;
+
+    fn element(tree: &mut DomTree, parent: NodeId, tag: &str, attrs: &[(&str, &str)]) -> NodeId {
+        let mut data = ElementData::new(TagName::new(tag));
+        for (name, value) in attrs {
+            data.set_attribute(name, value);
+        }
+        let node = tree.create_element(data);
+        tree.append_child(parent, node);
+        node
+    }
+
+    /// `<my-card><b slot=title></b><i></i></my-card>` with shadow tree
+    /// `<h1><slot name=title></slot></h1><slot>fallback</slot>`.
+    fn card() -> (DomTree, NodeId, NodeId, [NodeId; 4]) {
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let host = element(&mut tree, doc, "my-card", &[]);
+        let title = element(&mut tree, host, "b", &[("slot", "title")]);
+        let body = element(&mut tree, host, "i", &[]);
+
+        let root = tree
+            .attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        let h1 = element(&mut tree, root, "h1", &[]);
+        let named = element(&mut tree, h1, "slot", &[("name", "title")]);
+        let default = element(&mut tree, root, "slot", &[]);
+        let fallback = tree.create_text("fallback".to_string());
+        tree.append_child(default, fallback);
+        (tree, host, root, [title, body, named, default])
+    }
+
+    #[test]
+    fn test_attach_shadow() {
+        let (mut tree, host, root, _) = card();
+        assert_eq!(tree.shadow_host(root), Some(host));
+        assert_eq!(
+            tree.shadow_root(host).map(|s| s.mode),
+            Some(ShadowRootMode::Open)
+        );
+        assert_eq!(
+            tree.attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Closed)),
+            Err(ShadowError::AlreadyAttached)
+        );
+
+        let doc = tree.root().unwrap();
+        let input = element(&mut tree, doc, "input", &[]);
+        assert_eq!(
+            tree.attach_shadow(input, ShadowRootInit::new(ShadowRootMode::Open)),
+            Err(ShadowError::NotSupported("input".to_string()))
+        );
+
+        tree.remove(host);
+        assert!(!tree.is_shadow_root(root));
+    }
+
+    #[test]
+    fn test_slot_assignment_and_flat_tree() {
+        let (mut tree, host, root, [title, body, named, default]) = card();
+        let h1 = tree.parent(named).unwrap();
+
+        assert_eq!(tree.assigned_slot(title), Some(named));
+        assert_eq!(tree.assigned_slot(body), Some(default));
+        assert_eq!(tree.assigned_nodes(default), vec![body]);
+        assert_eq!(tree.containing_shadow_root(named), Some(root));
+        assert!(tree.is_shadow_including_inclusive_ancestor(host, named));
+
+        assert_eq!(tree.flat_children(host), vec![h1, default]);
+        assert_eq!(tree.flat_parent(title), Some(named));
+        assert_eq!(tree.flat_parent(h1), Some(host));
+        assert_eq!(
+            tree.flat_descendants(host).collect::<Vec<_>>(),
+            vec![h1, named, title, default, body]
+        );
+
+        // Without assigned nodes a slot shows its fallback content.
+        tree.remove(body);
+        let fallback = tree.first_child(default).unwrap();
+        assert_eq!(tree.flat_children(default), vec![fallback]);
+        assert!(tree.assigned_nodes(default).is_empty());
+        assert_eq!(tree.flattened_assigned_nodes(default), vec![fallback]);
+
+        // The cached assignment follows later changes.
+        let text = tree.create_text("text".to_string());
+        tree.append_child(host, text);
+        tree.set_attribute(title, "slot", "other");
+        assert_eq!(tree.assigned_nodes(default), vec![text]);
+        assert_eq!(tree.assigned_slot(title), None);
+        assert!(tree.assigned_nodes(named).is_empty());
+    }
+
+    #[test]
+    fn test_slotchange() {
+        let (mut tree, host, root, [title, _, named, default]) = card();
+        let tracker = Arc::new(SlotChangeTracker::new());
+        tracker.observe_shadow_root(&tree, root);
+        tree.add_mutation_hook(tracker.clone());
+
+        let extra = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(host, extra);
+        assert_eq!(tracker.take_signaled(), vec![default]);
+
+        tree.get_element_mut(title)
+            .unwrap()
+            .remove_attribute("slot");
+        tree.flush_mutations();
+        assert_eq!(tracker.take_signaled(), vec![named, default]);
+
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let mut events = EventManager::new();
+        let log = fired.clone();
+        events.add_listener(
+            root,
+            "slotchange",
+            Arc::new(move |event: &mut Event| log.lock().push(event.target)),
+            Default::default(),
+        );
+        tree.remove(default);
+        tracker.dispatch(&tree, &mut events);
+        assert!(fired.lock().is_empty(), "removed slots are not fired at");
+
+        tree.get_element_mut(named)
+            .unwrap()
+            .set_attribute("name", "");
+        tree.flush_mutations();
+        tracker.dispatch(&tree, &mut events);
+        assert_eq!(*fired.lock(), vec![Some(named)]);
+    }
+}
@@ -0,0 +1,945 @@
+//! Serializable DOM snapshots.
+//!
+//! A [`DocumentSnapshot`] holds a document's nodes, attributes, doctype,
+//! shadow roots and form control state, with each node under its raw
+//! [`NodeId`], so a restored document keeps the ids of the original. It
+//! has a readable JSON form and a compact binary form, and `DomTree` and
+//! `Document` implement serde's traits through it. Restoring rejects ids
+//! whose slot indices or reuse counts run far past the node count.
+//!
+//! Listeners, focus, selection, undo history and layout or style data are
+//! runtime state and are not part of a snapshot. Custom elements come back
+//! undefined, to be upgraded when their definitions are registered again.
+
+use crate::attributes::AttributeMap;
+use crate::document::{ContentType, Document, QuirksMode, ReadyState};
+use crate::element::{ElementData, ElementFlags, ShadowRoot, ShadowRootMode, TagName};
+use crate::forms::ControlState;
+use crate::node::{DocumentType, Node, NodeData, NodeId, NodeType};
+use crate::tree::DomTree;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+use url::Url;
+
+/// Version of the snapshot format.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Leading bytes of the binary form.
+const MAGIC: &[u8; 4] = b"OXDS";
+
+/// Slot indices and slot reuses allowed beyond the node count, which bound
+/// the work of restoring ids.
+const ID_SLACK: usize = 1 << 16;
+
+/// Flags that describe runtime state rather than the document.
+const RUNTIME_FLAGS: ElementFlags = ElementFlags::FOCUSED
+    .union(ElementFlags::FOCUS_VISIBLE)
+    .union(ElementFlags::FOCUS_WITHIN);
+
+/// Errors reading or restoring a snapshot.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("invalid snapshot JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("not a DOM snapshot")]
+    BadMagic,
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+    #[error("snapshot data ends unexpectedly")]
+    UnexpectedEnd,
+    #[error("malformed snapshot data: {0}")]
+    Malformed(&'static str),
+    #[error("invalid node id {0:#x}")]
+    InvalidId(u64),
+    #[error("node {0:#x} appears more than once")]
+    DuplicateNode(u64),
+    #[error("node {0:#x} is referenced but missing")]
+    MissingNode(u64),
+    #[error("node {0:#x} has more than one parent")]
+    MultipleParents(u64),
+    #[error("the child lists contain a cycle")]
+    Cycle,
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+}
+
+/// A document and its tree.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DocumentSnapshot {
+    pub version: u32,
+    pub url: String,
+    pub base_url: String,
+    pub title: String,
+    pub content_type: ContentType,
+    pub encoding: String,
+    pub ready_state: ReadyState,
+    pub quirks_mode: QuirksMode,
+    pub referrer: String,
+    pub last_modified: Option<String>,
+    pub document_element: Option<u64>,
+    pub head: Option<u64>,
+    pub body: Option<u64>,
+    pub tree: TreeSnapshot,
+}
+
+/// Every node of a tree, including detached ones, in slot order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TreeSnapshot {
+    pub root: u64,
+    pub nodes: Vec<NodeSnapshot>,
+}
+
+/// A node under its raw id, with the raw ids of its children.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub id: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<u64>,
+    pub data: NodeDataSnapshot,
+}
+
+/// Node data, as in [`NodeData`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeDataSnapshot {
+    Document { doctype: Option<DocumentType> },
+    DocumentFragment,
+    Element(ElementSnapshot),
+    Text { content: String },
+    Comment { content: String },
+    ProcessingInstruction { target: String, data: String },
+    DocumentType(DocumentType),
+}
+
+/// An element. Cached ids, classes and inline style are derived from the
+/// attributes on restore.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElementSnapshot {
+    pub tag_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<(String, String)>,
+    pub flags: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow_root: Option<ShadowRootSnapshot>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template_contents: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub control: ControlState,
+}
+
+/// A shadow root attached to an element.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShadowRootSnapshot {
+    pub root: u64,
+    pub mode: ShadowRootMode,
+    pub delegates_focus: bool,
+    pub clonable: bool,
+    pub serializable: bool,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl DocumentSnapshot {
+    pub fn capture(document: &Document) -> Self {
+        Self {
+            version: SNAPSHOT_VERSION,
+            url: document.url.to_string(),
+            base_url: document.base_url.to_string(),
+            title: document.title.clone(),
+            content_type: document.content_type,
+            encoding: document.encoding.clone(),
+            ready_state: document.ready_state,
+            quirks_mode: document.quirks_mode,
+            referrer: document.referrer.clone(),
+            last_modified: document.last_modified.clone(),
+            document_element: document.document_element.map(NodeId::to_raw),
+            head: document.head.map(NodeId::to_raw),
+            body: document.body.map(NodeId::to_raw),
+            tree: TreeSnapshot::capture(&document.tree),
+        }
+    }
+
+    /// Build the document, with every node under its snapshot id.
+    pub fn restore(&self) -> Result<Document, SnapshotError> {
+        if self.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(self.version));
+        }
+        let tree = self.tree.restore()?;
+        let node = |raw: Option<u64>| -> Result<Option<NodeId>, SnapshotError> {
+            match raw {
+                Some(raw) if tree.get(NodeId::from_raw(raw)).is_none() => {
+                    Err(SnapshotError::MissingNode(raw))
+                }
+                raw => Ok(raw.map(NodeId::from_raw)),
+            }
+        };
+        let (document_element, head, body) = (
+            node(self.document_element)?,
+            node(self.head)?,
+            node(self.body)?,
+        );
+
+        let mut document = Document::with_tree(Url::parse(&self.url)?, tree);
+        document.base_url = Url::parse(&self.base_url)?;
+        document.title = self.title.clone();
+        document.content_type = self.content_type;
+        document.encoding = self.encoding.clone();
+        document.ready_state = self.ready_state;
+        document.loading = self.ready_state == ReadyState::Loading;
+        document.quirks_mode = self.quirks_mode;
+        document.referrer = self.referrer.clone();
+        document.last_modified = self.last_modified.clone();
+        document.document_element = document_element;
+        document.head = head;
+        document.body = body;
+        Ok(document)
+    }
+
+    /// The readable form: pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots serialize to JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        let snapshot: Self = serde_json::from_str(json)?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+
+    /// The compact form: varint-encoded fields after a magic number and
+    /// the format version.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.bytes.extend_from_slice(MAGIC);
+        w.uint(u64::from(self.version));
+        w.str(&self.url);
+        w.str(&self.base_url);
+        w.str(&self.title);
+        w.uint(match self.content_type {
+            ContentType::Html => 0,
+            ContentType::Xml => 1,
+            ContentType::Xhtml => 2,
+        });
+        w.str(&self.encoding);
+        w.uint(match self.ready_state {
+            ReadyState::Loading => 0,
+            ReadyState::Interactive => 1,
+            ReadyState::Complete => 2,
+        });
+        w.uint(match self.quirks_mode {
+            QuirksMode::NoQuirks => 0,
+            QuirksMode::Quirks => 1,
+            QuirksMode::LimitedQuirks => 2,
+        });
+        w.str(&self.referrer);
+        w.option(self.last_modified.as_deref(), Writer::str);
+        for node in [self.document_element, self.head, self.body] {
+            w.option(node, Writer::uint);
+        }
+        self.tree.write(&mut w);
+        w.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut r = Reader { bytes };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = u32::try_from(r.uint()?).map_err(|_| SnapshotError::Malformed("version"))?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let snapshot = Self {
+            version,
+            url: r.string()?,
+            base_url: r.string()?,
+            title: r.string()?,
+            content_type: match r.uint()? {
+                0 => ContentType::Html,
+                1 => ContentType::Xml,
+                2 => ContentType::Xhtml,
+                _ => return Err(SnapshotError::Malformed("content type")),
+            },
+            encoding: r.string()?,
+            ready_state: match r.uint()? {
+                0 => ReadyState::Loading,
+                1 => ReadyState::Interactive,
+                2 => ReadyState::Complete,
+                _ => return Err(SnapshotError::Malformed("ready state")),
+            },
+            quirks_mode: match r.uint()? {
+                0 => QuirksMode::NoQuirks,
+                1 => QuirksMode::Quirks,
+                2 => QuirksMode::LimitedQuirks,
+                _ => return Err(SnapshotError::Malformed("quirks mode")),
+            },
+            referrer: r.string()?,
+            last_modified: r.option(Reader::string)?,
+            document_element: r.option(Reader::uint)?,
+            head: r.option(Reader::uint)?,
+            body: r.option(Reader::uint)?,
+            tree: TreeSnapshot::read(&mut r)?,
+        };
+        if !r.bytes.is_empty() {
+            return Err(SnapshotError::Malformed("trailing bytes"));
+        }
+        Ok(snapshot)
+    }
+}
+
+impl TreeSnapshot {
+    pub fn capture(tree: &DomTree) -> Self {
+        let nodes = tree
+            .iter()
+            .map(|(id, node)| NodeSnapshot {
+                id: id.to_raw(),
+                children: node.children.iter().map(|child| child.to_raw()).collect(),
+                data: NodeDataSnapshot::capture(&node.data),
+            })
+            .collect();
+        Self {
+            root: tree.root().map_or(0, NodeId::to_raw),
+            nodes,
+        }
+    }
+
+    /// Build the tree, after checking that the ids are valid and unique
+    /// and that the child lists form a forest.
+    pub fn restore(&self) -> Result<DomTree, SnapshotError> {
+        let mut ids = HashSet::with_capacity(self.nodes.len());
+        // Rebuilding the slot map fills the slots below each index and
+        // reuses a slot once per version step, so neither is left to the
+        // snapshot.
+        let limit = self.nodes.len() + ID_SLACK;
+        let mut reuses = 0;
+        for node in &self.nodes {
+            // Occupied slot map keys have a nonzero index and an odd version.
+            let (index, version) = (node.id as u32, node.id >> 32);
+            reuses += (version >> 1) as usize;
+            if index == 0 || index as usize > limit || version & 1 == 0 || reuses > limit {
+                return Err(SnapshotError::InvalidId(node.id));
+            }
+            if !ids.insert(node.id) {
+                return Err(SnapshotError::DuplicateNode(node.id));
+            }
+        }
+        let known = |raw: u64| {
+            if ids.contains(&raw) {
+                Ok(())
+            } else {
+                Err(SnapshotError::MissingNode(raw))
+            }
+        };
+        known(self.root)?;
+
+        let mut parents = HashMap::new();
+        for node in &self.nodes {
+            for &child in &node.children {
+                known(child)?;
+                if child == self.root || parents.insert(child, node.id).is_some() {
+                    return Err(SnapshotError::MultipleParents(child));
+                }
+            }
+            if let NodeDataSnapshot::Element(element) = &node.data {
+                if let Some(shadow) = &element.shadow_root {
+                    known(shadow.root)?;
+                }
+                if let Some(contents) = element.template_contents {
+                    known(contents)?;
+                }
+            }
+        }
+        // With one parent each, nodes on a cycle cannot be reached from a
+        // parentless node.
+        let children: HashMap<u64, &[u64]> = self
+            .nodes
+            .iter()
+            .map(|n| (n.id, n.children.as_slice()))
+            .collect();
+        let mut stack: Vec<u64> = self
+            .nodes
+            .iter()
+            .map(|n| n.id)
+            .filter(|id| !parents.contains_key(id))
+            .collect();
+        let mut reached = 0;
+        while let Some(id) = stack.pop() {
+            reached += 1;
+            stack.extend_from_slice(children[&id]);
+        }
+        if reached != self.nodes.len() {
+            return Err(SnapshotError::Cycle);
+        }
+
+        let nodes = self
+            .nodes
+            .iter()
+            .map(|snapshot| {
+                let id = NodeId::from_raw(snapshot.id);
+                let data = snapshot.data.restore();
+                let node_type = match &data {
+                    NodeData::Document { .. } => NodeType::Document,
+                    NodeData::DocumentFragment => NodeType::DocumentFragment,
+                    NodeData::Element(_) => NodeType::Element,
+                    NodeData::Text { .. } => NodeType::Text,
+                    NodeData::Comment { .. } => NodeType::Comment,
+                    NodeData::ProcessingInstruction { .. } => NodeType::ProcessingInstruction,
+                    NodeData::DocumentType(_) => NodeType::DocumentType,
+                };
+                let mut node = Node::new(id, node_type, data);
+                node.children = snapshot
+                    .children
+                    .iter()
+                    .map(|&child| NodeId::from_raw(child))
+                    .collect();
+                node
+            })
+            .collect();
+        Ok(DomTree::from_nodes(NodeId::from_raw(self.root), nodes))
+    }
+
+    fn write(&self, w: &mut Writer) {
+        w.uint(self.root);
+        w.uint(self.nodes.len() as u64);
+        for node in &self.nodes {
+            w.uint(node.id);
+            w.uint(node.children.len() as u64);
+            for &child in &node.children {
+                w.uint(child);
+            }
+            node.data.write(w);
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, SnapshotError> {
+        let root = r.uint()?;
+        let nodes = r.seq(|r| {
+            Ok(NodeSnapshot {
+                id: r.uint()?,
+                children: r.seq(Reader::uint)?,
+                data: NodeDataSnapshot::read(r)?,
+            })
+        })?;
+        Ok(Self { root, nodes })
+    }
+}
+
+impl NodeDataSnapshot {
+    fn capture(data: &NodeData) -> Self {
+        match data {
+            NodeData::Document { doctype } => Self::Document {
+                doctype: doctype.clone(),
+            },
+            NodeData::DocumentFragment => Self::DocumentFragment,
+            NodeData::Element(element) => Self::Element(ElementSnapshot::capture(element)),
+            NodeData::Text { content } => Self::Text {
+                content: content.clone(),
+            },
+            NodeData::Comment { content } => Self::Comment {
+                content: content.clone(),
+            },
+            NodeData::ProcessingInstruction { target, data } => Self::ProcessingInstruction {
+                target: target.clone(),
+                data: data.clone(),
+            },
+            NodeData::DocumentType(doctype) => Self::DocumentType(doctype.clone()),
+        }
+    }
+
+    fn restore(&self) -> NodeData {
+        match self {
+            Self::Document { doctype } => NodeData::Document {
+                doctype: doctype.clone(),
+            },
+            Self::DocumentFragment => NodeData::DocumentFragment,
+            Self::Element(element) => NodeData::Element(element.restore()),
+            Self::Text { content } => NodeData::Text {
+                content: content.clone(),
+            },
+            Self::Comment { content } => NodeData::Comment {
+                content: content.clone(),
+            },
+            Self::ProcessingInstruction { target, data } => NodeData::ProcessingInstruction {
+                target: target.clone(),
+                data: data.clone(),
+            },
+            Self::DocumentType(doctype) => NodeData::DocumentType(doctype.clone()),
+        }
+    }
+
+    fn write(&self, w: &mut Writer) {
+        match self {
+            Self::Document { doctype } => {
+                w.uint(0);
+                w.option(doctype.as_ref(), write_doctype);
+            }
+            Self::DocumentFragment => w.uint(1),
+            Self::Element(element) => {
+                w.uint(2);
+                element.write(w);
+            }
+            Self::Text { content } => {
+                w.uint(3);
+                w.str(content);
+            }
+            Self::Comment { content } => {
+                w.uint(4);
+                w.str(content);
+            }
+            Self::ProcessingInstruction { target, data } => {
+                w.uint(5);
+                w.str(target);
+                w.str(data);
+            }
+            Self::DocumentType(doctype) => {
+                w.uint(6);
+                write_doctype(w, doctype);
+            }
+        }
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(match r.uint()? {
+            0 => Self::Document {
+                doctype: r.option(read_doctype)?,
+            },
+            1 => Self::DocumentFragment,
+            2 => Self::Element(ElementSnapshot::read(r)?),
+            3 => Self::Text {
+                content: r.string()?,
+            },
+            4 => Self::Comment {
+                content: r.string()?,
+            },
+            5 => Self::ProcessingInstruction {
+                target: r.string()?,
+                data: r.string()?,
+            },
+            6 => Self::DocumentType(read_doctype(r)?),
+            _ => return Err(SnapshotError::Malformed("node type")),
+        })
+    }
+}
+
+impl ElementSnapshot {
+    fn capture(element: &ElementData) -> Self {
+        Self {
+            tag_name: element.tag_name.as_str().to_string(),
+            namespace: element.namespace.as_deref().map(str::to_string),
+            attributes: element
+                .attributes
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            flags: element.flags.difference(RUNTIME_FLAGS).bits(),
+            shadow_root: element
+                .shadow_root
+                .as_ref()
+                .map(|shadow| ShadowRootSnapshot {
+                    root: shadow.root.to_raw(),
+                    mode: shadow.mode,
+                    delegates_focus: shadow.delegates_focus,
+                    clonable: shadow.clonable,
+                    serializable: shadow.serializable,
+                }),
+            template_contents: element.template_contents.map(NodeId::to_raw),
+            control: (*element.control).clone(),
+        }
+    }
+
+    fn restore(&self) -> ElementData {
+        let tag_name = TagName::new(&self.tag_name);
+        let mut element = match &self.namespace {
+            Some(namespace) => ElementData::with_namespace(tag_name, namespace),
+            None => ElementData::new(tag_name),
+        };
+        // Setting the attributes fills the caches; the map is then replaced
+        // to keep the names exactly as they were.
+        let mut attributes = AttributeMap::with_capacity(self.attributes.len());
+        for (name, value) in &self.attributes {
+            element.set_attribute(name, value);
+            attributes.set(name, value);
+        }
+        element.attributes = attributes;
+        element.flags = ElementFlags::from_bits_truncate(self.flags);
+        element.shadow_root = self.shadow_root.as_ref().map(|shadow| ShadowRoot {
+            root: NodeId::from_raw(shadow.root),
+            mode: shadow.mode,
+            delegates_focus: shadow.delegates_focus,
+            clonable: shadow.clonable,
+            serializable: shadow.serializable,
+        });
+        element.template_contents = self.template_contents.map(NodeId::from_raw);
+        element.control = Box::new(self.control.clone());
+        element
+    }
+
+    fn write(&self, w: &mut Writer) {
+        w.str(&self.tag_name);
+        w.option(self.namespace.as_deref(), Writer::str);
+        w.uint(self.attributes.len() as u64);
+        for (name, value) in &self.attributes {
+            w.str(name);
+            w.str(value);
+        }
+        w.uint(u64::from(self.flags));
+        w.option(self.shadow_root.as_ref(), |w, shadow| {
+            w.uint(shadow.root);
+            w.uint(match shadow.mode {
+                ShadowRootMode::Open => 0,
+                ShadowRootMode::Closed => 1,
+            });
+            w.bool(shadow.delegates_focus);
+            w.bool(shadow.clonable);
+            w.bool(shadow.serializable);
+        });
+        w.option(self.template_contents, Writer::uint);
+        w.option(self.control.value.as_deref(), Writer::str);
+        w.bool(self.control.dirty_checkedness);
+        w.str(&self.control.custom_validity);
+        w.uint(self.control.selection.0 as u64);
+        w.uint(self.control.selection.1 as u64);
+    }
+
+    fn read(r: &mut Reader) -> Result<Self, SnapshotError> {
+        Ok(Self {
+            tag_name: r.string()?,
+            namespace: r.option(Reader::string)?,
+            attributes: r.seq(|r| Ok((r.string()?, r.string()?)))?,
+            flags: u32::try_from(r.uint()?)
+                .map_err(|_| SnapshotError::Malformed("element flags"))?,
+            shadow_root: r.option(|r| {
+                Ok(ShadowRootSnapshot {
+                    root: r.uint()?,
+                    mode: match r.uint()? {
+                        0 => ShadowRootMode::Open,
+                        1 => ShadowRootMode::Closed,
+                        _ => return Err(SnapshotError::Malformed("shadow root mode")),
+                    },
+                    delegates_focus: r.bool()?,
+                    clonable: r.bool()?,
+                    serializable: r.bool()?,
+                })
+            })?,
+            template_contents: r.option(Reader::uint)?,
+            control: ControlState {
+                value: r.option(Reader::string)?,
+                dirty_checkedness: r.bool()?,
+                custom_validity: r.string()?,
+                selection: (r.usize()?, r.usize()?),
+            },
+        })
+    }
+}
+
+fn write_doctype(w: &mut Writer, doctype: &DocumentType) {
+    w.str(&doctype.name);
+    w.str(&doctype.public_id);
+    w.str(&doctype.system_id);
+}
+
+fn read_doctype(r: &mut Reader) -> Result<DocumentType, SnapshotError> {
+    Ok(DocumentType {
+        name: r.string()?,
+        public_id: r.string()?,
+        system_id: r.string()?,
+    })
+}
+
+/// Appends fields in the binary form.
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    /// LEB128 varint.
+    fn uint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.bytes.push(value as u8);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.uint(value.len() as u64);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn option<T>(&mut self, value: Option<T>, write: impl FnOnce(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            write(self, value);
+        }
+    }
+}
+
+/// Consumes fields of the binary form.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        if self.bytes.len() < len {
+            return Err(SnapshotError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn uint(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SnapshotError::Malformed("varint"))
+    }
+
+    fn usize(&mut self) -> Result<usize, SnapshotError> {
+        usize::try_from(self.uint()?).map_err(|_| SnapshotError::Malformed("length"))
+    }
+
+    fn bool(&mut self) -> Result<bool, SnapshotError> {
+        match self.take(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SnapshotError::Malformed("boolean")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, SnapshotError> {
+        let len = self.usize()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| SnapshotError::Malformed("string"))
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, SnapshotError>,
+    ) -> Result<Option<T>, SnapshotError> {
+        if self.bool()? {
+            read(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// A length-prefixed sequence. Lengths are not trusted for allocation,
+    /// as every item takes at least a byte.
+    fn seq<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Self) -> Result<T, SnapshotError>,
+    ) -> Result<Vec<T>, SnapshotError> {
+        let len = self.usize()?;
+        let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            items.push(read(self)?);
+        }
+        Ok(items)
+    }
+}
+
+impl Serialize for DomTree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TreeSnapshot::capture(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DomTree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        TreeSnapshot::deserialize(deserializer)?
+            .restore()
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Document {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        DocumentSnapshot::capture(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Document {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        DocumentSnapshot::deserialize(deserializer)?
+            .restore()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::ShadowRootMode;
+
+    fn sample() -> (Document, NodeId, NodeId, NodeId) {
+        let mut document = Document::new(Url::parse("https://example.com/page").unwrap());
+        let root = document.tree.root().unwrap();
+        // A freed slot, so that a later node reuses it at a newer version.
+        let scratch = document.create_element("scratch");
+        document.tree.remove(scratch);
+        let html = document.create_element("html");
+        document.tree.append_child(root, html);
+        let div = document.create_element("div");
+        document
+            .tree
+            .get_element_mut(div)
+            .unwrap()
+            .set_attribute("class", "a b");
+        document
+            .tree
+            .get_element_mut(div)
+            .unwrap()
+            .set_attribute("viewBox", "0 0 1 1");
+        document.tree.append_child(html, div);
+        let text = document.create_text_node("caf\u{e9}");
+        document.tree.append_child(div, text);
+
+        let input = document.create_element("input");
+        document.tree.append_child(html, input);
+        crate::forms::set_value(&mut document.tree, input, "typed");
+
+        let shadow = document.tree.create_document_fragment();
+        let slot = document.create_element("slot");
+        document.tree.append_child(shadow, slot);
+        document.tree.get_element_mut(div).unwrap().shadow_root =
+            Some(ShadowRoot::new(shadow, ShadowRootMode::Closed));
+        document.document_element = Some(html);
+        document.title = "Snapshot".to_string();
+        (document, div, text, input)
+    }
+
+    #[test]
+    fn test_snapshot_round_trips_with_stable_ids() {
+        let (document, div, text, input) = sample();
+        let snapshot = DocumentSnapshot::capture(&document);
+
+        let from_json = DocumentSnapshot::from_json(&snapshot.to_json()).unwrap();
+        let from_bytes = DocumentSnapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+        assert_eq!(from_json, snapshot);
+        assert_eq!(from_bytes, snapshot);
+        assert!(snapshot.to_bytes().len() < snapshot.to_json().len() / 3);
+
+        let mut restored = from_bytes.restore().unwrap();
+        assert_eq!(DocumentSnapshot::capture(&restored), snapshot);
+        assert_eq!(restored.title, "Snapshot");
+        assert_eq!(restored.tree.get_text_content(div), "caf\u{e9}");
+        assert_eq!(restored.tree.parent(text), Some(div));
+        assert_eq!(restored.tree.elements_with_class("b"), [div]);
+        assert_eq!(
+            restored
+                .tree
+                .get_element(div)
+                .unwrap()
+                .get_attribute("viewBox"),
+            Some("0 0 1 1")
+        );
+        assert_eq!(crate::forms::value(&restored.tree, input), "typed");
+        let shadow = restored.tree.shadow_root(div).unwrap().root;
+        assert_eq!(restored.tree.shadow_hosts.get(&shadow), Some(&div));
+
+        // New nodes do not collide with restored ones.
+        let p = restored.create_element("p");
+        assert!(DocumentSnapshot::capture(&document)
+            .tree
+            .nodes
+            .iter()
+            .all(|n| n.id != p.to_raw()));
+        assert_eq!(restored.tree.len(), document.tree.len() + 1);
+
+        let json = serde_json::to_string(&restored.tree).unwrap();
+        let tree: DomTree = serde_json::from_str(&json).unwrap();
+        assert_eq!(tree.get_text_content(div), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_invalid_snapshots_are_rejected() {
+        let (document, div, text, _) = sample();
+        let snapshot = DocumentSnapshot::capture(&document);
+
+        let bytes = snapshot.to_bytes();
+        assert!(matches!(
+            DocumentSnapshot::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SnapshotError::UnexpectedEnd)
+        ));
+        assert!(matches!(
+            DocumentSnapshot::from_bytes(b"HTML"),
+            Err(SnapshotError::BadMagic)
+        ));
+
+        let html = document.document_element.unwrap().to_raw();
+        let mut shared = snapshot.clone();
+        shared
+            .tree
+            .nodes
+            .iter_mut()
+            .find(|n| n.id == div.to_raw())
+            .unwrap()
+            .children
+            .push(html);
+        assert!(matches!(shared.restore(), Err(SnapshotError::MultipleParents(id)) if id == html));
+
+        let mut cyclic = shared.clone();
+        cyclic
+            .tree
+            .nodes
+            .iter_mut()
+            .find(|n| n.id == cyclic.tree.root)
+            .unwrap()
+            .children
+            .clear();
+        assert!(matches!(cyclic.restore(), Err(SnapshotError::Cycle)));
+
+        let mut missing = snapshot.clone();
+        missing.tree.nodes.retain(|n| n.id != text.to_raw());
+        assert!(
+            matches!(missing.restore(), Err(SnapshotError::MissingNode(id)) if id == text.to_raw())
+        );
+
+        // Ids far beyond the node count would make the rebuild allocate and
+        // cycle slots for as long as the snapshot asks.
+        for id in [(0x7fff_ffff << 32) | 1, (1 << 32) | 0xffff_fff0] {
+            let mut forged = snapshot.clone();
+            forged
+                .tree
+                .nodes
+                .iter_mut()
+                .find(|n| n.id == text.to_raw())
+                .unwrap()
+                .id = id;
+            forged
+                .tree
+                .nodes
+                .iter_mut()
+                .find(|n| n.id == div.to_raw())
+                .unwrap()
+                .children = vec![id];
+            assert!(matches!(forged.restore(), Err(SnapshotError::InvalidId(bad)) if bad == id));
+        }
+
+        let mut future = snapshot;
+        future.version += 1;
+        assert!(matches!(
+            DocumentSnapshot::from_json(&future.to_json()),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+    }
+}
@@ -0,0 +1,735 @@
+//! `TreeWalker` and `NodeIterator`.
+//!
+//! The traversal algorithms reach the tree and the node filter through a
+//! [`TraversalSource`], one step at a time, so that nothing is borrowed
+//! while a filter runs: script filters may mutate the tree they walk.
+//! [`DomTree`] is a source that accepts every node, and [`Filtered`] adds a
+//! Rust filter. Node iterators follow removals through [`LiveNodeIterators`].
+
+use crate::mutation::{Mutation, MutationHook, MutationKind};
+use crate::node::NodeId;
+use crate::tree::DomTree;
+use parking_lot::Mutex;
+use std::sync::{Arc, Weak};
+
+/// `whatToShow` bits of `NodeFilter`.
+pub mod what_to_show {
+    pub const SHOW_ALL: u32 = 0xFFFF_FFFF;
+    pub const SHOW_ELEMENT: u32 = 0x1;
+    pub const SHOW_ATTRIBUTE: u32 = 0x2;
+    pub const SHOW_TEXT: u32 = 0x4;
+    pub const SHOW_CDATA_SECTION: u32 = 0x8;
+    pub const SHOW_PROCESSING_INSTRUCTION: u32 = 0x40;
+    pub const SHOW_COMMENT: u32 = 0x80;
+    pub const SHOW_DOCUMENT: u32 = 0x100;
+    pub const SHOW_DOCUMENT_TYPE: u32 = 0x200;
+    pub const SHOW_DOCUMENT_FRAGMENT: u32 = 0x400;
+}
+
+/// The answer of a node filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterResult {
+    Accept = 1,
+    /// Skip the node and its descendants.
+    Reject = 2,
+    /// Skip the node but not its descendants.
+    Skip = 3,
+}
+
+impl FilterResult {
+    /// Convert a script filter's return value; unknown values skip.
+    pub fn from_u16(value: u16) -> Self {
+        match value {
+            1 => FilterResult::Accept,
+            2 => FilterResult::Reject,
+            _ => FilterResult::Skip,
+        }
+    }
+}
+
+/// Tree navigation and filtering for a traversal.
+pub trait TraversalSource {
+    type Error;
+
+    fn parent(&mut self, node: NodeId) -> Option<NodeId>;
+    fn first_child(&mut self, node: NodeId) -> Option<NodeId>;
+    fn last_child(&mut self, node: NodeId) -> Option<NodeId>;
+    fn previous_sibling(&mut self, node: NodeId) -> Option<NodeId>;
+    fn next_sibling(&mut self, node: NodeId) -> Option<NodeId>;
+    /// The `whatToShow` bit of the node's type.
+    fn show_bit(&mut self, node: NodeId) -> u32;
+    /// Run the node filter on a node that `whatToShow` lets through.
+    fn accept(&mut self, node: NodeId) -> Result<FilterResult, Self::Error>;
+}
+
+impl TraversalSource for DomTree {
+    type Error = std::convert::Infallible;
+
+    fn parent(&mut self, node: NodeId) -> Option<NodeId> {
+        DomTree::parent(self, node)
+    }
+
+    fn first_child(&mut self, node: NodeId) -> Option<NodeId> {
+        DomTree::first_child(self, node)
+    }
+
+    fn last_child(&mut self, node: NodeId) -> Option<NodeId> {
+        DomTree::last_child(self, node)
+    }
+
+    fn previous_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        self.prev_sibling(node)
+    }
+
+    fn next_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        DomTree::next_sibling(self, node)
+    }
+
+    fn show_bit(&mut self, node: NodeId) -> u32 {
+        show_bit(self, node)
+    }
+
+    fn accept(&mut self, _node: NodeId) -> Result<FilterResult, Self::Error> {
+        Ok(FilterResult::Accept)
+    }
+}
+
+/// A tree with a Rust node filter.
+pub struct Filtered<'a, F> {
+    pub tree: &'a DomTree,
+    pub filter: F,
+}
+
+impl<F: FnMut(&DomTree, NodeId) -> FilterResult> TraversalSource for Filtered<'_, F> {
+    type Error = std::convert::Infallible;
+
+    fn parent(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree.parent(node)
+    }
+
+    fn first_child(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree.first_child(node)
+    }
+
+    fn last_child(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree.last_child(node)
+    }
+
+    fn previous_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree.prev_sibling(node)
+    }
+
+    fn next_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree.next_sibling(node)
+    }
+
+    fn show_bit(&mut self, node: NodeId) -> u32 {
+        show_bit(self.tree, node)
+    }
+
+    fn accept(&mut self, node: NodeId) -> Result<FilterResult, Self::Error> {
+        Ok((self.filter)(self.tree, node))
+    }
+}
+
+/// The `whatToShow` bit of a node's type.
+pub fn show_bit(tree: &DomTree, node: NodeId) -> u32 {
+    tree.get(node)
+        .map_or(0, |node| 1 << (node.node_type as u32 - 1))
+}
+
+/// Filter `node`: `whatToShow` first, then the source's filter.
+fn filter<S: TraversalSource>(
+    source: &mut S,
+    what_to_show: u32,
+    node: NodeId,
+) -> Result<FilterResult, S::Error> {
+    if what_to_show & source.show_bit(node) == 0 {
+        return Ok(FilterResult::Skip);
+    }
+    source.accept(node)
+}
+
+/// A `TreeWalker`: a cursor over the filtered view of a subtree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TreeWalker {
+    pub root: NodeId,
+    pub what_to_show: u32,
+    pub current: NodeId,
+}
+
+impl TreeWalker {
+    pub fn new(root: NodeId, what_to_show: u32) -> Self {
+        Self {
+            root,
+            what_to_show,
+            current: root,
+        }
+    }
+
+    /// Move to the closest accepted ancestor within the root.
+    pub fn parent_node<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let mut node = Some(self.current);
+        while let Some(current) = node.filter(|&n| n != self.root) {
+            node = source.parent(current);
+            if let Some(parent) = node {
+                if filter(source, self.what_to_show, parent)? == FilterResult::Accept {
+                    self.current = parent;
+                    return Ok(Some(parent));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn first_child<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse_children(source, true)
+    }
+
+    pub fn last_child<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse_children(source, false)
+    }
+
+    pub fn previous_sibling<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse_siblings(source, false)
+    }
+
+    pub fn next_sibling<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse_siblings(source, true)
+    }
+
+    fn traverse_children<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+        first: bool,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let child = |source: &mut S, node| {
+            if first {
+                source.first_child(node)
+            } else {
+                source.last_child(node)
+            }
+        };
+        let sibling = |source: &mut S, node| {
+            if first {
+                source.next_sibling(node)
+            } else {
+                source.previous_sibling(node)
+            }
+        };
+
+        let mut node = child(source, self.current);
+        'outer: while let Some(current) = node {
+            match filter(source, self.what_to_show, current)? {
+                FilterResult::Accept => {
+                    self.current = current;
+                    return Ok(Some(current));
+                }
+                FilterResult::Skip => {
+                    if let Some(grandchild) = child(source, current) {
+                        node = Some(grandchild);
+                        continue;
+                    }
+                }
+                FilterResult::Reject => {}
+            }
+            let mut current = current;
+            loop {
+                if let Some(next) = sibling(source, current) {
+                    node = Some(next);
+                    continue 'outer;
+                }
+                match source.parent(current) {
+                    Some(parent) if parent != self.root && parent != self.current => {
+                        current = parent
+                    }
+                    _ => return Ok(None),
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn traverse_siblings<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+        next: bool,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let sibling = |source: &mut S, node| {
+            if next {
+                source.next_sibling(node)
+            } else {
+                source.previous_sibling(node)
+            }
+        };
+        let child = |source: &mut S, node| {
+            if next {
+                source.first_child(node)
+            } else {
+                source.last_child(node)
+            }
+        };
+
+        let mut node = self.current;
+        if node == self.root {
+            return Ok(None);
+        }
+        loop {
+            let mut candidate = sibling(source, node);
+            while let Some(current) = candidate {
+                node = current;
+                let result = filter(source, self.what_to_show, node)?;
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Ok(Some(node));
+                }
+                candidate = child(source, node);
+                if result == FilterResult::Reject || candidate.is_none() {
+                    candidate = sibling(source, node);
+                }
+            }
+            match source.parent(node) {
+                Some(parent) if parent != self.root => node = parent,
+                _ => return Ok(None),
+            }
+            if filter(source, self.what_to_show, node)? == FilterResult::Accept {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Move to the previous accepted node in tree order.
+    pub fn previous_node<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let mut node = self.current;
+        while node != self.root {
+            let mut sibling = source.previous_sibling(node);
+            while let Some(current) = sibling {
+                node = current;
+                let mut result = filter(source, self.what_to_show, node)?;
+                while result != FilterResult::Reject {
+                    let Some(last) = source.last_child(node) else {
+                        break;
+                    };
+                    node = last;
+                    result = filter(source, self.what_to_show, node)?;
+                }
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Ok(Some(node));
+                }
+                sibling = source.previous_sibling(node);
+            }
+            let Some(parent) = source.parent(node).filter(|_| node != self.root) else {
+                return Ok(None);
+            };
+            node = parent;
+            if filter(source, self.what_to_show, node)? == FilterResult::Accept {
+                self.current = node;
+                return Ok(Some(node));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Move to the next accepted node in tree order.
+    pub fn next_node<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let mut node = self.current;
+        let mut result = FilterResult::Accept;
+        loop {
+            while result != FilterResult::Reject {
+                let Some(first) = source.first_child(node) else {
+                    break;
+                };
+                node = first;
+                result = filter(source, self.what_to_show, node)?;
+                if result == FilterResult::Accept {
+                    self.current = node;
+                    return Ok(Some(node));
+                }
+            }
+            let mut temporary = Some(node);
+            let mut sibling = None;
+            while let Some(current) = temporary {
+                if current == self.root {
+                    return Ok(None);
+                }
+                sibling = source.next_sibling(current);
+                if sibling.is_some() {
+                    break;
+                }
+                temporary = source.parent(current);
+            }
+            let Some(next) = sibling else {
+                return Ok(None);
+            };
+            node = next;
+            result = filter(source, self.what_to_show, node)?;
+            if result == FilterResult::Accept {
+                self.current = node;
+                return Ok(Some(node));
+            }
+        }
+    }
+}
+
+/// A `NodeIterator`: a position between two nodes of a subtree in tree
+/// order, which keeps its place when nodes are removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeIterator {
+    pub root: NodeId,
+    pub what_to_show: u32,
+    pub reference: NodeId,
+    pub pointer_before_reference: bool,
+}
+
+impl NodeIterator {
+    pub fn new(root: NodeId, what_to_show: u32) -> Self {
+        Self {
+            root,
+            what_to_show,
+            reference: root,
+            pointer_before_reference: true,
+        }
+    }
+
+    pub fn next_node<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse(source, true)
+    }
+
+    pub fn previous_node<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+    ) -> Result<Option<NodeId>, S::Error> {
+        self.traverse(source, false)
+    }
+
+    fn traverse<S: TraversalSource>(
+        &mut self,
+        source: &mut S,
+        next: bool,
+    ) -> Result<Option<NodeId>, S::Error> {
+        let mut node = self.reference;
+        let mut before_node = self.pointer_before_reference;
+        loop {
+            if next {
+                if before_node {
+                    before_node = false;
+                } else {
+                    match following(source, node, self.root, true) {
+                        Some(following) => node = following,
+                        None => return Ok(None),
+                    }
+                }
+            } else if !before_node {
+                before_node = true;
+            } else {
+                match preceding(source, node, self.root) {
+                    Some(preceding) => node = preceding,
+                    None => return Ok(None),
+                }
+            }
+            if filter(source, self.what_to_show, node)? == FilterResult::Accept {
+                break;
+            }
+        }
+        self.reference = node;
+        self.pointer_before_reference = before_node;
+        Ok(Some(node))
+    }
+
+    /// Keep the iterator's place across a removal, once the removed nodes
+    /// are detached (the "pre-removing steps", run after the fact).
+    pub fn update(&mut self, tree: &DomTree, mutation: &Mutation) {
+        if mutation.kind != MutationKind::ChildList {
+            return;
+        }
+        for &removed in &mutation.removed_nodes {
+            if !tree.is_inclusive_ancestor(removed, self.reference)
+                || tree.is_inclusive_ancestor(removed, self.root)
+            {
+                continue;
+            }
+            if self.pointer_before_reference {
+                // The first node following the removed subtree.
+                let next = mutation.next_sibling.or_else(|| {
+                    std::iter::once(mutation.target)
+                        .chain(tree.ancestors(mutation.target))
+                        .take_while(|&node| node != self.root)
+                        .find_map(|node| tree.next_sibling(node))
+                });
+                if let Some(next) = next {
+                    self.reference = next;
+                    continue;
+                }
+                self.pointer_before_reference = false;
+            }
+            self.reference = match mutation.previous_sibling {
+                Some(mut last) => {
+                    while let Some(child) = tree.last_child(last) {
+                        last = child;
+                    }
+                    last
+                }
+                None => mutation.target,
+            };
+        }
+    }
+}
+
+/// The node after `node` in tree order within `root`, descending into
+/// `node`'s children only if `descend` is set.
+fn following<S: TraversalSource>(
+    source: &mut S,
+    node: NodeId,
+    root: NodeId,
+    descend: bool,
+) -> Option<NodeId> {
+    if descend {
+        if let Some(child) = source.first_child(node) {
+            return Some(child);
+        }
+    }
+    let mut current = node;
+    while current != root {
+        if let Some(sibling) = source.next_sibling(current) {
+            return Some(sibling);
+        }
+        current = source.parent(current)?;
+    }
+    None
+}
+
+/// The node before `node` in tree order within `root`.
+fn preceding<S: TraversalSource>(source: &mut S, node: NodeId, root: NodeId) -> Option<NodeId> {
+    if node == root {
+        return None;
+    }
+    match source.previous_sibling(node) {
+        Some(mut last) => {
+            while let Some(child) = source.last_child(last) {
+                last = child;
+            }
+            Some(last)
+        }
+        None => source.parent(node),
+    }
+}
+
+/// Node iterators kept in place as their tree changes. Registered as a
+/// mutation hook; iterators are dropped once no longer held.
+#[derive(Default)]
+pub struct LiveNodeIterators {
+    iterators: Mutex<Vec<Weak<Mutex<NodeIterator>>>>,
+}
+
+impl LiveNodeIterators {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start keeping `iterator` live.
+    pub fn track(&self, iterator: NodeIterator) -> Arc<Mutex<NodeIterator>> {
+        let iterator = Arc::new(Mutex::new(iterator));
+        self.iterators.lock().push(Arc::downgrade(&iterator));
+        iterator
+    }
+}
+
+impl MutationHook for LiveNodeIterators {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        self.iterators
+            .lock()
+            .retain(|iterator| match iterator.upgrade() {
+                Some(iterator) => {
+                    iterator.lock().update(tree, mutation);
+                    true
+                }
+                None => false,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::element::{ElementData, TagName};
+
+    /// `<div><p>a</p><span><b></b></span><i></i></div>`
+    fn sample() -> (DomTree, Vec<NodeId>) {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let element = |tree: &mut DomTree, parent, tag| {
+            let node = tree.create_element(ElementData::new(TagName::new(tag)));
+            tree.append_child(parent, node);
+            node
+        };
+        let div = element(&mut tree, root, "div");
+        let p = element(&mut tree, div, "p");
+        let text = tree.create_text("a".to_string());
+        tree.append_child(p, text);
+        let span = element(&mut tree, div, "span");
+        let b = element(&mut tree, span, "b");
+        let i = element(&mut tree, div, "i");
+        (tree, vec![div, p, text, span, b, i])
+    }
+
+    #[test]
+    fn test_tree_walker_with_filter() {
+        let (tree, nodes) = sample();
+        let [div, p, text, span, b, i] = nodes[..] else {
+            unreachable!()
+        };
+
+        let mut walker = TreeWalker::new(div, what_to_show::SHOW_ELEMENT);
+        let mut all = tree;
+        let mut order = Vec::new();
+        while let Ok(Some(node)) = walker.next_node(&mut all) {
+            order.push(node);
+        }
+        assert_eq!(order, vec![p, span, b, i]);
+        assert_eq!(walker.previous_node(&mut all), Ok(Some(b)));
+        assert_eq!(walker.parent_node(&mut all), Ok(Some(span)));
+        assert_eq!(walker.previous_sibling(&mut all), Ok(Some(p)));
+        assert_eq!(walker.first_child(&mut all), Ok(None));
+
+        let tree = all;
+        // Skipping the span exposes its child; rejecting it hides both.
+        let mut skip = Filtered {
+            tree: &tree,
+            filter: |_: &DomTree, node| {
+                if node == span {
+                    FilterResult::Skip
+                } else {
+                    FilterResult::Accept
+                }
+            },
+        };
+        let mut walker = TreeWalker::new(div, what_to_show::SHOW_ALL);
+        walker.current = p;
+        assert_eq!(walker.next_sibling(&mut skip), Ok(Some(b)));
+        assert_eq!(walker.next_sibling(&mut skip), Ok(Some(i)));
+        let mut reject = Filtered {
+            tree: &tree,
+            filter: |_: &DomTree, node| {
+                if node == span {
+                    FilterResult::Reject
+                } else {
+                    FilterResult::Accept
+                }
+            },
+        };
+        walker.current = div;
+        let mut order = Vec::new();
+        while let Ok(Some(node)) = walker.next_node(&mut reject) {
+            order.push(node);
+        }
+        assert_eq!(order, vec![p, text, i]);
+    }
+
+    #[test]
+    fn test_node_iterator_survives_removal() {
+        let (mut tree, nodes) = sample();
+        let [div, p, text, span, b, i] = nodes[..] else {
+            unreachable!()
+        };
+        let live = Arc::new(LiveNodeIterators::new());
+        tree.add_mutation_hook(live.clone());
+        let iterator = live.track(NodeIterator::new(div, what_to_show::SHOW_ELEMENT));
+
+        let next = |tree: &mut DomTree| iterator.lock().next_node(tree).unwrap();
+        assert_eq!(next(&mut tree), Some(div));
+        assert_eq!(next(&mut tree), Some(p));
+        assert_eq!(next(&mut tree), Some(span));
+        assert_eq!(next(&mut tree), Some(b));
+
+        // Removing the reference's ancestor moves the reference to the
+        // node before it, whether shown or not.
+        tree.remove_from_parent(span);
+        assert_eq!(iterator.lock().reference, text);
+        assert_eq!(next(&mut tree), Some(i));
+        assert_eq!(iterator.lock().previous_node(&mut tree).unwrap(), Some(i));
+        assert_eq!(iterator.lock().previous_node(&mut tree).unwrap(), Some(p));
+        assert!(iterator.lock().pointer_before_reference);
+
+        // With the pointer before the reference, it moves to the next node.
+        tree.remove_from_parent(p);
+        assert_eq!(iterator.lock().reference, i);
+        assert_eq!(next(&mut tree), Some(i));
+        assert_eq!(next(&mut tree), None);
+    }
+
+    #[test]
+    fn test_node_iterator_reference_adjustment() {
+        let (mut tree, nodes) = sample();
+        let [div, p, text, span, b, i] = nodes[..] else {
+            unreachable!()
+        };
+        let live = Arc::new(LiveNodeIterators::new());
+        tree.add_mutation_hook(live.clone());
+        let iterator = live.track(NodeIterator::new(div, what_to_show::SHOW_ALL));
+        let state = || {
+            let iterator = iterator.lock();
+            (iterator.reference, iterator.pointer_before_reference)
+        };
+
+        let mut all = Vec::new();
+        while let Some(node) = iterator.lock().next_node(&mut tree).unwrap() {
+            all.push(node);
+            if node == b {
+                break;
+            }
+        }
+        assert_eq!(all, vec![div, p, text, span, b]);
+        assert_eq!(iterator.lock().previous_node(&mut tree).unwrap(), Some(b));
+
+        // Before a removed last child, the reference moves to the next
+        // sibling of the nearest ancestor that has one.
+        tree.remove_from_parent(b);
+        assert_eq!(state(), (i, true));
+        // Removing nodes that don't contain the reference changes nothing.
+        tree.remove_from_parent(p);
+        assert_eq!(state(), (i, true));
+
+        // After a removed reference, it moves to the last inclusive
+        // descendant of the previous sibling.
+        let em = tree.create_element(ElementData::new(TagName::new("em")));
+        tree.append_child(span, em);
+        assert_eq!(iterator.lock().next_node(&mut tree).unwrap(), Some(i));
+        tree.remove_from_parent(i);
+        assert_eq!(state(), (em, false));
+        assert_eq!(iterator.lock().next_node(&mut tree).unwrap(), None);
+
+        // Without a previous sibling it moves to the parent; removing the
+        // root itself changes nothing.
+        tree.remove_from_parent(span);
+        assert_eq!(state(), (div, false));
+        tree.remove_from_parent(div);
+        assert_eq!(state(), (div, false));
+        assert_eq!(iterator.lock().previous_node(&mut tree).unwrap(), Some(div));
+        assert_eq!(iterator.lock().previous_node(&mut tree).unwrap(), None);
+    }
+}
@@ -1,11 +1,14 @@
 //! DOM Tree implementation.
 
-use crate::element::{ElementData, TagName};
+use crate::element::{ElementData, ShadowRoot, TagName};
+use crate::mutation::{Mutation, MutationHook, MutationHookId};
 use crate::node::{Node, NodeData, NodeId, NodeType};
-use parking_lot::RwLock;
+use crate::shadow::SlotAssignment;
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use slotmap::SlotMap;
 use smallvec::SmallVec;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 /// The DOM tree structure.
@@ -14,8 +17,22 @@ pub struct DomTree {
     nodes: SlotMap<NodeId, Node>,
     /// Root node (document).
     root: Option<NodeId>,
-    /// ID to node mapping for fast lookups.
-    id_map: HashMap<String, NodeId>,
+    /// Id and class index for fast lookups.
+    index: RwLock<SelectorIndex>,
+    /// Registered mutation hooks.
+    hooks: Vec<(MutationHookId, Arc<dyn MutationHook>)>,
+    next_hook_id: u64,
+    /// Node borrowed mutably since the last flush, with its prior state.
+    pending: Option<(NodeId, NodeSnapshot)>,
+    /// Host element of each shadow root.
+    pub(crate) shadow_hosts: HashMap<NodeId, NodeId>,
+    /// `<template>` element owning each template contents fragment.
+    template_hosts: HashMap<NodeId, NodeId>,
+    /// Slot assignment of each shadow root, computed on demand.
+    pub(crate) slot_assignments: RwLock<HashMap<NodeId, SlotAssignment>>,
+    /// Bumped by every change, whether or not hooks are registered, for
+    /// caches of tree queries.
+    generation: u64,
 }
 
 impl DomTree {
@@ -23,7 +40,14 @@ impl DomTree {
         let mut tree = Self {
             nodes: SlotMap::with_key(),
             root: None,
-            id_map: HashMap::new(),
+            index: RwLock::new(SelectorIndex::default()),
+            hooks: Vec::new(),
+            next_hook_id: 0,
+            pending: None,
+            shadow_hosts: HashMap::new(),
+            template_hosts: HashMap::new(),
+            slot_assignments: RwLock::default(),
+            generation: 0,
         };
         // Create document node
         let root_id = tree.nodes.insert_with_key(|id| Node::new_document(id));
@@ -31,6 +55,80 @@ impl DomTree {
         tree
     }
 
+    /// Rebuild a tree from nodes that keep their ids, as restored from a
+    /// snapshot. Parent and sibling links are derived from the child lists,
+    /// which the caller has checked to form a forest; ids must be occupied
+    /// slot map keys (odd versions), with indices and versions bounded so
+    /// that filling and cycling slots stays cheap.
+    pub(crate) fn from_nodes(root: NodeId, mut nodes: Vec<Node>) -> Self {
+        nodes.sort_by_key(|node| node.id.to_raw() as u32);
+        let mut slots: SlotMap<NodeId, Node> = SlotMap::with_key();
+        let mut gaps = Vec::new();
+        for node in nodes {
+            let index = node.id.to_raw() as u32;
+            let mut key = slots.insert_with_key(Node::new_document_fragment);
+            while (key.to_raw() as u32) < index {
+                gaps.push(key);
+                key = slots.insert_with_key(Node::new_document_fragment);
+            }
+            // A slot's version grows by two with each reuse; freed slots are
+            // reused first, so cycling one reaches the wanted version.
+            while key != node.id {
+                slots.remove(key);
+                key = slots.insert_with_key(Node::new_document_fragment);
+            }
+            slots[key] = node;
+        }
+        for key in gaps {
+            slots.remove(key);
+        }
+
+        let mut links = Vec::new();
+        let mut shadow_hosts = HashMap::new();
+        let mut template_hosts = HashMap::new();
+        for (id, node) in slots.iter() {
+            for (i, &child) in node.children.iter().enumerate() {
+                let prev = i.checked_sub(1).map(|i| node.children[i]);
+                links.push((child, id, prev, node.children.get(i + 1).copied()));
+            }
+            if let Some(shadow) = node.as_element().and_then(|e| e.shadow_root.as_ref()) {
+                shadow_hosts.insert(shadow.root, id);
+            }
+            if let Some(contents) = node.as_element().and_then(|e| e.template_contents) {
+                template_hosts.insert(contents, id);
+            }
+        }
+        for (child, parent, prev, next) in links {
+            let node = &mut slots[child];
+            node.parent = Some(parent);
+            node.prev_sibling = prev;
+            node.next_sibling = next;
+        }
+
+        Self {
+            nodes: slots,
+            root: Some(root),
+            index: RwLock::new(SelectorIndex {
+                rebuild: true,
+                ..SelectorIndex::default()
+            }),
+            hooks: Vec::new(),
+            next_hook_id: 0,
+            pending: None,
+            shadow_hosts,
+            template_hosts,
+            slot_assignments: RwLock::default(),
+            generation: 0,
+        }
+    }
+
+    /// A counter that changes whenever the tree may have changed, including
+    /// through [`get_mut`](Self::get_mut). Cached query results are valid
+    /// while it stays the same.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// Get the root document node.
     pub fn root(&self) -> Option<NodeId> {
         self.root
@@ -43,6 +141,8 @@ impl DomTree {
 
     /// Get a mutable node by ID.
     pub fn get_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.watch(id);
+        self.mark_stale(id);
         self.nodes.get_mut(id)
     }
 
@@ -53,21 +153,64 @@ impl DomTree {
 
     /// Get mutable element data for a node.
     pub fn get_element_mut(&mut self, id: NodeId) -> Option<&mut ElementData> {
+        self.watch(id);
+        self.mark_stale(id);
         self.nodes.get_mut(id).and_then(|n| n.as_element_mut())
     }
 
+    /// Set an attribute of an element, reporting the change even if the
+    /// value stays the same.
+    pub fn set_attribute(&mut self, node: NodeId, name: &str, value: &str) {
+        self.flush_mutations();
+        let elem = match self.nodes.get_mut(node).and_then(|n| n.as_element_mut()) {
+            Some(elem) => elem,
+            None => return,
+        };
+        let name = name.to_ascii_lowercase();
+        let old_value = elem.get_attribute(&name).map(str::to_string);
+        elem.set_attribute(&name, value);
+        self.mark_stale(node);
+        self.notify(&Mutation::attribute(node, &name, old_value));
+    }
+
+    /// Remove an attribute of an element. Returns false, reporting nothing,
+    /// if it was not set.
+    pub fn remove_attribute(&mut self, node: NodeId, name: &str) -> bool {
+        self.flush_mutations();
+        let elem = match self.nodes.get_mut(node).and_then(|n| n.as_element_mut()) {
+            Some(elem) => elem,
+            None => return false,
+        };
+        let name = name.to_ascii_lowercase();
+        let old_value = match elem.get_attribute(&name) {
+            Some(value) => value.to_string(),
+            None => return false,
+        };
+        elem.remove_attribute(&name);
+        self.mark_stale(node);
+        self.notify(&Mutation::attribute(node, &name, Some(old_value)));
+        true
+    }
+
     /// Create an element node.
-    pub fn create_element(&mut self, data: ElementData) -> NodeId {
-        let id_value = data.id.clone();
+    ///
+    /// HTML `<template>` elements get an empty contents fragment.
+    pub fn create_element(&mut self, mut data: ElementData) -> NodeId {
+        if data.template_contents.is_none()
+            && data.namespace.is_none()
+            && data.tag_name == "template"
+        {
+            data.template_contents = Some(self.create_document_fragment());
+        }
+
+        let contents = data.template_contents;
         let id = self
             .nodes
             .insert_with_key(|id| Node::new_element(id, data));
-
-        // Update ID map
-        if let Some(elem_id) = id_value {
-            self.id_map.insert(elem_id.to_string(), id);
+        if let Some(contents) = contents {
+            self.template_hosts.insert(contents, id);
         }
-
+        self.mark_stale(id);
         id
     }
 
@@ -91,84 +234,143 @@ impl DomTree {
 
     /// Append a child to a parent node.
     pub fn append_child(&mut self, parent: NodeId, child: NodeId) {
+        self.insert_before(parent, child, None);
+    }
+
+    /// Insert a child before a reference node.
+    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
+        let reference = match reference {
+            Some(ref_id) if ref_id == child => self.next_sibling(child),
+            reference => reference,
+        };
         // Remove from old parent if any
         self.remove_from_parent(child);
+        self.attach(parent, child, reference);
 
-        // Update sibling links
-        if let Some(parent_node) = self.nodes.get(parent) {
-            if let Some(last_child) = parent_node.last_child() {
-                if let Some(last) = self.nodes.get_mut(last_child) {
-                    last.next_sibling = Some(child);
-                }
-                if let Some(child_node) = self.nodes.get_mut(child) {
-                    child_node.prev_sibling = Some(last_child);
-                }
-            }
-        }
-
-        // Add to parent
-        if let Some(parent_node) = self.nodes.get_mut(parent) {
-            parent_node.children.push(child);
+        if !self.hooks.is_empty() {
+            let (prev, next) = (self.prev_sibling(child), self.next_sibling(child));
+            self.notify(&Mutation::child_list(parent, vec![child], Vec::new(), prev, next));
         }
+    }
 
-        if let Some(child_node) = self.nodes.get_mut(child) {
-            child_node.parent = Some(parent);
-            child_node.next_sibling = None;
+    /// Remove a node from its parent.
+    pub fn remove_from_parent(&mut self, node: NodeId) {
+        self.flush_mutations();
+        if let Some((parent, prev, next)) = self.detach(node) {
+            self.notify(&Mutation::child_list(parent, Vec::new(), vec![node], prev, next));
         }
-
-        // Update ID map if needed
-        self.update_id_map(child);
     }
 
-    /// Insert a child before a reference node.
-    pub fn insert_before(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
-        match reference {
-            None => self.append_child(parent, child),
-            Some(ref_id) => {
-                self.remove_from_parent(child);
-
-                // Find position in parent's children
-                if let Some(parent_node) = self.nodes.get_mut(parent) {
-                    if let Some(pos) = parent_node.children.iter().position(|&id| id == ref_id) {
-                        parent_node.children.insert(pos, child);
-                    }
-                }
+    /// Remove a node and its subtree from the tree.
+    pub fn remove(&mut self, node: NodeId) {
+        self.remove_from_parent(node);
+        self.drop_subtree(node);
+    }
 
-                // Update sibling links
-                if let Some(ref_node) = self.nodes.get(ref_id) {
-                    let prev = ref_node.prev_sibling;
+    /// Replace a node with another.
+    pub fn replace_child(&mut self, parent: NodeId, new_child: NodeId, old_child: NodeId) {
+        if new_child == old_child || self.parent(old_child) != Some(parent) {
+            return;
+        }
+        self.remove_from_parent(new_child);
+        if let Some((_, prev, next)) = self.detach(old_child) {
+            self.attach(parent, new_child, next);
+            self.notify(&Mutation::child_list(parent, vec![new_child], vec![old_child], prev, next));
+            self.drop_subtree(old_child);
+        }
+    }
 
-                    if let Some(prev_id) = prev {
-                        if let Some(prev_node) = self.nodes.get_mut(prev_id) {
-                            prev_node.next_sibling = Some(child);
-                        }
-                    }
+    /// Move all children of `from` into `parent` before `reference`,
+    /// reported as a single insertion like inserting a `DocumentFragment`.
+    pub fn move_children(&mut self, from: NodeId, parent: NodeId, reference: Option<NodeId>) {
+        self.flush_mutations();
+        let children = self.take_children(from);
+        if children.is_empty() {
+            return;
+        }
+        for &child in &children {
+            self.attach(parent, child, reference);
+        }
+        if !self.hooks.is_empty() {
+            let prev = self.prev_sibling(children[0]);
+            let next = self.next_sibling(children[children.len() - 1]);
+            self.notify(&Mutation::child_list(parent, children, Vec::new(), prev, next));
+        }
+    }
 
-                    if let Some(child_node) = self.nodes.get_mut(child) {
-                        child_node.parent = Some(parent);
-                        child_node.prev_sibling = prev;
-                        child_node.next_sibling = Some(ref_id);
-                    }
+    /// Replace all children of `parent` with the children of `from`,
+    /// dropping the old ones and reporting a single mutation.
+    pub fn replace_children(&mut self, parent: NodeId, from: NodeId) {
+        self.flush_mutations();
+        let added = self.take_children(from);
+        let removed: Vec<NodeId> = self.children(parent).collect();
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+        for &child in &removed {
+            self.detach(child);
+        }
+        for &child in &added {
+            self.attach(parent, child, None);
+        }
+        // Hooks still see the removed subtrees.
+        self.notify(&Mutation::child_list(parent, added, removed.clone(), None, None));
+        for child in removed {
+            self.drop_subtree(child);
+        }
+    }
 
-                    if let Some(ref_node) = self.nodes.get_mut(ref_id) {
-                        ref_node.prev_sibling = Some(child);
-                    }
-                }
+    /// Detach all children of `node`, reported as one removal.
+    fn take_children(&mut self, node: NodeId) -> Vec<NodeId> {
+        let children: Vec<NodeId> = self.children(node).collect();
+        if !children.is_empty() {
+            for &child in &children {
+                self.detach(child);
+            }
+            self.notify(&Mutation::child_list(node, Vec::new(), children.clone(), None, None));
+        }
+        children
+    }
 
-                self.update_id_map(child);
+    /// Link a detached node into `parent` before `reference`, or last if
+    /// `reference` is not a child of `parent`.
+    fn attach(&mut self, parent: NodeId, child: NodeId, reference: Option<NodeId>) {
+        self.generation += 1;
+        let (prev, next) = match self.nodes.get_mut(parent) {
+            Some(parent_node) => {
+                let pos = reference
+                    .and_then(|ref_id| parent_node.children.iter().position(|&id| id == ref_id))
+                    .unwrap_or(parent_node.children.len());
+                let prev = pos.checked_sub(1).map(|i| parent_node.children[i]);
+                let next = parent_node.children.get(pos).copied();
+                parent_node.children.insert(pos, child);
+                (prev, next)
             }
+            None => return,
+        };
+
+        // Update sibling links
+        if let Some(prev_node) = prev.and_then(|id| self.nodes.get_mut(id)) {
+            prev_node.next_sibling = Some(child);
+        }
+        if let Some(next_node) = next.and_then(|id| self.nodes.get_mut(id)) {
+            next_node.prev_sibling = Some(child);
+        }
+        if let Some(child_node) = self.nodes.get_mut(child) {
+            child_node.parent = Some(parent);
+            child_node.prev_sibling = prev;
+            child_node.next_sibling = next;
         }
     }
 
-    /// Remove a node from its parent.
-    pub fn remove_from_parent(&mut self, node: NodeId) {
+    /// Unlink a node from its parent without reporting it, returning the
+    /// parent and the node's former siblings.
+    fn detach(&mut self, node: NodeId) -> Option<(NodeId, Option<NodeId>, Option<NodeId>)> {
         let (parent, prev, next) = {
-            let node_data = match self.nodes.get(node) {
-                Some(n) => n,
-                None => return,
-            };
+            let node_data = self.nodes.get(node)?;
             (node_data.parent, node_data.prev_sibling, node_data.next_sibling)
         };
+        self.generation += 1;
 
         // Update parent's children list
         if let Some(parent_id) = parent {
@@ -195,44 +397,91 @@ impl DomTree {
             node_data.prev_sibling = None;
             node_data.next_sibling = None;
         }
-    }
 
-    /// Remove a node and its subtree from the tree.
-    pub fn remove(&mut self, node: NodeId) {
-        // First remove from parent
-        self.remove_from_parent(node);
+        parent.map(|parent| (parent, prev, next))
+    }
 
-        // Collect all descendant IDs
+    /// Drop a detached node and its subtree, including template contents
+    /// and shadow trees.
+    fn drop_subtree(&mut self, node: NodeId) {
         let mut to_remove = vec![node];
         let mut i = 0;
         while i < to_remove.len() {
             if let Some(n) = self.nodes.get(to_remove[i]) {
                 to_remove.extend(n.children.iter().copied());
+                if let Some(elem) = n.as_element() {
+                    to_remove.extend(elem.template_contents);
+                    to_remove.extend(elem.shadow_root.as_ref().map(|s| s.root));
+                }
             }
             i += 1;
         }
 
-        // Remove from ID map
+        to_remove.retain(|&id| self.nodes.remove(id).is_some());
         for &id in &to_remove {
-            if let Some(node) = self.nodes.get(id) {
-                if let Some(elem) = node.as_element() {
-                    if let Some(elem_id) = &elem.id {
-                        self.id_map.remove(elem_id.as_ref());
-                    }
-                }
+            self.mark_stale(id);
+            self.template_hosts.remove(&id);
+            if self.shadow_hosts.remove(&id).is_some() {
+                self.slot_assignments.get_mut().remove(&id);
+            }
+        }
+        for (_, hook) in &self.hooks {
+            hook.dropped(self, &to_remove);
+        }
+    }
+
+    /// Register a hook that is told about every mutation of this tree.
+    pub fn add_mutation_hook(&mut self, hook: Arc<dyn MutationHook>) -> MutationHookId {
+        self.flush_mutations();
+        let id = MutationHookId(self.next_hook_id);
+        self.next_hook_id += 1;
+        self.hooks.push((id, hook));
+        id
+    }
+
+    /// Unregister a hook. Returns false if it was not registered.
+    pub fn remove_mutation_hook(&mut self, id: MutationHookId) -> bool {
+        self.flush_mutations();
+        let len = self.hooks.len();
+        self.hooks.retain(|(hook_id, _)| *hook_id != id);
+        self.hooks.len() != len
+    }
+
+    /// Report attribute and character data changes made through
+    /// [`get_mut`](Self::get_mut) or [`get_element_mut`](Self::get_element_mut).
+    ///
+    /// Such changes are found by comparing against a snapshot taken when the
+    /// node was borrowed, so they are reported when the next mutation starts
+    /// or when this is called. Setting a value to itself is not reported;
+    /// [`set_attribute`](Self::set_attribute) reports every set.
+    pub fn flush_mutations(&mut self) {
+        let (id, snapshot) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        if let Some(node) = self.nodes.get(id) {
+            for mutation in snapshot.changes(id, node) {
+                self.notify(&mutation);
             }
         }
+    }
 
-        // Remove nodes
-        for id in to_remove {
-            self.nodes.remove(id);
+    /// Snapshot a node that is about to be borrowed mutably.
+    fn watch(&mut self, id: NodeId) {
+        if self.hooks.is_empty() {
+            return;
+        }
+        self.flush_mutations();
+        if let Some(snapshot) = self.nodes.get(id).and_then(NodeSnapshot::take) {
+            self.pending = Some((id, snapshot));
         }
     }
 
-    /// Replace a node with another.
-    pub fn replace_child(&mut self, parent: NodeId, new_child: NodeId, old_child: NodeId) {
-        self.insert_before(parent, new_child, Some(old_child));
-        self.remove(old_child);
+    fn notify(&mut self, mutation: &Mutation) {
+        self.generation += 1;
+        for (_, hook) in &self.hooks {
+            hook.mutated(self, mutation);
+        }
     }
 
     /// Clone a node (optionally deep).
@@ -249,6 +498,44 @@ impl DomTree {
             new_node
         });
 
+        // Template contents are always copied; shadow trees only when clonable.
+        if let Some(elem) = node_data.as_element() {
+            let contents = match elem.template_contents {
+                Some(contents) => {
+                    let new_contents = self.create_document_fragment();
+                    if deep {
+                        let children: Vec<NodeId> = self.children(contents).collect();
+                        for child in children {
+                            if let Some(cloned_child) = self.clone_node(child, true) {
+                                self.attach(new_contents, cloned_child, None);
+                            }
+                        }
+                    }
+                    Some(new_contents)
+                }
+                None => None,
+            };
+            let shadow_root = match &elem.shadow_root {
+                Some(shadow) if shadow.clonable => {
+                    self.clone_node(shadow.root, true).map(|root| ShadowRoot {
+                        root,
+                        ..shadow.clone()
+                    })
+                }
+                _ => None,
+            };
+            if let Some(new_elem) = self.get_element_mut(new_id) {
+                new_elem.template_contents = contents;
+                new_elem.shadow_root = None;
+            }
+            if let Some(contents) = contents {
+                self.template_hosts.insert(contents, new_id);
+            }
+            if let Some(shadow_root) = shadow_root {
+                self.set_shadow_root(new_id, shadow_root);
+            }
+        }
+
         if deep {
             let children: SmallVec<[NodeId; 8]> = self.nodes.get(node)
                 .map(|n| n.children.clone())
@@ -256,7 +543,7 @@ impl DomTree {
 
             for child in children {
                 if let Some(cloned_child) = self.clone_node(child, true) {
-                    self.append_child(new_id, cloned_child);
+                    self.attach(new_id, cloned_child, None);
                 }
             }
         }
@@ -264,6 +551,80 @@ impl DomTree {
         Some(new_id)
     }
 
+    /// Deep-copy a node from another tree into this one, returning the detached copy.
+    pub fn import_subtree(&mut self, source: &DomTree, node: NodeId) -> Option<NodeId> {
+        let data = source.get(node)?.data.clone();
+        let new_id = match data {
+            NodeData::Element(mut elem) => {
+                let contents = elem.template_contents.take();
+                let shadow_root = elem.shadow_root.take();
+                let new_id = self.create_element(elem);
+
+                if let (Some(contents), Some(new_contents)) =
+                    (contents, self.template_contents(new_id))
+                {
+                    for child in source.children(contents) {
+                        if let Some(imported) = self.import_subtree(source, child) {
+                            self.attach(new_contents, imported, None);
+                        }
+                    }
+                }
+                if let Some(mut shadow) = shadow_root {
+                    if let Some(root) = self.import_subtree(source, shadow.root) {
+                        shadow.root = root;
+                        self.set_shadow_root(new_id, shadow);
+                    }
+                }
+                new_id
+            }
+            data => {
+                let node_type = source.get(node)?.node_type;
+                self.nodes
+                    .insert_with_key(|id| Node::new(id, node_type, data))
+            }
+        };
+
+        for child in source.children(node) {
+            if let Some(imported) = self.import_subtree(source, child) {
+                self.attach(new_id, imported, None);
+            }
+        }
+
+        Some(new_id)
+    }
+
+    /// Get the contents fragment of a `<template>` element.
+    pub fn template_contents(&self, node: NodeId) -> Option<NodeId> {
+        self.get_element(node).and_then(|e| e.template_contents)
+    }
+
+    /// Get the `<template>` element whose contents fragment is `contents`.
+    pub fn template_host(&self, contents: NodeId) -> Option<NodeId> {
+        self.template_hosts
+            .get(&contents)
+            .copied()
+            .filter(|&template| self.template_contents(template) == Some(contents))
+    }
+
+    /// Get the shadow root attached to a host element.
+    pub fn shadow_root(&self, host: NodeId) -> Option<&ShadowRoot> {
+        self.get_element(host).and_then(|e| e.shadow_root.as_ref())
+    }
+
+    /// Remove all children of a node, dropping their subtrees.
+    pub fn remove_children(&mut self, node: NodeId) {
+        self.flush_mutations();
+        let children: Vec<NodeId> = self.children(node).collect();
+        if children.is_empty() {
+            return;
+        }
+        for &child in &children {
+            self.detach(child);
+            self.drop_subtree(child);
+        }
+        self.notify(&Mutation::child_list(node, Vec::new(), children, None, None));
+    }
+
     /// Get parent node.
     pub fn parent(&self, node: NodeId) -> Option<NodeId> {
         self.nodes.get(node).and_then(|n| n.parent)
@@ -317,8 +678,113 @@ impl DomTree {
     }
 
     /// Find element by ID.
+    ///
+    /// Prefers the first match in the document, in tree order, over
+    /// detached elements.
     pub fn find_element_by_id(&self, id: &str) -> Option<NodeId> {
-        self.id_map.get(id).copied()
+        let mut candidates = self.elements_with_id(id);
+        self.sort_in_tree_order(&mut candidates);
+        candidates
+            .iter()
+            .copied()
+            .find(|&node| self.is_connected(node))
+            .or_else(|| candidates.first().copied())
+    }
+
+    /// All elements with the given id, connected or not, in no particular order.
+    pub fn elements_with_id(&self, id: &str) -> Vec<NodeId> {
+        self.selector_index()
+            .ids
+            .get(id)
+            .map(|nodes| nodes.to_vec())
+            .unwrap_or_default()
+    }
+
+    /// All elements with the given class, connected or not, in no particular order.
+    pub fn elements_with_class(&self, class: &str) -> Vec<NodeId> {
+        self.selector_index()
+            .classes
+            .get(class)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether `node` is in the document, i.e. has the root as an inclusive ancestor.
+    pub fn is_connected(&self, node: NodeId) -> bool {
+        self.root.is_some_and(|root| self.is_inclusive_ancestor(root, node))
+    }
+
+    /// Whether `ancestor` is `node` or one of its ancestors.
+    pub fn is_inclusive_ancestor(&self, ancestor: NodeId, node: NodeId) -> bool {
+        node == ancestor || self.ancestors(node).any(|a| a == ancestor)
+    }
+
+    /// Compare two nodes by tree order (pre-order, depth-first).
+    ///
+    /// Nodes in different trees compare by their roots' positions, which is
+    /// arbitrary but consistent.
+    pub fn compare_tree_order(&self, a: NodeId, b: NodeId) -> Ordering {
+        if a == b {
+            return Ordering::Equal;
+        }
+        let path_a = self.tree_path(a);
+        let path_b = self.tree_path(b);
+        path_a.cmp(&path_b)
+    }
+
+    /// Sort `nodes` into tree order and drop duplicates.
+    pub fn sort_in_tree_order(&self, nodes: &mut Vec<NodeId>) {
+        let mut keyed: Vec<_> = nodes.drain(..).map(|n| (self.tree_path(n), n)).collect();
+        keyed.sort();
+        keyed.dedup_by(|a, b| a.1 == b.1);
+        nodes.extend(keyed.into_iter().map(|(_, n)| n));
+    }
+
+    /// Child indices from the node's root down to the node, prefixed with
+    /// the root's slot so nodes in separate trees never interleave.
+    fn tree_path(&self, node: NodeId) -> SmallVec<[u64; 16]> {
+        let mut path = SmallVec::new();
+        let mut current = node;
+        while let Some(parent) = self.parent(current) {
+            let position = self
+                .get(parent)
+                .and_then(|p| p.children.iter().position(|&c| c == current))
+                .unwrap_or(0);
+            path.push(position as u64);
+            current = parent;
+        }
+        path.push(if Some(current) == self.root { 0 } else { current.to_raw() });
+        path.reverse();
+        path
+    }
+
+    /// Queue `node` for re-indexing.
+    fn mark_stale(&mut self, node: NodeId) {
+        self.generation += 1;
+        let index = self.index.get_mut();
+        if index.rebuild {
+            return;
+        }
+        if index.stale.len() > self.nodes.len() {
+            // Cheaper to start over than to replay every change.
+            index.stale.clear();
+            index.rebuild = true;
+        } else if index.stale.last() != Some(&node) {
+            index.stale.push(node);
+        }
+    }
+
+    /// The id/class index, brought up to date first.
+    fn selector_index(&self) -> RwLockReadGuard<'_, SelectorIndex> {
+        {
+            let index = self.index.read();
+            if index.stale.is_empty() && !index.rebuild {
+                return index;
+            }
+        }
+        let mut index = self.index.write();
+        index.refresh(&self.nodes);
+        RwLockWriteGuard::downgrade(index)
     }
 
     /// Find elements by tag name.
@@ -349,20 +815,19 @@ impl DomTree {
             return Vec::new();
         }
 
-        let root = match self.root {
-            Some(r) => r,
-            None => return Vec::new(),
-        };
-
-        self.descendants(root)
+        let mut found: Vec<NodeId> = self
+            .elements_with_class(classes[0])
+            .into_iter()
             .filter(|&id| {
-                if let Some(elem) = self.get_element(id) {
-                    classes.iter().all(|c| elem.has_class(c))
-                } else {
-                    false
-                }
+                self.is_connected(id)
+                    && self
+                        .get_element(id)
+                        .map(|e| classes.iter().all(|c| e.has_class(c)))
+                        .unwrap_or(false)
             })
-            .collect()
+            .collect();
+        self.sort_in_tree_order(&mut found);
+        found
     }
 
     /// Find child by tag name.
@@ -375,151 +840,73 @@ impl DomTree {
         })
     }
 
-    /// Query selector (basic implementation).
-    pub fn query_selector(&self, selector: &str) -> Option<NodeId> {
-        self.query_selector_all(selector).into_iter().next()
-    }
-
-    /// Query selector all (basic implementation).
-    pub fn query_selector_all(&self, selector: &str) -> Vec<NodeId> {
-        let root = match self.root {
-            Some(r) => r,
-            None => return Vec::new(),
+    /// Set text content of a node.
+    ///
+    /// Replaces the data of text and comment nodes, and the children of
+    /// elements, reporting a single mutation either way.
+    pub fn set_text_content(&mut self, node: NodeId, text: &str) {
+        self.flush_mutations();
+        let node_data = match self.nodes.get_mut(node) {
+            Some(node_data) => node_data,
+            None => return,
         };
-
-        // Simple selector parsing
-        let selector = selector.trim();
-
-        if selector.starts_with('#') {
-            // ID selector
-            let id = &selector[1..];
-            self.find_element_by_id(id).into_iter().collect()
-        } else if selector.starts_with('.') {
-            // Class selector
-            let class = &selector[1..];
-            self.find_elements_by_class_name(class)
-        } else if selector.starts_with('[') && selector.ends_with(']') {
-            // Attribute selector
-            let attr = &selector[1..selector.len()-1];
-            self.find_elements_by_attribute(attr)
-        } else {
-            // Tag selector (might include class/id)
-            if let Some(dot_pos) = selector.find('.') {
-                let tag = &selector[..dot_pos];
-                let class = &selector[dot_pos+1..];
-                self.find_elements_by_tag_name(tag)
-                    .into_iter()
-                    .filter(|&id| {
-                        self.get_element(id)
-                            .map(|e| e.has_class(class))
-                            .unwrap_or(false)
-                    })
-                    .collect()
-            } else if let Some(hash_pos) = selector.find('#') {
-                let tag = &selector[..hash_pos];
-                let id = &selector[hash_pos+1..];
-                self.find_element_by_id(id)
-                    .filter(|&node_id| {
-                        self.get_element(node_id)
-                            .map(|e| tag.is_empty() || e.tag_name.as_str() == tag)
-                            .unwrap_or(false)
-                    })
-                    .into_iter()
-                    .collect()
-            } else {
-                self.find_elements_by_tag_name(selector)
+        match &mut node_data.data {
+            NodeData::Text { content } | NodeData::Comment { content } => {
+                let old_value = std::mem::replace(content, text.to_string());
+                self.notify(&Mutation::character_data(node, old_value));
+            }
+            NodeData::Element(_) => {
+                // Remove all children and add text node
+                let children: Vec<NodeId> = node_data.children.to_vec();
+                for &child in &children {
+                    self.detach(child);
+                    self.drop_subtree(child);
+                }
+                let text_node = self.create_text(text.to_string());
+                self.attach(node, text_node, None);
+                self.notify(&Mutation::child_list(node, vec![text_node], children, None, None));
             }
+            _ => {}
         }
     }
 
-    /// Find elements by attribute.
-    fn find_elements_by_attribute(&self, attr_selector: &str) -> Vec<NodeId> {
-        let root = match self.root {
-            Some(r) => r,
-            None => return Vec::new(),
-        };
-
-        // Parse attribute selector: name, name=value, name^=value, etc.
-        let (name, op, value) = if let Some(eq_pos) = attr_selector.find('=') {
-            let (name_part, value_part) = attr_selector.split_at(eq_pos);
-            let value = value_part[1..].trim_matches(|c| c == '"' || c == '\'');
-
-            if name_part.ends_with('^') {
-                (&name_part[..name_part.len()-1], Some("^="), Some(value))
-            } else if name_part.ends_with('$') {
-                (&name_part[..name_part.len()-1], Some("$="), Some(value))
-            } else if name_part.ends_with('*') {
-                (&name_part[..name_part.len()-1], Some("*="), Some(value))
-            } else if name_part.ends_with('~') {
-                (&name_part[..name_part.len()-1], Some("~="), Some(value))
-            } else if name_part.ends_with('|') {
-                (&name_part[..name_part.len()-1], Some("|="), Some(value))
-            } else {
-                (name_part, Some("="), Some(value))
-            }
-        } else {
-            (attr_selector, None, None)
+    /// Replace `count` bytes of a text or comment node's data at `offset`
+    /// (`CharacterData.replaceData`). Returns false if the node has no data
+    /// or the span does not fall on character boundaries.
+    pub fn replace_data(&mut self, node: NodeId, offset: usize, count: usize, data: &str) -> bool {
+        let mut content = match self.nodes.get(node).map(|n| &n.data) {
+            Some(NodeData::Text { content }) | Some(NodeData::Comment { content }) => content.clone(),
+            _ => return false,
         };
-
-        self.descendants(root)
-            .filter(|&id| {
-                if let Some(elem) = self.get_element(id) {
-                    match (op, value) {
-                        (None, _) => elem.has_attribute(name),
-                        (Some("="), Some(v)) => elem.get_attribute(name) == Some(v),
-                        (Some("^="), Some(v)) => {
-                            elem.get_attribute(name)
-                                .map(|a| a.starts_with(v))
-                                .unwrap_or(false)
-                        }
-                        (Some("$="), Some(v)) => {
-                            elem.get_attribute(name)
-                                .map(|a| a.ends_with(v))
-                                .unwrap_or(false)
-                        }
-                        (Some("*="), Some(v)) => {
-                            elem.get_attribute(name)
-                                .map(|a| a.contains(v))
-                                .unwrap_or(false)
-                        }
-                        (Some("~="), Some(v)) => {
-                            elem.get_attribute(name)
-                                .map(|a| a.split_whitespace().any(|w| w == v))
-                                .unwrap_or(false)
-                        }
-                        (Some("|="), Some(v)) => {
-                            elem.get_attribute(name)
-                                .map(|a| a == v || a.starts_with(&format!("{}-", v)))
-                                .unwrap_or(false)
-                        }
-                        _ => false,
-                    }
-                } else {
-                    false
-                }
-            })
-            .collect()
+        let end = offset.saturating_add(count).min(content.len());
+        if offset > end || !content.is_char_boundary(offset) || !content.is_char_boundary(end) {
+            return false;
+        }
+        content.replace_range(offset..end, data);
+        self.set_text_content(node, &content);
+        true
     }
 
-    /// Set text content of a node.
-    pub fn set_text_content(&mut self, node: NodeId, text: &str) {
-        if let Some(node_data) = self.nodes.get_mut(node) {
-            match &mut node_data.data {
-                NodeData::Text { content } => {
-                    *content = text.to_string();
-                }
-                NodeData::Element(_) => {
-                    // Remove all children and add text node
-                    let children: Vec<_> = node_data.children.drain(..).collect();
-                    for child in children {
-                        self.remove(child);
-                    }
-                    let text_node = self.create_text(text.to_string());
-                    self.append_child(node, text_node);
-                }
-                _ => {}
+    /// Split a text node at `offset`, inserting the tail as its next sibling
+    /// (`Text.splitText`). Returns the new node.
+    pub fn split_text(&mut self, node: NodeId, offset: usize) -> Option<NodeId> {
+        let tail = match self.nodes.get(node).map(|n| &n.data) {
+            Some(NodeData::Text { content }) if content.is_char_boundary(offset) => {
+                content.get(offset..)?.to_string()
+            }
+            _ => return None,
+        };
+        let new_node = self.create_text(tail.clone());
+        if let Some(parent) = self.parent(node) {
+            let next = self.next_sibling(node);
+            self.flush_mutations();
+            self.attach(parent, new_node, next);
+            if !self.hooks.is_empty() {
+                self.notify(&Mutation::split_text(parent, node, new_node, offset, next));
             }
         }
+        self.replace_data(node, offset, tail.len(), "");
+        Some(new_node)
     }
 
     /// Get text content of a node and its descendants.
@@ -545,17 +932,6 @@ impl DomTree {
         }
     }
 
-    /// Update ID map after node insertion.
-    fn update_id_map(&mut self, node: NodeId) {
-        if let Some(n) = self.nodes.get(node) {
-            if let Some(elem) = n.as_element() {
-                if let Some(id) = &elem.id {
-                    self.id_map.insert(id.to_string(), node);
-                }
-            }
-        }
-    }
-
     /// Get total number of nodes.
     pub fn len(&self) -> usize {
         self.nodes.len()
@@ -578,6 +954,132 @@ impl Default for DomTree {
     }
 }
 
+/// State of a mutably borrowed node, compared on flush.
+enum NodeSnapshot {
+    Attributes(Vec<(String, String)>),
+    Data(String),
+}
+
+impl NodeSnapshot {
+    fn take(node: &Node) -> Option<Self> {
+        match &node.data {
+            NodeData::Element(elem) => Some(NodeSnapshot::Attributes(
+                elem.attributes
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+            )),
+            NodeData::Text { content } | NodeData::Comment { content } => {
+                Some(NodeSnapshot::Data(content.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Mutations turning the snapshot into the current state of `node`.
+    fn changes(self, id: NodeId, node: &Node) -> Vec<Mutation> {
+        match (self, &node.data) {
+            (NodeSnapshot::Attributes(old), NodeData::Element(elem)) => {
+                let mut mutations = Vec::new();
+                for (name, value) in &old {
+                    if elem.attributes.get(name) != Some(value.as_str()) {
+                        mutations.push(Mutation::attribute(id, name, Some(value.clone())));
+                    }
+                }
+                for name in elem.attributes.names() {
+                    if !old.iter().any(|(old_name, _)| old_name == name) {
+                        mutations.push(Mutation::attribute(id, name, None));
+                    }
+                }
+                mutations
+            }
+            (
+                NodeSnapshot::Data(old),
+                NodeData::Text { content } | NodeData::Comment { content },
+            ) if *content != old => vec![Mutation::character_data(id, old)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// An element's id and classes.
+type IndexEntry = (Option<Arc<str>>, SmallVec<[Arc<str>; 4]>);
+
+/// Id and class lookup tables, refreshed lazily after element mutations.
+#[derive(Default)]
+struct SelectorIndex {
+    ids: HashMap<Arc<str>, SmallVec<[NodeId; 1]>>,
+    classes: HashMap<Arc<str>, Vec<NodeId>>,
+    /// The id and classes each element is currently filed under.
+    entries: HashMap<NodeId, IndexEntry>,
+    /// Nodes that may have changed since the last refresh.
+    stale: Vec<NodeId>,
+    /// Set when so much changed that a full rebuild is cheaper.
+    rebuild: bool,
+}
+
+impl SelectorIndex {
+    fn refresh(&mut self, nodes: &SlotMap<NodeId, Node>) {
+        if self.rebuild {
+            self.ids.clear();
+            self.classes.clear();
+            self.entries.clear();
+            self.stale.clear();
+            self.rebuild = false;
+            for (node, data) in nodes.iter() {
+                if let Some(elem) = data.as_element() {
+                    self.insert(node, elem);
+                }
+            }
+            return;
+        }
+
+        let stale: HashSet<NodeId> = self.stale.drain(..).collect();
+        for node in stale {
+            self.remove(node);
+            if let Some(elem) = nodes.get(node).and_then(|n| n.as_element()) {
+                self.insert(node, elem);
+            }
+        }
+    }
+
+    fn insert(&mut self, node: NodeId, elem: &ElementData) {
+        if elem.id.is_none() && elem.class_list.is_empty() {
+            return;
+        }
+        if let Some(id) = &elem.id {
+            self.ids.entry(id.clone()).or_default().push(node);
+        }
+        for class in &elem.class_list {
+            self.classes.entry(class.clone()).or_default().push(node);
+        }
+        self.entries.insert(node, (elem.id.clone(), elem.class_list.clone()));
+    }
+
+    fn remove(&mut self, node: NodeId) {
+        let (id, classes) = match self.entries.remove(&node) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if let Some(id) = id {
+            if let Some(nodes) = self.ids.get_mut(&id) {
+                nodes.retain(|n| *n != node);
+                if nodes.is_empty() {
+                    self.ids.remove(&id);
+                }
+            }
+        }
+        for class in classes {
+            if let Some(nodes) = self.classes.get_mut(&class) {
+                nodes.retain(|n| *n != node);
+                if nodes.is_empty() {
+                    self.classes.remove(&class);
+                }
+            }
+        }
+    }
+}
+
 /// Iterator over ancestor nodes.
 pub struct AncestorIterator<'a> {
     tree: &'a DomTree,
@@ -669,4 +1171,126 @@ mod tests {
         assert!(tree.get(div).is_none());
         assert!(tree.get(span).is_none());
     }
+
+    #[test]
+    fn test_template_contents() {
+        let mut tree = DomTree::new();
+        let template = tree.create_element(ElementData::new(TagName::new("template")));
+        let contents = tree.template_contents(template).unwrap();
+        let span = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(contents, span);
+
+        assert!(tree.first_child(template).is_none());
+        assert_eq!(tree.get(contents).unwrap().node_type, NodeType::DocumentFragment);
+
+        let copy = tree.clone_node(template, true).unwrap();
+        let copy_contents = tree.template_contents(copy).unwrap();
+        assert_ne!(copy_contents, contents);
+        assert_eq!(tree.children(copy_contents).count(), 1);
+
+        tree.remove(template);
+        assert!(tree.get(contents).is_none());
+        assert!(tree.get(span).is_none());
+    }
+
+    #[test]
+    fn test_import_subtree() {
+        let mut source = DomTree::new();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("id", "outer");
+        let div = source.create_element(data);
+        let text = source.create_text("hello".to_string());
+        source.append_child(div, text);
+
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let copy = tree.import_subtree(&source, div).unwrap();
+        tree.append_child(root, copy);
+
+        assert_eq!(tree.find_element_by_id("outer"), Some(copy));
+        assert_eq!(tree.get_text_content(copy), "hello");
+
+        tree.remove_children(copy);
+        assert!(tree.first_child(copy).is_none());
+    }
+
+    #[test]
+    fn test_id_and_class_index() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("id", "dup");
+        let detached = tree.create_element(data.clone());
+        let first = tree.create_element(data.clone());
+        let second = tree.create_element(data);
+        tree.append_child(root, first);
+        tree.append_child(first, second);
+
+        // Connected elements win, in tree order.
+        assert_eq!(tree.elements_with_id("dup").len(), 3);
+        assert_eq!(tree.find_element_by_id("dup"), Some(first));
+        assert_eq!(tree.compare_tree_order(first, second), Ordering::Less);
+
+        tree.get_element_mut(first).unwrap().set_attribute("id", "other");
+        tree.get_element_mut(second).unwrap().set_attribute("class", "a b");
+        assert_eq!(tree.find_element_by_id("dup"), Some(second));
+        assert_eq!(tree.find_elements_by_class_name("b a"), vec![second]);
+
+        tree.remove(second);
+        assert_eq!(tree.find_element_by_id("dup"), Some(detached));
+        assert!(tree.elements_with_class("a").is_empty());
+    }
+
+    #[test]
+    fn test_mutation_hooks() {
+        use crate::mutation::MutationKind;
+        use parking_lot::Mutex;
+
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let list = tree.create_element(ElementData::new(TagName::ul()));
+        let first = tree.create_element(ElementData::new(TagName::li()));
+        let second = tree.create_element(ElementData::new(TagName::li()));
+        let text = tree.create_text("a".to_string());
+        tree.append_child(root, list);
+        tree.append_child(list, second);
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let sink = log.clone();
+        let hook = tree.add_mutation_hook(Arc::new(move |_: &DomTree, m: &Mutation| {
+            sink.lock().push(m.clone())
+        }));
+
+        tree.insert_before(list, first, Some(second));
+        tree.append_child(first, text);
+        tree.get_element_mut(first).unwrap().set_attribute("class", "x");
+        tree.get_element_mut(first).unwrap().set_attribute("class", "y");
+        tree.set_attribute(first, "class", "y");
+        tree.set_text_content(text, "b");
+        tree.replace_child(list, text, second);
+        assert!(tree.remove_attribute(first, "CLASS"));
+        assert!(!tree.remove_attribute(first, "class"));
+        tree.flush_mutations();
+
+        let log = std::mem::take(&mut *log.lock());
+        assert_eq!(
+            log,
+            vec![
+                Mutation::child_list(list, vec![first], vec![], None, Some(second)),
+                Mutation::child_list(first, vec![text], vec![], None, None),
+                Mutation::attribute(first, "class", None),
+                Mutation::attribute(first, "class", Some("x".to_string())),
+                Mutation::attribute(first, "class", Some("y".to_string())),
+                Mutation::character_data(text, "a".to_string()),
+                Mutation::child_list(first, vec![], vec![text], None, None),
+                Mutation::child_list(list, vec![text], vec![second], Some(first), None),
+                Mutation::attribute(first, "class", Some("y".to_string())),
+            ]
+        );
+        assert_eq!(log[5].kind, MutationKind::CharacterData);
+
+        assert!(tree.remove_mutation_hook(hook));
+        tree.remove(first);
+        assert!(!tree.remove_mutation_hook(hook));
+    }
 }
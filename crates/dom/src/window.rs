@@ -1,6 +1,7 @@
 //! DOM Window object implementation.
 
 use crate::document::DocumentRef;
+use crate::selection::Selection;
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -278,9 +279,11 @@ impl Window {
         // Would trigger print dialog
     }
 
-    /// Get selection.
+    /// Get a snapshot of the document's selection.
     pub fn get_selection(&self) -> Option<Selection> {
-        None
+        self.document
+            .as_ref()
+            .map(|document| document.read().selection.lock().clone())
     }
 }
 
@@ -566,17 +569,6 @@ pub struct MediaQueryList {
     pub matches: bool,
 }
 
-/// Text selection.
-#[derive(Clone, Debug)]
-pub struct Selection {
-    pub anchor_node: Option<crate::node::NodeId>,
-    pub anchor_offset: usize,
-    pub focus_node: Option<crate::node::NodeId>,
-    pub focus_offset: usize,
-    pub is_collapsed: bool,
-    pub range_count: usize,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,340 @@
+//! Fragment-parsing mutations: `innerHTML`, `outerHTML` and `insertAdjacentHTML`.
+
+use crate::parser::parse_html_fragment;
+use crate::serializer::{serialize_inner_html, serialize_outer_html};
+use dom::element::{Element, ElementData, TagName};
+use dom::node::{NodeData, NodeId};
+use dom::tree::DomTree;
+use thiserror::Error;
+
+/// Errors raised by fragment-parsing mutations.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum FragmentError {
+    #[error("Node is not an element")]
+    NotAnElement,
+
+    #[error("Invalid insertion position: {0}")]
+    InvalidPosition(String),
+
+    #[error("Cannot modify the children of a document or a detached element")]
+    NoModificationAllowed,
+}
+
+/// Position argument of `insertAdjacentHTML`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InsertPosition {
+    BeforeBegin,
+    AfterBegin,
+    BeforeEnd,
+    AfterEnd,
+}
+
+impl InsertPosition {
+    /// Parse a position keyword (ASCII case-insensitive).
+    pub fn parse(s: &str) -> Result<Self, FragmentError> {
+        match s.to_ascii_lowercase().as_str() {
+            "beforebegin" => Ok(InsertPosition::BeforeBegin),
+            "afterbegin" => Ok(InsertPosition::AfterBegin),
+            "beforeend" => Ok(InsertPosition::BeforeEnd),
+            "afterend" => Ok(InsertPosition::AfterEnd),
+            _ => Err(FragmentError::InvalidPosition(s.to_string())),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InsertPosition::BeforeBegin => "beforebegin",
+            InsertPosition::AfterBegin => "afterbegin",
+            InsertPosition::BeforeEnd => "beforeend",
+            InsertPosition::AfterEnd => "afterend",
+        }
+    }
+}
+
+/// Replace the children of `node` with the parsed `html`.
+pub fn set_inner_html(tree: &mut DomTree, node: NodeId, html: &str) -> Result<(), FragmentError> {
+    // A shadow root's markup is parsed in the context of its host.
+    let context = tree
+        .get_element(tree.shadow_host(node).unwrap_or(node))
+        .cloned()
+        .ok_or(FragmentError::NotAnElement)?;
+
+    let fragment = parse_html_fragment(tree, html, &context);
+    let target = tree.template_contents(node).unwrap_or(node);
+    replace_with_fragment(tree, fragment, target);
+    Ok(())
+}
+
+/// Replace `node` with the parsed `html`.
+///
+/// The replaced element is detached rather than dropped, since script may
+/// still hold a reference to it.
+pub fn set_outer_html(tree: &mut DomTree, node: NodeId, html: &str) -> Result<(), FragmentError> {
+    if tree.get_element(node).is_none() {
+        return Err(FragmentError::NotAnElement);
+    }
+    let parent = match tree.parent(node) {
+        Some(parent) => parent,
+        None => return Ok(()),
+    };
+
+    let context = match tree.get(parent).map(|n| &n.data) {
+        Some(NodeData::Element(elem)) => elem.clone(),
+        Some(NodeData::DocumentFragment) => ElementData::new(TagName::body()),
+        _ => return Err(FragmentError::NoModificationAllowed),
+    };
+
+    let fragment = parse_html_fragment(tree, html, &context);
+    insert_fragment(tree, fragment, parent, Some(node));
+    tree.remove_from_parent(node);
+    Ok(())
+}
+
+/// Parse `html` and insert the result relative to `node`.
+pub fn insert_adjacent_html(
+    tree: &mut DomTree,
+    node: NodeId,
+    position: InsertPosition,
+    html: &str,
+) -> Result<(), FragmentError> {
+    if tree.get_element(node).is_none() {
+        return Err(FragmentError::NotAnElement);
+    }
+
+    let (parent, reference) = match position {
+        InsertPosition::BeforeBegin => (tree.parent(node), Some(node)),
+        InsertPosition::AfterBegin => (Some(node), tree.first_child(node)),
+        InsertPosition::BeforeEnd => (Some(node), None),
+        InsertPosition::AfterEnd => (tree.parent(node), tree.next_sibling(node)),
+    };
+    let parent = parent.ok_or(FragmentError::NoModificationAllowed)?;
+
+    let context = match tree.get(parent).map(|n| &n.data) {
+        Some(NodeData::Element(elem)) if elem.tag_name != "html" || elem.namespace.is_some() => {
+            elem.clone()
+        }
+        Some(NodeData::Element(_)) | Some(NodeData::DocumentFragment) => {
+            ElementData::new(TagName::body())
+        }
+        _ => return Err(FragmentError::NoModificationAllowed),
+    };
+
+    let fragment = parse_html_fragment(tree, html, &context);
+    insert_fragment(tree, fragment, parent, reference);
+    Ok(())
+}
+
+/// Move the children of `fragment` into `parent` before `reference`, then
+/// discard the emptied fragment.
+pub(crate) fn insert_fragment(
+    tree: &mut DomTree,
+    fragment: NodeId,
+    parent: NodeId,
+    reference: Option<NodeId>,
+) {
+    tree.move_children(fragment, parent, reference);
+    tree.remove(fragment);
+}
+
+/// Replace the children of `parent` with those of `fragment`, then discard
+/// the emptied fragment.
+pub(crate) fn replace_with_fragment(tree: &mut DomTree, fragment: NodeId, parent: NodeId) {
+    tree.replace_children(parent, fragment);
+    tree.remove(fragment);
+}
+
+/// An element in a `DomTree`, exposed through the `Element` interface.
+pub struct ElementHandle<'a> {
+    tree: &'a mut DomTree,
+    node: NodeId,
+}
+
+impl<'a> ElementHandle<'a> {
+    /// Wrap `node`, returning `None` if it is not an element.
+    pub fn new(tree: &'a mut DomTree, node: NodeId) -> Option<Self> {
+        tree.get_element(node)?;
+        Some(Self { tree, node })
+    }
+
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    fn data(&self) -> &ElementData {
+        self.tree
+            .get_element(self.node)
+            .expect("ElementHandle points at an element")
+    }
+
+    pub fn set_outer_html(&mut self, html: &str) -> Result<(), FragmentError> {
+        set_outer_html(self.tree, self.node, html)
+    }
+
+    pub fn insert_adjacent_html(
+        &mut self,
+        position: &str,
+        html: &str,
+    ) -> Result<(), FragmentError> {
+        insert_adjacent_html(self.tree, self.node, InsertPosition::parse(position)?, html)
+    }
+}
+
+impl Element for ElementHandle<'_> {
+    fn tag_name(&self) -> &str {
+        self.data().tag_name.as_str()
+    }
+
+    fn id(&self) -> Option<&str> {
+        self.data().id.as_deref()
+    }
+
+    fn class_name(&self) -> String {
+        self.data()
+            .get_attribute("class")
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.data().get_attribute(name)
+    }
+
+    fn set_attribute(&mut self, name: &str, value: &str) {
+        self.tree.set_attribute(self.node, name, value);
+    }
+
+    fn remove_attribute(&mut self, name: &str) {
+        self.tree.remove_attribute(self.node, name);
+    }
+
+    fn has_attribute(&self, name: &str) -> bool {
+        self.data().has_attribute(name)
+    }
+
+    fn inner_html(&self) -> String {
+        serialize_inner_html(self.tree, self.node)
+    }
+
+    fn outer_html(&self) -> String {
+        serialize_outer_html(self.tree, self.node)
+    }
+
+    fn set_inner_html(&mut self, html: &str) {
+        // The node is known to be an element, so this cannot fail.
+        let _ = set_inner_html(self.tree, self.node, html);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_with(tag: &str) -> (DomTree, NodeId, NodeId) {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let body = tree.create_element(ElementData::new(TagName::body()));
+        tree.append_child(root, body);
+        let elem = tree.create_element(ElementData::new(TagName::new(tag)));
+        tree.append_child(body, elem);
+        (tree, body, elem)
+    }
+
+    #[test]
+    fn test_set_inner_html() {
+        let (mut tree, _, div) = tree_with("div");
+        set_inner_html(&mut tree, div, "<p id=a>One</p><p>Two</p>").unwrap();
+        assert_eq!(tree.children(div).count(), 2);
+        assert_eq!(
+            serialize_inner_html(&tree, div),
+            "<p id=\"a\">One</p><p>Two</p>"
+        );
+        assert!(tree.find_element_by_id("a").is_some());
+
+        set_inner_html(&mut tree, div, "plain").unwrap();
+        assert_eq!(tree.get_text_content(div), "plain");
+    }
+
+    #[test]
+    fn test_set_inner_html_on_shadow_root() {
+        let (mut tree, _, div) = tree_with("div");
+        let shadow = tree
+            .attach_shadow(
+                div,
+                dom::ShadowRootInit::new(dom::element::ShadowRootMode::Open),
+            )
+            .unwrap();
+        set_inner_html(&mut tree, shadow, "<slot name=a></slot><b>x</b>").unwrap();
+        assert_eq!(
+            serialize_inner_html(&tree, shadow),
+            "<slot name=\"a\"></slot><b>x</b>"
+        );
+        assert_eq!(tree.children(div).count(), 0);
+    }
+
+    #[test]
+    fn test_set_inner_html_table_and_select() {
+        let (mut tree, _, table) = tree_with("table");
+        set_inner_html(&mut tree, table, "<tr><td>1</td></tr>").unwrap();
+        let tbody = tree.first_child(table).unwrap();
+        assert_eq!(tree.get_element(tbody).unwrap().tag_name, "tbody");
+
+        let (mut tree, _, select) = tree_with("select");
+        set_inner_html(&mut tree, select, "<option>a</option><div>b</div>").unwrap();
+        let tags: Vec<_> = tree
+            .children(select)
+            .filter_map(|c| tree.get_element(c).map(|e| e.tag_name.to_string()))
+            .collect();
+        assert_eq!(tags, vec!["option"]);
+    }
+
+    #[test]
+    fn test_set_inner_html_template() {
+        let (mut tree, _, template) = tree_with("template");
+        set_inner_html(&mut tree, template, "<td>cell</td><li>item</li>").unwrap();
+        assert!(tree.first_child(template).is_none());
+
+        let contents = tree.template_contents(template).unwrap();
+        assert_eq!(tree.children(contents).count(), 2);
+        assert_eq!(
+            serialize_inner_html(&tree, template),
+            "<td>cell</td><li>item</li>"
+        );
+    }
+
+    #[test]
+    fn test_set_outer_html() {
+        let (mut tree, body, div) = tree_with("div");
+        set_outer_html(&mut tree, div, "<span>a</span><span>b</span>").unwrap();
+        assert_eq!(tree.children(body).count(), 2);
+        assert!(tree.parent(div).is_none());
+    }
+
+    #[test]
+    fn test_insert_adjacent_html() {
+        let (mut tree, body, div) = tree_with("div");
+        set_inner_html(&mut tree, div, "<b>x</b>").unwrap();
+
+        insert_adjacent_html(&mut tree, div, InsertPosition::BeforeBegin, "<i>1</i>").unwrap();
+        insert_adjacent_html(&mut tree, div, InsertPosition::AfterBegin, "<i>2</i>").unwrap();
+        insert_adjacent_html(&mut tree, div, InsertPosition::BeforeEnd, "<i>3</i>").unwrap();
+        insert_adjacent_html(&mut tree, div, InsertPosition::AfterEnd, "<i>4</i>").unwrap();
+
+        assert_eq!(
+            serialize_inner_html(&tree, body),
+            "<i>1</i><div><i>2</i><b>x</b><i>3</i></div><i>4</i>"
+        );
+        assert_eq!(
+            InsertPosition::parse("nowhere"),
+            Err(FragmentError::InvalidPosition("nowhere".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_element_handle() {
+        let (mut tree, _, div) = tree_with("div");
+        let mut handle = ElementHandle::new(&mut tree, div).unwrap();
+        handle.set_inner_html("<em>hi</em>");
+        assert_eq!(handle.inner_html(), "<em>hi</em>");
+        assert_eq!(handle.outer_html(), "<div><em>hi</em></div>");
+    }
+}
@@ -7,6 +7,17 @@ pub mod parser;
 pub mod tree_builder;
 pub mod serializer;
 pub mod tokenizer;
+pub mod fragment;
+pub mod sanitizer;
+pub mod view_source;
 
 pub use parser::{parse_html, parse_html_fragment, HtmlParser, ParseOptions};
-pub use serializer::serialize_html;
+pub use serializer::{
+    serialize_html, serialize_html_with_options, serialize_range, SerializeMode, SerializeOptions,
+};
+pub use fragment::{
+    insert_adjacent_html, set_inner_html, set_outer_html, ElementHandle, FragmentError,
+    InsertPosition,
+};
+pub use sanitizer::{Sanitizer, SanitizerConfig};
+pub use view_source::render_view_source;
@@ -98,15 +98,20 @@ impl HtmlParser {
         document
     }
 
-    /// Parse HTML fragment.
-    pub fn parse_fragment(&self, html: &str, context_tag: &str) -> Vec<NodeId> {
+    /// Parse an HTML fragment in the context of `context`, returning a
+    /// detached document fragment created in `tree`.
+    pub fn parse_fragment(&self, tree: &mut DomTree, html: &str, context: &ElementData) -> NodeId {
         let mut document = Document::new(self.options.url.clone());
-        let sink = DomTreeSink::new(&mut document);
+        let sink = DomTreeSink::for_fragment(&mut document);
 
-        let context = QualName::new(
+        let ns = match context.namespace.as_deref() {
+            Some(ns) => html5ever::Namespace::from(ns),
+            None => html5ever::ns!(html),
+        };
+        let context_name = QualName::new(
             None,
-            html5ever::ns!(html),
-            html5ever::LocalName::from(context_tag),
+            ns,
+            html5ever::LocalName::from(context.tag_name.as_str()),
         );
 
         let opts = ParseOpts {
@@ -117,17 +122,27 @@ impl HtmlParser {
             ..Default::default()
         };
 
-        parse_fragment(sink, opts, context, vec![])
+        parse_fragment(sink, opts, context_name, vec![])
             .from_utf8()
             .read_from(&mut html.as_bytes())
             .unwrap();
 
-        // Return children of root
-        document
+        // The fragment algorithm parses into a synthetic <html> root; its
+        // children are the fragment's top-level nodes.
+        let fragment = tree.create_document_fragment();
+        let html_root = document
             .tree
             .root()
-            .map(|root| document.tree.children(root).collect())
-            .unwrap_or_default()
+            .and_then(|root| document.tree.first_child(root));
+        if let Some(html_root) = html_root {
+            for child in document.tree.children(html_root).collect::<Vec<_>>() {
+                if let Some(imported) = tree.import_subtree(&document.tree, child) {
+                    tree.append_child(fragment, imported);
+                }
+            }
+        }
+
+        fragment
     }
 
     fn find_special_elements(document: &mut Document) {
@@ -173,10 +188,10 @@ pub fn parse_html(html: &str, url: Url) -> Document {
     parser.parse(html)
 }
 
-/// Parse HTML fragment.
-pub fn parse_html_fragment(html: &str, context_tag: &str) -> Vec<NodeId> {
+/// Parse an HTML fragment into `tree` using `context` as the context element.
+pub fn parse_html_fragment(tree: &mut DomTree, html: &str, context: &ElementData) -> NodeId {
     let parser = HtmlParser::new(ParseOptions::default().fragment());
-    parser.parse_fragment(html, context_tag)
+    parser.parse_fragment(tree, html, context)
 }
 
 /// Incremental parser for streaming HTML.
@@ -233,8 +248,21 @@ mod tests {
 
     #[test]
     fn test_parse_fragment() {
+        let mut tree = DomTree::new();
         let html = "<div><span>Test</span></div>";
-        let nodes = parse_html_fragment(html, "body");
-        assert!(!nodes.is_empty());
+        let fragment = parse_html_fragment(&mut tree, html, &ElementData::new(TagName::body()));
+        let nodes: Vec<_> = tree.children(fragment).collect();
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(tree.get_element(nodes[0]).unwrap().tag_name, "div");
+    }
+
+    #[test]
+    fn test_parse_fragment_table_context() {
+        let mut tree = DomTree::new();
+        let html = "<tr><td>Cell</td></tr>";
+        let fragment =
+            parse_html_fragment(&mut tree, html, &ElementData::new(TagName::new("tbody")));
+        let row = tree.first_child(fragment).unwrap();
+        assert_eq!(tree.get_element(row).unwrap().tag_name, "tr");
     }
 }
@@ -0,0 +1,534 @@
+//! HTML sanitizer built on fragment parsing.
+//!
+//! Markup is parsed with the same fragment algorithm as `innerHTML`, filtered
+//! against element, attribute and URL-scheme lists, and then either inserted
+//! into a tree or serialized back to a string.
+
+use crate::fragment::{replace_with_fragment, FragmentError};
+use crate::parser::parse_html_fragment;
+use crate::serializer::serialize_inner_html;
+use dom::element::{ElementData, TagName};
+use dom::node::{NodeData, NodeId};
+use dom::tree::DomTree;
+use std::collections::HashSet;
+
+/// Elements that are removed with their content no matter the configuration.
+const UNSAFE_ELEMENTS: &[&str] = &[
+    "script", "iframe", "frame", "frameset", "object", "embed", "applet", "base", "link", "meta",
+];
+
+/// Elements whose content is not meant to be rendered as text, so they are
+/// dropped rather than unwrapped when not allowed.
+const DROP_CONTENT_ELEMENTS: &[&str] = &[
+    "style", "title", "template", "textarea", "noscript", "noembed", "noframes", "xmp", "select",
+];
+
+/// Elements kept by the default configuration.
+const DEFAULT_ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "address",
+    "article",
+    "aside",
+    "b",
+    "bdi",
+    "bdo",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "data",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "i",
+    "img",
+    "ins",
+    "kbd",
+    "li",
+    "main",
+    "mark",
+    "nav",
+    "ol",
+    "p",
+    "picture",
+    "pre",
+    "q",
+    "rp",
+    "rt",
+    "ruby",
+    "s",
+    "samp",
+    "section",
+    "small",
+    "source",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "u",
+    "ul",
+    "var",
+    "wbr",
+];
+
+/// Attributes kept by the default configuration.
+const DEFAULT_ATTRIBUTES: &[&str] = &[
+    "abbr",
+    "alt",
+    "cite",
+    "class",
+    "colspan",
+    "datetime",
+    "dir",
+    "headers",
+    "height",
+    "href",
+    "lang",
+    "open",
+    "rel",
+    "reversed",
+    "rowspan",
+    "scope",
+    "span",
+    "src",
+    "srcset",
+    "sizes",
+    "start",
+    "title",
+    "translate",
+    "type",
+    "value",
+    "width",
+];
+
+/// Attributes whose value is a URL.
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "cite",
+    "action",
+    "formaction",
+    "poster",
+    "background",
+    "longdesc",
+    "ping",
+    "xlink:href",
+];
+
+/// Sanitizer configuration.
+#[derive(Clone, Debug)]
+pub struct SanitizerConfig {
+    /// Elements to keep; `None` keeps the default set.
+    pub elements: Option<HashSet<String>>,
+    /// Elements removed together with their content.
+    pub remove_elements: HashSet<String>,
+    /// Elements replaced by their children.
+    pub replace_with_children_elements: HashSet<String>,
+    /// Attributes to keep; `None` keeps the default set.
+    pub attributes: Option<HashSet<String>>,
+    /// Attributes always removed.
+    pub remove_attributes: HashSet<String>,
+    /// Schemes allowed in URL-valued attributes. Relative URLs are always allowed.
+    pub url_schemes: HashSet<String>,
+    /// Keep comments.
+    pub comments: bool,
+    /// Keep `data-*` attributes.
+    pub data_attributes: bool,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        Self {
+            elements: None,
+            remove_elements: HashSet::new(),
+            replace_with_children_elements: HashSet::new(),
+            attributes: None,
+            remove_attributes: HashSet::new(),
+            url_schemes: ["http", "https", "mailto"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            comments: false,
+            data_attributes: true,
+        }
+    }
+}
+
+impl SanitizerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow_element(mut self, name: &str) -> Self {
+        self.elements
+            .get_or_insert_with(HashSet::new)
+            .insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn remove_element(mut self, name: &str) -> Self {
+        self.remove_elements.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn replace_element_with_children(mut self, name: &str) -> Self {
+        self.replace_with_children_elements
+            .insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn allow_attribute(mut self, name: &str) -> Self {
+        self.attributes
+            .get_or_insert_with(HashSet::new)
+            .insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn remove_attribute(mut self, name: &str) -> Self {
+        self.remove_attributes.insert(name.to_ascii_lowercase());
+        self
+    }
+
+    pub fn allow_url_scheme(mut self, scheme: &str) -> Self {
+        self.url_schemes.insert(scheme.to_ascii_lowercase());
+        self
+    }
+
+    pub fn comments(mut self, allow: bool) -> Self {
+        self.comments = allow;
+        self
+    }
+
+    pub fn data_attributes(mut self, allow: bool) -> Self {
+        self.data_attributes = allow;
+        self
+    }
+}
+
+/// What to do with an element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ElementAction {
+    Keep,
+    Unwrap,
+    Remove,
+}
+
+/// HTML sanitizer.
+#[derive(Clone, Debug, Default)]
+pub struct Sanitizer {
+    config: SanitizerConfig,
+}
+
+impl Sanitizer {
+    pub fn new(config: SanitizerConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn config(&self) -> &SanitizerConfig {
+        &self.config
+    }
+
+    /// Sanitize `html` into a string, parsed in a `<body>` context.
+    pub fn sanitize_html(&self, html: &str) -> String {
+        let mut tree = DomTree::new();
+        let fragment = self.sanitize_fragment(&mut tree, html, &ElementData::new(TagName::body()));
+        serialize_inner_html(&tree, fragment)
+    }
+
+    /// Parse and sanitize `html` into a detached document fragment in `tree`.
+    pub fn sanitize_fragment(
+        &self,
+        tree: &mut DomTree,
+        html: &str,
+        context: &ElementData,
+    ) -> NodeId {
+        // Sanitize in a scratch tree so removed nodes never touch `tree`.
+        let mut scratch = DomTree::new();
+        let parsed = parse_html_fragment(&mut scratch, html, context);
+        self.sanitize(&mut scratch, parsed);
+
+        let fragment = tree.create_document_fragment();
+        for child in scratch.children(parsed) {
+            if let Some(imported) = tree.import_subtree(&scratch, child) {
+                tree.append_child(fragment, imported);
+            }
+        }
+        fragment
+    }
+
+    /// Replace the children of `node` with sanitized `html` (`Element.setHTML`).
+    pub fn set_html(
+        &self,
+        tree: &mut DomTree,
+        node: NodeId,
+        html: &str,
+    ) -> Result<(), FragmentError> {
+        let context = tree
+            .get_element(node)
+            .cloned()
+            .ok_or(FragmentError::NotAnElement)?;
+
+        let fragment = self.sanitize_fragment(tree, html, &context);
+        let target = tree.template_contents(node).unwrap_or(node);
+        replace_with_fragment(tree, fragment, target);
+        Ok(())
+    }
+
+    /// Sanitize the descendants of `node` in place.
+    pub fn sanitize(&self, tree: &mut DomTree, node: NodeId) {
+        let children: Vec<NodeId> = tree.children(node).collect();
+        for child in children {
+            let action = match tree.get(child).map(|n| &n.data) {
+                Some(NodeData::Text { .. }) => ElementAction::Keep,
+                Some(NodeData::Comment { .. }) if self.config.comments => ElementAction::Keep,
+                Some(NodeData::Element(elem)) => self.element_action(elem),
+                _ => ElementAction::Remove,
+            };
+
+            match action {
+                ElementAction::Keep => {
+                    if tree.get_element(child).is_some() {
+                        self.sanitize_element(tree, child);
+                    }
+                }
+                ElementAction::Unwrap => {
+                    self.sanitize(tree, child);
+                    let grandchildren: Vec<NodeId> = tree.children(child).collect();
+                    for grandchild in grandchildren {
+                        tree.insert_before(node, grandchild, Some(child));
+                    }
+                    tree.remove(child);
+                }
+                ElementAction::Remove => tree.remove(child),
+            }
+        }
+    }
+
+    fn sanitize_element(&self, tree: &mut DomTree, node: NodeId) {
+        let (contents, shadow_root) = match tree.get_element_mut(node) {
+            Some(elem) => {
+                let removed: Vec<String> = elem
+                    .attributes
+                    .iter()
+                    .filter(|(name, value)| !self.keep_attribute(name, value))
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                for name in removed {
+                    elem.remove_attribute(&name);
+                }
+                (elem.template_contents, elem.shadow_root.take())
+            }
+            None => return,
+        };
+
+        if let Some(shadow_root) = shadow_root {
+            tree.remove(shadow_root.root);
+        }
+        if let Some(contents) = contents {
+            self.sanitize(tree, contents);
+        }
+        self.sanitize(tree, node);
+    }
+
+    fn element_action(&self, elem: &ElementData) -> ElementAction {
+        let name = elem.tag_name.as_str();
+        let foreign = elem.namespace.is_some();
+
+        // Scripts run in the SVG namespace, too.
+        if (!foreign && UNSAFE_ELEMENTS.contains(&name)) || name.eq_ignore_ascii_case("script") {
+            return ElementAction::Remove;
+        }
+        if self.config.remove_elements.contains(name) {
+            return ElementAction::Remove;
+        }
+        if self.config.replace_with_children_elements.contains(name) {
+            return ElementAction::Unwrap;
+        }
+
+        let allowed = match &self.config.elements {
+            Some(elements) => elements.contains(name),
+            None => !foreign && DEFAULT_ELEMENTS.contains(&name),
+        };
+        if allowed {
+            ElementAction::Keep
+        } else if foreign || DROP_CONTENT_ELEMENTS.contains(&name) {
+            ElementAction::Remove
+        } else {
+            ElementAction::Unwrap
+        }
+    }
+
+    fn keep_attribute(&self, name: &str, value: &str) -> bool {
+        // Event handler content attributes are never safe.
+        if name.starts_with("on") || name == "srcdoc" {
+            return false;
+        }
+        if self.config.remove_attributes.contains(name) {
+            return false;
+        }
+
+        let allowed = match &self.config.attributes {
+            Some(attributes) => attributes.contains(name),
+            None => DEFAULT_ATTRIBUTES.contains(&name) || name.starts_with("aria-"),
+        } || (self.config.data_attributes && name.starts_with("data-"));
+        if !allowed {
+            return false;
+        }
+
+        if URL_ATTRIBUTES.contains(&name) {
+            self.url_allowed(value)
+        } else if name == "srcset" {
+            value
+                .split(',')
+                .filter_map(|candidate| candidate.split_whitespace().next())
+                .all(|url| self.url_allowed(url))
+        } else {
+            true
+        }
+    }
+
+    fn url_allowed(&self, url: &str) -> bool {
+        match url_scheme(url) {
+            Some(scheme) => self.config.url_schemes.contains(&scheme),
+            None => true,
+        }
+    }
+}
+
+/// Extract the lowercased scheme of a URL, ignoring the whitespace and
+/// control characters that URL parsing strips.
+fn url_scheme(url: &str) -> Option<String> {
+    let cleaned: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+
+    let end = cleaned.find(|c| matches!(c, ':' | '/' | '?' | '#'))?;
+    if !cleaned[end..].starts_with(':') || end == 0 {
+        return None;
+    }
+
+    let scheme = &cleaned[..end];
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| scheme.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_sanitizer() {
+        let sanitizer = Sanitizer::default();
+        assert_eq!(
+            sanitizer.sanitize_html("<p onclick=\"x()\" class=a>Hi<script>alert(1)</script></p>"),
+            "<p class=\"a\">Hi</p>"
+        );
+        assert_eq!(
+            sanitizer.sanitize_html("<custom-tag><b>bold</b></custom-tag><!-- c -->"),
+            "<b>bold</b>"
+        );
+        assert_eq!(sanitizer.sanitize_html("<style>p{}</style>text"), "text");
+    }
+
+    #[test]
+    fn test_url_schemes() {
+        let sanitizer = Sanitizer::default();
+        assert_eq!(
+            sanitizer.sanitize_html("<a href=\"java\tscript:alert(1)\">x</a>"),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            sanitizer.sanitize_html("<a href=\"/relative\">x</a><a href=\"HTTPS://e.com\">y</a>"),
+            "<a href=\"/relative\">x</a><a href=\"HTTPS://e.com\">y</a>"
+        );
+
+        let sanitizer = Sanitizer::new(SanitizerConfig::new().allow_url_scheme("tel"));
+        assert_eq!(
+            sanitizer.sanitize_html("<a href=\"tel:123\">call</a>"),
+            "<a href=\"tel:123\">call</a>"
+        );
+    }
+
+    #[test]
+    fn test_custom_config() {
+        let config = SanitizerConfig::new()
+            .allow_element("p")
+            .allow_element("script")
+            .allow_attribute("style")
+            .remove_element("em")
+            .comments(true)
+            .data_attributes(false);
+        let sanitizer = Sanitizer::new(config);
+        assert_eq!(
+            sanitizer.sanitize_html(
+                "<p style=\"color:red\" data-x=1><em>gone</em><b>kept</b><!--c--><script></script></p>"
+            ),
+            "<p style=\"color:red\">kept<!--c--></p>"
+        );
+
+        let config = SanitizerConfig::new()
+            .allow_element("svg")
+            .allow_element("math")
+            .allow_element("script");
+        let sanitizer = Sanitizer::new(config);
+        assert_eq!(
+            sanitizer.sanitize_html(
+                "<svg><script>alert(1)</script></svg><math><script>x</script></math>"
+            ),
+            "<svg></svg><math></math>"
+        );
+    }
+
+    #[test]
+    fn test_set_html() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        tree.append_child(root, div);
+
+        let sanitizer = Sanitizer::default();
+        sanitizer
+            .set_html(
+                &mut tree,
+                div,
+                "<img src=x onerror=alert(1) id=logo><iframe></iframe>",
+            )
+            .unwrap();
+        assert_eq!(serialize_inner_html(&tree, div), "<img src=\"x\">");
+        assert!(tree.find_element_by_id("logo").is_none());
+    }
+}
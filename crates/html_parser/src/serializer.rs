@@ -3,7 +3,76 @@
 use dom::document::Document;
 use dom::element::ElementData;
 use dom::node::{NodeData, NodeId, NodeType};
+use dom::range::Range;
 use dom::tree::DomTree;
+use std::borrow::Cow;
+
+/// Elements whose descendant text keeps its whitespace in canonical and
+/// minified output.
+const WHITESPACE_SENSITIVE_ELEMENTS: &[&str] = &[
+    "pre",
+    "textarea",
+    "listing",
+    "plaintext",
+    "xmp",
+    "script",
+    "style",
+];
+
+/// Parents in which whitespace-only text never renders.
+const WHITESPACE_INSENSITIVE_PARENTS: &[&str] = &[
+    "html", "head", "body", "table", "thead", "tbody", "tfoot", "tr", "colgroup", "select",
+    "optgroup", "ul", "ol", "dl",
+];
+
+/// Elements that close an open `<p>`, allowing its end tag to be omitted.
+const P_CLOSING_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "search",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Serialization mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SerializeMode {
+    /// Attributes in source order and text as-is.
+    #[default]
+    Standard,
+    /// Sorted attributes, always-quoted values and normalized whitespace,
+    /// so equivalent documents serialize identically.
+    Canonical,
+    /// Optional tags and collapsible whitespace removed.
+    Minified,
+}
 
 /// Options for HTML serialization.
 #[derive(Clone, Debug, Default)]
@@ -16,6 +85,8 @@ pub struct SerializeOptions {
     pub include_doctype: bool,
     /// Escape text content.
     pub escape_text: bool,
+    /// Serialization mode.
+    pub mode: SerializeMode,
 }
 
 impl SerializeOptions {
@@ -25,6 +96,7 @@ impl SerializeOptions {
             indent: "  ".to_string(),
             include_doctype: true,
             escape_text: true,
+            mode: SerializeMode::Standard,
         }
     }
 
@@ -32,6 +104,18 @@ impl SerializeOptions {
         self.pretty = true;
         self
     }
+
+    pub fn canonical(mut self) -> Self {
+        self.mode = SerializeMode::Canonical;
+        self
+    }
+
+    /// Minified output is never pretty printed.
+    pub fn minify(mut self) -> Self {
+        self.mode = SerializeMode::Minified;
+        self.pretty = false;
+        self
+    }
 }
 
 /// Serialize a document to HTML string.
@@ -44,7 +128,10 @@ pub fn serialize_html_with_options(document: &Document, options: &SerializeOptio
     let mut output = String::new();
 
     if options.include_doctype {
-        output.push_str("<!DOCTYPE html>\n");
+        output.push_str("<!DOCTYPE html>");
+        if options.mode != SerializeMode::Minified {
+            output.push('\n');
+        }
     }
 
     if let Some(root) = document.tree.root() {
@@ -75,6 +162,21 @@ pub fn serialize_inner_html(tree: &DomTree, node: NodeId) -> String {
     output
 }
 
+/// Serialize the contents of a range, as copied to the clipboard.
+///
+/// Partially selected elements are included as shallow copies around the
+/// selected part of their contents.
+pub fn serialize_range(tree: &mut DomTree, range: &Range) -> String {
+    match range.clone_contents(tree) {
+        Ok(fragment) => {
+            let html = serialize_inner_html(tree, fragment);
+            tree.remove(fragment);
+            html
+        }
+        Err(_) => String::new(),
+    }
+}
+
 fn serialize_node_internal(
     tree: &DomTree,
     node: NodeId,
@@ -98,10 +200,14 @@ fn serialize_node_internal(
             serialize_element(tree, node, elem, output, options, depth);
         }
         NodeData::Text { content } => {
+            if skip_text(tree, node, content, options) {
+                return;
+            }
+            let content = normalize_text(tree, node, content, options);
             if options.escape_text {
-                output.push_str(&escape_html_text(content));
+                output.push_str(&escape_html_text(&content));
             } else {
-                output.push_str(content);
+                output.push_str(&content);
             }
         }
         NodeData::Comment { content } => {
@@ -160,43 +266,50 @@ fn serialize_element(
     depth: usize,
 ) {
     let tag_name = elem.tag_name.as_str();
+    let minified = options.mode == SerializeMode::Minified;
 
     if options.pretty {
         add_indent(output, options, depth);
     }
 
-    // Start tag
-    output.push('<');
-    output.push_str(tag_name);
+    if !(minified && can_omit_start_tag(tree, node, elem, options)) {
+        // Start tag
+        output.push('<');
+        output.push_str(tag_name);
 
-    // Attributes
-    for (name, value) in elem.attributes.iter() {
-        output.push(' ');
-        output.push_str(name);
-        if !value.is_empty() {
-            output.push_str("=\"");
-            output.push_str(&escape_html_attribute(value));
-            output.push('"');
+        // Attributes
+        let mut attributes: Vec<(&str, &str)> = elem.attributes.iter().collect();
+        if options.mode == SerializeMode::Canonical {
+            attributes.sort_by(|a, b| a.0.cmp(b.0));
+        }
+        for (name, value) in attributes {
+            output.push(' ');
+            output.push_str(name);
+            if !value.is_empty() || options.mode == SerializeMode::Canonical {
+                output.push_str("=\"");
+                output.push_str(&escape_html_attribute(value));
+                output.push('"');
+            }
         }
+
+        output.push('>');
     }
 
     // Void elements
     if elem.is_void() {
-        output.push('>');
         if options.pretty {
             output.push('\n');
         }
         return;
     }
 
-    output.push('>');
-
-    // Children
-    let has_children = tree.get(node).map(|n| !n.children.is_empty()).unwrap_or(false);
+    // Children (a template's children are its contents)
+    let content = tree.template_contents(node).unwrap_or(node);
+    let has_children = tree.get(content).map(|n| !n.children.is_empty()).unwrap_or(false);
 
     if has_children {
         // Check if children are only text
-        let only_text = tree.get(node)
+        let only_text = tree.get(content)
             .map(|n| {
                 n.children.len() == 1
                     && tree.get(n.children[0])
@@ -228,9 +341,11 @@ fn serialize_element(
     }
 
     // End tag
-    output.push_str("</");
-    output.push_str(tag_name);
-    output.push('>');
+    if !(minified && can_omit_end_tag(tree, node, elem, options)) {
+        output.push_str("</");
+        output.push_str(tag_name);
+        output.push('>');
+    }
 
     if options.pretty {
         output.push('\n');
@@ -244,6 +359,7 @@ fn serialize_children(
     options: &SerializeOptions,
     depth: usize,
 ) {
+    let node = tree.template_contents(node).unwrap_or(node);
     if let Some(node_data) = tree.get(node) {
         for &child in &node_data.children {
             serialize_node_internal(tree, child, output, options, depth);
@@ -251,6 +367,209 @@ fn serialize_children(
     }
 }
 
+/// Whether whitespace in the text node `node` must be kept verbatim.
+fn preserves_whitespace(tree: &DomTree, node: NodeId) -> bool {
+    tree.ancestors(node).any(|ancestor| {
+        tree.get_element(ancestor)
+            .map(|e| {
+                e.namespace.is_none()
+                    && WHITESPACE_SENSITIVE_ELEMENTS.contains(&e.tag_name.as_str())
+            })
+            .unwrap_or(false)
+    })
+}
+
+/// Whether the text node `node` is left out of the output entirely.
+fn skip_text(tree: &DomTree, node: NodeId, content: &str, options: &SerializeOptions) -> bool {
+    if options.mode == SerializeMode::Standard
+        || !content.chars().all(|c| c.is_ascii_whitespace())
+        || preserves_whitespace(tree, node)
+    {
+        return false;
+    }
+    // Whitespace next to a block boundary never renders; between inline
+    // content it does.
+    let parent = tree.parent(node).and_then(|p| tree.get_element(p));
+    let parent_is_boundary = parent.map_or(true, |p| {
+        p.is_block() || WHITESPACE_INSENSITIVE_PARENTS.contains(&p.tag_name.as_str())
+    });
+    if parent.is_some_and(|p| WHITESPACE_INSENSITIVE_PARENTS.contains(&p.tag_name.as_str())) {
+        return true;
+    }
+    let is_boundary = |sibling: Option<NodeId>| match sibling {
+        Some(sibling) => tree.get_element(sibling).is_some_and(|e| e.is_block()),
+        None => parent_is_boundary,
+    };
+    is_boundary(tree.prev_sibling(node)) || is_boundary(tree.next_sibling(node))
+}
+
+/// Collapse whitespace runs to a single space outside whitespace-sensitive
+/// elements.
+fn normalize_text<'a>(
+    tree: &DomTree,
+    node: NodeId,
+    content: &'a str,
+    options: &SerializeOptions,
+) -> Cow<'a, str> {
+    if options.mode == SerializeMode::Standard || preserves_whitespace(tree, node) {
+        return Cow::Borrowed(content);
+    }
+    let mut collapsed = String::with_capacity(content.len());
+    let mut in_whitespace = false;
+    for c in content.chars() {
+        if c.is_ascii_whitespace() {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    Cow::Owned(collapsed)
+}
+
+/// Whether `node` produces any output.
+fn is_emitted(tree: &DomTree, node: NodeId, options: &SerializeOptions) -> bool {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => !skip_text(tree, node, content, options),
+        Some(_) => true,
+        None => false,
+    }
+}
+
+fn first_emitted_child(tree: &DomTree, node: NodeId, options: &SerializeOptions) -> Option<NodeId> {
+    let content = tree.template_contents(node).unwrap_or(node);
+    tree.children(content)
+        .find(|&child| is_emitted(tree, child, options))
+}
+
+fn next_emitted_sibling(
+    tree: &DomTree,
+    node: NodeId,
+    options: &SerializeOptions,
+) -> Option<NodeId> {
+    let mut next = tree.next_sibling(node);
+    while let Some(sibling) = next {
+        if is_emitted(tree, sibling, options) {
+            return Some(sibling);
+        }
+        next = tree.next_sibling(sibling);
+    }
+    None
+}
+
+fn is_comment(tree: &DomTree, node: Option<NodeId>) -> bool {
+    node.and_then(|n| tree.get(n))
+        .is_some_and(|n| n.node_type == NodeType::Comment)
+}
+
+fn starts_with_whitespace(tree: &DomTree, node: Option<NodeId>) -> bool {
+    match node.and_then(|n| tree.get(n)).map(|n| &n.data) {
+        Some(NodeData::Text { content }) => content.starts_with(|c: char| c.is_ascii_whitespace()),
+        _ => false,
+    }
+}
+
+fn html_tag(tree: &DomTree, node: Option<NodeId>) -> Option<&str> {
+    node.and_then(|n| tree.get_element(n))
+        .filter(|e| e.namespace.is_none())
+        .map(|e| e.tag_name.as_str())
+}
+
+/// Whether the start tag of `node` may be omitted (HTML "optional tags").
+fn can_omit_start_tag(
+    tree: &DomTree,
+    node: NodeId,
+    elem: &ElementData,
+    options: &SerializeOptions,
+) -> bool {
+    if elem.namespace.is_some() || !elem.attributes.is_empty() {
+        return false;
+    }
+    let first = first_emitted_child(tree, node, options);
+    match elem.tag_name.as_str() {
+        "html" => !is_comment(tree, first),
+        "head" => first.is_none() || html_tag(tree, first).is_some(),
+        "body" => {
+            !is_comment(tree, first)
+                && !starts_with_whitespace(tree, first)
+                && !matches!(
+                    html_tag(tree, first),
+                    Some("meta" | "noscript" | "link" | "script" | "style" | "template")
+                )
+        }
+        "tbody" | "colgroup" => {
+            let (first_tag, closers): (&str, &[&str]) = if elem.tag_name == "tbody" {
+                ("tr", &["tbody", "thead", "tfoot"])
+            } else {
+                ("col", &["colgroup"])
+            };
+            // A preceding section with an omitted end tag would swallow the
+            // implied start tag.
+            let previous_open =
+                tree.prev_sibling(node)
+                    .is_some_and(|prev| match tree.get_element(prev) {
+                        Some(prev_elem)
+                            if html_tag(tree, Some(prev)).is_some_and(|t| closers.contains(&t)) =>
+                        {
+                            can_omit_end_tag(tree, prev, prev_elem, options)
+                        }
+                        _ => false,
+                    });
+            html_tag(tree, first) == Some(first_tag) && !previous_open
+        }
+        _ => false,
+    }
+}
+
+/// Whether the end tag of `node` may be omitted (HTML "optional tags").
+fn can_omit_end_tag(
+    tree: &DomTree,
+    node: NodeId,
+    elem: &ElementData,
+    options: &SerializeOptions,
+) -> bool {
+    if elem.namespace.is_some() {
+        return false;
+    }
+    let next = next_emitted_sibling(tree, node, options);
+    let at_end = next.is_none();
+    let next_is = |tags: &[&str]| html_tag(tree, next).is_some_and(|tag| tags.contains(&tag));
+    match elem.tag_name.as_str() {
+        "html" | "body" => !is_comment(tree, next),
+        "head" | "colgroup" | "caption" => {
+            !is_comment(tree, next) && !starts_with_whitespace(tree, next)
+        }
+        "li" => at_end || next_is(&["li"]),
+        "dt" => next_is(&["dt", "dd"]),
+        "dd" => at_end || next_is(&["dd", "dt"]),
+        "p" => {
+            next_is(P_CLOSING_ELEMENTS)
+                || (at_end
+                    && tree
+                        .parent(node)
+                        .and_then(|p| tree.get_element(p))
+                        .map_or(true, |p| {
+                            !matches!(
+                                p.tag_name.as_str(),
+                                "a" | "audio" | "del" | "ins" | "map" | "noscript" | "video"
+                            ) && !p.tag_name.as_str().contains('-')
+                        }))
+        }
+        "rt" | "rp" => at_end || next_is(&["rt", "rp"]),
+        "optgroup" => at_end || next_is(&["optgroup", "hr"]),
+        "option" => at_end || next_is(&["option", "optgroup", "hr"]),
+        "thead" => next_is(&["tbody", "tfoot"]),
+        "tbody" => at_end || next_is(&["tbody", "tfoot"]),
+        "tfoot" => at_end,
+        "tr" => at_end || next_is(&["tr"]),
+        "td" | "th" => at_end || next_is(&["td", "th"]),
+        _ => false,
+    }
+}
+
 fn add_indent(output: &mut String, options: &SerializeOptions, depth: usize) {
     for _ in 0..depth {
         output.push_str(&options.indent);
@@ -366,6 +685,78 @@ mod tests {
         assert!(output.contains("<p>Hello</p>"));
     }
 
+    #[test]
+    fn test_serialize_template_contents() {
+        let html = "<template><p>Inert</p></template>";
+        let doc = parse_html(html, Url::parse("about:blank").unwrap());
+        let template = doc.tree.find_elements_by_tag_name("template")[0];
+        assert_eq!(serialize_inner_html(&doc.tree, template), "<p>Inert</p>");
+        assert_eq!(
+            serialize_outer_html(&doc.tree, template),
+            "<template><p>Inert</p></template>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_canonical() {
+        let html = "<div id=a class='b' hidden>  x \n  y </div><pre>  keep\n  this</pre>";
+        let doc = parse_html(html, Url::parse("about:blank").unwrap());
+        let output = serialize_html_with_options(&doc, &SerializeOptions::new().canonical());
+        assert!(output.contains("<div class=\"b\" hidden=\"\" id=\"a\"> x y </div>"));
+        assert!(output.contains("<pre>  keep\n  this</pre>"));
+
+        // Whitespace between inline elements renders; between blocks it
+        // does not.
+        let doc = parse_html("<p><b>a</b> <i>b</i></p>\n<div></div>", Url::parse("about:blank").unwrap());
+        let output = serialize_html_with_options(&doc, &SerializeOptions::new().canonical());
+        assert!(output.contains("<p><b>a</b> <i>b</i></p><div></div>"));
+    }
+
+    #[test]
+    fn test_serialize_minified() {
+        let html = "<!DOCTYPE html><html><head><title>T</title></head><body>\n<ul>\n  <li>a</li>\n  <li>b</li>\n</ul>\n<p>one</p><p>two <b>x</b> y</p></body></html>";
+        let doc = parse_html(html, Url::parse("about:blank").unwrap());
+        let output = serialize_html_with_options(&doc, &SerializeOptions::new().minify());
+        assert_eq!(
+            output,
+            "<!DOCTYPE html><title>T</title><ul><li>a<li>b</ul><p>one<p>two <b>x</b> y"
+        );
+
+        // Reparsing the minified output yields an equivalent document.
+        let reparsed = parse_html(&output, Url::parse("about:blank").unwrap());
+        let canonical = SerializeOptions::new().canonical();
+        assert_eq!(
+            serialize_html_with_options(&reparsed, &canonical),
+            serialize_html_with_options(&doc, &canonical)
+        );
+    }
+
+    #[test]
+    fn test_minified_keeps_required_tags() {
+        let html = "<body class=x><a href=y><p>in link</p></a><table><tr><td>1</td><td>2</td></tr></table></body>";
+        let doc = parse_html(html, Url::parse("about:blank").unwrap());
+        let output = serialize_html_with_options(&doc, &SerializeOptions::new().minify());
+        assert!(output.contains("<body class=\"x\">"));
+        assert!(output.contains("<p>in link</p></a>"));
+        assert!(output.contains("<table><tr><td>1<td>2</table>"));
+    }
+
+    #[test]
+    fn test_serialize_range() {
+        let mut doc = parse_html("<p>Hello <b>bold</b> world</p>", Url::parse("about:blank").unwrap());
+        let tree = &mut doc.tree;
+        let p = tree.find_elements_by_tag_name("p")[0];
+        let hello = tree.first_child(p).unwrap();
+        let b = tree.find_elements_by_tag_name("b")[0];
+        let bold = tree.first_child(b).unwrap();
+
+        let mut range = Range::new(hello);
+        range.set_start(tree, hello, 2).unwrap();
+        range.set_end(tree, bold, 2).unwrap();
+        assert_eq!(range.to_string(tree), "llo bo");
+        assert_eq!(serialize_range(tree, &range), "llo <b>bo</b>");
+    }
+
     #[test]
     fn test_escape_html() {
         assert_eq!(escape_html_text("<script>"), "&lt;script&gt;");
@@ -26,11 +26,66 @@ pub enum Token {
     EndOfFile,
 }
 
+/// Byte range within the tokenizer input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Source positions of one attribute in a start tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttributeSpan {
+    pub name: Span,
+    /// Value without its quotes, if the attribute has one.
+    pub value: Option<Span>,
+}
+
+/// A token together with its position in the input.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+    /// Attribute positions, parallel to a start tag's attributes.
+    pub attributes: Vec<AttributeSpan>,
+}
+
+impl SpannedToken {
+    fn new(token: Token, start: usize, end: usize) -> Self {
+        Self {
+            token,
+            span: Span::new(start, end),
+            attributes: Vec::new(),
+        }
+    }
+}
+
+/// Elements whose content is raw text up to the matching end tag.
+const RAW_TEXT_ELEMENTS: &[&str] = &[
+    "script", "style", "textarea", "title", "xmp", "iframe", "noembed", "noframes",
+];
+
 /// Simple HTML tokenizer for basic operations.
 pub struct Tokenizer<'a> {
     input: &'a str,
     pos: usize,
-    tokens: VecDeque<Token>,
+    tokens: VecDeque<SpannedToken>,
+    /// End of the raw text content of the current `<script>`, `<style>`, etc.
+    raw_text_end: Option<usize>,
 }
 
 impl<'a> Tokenizer<'a> {
@@ -39,11 +94,17 @@ impl<'a> Tokenizer<'a> {
             input,
             pos: 0,
             tokens: VecDeque::new(),
+            raw_text_end: None,
         }
     }
 
     /// Get next token.
     pub fn next_token(&mut self) -> Option<Token> {
+        self.next_spanned().map(|spanned| spanned.token)
+    }
+
+    /// Get next token with its source span.
+    pub fn next_spanned(&mut self) -> Option<SpannedToken> {
         if let Some(token) = self.tokens.pop_front() {
             return Some(token);
         }
@@ -51,11 +112,21 @@ impl<'a> Tokenizer<'a> {
         self.scan_token()
     }
 
-    fn scan_token(&mut self) -> Option<Token> {
+    fn scan_token(&mut self) -> Option<SpannedToken> {
         if self.pos >= self.input.len() {
             return None;
         }
 
+        if let Some(end) = self.raw_text_end {
+            if self.pos < end {
+                let start = self.pos;
+                let c = self.input[start..].chars().next()?;
+                self.pos += c.len_utf8();
+                return Some(SpannedToken::new(Token::Character(c), start, self.pos));
+            }
+            self.raw_text_end = None;
+        }
+
         let remaining = &self.input[self.pos..];
 
         // Check for tag
@@ -73,7 +144,7 @@ impl<'a> Tokenizer<'a> {
                 // Skip special sequences
                 if let Some(end) = remaining.find('>') {
                     self.pos += end + 1;
-                    return self.next_token();
+                    return self.next_spanned();
                 }
             } else {
                 return self.scan_start_tag();
@@ -84,23 +155,25 @@ impl<'a> Tokenizer<'a> {
         self.scan_text()
     }
 
-    fn scan_comment(&mut self) -> Option<Token> {
+    fn scan_comment(&mut self) -> Option<SpannedToken> {
+        let token_start = self.pos;
         let start = self.pos + 4; // Skip "<!--"
         let remaining = &self.input[start..];
 
         if let Some(end) = remaining.find("-->") {
             let content = &remaining[..end];
             self.pos = start + end + 3;
-            Some(Token::Comment(content.to_string()))
+            Some(SpannedToken::new(Token::Comment(content.to_string()), token_start, self.pos))
         } else {
             // Unclosed comment - take rest of input
             let content = remaining.to_string();
             self.pos = self.input.len();
-            Some(Token::Comment(content))
+            Some(SpannedToken::new(Token::Comment(content), token_start, self.pos))
         }
     }
 
-    fn scan_doctype(&mut self) -> Option<Token> {
+    fn scan_doctype(&mut self) -> Option<SpannedToken> {
+        let token_start = self.pos;
         let remaining = &self.input[self.pos..];
 
         if let Some(end) = remaining.find('>') {
@@ -122,19 +195,21 @@ impl<'a> Tokenizer<'a> {
             };
 
             self.pos += end + 1;
-            Some(Token::Doctype {
+            let token = Token::Doctype {
                 name,
                 public_id,
                 system_id,
                 force_quirks: false,
-            })
+            };
+            Some(SpannedToken::new(token, token_start, self.pos))
         } else {
             self.pos = self.input.len();
             None
         }
     }
 
-    fn scan_start_tag(&mut self) -> Option<Token> {
+    fn scan_start_tag(&mut self) -> Option<SpannedToken> {
+        let token_start = self.pos;
         let start = self.pos + 1; // Skip "<"
         let remaining = &self.input[start..];
 
@@ -147,37 +222,54 @@ impl<'a> Tokenizer<'a> {
             let mut parts = tag_content.split_whitespace();
             let name = parts.next().unwrap_or("").to_ascii_lowercase();
 
-            let mut attributes = Vec::new();
-            let attr_str = tag_content[name.len()..].trim();
-            attributes = self.parse_attributes(attr_str);
+            let after_name = &tag_content[name.len()..];
+            let attr_str = after_name.trim();
+            let attr_offset = start + name.len() + (after_name.len() - after_name.trim_start().len());
+            let (attributes, attribute_spans) = self.parse_attributes(attr_str, attr_offset);
 
             self.pos = start + end + 1;
-            Some(Token::StartTag {
+
+            if !self_closing && RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                let closing = format!("</{}", name);
+                let rest = self.input[self.pos..].to_ascii_lowercase();
+                self.raw_text_end = Some(
+                    rest.find(&closing)
+                        .map(|i| self.pos + i)
+                        .unwrap_or(self.input.len()),
+                );
+            }
+
+            let token = Token::StartTag {
                 name,
                 attributes,
                 self_closing,
-            })
+            };
+            let mut spanned = SpannedToken::new(token, token_start, self.pos);
+            spanned.attributes = attribute_spans;
+            Some(spanned)
         } else {
             self.pos = self.input.len();
             None
         }
     }
 
-    fn scan_end_tag(&mut self) -> Option<Token> {
+    fn scan_end_tag(&mut self) -> Option<SpannedToken> {
+        let token_start = self.pos;
         let start = self.pos + 2; // Skip "</"
         let remaining = &self.input[start..];
 
         if let Some(end) = remaining.find('>') {
             let name = remaining[..end].trim().to_ascii_lowercase();
             self.pos = start + end + 1;
-            Some(Token::EndTag { name })
+            Some(SpannedToken::new(Token::EndTag { name }, token_start, self.pos))
         } else {
             self.pos = self.input.len();
             None
         }
     }
 
-    fn scan_text(&mut self) -> Option<Token> {
+    fn scan_text(&mut self) -> Option<SpannedToken> {
+        let start = self.pos;
         let remaining = &self.input[self.pos..];
 
         // Find next tag
@@ -186,34 +278,40 @@ impl<'a> Tokenizer<'a> {
         if end > 0 {
             let c = remaining.chars().next()?;
             self.pos += c.len_utf8();
-            Some(Token::Character(c))
+            Some(SpannedToken::new(Token::Character(c), start, self.pos))
         } else {
             None
         }
     }
 
-    fn parse_attributes(&self, attr_str: &str) -> Vec<(String, String)> {
+    /// Parse attributes, returning them with their spans. `offset` is the
+    /// position of `attr_str` in the input.
+    fn parse_attributes(
+        &self,
+        attr_str: &str,
+        offset: usize,
+    ) -> (Vec<(String, String)>, Vec<AttributeSpan>) {
         let mut attributes = Vec::new();
-        let mut chars = attr_str.chars().peekable();
+        let mut spans = Vec::new();
+        let mut chars = attr_str.char_indices().peekable();
+        let len = attr_str.len();
 
-        while let Some(c) = chars.next() {
+        while let Some((name_start, c)) = chars.next() {
             if c.is_whitespace() {
                 continue;
             }
 
             // Parse attribute name
-            let mut name = String::new();
-            name.push(c);
-            while let Some(&c) = chars.peek() {
+            while let Some(&(_, c)) = chars.peek() {
                 if c.is_whitespace() || c == '=' || c == '/' || c == '>' {
                     break;
                 }
-                name.push(c);
                 chars.next();
             }
+            let name_end = chars.peek().map(|&(i, _)| i).unwrap_or(len);
 
             // Skip whitespace
-            while let Some(&c) = chars.peek() {
+            while let Some(&(_, c)) = chars.peek() {
                 if !c.is_whitespace() {
                     break;
                 }
@@ -221,11 +319,11 @@ impl<'a> Tokenizer<'a> {
             }
 
             // Check for =
-            let value = if chars.peek() == Some(&'=') {
+            let value = if matches!(chars.peek(), Some(&(_, '='))) {
                 chars.next(); // consume =
 
                 // Skip whitespace
-                while let Some(&c) = chars.peek() {
+                while let Some(&(_, c)) = chars.peek() {
                     if !c.is_whitespace() {
                         break;
                     }
@@ -233,56 +331,49 @@ impl<'a> Tokenizer<'a> {
                 }
 
                 // Parse value
-                match chars.peek() {
-                    Some(&'"') => {
+                match chars.peek().copied() {
+                    Some((quote_pos, quote)) if quote == '"' || quote == '\'' => {
                         chars.next();
-                        let mut value = String::new();
-                        while let Some(&c) = chars.peek() {
-                            if c == '"' {
-                                chars.next();
+                        let value_start = quote_pos + 1;
+                        let mut value_end = len;
+                        for (i, c) in chars.by_ref() {
+                            if c == quote {
+                                value_end = i;
                                 break;
                             }
-                            value.push(c);
-                            chars.next();
                         }
-                        value
+                        Some((value_start, value_end))
                     }
-                    Some(&'\'') => {
-                        chars.next();
-                        let mut value = String::new();
-                        while let Some(&c) = chars.peek() {
-                            if c == '\'' {
-                                chars.next();
-                                break;
-                            }
-                            value.push(c);
-                            chars.next();
-                        }
-                        value
-                    }
-                    Some(_) => {
-                        let mut value = String::new();
-                        while let Some(&c) = chars.peek() {
+                    Some((value_start, _)) => {
+                        while let Some(&(_, c)) = chars.peek() {
                             if c.is_whitespace() || c == '/' || c == '>' {
                                 break;
                             }
-                            value.push(c);
                             chars.next();
                         }
-                        value
+                        let value_end = chars.peek().map(|&(i, _)| i).unwrap_or(len);
+                        Some((value_start, value_end))
                     }
-                    None => String::new(),
+                    None => Some((len, len)),
                 }
             } else {
-                String::new() // Boolean attribute
+                None // Boolean attribute
             };
 
+            let name = &attr_str[name_start..name_end];
             if !name.is_empty() {
-                attributes.push((name.to_ascii_lowercase(), value));
+                let value_text = value
+                    .map(|(start, end)| attr_str[start..end].to_string())
+                    .unwrap_or_default();
+                attributes.push((name.to_ascii_lowercase(), value_text));
+                spans.push(AttributeSpan {
+                    name: Span::new(offset + name_start, offset + name_end),
+                    value: value.map(|(start, end)| Span::new(offset + start, offset + end)),
+                });
             }
         }
 
-        attributes
+        (attributes, spans)
     }
 }
 
@@ -354,6 +445,33 @@ mod tests {
         assert!(matches!(token, Token::Character('H')));
     }
 
+    #[test]
+    fn test_token_spans() {
+        let html = "<a href='/x' hidden>link</a><!--c-->";
+        let mut tokenizer = Tokenizer::new(html);
+
+        let start = tokenizer.next_spanned().unwrap();
+        assert_eq!(start.span, Span::new(0, 20));
+        let href = start.attributes[0];
+        assert_eq!(&html[href.name.start..href.name.end], "href");
+        let value = href.value.unwrap();
+        assert_eq!(&html[value.start..value.end], "/x");
+        assert_eq!(start.attributes[1].value, None);
+
+        // "link" as characters, the end tag and the comment.
+        assert_eq!(tokenizer.count(), 6);
+    }
+
+    #[test]
+    fn test_raw_text_elements() {
+        let text = extract_text("<script>if (a<b) {}</script><p>shown</p>");
+        assert_eq!(text, "shown");
+
+        let tokens: Vec<_> = Tokenizer::new("<style>a<b{}</style>").collect();
+        assert_eq!(tokens.last(), Some(&Token::EndTag { name: "style".to_string() }));
+        assert_eq!(tokens.len(), 7);
+    }
+
     #[test]
     fn test_extract_text() {
         let html = "<p>Hello <b>World</b></p>";
@@ -1,7 +1,7 @@
 //! Tree builder sink for html5ever.
 
 use dom::document::Document;
-use dom::element::{ElementData, TagName};
+use dom::element::{CustomElementState, ElementData, ShadowRoot, ShadowRootMode, TagName};
 use dom::node::{DocumentType, NodeData, NodeId};
 use html5ever::interface::tree_builder::{ElementFlags, NodeOrText, QuirksMode, TreeSink};
 use html5ever::tendril::StrTendril;
@@ -18,6 +18,11 @@ pub struct DomTreeSink<'a> {
     document: &'a mut Document,
     /// Nodes that have been removed but might be re-parented.
     pending_nodes: HashSet<NodeId>,
+    /// Whether `<template shadowrootmode>` attaches a shadow root.
+    declarative_shadow_roots: bool,
+    /// Templates whose contents became a shadow root, dropped once parsing
+    /// no longer needs them.
+    shadow_root_templates: Vec<NodeId>,
 }
 
 impl<'a> DomTreeSink<'a> {
@@ -25,6 +30,16 @@ impl<'a> DomTreeSink<'a> {
         Self {
             document,
             pending_nodes: HashSet::new(),
+            declarative_shadow_roots: true,
+            shadow_root_templates: Vec::new(),
+        }
+    }
+
+    /// Sink for fragment parsing, where declarative shadow roots are inert.
+    pub fn for_fragment(document: &'a mut Document) -> Self {
+        Self {
+            declarative_shadow_roots: false,
+            ..Self::new(document)
         }
     }
 
@@ -35,6 +50,7 @@ impl<'a> DomTreeSink<'a> {
         // Set namespace if not HTML
         if name.ns != html5ever::ns!(html) {
             data.namespace = Some(name.ns.to_string().into());
+            data.custom_state = CustomElementState::Uncustomized;
         }
 
         // Set attributes
@@ -42,8 +58,62 @@ impl<'a> DomTreeSink<'a> {
             data.set_attribute(attr.name.local.as_ref(), &attr.value);
         }
 
+        // Customized built-ins wait for a definition, too
+        if data.namespace.is_none() && data.has_attribute("is") {
+            data.custom_state = CustomElementState::Undefined;
+        }
+
         self.document.tree.create_element(data)
     }
+
+    /// Turn a `<template shadowrootmode>` being inserted into `host` into a
+    /// shadow root on `host`, unless `host` already has one. The template
+    /// is left out of the tree, and its contents, which the rest of the
+    /// template is parsed into, become the shadow root.
+    fn attach_declarative_shadow_root(&mut self, host: NodeId, template: NodeId) -> bool {
+        if !self.declarative_shadow_roots {
+            return false;
+        }
+        let tree = &mut self.document.tree;
+        let (mode, contents, delegates_focus, clonable, serializable) =
+            match tree.get_element(template) {
+                Some(elem) if elem.tag_name == "template" && elem.namespace.is_none() => {
+                    let mode = match elem.get_attribute("shadowrootmode").and_then(ShadowRootMode::parse) {
+                        Some(mode) => mode,
+                        None => return false,
+                    };
+                    let contents = match elem.template_contents {
+                        Some(contents) => contents,
+                        None => return false,
+                    };
+                    (
+                        mode,
+                        contents,
+                        elem.has_attribute("shadowrootdelegatesfocus"),
+                        elem.has_attribute("shadowrootclonable"),
+                        elem.has_attribute("shadowrootserializable"),
+                    )
+                }
+                _ => return false,
+            };
+
+        match tree.get_element(host) {
+            Some(host_elem) if host_elem.shadow_root.is_none() => {}
+            _ => return false,
+        }
+
+        tree.set_shadow_root(
+            host,
+            ShadowRoot {
+                delegates_focus,
+                clonable,
+                serializable,
+                ..ShadowRoot::new(contents, mode)
+            },
+        );
+        self.shadow_root_templates.push(template);
+        true
+    }
 }
 
 impl<'a> TreeSink for DomTreeSink<'a> {
@@ -51,7 +121,13 @@ impl<'a> TreeSink for DomTreeSink<'a> {
     type Output = Self;
     type ElemName<'b> = ExpandedName<'b> where Self: 'b;
 
-    fn finish(self) -> Self::Output {
+    fn finish(mut self) -> Self::Output {
+        for template in std::mem::take(&mut self.shadow_root_templates) {
+            if let Some(elem) = self.document.tree.get_element_mut(template) {
+                elem.template_contents = None;
+            }
+            self.document.tree.remove(template);
+        }
         self
     }
 
@@ -103,6 +179,9 @@ impl<'a> TreeSink for DomTreeSink<'a> {
         match child {
             NodeOrText::AppendNode(handle) => {
                 self.pending_nodes.remove(&handle.0);
+                if self.attach_declarative_shadow_root(parent.0, handle.0) {
+                    return;
+                }
                 self.document.tree.append_child(parent.0, handle.0);
             }
             NodeOrText::AppendText(text) => {
@@ -147,8 +226,11 @@ impl<'a> TreeSink for DomTreeSink<'a> {
     }
 
     fn get_template_contents(&self, target: &Self::Handle) -> Self::Handle {
-        // Return template content (for now, just return the element itself)
-        *target
+        self.document
+            .tree
+            .template_contents(target.0)
+            .map(Handle)
+            .unwrap_or(*target)
     }
 
     fn same_node(&self, x: &Self::Handle, y: &Self::Handle) -> bool {
@@ -172,6 +254,9 @@ impl<'a> TreeSink for DomTreeSink<'a> {
             match new_node {
                 NodeOrText::AppendNode(handle) => {
                     self.pending_nodes.remove(&handle.0);
+                    if self.attach_declarative_shadow_root(parent, handle.0) {
+                        return;
+                    }
                     self.document
                         .tree
                         .insert_before(parent, handle.0, Some(sibling.0));
@@ -250,4 +335,56 @@ mod tests {
         // Basic functionality is tested through the parser
         assert!(doc.tree.root().is_some());
     }
+
+    #[test]
+    fn test_template_contents_are_inert() {
+        let doc = crate::parse_html(
+            "<template id=t><p id=inside>Hi</p></template>",
+            Url::parse("about:blank").unwrap(),
+        );
+        let template = doc.tree.find_elements_by_tag_name("template")[0];
+        assert!(doc.tree.first_child(template).is_none());
+
+        let contents = doc.tree.template_contents(template).unwrap();
+        let p = doc.tree.first_child(contents).unwrap();
+        assert_eq!(doc.tree.get_element(p).unwrap().tag_name, "p");
+        assert!(doc.tree.find_elements_by_tag_name("p").is_empty());
+    }
+
+    #[test]
+    fn test_declarative_shadow_root() {
+        let doc = crate::parse_html(
+            "<div id=host><template shadowrootmode=open shadowrootdelegatesfocus>\
+             <slot></slot></template><span>light</span></div>",
+            Url::parse("about:blank").unwrap(),
+        );
+        let host = doc.tree.find_element_by_id("host").unwrap();
+        let shadow = doc.tree.shadow_root(host).unwrap();
+        assert_eq!(shadow.mode, ShadowRootMode::Open);
+        assert!(shadow.delegates_focus);
+
+        let slot = doc.tree.first_child(shadow.root).unwrap();
+        assert_eq!(doc.tree.get_element(slot).unwrap().tag_name, "slot");
+
+        let children: Vec<_> = doc.tree.children(host).collect();
+        assert_eq!(children.len(), 1);
+        assert_eq!(doc.tree.get_element(children[0]).unwrap().tag_name, "span");
+    }
+
+    #[test]
+    fn test_declarative_shadow_root_attached_while_parsing() {
+        use html5ever::tendril::TendrilSink;
+
+        let mut doc = Document::new(Url::parse("about:blank").unwrap());
+        let mut parser = html5ever::parse_document(DomTreeSink::new(&mut doc), Default::default());
+        parser.process("<div id=host><template shadowrootmode=closed><p>inside".into());
+
+        let tree = &parser.tokenizer.sink.sink.document.tree;
+        let host = tree.find_element_by_id("host").unwrap();
+        let shadow = tree.shadow_root(host).unwrap();
+        assert_eq!(shadow.mode, ShadowRootMode::Closed);
+        let p = tree.first_child(shadow.root).unwrap();
+        assert_eq!(tree.get_element(p).unwrap().tag_name, "p");
+        assert!(tree.first_child(host).is_none());
+    }
 }
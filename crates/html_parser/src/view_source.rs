@@ -0,0 +1,244 @@
+//! `view-source:` rendering.
+//!
+//! Turns a raw HTML response body into an HTML page with line numbers,
+//! highlighted markup and clickable `href`/`src` links.
+
+use crate::serializer::{escape_html_attribute, escape_html_text};
+use crate::tokenizer::{SpannedToken, Token, Tokenizer};
+use url::Url;
+
+/// URL scheme prefix for view-source pages.
+pub const VIEW_SOURCE_SCHEME: &str = "view-source";
+
+const VIEW_SOURCE_STYLE: &str = "\
+body { margin: 0; font-family: monospace; font-size: 13px; white-space: pre-wrap; }
+table { border-collapse: collapse; }
+.line-number { color: #999; text-align: right; padding: 0 8px; user-select: none; vertical-align: top; }
+.line-content { padding-left: 4px; }
+.html-tag { color: #881280; }
+.html-attribute-name { color: #994500; }
+.html-attribute-value { color: #1a1aa6; }
+.html-comment { color: #236e25; }
+.html-doctype { color: #c0c0c0; }
+";
+
+/// Attributes rendered as links.
+const LINK_ATTRIBUTES: &[&str] = &["href", "src"];
+
+/// URL schemes that are safe to link to. `file:` URLs are only linked
+/// from pages that are themselves `file:`.
+const LINK_SCHEMES: &[&str] = &["http", "https", "ftp"];
+
+/// Render `source`, fetched from `url`, as a view-source page.
+pub fn render_view_source(source: &str, url: &Url) -> String {
+    let mut writer = SourceWriter::default();
+    let mut base = url.clone();
+    let mut base_set = false;
+    let mut pos = 0;
+
+    let mut tokenizer = Tokenizer::new(source);
+    while let Some(spanned) = tokenizer.next_spanned() {
+        // Bytes the tokenizer skipped (`<?...>`, CDATA) are shown as-is.
+        if spanned.span.start > pos {
+            writer.push(None, &source[pos..spanned.span.start]);
+        }
+
+        let text = &source[spanned.span.start..spanned.span.end];
+        match &spanned.token {
+            Token::Character(_) => writer.push(None, text),
+            Token::Comment(_) => writer.push(Some("html-comment"), text),
+            Token::Doctype { .. } => writer.push(Some("html-doctype"), text),
+            Token::EndTag { .. } => writer.push(Some("html-tag"), text),
+            Token::StartTag {
+                name, attributes, ..
+            } => {
+                // The first <base href> changes how later links resolve.
+                if name == "base" && !base_set {
+                    if let Some((_, href)) = attributes.iter().find(|(n, _)| n == "href") {
+                        if let Ok(resolved) = url.join(href.trim()) {
+                            base = resolved;
+                            base_set = true;
+                        }
+                    }
+                }
+                write_start_tag(&mut writer, source, &spanned, &base, url);
+            }
+            Token::EndOfFile => {}
+        }
+
+        pos = spanned.span.end;
+    }
+    if pos < source.len() {
+        writer.push(None, &source[pos..]);
+    }
+
+    let title = format!("{}:{}", VIEW_SOURCE_SCHEME, url);
+    let mut output = String::new();
+    output.push_str("<!DOCTYPE html>\n<html><head><title>");
+    output.push_str(&escape_html_text(&title));
+    output.push_str("</title><style>");
+    output.push_str(VIEW_SOURCE_STYLE);
+    output.push_str("</style></head><body><table>");
+    for (i, line) in writer.finish().iter().enumerate() {
+        output.push_str("<tr><td class=\"line-number\">");
+        output.push_str(&(i + 1).to_string());
+        output.push_str("</td><td class=\"line-content\">");
+        output.push_str(line);
+        output.push_str("</td></tr>");
+    }
+    output.push_str("</table></body></html>\n");
+    output
+}
+
+fn write_start_tag(
+    writer: &mut SourceWriter,
+    source: &str,
+    spanned: &SpannedToken,
+    base: &Url,
+    document: &Url,
+) {
+    let names = match &spanned.token {
+        Token::StartTag { attributes, .. } => attributes,
+        _ => return,
+    };
+
+    let mut cursor = spanned.span.start;
+    for ((name, value), span) in names.iter().zip(&spanned.attributes) {
+        writer.push(Some("html-tag"), &source[cursor..span.name.start]);
+        writer.push(
+            Some("html-attribute-name"),
+            &source[span.name.start..span.name.end],
+        );
+        cursor = span.name.end;
+
+        if let Some(value_span) = span.value {
+            writer.push(Some("html-tag"), &source[cursor..value_span.start]);
+            let text = &source[value_span.start..value_span.end];
+            let link = LINK_ATTRIBUTES
+                .contains(&name.as_str())
+                .then(|| base.join(value.trim()).ok())
+                .flatten()
+                .filter(|resolved| {
+                    LINK_SCHEMES.contains(&resolved.scheme())
+                        || (resolved.scheme() == "file" && document.scheme() == "file")
+                });
+            match link {
+                Some(resolved) => writer.push_link(text, resolved.as_str()),
+                None => writer.push(Some("html-attribute-value"), text),
+            }
+            cursor = value_span.end;
+        }
+    }
+    writer.push(Some("html-tag"), &source[cursor..spanned.span.end]);
+}
+
+/// Accumulates highlighted output one source line at a time, closing and
+/// reopening highlight spans at line breaks.
+#[derive(Default)]
+struct SourceWriter {
+    lines: Vec<String>,
+    current: String,
+}
+
+impl SourceWriter {
+    fn push(&mut self, class: Option<&str>, text: &str) {
+        self.write(text, |output, part| match class {
+            Some(class) => {
+                output.push_str("<span class=\"");
+                output.push_str(class);
+                output.push_str("\">");
+                output.push_str(&escape_html_text(part));
+                output.push_str("</span>");
+            }
+            None => output.push_str(&escape_html_text(part)),
+        });
+    }
+
+    fn push_link(&mut self, text: &str, href: &str) {
+        self.write(text, |output, part| {
+            output.push_str(
+                "<a class=\"html-attribute-value html-resource-link\" target=\"_blank\" href=\"",
+            );
+            output.push_str(&escape_html_attribute(href));
+            output.push_str("\">");
+            output.push_str(&escape_html_text(part));
+            output.push_str("</a>");
+        });
+    }
+
+    fn write(&mut self, text: &str, mut wrap: impl FnMut(&mut String, &str)) {
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.lines.push(std::mem::take(&mut self.current));
+            }
+            let part = part.strip_suffix('\r').unwrap_or(part);
+            if !part.is_empty() {
+                wrap(&mut self.current, part);
+            }
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if !self.current.is_empty() || self.lines.is_empty() {
+            self.lines.push(self.current);
+        }
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(source: &str) -> String {
+        render_view_source(
+            source,
+            &Url::parse("https://example.com/dir/page.html").unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let output = render("<p>\none\n</p>\n");
+        assert!(output.contains("<td class=\"line-number\">3</td>"));
+        assert!(!output.contains("<td class=\"line-number\">4</td>"));
+        assert!(output.contains("<title>view-source:https://example.com/dir/page.html</title>"));
+    }
+
+    #[test]
+    fn test_highlighting() {
+        let output = render("<!DOCTYPE html><div class=\"a\"><!-- note --></div>");
+        assert!(output.contains("<span class=\"html-doctype\">&lt;!DOCTYPE html&gt;</span>"));
+        assert!(output.contains("<span class=\"html-attribute-name\">class</span>"));
+        assert!(output.contains("<span class=\"html-attribute-value\">a</span>"));
+        assert!(output.contains("<span class=\"html-comment\">&lt;!-- note --&gt;</span>"));
+        assert!(output.contains("<span class=\"html-tag\">&lt;/div&gt;</span>"));
+    }
+
+    #[test]
+    fn test_multiline_comment_spans_lines() {
+        let output = render("<!-- a\nb -->");
+        assert!(output.contains("<span class=\"html-comment\">&lt;!-- a</span>"));
+        assert!(output.contains("<span class=\"html-comment\">b --&gt;</span>"));
+    }
+
+    #[test]
+    fn test_links_resolve_against_base() {
+        let output = render("<img src=\"logo.png\"><base href=\"/root/\"><a href=\"x\">x</a><a href=\"javascript:void(0)\">y</a>\
+                             <a href=\"VBScript:x\">z</a><iframe src=\"data:text/html,<b>\"></iframe>");
+        assert!(output.contains("href=\"https://example.com/dir/logo.png\""));
+        assert!(output.contains("href=\"https://example.com/root/x\""));
+        assert!(!output.contains("href=\"javascript:"));
+        assert!(!output.contains("href=\"vbscript:"));
+        assert!(!output.contains("href=\"data:"));
+    }
+
+    #[test]
+    fn test_file_links_only_from_file_pages() {
+        let source = "<a href=\"file:///etc/passwd\">x</a>";
+        assert!(!render(source).contains("href=\"file:"));
+
+        let local = render_view_source(source, &Url::parse("file:///home/page.html").unwrap());
+        assert!(local.contains("href=\"file:///etc/passwd\""));
+    }
+}
@@ -1,22 +1,57 @@
 //! DOM bindings for JavaScript.
 
 use boa_engine::{
-    Context, JsArgs, JsNativeError, JsResult, JsValue, NativeFunction,
+    Context, JsArgs, JsData, JsNativeError, JsResult, JsValue, NativeFunction,
     js_string,
-    object::{builtins::JsFunction, ObjectInitializer, JsObject},
+    object::{builtins::{JsArray, JsFunction}, FunctionObjectBuilder, ObjectInitializer, JsObject},
     property::{Attribute, PropertyDescriptor},
     class::{Class, ClassBuilder},
 };
+use dom::document::{Document, DocumentRef};
+use dom::element::ShadowRootMode;
+use dom::mutation::{Mutation, MutationHookId};
+use dom::node::{NodeData, NodeId};
+use dom::tree::DomTree;
+use dom::{Event, EventListenerOptions, EventType, FocusOrigin};
+use dom::{ShadowError, ShadowRootInit};
+use layout::LayoutTree;
+use boa_gc::{Finalize, Trace};
+use html_parser::fragment::{self, FragmentError, InsertPosition};
+use html_parser::sanitizer::{Sanitizer, SanitizerConfig};
+use html_parser::serializer::{serialize_inner_html, serialize_outer_html};
+use style::query::SelectorQuery;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::Arc;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
+use web_apis::mutation_observer::{
+    MutationObserver, MutationObserverController, MutationObserverInit, MutationRecord,
+};
+
+thread_local! {
+    /// Document backing the native DOM functions on this thread.
+    static BOUND_DOCUMENT: RefCell<Option<DocumentRef>> = const { RefCell::new(None) };
+    /// Mutation observers created by scripts on this thread.
+    static MUTATION_OBSERVERS: RefCell<ScriptMutationObservers> = RefCell::new(ScriptMutationObservers::default());
+}
+
+/// Script-created mutation observers and their callbacks.
+#[derive(Default)]
+struct ScriptMutationObservers {
+    /// Shared with the hook on the bound document's tree.
+    controller: Arc<Mutex<MutationObserverController>>,
+    /// Observers with observed targets by callback handle, kept alive while
+    /// they observe something like nodes keep their registered observers.
+    observing: HashMap<u64, JsObject>,
+    next_callback: u64,
+    /// Hook installed on the bound document.
+    hook: Option<MutationHookId>,
+}
 
 /// DOM binding registry.
 pub struct DomBindings {
     /// Node cache (DOM node ID -> JS object).
     node_cache: HashMap<u64, JsValue>,
-    /// Event handlers.
-    event_handlers: HashMap<(u64, String), Vec<JsValue>>,
 }
 
 impl DomBindings {
@@ -24,10 +59,47 @@ impl DomBindings {
     pub fn new() -> Self {
         Self {
             node_cache: HashMap::new(),
-            event_handlers: HashMap::new(),
         }
     }
 
+    /// Bind the document that native DOM functions operate on.
+    ///
+    /// Mutations of its tree are queued for script mutation observers and
+    /// custom element reactions.
+    pub fn bind_document(&self, document: DocumentRef) {
+        self.unbind_document();
+        let controller = MUTATION_OBSERVERS.with(|observers| observers.borrow().controller.clone());
+        let hook = document.write().tree.add_mutation_hook(Arc::new(
+            move |tree: &DomTree, mutation: &Mutation| controller.lock().notify_mutation(tree, mutation),
+        ));
+        MUTATION_OBSERVERS.with(|observers| observers.borrow_mut().hook = Some(hook));
+        crate::custom_elements::observe_tree(&mut document.write().tree);
+        crate::range::observe_tree(&mut document.write().tree);
+        crate::traversal::observe_tree(&mut document.write().tree);
+        BOUND_DOCUMENT.with(|bound| *bound.borrow_mut() = Some(document));
+    }
+
+    /// Release the bound document.
+    pub fn unbind_document(&self) {
+        let document = BOUND_DOCUMENT.with(|bound| bound.borrow_mut().take());
+        let hook = MUTATION_OBSERVERS.with(|observers| observers.borrow_mut().hook.take());
+        if let Some(document) = document {
+            let tree = &mut document.write().tree;
+            if let Some(hook) = hook {
+                tree.remove_mutation_hook(hook);
+            }
+            crate::custom_elements::unobserve_tree(tree);
+            crate::range::unobserve_tree(tree);
+            crate::traversal::unobserve_tree(tree);
+        }
+        crate::collections::clear();
+    }
+
+    /// Bind the layout of the bound document, used for range geometry.
+    pub fn bind_layout(&self, layout: Option<Arc<RwLock<LayoutTree>>>) {
+        crate::range::bind_layout(layout);
+    }
+
     /// Register all DOM classes and constructors.
     pub fn register(&self, context: &mut Context) {
         // Register Node class
@@ -40,10 +112,33 @@ impl DomBindings {
         register_document_class(context);
 
         // Register Event class
-        register_event_class(context);
+        crate::events::register(context);
 
         // Register HTMLElement and subclasses
         register_html_element_classes(context);
+
+        // Register the custom element registry
+        crate::custom_elements::register(context);
+
+        // Register Range, Selection and getSelection()
+        crate::range::register(context);
+
+        // Register the CSS namespace
+        crate::css::register(context);
+
+        // Register HTMLCollection, NodeList, TreeWalker and NodeIterator
+        crate::collections::register(context);
+        crate::traversal::register(context);
+
+        // Register Sanitizer class
+        context
+            .register_global_class::<JsSanitizer>()
+            .expect("Failed to register Sanitizer");
+
+        // Register MutationObserver class
+        context
+            .register_global_class::<JsMutationObserver>()
+            .expect("Failed to register MutationObserver");
     }
 
     /// Create a JavaScript object for a DOM node.
@@ -73,34 +168,41 @@ impl DomBindings {
         obj
     }
 
-    /// Add an event listener.
+    /// Add a script event listener to a node of the bound document.
     pub fn add_event_listener(
         &mut self,
         node_id: u64,
         event_type: &str,
         handler: JsValue,
     ) {
-        let key = (node_id, event_type.to_string());
-        self.event_handlers
-            .entry(key)
-            .or_insert_with(Vec::new)
-            .push(handler);
+        if let Some(handler) = handler.as_object() {
+            let node = NodeId::from_raw(node_id);
+            let options = EventListenerOptions::default();
+            if let Err(error) = crate::events::add_listener(node, event_type, handler.clone(), options) {
+                tracing::warn!("Failed to add event listener: {}", error);
+            }
+        }
     }
 
-    /// Remove an event listener.
+    /// Remove a script event listener.
     pub fn remove_event_listener(
         &mut self,
         node_id: u64,
         event_type: &str,
         handler: &JsValue,
     ) {
-        let key = (node_id, event_type.to_string());
-        if let Some(handlers) = self.event_handlers.get_mut(&key) {
-            handlers.retain(|h| !js_value_equals(h, handler));
+        if let Some(handler) = handler.as_object() {
+            let node = NodeId::from_raw(node_id);
+            if let Err(error) = crate::events::remove_listener(node, event_type, handler, false) {
+                tracing::warn!("Failed to remove event listener: {}", error);
+            }
         }
     }
 
-    /// Dispatch an event to handlers.
+    /// Dispatch an event at a node through the capture, target and bubble
+    /// phases. `event` is a script `Event`, or else an event of
+    /// `event_type` is created. Returns `false` if the default action was
+    /// prevented.
     pub fn dispatch_event(
         &self,
         node_id: u64,
@@ -108,31 +210,34 @@ impl DomBindings {
         event: JsValue,
         context: &mut Context,
     ) -> bool {
-        let key = (node_id, event_type.to_string());
-
-        if let Some(handlers) = self.event_handlers.get(&key) {
-            for handler in handlers {
-                if let Some(callable) = handler.as_callable() {
-                    let this = self.node_cache.get(&node_id).cloned().unwrap_or(JsValue::undefined());
-                    let _ = callable.call(&this, &[event.clone()], context);
-                }
+        let node = NodeId::from_raw(node_id);
+        let result = match event.as_object() {
+            Some(object) if crate::events::is_event(object) => {
+                crate::events::dispatch_object(node, object, context)
             }
-        }
-
-        true // Would return false if preventDefault was called
+            _ => {
+                let mut event = Event::new(EventType::from_str(event_type));
+                crate::events::dispatch_event(node, &mut event, context)
+            }
+        };
+        result.unwrap_or_else(|error| {
+            tracing::warn!("Failed to dispatch {} event: {}", event_type, error);
+            true
+        })
     }
 
     /// Remove a node from the cache.
     pub fn remove_node(&mut self, node_id: u64) {
         self.node_cache.remove(&node_id);
-        // Remove associated event handlers
-        self.event_handlers.retain(|(id, _), _| *id != node_id);
+        // Remove associated event listeners
+        let _ = with_document(|doc| doc.events.lock().remove_all(NodeId::from_raw(node_id)));
     }
 
     /// Clear all bindings.
     pub fn clear(&mut self) {
         self.node_cache.clear();
-        self.event_handlers.clear();
+        crate::collections::clear();
+        crate::events::clear_listeners();
     }
 }
 
@@ -152,14 +257,6 @@ pub enum NodeType {
     DocumentFragment,
 }
 
-/// Compare two JsValues for equality.
-fn js_value_equals(a: &JsValue, b: &JsValue) -> bool {
-    match (a, b) {
-        (JsValue::Object(a), JsValue::Object(b)) => std::ptr::eq(a.as_ref(), b.as_ref()),
-        _ => false,
-    }
-}
-
 /// Register the Node class.
 fn register_node_class(context: &mut Context) {
     let node = ObjectInitializer::new(context)
@@ -182,15 +279,34 @@ fn register_node_class(context: &mut Context) {
 
 /// Register the Element class.
 fn register_element_class(context: &mut Context) {
+    let realm = context.realm().clone();
+    let inner_html_get = NativeFunction::from_fn_ptr(element_get_inner_html).to_js_function(&realm);
+    let inner_html_set = NativeFunction::from_fn_ptr(element_set_inner_html).to_js_function(&realm);
+    let outer_html_get = NativeFunction::from_fn_ptr(element_get_outer_html).to_js_function(&realm);
+    let outer_html_set = NativeFunction::from_fn_ptr(element_set_outer_html).to_js_function(&realm);
+    let shadow_root_get = NativeFunction::from_fn_ptr(element_get_shadow_root).to_js_function(&realm);
+    let assigned_slot_get = NativeFunction::from_fn_ptr(node_get_assigned_slot).to_js_function(&realm);
+
     let element_proto = ObjectInitializer::new(context)
+        .accessor(js_string!("innerHTML"), Some(inner_html_get), Some(inner_html_set), Attribute::CONFIGURABLE)
+        .accessor(js_string!("outerHTML"), Some(outer_html_get), Some(outer_html_set), Attribute::CONFIGURABLE)
+        .accessor(js_string!("shadowRoot"), Some(shadow_root_get), None, Attribute::CONFIGURABLE)
+        .accessor(js_string!("assignedSlot"), Some(assigned_slot_get), None, Attribute::CONFIGURABLE)
+        .function(NativeFunction::from_fn_ptr(element_attach_shadow), js_string!("attachShadow"), 1)
+        .function(NativeFunction::from_fn_ptr(slot_assigned_nodes), js_string!("assignedNodes"), 0)
+        .function(NativeFunction::from_fn_ptr(slot_assigned_elements), js_string!("assignedElements"), 0)
+        .function(NativeFunction::from_fn_ptr(element_insert_adjacent_html), js_string!("insertAdjacentHTML"), 2)
+        .function(NativeFunction::from_fn_ptr(element_set_html), js_string!("setHTML"), 1)
         .function(NativeFunction::from_fn_ptr(element_get_attribute), js_string!("getAttribute"), 1)
         .function(NativeFunction::from_fn_ptr(element_set_attribute), js_string!("setAttribute"), 2)
         .function(NativeFunction::from_fn_ptr(element_remove_attribute), js_string!("removeAttribute"), 1)
         .function(NativeFunction::from_fn_ptr(element_has_attribute), js_string!("hasAttribute"), 1)
         .function(NativeFunction::from_fn_ptr(element_query_selector), js_string!("querySelector"), 1)
         .function(NativeFunction::from_fn_ptr(element_query_selector_all), js_string!("querySelectorAll"), 1)
-        .function(NativeFunction::from_fn_ptr(element_get_elements_by_class_name), js_string!("getElementsByClassName"), 1)
-        .function(NativeFunction::from_fn_ptr(element_get_elements_by_tag_name), js_string!("getElementsByTagName"), 1)
+        .function(NativeFunction::from_fn_ptr(element_matches), js_string!("matches"), 1)
+        .function(NativeFunction::from_fn_ptr(element_closest), js_string!("closest"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::collections::get_elements_by_class_name), js_string!("getElementsByClassName"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::collections::get_elements_by_tag_name), js_string!("getElementsByTagName"), 1)
         .function(NativeFunction::from_fn_ptr(element_append), js_string!("append"), 1)
         .function(NativeFunction::from_fn_ptr(element_prepend), js_string!("prepend"), 1)
         .function(NativeFunction::from_fn_ptr(element_remove), js_string!("remove"), 0)
@@ -200,10 +316,11 @@ fn register_element_class(context: &mut Context) {
         .function(NativeFunction::from_fn_ptr(node_replace_child), js_string!("replaceChild"), 2)
         .function(NativeFunction::from_fn_ptr(node_clone_node), js_string!("cloneNode"), 1)
         .function(NativeFunction::from_fn_ptr(node_contains), js_string!("contains"), 1)
-        .function(NativeFunction::from_fn_ptr(event_target_add_event_listener), js_string!("addEventListener"), 2)
-        .function(NativeFunction::from_fn_ptr(event_target_remove_event_listener), js_string!("removeEventListener"), 2)
-        .function(NativeFunction::from_fn_ptr(event_target_dispatch_event), js_string!("dispatchEvent"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_add_event_listener), js_string!("addEventListener"), 2)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_remove_event_listener), js_string!("removeEventListener"), 2)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_dispatch_event), js_string!("dispatchEvent"), 1)
         .build();
+    crate::collections::install_node(&element_proto, context);
 
     context
         .register_global_property(js_string!("Element"), element_proto, Attribute::all())
@@ -212,159 +329,922 @@ fn register_element_class(context: &mut Context) {
 
 /// Register the Document class.
 fn register_document_class(context: &mut Context) {
+    let realm = context.realm().clone();
+    let active_element_get = NativeFunction::from_fn_ptr(document_get_active_element).to_js_function(&realm);
+
     let document = ObjectInitializer::new(context)
+        .accessor(js_string!("activeElement"), Some(active_element_get), None, Attribute::CONFIGURABLE)
         .function(NativeFunction::from_fn_ptr(document_get_element_by_id), js_string!("getElementById"), 1)
         .function(NativeFunction::from_fn_ptr(document_create_element), js_string!("createElement"), 1)
         .function(NativeFunction::from_fn_ptr(document_create_text_node), js_string!("createTextNode"), 1)
         .function(NativeFunction::from_fn_ptr(document_create_comment), js_string!("createComment"), 1)
         .function(NativeFunction::from_fn_ptr(document_create_document_fragment), js_string!("createDocumentFragment"), 0)
+        .function(NativeFunction::from_fn_ptr(crate::range::document_create_range), js_string!("createRange"), 0)
+        .function(NativeFunction::from_fn_ptr(crate::range::get_selection), js_string!("getSelection"), 0)
+        .function(NativeFunction::from_fn_ptr(crate::traversal::document_create_tree_walker), js_string!("createTreeWalker"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::traversal::document_create_node_iterator), js_string!("createNodeIterator"), 1)
         .function(NativeFunction::from_fn_ptr(element_query_selector), js_string!("querySelector"), 1)
         .function(NativeFunction::from_fn_ptr(element_query_selector_all), js_string!("querySelectorAll"), 1)
-        .function(NativeFunction::from_fn_ptr(element_get_elements_by_class_name), js_string!("getElementsByClassName"), 1)
-        .function(NativeFunction::from_fn_ptr(element_get_elements_by_tag_name), js_string!("getElementsByTagName"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::collections::get_elements_by_class_name), js_string!("getElementsByClassName"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::collections::get_elements_by_tag_name), js_string!("getElementsByTagName"), 1)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_add_event_listener), js_string!("addEventListener"), 2)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_remove_event_listener), js_string!("removeEventListener"), 2)
+        .function(NativeFunction::from_fn_ptr(crate::events::event_target_dispatch_event), js_string!("dispatchEvent"), 1)
         .build();
+    crate::collections::install_document(&document, context);
+    crate::editing::install_document(&document, context);
 
     context
         .register_global_property(js_string!("document"), document, Attribute::all())
         .expect("Failed to register document");
 }
 
-/// Register the Event class.
-fn register_event_class(context: &mut Context) {
-    let event = ObjectInitializer::new(context)
-        .function(NativeFunction::from_fn_ptr(event_prevent_default), js_string!("preventDefault"), 0)
-        .function(NativeFunction::from_fn_ptr(event_stop_propagation), js_string!("stopPropagation"), 0)
-        .function(NativeFunction::from_fn_ptr(event_stop_immediate_propagation), js_string!("stopImmediatePropagation"), 0)
-        .build();
-
-    context
-        .register_global_property(js_string!("Event"), event, Attribute::all())
-        .expect("Failed to register Event");
-}
-
 /// Register HTMLElement and common subclasses.
 fn register_html_element_classes(context: &mut Context) {
-    // HTMLElement (extends Element)
-    let html_element = ObjectInitializer::new(context)
+    // HTMLElement (extends Element), constructed by custom element classes
+    let prototype = ObjectInitializer::new(context)
         .function(NativeFunction::from_fn_ptr(html_element_focus), js_string!("focus"), 0)
         .function(NativeFunction::from_fn_ptr(html_element_blur), js_string!("blur"), 0)
         .function(NativeFunction::from_fn_ptr(html_element_click), js_string!("click"), 0)
         .build();
+    crate::forms::install(&prototype, context);
+    crate::editing::install(&prototype, context);
+    if let Ok(JsValue::Object(element_proto)) = context.global_object().get(js_string!("Element"), context) {
+        prototype.set_prototype(Some(element_proto));
+    }
+
+    let realm = context.realm().clone();
+    let html_element = FunctionObjectBuilder::new(
+        &realm,
+        NativeFunction::from_fn_ptr(crate::custom_elements::html_element_constructor),
+    )
+    .name(js_string!("HTMLElement"))
+    .length(0)
+    .constructor(true)
+    .build();
+    html_element
+        .define_property_or_throw(
+            js_string!("prototype"),
+            PropertyDescriptor::builder().value(prototype.clone()).writable(false).enumerable(false).configurable(false),
+            context,
+        )
+        .expect("Failed to define HTMLElement.prototype");
+    prototype
+        .define_property_or_throw(
+            js_string!("constructor"),
+            PropertyDescriptor::builder().value(html_element.clone()).writable(true).enumerable(false).configurable(true),
+            context,
+        )
+        .expect("Failed to define HTMLElement.prototype.constructor");
 
     context
         .register_global_property(js_string!("HTMLElement"), html_element, Attribute::all())
         .expect("Failed to register HTMLElement");
 }
 
-// === Native function implementations ===
+/// The `Sanitizer` class, wrapping a sanitizer configuration.
+#[derive(Debug, Trace, Finalize, JsData)]
+struct JsSanitizer {
+    #[unsafe_ignore_trace]
+    sanitizer: Sanitizer,
+}
 
-fn element_get_attribute(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _name = args.get_or_undefined(0).to_string(ctx)?;
-    // Would look up attribute on the DOM node
-    Ok(JsValue::null())
+impl Class for JsSanitizer {
+    const NAME: &'static str = "Sanitizer";
+    const LENGTH: usize = 1;
+
+    fn init(_class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        Ok(())
+    }
+
+    fn data_constructor(_new_target: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Self> {
+        Ok(Self {
+            sanitizer: Sanitizer::new(sanitizer_config(args.get_or_undefined(0), ctx)?),
+        })
+    }
+}
+
+/// Convert a `SanitizerConfig` dictionary into a config.
+fn sanitizer_config(value: &JsValue, ctx: &mut Context) -> JsResult<SanitizerConfig> {
+    let mut config = SanitizerConfig::new();
+    let Some(object) = value.as_object() else {
+        return Ok(config);
+    };
+
+    config.elements = string_list(&object.get(js_string!("elements"), ctx)?, ctx)?;
+    config.attributes = string_list(&object.get(js_string!("attributes"), ctx)?, ctx)?;
+    if let Some(names) = string_list(&object.get(js_string!("removeElements"), ctx)?, ctx)? {
+        config.remove_elements = names;
+    }
+    if let Some(names) = string_list(&object.get(js_string!("replaceWithChildrenElements"), ctx)?, ctx)? {
+        config.replace_with_children_elements = names;
+    }
+    if let Some(names) = string_list(&object.get(js_string!("removeAttributes"), ctx)?, ctx)? {
+        config.remove_attributes = names;
+    }
+    let comments = object.get(js_string!("comments"), ctx)?;
+    if !comments.is_undefined() {
+        config.comments = comments.to_boolean();
+    }
+    let data_attributes = object.get(js_string!("dataAttributes"), ctx)?;
+    if !data_attributes.is_undefined() {
+        config.data_attributes = data_attributes.to_boolean();
+    }
+
+    Ok(config)
+}
+
+/// Read an array of names, accepting strings or `{ name }` dictionaries.
+fn string_list(value: &JsValue, ctx: &mut Context) -> JsResult<Option<std::collections::HashSet<String>>> {
+    let Some(array) = value.as_object() else {
+        return Ok(None);
+    };
+
+    let length = array.get(js_string!("length"), ctx)?.to_length(ctx)?;
+    let mut names = std::collections::HashSet::new();
+    for i in 0..length {
+        let item = array.get(i, ctx)?;
+        let name = match item.as_object() {
+            Some(dict) => dict.get(js_string!("name"), ctx)?,
+            None => item,
+        };
+        names.insert(name.to_string(ctx)?.to_std_string_escaped().to_ascii_lowercase());
+    }
+    Ok(Some(names))
+}
+
+/// The `MutationObserver` class.
+#[derive(Debug, Trace, JsData)]
+struct JsMutationObserver {
+    /// Observer registered with the controller.
+    #[unsafe_ignore_trace]
+    id: u64,
+    /// Handle the controller delivers records under.
+    #[unsafe_ignore_trace]
+    handle: u64,
+    callback: JsFunction,
+}
+
+impl Finalize for JsMutationObserver {
+    fn finalize(&self) {
+        // Observers that observe something are reachable through the
+        // registry, so this one only needs unregistering. Collection may run
+        // while the registry is in use, leaving an inert registration.
+        let _ = MUTATION_OBSERVERS.try_with(|observers| {
+            if let Ok(observers) = observers.try_borrow() {
+                if let Some(mut controller) = observers.controller.try_lock() {
+                    controller.unregister(self.id);
+                }
+            }
+        });
+    }
 }
 
-fn element_set_attribute(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _name = args.get_or_undefined(0).to_string(ctx)?;
-    let _value = args.get_or_undefined(1).to_string(ctx)?;
-    // Would set attribute on the DOM node
+impl Class for JsMutationObserver {
+    const NAME: &'static str = "MutationObserver";
+    const LENGTH: usize = 1;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        class
+            .method(js_string!("observe"), 2, NativeFunction::from_fn_ptr(mutation_observer_observe))
+            .method(js_string!("disconnect"), 0, NativeFunction::from_fn_ptr(mutation_observer_disconnect))
+            .method(js_string!("takeRecords"), 0, NativeFunction::from_fn_ptr(mutation_observer_take_records));
+        Ok(())
+    }
+
+    fn data_constructor(_new_target: &JsValue, args: &[JsValue], _ctx: &mut Context) -> JsResult<Self> {
+        let callback = args
+            .get_or_undefined(0)
+            .as_object()
+            .cloned()
+            .and_then(JsFunction::from_object)
+            .ok_or_else(|| JsNativeError::typ().with_message("MutationObserver callback is not a function"))?;
+        Ok(MUTATION_OBSERVERS.with(|observers| {
+            let mut observers = observers.borrow_mut();
+            observers.next_callback += 1;
+            let handle = observers.next_callback;
+            let observer = MutationObserver::new(handle);
+            let id = observer.id();
+            observers.controller.lock().register(observer);
+            Self { id, handle, callback }
+        }))
+    }
+}
+
+/// Convert a `MutationObserverInit` dictionary, applying its implied flags.
+fn mutation_observer_init(value: &JsValue, ctx: &mut Context) -> JsResult<MutationObserverInit> {
+    let mut init = MutationObserverInit::new();
+    let Some(object) = value.as_object() else {
+        return Ok(init);
+    };
+
+    let flag = |name: &str, ctx: &mut Context| -> JsResult<Option<bool>> {
+        let value = object.get(js_string!(name), ctx)?;
+        Ok((!value.is_undefined()).then(|| value.to_boolean()))
+    };
+    init.child_list = flag("childList", ctx)?.unwrap_or(false);
+    init.subtree = flag("subtree", ctx)?.unwrap_or(false);
+    init.attribute_old_value = flag("attributeOldValue", ctx)?.unwrap_or(false);
+    init.character_data_old_value = flag("characterDataOldValue", ctx)?.unwrap_or(false);
+    let attributes = flag("attributes", ctx)?;
+    let character_data = flag("characterData", ctx)?;
+    init.attribute_filter = string_list(&object.get(js_string!("attributeFilter"), ctx)?, ctx)?
+        .map(|names| names.into_iter().collect());
+
+    init.attributes = attributes.unwrap_or(init.attribute_old_value || init.attribute_filter.is_some());
+    init.character_data = character_data.unwrap_or(init.character_data_old_value);
+    Ok(init)
+}
+
+/// Resolve the controller id and callback handle of the observer behind
+/// `this`.
+fn this_observer(this: &JsValue) -> JsResult<(JsObject, u64, u64)> {
+    this.as_object()
+        .and_then(|object| {
+            let (id, handle) = object
+                .downcast_ref::<JsMutationObserver>()
+                .map(|observer| (observer.id, observer.handle))?;
+            Some((object.clone(), id, handle))
+        })
+        .ok_or_else(|| JsNativeError::typ().with_message("'this' is not a MutationObserver").into())
+}
+
+fn mutation_observer_observe(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (object, id, handle) = this_observer(this)?;
+    let target = this_node_id(args.get_or_undefined(0), ctx)?;
+    let options = mutation_observer_init(args.get_or_undefined(1), ctx)?;
+    let result = MUTATION_OBSERVERS.with(|observers| {
+        let mut observers = observers.borrow_mut();
+        let result = match observers.controller.lock().observer_mut(id) {
+            Some(observer) => observer.observe(target.to_raw(), options),
+            None => Ok(()),
+        };
+        if result.is_ok() {
+            observers.observing.insert(handle, object);
+        }
+        result
+    });
+    result.map_err(|message| JsNativeError::typ().with_message(message))?;
     Ok(JsValue::undefined())
 }
 
-fn element_remove_attribute(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _name = args.get_or_undefined(0).to_string(ctx)?;
-    // Would remove attribute from the DOM node
+fn mutation_observer_disconnect(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let (_, id, handle) = this_observer(this)?;
+    MUTATION_OBSERVERS.with(|observers| {
+        let mut observers = observers.borrow_mut();
+        if let Some(observer) = observers.controller.lock().observer_mut(id) {
+            observer.disconnect();
+            observer.take_records();
+        }
+        observers.observing.remove(&handle);
+    });
     Ok(JsValue::undefined())
 }
 
-fn element_has_attribute(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _name = args.get_or_undefined(0).to_string(ctx)?;
-    // Would check if attribute exists on the DOM node
-    Ok(JsValue::from(false))
+fn mutation_observer_take_records(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (_, id, _) = this_observer(this)?;
+    // Edits made in place are only reported on flush.
+    let _ = with_document(|doc| doc.tree.flush_mutations());
+    let records = MUTATION_OBSERVERS.with(|observers| {
+        observers
+            .borrow()
+            .controller
+            .lock()
+            .observer_mut(id)
+            .map(|observer| observer.take_records())
+            .unwrap_or_default()
+    });
+    mutation_records_array(&records, ctx)
 }
 
-fn element_query_selector(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _selector = args.get_or_undefined(0).to_string(ctx)?;
-    // Would query the DOM
-    Ok(JsValue::null())
+/// Deliver queued mutation records to the callbacks of script observers.
+///
+/// Called at microtask checkpoints; returns whether any callback ran, in
+/// which case the callbacks may have queued further records or jobs.
+pub fn notify_mutation_observers(context: &mut Context) -> bool {
+    // Edits made in place are only reported on flush.
+    let _ = with_document(|doc| doc.tree.flush_mutations());
+    let pending = MUTATION_OBSERVERS.with(|observers| observers.borrow().controller.lock().flush());
+
+    let mut delivered = false;
+    for (handle, records) in pending {
+        let Some(observer) =
+            MUTATION_OBSERVERS.with(|observers| observers.borrow().observing.get(&handle).cloned())
+        else {
+            continue;
+        };
+        let Some(callback) = observer
+            .downcast_ref::<JsMutationObserver>()
+            .map(|observer| observer.callback.clone())
+        else {
+            continue;
+        };
+        let result = mutation_records_array(&records, context).and_then(|records| {
+            callback.call(&observer.clone().into(), &[records, observer.into()], context)
+        });
+        if let Err(error) = result {
+            tracing::warn!("MutationObserver callback failed: {}", error);
+        }
+        delivered = true;
+    }
+    release_idle_observers();
+    delivered
+}
+
+/// Drop the registry's references to observers whose targets have all been
+/// removed from the document, letting them be collected.
+fn release_idle_observers() {
+    let _ = with_document(|doc| {
+        MUTATION_OBSERVERS.with(|observers| {
+            let mut observers = observers.borrow_mut();
+            let controller = observers.controller.clone();
+            let mut controller = controller.lock();
+            observers.observing.retain(|_, object| {
+                let Some(id) = object.downcast_ref::<JsMutationObserver>().map(|observer| observer.id) else {
+                    return false;
+                };
+                controller.observer_mut(id).is_some_and(|observer| {
+                    observer.targets().any(|target| doc.tree.get(NodeId::from_raw(target)).is_some())
+                })
+            });
+        })
+    });
+}
+
+/// Build an array of `MutationRecord` objects.
+fn mutation_records_array(records: &[MutationRecord], ctx: &mut Context) -> JsResult<JsValue> {
+    let mut objects = Vec::with_capacity(records.len());
+    for record in records {
+        objects.push(mutation_record_object(record, ctx)?);
+    }
+    Ok(JsArray::from_iter(objects, ctx).into())
+}
+
+fn mutation_record_object(record: &MutationRecord, ctx: &mut Context) -> JsResult<JsValue> {
+    let target = node_object(record.target, ctx)?;
+    let nodes = |ids: &[u64], ctx: &mut Context| -> JsResult<JsValue> {
+        let mut objects = Vec::with_capacity(ids.len());
+        for &id in ids {
+            objects.push(node_object(id, ctx)?);
+        }
+        Ok(JsArray::from_iter(objects, ctx).into())
+    };
+    let added_nodes = nodes(&record.added_nodes, ctx)?;
+    let removed_nodes = nodes(&record.removed_nodes, ctx)?;
+    let previous_sibling = match record.previous_sibling {
+        Some(id) => node_object(id, ctx)?,
+        None => JsValue::null(),
+    };
+    let next_sibling = match record.next_sibling {
+        Some(id) => node_object(id, ctx)?,
+        None => JsValue::null(),
+    };
+    let string_or_null = |value: &Option<String>| -> JsValue {
+        value.as_deref().map_or(JsValue::null(), |value| js_string!(value).into())
+    };
+
+    Ok(ObjectInitializer::new(ctx)
+        .property(js_string!("type"), js_string!(record.mutation_type.to_string()), Attribute::READONLY)
+        .property(js_string!("target"), target, Attribute::READONLY)
+        .property(js_string!("addedNodes"), added_nodes, Attribute::READONLY)
+        .property(js_string!("removedNodes"), removed_nodes, Attribute::READONLY)
+        .property(js_string!("previousSibling"), previous_sibling, Attribute::READONLY)
+        .property(js_string!("nextSibling"), next_sibling, Attribute::READONLY)
+        .property(js_string!("attributeName"), string_or_null(&record.attribute_name), Attribute::READONLY)
+        .property(js_string!("attributeNamespace"), string_or_null(&record.attribute_namespace), Attribute::READONLY)
+        .property(js_string!("oldValue"), string_or_null(&record.old_value), Attribute::READONLY)
+        .build()
+        .into())
+}
+
+/// Wrap any node of the bound document. Nodes that have since been dropped
+/// from the tree only keep their identity.
+pub(crate) fn node_object(raw: u64, ctx: &mut Context) -> JsResult<JsValue> {
+    let shadow = with_document(|doc| {
+        let root = NodeId::from_raw(raw);
+        let host = doc.tree.shadow_host(root)?;
+        Some((host, doc.tree.shadow_root(host)?.mode))
+    })?;
+    if let Some((host, mode)) = shadow {
+        return create_shadow_root_object(raw, host.to_raw(), mode, ctx);
+    }
+
+    let node_type = with_document(|doc| {
+        doc.tree.get(NodeId::from_raw(raw)).and_then(|node| match &node.data {
+            NodeData::Element(elem) => Some(NodeType::Element(elem.tag_name.to_string())),
+            NodeData::Text { .. } => Some(NodeType::Text),
+            NodeData::Comment { .. } => Some(NodeType::Comment),
+            NodeData::Document { .. } => Some(NodeType::Document),
+            NodeData::DocumentFragment => Some(NodeType::DocumentFragment),
+            _ => None,
+        })
+    })?;
+    Ok(match node_type {
+        Some(NodeType::Element(tag_name)) => create_element_object(raw, &tag_name, ctx),
+        Some(NodeType::Text) => create_text_node_object(raw, ctx),
+        Some(NodeType::Comment) => create_comment_node_object(raw, ctx),
+        Some(NodeType::Document) => create_document_object(raw, ctx),
+        Some(NodeType::DocumentFragment) => create_document_fragment_object(raw, ctx),
+        None => ObjectInitializer::new(ctx)
+            .property(js_string!("__nodeId"), raw as f64, Attribute::empty())
+            .build()
+            .into(),
+    })
+}
+
+// === Native function implementations ===
+
+/// Run `f` against the bound document.
+pub(crate) fn with_document<R>(f: impl FnOnce(&mut Document) -> R) -> JsResult<R> {
+    let document = BOUND_DOCUMENT
+        .with(|bound| bound.borrow().clone())
+        .ok_or_else(|| JsNativeError::error().with_message("No document is bound"))?;
+    let mut document = document.write();
+    Ok(f(&mut document))
 }
 
-fn element_query_selector_all(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _selector = args.get_or_undefined(0).to_string(ctx)?;
-    // Would query the DOM and return NodeList
-    Ok(JsValue::undefined()) // Would return NodeList
+/// Resolve the DOM node behind a node wrapper object.
+pub(crate) fn this_node_id(this: &JsValue, ctx: &mut Context) -> JsResult<NodeId> {
+    let object = this
+        .as_object()
+        .ok_or_else(|| JsNativeError::typ().with_message("'this' is not a Node"))?;
+    let raw = object.get(js_string!("__nodeId"), ctx)?;
+    let raw = raw
+        .as_number()
+        .ok_or_else(|| JsNativeError::typ().with_message("'this' is not a Node"))?;
+    Ok(NodeId::from_raw(raw as u64))
 }
 
-fn element_get_elements_by_class_name(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _class_name = args.get_or_undefined(0).to_string(ctx)?;
-    Ok(JsValue::undefined()) // Would return HTMLCollection
+fn fragment_error(error: FragmentError) -> boa_engine::JsError {
+    match error {
+        FragmentError::InvalidPosition(_) => JsNativeError::syntax(),
+        FragmentError::NotAnElement => JsNativeError::typ(),
+        FragmentError::NoModificationAllowed => JsNativeError::error(),
+    }
+    .with_message(error.to_string())
+    .into()
 }
 
-fn element_get_elements_by_tag_name(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _tag_name = args.get_or_undefined(0).to_string(ctx)?;
-    Ok(JsValue::undefined()) // Would return HTMLCollection
+fn element_get_inner_html(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let html = with_document(|doc| serialize_inner_html(&doc.tree, node))?;
+    Ok(js_string!(html).into())
 }
 
-fn element_append(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_set_inner_html(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let html = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    with_document(|doc| fragment::set_inner_html(&mut doc.tree, node, &html))?
+        .map_err(fragment_error)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn element_prepend(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_get_outer_html(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let html = with_document(|doc| serialize_outer_html(&doc.tree, node))?;
+    Ok(js_string!(html).into())
+}
+
+fn element_set_outer_html(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let html = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    with_document(|doc| fragment::set_outer_html(&mut doc.tree, node, &html))?
+        .map_err(fragment_error)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn element_remove(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_set_html(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let html = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+
+    // `options.sanitizer` may be a Sanitizer, a config dictionary, or absent.
+    let sanitizer = match args.get_or_undefined(1).as_object() {
+        Some(options) => {
+            let value = options.get(js_string!("sanitizer"), ctx)?;
+            let existing = value
+                .as_object()
+                .and_then(|object| object.downcast_ref::<JsSanitizer>().map(|s| s.sanitizer.clone()));
+            match existing {
+                Some(sanitizer) => sanitizer,
+                None => Sanitizer::new(sanitizer_config(&value, ctx)?),
+            }
+        }
+        None => Sanitizer::default(),
+    };
+
+    with_document(|doc| sanitizer.set_html(&mut doc.tree, node, &html))?
+        .map_err(fragment_error)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_append_child(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_insert_adjacent_html(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let position = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    let html = args.get_or_undefined(1).to_string(ctx)?.to_std_string_escaped();
+    let position = InsertPosition::parse(&position).map_err(fragment_error)?;
+    with_document(|doc| fragment::insert_adjacent_html(&mut doc.tree, node, position, &html))?
+        .map_err(fragment_error)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_remove_child(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_get_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let name = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    let value = with_document(|doc| {
+        doc.tree
+            .get_element(node)
+            .and_then(|elem| elem.get_attribute(&name).map(str::to_string))
+    })?;
+    Ok(value.map_or(JsValue::null(), |value| js_string!(value).into()))
+}
+
+fn element_set_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let name = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    let value = args.get_or_undefined(1).to_string(ctx)?.to_std_string_escaped();
+    with_document(|doc| doc.tree.set_attribute(node, &name, &value))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_insert_before(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_remove_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let name = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    with_document(|doc| doc.tree.remove_attribute(node, &name))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_replace_child(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_has_attribute(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let name = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    let has = with_document(|doc| {
+        doc.tree.get_element(node).is_some_and(|elem| elem.has_attribute(&name))
+    })?;
+    Ok(JsValue::from(has))
+}
+
+/// Parse the selector argument, throwing a SyntaxError if it is invalid.
+fn selector_query(args: &[JsValue], ctx: &mut Context) -> JsResult<SelectorQuery> {
+    let selectors = args.get_or_undefined(0).to_string(ctx)?.to_std_string_escaped();
+    SelectorQuery::parse(&selectors)
+        .map_err(|error| JsNativeError::syntax().with_message(error.to_string()).into())
+}
+
+/// Node searched by `querySelector` on `this`: the node itself, or the
+/// document root for the `document` object.
+pub(crate) fn query_scope(this: &JsValue, ctx: &mut Context) -> JsResult<NodeId> {
+    let is_node = match this.as_object() {
+        Some(object) => object.has_property(js_string!("__nodeId"), ctx)?,
+        None => false,
+    };
+    if is_node {
+        return this_node_id(this, ctx);
+    }
+    with_document(|doc| doc.tree.root())?
+        .ok_or_else(|| JsNativeError::error().with_message("Document has no root").into())
+}
+
+fn shadow_error(error: ShadowError) -> boa_engine::JsError {
+    match error {
+        ShadowError::NotAnElement => JsNativeError::typ(),
+        ShadowError::NotSupported(_) | ShadowError::AlreadyAttached => JsNativeError::error(),
+    }
+    .with_message(error.to_string())
+    .into()
+}
+
+fn element_attach_shadow(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let host = this_node_id(this, ctx)?;
+    let options = args
+        .get_or_undefined(0)
+        .as_object()
+        .ok_or_else(|| JsNativeError::typ().with_message("attachShadow requires a ShadowRootInit"))?
+        .clone();
+    let mode = options.get(js_string!("mode"), ctx)?.to_string(ctx)?.to_std_string_escaped();
+    let mode = ShadowRootMode::parse(&mode)
+        .ok_or_else(|| JsNativeError::typ().with_message(format!("Invalid shadow root mode '{}'", mode)))?;
+
+    let mut init = ShadowRootInit::new(mode);
+    if options.get(js_string!("delegatesFocus"), ctx)?.to_boolean() {
+        init = init.delegates_focus();
+    }
+    if options.get(js_string!("clonable"), ctx)?.to_boolean() {
+        init = init.clonable();
+    }
+    if options.get(js_string!("serializable"), ctx)?.to_boolean() {
+        init = init.serializable();
+    }
+
+    let root = with_document(|doc| doc.tree.attach_shadow(host, init))?.map_err(shadow_error)?;
+    create_shadow_root_object(root.to_raw(), host.to_raw(), mode, ctx)
+}
+
+/// `shadowRoot`: only open shadow roots are exposed.
+fn element_get_shadow_root(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let host = this_node_id(this, ctx)?;
+    let root = with_document(|doc| {
+        doc.tree
+            .shadow_root(host)
+            .filter(|shadow| shadow.mode == ShadowRootMode::Open)
+            .map(|shadow| shadow.root)
+    })?;
+    match root {
+        Some(root) => create_shadow_root_object(root.to_raw(), host.to_raw(), ShadowRootMode::Open, ctx),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// `assignedSlot`: only slots of open shadow roots are exposed.
+fn node_get_assigned_slot(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let slot = with_document(|doc| {
+        let slot = doc.tree.assigned_slot(node)?;
+        let host = doc.tree.parent(node)?;
+        let open = doc.tree.shadow_root(host)?.mode == ShadowRootMode::Open;
+        open.then_some(slot)
+    })?;
+    element_object(slot, ctx)
+}
+
+/// Nodes assigned to the slot `this`, flattened if `options.flatten`.
+fn assigned_nodes(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<Vec<NodeId>> {
+    let slot = this_node_id(this, ctx)?;
+    let flatten = match args.get_or_undefined(0).as_object() {
+        Some(options) => options.get(js_string!("flatten"), ctx)?.to_boolean(),
+        None => false,
+    };
+    with_document(|doc| {
+        if flatten {
+            doc.tree.flattened_assigned_nodes(slot)
+        } else {
+            doc.tree.assigned_nodes(slot)
+        }
+    })
+}
+
+fn slot_assigned_nodes(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let nodes = assigned_nodes(this, args, ctx)?
+        .into_iter()
+        .map(|node| node_object(node.to_raw(), ctx))
+        .collect::<JsResult<Vec<_>>>()?;
+    Ok(JsArray::from_iter(nodes, ctx).into())
+}
+
+fn slot_assigned_elements(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let elements = assigned_nodes(this, args, ctx)?
+        .into_iter()
+        .map(|node| element_object(Some(node), ctx))
+        .collect::<JsResult<Vec<_>>>()?
+        .into_iter()
+        .filter(|element| !element.is_null())
+        .collect::<Vec<_>>();
+    Ok(JsArray::from_iter(elements, ctx).into())
+}
+
+/// Wrap the element `node` of the bound document.
+pub(crate) fn element_object(node: Option<NodeId>, ctx: &mut Context) -> JsResult<JsValue> {
+    let tag_name = match node {
+        Some(node) => with_document(|doc| {
+            doc.tree.get_element(node).map(|e| e.tag_name.to_string())
+        })?,
+        None => None,
+    };
+    Ok(match (node, tag_name) {
+        (Some(node), Some(tag_name)) => create_element_object(node.to_raw(), &tag_name, ctx),
+        _ => JsValue::null(),
+    })
+}
+
+fn element_query_selector(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let scope = query_scope(this, ctx)?;
+    let query = selector_query(args, ctx)?;
+    let found = with_document(|doc| query.query(&doc.tree, scope))?;
+    element_object(found, ctx)
+}
+
+fn element_query_selector_all(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let scope = query_scope(this, ctx)?;
+    let query = selector_query(args, ctx)?;
+    let found = with_document(|doc| query.query_all(&doc.tree, scope))?;
+    let elements = found
+        .into_iter()
+        .map(|node| element_object(Some(node), ctx))
+        .collect::<JsResult<Vec<_>>>()?;
+    Ok(JsArray::from_iter(elements, ctx).into())
+}
+
+fn element_matches(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let query = selector_query(args, ctx)?;
+    let matched = with_document(|doc| query.matches(&doc.tree, node, Some(node)))?;
+    Ok(JsValue::from(matched))
+}
+
+fn element_closest(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let query = selector_query(args, ctx)?;
+    let found = with_document(|doc| query.closest(&doc.tree, node))?;
+    element_object(found, ctx)
+}
+
+/// Node argument of a DOM method.
+pub(crate) fn node_argument(value: &JsValue, ctx: &mut Context) -> JsResult<NodeId> {
+    let is_node = match value.as_object() {
+        Some(object) => object.has_property(js_string!("__nodeId"), ctx)?,
+        None => false,
+    };
+    if !is_node {
+        return Err(JsNativeError::typ().with_message("parameter is not of type 'Node'").into());
+    }
+    this_node_id(value, ctx)
+}
+
+fn hierarchy_error(message: &str) -> boa_engine::JsError {
+    JsNativeError::error().with_message(message.to_string()).into()
+}
+
+/// Insert `node` into `parent` before `reference`; the children of a
+/// document fragment are inserted in its place.
+fn insert_node(parent: NodeId, node: NodeId, reference: Option<NodeId>) -> JsResult<()> {
+    with_document(|doc| {
+        let tree = &mut doc.tree;
+        if tree.is_shadow_including_inclusive_ancestor(node, parent) {
+            return Err(hierarchy_error("The new child contains the parent"));
+        }
+        if reference.is_some_and(|reference| tree.parent(reference) != Some(parent)) {
+            return Err(hierarchy_error("The reference node is not a child of this node"));
+        }
+        match tree.get(node).map(|node| &node.data) {
+            Some(NodeData::Document { .. }) | None => {
+                Err(hierarchy_error("The node cannot be inserted"))
+            }
+            Some(NodeData::DocumentFragment) => {
+                tree.move_children(node, parent, reference);
+                Ok(())
+            }
+            Some(_) => {
+                tree.insert_before(parent, node, reference);
+                Ok(())
+            }
+        }
+    })?
+}
+
+/// Collect the arguments of `append()` and friends into a new fragment,
+/// with strings as text nodes.
+fn node_arguments(parent: NodeId, args: &[JsValue], ctx: &mut Context) -> JsResult<NodeId> {
+    let mut nodes = Vec::with_capacity(args.len());
+    for arg in args {
+        let is_node = match arg.as_object() {
+            Some(object) => object.has_property(js_string!("__nodeId"), ctx)?,
+            None => false,
+        };
+        nodes.push(if is_node {
+            Ok(this_node_id(arg, ctx)?)
+        } else {
+            Err(arg.to_string(ctx)?.to_std_string_escaped())
+        });
+    }
+    with_document(|doc| {
+        let tree = &mut doc.tree;
+        if nodes
+            .iter()
+            .any(|node| node.as_ref().is_ok_and(|&node| tree.is_shadow_including_inclusive_ancestor(node, parent)))
+        {
+            return Err(hierarchy_error("The new child contains the parent"));
+        }
+        let fragment = tree.create_document_fragment();
+        for node in nodes {
+            match node {
+                Ok(node) if matches!(tree.get(node).map(|n| &n.data), Some(NodeData::DocumentFragment)) => {
+                    tree.move_children(node, fragment, None);
+                }
+                Ok(node) => tree.append_child(fragment, node),
+                Err(text) => {
+                    let text = tree.create_text(text);
+                    tree.append_child(fragment, text);
+                }
+            }
+        }
+        Ok(fragment)
+    })?
+}
+
+fn element_append(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let parent = query_scope(this, ctx)?;
+    let fragment = node_arguments(parent, args, ctx)?;
+    insert_node(parent, fragment, None)?;
+    with_document(|doc| doc.tree.remove(fragment))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_clone_node(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn element_prepend(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let parent = query_scope(this, ctx)?;
+    let fragment = node_arguments(parent, args, ctx)?;
+    let first_child = with_document(|doc| doc.tree.first_child(parent))?;
+    insert_node(parent, fragment, first_child)?;
+    with_document(|doc| doc.tree.remove(fragment))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
     Ok(JsValue::undefined())
 }
 
-fn node_contains(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
-    Ok(JsValue::from(false))
+fn element_remove(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    with_document(|doc| doc.tree.remove_from_parent(node))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(JsValue::undefined())
 }
 
-fn event_target_add_event_listener(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _event_type = args.get_or_undefined(0).to_string(ctx)?;
-    let _handler = args.get_or_undefined(1);
+fn node_append_child(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let parent = query_scope(this, ctx)?;
+    let child = node_argument(args.get_or_undefined(0), ctx)?;
+    insert_node(parent, child, None)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn node_remove_child(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let parent = query_scope(this, ctx)?;
+    let child = node_argument(args.get_or_undefined(0), ctx)?;
+    with_document(|doc| {
+        if doc.tree.parent(child) != Some(parent) {
+            return Err(hierarchy_error("The node to be removed is not a child of this node"));
+        }
+        doc.tree.remove_from_parent(child);
+        Ok(())
+    })??;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn node_insert_before(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let parent = query_scope(this, ctx)?;
+    let child = node_argument(args.get_or_undefined(0), ctx)?;
+    let reference = match args.get_or_undefined(1) {
+        JsValue::Null | JsValue::Undefined => None,
+        reference => Some(node_argument(reference, ctx)?),
+    };
+    insert_node(parent, child, reference)?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(args.get_or_undefined(0).clone())
+}
+
+fn node_replace_child(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
     Ok(JsValue::undefined())
 }
 
-fn event_target_remove_event_listener(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _event_type = args.get_or_undefined(0).to_string(ctx)?;
-    let _handler = args.get_or_undefined(1);
+fn node_clone_node(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
     Ok(JsValue::undefined())
 }
 
-fn event_target_dispatch_event(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
-    Ok(JsValue::from(true))
+fn node_contains(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::from(false))
 }
 
+
+
+
 fn document_get_element_by_id(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
     let _id = args.get_or_undefined(0).to_string(ctx)?;
     Ok(JsValue::null())
 }
 
 fn document_create_element(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
-    let _tag_name = args.get_or_undefined(0).to_string(ctx)?;
-    Ok(JsValue::undefined()) // Would create and return element
+    let local_name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped()
+        .to_ascii_lowercase();
+    let valid = !local_name.is_empty()
+        && !local_name
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || matches!(c, '<' | '>' | '/' | '=' | '\0'));
+    if !valid {
+        return Err(JsNativeError::error()
+            .with_message(format!("'{}' is not a valid element name", local_name))
+            .into());
+    }
+
+    // `options` is either `{ is }` or, historically, the `is` string.
+    let is = match args.get_or_undefined(1) {
+        JsValue::Object(options) => {
+            let is = options.get(js_string!("is"), ctx)?;
+            if is.is_undefined() {
+                None
+            } else {
+                Some(is.to_string(ctx)?.to_std_string_escaped())
+            }
+        }
+        JsValue::String(is) => Some(is.to_std_string_escaped()),
+        _ => None,
+    };
+    crate::custom_elements::create_element(&local_name, is, ctx)
 }
 
 fn document_create_text_node(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
@@ -381,48 +1261,76 @@ fn document_create_document_fragment(_: &JsValue, _args: &[JsValue], _ctx: &mut
     Ok(JsValue::undefined()) // Would create and return document fragment
 }
 
-fn event_prevent_default(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
-    Ok(JsValue::undefined())
-}
 
-fn event_stop_propagation(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
-    Ok(JsValue::undefined())
-}
 
-fn event_stop_immediate_propagation(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+
+fn html_element_focus(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    if let Some(change) = with_document(|doc| doc.focus(node, FocusOrigin::Script))? {
+        crate::events::dispatch_focus_change(change, ctx)?;
+    }
     Ok(JsValue::undefined())
 }
 
-fn html_element_focus(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn html_element_blur(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    if let Some(change) = with_document(|doc| doc.blur(node))? {
+        crate::events::dispatch_focus_change(change, ctx)?;
+    }
     Ok(JsValue::undefined())
 }
 
-fn html_element_blur(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
-    Ok(JsValue::undefined())
+fn document_get_active_element(_: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let active = with_document(|doc| doc.active_element())?;
+    element_object(active, ctx)
 }
 
-fn html_element_click(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+fn html_element_click(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    crate::forms::click(node, ctx)?;
     Ok(JsValue::undefined())
 }
 
 /// Create an Element JavaScript object.
-fn create_element_object(node_id: u64, tag_name: &str, context: &mut Context) -> JsValue {
+pub(crate) fn create_element_object(node_id: u64, tag_name: &str, context: &mut Context) -> JsValue {
+    // Custom elements keep the object made by their constructor.
+    if let Some(instance) = crate::custom_elements::instance(node_id) {
+        return instance.into();
+    }
+
     let obj = ObjectInitializer::new(context)
         .property(js_string!("nodeType"), 1, Attribute::READONLY)
         .property(js_string!("nodeName"), js_string!(tag_name.to_uppercase()), Attribute::READONLY)
         .property(js_string!("tagName"), js_string!(tag_name.to_uppercase()), Attribute::READONLY)
-        .property(js_string!("__nodeId"), node_id as i32, Attribute::empty())
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
         .build();
 
+    // Element methods and accessors live on the global Element object,
+    // under HTMLElement.prototype for `focus()` and `blur()`.
+    if let Some(proto) = html_element_prototype(context) {
+        obj.set_prototype(Some(proto));
+    } else if let Ok(JsValue::Object(proto)) = context.global_object().get(js_string!("Element"), context) {
+        obj.set_prototype(Some(proto));
+    }
+
     obj.into()
 }
 
+/// `HTMLElement.prototype`, once registered.
+fn html_element_prototype(context: &mut Context) -> Option<JsObject> {
+    let constructor = context.global_object().get(js_string!("HTMLElement"), context).ok()?;
+    match constructor.as_object()?.get(js_string!("prototype"), context).ok()? {
+        JsValue::Object(proto) => Some(proto),
+        _ => None,
+    }
+}
+
 /// Create a Text node JavaScript object.
 fn create_text_node_object(node_id: u64, context: &mut Context) -> JsValue {
     let obj = ObjectInitializer::new(context)
         .property(js_string!("nodeType"), 3, Attribute::READONLY)
         .property(js_string!("nodeName"), js_string!("#text"), Attribute::READONLY)
-        .property(js_string!("__nodeId"), node_id as i32, Attribute::empty())
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
         .build();
 
     obj.into()
@@ -433,7 +1341,7 @@ fn create_comment_node_object(node_id: u64, context: &mut Context) -> JsValue {
     let obj = ObjectInitializer::new(context)
         .property(js_string!("nodeType"), 8, Attribute::READONLY)
         .property(js_string!("nodeName"), js_string!("#comment"), Attribute::READONLY)
-        .property(js_string!("__nodeId"), node_id as i32, Attribute::empty())
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
         .build();
 
     obj.into()
@@ -444,18 +1352,42 @@ fn create_document_object(node_id: u64, context: &mut Context) -> JsValue {
     let obj = ObjectInitializer::new(context)
         .property(js_string!("nodeType"), 9, Attribute::READONLY)
         .property(js_string!("nodeName"), js_string!("#document"), Attribute::READONLY)
-        .property(js_string!("__nodeId"), node_id as i32, Attribute::empty())
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
         .build();
 
     obj.into()
 }
 
+/// Create a ShadowRoot JavaScript object. It shares the Element prototype
+/// for `innerHTML` and the query methods.
+fn create_shadow_root_object(
+    node_id: u64,
+    host: u64,
+    mode: ShadowRootMode,
+    context: &mut Context,
+) -> JsResult<JsValue> {
+    let host = node_object(host, context)?;
+    let obj = ObjectInitializer::new(context)
+        .property(js_string!("nodeType"), 11, Attribute::READONLY)
+        .property(js_string!("nodeName"), js_string!("#document-fragment"), Attribute::READONLY)
+        .property(js_string!("mode"), js_string!(mode.as_str()), Attribute::READONLY)
+        .property(js_string!("host"), host, Attribute::READONLY)
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
+        .build();
+
+    if let Ok(JsValue::Object(proto)) = context.global_object().get(js_string!("Element"), context) {
+        obj.set_prototype(Some(proto));
+    }
+
+    Ok(obj.into())
+}
+
 /// Create a DocumentFragment JavaScript object.
 fn create_document_fragment_object(node_id: u64, context: &mut Context) -> JsValue {
     let obj = ObjectInitializer::new(context)
         .property(js_string!("nodeType"), 11, Attribute::READONLY)
         .property(js_string!("nodeName"), js_string!("#document-fragment"), Attribute::READONLY)
-        .property(js_string!("__nodeId"), node_id as i32, Attribute::empty())
+        .property(js_string!("__nodeId"), node_id as f64, Attribute::empty())
         .build();
 
     obj.into()
@@ -471,6 +1403,305 @@ mod tests {
         assert!(bindings.node_cache.is_empty());
     }
 
+    #[test]
+    fn test_inner_html_accessors() {
+        let document = Arc::new(RwLock::new(Document::new(
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let div = {
+            let mut doc = document.write();
+            let root = doc.tree.root().unwrap();
+            let div = doc
+                .tree
+                .create_element(dom::ElementData::new(dom::TagName::div()));
+            doc.tree.append_child(root, div);
+            div
+        };
+
+        let mut context = Context::default();
+        let mut bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let elem = bindings.create_node_object(
+            div.to_raw(),
+            NodeType::Element("div".to_string()),
+            &mut context,
+        );
+        context
+            .register_global_property(js_string!("el"), elem, Attribute::all())
+            .unwrap();
+
+        let result = context
+            .eval(boa_engine::Source::from_bytes(
+                "el.innerHTML = '<b>x</b>'; el.insertAdjacentHTML('beforeend', '<i>y</i>'); el.outerHTML",
+            ))
+            .unwrap();
+        assert_eq!(
+            result.to_string(&mut context).unwrap().to_std_string_escaped(),
+            "<div><b>x</b><i>y</i></div>"
+        );
+        assert_eq!(document.read().tree.children(div).count(), 2);
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_set_html_sanitizer() {
+        let document = Arc::new(RwLock::new(Document::new(
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let div = {
+            let mut doc = document.write();
+            let root = doc.tree.root().unwrap();
+            let div = doc
+                .tree
+                .create_element(dom::ElementData::new(dom::TagName::div()));
+            doc.tree.append_child(root, div);
+            div
+        };
+
+        let mut context = Context::default();
+        let mut bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let elem = bindings.create_node_object(
+            div.to_raw(),
+            NodeType::Element("div".to_string()),
+            &mut context,
+        );
+        context
+            .register_global_property(js_string!("el"), elem, Attribute::all())
+            .unwrap();
+
+        let result = context
+            .eval(boa_engine::Source::from_bytes(
+                "el.setHTML('<b onclick=x()>a</b><em>b</em>', { sanitizer: new Sanitizer({ elements: ['b'] }) }); \
+                 el.setHTML(el.innerHTML + '<script>1</script>'); el.innerHTML",
+            ))
+            .unwrap();
+        assert_eq!(
+            result.to_string(&mut context).unwrap().to_std_string_escaped(),
+            "<b>a</b>b"
+        );
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_selector_queries() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<ul><li class=x><a href=http://a>1</a></li><li><a href=http://b>2</a><a href=/c>3</a></li></ul>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(boa_engine::Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        assert_eq!(
+            eval(&mut context, "document.querySelectorAll('ul > li:not(.x) a[href^=http]').length"),
+            "1"
+        );
+        assert_eq!(
+            eval(&mut context, "const a = document.querySelector('li + li a'); a.innerHTML + a.closest('ul').tagName"),
+            "2UL"
+        );
+        assert_eq!(
+            eval(&mut context, "[a.matches('li:last-child > a'), a.matches('.x a')].join()"),
+            "true,false"
+        );
+        assert_eq!(
+            eval(&mut context, "document.querySelector('ul').querySelectorAll('a').length"),
+            "3"
+        );
+        assert_eq!(
+            eval(&mut context, "try { document.querySelector('ul >'); 'no error' } catch (e) { e.name }"),
+            "SyntaxError"
+        );
+        assert_eq!(
+            eval(&mut context, "try { document.querySelector(':bogus'); 'no error' } catch (e) { e.name }"),
+            "SyntaxError"
+        );
+        assert_eq!(eval(&mut context, "document.querySelector('table')"), "null");
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_mutation_observer() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<ul><li>a</li></ul>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(boa_engine::Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        eval(
+            &mut context,
+            "var log = []; var ul = document.querySelector('ul'); \
+             var observer = new MutationObserver((records, o) => { \
+                 for (const r of records) log.push([r.type, r.target.tagName, r.addedNodes.length, \
+                     r.removedNodes.length, r.attributeName, r.oldValue, o === observer].join()); \
+             }); \
+             observer.observe(ul, { childList: true, subtree: true, attributeOldValue: true }); \
+             ul.setAttribute('class', 'x'); ul.setAttribute('class', 'y'); \
+             ul.innerHTML = '<li>b</li><li>c</li>'; \
+             ul.querySelector('li').setAttribute('id', 'b');",
+        );
+        // Nothing is delivered before the microtask checkpoint.
+        assert_eq!(eval(&mut context, "log.length"), "0");
+
+        assert!(notify_mutation_observers(&mut context));
+        assert_eq!(
+            eval(&mut context, "log.join(';')"),
+            "attributes,UL,0,0,class,,true;attributes,UL,0,0,class,x,true;\
+             childList,UL,2,1,,,true;attributes,LI,0,0,id,,true"
+        );
+        assert!(!notify_mutation_observers(&mut context));
+
+        // Setting an attribute to its current value is still a mutation.
+        assert_eq!(
+            eval(&mut context, "ul.setAttribute('class', 'y'); observer.takeRecords().map(r => r.oldValue).join()"),
+            "y"
+        );
+        assert_eq!(
+            eval(&mut context, "ul.removeAttribute('class'); observer.takeRecords()[0].oldValue"),
+            "y"
+        );
+        let observing = || MUTATION_OBSERVERS.with(|observers| observers.borrow().observing.len());
+        assert_eq!(observing(), 1);
+        eval(&mut context, "observer.disconnect(); ul.setAttribute('class', 'z')");
+        assert!(!notify_mutation_observers(&mut context));
+        assert_eq!(observing(), 0);
+
+        // Observers of removed nodes are released.
+        eval(
+            &mut context,
+            "var li = ul.querySelector('li'); new MutationObserver(() => {}).observe(li, { attributes: true }); \
+             ul.innerHTML = '';",
+        );
+        assert_eq!(observing(), 1);
+        notify_mutation_observers(&mut context);
+        assert_eq!(observing(), 0);
+        assert_eq!(
+            eval(&mut context, "try { observer.observe(ul, {}); 'no error' } catch (e) { e.name }"),
+            "TypeError"
+        );
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_shadow_dom() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div id=host><b slot=title>T</b><i>body</i></div><input>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(boa_engine::Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        assert_eq!(
+            eval(
+                &mut context,
+                "var host = document.querySelector('#host'); \
+                 var root = host.attachShadow({ mode: 'open' }); \
+                 root.innerHTML = '<h1><slot name=title></slot></h1><slot>none</slot>'; \
+                 [root.nodeType, root.mode, root.host.tagName, host.shadowRoot.mode].join()",
+            ),
+            "11,open,DIV,open"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "var slots = root.querySelectorAll('slot'); \
+                 [slots[0].assignedNodes().length, slots[1].assignedElements()[0].tagName, \
+                  host.querySelector('b').assignedSlot.getAttribute('name')].join()",
+            ),
+            "1,I,title"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "try { host.attachShadow({ mode: 'open' }); 'no error' } catch (e) { e.message }",
+            ),
+            "element already hosts a shadow root"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "var closed = document.querySelector('input'); \
+                 try { closed.attachShadow({ mode: 'closed' }); 'no error' } catch (e) { e.message }",
+            ),
+            "<input> cannot host a shadow root"
+        );
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_focus_and_active_element() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<form><input id=name><button id=send>Send</button></form>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(boa_engine::Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        eval(
+            &mut context,
+            "var log = []; var form = document.querySelector('form'); \
+             var name = document.querySelector('#name'), send = document.querySelector('#send'); \
+             ['focus', 'blur', 'focusin', 'focusout'].forEach(function (type) { \
+                 form.addEventListener(type, function (e) { log.push(type + ':' + e.target.getAttribute('id')); }, true); \
+             }); \
+             var before = document.activeElement.tagName; name.focus(); send.focus(); send.blur();",
+        );
+        assert_eq!(
+            eval(&mut context, "before + ' ' + log.join()"),
+            "BODY focus:name,focusin:name,blur:name,focusout:name,focus:send,focusin:send,blur:send,focusout:send"
+        );
+        assert_eq!(eval(&mut context, "document.activeElement.tagName"), "BODY");
+
+        // Tab navigation from Rust fires the same events.
+        let change = document.write().navigate_focus(dom::FocusDirection::Forward).unwrap();
+        crate::events::dispatch_focus_change(change, &mut context).unwrap();
+        assert_eq!(eval(&mut context, "document.activeElement.getAttribute('id') + ' ' + log.slice(-2).join()"), "name focus:name,focusin:name");
+        assert_eq!(eval(&mut context, "form.matches(':focus-within') + ' ' + name.matches(':focus-visible')"), "true true");
+
+        bindings.unbind_document();
+    }
+
     #[test]
     fn test_create_element_object() {
         let mut context = Context::default();
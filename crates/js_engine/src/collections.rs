@@ -0,0 +1,595 @@
+//! Live `HTMLCollection` and `NodeList` for scripts.
+//!
+//! A collection is a proxy over a target object carrying its id into the
+//! thread's `dom::LiveCollection`s. Indexed and named properties are read
+//! from the live collection on every access; the members are cached until
+//! the tree's generation changes. Collections are cached per root and
+//! filter, so `el.children === el.children`.
+
+use crate::bindings::{element_object, node_object, query_scope, with_document};
+use boa_engine::{
+    js_string,
+    object::{
+        builtins::{JsArray, JsProxyBuilder},
+        FunctionObjectBuilder, JsObject, ObjectInitializer,
+    },
+    property::{Attribute, PropertyDescriptor, PropertyKey},
+    Context, JsArgs, JsNativeError, JsResult, JsSymbol, JsValue, NativeFunction,
+};
+use dom::collection::{CollectionFilter, LiveCollection};
+use dom::node::NodeId;
+use dom::tree::DomTree;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// Live collections handed to scripts on this thread.
+    static SCRIPT_COLLECTIONS: RefCell<ScriptCollections> = RefCell::new(ScriptCollections::default());
+}
+
+/// Collections by id, and their proxies by root and filter.
+#[derive(Default)]
+struct ScriptCollections {
+    collections: Vec<LiveCollection>,
+    objects: HashMap<(NodeId, CollectionFilter), JsObject>,
+}
+
+/// Native getter of an accessor property.
+type Getter = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Register the `HTMLCollection` and `NodeList` interfaces.
+pub(crate) fn register(context: &mut Context) {
+    let realm = context.realm().clone();
+    let length = NativeFunction::from_fn_ptr(collection_length).to_js_function(&realm);
+    let values = array_prototype_method("values", context);
+    let for_each = array_prototype_method("forEach", context);
+    let entries = array_prototype_method("entries", context);
+    let keys = array_prototype_method("keys", context);
+
+    let html_collection = ObjectInitializer::new(context)
+        .accessor(
+            js_string!("length"),
+            Some(length.clone()),
+            None,
+            Attribute::CONFIGURABLE,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(collection_item),
+            js_string!("item"),
+            1,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(collection_named_item),
+            js_string!("namedItem"),
+            1,
+        )
+        .property(
+            JsSymbol::iterator(),
+            values.clone(),
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .build();
+    let node_list = ObjectInitializer::new(context)
+        .accessor(
+            js_string!("length"),
+            Some(length),
+            None,
+            Attribute::CONFIGURABLE,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(collection_item),
+            js_string!("item"),
+            1,
+        )
+        .property(
+            js_string!("forEach"),
+            for_each,
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .property(
+            js_string!("entries"),
+            entries,
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .property(
+            js_string!("keys"),
+            keys,
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .property(
+            js_string!("values"),
+            values.clone(),
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .property(
+            JsSymbol::iterator(),
+            values,
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .build();
+
+    for (name, prototype) in [("HTMLCollection", html_collection), ("NodeList", node_list)] {
+        let constructor =
+            FunctionObjectBuilder::new(&realm, NativeFunction::from_fn_ptr(illegal_constructor))
+                .name(js_string!(name))
+                .length(0)
+                .constructor(true)
+                .build();
+        constructor
+            .define_property_or_throw(
+                js_string!("prototype"),
+                PropertyDescriptor::builder()
+                    .value(prototype.clone())
+                    .writable(false)
+                    .enumerable(false)
+                    .configurable(false),
+                context,
+            )
+            .expect("Failed to define collection prototype");
+        prototype
+            .define_property_or_throw(
+                js_string!("constructor"),
+                PropertyDescriptor::builder()
+                    .value(constructor.clone())
+                    .writable(true)
+                    .enumerable(false)
+                    .configurable(true),
+                context,
+            )
+            .expect("Failed to define collection constructor");
+        context
+            .register_global_property(
+                js_string!(name),
+                constructor,
+                Attribute::WRITABLE | Attribute::CONFIGURABLE,
+            )
+            .expect("Failed to register collection interface");
+    }
+}
+
+/// Install `children` and `childNodes` on a node prototype.
+pub(crate) fn install_node(prototype: &JsObject, context: &mut Context) {
+    install(
+        prototype,
+        &[
+            ("children", node_get_children),
+            ("childNodes", node_get_child_nodes),
+        ],
+        context,
+    );
+}
+
+/// Install the document's collections on the `document` object.
+pub(crate) fn install_document(document: &JsObject, context: &mut Context) {
+    install(
+        document,
+        &[
+            ("children", node_get_children),
+            ("childNodes", node_get_child_nodes),
+            ("forms", document_get_forms),
+        ],
+        context,
+    );
+}
+
+fn install(object: &JsObject, getters: &[(&str, Getter)], context: &mut Context) {
+    let realm = context.realm().clone();
+    for &(name, getter) in getters {
+        object
+            .define_property_or_throw(
+                js_string!(name),
+                PropertyDescriptor::builder()
+                    .get(NativeFunction::from_fn_ptr(getter).to_js_function(&realm))
+                    .enumerable(false)
+                    .configurable(true),
+                context,
+            )
+            .expect("Failed to define collection accessor");
+    }
+}
+
+/// Forget every collection, when the bound document changes.
+pub(crate) fn clear() {
+    SCRIPT_COLLECTIONS.with(|collections| *collections.borrow_mut() = ScriptCollections::default());
+}
+
+/// The live collection of `filter` under `root`.
+pub(crate) fn collection(
+    root: NodeId,
+    filter: CollectionFilter,
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let key = (root, filter);
+    if let Some(object) =
+        SCRIPT_COLLECTIONS.with(|collections| collections.borrow().objects.get(&key).cloned())
+    {
+        return Ok(object.into());
+    }
+
+    let interface = if key.1 == CollectionFilter::ChildNodes {
+        "NodeList"
+    } else {
+        "HTMLCollection"
+    };
+    let id = SCRIPT_COLLECTIONS.with(|collections| {
+        let mut collections = collections.borrow_mut();
+        collections
+            .collections
+            .push(LiveCollection::new(root, key.1.clone()));
+        collections.collections.len() - 1
+    });
+    let target = ObjectInitializer::new(ctx)
+        .property(
+            js_string!("__collectionId"),
+            id as f64,
+            Attribute::CONFIGURABLE,
+        )
+        .build();
+    let constructor = ctx.global_object().get(js_string!(interface), ctx)?;
+    if let Some(constructor) = constructor.as_object() {
+        if let JsValue::Object(prototype) = constructor.get(js_string!("prototype"), ctx)? {
+            target.set_prototype(Some(prototype));
+        }
+    }
+
+    let proxy: JsObject = JsProxyBuilder::new(target)
+        .get(trap_get)
+        .set(trap_set)
+        .has(trap_has)
+        .own_keys(trap_own_keys)
+        .get_own_property_descriptor(trap_get_own_property_descriptor)
+        .build(ctx)
+        .into();
+    SCRIPT_COLLECTIONS
+        .with(|collections| collections.borrow_mut().objects.insert(key, proxy.clone()));
+    Ok(proxy.into())
+}
+
+/// Run `f` on the live collection behind `object` and the bound tree.
+fn with_collection<R>(
+    object: &JsValue,
+    f: impl FnOnce(&mut LiveCollection, &DomTree) -> R,
+    ctx: &mut Context,
+) -> JsResult<R> {
+    let id = match object.as_object() {
+        Some(object) => object.get(js_string!("__collectionId"), ctx)?.as_number(),
+        None => None,
+    };
+    let id =
+        id.ok_or_else(|| JsNativeError::typ().with_message("'this' is not a collection"))? as usize;
+    SCRIPT_COLLECTIONS.with(|collections| {
+        let mut collections = collections.borrow_mut();
+        let collection = collections.collections.get_mut(id).ok_or_else(|| {
+            JsNativeError::error().with_message("The collection's document is gone")
+        })?;
+        with_document(|doc| f(collection, &doc.tree))
+    })
+}
+
+/// Whether the collection behind `object` has named properties.
+fn is_html_collection(object: &JsValue, ctx: &mut Context) -> JsResult<bool> {
+    with_collection(
+        object,
+        |collection, _| collection.filter != CollectionFilter::ChildNodes,
+        ctx,
+    )
+}
+
+/// Wrap a member: elements keep their custom element instance.
+fn member_object(node: Option<NodeId>, ctx: &mut Context) -> JsResult<JsValue> {
+    let Some(node) = node else {
+        return Ok(JsValue::undefined());
+    };
+    if with_document(|doc| doc.tree.get_element(node).is_some())? {
+        element_object(Some(node), ctx)
+    } else {
+        node_object(node.to_raw(), ctx)
+    }
+}
+
+fn collection_length(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(with_collection(this, |collection, tree| collection.len(tree), ctx)?.into())
+}
+
+fn collection_item(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let index = args.get_or_undefined(0).to_u32(ctx)? as usize;
+    match with_collection(this, |collection, tree| collection.item(tree, index), ctx)? {
+        Some(node) => member_object(Some(node), ctx),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn collection_named_item(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    let node = with_collection(
+        this,
+        |collection, tree| collection.named_item(tree, &name),
+        ctx,
+    )?;
+    element_object(node, ctx)
+}
+
+/// A member for an own property key: an index, or a name not shadowed by
+/// the prototype chain.
+fn property_member(
+    target: &JsObject,
+    key: &PropertyKey,
+    ctx: &mut Context,
+) -> JsResult<Option<NodeId>> {
+    let this = JsValue::from(target.clone());
+    match key {
+        PropertyKey::Index(index) => {
+            let index = index.get() as usize;
+            with_collection(&this, |collection, tree| collection.item(tree, index), ctx)
+        }
+        PropertyKey::String(name)
+            if is_html_collection(&this, ctx)? && !target.has_property(key.clone(), ctx)? =>
+        {
+            let name = name.to_std_string_escaped();
+            with_collection(
+                &this,
+                |collection, tree| collection.named_item(tree, &name),
+                ctx,
+            )
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Target and key of a proxy trap.
+fn trap_arguments(args: &[JsValue], ctx: &mut Context) -> JsResult<(JsObject, PropertyKey)> {
+    let target = args
+        .get_or_undefined(0)
+        .as_object()
+        .cloned()
+        .ok_or_else(|| JsNativeError::typ().with_message("Proxy target is not an object"))?;
+    let key = args.get_or_undefined(1).to_property_key(ctx)?;
+    Ok((target, key))
+}
+
+fn trap_get(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (target, key) = trap_arguments(args, ctx)?;
+    if let Some(node) = property_member(&target, &key, ctx)? {
+        return member_object(Some(node), ctx);
+    }
+    if matches!(key, PropertyKey::Index(_)) {
+        return Ok(JsValue::undefined());
+    }
+    target.get(key, ctx)
+}
+
+/// Indexed and named properties are read-only.
+fn trap_set(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (target, key) = trap_arguments(args, ctx)?;
+    if matches!(key, PropertyKey::Index(_)) || property_member(&target, &key, ctx)?.is_some() {
+        return Ok(false.into());
+    }
+    Ok(target
+        .set(key, args.get_or_undefined(2).clone(), false, ctx)?
+        .into())
+}
+
+fn trap_has(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (target, key) = trap_arguments(args, ctx)?;
+    if property_member(&target, &key, ctx)?.is_some() {
+        return Ok(true.into());
+    }
+    Ok(target.has_property(key, ctx)?.into())
+}
+
+fn trap_own_keys(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let target = args
+        .get_or_undefined(0)
+        .as_object()
+        .cloned()
+        .ok_or_else(|| JsNativeError::typ().with_message("Proxy target is not an object"))?;
+    let this = JsValue::from(target.clone());
+    let named = is_html_collection(&this, ctx)?;
+    let (length, names) = with_collection(
+        &this,
+        |collection, tree| {
+            (
+                collection.len(tree),
+                if named {
+                    collection.names(tree)
+                } else {
+                    Vec::new()
+                },
+            )
+        },
+        ctx,
+    )?;
+
+    let mut keys: Vec<JsValue> = (0..length)
+        .map(|index| js_string!(index.to_string()).into())
+        .collect();
+    for name in names {
+        if !target.has_property(js_string!(name.as_str()), ctx)? {
+            keys.push(js_string!(name).into());
+        }
+    }
+    keys.extend(target.own_property_keys(ctx)?.iter().map(JsValue::from));
+    Ok(JsArray::from_iter(keys, ctx).into())
+}
+
+fn trap_get_own_property_descriptor(
+    _: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (target, key) = trap_arguments(args, ctx)?;
+    if let Some(node) = property_member(&target, &key, ctx)? {
+        let value = member_object(Some(node), ctx)?;
+        let enumerable = matches!(key, PropertyKey::Index(_));
+        return Ok(ObjectInitializer::new(ctx)
+            .property(js_string!("value"), value, Attribute::all())
+            .property(js_string!("writable"), false, Attribute::all())
+            .property(js_string!("enumerable"), enumerable, Attribute::all())
+            .property(js_string!("configurable"), true, Attribute::all())
+            .build()
+            .into());
+    }
+    let reflect = ctx.global_object().get(js_string!("Reflect"), ctx)?;
+    let describe = match reflect.as_object() {
+        Some(reflect) => reflect.get(js_string!("getOwnPropertyDescriptor"), ctx)?,
+        None => JsValue::undefined(),
+    };
+    match describe.as_callable() {
+        Some(describe) => describe.call(
+            &JsValue::undefined(),
+            &[target.into(), JsValue::from(&key)],
+            ctx,
+        ),
+        None => Ok(JsValue::undefined()),
+    }
+}
+
+fn array_prototype_method(name: &str, ctx: &mut Context) -> JsValue {
+    let prototype = ctx.intrinsics().constructors().array().prototype();
+    prototype.get(js_string!(name), ctx).unwrap_or_default()
+}
+
+fn illegal_constructor(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Err(JsNativeError::typ()
+        .with_message("Illegal constructor")
+        .into())
+}
+
+fn node_get_children(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let root = query_scope(this, ctx)?;
+    collection(root, CollectionFilter::Children, ctx)
+}
+
+fn node_get_child_nodes(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let root = query_scope(this, ctx)?;
+    collection(root, CollectionFilter::ChildNodes, ctx)
+}
+
+fn document_get_forms(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let root = query_scope(this, ctx)?;
+    collection(root, CollectionFilter::Forms, ctx)
+}
+
+/// `getElementsByClassName()` on an element or the document.
+pub(crate) fn get_elements_by_class_name(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let root = query_scope(this, ctx)?;
+    let names = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    collection(root, CollectionFilter::class_names(&names), ctx)
+}
+
+/// `getElementsByTagName()` on an element or the document.
+pub(crate) fn get_elements_by_tag_name(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let root = query_scope(this, ctx)?;
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    collection(root, CollectionFilter::tag_name(&name), ctx)
+}
+
+/// `form.elements`.
+pub(crate) fn form_elements(form: NodeId, ctx: &mut Context) -> JsResult<JsValue> {
+    collection(form, CollectionFilter::FormElements, ctx)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_live_collections() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<ul id=\"list\"><li class=\"item\" id=\"a\">A</li><li class=\"item\" name=\"b\">B</li>\
+             <li class=\"item\">C</li></ul><form id=\"f\"><input name=\"q\"></form>",
+            url::Url::parse("https://example.com/").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var list = document.querySelector('ul'); var log = []; \
+                 var items = document.getElementsByClassName('item'); \
+                 log.push(items instanceof HTMLCollection, items === document.getElementsByClassName('item')); \
+                 log.push(items.length, items[1].getAttribute('name'), \
+                          items.namedItem('a').getAttribute('id'), items.b.getAttribute('name'), 'b' in items, items[5]); \
+                 for (var i = 0; i < items.length; i++) { items[i].setAttribute('class', 'done'); } \
+                 log.push(items.length); \
+                 while (items.length) { items[0].removeAttribute('class'); } log.push(items.length); \
+                 var children = list.children; \
+                 log.push(children.length, Object.keys(children).join(','), Array.from(children).length); \
+                 list.insertAdjacentHTML('beforeend', '<li>D</li>x'); \
+                 log.push(children.length, list.childNodes.length, list.childNodes instanceof NodeList); \
+                 var seen = 0; list.childNodes.forEach(function () { seen++; }); \
+                 log.push(seen, document.forms.length, document.forms.f.elements.q.getAttribute('name')); \
+                 log.push(document.getElementsByTagName('LI').length, list.getElementsByTagName('*').length); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "true|true|3|b|a|b|true||1|0|3|0,1,2|3|4|5|true|5|1|q|4|4"
+        );
+    }
+
+    #[test]
+    fn test_collections_follow_structural_mutations() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div><p>one</p><p>two</p></div>",
+            url::Url::parse("https://example.com/").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var div = document.querySelector('div'); var log = []; \
+                 var paragraphs = document.getElementsByTagName('p'); \
+                 var children = div.children, nodes = div.childNodes; \
+                 log.push(paragraphs.length, children.length, nodes.length); \
+                 div.appendChild(document.createElement('p')); \
+                 log.push(paragraphs.length, children.length, nodes.length); \
+                 div.insertBefore(document.createElement('span'), nodes[0]); \
+                 log.push(paragraphs.length, children[0].nodeName, nodes[1].nodeName); \
+                 div.removeChild(children[1]); paragraphs[0].remove(); \
+                 log.push(paragraphs.length, children.length, nodes.length); \
+                 div.innerHTML = '<form><p>x</p></form>'; \
+                 log.push(paragraphs.length, children.length, document.forms.length); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "2|2|2|3|3|3|3|SPAN|P|1|2|2|1|1|1"
+        );
+    }
+}
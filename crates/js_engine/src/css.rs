@@ -0,0 +1,69 @@
+//! The `CSS` namespace.
+
+use boa_engine::{
+    js_string, object::ObjectInitializer, property::Attribute, Context, JsArgs, JsResult, JsValue,
+    NativeFunction,
+};
+
+/// Register the `CSS` namespace on the global object.
+pub(crate) fn register(context: &mut Context) {
+    let css = ObjectInitializer::new(context)
+        .function(
+            NativeFunction::from_fn_ptr(css_supports),
+            js_string!("supports"),
+            1,
+        )
+        .build();
+
+    context
+        .register_global_property(
+            js_string!("CSS"),
+            css,
+            Attribute::WRITABLE | Attribute::CONFIGURABLE,
+        )
+        .expect("Failed to register CSS");
+}
+
+/// `CSS.supports(property, value)` or `CSS.supports(conditionText)`.
+fn css_supports(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let first = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    let supported = if args.len() >= 2 {
+        let value = args
+            .get_or_undefined(1)
+            .to_string(ctx)?
+            .to_std_string_escaped();
+        css_parser::supports::supports_declaration(&first, &value)
+    } else {
+        css_parser::supports::supports(&first)
+    };
+    Ok(supported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+
+    #[test]
+    fn test_css_supports() {
+        let mut context = Context::default();
+        DomBindings::new().register(&mut context);
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+        assert_eq!(
+            eval(
+                &mut context,
+                "[CSS.supports('display', 'grid'), CSS.supports('display', 'grid !important'), \
+                 CSS.supports('display', 'subgrid'), CSS.supports('(color: red) and selector(p:has(a))'), \
+                 CSS.supports('color: red'), CSS.supports('not (gap: 1px)')].join()"
+            ),
+            "true,false,false,true,true,false"
+        );
+    }
+}
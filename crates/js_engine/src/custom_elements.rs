@@ -0,0 +1,829 @@
+//! Custom elements for scripts.
+//!
+//! `dom::custom_elements` tracks definitions and turns tree mutations into
+//! reactions; this module owns the script side of each definition (the
+//! constructor and lifecycle callbacks), the element objects of upgraded
+//! elements, and runs the reactions.
+
+use crate::bindings::{create_element_object, element_object, this_node_id, with_document};
+use boa_engine::{
+    builtins::promise::ResolvingFunctions,
+    js_string,
+    object::{
+        builtins::{JsFunction, JsPromise},
+        JsObject, ObjectInitializer,
+    },
+    property::Attribute,
+    Context, JsArgs, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use dom::custom_elements::is_valid_custom_element_name;
+use dom::element::{CustomElementState, ElementData, TagName};
+use dom::mutation::MutationHookId;
+use dom::node::NodeId;
+use dom::tree::DomTree;
+use dom::{
+    CustomElementDefinition, CustomElementError, CustomElementReaction, CustomElementReactions,
+    CustomElementRegistry,
+};
+use parking_lot::RwLock;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+thread_local! {
+    /// Custom elements of the script context on this thread.
+    static CUSTOM_ELEMENTS: RefCell<ScriptCustomElements> = RefCell::new(ScriptCustomElements::default());
+}
+
+/// Definitions, element objects and pending reactions.
+struct ScriptCustomElements {
+    registry: Arc<RwLock<CustomElementRegistry>>,
+    /// Installed as a hook on the bound document's tree.
+    reactions: Arc<CustomElementReactions>,
+    hook: Option<MutationHookId>,
+    definitions: HashMap<String, ScriptDefinition>,
+    /// Custom element objects by node, with their definition name. They
+    /// keep their identity and prototype for as long as the node lives.
+    instances: HashMap<u64, (String, JsObject)>,
+    /// Elements being upgraded, by definition name; `None` once the
+    /// constructor has called `super()`.
+    construction_stack: Vec<(String, Option<JsObject>)>,
+    /// Promises returned by `whenDefined()` for names not defined yet.
+    when_defined: HashMap<String, (JsPromise, ResolvingFunctions)>,
+}
+
+impl Default for ScriptCustomElements {
+    fn default() -> Self {
+        let registry = Arc::new(RwLock::new(CustomElementRegistry::new()));
+        Self {
+            reactions: Arc::new(CustomElementReactions::new(registry.clone())),
+            registry,
+            hook: None,
+            definitions: HashMap::new(),
+            instances: HashMap::new(),
+            construction_stack: Vec::new(),
+            when_defined: HashMap::new(),
+        }
+    }
+}
+
+/// Constructor and lifecycle callbacks of a definition.
+#[derive(Clone)]
+struct ScriptDefinition {
+    constructor: JsObject,
+    connected: Option<JsFunction>,
+    disconnected: Option<JsFunction>,
+    adopted: Option<JsFunction>,
+    attribute_changed: Option<JsFunction>,
+}
+
+/// Register the global `customElements` registry, dropping any earlier
+/// definitions on this thread.
+pub(crate) fn register(context: &mut Context) {
+    CUSTOM_ELEMENTS.with(|state| {
+        let mut state = state.borrow_mut();
+        *state.registry.write() = CustomElementRegistry::new();
+        state.reactions.take();
+        state.reactions.take_dropped();
+        state.definitions.clear();
+        state.instances.clear();
+        state.construction_stack.clear();
+        state.when_defined.clear();
+    });
+
+    let custom_elements = ObjectInitializer::new(context)
+        .function(
+            NativeFunction::from_fn_ptr(custom_elements_define),
+            js_string!("define"),
+            2,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(custom_elements_get),
+            js_string!("get"),
+            1,
+        )
+        .function(
+            NativeFunction::from_fn_ptr(custom_elements_when_defined),
+            js_string!("whenDefined"),
+            1,
+        )
+        .build();
+
+    context
+        .register_global_property(
+            js_string!("customElements"),
+            custom_elements,
+            Attribute::all(),
+        )
+        .expect("Failed to register customElements");
+}
+
+/// Start queueing reactions for mutations of `tree`.
+pub(crate) fn observe_tree(tree: &mut DomTree) {
+    let reactions = CUSTOM_ELEMENTS.with(|state| state.borrow().reactions.clone());
+    let hook = tree.add_mutation_hook(reactions);
+    CUSTOM_ELEMENTS.with(|state| state.borrow_mut().hook = Some(hook));
+}
+
+/// Stop observing `tree` and forget its element objects.
+pub(crate) fn unobserve_tree(tree: &mut DomTree) {
+    let hook = CUSTOM_ELEMENTS.with(|state| {
+        let mut state = state.borrow_mut();
+        state.instances.clear();
+        state.reactions.take();
+        state.reactions.take_dropped();
+        state.hook.take()
+    });
+    if let Some(hook) = hook {
+        tree.remove_mutation_hook(hook);
+    }
+}
+
+/// Object of the custom element `node`, once it has been constructed.
+pub(crate) fn instance(node: u64) -> Option<JsObject> {
+    CUSTOM_ELEMENTS.with(|state| {
+        state
+            .borrow()
+            .instances
+            .get(&node)
+            .map(|(_, object)| object.clone())
+    })
+}
+
+fn definition_error(error: CustomElementError) -> JsError {
+    match error {
+        CustomElementError::InvalidName(_) => JsNativeError::syntax(),
+        CustomElementError::AlreadyDefined(_) | CustomElementError::InvalidExtends(_) => {
+            JsNativeError::error()
+        }
+    }
+    .with_message(error.to_string())
+    .into()
+}
+
+fn illegal_constructor() -> JsError {
+    JsNativeError::typ()
+        .with_message("Illegal constructor")
+        .into()
+}
+
+/// Strings of an array-like `value`, as for `observedAttributes`.
+fn string_sequence(value: &JsValue, ctx: &mut Context) -> JsResult<Vec<String>> {
+    let object = value
+        .as_object()
+        .ok_or_else(|| JsNativeError::typ().with_message("observedAttributes is not a sequence"))?;
+    let length = object.get(js_string!("length"), ctx)?.to_length(ctx)?;
+    (0..length)
+        .map(|index| {
+            Ok(object
+                .get(index, ctx)?
+                .to_string(ctx)?
+                .to_std_string_escaped())
+        })
+        .collect()
+}
+
+/// `customElements.define(name, constructor, options)`.
+fn custom_elements_define(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    let constructor = args
+        .get_or_undefined(1)
+        .as_constructor()
+        .cloned()
+        .ok_or_else(|| {
+            JsNativeError::typ().with_message("Custom element constructor is not a constructor")
+        })?;
+
+    if !is_valid_custom_element_name(&name) {
+        return Err(definition_error(CustomElementError::InvalidName(name)));
+    }
+    let in_use = CUSTOM_ELEMENTS.with(|state| {
+        let state = state.borrow();
+        let defined = state.registry.read().get(&name).is_some();
+        let constructor_used = state
+            .definitions
+            .values()
+            .any(|definition| JsObject::equals(&definition.constructor, &constructor));
+        (defined, constructor_used)
+    });
+    match in_use {
+        (true, _) => return Err(definition_error(CustomElementError::AlreadyDefined(name))),
+        (_, true) => {
+            return Err(JsNativeError::error()
+                .with_message("Custom element constructor has already been defined")
+                .into())
+        }
+        _ => {}
+    }
+
+    let extends = match args.get_or_undefined(2).as_object() {
+        Some(options) => {
+            let extends = options.get(js_string!("extends"), ctx)?;
+            if extends.is_undefined() {
+                None
+            } else {
+                Some(extends.to_string(ctx)?.to_std_string_escaped())
+            }
+        }
+        None => None,
+    };
+
+    let prototype = constructor.get(js_string!("prototype"), ctx)?;
+    let prototype = prototype.as_object().ok_or_else(|| {
+        JsNativeError::typ().with_message("Custom element prototype is not an object")
+    })?;
+    let mut callback = |name: &str| -> JsResult<Option<JsFunction>> {
+        let value = prototype.get(js_string!(name), ctx)?;
+        if value.is_undefined() {
+            return Ok(None);
+        }
+        value
+            .as_object()
+            .cloned()
+            .and_then(JsFunction::from_object)
+            .map(Some)
+            .ok_or_else(|| {
+                JsNativeError::typ()
+                    .with_message(format!("{} is not a function", name))
+                    .into()
+            })
+    };
+    let script_definition = ScriptDefinition {
+        constructor: constructor.clone(),
+        connected: callback("connectedCallback")?,
+        disconnected: callback("disconnectedCallback")?,
+        adopted: callback("adoptedCallback")?,
+        attribute_changed: callback("attributeChangedCallback")?,
+    };
+
+    let mut definition = CustomElementDefinition::new(&name);
+    if script_definition.attribute_changed.is_some() {
+        let observed = constructor.get(js_string!("observedAttributes"), ctx)?;
+        if !observed.is_undefined() {
+            definition = definition.observed_attributes(string_sequence(&observed, ctx)?);
+        }
+    }
+    if let Some(extends) = extends {
+        definition = definition.extends(&extends);
+    }
+
+    let registry = CUSTOM_ELEMENTS.with(|state| state.borrow().registry.clone());
+    registry
+        .write()
+        .define(definition.clone())
+        .map_err(definition_error)?;
+    CUSTOM_ELEMENTS.with(|state| {
+        state
+            .borrow_mut()
+            .definitions
+            .insert(name.clone(), script_definition)
+    });
+
+    // Upgrade the elements that were waiting for this definition.
+    let candidates =
+        with_document(|doc| registry.read().upgrade_candidates(&doc.tree, &definition))
+            .unwrap_or_default();
+    for node in candidates {
+        if let Err(error) = upgrade(node, ctx) {
+            tracing::warn!("Custom element upgrade failed: {}", error);
+        }
+    }
+
+    let pending = CUSTOM_ELEMENTS.with(|state| state.borrow_mut().when_defined.remove(&name));
+    if let Some((_, resolvers)) = pending {
+        resolvers
+            .resolve
+            .call(&JsValue::undefined(), &[constructor.into()], ctx)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+/// `customElements.get(name)`.
+fn custom_elements_get(_: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    let constructor = CUSTOM_ELEMENTS.with(|state| {
+        state
+            .borrow()
+            .definitions
+            .get(&name)
+            .map(|definition| definition.constructor.clone())
+    });
+    Ok(constructor.map_or(JsValue::undefined(), JsValue::from))
+}
+
+/// `customElements.whenDefined(name)`.
+fn custom_elements_when_defined(
+    _: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    if !is_valid_custom_element_name(&name) {
+        let error = definition_error(CustomElementError::InvalidName(name));
+        return Ok(JsPromise::reject(error, ctx).into());
+    }
+
+    let (constructor, pending) = CUSTOM_ELEMENTS.with(|state| {
+        let state = state.borrow();
+        (
+            state
+                .definitions
+                .get(&name)
+                .map(|definition| definition.constructor.clone()),
+            state
+                .when_defined
+                .get(&name)
+                .map(|(promise, _)| promise.clone()),
+        )
+    });
+    if let Some(constructor) = constructor {
+        return Ok(JsPromise::resolve(constructor, ctx).into());
+    }
+    if let Some(promise) = pending {
+        return Ok(promise.into());
+    }
+    let (promise, resolvers) = JsPromise::new_pending(ctx);
+    CUSTOM_ELEMENTS.with(|state| {
+        state
+            .borrow_mut()
+            .when_defined
+            .insert(name, (promise.clone(), resolvers));
+    });
+    Ok(promise.into())
+}
+
+/// The `HTMLElement` constructor, called as `super()` from custom element
+/// classes with `new_target` being the class.
+pub(crate) fn html_element_constructor(
+    new_target: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let new_target = new_target
+        .as_object()
+        .cloned()
+        .ok_or_else(illegal_constructor)?;
+    let name = CUSTOM_ELEMENTS
+        .with(|state| {
+            state
+                .borrow()
+                .definitions
+                .iter()
+                .find(|(_, definition)| JsObject::equals(&definition.constructor, &new_target))
+                .map(|(name, _)| name.clone())
+        })
+        .ok_or_else(illegal_constructor)?;
+    let prototype = new_target
+        .get(js_string!("prototype"), ctx)?
+        .as_object()
+        .cloned();
+
+    // An upgrade constructs the element that already exists.
+    let upgrading = CUSTOM_ELEMENTS.with(|state| {
+        let mut state = state.borrow_mut();
+        state
+            .construction_stack
+            .iter_mut()
+            .rev()
+            .find(|(entry, _)| *entry == name)
+            .map(|(_, element)| element.take())
+    });
+    match upgrading {
+        Some(Some(element)) => {
+            element.set_prototype(prototype);
+            return Ok(element.into());
+        }
+        Some(None) => {
+            return Err(JsNativeError::error()
+                .with_message("Custom element constructor called super() more than once")
+                .into())
+        }
+        None => {}
+    }
+
+    let local_name = CUSTOM_ELEMENTS
+        .with(|state| {
+            state
+                .borrow()
+                .registry
+                .read()
+                .get(&name)
+                .map(|definition| definition.local_name.clone())
+        })
+        .ok_or_else(illegal_constructor)?;
+    let node = with_document(|doc| {
+        let mut data = ElementData::new(TagName::new(&local_name));
+        if local_name != name {
+            data.set_attribute("is", &name);
+        }
+        data.custom_state = CustomElementState::Custom;
+        doc.tree.create_element(data)
+    })?;
+    let element = create_element_object(node.to_raw(), &local_name, ctx)
+        .as_object()
+        .cloned()
+        .ok_or_else(illegal_constructor)?;
+    element.set_prototype(prototype);
+    CUSTOM_ELEMENTS.with(|state| {
+        state
+            .borrow_mut()
+            .instances
+            .insert(node.to_raw(), (name, element.clone()));
+    });
+    Ok(element.into())
+}
+
+/// Create an element for `document.createElement(local_name, { is })`,
+/// running the constructor of its definition.
+pub(crate) fn create_element(
+    local_name: &str,
+    is: Option<String>,
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let name = is.clone().unwrap_or_else(|| local_name.to_string());
+    let definition = CUSTOM_ELEMENTS.with(|state| {
+        let state = state.borrow();
+        let definition = state.registry.read().get(&name).cloned()?;
+        let script = state.definitions.get(&name)?.clone();
+        (definition.local_name == local_name).then_some((definition, script))
+    });
+
+    let mut state = CustomElementState::Uncustomized;
+    match &definition {
+        // Autonomous custom elements are constructed right away.
+        Some((definition, script)) if definition.local_name == definition.name => {
+            let constructor = script.constructor.clone();
+            let result = constructor
+                .construct(&[], Some(&constructor), ctx)
+                .and_then(|element| {
+                    let value = JsValue::from(element);
+                    let node = this_node_id(&value, ctx)?;
+                    let valid = with_document(|doc| {
+                        doc.tree.get_element(node).is_some_and(|element| {
+                            element.tag_name == local_name
+                                && element.custom_state == CustomElementState::Custom
+                        })
+                    })?;
+                    if valid {
+                        Ok(value)
+                    } else {
+                        Err(JsNativeError::typ()
+                            .with_message("Custom element constructor did not return a new element")
+                            .into())
+                    }
+                });
+            match result {
+                Ok(element) => return Ok(element),
+                Err(error) => {
+                    tracing::warn!("Custom element constructor failed: {}", error);
+                    state = CustomElementState::Failed;
+                }
+            }
+        }
+        Some(_) => state = CustomElementState::Undefined,
+        None if is.is_some() || is_valid_custom_element_name(local_name) => {
+            state = CustomElementState::Undefined;
+        }
+        None => {}
+    }
+
+    let node = with_document(|doc| {
+        let mut data = ElementData::new(TagName::new(local_name));
+        if let Some(is) = &is {
+            data.set_attribute("is", is);
+        }
+        data.custom_state = state;
+        doc.tree.create_element(data)
+    })?;
+    // Customized built-in elements are upgraded right away.
+    if definition.is_some() && state == CustomElementState::Undefined {
+        if let Err(error) = upgrade(node, ctx) {
+            tracing::warn!("Custom element upgrade failed: {}", error);
+        }
+    }
+    element_object(Some(node), ctx)
+}
+
+fn set_state(node: NodeId, state: CustomElementState) -> JsResult<()> {
+    with_document(|doc| {
+        if let Some(element) = doc.tree.get_element_mut(node) {
+            element.custom_state = state;
+        }
+    })
+}
+
+/// Upgrade `node` if it is an undefined element with a definition: run
+/// the constructor on its existing object, then the callbacks for its
+/// observed attributes and for being connected.
+fn upgrade(node: NodeId, ctx: &mut Context) -> JsResult<()> {
+    let registry = CUSTOM_ELEMENTS.with(|state| state.borrow().registry.clone());
+    let found = with_document(|doc| {
+        let element = doc.tree.get_element(node)?;
+        if element.custom_state != CustomElementState::Undefined {
+            return None;
+        }
+        let registry = registry.read();
+        let definition = registry.lookup(element)?;
+        let attributes: Vec<(String, String)> = definition
+            .observed_attributes
+            .iter()
+            .filter_map(|name| {
+                element
+                    .get_attribute(name)
+                    .map(|value| (name.clone(), value.to_string()))
+            })
+            .collect();
+        let connected = doc
+            .tree
+            .root()
+            .is_some_and(|root| doc.tree.is_shadow_including_inclusive_ancestor(root, node));
+        Some((
+            definition.name.clone(),
+            element.tag_name.to_string(),
+            attributes,
+            connected,
+        ))
+    })?;
+    let Some((name, tag_name, attributes, connected)) = found else {
+        return Ok(());
+    };
+    let Some(definition) =
+        CUSTOM_ELEMENTS.with(|state| state.borrow().definitions.get(&name).cloned())
+    else {
+        return Ok(());
+    };
+
+    let element = create_element_object(node.to_raw(), &tag_name, ctx)
+        .as_object()
+        .cloned()
+        .ok_or_else(illegal_constructor)?;
+    set_state(node, CustomElementState::Precustomized)?;
+    CUSTOM_ELEMENTS.with(|state| {
+        let mut state = state.borrow_mut();
+        state
+            .instances
+            .insert(node.to_raw(), (name.clone(), element.clone()));
+        state.construction_stack.push((name, Some(element.clone())));
+    });
+    let result = definition
+        .constructor
+        .construct(&[], Some(&definition.constructor), ctx);
+    CUSTOM_ELEMENTS.with(|state| state.borrow_mut().construction_stack.pop());
+
+    let result = result.and_then(|constructed| {
+        if JsObject::equals(&constructed, &element) {
+            Ok(())
+        } else {
+            Err(JsNativeError::typ()
+                .with_message("Custom element constructor did not return the upgraded element")
+                .into())
+        }
+    });
+    if let Err(error) = result {
+        set_state(node, CustomElementState::Failed)?;
+        CUSTOM_ELEMENTS.with(|state| state.borrow_mut().instances.remove(&node.to_raw()));
+        return Err(error);
+    }
+    set_state(node, CustomElementState::Custom)?;
+
+    for (name, value) in attributes {
+        let args = [
+            js_string!(name).into(),
+            JsValue::null(),
+            js_string!(value).into(),
+            JsValue::null(),
+        ];
+        invoke(definition.attribute_changed.as_ref(), &element, &args, ctx);
+    }
+    if connected {
+        invoke(definition.connected.as_ref(), &element, &[], ctx);
+    }
+    Ok(())
+}
+
+fn invoke(callback: Option<&JsFunction>, element: &JsObject, args: &[JsValue], ctx: &mut Context) {
+    if let Some(callback) = callback {
+        if let Err(error) = callback.call(&element.clone().into(), args, ctx) {
+            tracing::warn!("Custom element callback failed: {}", error);
+        }
+    }
+}
+
+fn run_reaction(reaction: CustomElementReaction, ctx: &mut Context) {
+    let node = match &reaction {
+        CustomElementReaction::Upgrade(node) => {
+            if let Err(error) = upgrade(*node, ctx) {
+                tracing::warn!("Custom element upgrade failed: {}", error);
+            }
+            return;
+        }
+        CustomElementReaction::Connected(node)
+        | CustomElementReaction::Disconnected(node)
+        | CustomElementReaction::Adopted(node) => *node,
+        CustomElementReaction::AttributeChanged { element, .. } => *element,
+    };
+    let Some((definition, element)) = CUSTOM_ELEMENTS.with(|state| {
+        let state = state.borrow();
+        let (name, element) = state.instances.get(&node.to_raw())?;
+        Some((state.definitions.get(name)?.clone(), element.clone()))
+    }) else {
+        return;
+    };
+
+    let string_or_null =
+        |value: Option<String>| value.map_or(JsValue::null(), |value| js_string!(value).into());
+    match reaction {
+        CustomElementReaction::Connected(_) => {
+            invoke(definition.connected.as_ref(), &element, &[], ctx)
+        }
+        CustomElementReaction::Disconnected(_) => {
+            invoke(definition.disconnected.as_ref(), &element, &[], ctx)
+        }
+        CustomElementReaction::Adopted(_) => {
+            // Template contents and the document share one tree here.
+            let document: JsValue = ctx
+                .global_object()
+                .get(js_string!("document"), ctx)
+                .unwrap_or_default();
+            invoke(
+                definition.adopted.as_ref(),
+                &element,
+                &[document.clone(), document],
+                ctx,
+            )
+        }
+        CustomElementReaction::AttributeChanged {
+            name,
+            old_value,
+            new_value,
+            ..
+        } => {
+            let args = [
+                js_string!(name).into(),
+                string_or_null(old_value),
+                string_or_null(new_value),
+                JsValue::null(),
+            ];
+            invoke(definition.attribute_changed.as_ref(), &element, &args, ctx)
+        }
+        CustomElementReaction::Upgrade(_) => {}
+    }
+}
+
+/// Run the queued custom element reactions: upgrades and lifecycle
+/// callbacks, including those queued by the callbacks themselves.
+///
+/// Called after DOM operations from script and at microtask checkpoints;
+/// returns whether any reaction ran.
+pub fn run_custom_element_reactions(context: &mut Context) -> bool {
+    let reactions = CUSTOM_ELEMENTS.with(|state| state.borrow().reactions.clone());
+    let mut ran = false;
+    // Edits made in place are only reported on flush.
+    while with_document(|doc| doc.tree.flush_mutations()).is_ok() {
+        let queued = reactions.take();
+        if queued.is_empty() {
+            break;
+        }
+        for reaction in queued {
+            run_reaction(reaction, context);
+        }
+        ran = true;
+    }
+
+    // Objects of dropped nodes are no longer reachable from the tree.
+    let dropped = reactions.take_dropped();
+    if !dropped.is_empty() {
+        CUSTOM_ELEMENTS.with(|state| {
+            let mut state = state.borrow_mut();
+            for node in dropped {
+                state.instances.remove(&node.to_raw());
+            }
+        });
+    }
+    ran
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_custom_elements() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<x-early open=1 title=t></x-early><div id=out></div>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        eval(
+            &mut context,
+            "var log = []; var resolved = null; \
+             customElements.whenDefined('x-early').then(c => { resolved = c.name; }); \
+             class XEarly extends HTMLElement { \
+                 static get observedAttributes() { return ['open']; } \
+                 constructor() { super(); this.created = true; log.push('constructed'); } \
+                 connectedCallback() { log.push('connected'); } \
+                 disconnectedCallback() { log.push('disconnected'); } \
+                 attributeChangedCallback(name, oldValue, newValue) { log.push([name, oldValue, newValue].join(':')); } \
+             }",
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "document.querySelectorAll('x-early:defined').length"
+            ),
+            "0"
+        );
+
+        // Elements parsed before the definition are upgraded in place.
+        eval(&mut context, "customElements.define('x-early', XEarly)");
+        assert_eq!(
+            eval(&mut context, "log.join()"),
+            "constructed,open::1,connected"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "var early = document.querySelector('x-early'); \
+                 [early instanceof XEarly, early.created, early === document.querySelector('x-early:defined'), \
+                  customElements.get('x-early') === XEarly].join()",
+            ),
+            "true,true,true,true"
+        );
+        context.run_jobs();
+        assert_eq!(eval(&mut context, "resolved"), "XEarly");
+
+        // Only observed attributes are reported, once for every set.
+        let instances = || super::CUSTOM_ELEMENTS.with(|state| state.borrow().instances.len());
+        assert_eq!(instances(), 1);
+        eval(
+            &mut context,
+            "log = []; early.setAttribute('title', 'x'); early.setAttribute('open', '2'); \
+             early.setAttribute('open', '2'); early.setAttribute('open', '3'); early.remove()",
+        );
+        assert_eq!(
+            eval(&mut context, "log.join()"),
+            "open:1:2,open:2:2,open:2:3,disconnected"
+        );
+
+        // Elements created from script run their constructor right away.
+        eval(
+            &mut context,
+            "log = []; var created = document.createElement('x-early'); \
+             document.querySelector('#out').appendChild(created); \
+             document.querySelector('#out').innerHTML = '<x-early open></x-early>';",
+        );
+        assert_eq!(
+            eval(&mut context, "log.join()"),
+            "constructed,connected,disconnected,constructed,open::,connected"
+        );
+        // The element replaced by innerHTML was dropped, releasing its object.
+        assert_eq!(instances(), 2);
+        assert_eq!(
+            eval(
+                &mut context,
+                "document.querySelector('#out x-early') instanceof XEarly"
+            ),
+            "true"
+        );
+
+        assert_eq!(
+            eval(&mut context, "try { customElements.define('x-early', class extends HTMLElement {}); 'no error' } catch (e) { e.message }"),
+            "'x-early' has already been defined as a custom element"
+        );
+        assert_eq!(
+            eval(&mut context, "try { customElements.define('nodash', class extends HTMLElement {}); 'no error' } catch (e) { e.name }"),
+            "SyntaxError"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "try { new HTMLElement(); 'no error' } catch (e) { e.name }"
+            ),
+            "TypeError"
+        );
+
+        bindings.unbind_document();
+    }
+}
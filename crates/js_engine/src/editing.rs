@@ -0,0 +1,472 @@
+//! Editing for scripts.
+//!
+//! `document.execCommand()` and `queryCommand*()`, `isContentEditable` and
+//! `contentEditable`, and the selection of text controls, backed by
+//! `dom::editing`. A command that changes something fires `input` at its
+//! target through the shared dispatch. Text control offsets are UTF-16 code
+//! units on the script side and bytes in the DOM.
+
+use crate::bindings::{this_node_id, with_document};
+use boa_engine::{
+    js_string,
+    object::{FunctionObjectBuilder, JsObject},
+    property::PropertyDescriptor,
+    Context, JsArgs, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use dom::editing::{self, EditTarget, InputDetail, InputType};
+use dom::node::NodeId;
+use dom::{focus, forms};
+use dom::{Event, EventType};
+
+/// Native getter, setter or method.
+type Accessor = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Install `execCommand()` and the `queryCommand*()` methods on `document`.
+pub(crate) fn install_document(document: &JsObject, context: &mut Context) {
+    let methods: [(&str, Accessor, usize); 5] = [
+        ("execCommand", document_exec_command, 1),
+        ("queryCommandEnabled", document_query_command_enabled, 1),
+        ("queryCommandState", document_query_command_state, 1),
+        ("queryCommandSupported", document_query_command_supported, 1),
+        ("queryCommandValue", document_query_command_value, 1),
+    ];
+    define_methods(document, &methods, context);
+}
+
+/// Install the editing accessors and methods on `HTMLElement.prototype`.
+pub(crate) fn install(prototype: &JsObject, context: &mut Context) {
+    let realm = context.realm().clone();
+    let accessors: [(&str, Accessor, Option<Accessor>); 4] = [
+        ("isContentEditable", element_get_is_content_editable, None),
+        (
+            "contentEditable",
+            element_get_content_editable,
+            Some(element_set_content_editable),
+        ),
+        (
+            "selectionStart",
+            control_get_selection_start,
+            Some(control_set_selection_start),
+        ),
+        (
+            "selectionEnd",
+            control_get_selection_end,
+            Some(control_set_selection_end),
+        ),
+    ];
+    for (name, getter, setter) in accessors {
+        let mut descriptor = PropertyDescriptor::builder()
+            .get(NativeFunction::from_fn_ptr(getter).to_js_function(&realm))
+            .enumerable(false)
+            .configurable(true);
+        if let Some(setter) = setter {
+            descriptor = descriptor.set(NativeFunction::from_fn_ptr(setter).to_js_function(&realm));
+        }
+        prototype
+            .define_property_or_throw(js_string!(name), descriptor, context)
+            .expect("Failed to define editing accessor");
+    }
+
+    let methods: [(&str, Accessor, usize); 2] = [
+        ("setSelectionRange", control_set_selection_range, 2),
+        ("select", control_select, 0),
+    ];
+    define_methods(prototype, &methods, context);
+}
+
+fn define_methods(object: &JsObject, methods: &[(&str, Accessor, usize)], context: &mut Context) {
+    let realm = context.realm().clone();
+    for &(name, method, length) in methods {
+        let function = FunctionObjectBuilder::new(&realm, NativeFunction::from_fn_ptr(method))
+            .name(js_string!(name))
+            .length(length)
+            .build();
+        object
+            .define_property_or_throw(
+                js_string!(name),
+                PropertyDescriptor::builder()
+                    .value(function)
+                    .writable(true)
+                    .enumerable(false)
+                    .configurable(true),
+                context,
+            )
+            .expect("Failed to define editing method");
+    }
+}
+
+/// The edit of an `execCommand()` command name.
+fn command(name: &str) -> Option<InputType> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "bold" => InputType::FormatBold,
+        "italic" => InputType::FormatItalic,
+        "underline" => InputType::FormatUnderline,
+        "strikethrough" => InputType::FormatStrikeThrough,
+        "superscript" => InputType::FormatSuperscript,
+        "subscript" => InputType::FormatSubscript,
+        "removeformat" => InputType::FormatRemove,
+        "createlink" => InputType::InsertLink,
+        "inserttext" => InputType::InsertText,
+        "insertlinebreak" => InputType::InsertLineBreak,
+        "insertparagraph" => InputType::InsertParagraph,
+        "delete" => InputType::DeleteContentBackward,
+        "forwarddelete" => InputType::DeleteContentForward,
+        "undo" => InputType::HistoryUndo,
+        "redo" => InputType::HistoryRedo,
+        _ => return None,
+    })
+}
+
+fn command_argument(args: &[JsValue], ctx: &mut Context) -> JsResult<Option<InputType>> {
+    let name = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    Ok(command(&name))
+}
+
+fn document_exec_command(
+    _this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let Some(input_type) = command_argument(args, ctx)? else {
+        return Ok(JsValue::from(false));
+    };
+    let mut input = InputDetail::new(input_type);
+    if matches!(input_type, InputType::InsertText | InputType::InsertLink) {
+        let value = args.get_or_undefined(2);
+        let value = if value.is_undefined() {
+            String::new()
+        } else {
+            value.to_string(ctx)?.to_std_string_escaped()
+        };
+        input.data = Some(value);
+    }
+    let target = with_document(|doc| {
+        let target = doc.edit_target()?;
+        doc.apply_input(&input).then(|| target.node())
+    })?;
+    let Some(target) = target else {
+        return Ok(JsValue::from(false));
+    };
+    let mut event = Event::with_options(EventType::Input, true, false);
+    event.composed = true;
+    event.input = Some(input);
+    crate::events::dispatch_event(target, &mut event, ctx)?;
+    Ok(JsValue::from(true))
+}
+
+fn document_query_command_enabled(
+    _this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let Some(input_type) = command_argument(args, ctx)? else {
+        return Ok(JsValue::from(false));
+    };
+    let enabled = with_document(|doc| match input_type {
+        InputType::HistoryUndo => doc.editor.can_undo(),
+        InputType::HistoryRedo => doc.editor.can_redo(),
+        _ => doc.edit_target().is_some(),
+    })?;
+    Ok(JsValue::from(enabled))
+}
+
+/// Whether the selection has the formatting of `input_type`.
+fn command_state(input_type: InputType) -> JsResult<bool> {
+    with_document(|doc| {
+        let Some(EditTarget::Content(host)) = doc.edit_target() else {
+            return false;
+        };
+        let selection = doc.selection.lock();
+        selection
+            .range()
+            .is_some_and(|range| editing::format_state(&doc.tree, host, range, input_type))
+    })
+}
+
+fn document_query_command_state(
+    _this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let state = match command_argument(args, ctx)? {
+        Some(input_type) => command_state(input_type)?,
+        None => false,
+    };
+    Ok(JsValue::from(state))
+}
+
+fn document_query_command_supported(
+    _this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(command_argument(args, ctx)?.is_some()))
+}
+
+/// `"true"` or `"false"` for formatting commands, as their state.
+fn document_query_command_value(
+    _this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let value = match command_argument(args, ctx)? {
+        Some(
+            input_type @ (InputType::FormatBold
+            | InputType::FormatItalic
+            | InputType::FormatUnderline
+            | InputType::FormatStrikeThrough
+            | InputType::FormatSuperscript
+            | InputType::FormatSubscript),
+        ) => command_state(input_type)?.to_string(),
+        _ => String::new(),
+    };
+    Ok(js_string!(value).into())
+}
+
+fn element_get_is_content_editable(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let editable = with_document(|doc| editing::editing_host(&doc.tree, node).is_some())?;
+    Ok(JsValue::from(editable))
+}
+
+fn element_get_content_editable(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let value = with_document(|doc| {
+        let value = doc
+            .tree
+            .get_element(node)?
+            .get_attribute("contenteditable")?
+            .to_ascii_lowercase();
+        match value.as_str() {
+            "" | "true" => Some("true"),
+            "false" => Some("false"),
+            "plaintext-only" => Some("plaintext-only"),
+            _ => None,
+        }
+    })?;
+    Ok(js_string!(value.unwrap_or("inherit")).into())
+}
+
+fn element_set_content_editable(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let value = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped()
+        .to_ascii_lowercase();
+    if !matches!(
+        value.as_str(),
+        "true" | "false" | "plaintext-only" | "inherit"
+    ) {
+        return Err(JsNativeError::syntax()
+            .with_message(format!("'{value}' is not a valid contentEditable value"))
+            .into());
+    }
+    with_document(|doc| {
+        if value == "inherit" {
+            doc.tree.remove_attribute(node, "contenteditable");
+        } else {
+            doc.tree.set_attribute(node, "contenteditable", &value);
+        }
+    })?;
+    Ok(JsValue::undefined())
+}
+
+/// The text control behind `this`, or `None` for other elements.
+fn this_text_control(this: &JsValue, ctx: &mut Context) -> JsResult<Option<NodeId>> {
+    let node = this_node_id(this, ctx)?;
+    let is_control = with_document(|doc| focus::is_text_control(&doc.tree, node))?;
+    Ok(is_control.then_some(node))
+}
+
+/// The selection of a control in UTF-16 code units.
+fn script_selection(control: NodeId) -> JsResult<(usize, usize)> {
+    with_document(|doc| {
+        let value = forms::value(&doc.tree, control);
+        let (start, end) = forms::selection_range(&doc.tree, control);
+        let units = |offset: usize| value[..offset].encode_utf16().count();
+        (units(start), units(end))
+    })
+}
+
+/// Select UTF-16 offsets `start..end` of a control's value.
+fn set_script_selection(control: NodeId, start: usize, end: usize) -> JsResult<()> {
+    with_document(|doc| {
+        let value = forms::value(&doc.tree, control);
+        let byte = |offset: usize| {
+            let mut units = 0;
+            for (index, c) in value.char_indices() {
+                if units >= offset {
+                    return index;
+                }
+                units += c.len_utf16();
+            }
+            value.len()
+        };
+        forms::set_selection_range(&mut doc.tree, control, byte(start), byte(end));
+    })
+}
+
+fn offset_argument(value: &JsValue, ctx: &mut Context) -> JsResult<usize> {
+    Ok(value.to_u32(ctx)? as usize)
+}
+
+fn control_get_selection_start(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    match this_text_control(this, ctx)? {
+        Some(control) => Ok(JsValue::from(script_selection(control)?.0 as u32)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn control_get_selection_end(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    match this_text_control(this, ctx)? {
+        Some(control) => Ok(JsValue::from(script_selection(control)?.1 as u32)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn control_set_selection_start(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    if let Some(control) = this_text_control(this, ctx)? {
+        let start = offset_argument(args.get_or_undefined(0), ctx)?;
+        let (_, end) = script_selection(control)?;
+        set_script_selection(control, start, end.max(start))?;
+    }
+    Ok(JsValue::undefined())
+}
+
+fn control_set_selection_end(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    if let Some(control) = this_text_control(this, ctx)? {
+        let end = offset_argument(args.get_or_undefined(0), ctx)?;
+        let (start, _) = script_selection(control)?;
+        set_script_selection(control, start, end)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+fn control_set_selection_range(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    if let Some(control) = this_text_control(this, ctx)? {
+        let start = offset_argument(args.get_or_undefined(0), ctx)?;
+        let end = offset_argument(args.get_or_undefined(1), ctx)?;
+        set_script_selection(control, start, end)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+fn control_select(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    if let Some(control) = this_text_control(this, ctx)? {
+        set_script_selection(control, 0, usize::MAX)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_exec_command_and_control_selection() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div contenteditable>Hello world</div><input value=\"h\u{e9}llo\">",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var div = document.querySelector('div'), input = document.querySelector('input'); var log = []; \
+                 div.addEventListener('input', function (e) { log.push(e.inputType); }); \
+                 div.focus(); getSelection().selectAllChildren(div); \
+                 log.push(div.isContentEditable, div.contentEditable, document.execCommand('bold')); \
+                 log.push(div.innerHTML, document.queryCommandState('bold')); \
+                 document.execCommand('undo'); \
+                 log.push(document.querySelector('b') === null, document.queryCommandEnabled('redo')); \
+                 input.setSelectionRange(1, 3); \
+                 log.push(input.selectionStart, input.selectionEnd, div.selectionStart); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "formatBold|true|true|true|<b>Hello world</b>|true|historyUndo|true|true|1|3|"
+        );
+    }
+
+    #[test]
+    fn test_exec_command_on_collapsed_selection() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div contenteditable>Hello</div>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var div = document.querySelector('div'), selection = getSelection(); var log = []; \
+                 div.focus(); selection.selectAllChildren(div); selection.collapseToEnd(); \
+                 log.push(document.execCommand('insertText', false, ' world'), div.innerHTML); \
+                 log.push(selection.isCollapsed, selection.focusOffset); \
+                 log.push(document.execCommand('bold'), document.queryCommandState('bold'), div.innerHTML); \
+                 log.push(document.execCommand('delete'), document.execCommand('forwardDelete'), div.innerHTML); \
+                 document.execCommand('undo'); log.push(div.innerHTML); \
+                 document.execCommand('undo'); log.push(div.innerHTML, document.queryCommandEnabled('undo')); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "true|Hello world|true|11|false|false|Hello world|true|false|Hello worl|Hello world|Hello|false"
+        );
+    }
+}
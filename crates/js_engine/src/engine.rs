@@ -130,8 +130,19 @@ impl JsEngine {
     }
 
     /// Process pending tasks in the event loop.
+    ///
+    /// This is a microtask checkpoint: promise jobs run, then custom
+    /// element reactions, then mutation observers are notified, until none
+    /// has anything left to do.
     pub fn run_pending_jobs(&mut self) {
-        self.context.run_jobs();
+        loop {
+            self.context.run_jobs();
+            let reacted = crate::custom_elements::run_custom_element_reactions(&mut self.context);
+            let notified = crate::bindings::notify_mutation_observers(&mut self.context);
+            if !reacted && !notified {
+                break;
+            }
+        }
     }
 
     /// Check if there are pending jobs.
@@ -0,0 +1,751 @@
+//! Events for scripts.
+//!
+//! Script listeners are registered with the bound document's
+//! `EventManager` under a handle, and their callbacks are kept here.
+//! `dispatchEvent()` and [`dispatch_event`] run the shared `EventPath`
+//! phases, so Rust and script listeners take part in one propagation.
+
+use crate::bindings::{node_object, query_scope, with_document};
+use boa_engine::{
+    class::{Class, ClassBuilder},
+    js_string,
+    object::{
+        builtins::{JsArray, JsFunction},
+        JsObject,
+    },
+    property::Attribute,
+    Context, JsArgs, JsData, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{Finalize, Trace};
+use dom::editing::{InputDetail, InputType};
+use dom::events::{invoke_listeners, EventListenerOptions, ListenerCallback};
+use dom::node::NodeId;
+use dom::{Event, EventPath, EventPhase, EventType, FocusChange};
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+thread_local! {
+    /// Callbacks of script listeners on this thread.
+    static SCRIPT_LISTENERS: RefCell<ScriptListeners> = RefCell::new(ScriptListeners::default());
+}
+
+/// Script listener callbacks by handle.
+#[derive(Default)]
+struct ScriptListeners {
+    /// A function or an object with a `handleEvent` method.
+    callbacks: HashMap<u64, JsObject>,
+    next_handle: u64,
+}
+
+/// Native getter of an accessor property.
+type Getter = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Register the `Event` class.
+pub(crate) fn register(context: &mut Context) {
+    context
+        .register_global_class::<JsEvent>()
+        .expect("Failed to register Event");
+}
+
+/// Dispatch a trusted event at `target` of the bound document, invoking
+/// both Rust and script listeners. Returns `false` if the default action
+/// was prevented.
+pub fn dispatch_event(target: NodeId, event: &mut Event, context: &mut Context) -> JsResult<bool> {
+    event.is_trusted = true;
+    dispatch(target, event, &mut None, context)
+}
+
+/// Dispatch an event fired on behalf of script, as `click()` does.
+pub(crate) fn dispatch_untrusted(
+    target: NodeId,
+    event: &mut Event,
+    context: &mut Context,
+) -> JsResult<bool> {
+    event.is_trusted = false;
+    dispatch(target, event, &mut None, context)
+}
+
+/// Dispatch the `blur`, `focusout`, `focus` and `focusin` events of a
+/// focus change.
+pub fn dispatch_focus_change(change: FocusChange, context: &mut Context) -> JsResult<()> {
+    for (target, mut event) in change.events() {
+        dispatch_event(target, &mut event, context)?;
+    }
+    Ok(())
+}
+
+/// Forget every script listener callback.
+pub(crate) fn clear_listeners() {
+    SCRIPT_LISTENERS.with(|listeners| listeners.borrow_mut().callbacks.clear());
+}
+
+/// Run the propagation of `event`. `object` is the script's event object,
+/// created on first use when the event comes from Rust.
+fn dispatch(
+    target: NodeId,
+    event: &mut Event,
+    object: &mut Option<JsObject>,
+    ctx: &mut Context,
+) -> JsResult<bool> {
+    // Listeners run without the document locked, so they can use the DOM.
+    let (path, events) = with_document(|doc| {
+        (
+            EventPath::new(&doc.tree, target, event.composed),
+            doc.events.clone(),
+        )
+    })?;
+    let event_type = event.event_type.as_str().to_string();
+    Ok(path.dispatch(event, |node, event, capture| {
+        let listeners = events.lock().take_listeners(node, &event_type, capture);
+        invoke_listeners(&listeners, event, &mut |handle, event| {
+            if let Err(error) = call_listener(handle, event, object, ctx) {
+                tracing::warn!("Event listener failed: {}", error);
+            }
+        });
+        // `once` listeners are removed as they are invoked; the ones that
+        // did not get their turn stay.
+        let invoked_once = listeners
+            .iter()
+            .filter(|l| l.options.once && l.is_removed());
+        for listener in invoked_once {
+            if let ListenerCallback::Script(handle) = listener.callback {
+                forget_listener(handle);
+            }
+        }
+    }))
+}
+
+/// Invoke the script callback behind `handle`, syncing `event` with its
+/// script object around the call.
+fn call_listener(
+    handle: u64,
+    event: &mut Event,
+    object: &mut Option<JsObject>,
+    ctx: &mut Context,
+) -> JsResult<()> {
+    // Listeners removed earlier in this dispatch are gone from the map.
+    let Some(callback) =
+        SCRIPT_LISTENERS.with(|listeners| listeners.borrow().callbacks.get(&handle).cloned())
+    else {
+        return Ok(());
+    };
+    let object = match object {
+        Some(object) => object.clone(),
+        None => object
+            .insert(JsEvent::from_data(
+                JsEvent {
+                    event: RefCell::new(event.clone()),
+                },
+                ctx,
+            )?)
+            .clone(),
+    };
+    store_event(&object, event);
+
+    let this = match event.current_target {
+        Some(node) => node_object(node.to_raw(), ctx)?,
+        None => JsValue::undefined(),
+    };
+    let result = match JsFunction::from_object(callback.clone()) {
+        Some(function) => function
+            .call(&this, &[object.clone().into()], ctx)
+            .map(drop),
+        None => match callback.get(js_string!("handleEvent"), ctx)?.as_callable() {
+            Some(handle_event) => handle_event
+                .call(&callback.clone().into(), &[object.clone().into()], ctx)
+                .map(drop),
+            None => Err(JsNativeError::typ()
+                .with_message("The listener has no handleEvent method")
+                .into()),
+        },
+    };
+
+    if let Some(js_event) = object.downcast_ref::<JsEvent>() {
+        *event = js_event.event.borrow().clone();
+    }
+    result
+}
+
+fn store_event(object: &JsObject, event: &Event) {
+    if let Some(js_event) = object.downcast_ref::<JsEvent>() {
+        *js_event.event.borrow_mut() = event.clone();
+    }
+}
+
+fn forget_listener(handle: u64) {
+    SCRIPT_LISTENERS.with(|listeners| listeners.borrow_mut().callbacks.remove(&handle));
+}
+
+/// Whether `handle` is registered for `callback`.
+fn is_callback(handle: u64, callback: &JsObject) -> bool {
+    SCRIPT_LISTENERS.with(|listeners| {
+        listeners
+            .borrow()
+            .callbacks
+            .get(&handle)
+            .is_some_and(|registered| JsObject::equals(registered, callback))
+    })
+}
+
+/// The event type for `name`. Types are case-sensitive, so names that only
+/// match a known type case-insensitively stay custom.
+fn event_type(name: String) -> EventType {
+    match EventType::from_str(&name) {
+        known if known.as_str() == name => known,
+        _ => EventType::Custom(name),
+    }
+}
+
+/// Convert the options argument of `addEventListener()`, which may also
+/// be the `capture` flag.
+fn listener_options(value: &JsValue, ctx: &mut Context) -> JsResult<EventListenerOptions> {
+    let Some(object) = value.as_object() else {
+        return Ok(EventListenerOptions {
+            capture: value.to_boolean(),
+            ..Default::default()
+        });
+    };
+    Ok(EventListenerOptions {
+        capture: object.get(js_string!("capture"), ctx)?.to_boolean(),
+        once: object.get(js_string!("once"), ctx)?.to_boolean(),
+        passive: object.get(js_string!("passive"), ctx)?.to_boolean(),
+    })
+}
+
+/// Add a script listener to `target`, unless `callback` already listens
+/// for the same type and phase there.
+pub(crate) fn add_listener(
+    target: NodeId,
+    event_type: &str,
+    callback: JsObject,
+    options: EventListenerOptions,
+) -> JsResult<()> {
+    with_document(|doc| {
+        let mut events = doc.events.lock();
+        let duplicate = events.get_listeners(target, event_type).iter().any(|listener| {
+            listener.options.capture == options.capture
+                && matches!(listener.callback, ListenerCallback::Script(handle) if is_callback(handle, &callback))
+        });
+        if !duplicate {
+            let handle = SCRIPT_LISTENERS.with(|listeners| {
+                let mut listeners = listeners.borrow_mut();
+                listeners.next_handle += 1;
+                let handle = listeners.next_handle;
+                listeners.callbacks.insert(handle, callback);
+                handle
+            });
+            events.add_script_listener(target, event_type, handle, options);
+        }
+    })
+}
+
+/// Remove the script listener `callback` from `target`.
+pub(crate) fn remove_listener(
+    target: NodeId,
+    event_type: &str,
+    callback: &JsObject,
+    capture: bool,
+) -> JsResult<()> {
+    let removed = with_document(|doc| {
+        doc.events
+            .lock()
+            .remove_script_listener(target, event_type, capture, |handle| {
+                is_callback(handle, callback)
+            })
+    })?;
+    if let Some(handle) = removed {
+        forget_listener(handle);
+    }
+    Ok(())
+}
+
+/// Whether `object` is a script `Event`.
+pub(crate) fn is_event(object: &JsObject) -> bool {
+    object.is::<JsEvent>()
+}
+
+/// Dispatch the script event `object` at `target`.
+pub(crate) fn dispatch_object(
+    target: NodeId,
+    object: &JsObject,
+    ctx: &mut Context,
+) -> JsResult<bool> {
+    let mut event = this_event(&object.clone().into())?;
+    if event.phase != EventPhase::None {
+        return Err(JsNativeError::error()
+            .with_message("The event is already being dispatched")
+            .into());
+    }
+
+    event.is_trusted = false;
+    let result = dispatch(target, &mut event, &mut Some(object.clone()), ctx)?;
+    store_event(object, &event);
+    Ok(result)
+}
+
+pub(crate) fn event_target_add_event_listener(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let target = query_scope(this, ctx)?;
+    let event_type = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    if let Some(callback) = args.get_or_undefined(1).as_object().cloned() {
+        let options = listener_options(args.get_or_undefined(2), ctx)?;
+        add_listener(target, &event_type, callback, options)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+pub(crate) fn event_target_remove_event_listener(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let target = query_scope(this, ctx)?;
+    let event_type = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    if let Some(callback) = args.get_or_undefined(1).as_object().cloned() {
+        let capture = listener_options(args.get_or_undefined(2), ctx)?.capture;
+        remove_listener(target, &event_type, &callback, capture)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+pub(crate) fn event_target_dispatch_event(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let target = query_scope(this, ctx)?;
+    let object = args
+        .get_or_undefined(0)
+        .as_object()
+        .filter(|object| is_event(object))
+        .cloned()
+        .ok_or_else(|| JsNativeError::typ().with_message("parameter 1 is not of type 'Event'"))?;
+    Ok(JsValue::from(dispatch_object(target, &object, ctx)?))
+}
+
+/// The `Event` class.
+#[derive(Debug, Trace, Finalize, JsData)]
+struct JsEvent {
+    #[unsafe_ignore_trace]
+    event: RefCell<Event>,
+}
+
+impl Class for JsEvent {
+    const NAME: &'static str = "Event";
+    const LENGTH: usize = 1;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        for (name, value) in [
+            ("NONE", 0),
+            ("CAPTURING_PHASE", 1),
+            ("AT_TARGET", 2),
+            ("BUBBLING_PHASE", 3),
+        ] {
+            class.static_property(js_string!(name), value, Attribute::READONLY);
+            class.property(js_string!(name), value, Attribute::READONLY);
+        }
+
+        let realm = class.context().realm().clone();
+        let getters: [(&str, Getter); 13] = [
+            ("type", event_get_type),
+            ("bubbles", event_get_bubbles),
+            ("cancelable", event_get_cancelable),
+            ("composed", event_get_composed),
+            ("defaultPrevented", event_get_default_prevented),
+            ("eventPhase", event_get_event_phase),
+            ("target", event_get_target),
+            ("currentTarget", event_get_current_target),
+            ("isTrusted", event_get_is_trusted),
+            ("timeStamp", event_get_time_stamp),
+            ("inputType", event_get_input_type),
+            ("data", event_get_data),
+            ("isComposing", event_get_is_composing),
+        ];
+        for (name, getter) in getters {
+            let getter = NativeFunction::from_fn_ptr(getter).to_js_function(&realm);
+            class.accessor(
+                js_string!(name),
+                Some(getter),
+                None,
+                Attribute::CONFIGURABLE,
+            );
+        }
+
+        class
+            .method(
+                js_string!("preventDefault"),
+                0,
+                NativeFunction::from_fn_ptr(event_prevent_default),
+            )
+            .method(
+                js_string!("stopPropagation"),
+                0,
+                NativeFunction::from_fn_ptr(event_stop_propagation),
+            )
+            .method(
+                js_string!("stopImmediatePropagation"),
+                0,
+                NativeFunction::from_fn_ptr(event_stop_immediate_propagation),
+            )
+            .method(
+                js_string!("composedPath"),
+                0,
+                NativeFunction::from_fn_ptr(event_composed_path),
+            );
+        Ok(())
+    }
+
+    fn data_constructor(
+        _new_target: &JsValue,
+        args: &[JsValue],
+        ctx: &mut Context,
+    ) -> JsResult<Self> {
+        if args.is_empty() {
+            return Err(JsNativeError::typ()
+                .with_message("Event constructor requires a type")
+                .into());
+        }
+        let name = args[0].to_string(ctx)?.to_std_string_escaped();
+        let flag = |name: &str, ctx: &mut Context| -> JsResult<bool> {
+            match args.get_or_undefined(1).as_object() {
+                Some(init) => Ok(init.get(js_string!(name), ctx)?.to_boolean()),
+                None => Ok(false),
+            }
+        };
+        let mut event = Event::with_options(
+            event_type(name),
+            flag("bubbles", ctx)?,
+            flag("cancelable", ctx)?,
+        );
+        event.composed = flag("composed", ctx)?;
+        if let Some(init) = args.get_or_undefined(1).as_object() {
+            let input_type = init.get(js_string!("inputType"), ctx)?;
+            if !input_type.is_undefined() {
+                let input_type = input_type.to_string(ctx)?.to_std_string_escaped();
+                if let Some(input_type) = InputType::parse(&input_type) {
+                    let mut input = InputDetail::new(input_type);
+                    let data = init.get(js_string!("data"), ctx)?;
+                    if !data.is_null_or_undefined() {
+                        input.data = Some(data.to_string(ctx)?.to_std_string_escaped());
+                    }
+                    input.is_composing = flag("isComposing", ctx)?;
+                    event.input = Some(input);
+                }
+            }
+        }
+        Ok(Self {
+            event: RefCell::new(event),
+        })
+    }
+}
+
+fn this_event(this: &JsValue) -> JsResult<Event> {
+    this.as_object()
+        .and_then(|object| {
+            object
+                .downcast_ref::<JsEvent>()
+                .map(|event| event.event.borrow().clone())
+        })
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("'this' is not an Event")
+                .into()
+        })
+}
+
+/// Apply `f` to the event behind `this`.
+fn with_event<R>(this: &JsValue, f: impl FnOnce(&mut Event) -> R) -> JsResult<R> {
+    let object = this
+        .as_object()
+        .ok_or_else(|| JsNativeError::typ().with_message("'this' is not an Event"))?;
+    let event = object
+        .downcast_ref::<JsEvent>()
+        .ok_or_else(|| JsNativeError::typ().with_message("'this' is not an Event"))?;
+    let result = f(&mut event.event.borrow_mut());
+    Ok(result)
+}
+
+fn optional_node(node: Option<NodeId>, ctx: &mut Context) -> JsResult<JsValue> {
+    match node {
+        Some(node) => node_object(node.to_raw(), ctx),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn event_get_type(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(js_string!(this_event(this)?.event_type.as_str()).into())
+}
+
+fn event_get_bubbles(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.bubbles))
+}
+
+fn event_get_cancelable(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.cancelable))
+}
+
+fn event_get_composed(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.composed))
+}
+
+fn event_get_default_prevented(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.default_prevented))
+}
+
+fn event_get_event_phase(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.phase as u32))
+}
+
+fn event_get_target(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    optional_node(this_event(this)?.target, ctx)
+}
+
+fn event_get_current_target(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    optional_node(this_event(this)?.current_target, ctx)
+}
+
+fn event_get_is_trusted(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.is_trusted))
+}
+
+fn event_get_time_stamp(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_event(this)?.timestamp))
+}
+
+fn event_get_input_type(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let input_type = this_event(this)?
+        .input
+        .map_or("", |input| input.input_type.as_str());
+    Ok(js_string!(input_type).into())
+}
+
+fn event_get_data(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    match this_event(this)?.input.and_then(|input| input.data) {
+        Some(data) => Ok(js_string!(data).into()),
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn event_get_is_composing(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(
+        this_event(this)?
+            .input
+            .is_some_and(|input| input.is_composing),
+    ))
+}
+
+fn event_prevent_default(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_event(this, Event::prevent_default)?;
+    Ok(JsValue::undefined())
+}
+
+fn event_stop_propagation(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_event(this, Event::stop_propagation)?;
+    Ok(JsValue::undefined())
+}
+
+fn event_stop_immediate_propagation(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_event(this, Event::stop_immediate_propagation)?;
+    Ok(JsValue::undefined())
+}
+
+fn event_composed_path(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let event = this_event(this)?;
+    // The path is only exposed while the event is being dispatched.
+    let path = if event.phase == EventPhase::None {
+        Vec::new()
+    } else {
+        event.composed_path()
+    };
+    let nodes = path
+        .into_iter()
+        .map(|node| node_object(node.to_raw(), ctx))
+        .collect::<JsResult<Vec<_>>>()?;
+    Ok(JsArray::from_iter(nodes, ctx).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::DomBindings;
+    use boa_engine::Source;
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+    use style::query::QuerySelector;
+
+    #[test]
+    fn test_script_and_native_listeners_share_dispatch() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div><button>Go</button></div>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let button = document
+            .read()
+            .tree
+            .query_selector("button")
+            .unwrap()
+            .unwrap();
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let native_seen = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        {
+            let native_seen = native_seen.clone();
+            document.read().events.lock().add_listener(
+                button,
+                "click",
+                Arc::new(move |event: &mut Event| native_seen.lock().push(event.phase)),
+                EventListenerOptions::default(),
+            );
+        }
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        eval(
+            &mut context,
+            "var log = []; var div = document.querySelector('div'); var button = document.querySelector('button'); \
+             function record(name) { return function (e) { log.push(name + ':' + e.eventPhase + ':' + this.nodeName); }; } \
+             document.addEventListener('click', record('doc-capture'), true); \
+             div.addEventListener('click', record('div-bubble')); \
+             div.addEventListener('click', record('div-once'), { once: true }); \
+             button.addEventListener('click', function (e) { e.preventDefault(); log.push('passive:' + e.defaultPrevented); }, { passive: true }); \
+             var handler = { handleEvent: function (e) { log.push('object:' + (e.target.nodeName)); } }; \
+             button.addEventListener('click', handler); \
+             button.addEventListener('click', handler);",
+        );
+
+        // Rust-initiated dispatch reaches script and Rust listeners alike.
+        let mut event = Event::new(EventType::Click);
+        assert!(dispatch_event(button, &mut event, &mut context).unwrap());
+        assert_eq!(
+            eval(&mut context, "log.join()"),
+            "doc-capture:1:#document,passive:false,object:BUTTON,div-bubble:3:DIV,div-once:3:DIV"
+        );
+        assert_eq!(*native_seen.lock(), vec![EventPhase::AtTarget]);
+
+        eval(
+            &mut context,
+            "log = []; div.addEventListener('click', function (e) { e.preventDefault(); e.stopImmediatePropagation(); }, true); \
+             var custom = new Event('click', { bubbles: true, cancelable: true }); \
+             var result = button.dispatchEvent(custom);",
+        );
+        assert_eq!(
+            eval(&mut context, "[result, custom.defaultPrevented, custom.isTrusted, custom.eventPhase, log.join()].join()"),
+            "false,true,false,0,doc-capture:1:#document"
+        );
+        assert_eq!(native_seen.lock().len(), 1);
+
+        eval(
+            &mut context,
+            "log = []; button.removeEventListener('click', handler); \
+             var quiet = new Event('ping'); button.addEventListener('ping', function (e) { log.push(e.bubbles, e.composedPath().length); }); \
+             button.dispatchEvent(quiet);",
+        );
+        assert_eq!(eval(&mut context, "log.join()"), "false,5");
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_once_and_removal_during_dispatch() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<button>Go</button>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        // A `once` listener skipped by stopImmediatePropagation() stays
+        // registered until it runs.
+        eval(
+            &mut context,
+            "var log = [], stop = true, button = document.querySelector('button'); \
+             button.addEventListener('click', function (e) { \
+                 log.push('first'); if (stop) e.stopImmediatePropagation(); }); \
+             button.addEventListener('click', function () { log.push('once'); }, { once: true }); \
+             button.dispatchEvent(new Event('click')); stop = false; \
+             button.dispatchEvent(new Event('click')); button.dispatchEvent(new Event('click'));",
+        );
+        assert_eq!(eval(&mut context, "log.join()"), "first,first,once,first");
+
+        // A listener removed by an earlier one in the same dispatch is not
+        // called.
+        eval(
+            &mut context,
+            "log = []; function later() { log.push('later'); } \
+             button.addEventListener('ping', function () { \
+                 log.push('remover'); button.removeEventListener('ping', later); }); \
+             button.addEventListener('ping', later); \
+             button.dispatchEvent(new Event('ping')); button.dispatchEvent(new Event('ping'));",
+        );
+        assert_eq!(eval(&mut context, "log.join()"), "remover,remover");
+
+        bindings.unbind_document();
+    }
+}
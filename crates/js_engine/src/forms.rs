@@ -0,0 +1,539 @@
+//! Form controls for scripts.
+//!
+//! `value`, `checked`, `selectedIndex`, constraint validation and form
+//! submission on `HTMLElement.prototype`, backed by `dom::forms`. Events
+//! fired by validation, submission and `click()` go through the shared
+//! dispatch, so script and Rust listeners both see them. A submission plans
+//! a navigation on the document for the embedder to perform.
+
+use crate::bindings::{element_object, node_argument, this_node_id, with_document};
+use boa_engine::{
+    js_string,
+    object::{FunctionObjectBuilder, JsObject, ObjectInitializer},
+    property::{Attribute, PropertyDescriptor},
+    Context, JsArgs, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use dom::forms::{self, ValidityState};
+use dom::node::NodeId;
+use dom::{Event, EventType};
+
+/// Native getter or setter of an accessor property.
+type Accessor = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Install the form control accessors and methods on `prototype`.
+pub(crate) fn install(prototype: &JsObject, context: &mut Context) {
+    let realm = context.realm().clone();
+    let accessors: [(&str, Accessor, Option<Accessor>); 9] = [
+        ("value", control_get_value, Some(control_set_value)),
+        ("checked", control_get_checked, Some(control_set_checked)),
+        ("selected", option_get_selected, Some(option_set_selected)),
+        (
+            "selectedIndex",
+            select_get_selected_index,
+            Some(select_set_selected_index),
+        ),
+        ("form", control_get_form, None),
+        ("elements", form_get_elements, None),
+        ("willValidate", control_get_will_validate, None),
+        ("validity", control_get_validity, None),
+        ("validationMessage", control_get_validation_message, None),
+    ];
+    for (name, getter, setter) in accessors {
+        let mut descriptor = PropertyDescriptor::builder()
+            .get(NativeFunction::from_fn_ptr(getter).to_js_function(&realm))
+            .enumerable(false)
+            .configurable(true);
+        if let Some(setter) = setter {
+            descriptor = descriptor.set(NativeFunction::from_fn_ptr(setter).to_js_function(&realm));
+        }
+        prototype
+            .define_property_or_throw(js_string!(name), descriptor, context)
+            .expect("Failed to define form control accessor");
+    }
+
+    let methods: [(&str, Accessor, usize); 6] = [
+        ("checkValidity", control_check_validity, 0),
+        ("reportValidity", control_check_validity, 0),
+        ("setCustomValidity", control_set_custom_validity, 1),
+        ("submit", form_submit, 0),
+        ("requestSubmit", form_request_submit, 0),
+        ("reset", form_reset, 0),
+    ];
+    for (name, method, length) in methods {
+        let function = FunctionObjectBuilder::new(&realm, NativeFunction::from_fn_ptr(method))
+            .name(js_string!(name))
+            .length(length)
+            .build();
+        prototype
+            .define_property_or_throw(
+                js_string!(name),
+                PropertyDescriptor::builder()
+                    .value(function)
+                    .writable(true)
+                    .enumerable(false)
+                    .configurable(true),
+                context,
+            )
+            .expect("Failed to define form control method");
+    }
+}
+
+/// The tag name of the element behind `this`.
+fn this_tag(this: &JsValue, ctx: &mut Context) -> JsResult<(NodeId, String)> {
+    let node = this_node_id(this, ctx)?;
+    let tag = with_document(|doc| doc.tree.get_element(node).map(|e| e.tag_name.to_string()))?;
+    Ok((node, tag.unwrap_or_default()))
+}
+
+fn is_input_of(node: NodeId, types: &[&str]) -> JsResult<bool> {
+    with_document(|doc| {
+        doc.tree.get_element(node).is_some_and(|e| {
+            e.tag_name.as_str() == "input" && types.contains(&forms::input_type(e))
+        })
+    })
+}
+
+fn control_get_value(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, tag) = this_tag(this, ctx)?;
+    if !matches!(
+        tag.as_str(),
+        "input" | "textarea" | "select" | "option" | "button" | "output" | "data" | "li" | "param"
+    ) {
+        return Ok(JsValue::undefined());
+    }
+    let value = with_document(|doc| forms::value(&doc.tree, node))?;
+    Ok(js_string!(value).into())
+}
+
+fn control_set_value(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let value = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    with_document(|doc| forms::set_value(&mut doc.tree, node, &value))?;
+    Ok(JsValue::undefined())
+}
+
+fn control_get_checked(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    if !is_input_of(node, &["checkbox", "radio"])? {
+        return Ok(JsValue::undefined());
+    }
+    Ok(with_document(|doc| forms::is_checked(&doc.tree, node))?.into())
+}
+
+fn control_set_checked(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let checked = args.get_or_undefined(0).to_boolean();
+    with_document(|doc| forms::set_checked(&mut doc.tree, node, checked))?;
+    Ok(JsValue::undefined())
+}
+
+fn option_get_selected(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, tag) = this_tag(this, ctx)?;
+    if tag != "option" {
+        return Ok(JsValue::undefined());
+    }
+    Ok(with_document(|doc| forms::is_checked(&doc.tree, node))?.into())
+}
+
+fn option_set_selected(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let selected = args.get_or_undefined(0).to_boolean();
+    with_document(|doc| forms::set_selected(&mut doc.tree, node, selected))?;
+    Ok(JsValue::undefined())
+}
+
+fn select_get_selected_index(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (node, tag) = this_tag(this, ctx)?;
+    if tag != "select" {
+        return Ok(JsValue::undefined());
+    }
+    let index = with_document(|doc| forms::selected_index(&doc.tree, node))?;
+    Ok(index.map_or(-1.0, |index| index as f64).into())
+}
+
+fn select_set_selected_index(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let index = args.get_or_undefined(0).to_i32(ctx)?;
+    let index = usize::try_from(index).ok();
+    with_document(|doc| forms::set_selected_index(&mut doc.tree, node, index))?;
+    Ok(JsValue::undefined())
+}
+
+fn control_get_form(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let form = with_document(|doc| forms::form_owner(&doc.tree, node))?;
+    element_object(form, ctx)
+}
+
+fn form_get_elements(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, tag) = this_tag(this, ctx)?;
+    if tag != "form" {
+        return Ok(JsValue::undefined());
+    }
+    crate::collections::form_elements(node, ctx)
+}
+
+fn control_get_will_validate(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    Ok(with_document(|doc| forms::will_validate(&doc.tree, node))?.into())
+}
+
+/// The validity of a control, with its custom message.
+fn validity(node: NodeId) -> JsResult<(ValidityState, String)> {
+    with_document(|doc| {
+        let message = doc
+            .tree
+            .get_element(node)
+            .map(|e| e.control.custom_validity.clone())
+            .unwrap_or_default();
+        (forms::validity(&doc.tree, node), message)
+    })
+}
+
+fn control_get_validity(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let (state, _) = validity(node)?;
+    let object = ObjectInitializer::new(ctx)
+        .property(
+            js_string!("valueMissing"),
+            state.value_missing,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("typeMismatch"),
+            state.type_mismatch,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("patternMismatch"),
+            state.pattern_mismatch,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("rangeUnderflow"),
+            state.range_underflow,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("rangeOverflow"),
+            state.range_overflow,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("customError"),
+            state.custom_error,
+            Attribute::READONLY,
+        )
+        .property(js_string!("valid"), state.valid(), Attribute::READONLY)
+        .build();
+    Ok(object.into())
+}
+
+fn control_get_validation_message(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    if !with_document(|doc| forms::will_validate(&doc.tree, node))? {
+        return Ok(js_string!("").into());
+    }
+    let (state, custom) = validity(node)?;
+    let message = if state.custom_error {
+        custom.as_str()
+    } else if state.value_missing {
+        "Please fill out this field."
+    } else if state.type_mismatch {
+        "Please enter a valid value."
+    } else if state.pattern_mismatch {
+        "Please match the requested format."
+    } else if state.range_underflow {
+        "Value is below the minimum."
+    } else if state.range_overflow {
+        "Value is above the maximum."
+    } else {
+        ""
+    };
+    Ok(js_string!(message).into())
+}
+
+/// Fire `invalid` at each of `controls`.
+fn fire_invalid(controls: Vec<NodeId>, ctx: &mut Context) -> JsResult<()> {
+    for control in controls {
+        let mut event = Event::with_options(EventType::Invalid, false, true);
+        crate::events::dispatch_event(control, &mut event, ctx)?;
+    }
+    Ok(())
+}
+
+fn control_check_validity(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (node, tag) = this_tag(this, ctx)?;
+    let invalid = with_document(|doc| match tag.as_str() {
+        "form" => forms::invalid_controls(&doc.tree, node),
+        _ if !forms::check_validity(&doc.tree, node) => vec![node],
+        _ => Vec::new(),
+    })?;
+    let valid = invalid.is_empty();
+    fire_invalid(invalid, ctx)?;
+    Ok(valid.into())
+}
+
+fn control_set_custom_validity(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_node_id(this, ctx)?;
+    let message = args
+        .get_or_undefined(0)
+        .to_string(ctx)?
+        .to_std_string_escaped();
+    with_document(|doc| {
+        if let Some(element) = doc.tree.get_element_mut(node) {
+            element.control.custom_validity = message;
+        }
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn form_submit(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (form, tag) = this_tag(this, ctx)?;
+    if tag == "form" {
+        with_document(|doc| doc.submit_form(form, None))?;
+    }
+    Ok(JsValue::undefined())
+}
+
+fn form_request_submit(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (form, tag) = this_tag(this, ctx)?;
+    if tag != "form" {
+        return Ok(JsValue::undefined());
+    }
+    let submitter = match args.get_or_undefined(0) {
+        value if value.is_null_or_undefined() => None,
+        value => {
+            let submitter = node_argument(value, ctx)?;
+            let valid = with_document(|doc| {
+                doc.tree
+                    .get_element(submitter)
+                    .is_some_and(forms::is_submit_button)
+                    && forms::form_owner(&doc.tree, submitter) == Some(form)
+            })?;
+            if !valid {
+                return Err(JsNativeError::typ()
+                    .with_message("The submitter is not a submit button of this form")
+                    .into());
+            }
+            Some(submitter)
+        }
+    };
+    request_submit(form, submitter, ctx)?;
+    Ok(JsValue::undefined())
+}
+
+/// Submit `form` as the user does: validate unless disabled, fire `submit`
+/// and plan the navigation.
+pub(crate) fn request_submit(
+    form: NodeId,
+    submitter: Option<NodeId>,
+    ctx: &mut Context,
+) -> JsResult<bool> {
+    let invalid = with_document(|doc| {
+        if forms::novalidate(&doc.tree, form, submitter) {
+            Vec::new()
+        } else {
+            forms::invalid_controls(&doc.tree, form)
+        }
+    })?;
+    if !invalid.is_empty() {
+        fire_invalid(invalid, ctx)?;
+        return Ok(false);
+    }
+    let mut event = Event::with_options(EventType::Submit, true, true);
+    if !crate::events::dispatch_event(form, &mut event, ctx)? {
+        return Ok(false);
+    }
+    with_document(|doc| doc.submit_form(form, submitter))
+}
+
+fn form_reset(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (form, tag) = this_tag(this, ctx)?;
+    if tag == "form" {
+        reset(form, ctx)?;
+    }
+    Ok(JsValue::undefined())
+}
+
+/// Reset `form` unless its `reset` event is canceled.
+fn reset(form: NodeId, ctx: &mut Context) -> JsResult<()> {
+    let mut event = Event::with_options(EventType::Reset, true, true);
+    if crate::events::dispatch_event(form, &mut event, ctx)? {
+        with_document(|doc| forms::reset(&mut doc.tree, form))?;
+    }
+    Ok(())
+}
+
+/// What activating an element does.
+enum Activation {
+    /// A checkbox or radio button, with the group's checked state to
+    /// restore if the click is canceled.
+    Toggle {
+        previous: Vec<(NodeId, bool)>,
+    },
+    Submit(NodeId),
+    Reset(NodeId),
+    None,
+}
+
+/// `click()`: fire a synthetic `click` and run the element's activation
+/// behavior unless it is canceled.
+pub(crate) fn click(node: NodeId, ctx: &mut Context) -> JsResult<()> {
+    let activation = with_document(|doc| {
+        let tree = &mut doc.tree;
+        let element = tree.get_element(node)?;
+        if forms::can_be_disabled(element) && forms::is_disabled(tree, node) {
+            return None;
+        }
+        let owner = forms::form_owner(tree, node);
+        let tag = element.tag_name.as_str();
+        let ty = if tag == "input" {
+            forms::input_type(element)
+        } else {
+            ""
+        };
+        // Checkboxes and radio buttons change before the event is fired.
+        Some(match (tag, ty) {
+            ("input", "checkbox" | "radio") => {
+                let group = match ty {
+                    "radio" => forms::radio_group(tree, node),
+                    _ => vec![node],
+                };
+                let previous = group
+                    .into_iter()
+                    .map(|id| (id, forms::is_checked(tree, id)))
+                    .collect();
+                let checked = ty == "radio" || !forms::is_checked(tree, node);
+                forms::set_checked(tree, node, checked);
+                Activation::Toggle { previous }
+            }
+            _ if forms::is_submit_button(element) => {
+                owner.map_or(Activation::None, Activation::Submit)
+            }
+            ("button", _) if forms::button_type(element) == "reset" => {
+                owner.map_or(Activation::None, Activation::Reset)
+            }
+            ("input", "reset") => owner.map_or(Activation::None, Activation::Reset),
+            _ => Activation::None,
+        })
+    })?;
+    let Some(activation) = activation else {
+        return Ok(());
+    };
+
+    let mut event = Event::with_options(EventType::Click, true, true);
+    event.composed = true;
+    let proceed = crate::events::dispatch_untrusted(node, &mut event, ctx)?;
+
+    match activation {
+        Activation::Toggle { previous } if !proceed => {
+            with_document(|doc| {
+                for (id, checked) in previous {
+                    if let Some(element) = doc.tree.get_element_mut(id) {
+                        element
+                            .flags
+                            .set(dom::element::ElementFlags::CHECKED, checked);
+                    }
+                }
+            })?;
+        }
+        Activation::Toggle { previous } => {
+            let changed = with_document(|doc| {
+                previous
+                    .iter()
+                    .any(|&(id, checked)| forms::is_checked(&doc.tree, id) != checked)
+            })?;
+            if changed {
+                let mut input = Event::with_options(EventType::Input, true, false);
+                input.composed = true;
+                crate::events::dispatch_event(node, &mut input, ctx)?;
+                let mut change = Event::with_options(EventType::Change, true, false);
+                crate::events::dispatch_event(node, &mut change, ctx)?;
+            }
+        }
+        _ if !proceed => {}
+        Activation::Submit(form) => {
+            request_submit(form, Some(node), ctx)?;
+        }
+        Activation::Reset(form) => reset(form, ctx)?,
+        Activation::None => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_form_controls_and_submission() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<form action=\"/search\"><input name=\"q\" required><input type=\"checkbox\" name=\"all\">\
+             <select name=\"sort\"><option>new</option><option value=\"old\">Oldest</option></select>\
+             <button>Go</button></form>",
+            url::Url::parse("https://example.com/page").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var form = document.querySelector('form'); var log = []; \
+                 var q = form.elements[0], all = form.elements[1], sort = form.elements[2]; \
+                 form.addEventListener('invalid', function (e) { log.push('invalid:' + e.target.getAttribute('name')); }, true); \
+                 form.addEventListener('submit', function () { log.push('submit'); }); \
+                 all.addEventListener('change', function () { log.push('change:' + all.checked); }); \
+                 log.push(form.checkValidity(), q.validity.valueMissing, q.validationMessage); \
+                 document.querySelector('button').click(); \
+                 q.value = 'rust forms'; sort.selectedIndex = 1; all.click(); \
+                 document.querySelector('button').click(); \
+                 log.push(sort.value, q.form.tagName, form.matches(':valid')); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result.to_string(&mut context).unwrap().to_std_string_escaped(),
+            "invalid:q|false|true|Please fill out this field.|invalid:q|change:true|submit|old|FORM|true"
+        );
+
+        let submission = document.write().take_planned_navigation().unwrap();
+        assert_eq!(
+            submission.url().as_str(),
+            "https://example.com/search?q=rust+forms&all=on&sort=old"
+        );
+    }
+}
@@ -4,13 +4,21 @@
 //! including DOM bindings, Web APIs, and an event loop.
 
 pub mod bindings;
+pub mod collections;
 pub mod console;
 pub mod context;
+pub mod css;
+pub mod custom_elements;
+pub mod editing;
 pub mod engine;
+pub mod events;
+pub mod forms;
 pub mod event_loop;
 pub mod modules;
+pub mod range;
 pub mod runtime;
 pub mod timers;
+pub mod traversal;
 
 pub use context::JsContext;
 pub use engine::JsEngine;
@@ -0,0 +1,1249 @@
+//! `Range` and `Selection` for scripts.
+//!
+//! Script ranges are tracked by a `dom::LiveRanges` hook on the bound
+//! document's tree. The range returned by `Selection.getRangeAt()` reads and
+//! writes the document's selection instead. Text offsets are UTF-16 code
+//! units on the script side and bytes in the DOM.
+
+use crate::bindings::{node_argument, node_object, with_document};
+use boa_engine::{
+    class::{Class, ClassBuilder},
+    js_string,
+    object::{builtins::JsArray, JsObject, ObjectInitializer},
+    property::Attribute,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{Finalize, Trace};
+use common::geometry::Rect;
+use dom::mutation::MutationHookId;
+use dom::node::{NodeData, NodeId};
+use dom::selection::Selection;
+use dom::tree::DomTree;
+use dom::{LiveRanges, Range, RangeError};
+use layout::LayoutTree;
+use parking_lot::{Mutex, RwLock};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+thread_local! {
+    /// Ranges of the script context on this thread.
+    static SCRIPT_RANGES: RefCell<ScriptRanges> = RefCell::new(ScriptRanges::default());
+}
+
+/// Live ranges, the selection object and the layout used for geometry.
+#[derive(Default)]
+struct ScriptRanges {
+    /// Installed as a hook on the bound document's tree.
+    live: Arc<LiveRanges>,
+    hook: Option<MutationHookId>,
+    /// `getSelection()` returns the same object until the document changes.
+    selection: Option<JsObject>,
+    layout: Option<Arc<RwLock<LayoutTree>>>,
+}
+
+/// Native getter of an accessor property.
+type Getter = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Register `Range`, `Selection` and the global `getSelection()`.
+pub(crate) fn register(context: &mut Context) {
+    context
+        .register_global_class::<JsRange>()
+        .expect("Failed to register Range");
+    context
+        .register_global_class::<JsSelection>()
+        .expect("Failed to register Selection");
+    context
+        .register_global_builtin_callable(
+            js_string!("getSelection"),
+            0,
+            NativeFunction::from_fn_ptr(get_selection),
+        )
+        .expect("Failed to register getSelection");
+}
+
+/// Keep script ranges live across mutations of `tree`.
+pub(crate) fn observe_tree(tree: &mut DomTree) {
+    let live = SCRIPT_RANGES.with(|ranges| ranges.borrow().live.clone());
+    let hook = tree.add_mutation_hook(live);
+    SCRIPT_RANGES.with(|ranges| ranges.borrow_mut().hook = Some(hook));
+}
+
+/// Stop tracking `tree` and forget its selection object.
+pub(crate) fn unobserve_tree(tree: &mut DomTree) {
+    let hook = SCRIPT_RANGES.with(|ranges| {
+        let mut ranges = ranges.borrow_mut();
+        ranges.selection = None;
+        ranges.hook.take()
+    });
+    if let Some(hook) = hook {
+        tree.remove_mutation_hook(hook);
+    }
+}
+
+/// Use `layout` for `getBoundingClientRect()` and `getClientRects()`.
+pub(crate) fn bind_layout(layout: Option<Arc<RwLock<LayoutTree>>>) {
+    SCRIPT_RANGES.with(|ranges| ranges.borrow_mut().layout = layout);
+}
+
+/// Where a script range keeps its boundary points.
+#[derive(Clone, Debug)]
+enum RangeHandle {
+    Live(Arc<Mutex<Range>>),
+    /// The range of the document's selection.
+    Selection(Arc<Mutex<Selection>>),
+}
+
+impl RangeHandle {
+    fn get(&self) -> JsResult<Range> {
+        match self {
+            RangeHandle::Live(range) => Ok(range.lock().clone()),
+            RangeHandle::Selection(selection) => {
+                selection.lock().range().cloned().ok_or_else(|| {
+                    JsNativeError::error()
+                        .with_message("The selection no longer has this range")
+                        .into()
+                })
+            }
+        }
+    }
+
+    fn set(&self, range: Range) {
+        match self {
+            RangeHandle::Live(live) => *live.lock() = range,
+            RangeHandle::Selection(selection) => {
+                if let Some(selected) = selection.lock().range_mut() {
+                    *selected = range;
+                }
+            }
+        }
+    }
+}
+
+/// The `Range` class.
+#[derive(Debug, Trace, Finalize, JsData)]
+struct JsRange {
+    #[unsafe_ignore_trace]
+    handle: RangeHandle,
+}
+
+impl JsRange {
+    /// Track `range` and wrap it.
+    fn live(range: Range) -> Self {
+        let range = SCRIPT_RANGES.with(|ranges| ranges.borrow().live.track(range));
+        Self {
+            handle: RangeHandle::Live(range),
+        }
+    }
+}
+
+impl Class for JsRange {
+    const NAME: &'static str = "Range";
+    const LENGTH: usize = 0;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        for (name, value) in [
+            ("START_TO_START", 0),
+            ("START_TO_END", 1),
+            ("END_TO_END", 2),
+            ("END_TO_START", 3),
+        ] {
+            class.static_property(js_string!(name), value, Attribute::READONLY);
+            class.property(js_string!(name), value, Attribute::READONLY);
+        }
+
+        let realm = class.context().realm().clone();
+        let getters: [(&str, Getter); 6] = [
+            ("startContainer", range_start_container),
+            ("startOffset", range_start_offset),
+            ("endContainer", range_end_container),
+            ("endOffset", range_end_offset),
+            ("collapsed", range_collapsed),
+            ("commonAncestorContainer", range_common_ancestor_container),
+        ];
+        for (name, getter) in getters {
+            let getter = NativeFunction::from_fn_ptr(getter).to_js_function(&realm);
+            class.accessor(
+                js_string!(name),
+                Some(getter),
+                None,
+                Attribute::CONFIGURABLE,
+            );
+        }
+
+        class
+            .method(
+                js_string!("setStart"),
+                2,
+                NativeFunction::from_fn_ptr(range_set_start),
+            )
+            .method(
+                js_string!("setEnd"),
+                2,
+                NativeFunction::from_fn_ptr(range_set_end),
+            )
+            .method(
+                js_string!("setStartBefore"),
+                1,
+                NativeFunction::from_fn_ptr(range_set_start_before),
+            )
+            .method(
+                js_string!("setStartAfter"),
+                1,
+                NativeFunction::from_fn_ptr(range_set_start_after),
+            )
+            .method(
+                js_string!("setEndBefore"),
+                1,
+                NativeFunction::from_fn_ptr(range_set_end_before),
+            )
+            .method(
+                js_string!("setEndAfter"),
+                1,
+                NativeFunction::from_fn_ptr(range_set_end_after),
+            )
+            .method(
+                js_string!("collapse"),
+                0,
+                NativeFunction::from_fn_ptr(range_collapse),
+            )
+            .method(
+                js_string!("selectNode"),
+                1,
+                NativeFunction::from_fn_ptr(range_select_node),
+            )
+            .method(
+                js_string!("selectNodeContents"),
+                1,
+                NativeFunction::from_fn_ptr(range_select_node_contents),
+            )
+            .method(
+                js_string!("compareBoundaryPoints"),
+                2,
+                NativeFunction::from_fn_ptr(range_compare_boundary_points),
+            )
+            .method(
+                js_string!("comparePoint"),
+                2,
+                NativeFunction::from_fn_ptr(range_compare_point),
+            )
+            .method(
+                js_string!("isPointInRange"),
+                2,
+                NativeFunction::from_fn_ptr(range_is_point_in_range),
+            )
+            .method(
+                js_string!("intersectsNode"),
+                1,
+                NativeFunction::from_fn_ptr(range_intersects_node),
+            )
+            .method(
+                js_string!("cloneContents"),
+                0,
+                NativeFunction::from_fn_ptr(range_clone_contents),
+            )
+            .method(
+                js_string!("extractContents"),
+                0,
+                NativeFunction::from_fn_ptr(range_extract_contents),
+            )
+            .method(
+                js_string!("deleteContents"),
+                0,
+                NativeFunction::from_fn_ptr(range_delete_contents),
+            )
+            .method(
+                js_string!("insertNode"),
+                1,
+                NativeFunction::from_fn_ptr(range_insert_node),
+            )
+            .method(
+                js_string!("surroundContents"),
+                1,
+                NativeFunction::from_fn_ptr(range_surround_contents),
+            )
+            .method(
+                js_string!("cloneRange"),
+                0,
+                NativeFunction::from_fn_ptr(range_clone_range),
+            )
+            .method(
+                js_string!("detach"),
+                0,
+                NativeFunction::from_fn_ptr(range_detach),
+            )
+            .method(
+                js_string!("getBoundingClientRect"),
+                0,
+                NativeFunction::from_fn_ptr(range_get_bounding_client_rect),
+            )
+            .method(
+                js_string!("getClientRects"),
+                0,
+                NativeFunction::from_fn_ptr(range_get_client_rects),
+            )
+            .method(
+                js_string!("toString"),
+                0,
+                NativeFunction::from_fn_ptr(range_to_string),
+            );
+        Ok(())
+    }
+
+    fn data_constructor(
+        _new_target: &JsValue,
+        _args: &[JsValue],
+        _ctx: &mut Context,
+    ) -> JsResult<Self> {
+        let root = with_document(|doc| doc.tree.root())?
+            .ok_or_else(|| JsNativeError::error().with_message("The document has no root"))?;
+        Ok(Self::live(Range::new(root)))
+    }
+}
+
+/// `document.createRange()`.
+pub(crate) fn document_create_range(
+    _: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let root = with_document(|doc| doc.tree.root())?
+        .ok_or_else(|| JsNativeError::error().with_message("The document has no root"))?;
+    Ok(JsRange::from_data(JsRange::live(Range::new(root)), ctx)?.into())
+}
+
+fn range_error(error: RangeError) -> JsError {
+    let native = match error {
+        RangeError::IndexSize(_) => JsNativeError::range(),
+        RangeError::InvalidNodeType | RangeError::HierarchyRequest => JsNativeError::typ(),
+        RangeError::InvalidState => JsNativeError::error(),
+    };
+    native.with_message(error.to_string()).into()
+}
+
+fn this_range(this: &JsValue) -> JsResult<RangeHandle> {
+    this.as_object()
+        .and_then(|object| {
+            object
+                .downcast_ref::<JsRange>()
+                .map(|range| range.handle.clone())
+        })
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("'this' is not a Range")
+                .into()
+        })
+}
+
+/// Run `f` on a copy of the range behind `this` and store the result.
+///
+/// The range is not locked while `f` runs, since tree mutations lock every
+/// live range to update it.
+fn with_range<R>(
+    this: &JsValue,
+    f: impl FnOnce(&mut Range, &mut DomTree) -> Result<R, RangeError>,
+) -> JsResult<R> {
+    let handle = this_range(this)?;
+    let mut range = handle.get()?;
+    let result = with_document(|doc| f(&mut range, &mut doc.tree))?;
+    handle.set(range);
+    result.map_err(range_error)
+}
+
+/// Byte offset in the DOM of a script offset into `node`.
+fn dom_offset(tree: &DomTree, node: NodeId, offset: u32) -> usize {
+    let offset = offset as usize;
+    match character_data(tree, node) {
+        Some(data) => {
+            let mut units = 0;
+            for (index, c) in data.char_indices() {
+                if units >= offset {
+                    return index;
+                }
+                units += c.len_utf16();
+            }
+            // Past the end stays out of range.
+            data.len() + offset.saturating_sub(units)
+        }
+        None => offset,
+    }
+}
+
+/// Script offset of a byte offset into `node`.
+fn script_offset(tree: &DomTree, node: NodeId, offset: usize) -> usize {
+    match character_data(tree, node) {
+        Some(data) => data
+            .get(..offset)
+            .map_or(offset, |prefix| prefix.encode_utf16().count()),
+        None => offset,
+    }
+}
+
+fn character_data(tree: &DomTree, node: NodeId) -> Option<&str> {
+    match tree.get(node).map(|n| &n.data) {
+        Some(NodeData::Text { content }) | Some(NodeData::Comment { content }) => Some(content),
+        _ => None,
+    }
+}
+
+/// Node and offset arguments of `setStart()` and friends.
+fn point_arguments(args: &[JsValue], ctx: &mut Context) -> JsResult<(NodeId, u32)> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    let offset = args.get_or_undefined(1).to_u32(ctx)?;
+    Ok((node, offset))
+}
+
+fn range_start_container(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = this_range(this)?.get()?.start().node;
+    node_object(node.to_raw(), ctx)
+}
+
+fn range_start_offset(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let start = this_range(this)?.get()?.start();
+    let offset = with_document(|doc| script_offset(&doc.tree, start.node, start.offset))?;
+    Ok(JsValue::from(offset as u32))
+}
+
+fn range_end_container(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = this_range(this)?.get()?.end().node;
+    node_object(node.to_raw(), ctx)
+}
+
+fn range_end_offset(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let end = this_range(this)?.get()?.end();
+    let offset = with_document(|doc| script_offset(&doc.tree, end.node, end.offset))?;
+    Ok(JsValue::from(offset as u32))
+}
+
+fn range_collapsed(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_range(this)?.get()?.collapsed()))
+}
+
+fn range_common_ancestor_container(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let range = this_range(this)?.get()?;
+    let node = with_document(|doc| range.common_ancestor_container(&doc.tree))?;
+    node_object(node.to_raw(), ctx)
+}
+
+fn range_set_start(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, offset) = point_arguments(args, ctx)?;
+    with_range(this, |range, tree| {
+        let offset = dom_offset(tree, node, offset);
+        range.set_start(tree, node, offset)
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn range_set_end(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, offset) = point_arguments(args, ctx)?;
+    with_range(this, |range, tree| {
+        let offset = dom_offset(tree, node, offset);
+        range.set_end(tree, node, offset)
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn range_set_start_before(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.set_start_before(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn range_set_start_after(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.set_start_after(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn range_set_end_before(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.set_end_before(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn range_set_end_after(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.set_end_after(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn range_collapse(this: &JsValue, args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let to_start = args.get_or_undefined(0).to_boolean();
+    with_range(this, |range, _| {
+        range.collapse(to_start);
+        Ok(())
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn range_select_node(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.select_node(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn range_select_node_contents(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.select_node_contents(tree, node))?;
+    Ok(JsValue::undefined())
+}
+
+fn ordering_value(ordering: Ordering) -> JsValue {
+    JsValue::from(match ordering {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    })
+}
+
+fn range_compare_boundary_points(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let how = args.get_or_undefined(0).to_u32(ctx)?;
+    let (this_start, other_start) = match how {
+        0 => (true, true),
+        1 => (false, true),
+        2 => (false, false),
+        3 => (true, false),
+        _ => {
+            return Err(JsNativeError::error()
+                .with_message("The comparison method is not supported")
+                .into())
+        }
+    };
+    let other = this_range(args.get_or_undefined(1))?.get()?;
+    let ordering = with_range(this, |range, tree| {
+        range.compare_boundary_points(tree, this_start, &other, other_start)
+    })?;
+    Ok(ordering_value(ordering))
+}
+
+fn range_compare_point(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, offset) = point_arguments(args, ctx)?;
+    let ordering = with_range(this, |range, tree| {
+        let offset = dom_offset(tree, node, offset);
+        range.compare_point(tree, node, offset)
+    })?;
+    Ok(ordering_value(ordering))
+}
+
+fn range_is_point_in_range(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (node, offset) = point_arguments(args, ctx)?;
+    let inside = with_range(this, |range, tree| {
+        let offset = dom_offset(tree, node, offset);
+        range.is_point_in_range(tree, node, offset)
+    })?;
+    Ok(JsValue::from(inside))
+}
+
+fn range_intersects_node(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    let intersects = with_range(this, |range, tree| Ok(range.intersects_node(tree, node)))?;
+    Ok(JsValue::from(intersects))
+}
+
+fn range_clone_contents(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let fragment = with_range(this, |range, tree| range.clone_contents(tree))?;
+    node_object(fragment.to_raw(), ctx)
+}
+
+fn range_extract_contents(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let fragment = with_range(this, |range, tree| range.extract_contents(tree))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    node_object(fragment.to_raw(), ctx)
+}
+
+fn range_delete_contents(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_range(this, |range, tree| {
+        range.delete_contents(tree);
+        Ok(())
+    })?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(JsValue::undefined())
+}
+
+fn range_insert_node(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.insert_node(tree, node))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(JsValue::undefined())
+}
+
+fn range_surround_contents(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_range(this, |range, tree| range.surround_contents(tree, node))?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(JsValue::undefined())
+}
+
+fn range_clone_range(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let range = this_range(this)?.get()?;
+    Ok(JsRange::from_data(JsRange::live(range), ctx)?.into())
+}
+
+fn range_detach(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::undefined())
+}
+
+fn range_to_string(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let text = with_range(this, |range, tree| Ok(range.to_string(tree)))?;
+    Ok(js_string!(text).into())
+}
+
+/// Rects of the range in the bound layout, or none without one.
+fn client_rects(this: &JsValue) -> JsResult<Vec<Rect>> {
+    let layout = SCRIPT_RANGES.with(|ranges| ranges.borrow().layout.clone());
+    with_range(this, |range, tree| {
+        Ok(layout
+            .map(|layout| layout.read().range_rects(tree, range))
+            .unwrap_or_default())
+    })
+}
+
+fn dom_rect(rect: Rect, ctx: &mut Context) -> JsValue {
+    ObjectInitializer::new(ctx)
+        .property(js_string!("x"), rect.x, Attribute::READONLY)
+        .property(js_string!("y"), rect.y, Attribute::READONLY)
+        .property(js_string!("width"), rect.width, Attribute::READONLY)
+        .property(js_string!("height"), rect.height, Attribute::READONLY)
+        .property(js_string!("top"), rect.y, Attribute::READONLY)
+        .property(js_string!("left"), rect.x, Attribute::READONLY)
+        .property(
+            js_string!("right"),
+            rect.x + rect.width,
+            Attribute::READONLY,
+        )
+        .property(
+            js_string!("bottom"),
+            rect.y + rect.height,
+            Attribute::READONLY,
+        )
+        .build()
+        .into()
+}
+
+fn range_get_bounding_client_rect(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let rect = client_rects(this)?
+        .into_iter()
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or(Rect::ZERO);
+    Ok(dom_rect(rect, ctx))
+}
+
+fn range_get_client_rects(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let rects: Vec<JsValue> = client_rects(this)?
+        .into_iter()
+        .map(|rect| dom_rect(rect, ctx))
+        .collect();
+    Ok(JsArray::from_iter(rects, ctx).into())
+}
+
+/// The `Selection` class, wrapping the bound document's selection.
+#[derive(Debug, Trace, Finalize, JsData)]
+struct JsSelection {
+    #[unsafe_ignore_trace]
+    selection: Arc<Mutex<Selection>>,
+}
+
+impl Class for JsSelection {
+    const NAME: &'static str = "Selection";
+    const LENGTH: usize = 0;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        let realm = class.context().realm().clone();
+        let getters: [(&str, Getter); 8] = [
+            ("anchorNode", selection_anchor_node),
+            ("anchorOffset", selection_anchor_offset),
+            ("focusNode", selection_focus_node),
+            ("focusOffset", selection_focus_offset),
+            ("isCollapsed", selection_is_collapsed),
+            ("rangeCount", selection_range_count),
+            ("type", selection_type),
+            ("direction", selection_direction),
+        ];
+        for (name, getter) in getters {
+            let getter = NativeFunction::from_fn_ptr(getter).to_js_function(&realm);
+            class.accessor(
+                js_string!(name),
+                Some(getter),
+                None,
+                Attribute::CONFIGURABLE,
+            );
+        }
+
+        class
+            .method(
+                js_string!("getRangeAt"),
+                1,
+                NativeFunction::from_fn_ptr(selection_get_range_at),
+            )
+            .method(
+                js_string!("addRange"),
+                1,
+                NativeFunction::from_fn_ptr(selection_add_range),
+            )
+            .method(
+                js_string!("removeRange"),
+                1,
+                NativeFunction::from_fn_ptr(selection_remove_all_ranges),
+            )
+            .method(
+                js_string!("removeAllRanges"),
+                0,
+                NativeFunction::from_fn_ptr(selection_remove_all_ranges),
+            )
+            .method(
+                js_string!("empty"),
+                0,
+                NativeFunction::from_fn_ptr(selection_remove_all_ranges),
+            )
+            .method(
+                js_string!("collapse"),
+                2,
+                NativeFunction::from_fn_ptr(selection_collapse),
+            )
+            .method(
+                js_string!("setPosition"),
+                2,
+                NativeFunction::from_fn_ptr(selection_collapse),
+            )
+            .method(
+                js_string!("collapseToStart"),
+                0,
+                NativeFunction::from_fn_ptr(selection_collapse_to_start),
+            )
+            .method(
+                js_string!("collapseToEnd"),
+                0,
+                NativeFunction::from_fn_ptr(selection_collapse_to_end),
+            )
+            .method(
+                js_string!("extend"),
+                2,
+                NativeFunction::from_fn_ptr(selection_extend),
+            )
+            .method(
+                js_string!("setBaseAndExtent"),
+                4,
+                NativeFunction::from_fn_ptr(selection_set_base_and_extent),
+            )
+            .method(
+                js_string!("selectAllChildren"),
+                1,
+                NativeFunction::from_fn_ptr(selection_select_all_children),
+            )
+            .method(
+                js_string!("deleteFromDocument"),
+                0,
+                NativeFunction::from_fn_ptr(selection_delete_from_document),
+            )
+            .method(
+                js_string!("containsNode"),
+                2,
+                NativeFunction::from_fn_ptr(selection_contains_node),
+            )
+            .method(
+                js_string!("toString"),
+                0,
+                NativeFunction::from_fn_ptr(selection_to_string),
+            );
+        Ok(())
+    }
+
+    fn data_constructor(
+        _new_target: &JsValue,
+        _args: &[JsValue],
+        _ctx: &mut Context,
+    ) -> JsResult<Self> {
+        Err(JsNativeError::typ()
+            .with_message("Illegal constructor")
+            .into())
+    }
+}
+
+/// `getSelection()` on the window or the document.
+pub(crate) fn get_selection(
+    _: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    if let Some(object) = SCRIPT_RANGES.with(|ranges| ranges.borrow().selection.clone()) {
+        return Ok(object.into());
+    }
+    let selection = with_document(|doc| doc.selection.clone())?;
+    let object = JsSelection::from_data(JsSelection { selection }, ctx)?;
+    SCRIPT_RANGES.with(|ranges| ranges.borrow_mut().selection = Some(object.clone()));
+    Ok(object.into())
+}
+
+fn this_selection(this: &JsValue) -> JsResult<Arc<Mutex<Selection>>> {
+    this.as_object()
+        .and_then(|object| {
+            object
+                .downcast_ref::<JsSelection>()
+                .map(|selection| selection.selection.clone())
+        })
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("'this' is not a Selection")
+                .into()
+        })
+}
+
+/// Run `f` on the selection behind `this` with the bound document's tree.
+fn with_selection<R>(
+    this: &JsValue,
+    f: impl FnOnce(&mut Selection, &DomTree) -> Result<R, RangeError>,
+) -> JsResult<R> {
+    let selection = this_selection(this)?;
+    with_document(|doc| f(&mut selection.lock(), &doc.tree))?.map_err(range_error)
+}
+
+/// Script node and offset of a selection end.
+fn selection_point(
+    this: &JsValue,
+    anchor: bool,
+    ctx: &mut Context,
+) -> JsResult<(JsValue, JsValue)> {
+    let point = with_selection(this, |selection, tree| {
+        let point = if anchor {
+            selection.anchor()
+        } else {
+            selection.focus()
+        };
+        Ok(point.map(|point| (point.node, script_offset(tree, point.node, point.offset))))
+    })?;
+    match point {
+        Some((node, offset)) => Ok((
+            node_object(node.to_raw(), ctx)?,
+            JsValue::from(offset as u32),
+        )),
+        None => Ok((JsValue::null(), JsValue::from(0))),
+    }
+}
+
+fn selection_anchor_node(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(selection_point(this, true, ctx)?.0)
+}
+
+fn selection_anchor_offset(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(selection_point(this, true, ctx)?.1)
+}
+
+fn selection_focus_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(selection_point(this, false, ctx)?.0)
+}
+
+fn selection_focus_offset(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(selection_point(this, false, ctx)?.1)
+}
+
+fn selection_is_collapsed(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(this_selection(this)?.lock().is_collapsed()))
+}
+
+fn selection_range_count(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(JsValue::from(
+        this_selection(this)?.lock().range_count() as u32
+    ))
+}
+
+fn selection_type(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let selection = this_selection(this)?;
+    let selection = selection.lock();
+    let kind = match selection.range() {
+        None => "None",
+        Some(range) if range.collapsed() => "Caret",
+        Some(_) => "Range",
+    };
+    Ok(js_string!(kind).into())
+}
+
+fn selection_direction(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(js_string!(this_selection(this)?.lock().direction().as_str()).into())
+}
+
+fn selection_get_range_at(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let index = args.get_or_undefined(0).to_u32(ctx)?;
+    let selection = this_selection(this)?;
+    if index as usize >= selection.lock().range_count() {
+        return Err(range_error(RangeError::IndexSize(index as usize)));
+    }
+    let range = JsRange {
+        handle: RangeHandle::Selection(selection),
+    };
+    Ok(JsRange::from_data(range, ctx)?.into())
+}
+
+fn selection_add_range(this: &JsValue, args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let range = this_range(args.get_or_undefined(0))?.get()?;
+    let selection = this_selection(this)?;
+    let mut selection = selection.lock();
+    if selection.range_count() == 0 {
+        selection.set_range(range);
+    }
+    Ok(JsValue::undefined())
+}
+
+fn selection_remove_all_ranges(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    this_selection(this)?.lock().remove_all_ranges();
+    Ok(JsValue::undefined())
+}
+
+fn selection_collapse(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    if args.get_or_undefined(0).is_null() {
+        return selection_remove_all_ranges(this, args, ctx);
+    }
+    let (node, offset) = point_arguments(args, ctx)?;
+    with_selection(this, |selection, tree| {
+        selection.collapse(tree, node, dom_offset(tree, node, offset))
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_collapse_to_start(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_selection(this, |selection, _| selection.collapse_to_start())?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_collapse_to_end(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    with_selection(this, |selection, _| selection.collapse_to_end())?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_extend(this: &JsValue, args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let (node, offset) = point_arguments(args, ctx)?;
+    with_selection(this, |selection, tree| {
+        selection.extend(tree, node, dom_offset(tree, node, offset))
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_set_base_and_extent(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (anchor, anchor_offset) = point_arguments(args, ctx)?;
+    let (focus, focus_offset) = point_arguments(args.get(2..).unwrap_or_default(), ctx)?;
+    with_selection(this, |selection, tree| {
+        selection.set_base_and_extent(
+            tree,
+            anchor,
+            dom_offset(tree, anchor, anchor_offset),
+            focus,
+            dom_offset(tree, focus, focus_offset),
+        )
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_select_all_children(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    with_selection(this, |selection, tree| {
+        selection.select_all_children(tree, node)
+    })?;
+    Ok(JsValue::undefined())
+}
+
+fn selection_delete_from_document(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    this_selection(this)?;
+    with_document(|doc| doc.delete_selection())?;
+    crate::custom_elements::run_custom_element_reactions(ctx);
+    Ok(JsValue::undefined())
+}
+
+fn selection_contains_node(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    let allow_partial = args.get_or_undefined(1).to_boolean();
+    let contains = with_selection(this, |selection, tree| {
+        Ok(selection.contains_node(tree, node, allow_partial))
+    })?;
+    Ok(JsValue::from(contains))
+}
+
+fn selection_to_string(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    let text = with_selection(this, |selection, tree| Ok(selection.to_string(tree)))?;
+    Ok(js_string!(text).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use dom::node::NodeId;
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+    use style::query::QuerySelector;
+
+    #[test]
+    fn test_ranges_and_selection() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<p>Hello <b>bold</b> world</p>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        let hello: NodeId = {
+            let doc = document.read();
+            let p = doc.tree.query_selector("p").unwrap().unwrap();
+            doc.tree.first_child(p).unwrap()
+        };
+        {
+            let doc = document.read();
+            doc.selection
+                .lock()
+                .set_base_and_extent(&doc.tree, hello, 4, hello, 2)
+                .unwrap();
+        }
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+
+        eval(
+            &mut context,
+            "var p = document.querySelector('p'), b = document.querySelector('b'); \
+             var s = getSelection(); var t = s.anchorNode;",
+        );
+        assert_eq!(eval(&mut context, "s === document.getSelection()"), "true");
+        assert_eq!(
+            eval(&mut context, "[s.toString(), s.type, s.direction, s.anchorOffset, s.focusOffset, t.nodeName].join()"),
+            "ll,Range,backward,4,2,#text"
+        );
+
+        eval(
+            &mut context,
+            "var r = document.createRange(); r.setStart(t, 2); r.setEndAfter(b); \
+             var text = r.toString(); r.surroundContents(document.createElement('span'));",
+        );
+        assert_eq!(eval(&mut context, "text"), "llo bold");
+        assert_eq!(
+            eval(&mut context, "p.innerHTML"),
+            "He<span>llo <b>bold</b></span> world"
+        );
+        assert_eq!(eval(&mut context, "r.toString()"), "llo bold");
+        // The selection followed the text it was in.
+        assert_eq!(
+            eval(&mut context, "[s.isCollapsed, s.anchorOffset].join()"),
+            "true,2"
+        );
+
+        eval(
+            &mut context,
+            "var r3 = new Range(); r3.setStart(p, 2); r3.setEnd(p, 3); \
+             document.querySelector('span').remove();",
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "[r3.startOffset, r3.endOffset, r3.toString()].join()"
+            ),
+            "1,2, world"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "[r3.compareBoundaryPoints(Range.START_TO_START, r), r3.compareBoundaryPoints(Range.END_TO_END, r)].join()"
+            ),
+            "0,1"
+        );
+        assert_eq!(eval(&mut context, "r3.getBoundingClientRect().width"), "0");
+
+        eval(
+            &mut context,
+            "s.selectAllChildren(p); var range = s.getRangeAt(0); range.setStart(p, 1);",
+        );
+        assert_eq!(eval(&mut context, "s.toString()"), " world");
+        eval(&mut context, "s.deleteFromDocument()");
+        assert_eq!(eval(&mut context, "p.innerHTML + s.rangeCount"), "He1");
+
+        bindings.unbind_document();
+    }
+
+    #[test]
+    fn test_range_contents_and_live_updates() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div><p>ab<b>cd</b></p><p>ef</p></div>",
+            url::Url::parse("about:blank").unwrap(),
+        )));
+        {
+            // Scripts reach the text nodes through the selection.
+            let doc = document.read();
+            let b = doc.tree.query_selector("b").unwrap().unwrap();
+            let cd = doc.tree.first_child(b).unwrap();
+            let p2 = doc.tree.query_selector_all("p").unwrap()[1];
+            let ef = doc.tree.first_child(p2).unwrap();
+            doc.selection
+                .lock()
+                .set_base_and_extent(&doc.tree, cd, 0, ef, 0)
+                .unwrap();
+        }
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document.clone());
+
+        let eval = |context: &mut Context, script: &str| {
+            let result = context.eval(Source::from_bytes(script)).unwrap();
+            result.to_string(context).unwrap().to_std_string_escaped()
+        };
+        eval(
+            &mut context,
+            "var d = document.querySelector('div'), ps = document.querySelectorAll('p'); \
+             var p1 = ps[0], p2 = ps[1], s = getSelection(); \
+             var cd = s.anchorNode, ef = s.focusNode; s.removeAllRanges();",
+        );
+
+        // Partially selected nodes are copied down to the selected text.
+        eval(
+            &mut context,
+            "var r = document.createRange(); r.setStart(cd, 1); r.setEnd(ef, 1); \
+             var copy = document.createElement('div'); copy.appendChild(r.cloneContents());",
+        );
+        assert_eq!(
+            eval(&mut context, "copy.innerHTML"),
+            "<p><b>d</b></p><p>e</p>"
+        );
+        assert_eq!(
+            eval(&mut context, "d.innerHTML"),
+            "<p>ab<b>cd</b></p><p>ef</p>"
+        );
+
+        eval(
+            &mut context,
+            "var taken = document.createElement('div'); taken.appendChild(r.extractContents());",
+        );
+        assert_eq!(
+            eval(&mut context, "taken.innerHTML"),
+            "<p><b>d</b></p><p>e</p>"
+        );
+        assert_eq!(
+            eval(&mut context, "d.innerHTML"),
+            "<p>ab<b>c</b></p><p>f</p>"
+        );
+        assert_eq!(
+            eval(
+                &mut context,
+                "[r.collapsed, r.startContainer.nodeName, r.startOffset].join()"
+            ),
+            "true,DIV,1"
+        );
+
+        // Boundaries in a parent shift with inserted and removed children,
+        // and move to the parent when their node is removed.
+        eval(
+            &mut context,
+            "var r2 = document.createRange(); r2.setStart(d, 1); r2.setEnd(ef, 1); \
+             d.insertBefore(document.createElement('hr'), p1);",
+        );
+        assert_eq!(
+            eval(&mut context, "[r2.startOffset, r2.toString()].join()"),
+            "2,f"
+        );
+        eval(&mut context, "d.removeChild(p2);");
+        assert_eq!(
+            eval(
+                &mut context,
+                "[r2.startContainer.nodeName, r2.startOffset, r2.endContainer.nodeName, r2.endOffset].join()"
+            ),
+            "DIV,2,DIV,2"
+        );
+
+        bindings.unbind_document();
+    }
+}
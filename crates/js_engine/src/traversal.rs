@@ -0,0 +1,670 @@
+//! `TreeWalker`, `NodeIterator` and `NodeFilter` for scripts.
+//!
+//! The walking itself is `dom::traversal`; here the tree is read one step
+//! at a time and the script filter runs with no lock held, so filters may
+//! mutate the document. Script node iterators are kept in place by a
+//! `dom::LiveNodeIterators` hook on the bound document's tree.
+
+use crate::bindings::{node_argument, node_object, with_document};
+use boa_engine::{
+    class::{Class, ClassBuilder},
+    js_string,
+    object::{JsObject, ObjectInitializer},
+    property::Attribute,
+    Context, JsArgs, JsData, JsError, JsNativeError, JsResult, JsValue, NativeFunction,
+};
+use boa_gc::{Finalize, Trace};
+use dom::mutation::MutationHookId;
+use dom::node::NodeId;
+use dom::traversal::{self, what_to_show, FilterResult, TraversalSource};
+use dom::tree::DomTree;
+use dom::{LiveNodeIterators, NodeIterator, TreeWalker};
+use parking_lot::Mutex;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+
+thread_local! {
+    /// Node iterators of the script context on this thread.
+    static SCRIPT_ITERATORS: RefCell<ScriptIterators> = RefCell::new(ScriptIterators::default());
+}
+
+#[derive(Default)]
+struct ScriptIterators {
+    /// Installed as a hook on the bound document's tree.
+    live: Arc<LiveNodeIterators>,
+    hook: Option<MutationHookId>,
+}
+
+/// Native getter or setter of an accessor property.
+type Accessor = fn(&JsValue, &[JsValue], &mut Context) -> JsResult<JsValue>;
+
+/// Register `TreeWalker`, `NodeIterator` and the `NodeFilter` constants.
+pub(crate) fn register(context: &mut Context) {
+    context
+        .register_global_class::<JsTreeWalker>()
+        .expect("Failed to register TreeWalker");
+    context
+        .register_global_class::<JsNodeIterator>()
+        .expect("Failed to register NodeIterator");
+
+    let constants = [
+        ("FILTER_ACCEPT", FilterResult::Accept as u32),
+        ("FILTER_REJECT", FilterResult::Reject as u32),
+        ("FILTER_SKIP", FilterResult::Skip as u32),
+        ("SHOW_ALL", what_to_show::SHOW_ALL),
+        ("SHOW_ELEMENT", what_to_show::SHOW_ELEMENT),
+        ("SHOW_ATTRIBUTE", what_to_show::SHOW_ATTRIBUTE),
+        ("SHOW_TEXT", what_to_show::SHOW_TEXT),
+        ("SHOW_CDATA_SECTION", what_to_show::SHOW_CDATA_SECTION),
+        (
+            "SHOW_PROCESSING_INSTRUCTION",
+            what_to_show::SHOW_PROCESSING_INSTRUCTION,
+        ),
+        ("SHOW_COMMENT", what_to_show::SHOW_COMMENT),
+        ("SHOW_DOCUMENT", what_to_show::SHOW_DOCUMENT),
+        ("SHOW_DOCUMENT_TYPE", what_to_show::SHOW_DOCUMENT_TYPE),
+        (
+            "SHOW_DOCUMENT_FRAGMENT",
+            what_to_show::SHOW_DOCUMENT_FRAGMENT,
+        ),
+    ];
+    let mut node_filter = ObjectInitializer::new(context);
+    for (name, value) in constants {
+        node_filter.property(js_string!(name), value, Attribute::READONLY);
+    }
+    let node_filter = node_filter.build();
+    context
+        .register_global_property(js_string!("NodeFilter"), node_filter, Attribute::all())
+        .expect("Failed to register NodeFilter");
+}
+
+/// Keep script node iterators in place across mutations of `tree`.
+pub(crate) fn observe_tree(tree: &mut DomTree) {
+    let live = SCRIPT_ITERATORS.with(|iterators| iterators.borrow().live.clone());
+    let hook = tree.add_mutation_hook(live);
+    SCRIPT_ITERATORS.with(|iterators| iterators.borrow_mut().hook = Some(hook));
+}
+
+/// Stop tracking `tree`.
+pub(crate) fn unobserve_tree(tree: &mut DomTree) {
+    if let Some(hook) = SCRIPT_ITERATORS.with(|iterators| iterators.borrow_mut().hook.take()) {
+        tree.remove_mutation_hook(hook);
+    }
+}
+
+/// The bound tree seen through a script filter.
+struct ScriptSource<'a> {
+    filter: Option<JsObject>,
+    /// Set while the filter runs, against re-entrant traversal.
+    active: Rc<Cell<bool>>,
+    ctx: &'a mut Context,
+}
+
+impl ScriptSource<'_> {
+    fn tree<R: Default>(&self, f: impl FnOnce(&DomTree) -> R) -> R {
+        with_document(|doc| f(&doc.tree)).unwrap_or_default()
+    }
+
+    fn call_filter(&mut self, filter: &JsObject, node: NodeId) -> JsResult<JsValue> {
+        let node = node_object(node.to_raw(), self.ctx)?;
+        if filter.is_callable() {
+            return filter.call(&JsValue::undefined(), &[node], self.ctx);
+        }
+        let accept_node = filter.get(js_string!("acceptNode"), self.ctx)?;
+        let accept_node = accept_node.as_callable().ok_or_else(|| {
+            JsNativeError::typ().with_message("NodeFilter has no acceptNode method")
+        })?;
+        accept_node.call(&filter.clone().into(), &[node], self.ctx)
+    }
+}
+
+impl TraversalSource for ScriptSource<'_> {
+    type Error = JsError;
+
+    fn parent(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree(|tree| tree.parent(node))
+    }
+
+    fn first_child(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree(|tree| tree.first_child(node))
+    }
+
+    fn last_child(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree(|tree| tree.last_child(node))
+    }
+
+    fn previous_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree(|tree| tree.prev_sibling(node))
+    }
+
+    fn next_sibling(&mut self, node: NodeId) -> Option<NodeId> {
+        self.tree(|tree| tree.next_sibling(node))
+    }
+
+    fn show_bit(&mut self, node: NodeId) -> u32 {
+        self.tree(|tree| traversal::show_bit(tree, node))
+    }
+
+    fn accept(&mut self, node: NodeId) -> JsResult<FilterResult> {
+        let Some(filter) = self.filter.clone() else {
+            return Ok(FilterResult::Accept);
+        };
+        if self.active.get() {
+            return Err(JsNativeError::error()
+                .with_message("InvalidStateError: the node filter is already running")
+                .into());
+        }
+        self.active.set(true);
+        let result = self.call_filter(&filter, node);
+        self.active.set(false);
+        Ok(FilterResult::from_u16(result?.to_u32(self.ctx)? as u16))
+    }
+}
+
+/// `root`, `whatToShow` and `filter` arguments of the create methods.
+fn traversal_arguments(
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<(NodeId, u32, Option<JsObject>)> {
+    let root = node_argument(args.get_or_undefined(0), ctx)?;
+    let what_to_show = match args.get_or_undefined(1) {
+        JsValue::Undefined => what_to_show::SHOW_ALL,
+        value => value.to_u32(ctx)?,
+    };
+    let filter = args.get_or_undefined(2).as_object().cloned();
+    Ok((root, what_to_show, filter))
+}
+
+/// The `TreeWalker` class.
+#[derive(Clone, Debug, Trace, Finalize, JsData)]
+struct JsTreeWalker {
+    #[unsafe_ignore_trace]
+    walker: Cell<TreeWalker>,
+    filter: Option<JsObject>,
+    #[unsafe_ignore_trace]
+    active: Rc<Cell<bool>>,
+}
+
+impl Class for JsTreeWalker {
+    const NAME: &'static str = "TreeWalker";
+    const LENGTH: usize = 0;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        let realm = class.context().realm().clone();
+        let accessors: [(&str, Accessor, Option<Accessor>); 4] = [
+            ("root", walker_root, None),
+            ("whatToShow", walker_what_to_show, None),
+            ("filter", walker_filter, None),
+            (
+                "currentNode",
+                walker_current_node,
+                Some(walker_set_current_node),
+            ),
+        ];
+        for (name, getter, setter) in accessors {
+            let getter = NativeFunction::from_fn_ptr(getter).to_js_function(&realm);
+            let setter =
+                setter.map(|setter| NativeFunction::from_fn_ptr(setter).to_js_function(&realm));
+            class.accessor(
+                js_string!(name),
+                Some(getter),
+                setter,
+                Attribute::CONFIGURABLE,
+            );
+        }
+
+        class
+            .method(
+                js_string!("parentNode"),
+                0,
+                NativeFunction::from_fn_ptr(walker_parent_node),
+            )
+            .method(
+                js_string!("firstChild"),
+                0,
+                NativeFunction::from_fn_ptr(walker_first_child),
+            )
+            .method(
+                js_string!("lastChild"),
+                0,
+                NativeFunction::from_fn_ptr(walker_last_child),
+            )
+            .method(
+                js_string!("previousSibling"),
+                0,
+                NativeFunction::from_fn_ptr(walker_previous_sibling),
+            )
+            .method(
+                js_string!("nextSibling"),
+                0,
+                NativeFunction::from_fn_ptr(walker_next_sibling),
+            )
+            .method(
+                js_string!("previousNode"),
+                0,
+                NativeFunction::from_fn_ptr(walker_previous_node),
+            )
+            .method(
+                js_string!("nextNode"),
+                0,
+                NativeFunction::from_fn_ptr(walker_next_node),
+            );
+        Ok(())
+    }
+
+    fn data_constructor(
+        _new_target: &JsValue,
+        _args: &[JsValue],
+        _ctx: &mut Context,
+    ) -> JsResult<Self> {
+        Err(JsNativeError::typ()
+            .with_message("Illegal constructor")
+            .into())
+    }
+}
+
+/// `document.createTreeWalker(root, whatToShow, filter)`.
+pub(crate) fn document_create_tree_walker(
+    _: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (root, what_to_show, filter) = traversal_arguments(args, ctx)?;
+    let walker = JsTreeWalker {
+        walker: Cell::new(TreeWalker::new(root, what_to_show)),
+        filter,
+        active: Rc::default(),
+    };
+    Ok(JsTreeWalker::from_data(walker, ctx)?.into())
+}
+
+fn this_walker(this: &JsValue) -> JsResult<JsTreeWalker> {
+    this.as_object()
+        .and_then(|object| {
+            object
+                .downcast_ref::<JsTreeWalker>()
+                .map(|walker| walker.clone())
+        })
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("'this' is not a TreeWalker")
+                .into()
+        })
+}
+
+fn set_walker(this: &JsValue, update: impl FnOnce(&mut TreeWalker)) {
+    if let Some(walker) = this
+        .as_object()
+        .and_then(|object| object.downcast_ref::<JsTreeWalker>())
+    {
+        let mut state = walker.walker.get();
+        update(&mut state);
+        walker.walker.set(state);
+    }
+}
+
+/// Run a walker step on a copy of the walker; a node found becomes the
+/// current node.
+fn walk(
+    this: &JsValue,
+    ctx: &mut Context,
+    step: fn(&mut TreeWalker, &mut ScriptSource<'_>) -> JsResult<Option<NodeId>>,
+) -> JsResult<JsValue> {
+    let this_walker = this_walker(this)?;
+    let mut walker = this_walker.walker.get();
+    let mut source = ScriptSource {
+        filter: this_walker.filter.clone(),
+        active: this_walker.active.clone(),
+        ctx,
+    };
+    let found = step(&mut walker, &mut source)?;
+    match found {
+        Some(node) => {
+            set_walker(this, |walker| walker.current = node);
+            node_object(node.to_raw(), ctx)
+        }
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn walker_root(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    node_object(this_walker(this)?.walker.get().root.to_raw(), ctx)
+}
+
+fn walker_what_to_show(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(this_walker(this)?.walker.get().what_to_show.into())
+}
+
+fn walker_filter(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(this_walker(this)?
+        .filter
+        .clone()
+        .map_or(JsValue::null(), JsValue::from))
+}
+
+fn walker_current_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    node_object(this_walker(this)?.walker.get().current.to_raw(), ctx)
+}
+
+fn walker_set_current_node(
+    this: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    this_walker(this)?;
+    let node = node_argument(args.get_or_undefined(0), ctx)?;
+    set_walker(this, |walker| walker.current = node);
+    Ok(JsValue::undefined())
+}
+
+fn walker_parent_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.parent_node(source))
+}
+
+fn walker_first_child(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.first_child(source))
+}
+
+fn walker_last_child(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.last_child(source))
+}
+
+fn walker_previous_sibling(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.previous_sibling(source))
+}
+
+fn walker_next_sibling(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.next_sibling(source))
+}
+
+fn walker_previous_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.previous_node(source))
+}
+
+fn walker_next_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    walk(this, ctx, |walker, source| walker.next_node(source))
+}
+
+/// The `NodeIterator` class.
+#[derive(Clone, Debug, Trace, Finalize, JsData)]
+struct JsNodeIterator {
+    #[unsafe_ignore_trace]
+    iterator: Arc<Mutex<NodeIterator>>,
+    filter: Option<JsObject>,
+    #[unsafe_ignore_trace]
+    active: Rc<Cell<bool>>,
+}
+
+impl Class for JsNodeIterator {
+    const NAME: &'static str = "NodeIterator";
+    const LENGTH: usize = 0;
+
+    fn init(class: &mut ClassBuilder<'_>) -> JsResult<()> {
+        let realm = class.context().realm().clone();
+        let getters: [(&str, Accessor); 5] = [
+            ("root", iterator_root),
+            ("referenceNode", iterator_reference_node),
+            (
+                "pointerBeforeReferenceNode",
+                iterator_pointer_before_reference_node,
+            ),
+            ("whatToShow", iterator_what_to_show),
+            ("filter", iterator_filter),
+        ];
+        for (name, getter) in getters {
+            let getter = NativeFunction::from_fn_ptr(getter).to_js_function(&realm);
+            class.accessor(
+                js_string!(name),
+                Some(getter),
+                None,
+                Attribute::CONFIGURABLE,
+            );
+        }
+
+        class
+            .method(
+                js_string!("nextNode"),
+                0,
+                NativeFunction::from_fn_ptr(iterator_next_node),
+            )
+            .method(
+                js_string!("previousNode"),
+                0,
+                NativeFunction::from_fn_ptr(iterator_previous_node),
+            )
+            .method(
+                js_string!("detach"),
+                0,
+                NativeFunction::from_fn_ptr(iterator_detach),
+            );
+        Ok(())
+    }
+
+    fn data_constructor(
+        _new_target: &JsValue,
+        _args: &[JsValue],
+        _ctx: &mut Context,
+    ) -> JsResult<Self> {
+        Err(JsNativeError::typ()
+            .with_message("Illegal constructor")
+            .into())
+    }
+}
+
+/// `document.createNodeIterator(root, whatToShow, filter)`.
+pub(crate) fn document_create_node_iterator(
+    _: &JsValue,
+    args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let (root, what_to_show, filter) = traversal_arguments(args, ctx)?;
+    let iterator = SCRIPT_ITERATORS.with(|iterators| {
+        iterators
+            .borrow()
+            .live
+            .track(NodeIterator::new(root, what_to_show))
+    });
+    let iterator = JsNodeIterator {
+        iterator,
+        filter,
+        active: Rc::default(),
+    };
+    Ok(JsNodeIterator::from_data(iterator, ctx)?.into())
+}
+
+fn this_iterator(this: &JsValue) -> JsResult<JsNodeIterator> {
+    this.as_object()
+        .and_then(|object| {
+            object
+                .downcast_ref::<JsNodeIterator>()
+                .map(|iterator| iterator.clone())
+        })
+        .ok_or_else(|| {
+            JsNativeError::typ()
+                .with_message("'this' is not a NodeIterator")
+                .into()
+        })
+}
+
+/// Move the iterator. It is not locked while the filter runs, since tree
+/// mutations lock every live iterator to update it; the position found
+/// replaces any update made meanwhile.
+fn iterate(this: &JsValue, ctx: &mut Context, next: bool) -> JsResult<JsValue> {
+    let this_iterator = this_iterator(this)?;
+    let handle = &this_iterator.iterator;
+    let mut iterator = *handle.lock();
+    let mut source = ScriptSource {
+        filter: this_iterator.filter.clone(),
+        active: this_iterator.active.clone(),
+        ctx,
+    };
+    let found = if next {
+        iterator.next_node(&mut source)?
+    } else {
+        iterator.previous_node(&mut source)?
+    };
+    match found {
+        Some(node) => {
+            let mut live = handle.lock();
+            live.reference = iterator.reference;
+            live.pointer_before_reference = iterator.pointer_before_reference;
+            drop(live);
+            node_object(node.to_raw(), ctx)
+        }
+        None => Ok(JsValue::null()),
+    }
+}
+
+fn iterator_root(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    let root = this_iterator(this)?.iterator.lock().root;
+    node_object(root.to_raw(), ctx)
+}
+
+fn iterator_reference_node(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    let reference = this_iterator(this)?.iterator.lock().reference;
+    node_object(reference.to_raw(), ctx)
+}
+
+fn iterator_pointer_before_reference_node(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(this_iterator(this)?
+        .iterator
+        .lock()
+        .pointer_before_reference
+        .into())
+}
+
+fn iterator_what_to_show(
+    this: &JsValue,
+    _args: &[JsValue],
+    _ctx: &mut Context,
+) -> JsResult<JsValue> {
+    Ok(this_iterator(this)?.iterator.lock().what_to_show.into())
+}
+
+fn iterator_filter(this: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(this_iterator(this)?
+        .filter
+        .clone()
+        .map_or(JsValue::null(), JsValue::from))
+}
+
+fn iterator_next_node(this: &JsValue, _args: &[JsValue], ctx: &mut Context) -> JsResult<JsValue> {
+    iterate(this, ctx, true)
+}
+
+fn iterator_previous_node(
+    this: &JsValue,
+    _args: &[JsValue],
+    ctx: &mut Context,
+) -> JsResult<JsValue> {
+    iterate(this, ctx, false)
+}
+
+fn iterator_detach(_: &JsValue, _args: &[JsValue], _ctx: &mut Context) -> JsResult<JsValue> {
+    Ok(JsValue::undefined())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bindings::DomBindings;
+    use boa_engine::{Context, Source};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_tree_walker_and_node_iterator() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div id=\"root\"><p>one</p><!--note--><section><p>two</p></section><p>three</p></div>",
+            url::Url::parse("https://example.com/").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var root = document.querySelector('div'); var log = []; \
+                 var walker = document.createTreeWalker(root, NodeFilter.SHOW_ELEMENT, function (node) { \
+                     return node.nodeName === 'SECTION' ? NodeFilter.FILTER_SKIP : NodeFilter.FILTER_ACCEPT; }); \
+                 var names = []; while (walker.nextNode()) { names.push(walker.currentNode.nodeName); } \
+                 log.push(names.join(',')); \
+                 walker.currentNode = root; \
+                 log.push(walker.firstChild().nodeName, walker.nextSibling().nodeName, walker.parentNode().nodeName); \
+                 var comments = document.createTreeWalker(root, NodeFilter.SHOW_COMMENT); \
+                 log.push(comments.nextNode().nodeType, comments.nextNode()); \
+                 var iterator = document.createNodeIterator(root, NodeFilter.SHOW_ELEMENT, \
+                     { acceptNode: function (node) { return node.nodeName === 'SECTION' ? 2 : 1; } }); \
+                 var seen = [], node; \
+                 while ((node = iterator.nextNode())) { \
+                     seen.push(node.nodeName); \
+                     if (node.nodeName === 'P' && seen.length === 2) { node.remove(); } \
+                 } \
+                 log.push(seen.join(','), iterator.pointerBeforeReferenceNode); \
+                 var nested = document.createTreeWalker(root, NodeFilter.SHOW_ALL, function () { \
+                     try { nested.nextNode(); } catch (e) { log.push('reentered'); } return 1; }); \
+                 nested.nextNode(); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "P,P,P|P|P|DIV|8||DIV,P,P,P|false|reentered"
+        );
+    }
+
+    #[test]
+    fn test_node_iterator_reference_after_removal() {
+        let document = Arc::new(RwLock::new(html_parser::parse_html(
+            "<div><p>one</p><section><b>two</b></section><i>three</i></div>",
+            url::Url::parse("https://example.com/").unwrap(),
+        )));
+
+        let mut context = Context::default();
+        let bindings = DomBindings::new();
+        bindings.register(&mut context);
+        bindings.bind_document(document);
+
+        let result = context
+            .eval(Source::from_bytes(
+                "var root = document.querySelector('div'); var log = []; \
+                 var iterator = document.createNodeIterator(root, NodeFilter.SHOW_ELEMENT); \
+                 var state = function () { \
+                     log.push(iterator.referenceNode.nodeName + ':' + iterator.pointerBeforeReferenceNode); }; \
+                 iterator.nextNode(); iterator.nextNode(); iterator.nextNode(); iterator.nextNode(); \
+                 state(); \
+                 document.querySelector('section').remove(); state(); \
+                 log.push(iterator.nextNode().nodeName); \
+                 iterator.previousNode(); state(); \
+                 document.querySelector('i').remove(); state(); \
+                 log.push(iterator.nextNode(), iterator.previousNode().nodeName); \
+                 document.querySelector('p').remove(); state(); \
+                 log.join('|');",
+            ))
+            .unwrap();
+        assert_eq!(
+            result
+                .to_string(&mut context)
+                .unwrap()
+                .to_std_string_escaped(),
+            "B:false|#text:false|I|I:true|#text:false||P|DIV:false"
+        );
+    }
+}
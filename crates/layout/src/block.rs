@@ -246,6 +246,7 @@ impl BlockFormattingContext {
             match value {
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => containing_block.width * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(containing_block.width).unwrap_or(0.0),
                 SizeValue::Auto => 0.0,
                 _ => 0.0,
             }
@@ -281,6 +282,7 @@ impl BlockFormattingContext {
             match value {
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => containing_block.width * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(containing_block.width).unwrap_or(0.0),
                 _ => 0.0,
             }
         };
@@ -310,6 +312,7 @@ impl BlockFormattingContext {
         match &style.width {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => containing_block.width * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(containing_block.width).unwrap_or(0.0),
             SizeValue::Auto => {
                 // Fill available space
                 (containing_block.width - horizontal_space).max(0.0)
@@ -356,9 +359,9 @@ impl BlockFormattingContext {
 
         match &style.height {
             SizeValue::Length(l) => *l,
-            SizeValue::Percentage(p) => {
+            value @ (SizeValue::Percentage(_) | SizeValue::Calc(_)) => {
                 if let Some(cb_height) = containing_block.height {
-                    cb_height * p / 100.0
+                    value.resolve(cb_height)
                 } else {
                     self.calculate_content_height(tree, box_id)
                 }
@@ -247,6 +247,7 @@ impl UsedValues {
             match value {
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => containing_width * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(containing_width).unwrap_or(0.0),
                 _ => 0.0,
             }
         };
@@ -256,12 +257,14 @@ impl UsedValues {
                 SizeValue::Auto => 0.0,
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => containing_width * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(containing_width).unwrap_or(0.0),
                 _ => 0.0,
             },
             height: match &style.height {
                 SizeValue::Auto => 0.0,
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => containing_width * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(containing_width).unwrap_or(0.0),
                 _ => 0.0,
             },
             margin_top: resolve_size(&style.margin.top),
@@ -14,6 +14,10 @@ use std::sync::Arc;
 use style::computed::ComputedStyle;
 use style::resolver::StyleResolver;
 
+/// Bound on the restyle and relayout passes for container queries, in case
+/// container sizes keep changing.
+const MAX_CONTAINER_QUERY_PASSES: usize = 4;
+
 /// The layout engine.
 pub struct LayoutEngine {
     /// Viewport dimensions.
@@ -58,6 +62,32 @@ impl LayoutEngine {
         tree
     }
 
+    /// Perform layout on a document that uses container queries: lay out,
+    /// report the sizes of query containers to the style resolver, and
+    /// restyle and lay out again while they change.
+    pub fn layout_with_container_queries(
+        &mut self,
+        document: &Document,
+        style_resolver: &mut StyleResolver,
+    ) -> LayoutTree {
+        let mut tree = self.layout(document, style_resolver);
+        for _ in 0..MAX_CONTAINER_QUERY_PASSES {
+            let sizes: Vec<_> = style_resolver
+                .query_containers()
+                .into_iter()
+                .filter_map(|node| {
+                    let rect = tree.get(tree.find_by_node(node)?)?.content_rect();
+                    Some((node, rect.width, rect.height))
+                })
+                .collect();
+            if !style_resolver.update_container_sizes(&document.tree, sizes) {
+                break;
+            }
+            tree = self.layout(document, style_resolver);
+        }
+        tree
+    }
+
     /// Build layout tree from DOM tree.
     fn build_layout_tree(
         &mut self,
@@ -119,6 +149,16 @@ impl LayoutEngine {
             return None;
         }
 
+        // display: contents puts the children in place of the element
+        if style.display == style::computed::Display::Contents && node.node_type == NodeType::Element {
+            if let Some(parent_id) = parent_box {
+                for child_id in dom_tree.flat_children(node_id) {
+                    self.build_subtree(dom_tree, child_id, style_resolver, layout_tree, Some(parent_id));
+                }
+                return None;
+            }
+        }
+
         // Create layout box
         let box_type = if node.node_type == NodeType::Text {
             BoxType::Text
@@ -129,7 +169,7 @@ impl LayoutEngine {
         let layout_box = if node.node_type == NodeType::Text {
             if let Some(text_content) = node.as_text() {
                 let text_run = self.text_shaper.shape_text(text_content, &style);
-                Some(layout_tree.create_text_box(style.clone(), text_run))
+                Some(layout_tree.create_text_box(Some(node_id), style.clone(), text_run))
             } else {
                 None
             }
@@ -149,8 +189,9 @@ impl LayoutEngine {
             layout_tree.set_root(layout_box_id);
         }
 
-        // Build children
-        for child_id in dom_tree.children(node_id).collect::<Vec<_>>() {
+        // Build children from the flat tree, so shadow trees render in
+        // place of their host's children
+        for child_id in dom_tree.flat_children(node_id) {
             self.build_subtree(dom_tree, child_id, style_resolver, layout_tree, Some(layout_box_id));
         }
 
@@ -244,6 +285,7 @@ impl LayoutEngine {
             }
             style::computed::SizeValue::Length(l) => *l,
             style::computed::SizeValue::Percentage(p) => cb_width * p / 100.0,
+            style::computed::SizeValue::Calc(calc) => calc.resolve(cb_width).unwrap_or(0.0),
             _ => cb_width,
         };
 
@@ -366,6 +408,9 @@ impl LayoutEngine {
 
         let height = if let Some(h) = explicit_height {
             h
+        } else if style.container_type == style::computed::ContainerType::Size {
+            // Size containment: the box is sized as if it were empty.
+            0.0
         } else {
             // Auto height - sum of children
             let mut total_height = 0.0;
@@ -483,6 +528,7 @@ impl LayoutEngine {
         let specified_width = match &style.width {
             style::computed::SizeValue::Length(l) => Some(*l),
             style::computed::SizeValue::Percentage(p) => Some(containing_block.width * p / 100.0),
+            style::computed::SizeValue::Calc(calc) => calc.resolve(containing_block.width),
             _ => None,
         };
         let specified_height = match &style.height {
@@ -490,6 +536,9 @@ impl LayoutEngine {
             style::computed::SizeValue::Percentage(p) => {
                 containing_block.height.map(|h| h * p / 100.0)
             }
+            style::computed::SizeValue::Calc(calc) => {
+                containing_block.height.and_then(|h| calc.resolve(h))
+            }
             _ => None,
         };
 
@@ -549,4 +598,127 @@ mod tests {
         assert_eq!(engine.viewport_width, 800.0);
         assert_eq!(engine.viewport_height, 600.0);
     }
+
+    #[test]
+    fn test_shadow_tree_is_laid_out_in_place() {
+        use dom::element::{ElementData, ShadowRootMode, TagName};
+        use dom::ShadowRootInit;
+
+        let mut dom_tree = dom::tree::DomTree::new();
+        let doc = dom_tree.root().unwrap();
+        let body = dom_tree.create_element(ElementData::new(TagName::body()));
+        let host = dom_tree.create_element(ElementData::new(TagName::div()));
+        let light = dom_tree.create_element(ElementData::new(TagName::span()));
+        let unassigned = dom_tree.create_element(ElementData::new(TagName::new("i")));
+        dom_tree.get_element_mut(unassigned).unwrap().set_attribute("slot", "missing");
+        dom_tree.append_child(doc, body);
+        dom_tree.append_child(body, host);
+        dom_tree.append_child(host, light);
+        dom_tree.append_child(host, unassigned);
+
+        let shadow = dom_tree
+            .attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        let header = dom_tree.create_element(ElementData::new(TagName::p()));
+        let slot = dom_tree.create_element(ElementData::new(TagName::new("slot")));
+        dom_tree.append_child(shadow, header);
+        dom_tree.append_child(shadow, slot);
+
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        let mut document = Document::new(url::Url::parse("about:blank").unwrap());
+        document.tree = dom_tree;
+        style_resolver.resolve_document(&document);
+
+        let mut engine = LayoutEngine::new(800.0, 600.0);
+        let layout_tree = engine.layout(&document, &style_resolver);
+        let host_box = layout_tree.find_by_node(host).unwrap();
+        let children: Vec<_> = layout_tree
+            .children(host_box)
+            .filter_map(|child| layout_tree.get(child).and_then(|b| b.node))
+            .collect();
+        // The slot itself generates no box; its assigned span takes its place.
+        assert_eq!(children, vec![header, light]);
+        assert!(layout_tree.find_by_node(unassigned).is_none());
+    }
+
+    #[test]
+    fn test_caret_position_and_range_rects() {
+        use dom::element::{ElementData, TagName};
+        use dom::range::{BoundaryPoint, Range};
+
+        let mut document = Document::new(url::Url::parse("about:blank").unwrap());
+        let doc = document.tree.root().unwrap();
+        let html = document.tree.create_element(ElementData::new(TagName::new("html")));
+        let body = document.tree.create_element(ElementData::new(TagName::body()));
+        let p = document.tree.create_element(ElementData::new(TagName::p()));
+        let text = document.tree.create_text("Hello world".to_string());
+        document.tree.append_child(doc, html);
+        document.tree.append_child(html, body);
+        document.tree.append_child(body, p);
+        document.tree.append_child(p, text);
+
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        style_resolver.resolve_document(&document);
+        let mut engine = LayoutEngine::new(800.0, 600.0);
+        let layout_tree = engine.layout(&document, &style_resolver);
+
+        let text_box = layout_tree.find_by_node(text).unwrap();
+        let rect = layout_tree.get(text_box).unwrap().content_rect();
+        let middle = rect.y + rect.height / 2.0;
+        assert_eq!(
+            layout_tree.caret_position_from_point(rect.x - 20.0, middle),
+            Some(BoundaryPoint::new(text, 0))
+        );
+        assert_eq!(
+            layout_tree.caret_position_from_point(rect.x + rect.width + 20.0, middle),
+            Some(BoundaryPoint::new(text, 11))
+        );
+        // Hits on text still target the element for events.
+        assert_eq!(
+            layout_tree.hit_test(rect.x + 1.0, middle).and_then(|id| layout_tree.get(id)?.node),
+            Some(p)
+        );
+
+        let mut range = Range::new(text);
+        range.set_end(&document.tree, text, 5).unwrap();
+        let rects = layout_tree.range_rects(&document.tree, &range);
+        assert_eq!(rects.len(), 1);
+        assert_eq!(rects[0].x, rect.x);
+        assert!(rects[0].width > 0.0 && rects[0].width < rect.width);
+        assert_eq!(layout_tree.range_bounding_rect(&document.tree, &range), rects[0]);
+    }
+
+    #[test]
+    fn test_container_queries_restyle_after_layout() {
+        use dom::element::{ElementData, TagName};
+
+        let mut document = Document::new(url::Url::parse("about:blank").unwrap());
+        let doc = document.tree.root().unwrap();
+        let html = document.tree.create_element(ElementData::new(TagName::new("html")));
+        let body = document.tree.create_element(ElementData::new(TagName::body()));
+        let sidebar = document.tree.create_element(ElementData::new(TagName::div()));
+        let card = document.tree.create_element(ElementData::new(TagName::div()));
+        document.tree.append_child(doc, html);
+        document.tree.append_child(html, body);
+        document.tree.append_child(body, sidebar);
+        document.tree.append_child(sidebar, card);
+
+        let mut style_resolver = StyleResolver::new();
+        style_resolver.add_default_styles();
+        style_resolver.add_stylesheet(css_parser::parse_css(
+            "body > div { width: 300px; container-type: inline-size } \
+             div div { height: 10px; width: 50cqw } \
+             @container (max-width: 400px) { div div { height: 40px } }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+        style_resolver.resolve_document(&document);
+
+        let mut engine = LayoutEngine::new(800.0, 600.0);
+        let layout_tree = engine.layout_with_container_queries(&document, &mut style_resolver);
+        let card_box = layout_tree.get(layout_tree.find_by_node(card).unwrap()).unwrap();
+        assert_eq!(card_box.content_rect().height, 40.0);
+        assert_eq!(card_box.content_rect().width, 150.0);
+    }
 }
@@ -204,6 +204,7 @@ impl FlexFormattingContext {
         match flex_basis {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => self.main_size * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(self.main_size).unwrap_or(0.0),
             SizeValue::Auto => {
                 // Use content size
                 if self.is_row {
@@ -228,6 +229,7 @@ impl FlexFormattingContext {
             match v {
                 SizeValue::Length(l) => *l,
                 SizeValue::Percentage(p) => self.main_size * p / 100.0,
+                SizeValue::Calc(calc) => calc.resolve(self.main_size).unwrap_or(0.0),
                 SizeValue::Auto => 0.0,
                 _ => 0.0,
             }
@@ -283,6 +285,7 @@ impl FlexFormattingContext {
         match value {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => self.main_size * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(self.main_size).unwrap_or(0.0),
             SizeValue::Auto => 0.0,
             SizeValue::None => 0.0,
             _ => 0.0,
@@ -294,6 +297,7 @@ impl FlexFormattingContext {
         match value {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => self.main_size * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(self.main_size).unwrap_or(0.0),
             SizeValue::Auto => f32::INFINITY,
             SizeValue::None => f32::INFINITY,
             _ => f32::INFINITY,
@@ -505,9 +509,9 @@ impl FlexFormattingContext {
 
                 item.cross_size = match specified_cross {
                     SizeValue::Length(l) => *l,
-                    SizeValue::Percentage(p) => {
+                    value @ (SizeValue::Percentage(_) | SizeValue::Calc(_)) => {
                         if let Some(cs) = self.cross_size {
-                            cs * p / 100.0
+                            value.resolve(cs)
                         } else {
                             // Use intrinsic size
                             if self.is_row {
@@ -306,6 +306,7 @@ impl GridFormattingContext {
         match gap {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => available * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(available).unwrap_or(0.0),
             _ => 0.0,
         }
     }
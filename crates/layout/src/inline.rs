@@ -437,6 +437,7 @@ impl InlineFormattingContext {
         match &style.width {
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => containing_block.width * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(containing_block.width).unwrap_or(0.0),
             SizeValue::Auto => {
                 // Shrink-to-fit width
                 // For now, use a default
@@ -456,9 +457,9 @@ impl InlineFormattingContext {
 
         match &style.height {
             SizeValue::Length(l) => *l,
-            SizeValue::Percentage(p) => {
+            value @ (SizeValue::Percentage(_) | SizeValue::Calc(_)) => {
                 if let Some(h) = containing_block.height {
-                    h * p / 100.0
+                    value.resolve(h)
                 } else {
                     style.font_size * 1.2
                 }
@@ -80,8 +80,8 @@ impl LayoutBox {
     }
 
     /// Create text box.
-    pub fn text_box(id: LayoutBoxId, style: Arc<ComputedStyle>, text: TextRun) -> Self {
-        let mut layout_box = Self::new(id, None, BoxType::Text, style);
+    pub fn text_box(id: LayoutBoxId, node: Option<NodeId>, style: Arc<ComputedStyle>, text: TextRun) -> Self {
+        let mut layout_box = Self::new(id, node, BoxType::Text, style);
         layout_box.text = Some(text);
         layout_box
     }
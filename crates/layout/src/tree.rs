@@ -3,7 +3,10 @@
 use crate::box_model::BoxType;
 use crate::layout_box::{LayoutBox, LayoutBoxId};
 use crate::text::TextRun;
+use common::geometry::Rect;
 use dom::node::NodeId;
+use dom::range::{BoundaryPoint, Range};
+use dom::tree::DomTree;
 use slotmap::SlotMap;
 use smallvec::SmallVec;
 use std::sync::Arc;
@@ -49,11 +52,12 @@ impl LayoutTree {
     /// Create a text box.
     pub fn create_text_box(
         &mut self,
+        node: Option<NodeId>,
         style: Arc<ComputedStyle>,
         text: TextRun,
     ) -> LayoutBoxId {
         self.boxes
-            .insert_with_key(|id| LayoutBox::text_box(id, style, text))
+            .insert_with_key(|id| LayoutBox::text_box(id, node, style, text))
     }
 
     /// Create anonymous block box.
@@ -199,14 +203,86 @@ impl LayoutTree {
             }
         }
 
-        // Return this box if it has a DOM node
-        if layout_box.node.is_some() {
+        // Return this box if it has a DOM node; text hits go to the element
+        if layout_box.node.is_some() && layout_box.box_type != BoxType::Text {
             Some(box_id)
         } else {
             None
         }
     }
 
+    /// Find the text position nearest to a point, for placing a caret or
+    /// extending a selection while dragging.
+    ///
+    /// Points beside or between lines snap to the closest text box, with
+    /// vertical distance taking precedence.
+    pub fn caret_position_from_point(&self, x: f32, y: f32) -> Option<BoundaryPoint> {
+        let nearest = PreOrderIterator::new(self)
+            .filter_map(|id| self.boxes.get(id))
+            .filter_map(|b| Some((b.node?, b.text.as_ref()?, b.content_rect())))
+            .min_by(|a, b| {
+                let (da, db) = (distance_to(&a.2, x, y), distance_to(&b.2, x, y));
+                da.partial_cmp(&db).unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+        match nearest {
+            Some((node, text_run, rect)) => {
+                let offset = text_run
+                    .glyph_at_x(x - rect.x)
+                    .and_then(|i| text_run.glyphs.get(i))
+                    .map_or(text_run.text.len(), |glyph| glyph.cluster as usize);
+                Some(BoundaryPoint::new(node, offset))
+            }
+            None => self
+                .hit_test(x, y)
+                .and_then(|id| self.boxes.get(id)?.node)
+                .map(|node| BoundaryPoint::new(node, 0)),
+        }
+    }
+
+    /// The rects covered by a range: the selected part of each text box and
+    /// the border box of each fully selected replaced element.
+    pub fn range_rects(&self, dom: &DomTree, range: &Range) -> Vec<Rect> {
+        let (start, end) = (range.start(), range.end());
+        let mut rects = Vec::new();
+        for id in PreOrderIterator::new(self) {
+            let layout_box = match self.boxes.get(id) {
+                Some(b) => b,
+                None => continue,
+            };
+            let node = match layout_box.node {
+                Some(node) => node,
+                None => continue,
+            };
+
+            if let Some(text_run) = &layout_box.text {
+                if !range.intersects_node(dom, node) {
+                    continue;
+                }
+                let from = if node == start.node { start.offset } else { 0 };
+                let to = if node == end.node { end.offset } else { text_run.text.len() };
+                if from >= to {
+                    continue;
+                }
+                let content = layout_box.content_rect();
+                let x0 = x_of_offset(text_run, from);
+                let x1 = x_of_offset(text_run, to);
+                rects.push(Rect::new(content.x + x0, content.y, x1 - x0, content.height));
+            } else if layout_box.box_type == BoxType::Replaced && range.contains_node(dom, node) {
+                rects.push(layout_box.border_rect());
+            }
+        }
+        rects
+    }
+
+    /// Union of [`range_rects`](Self::range_rects) (`Range.getBoundingClientRect`).
+    pub fn range_bounding_rect(&self, dom: &DomTree, range: &Range) -> Rect {
+        self.range_rects(dom, range)
+            .into_iter()
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or(Rect::ZERO)
+    }
+
     /// Clear the tree.
     pub fn clear(&mut self) {
         self.boxes.clear();
@@ -220,6 +296,23 @@ impl Default for LayoutTree {
     }
 }
 
+/// Distance from a point to a rect, vertical first.
+fn distance_to(rect: &Rect, x: f32, y: f32) -> (f32, f32) {
+    let dx = (rect.x - x).max(x - (rect.x + rect.width)).max(0.0);
+    let dy = (rect.y - y).max(y - (rect.y + rect.height)).max(0.0);
+    (dy, dx)
+}
+
+/// X position of a byte offset within a text run.
+fn x_of_offset(text_run: &TextRun, offset: usize) -> f32 {
+    text_run
+        .glyphs
+        .iter()
+        .take_while(|glyph| (glyph.cluster as usize) < offset)
+        .map(|glyph| glyph.advance)
+        .sum()
+}
+
 /// Tree traversal order.
 pub struct PreOrderIterator<'a> {
     tree: &'a LayoutTree,
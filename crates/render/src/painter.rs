@@ -25,17 +25,29 @@ pub struct Painter {
     stacking_context_counter: usize,
     /// Current clip stack.
     clip_stack: Vec<ClipRegion>,
+    /// Selected text rects, painted behind the text.
+    selection: Vec<Rect>,
 }
 
+/// Background of selected text.
+pub const SELECTION_COLOR: Color = Color::rgba(51, 144, 255, 102);
+
 impl Painter {
     pub fn new() -> Self {
         Self {
             current_stacking_context: 0,
             stacking_context_counter: 0,
             clip_stack: Vec::new(),
+            selection: Vec::new(),
         }
     }
 
+    /// Set the selection highlight for the next paint, as computed by
+    /// [`LayoutTree::range_rects`].
+    pub fn set_selection(&mut self, rects: Vec<Rect>) {
+        self.selection = rects;
+    }
+
     /// Paint the layout tree and generate a display list.
     pub fn paint(&mut self, tree: &LayoutTree) -> DisplayList {
         let mut display_list = DisplayList::new();
@@ -540,6 +552,21 @@ impl Painter {
             },
         };
 
+        for rect in &self.selection {
+            if let Some(highlight) = rect.intersection(content_rect) {
+                display_list.push(
+                    DisplayItem::new(
+                        DisplayItemType::SolidColor(SolidColorItem {
+                            color: SELECTION_COLOR,
+                            radii: None,
+                        }),
+                        highlight,
+                    )
+                    .with_stacking_context(self.current_stacking_context),
+                );
+            }
+        }
+
         let item = DisplayItem::new(
             DisplayItemType::Text(TextItem {
                 text: text_run.text.clone(),
@@ -1,5 +1,7 @@
 //! CSS Cascade implementation.
 
+use crate::layers::UNLAYERED;
+pub use css_parser::shorthand::expand_shorthand;
 use css_parser::properties::{PropertyDeclaration, PropertyId};
 use css_parser::selector::Specificity;
 use std::cmp::Ordering;
@@ -52,6 +54,13 @@ pub struct CascadedDeclaration {
     pub specificity: Specificity,
     pub level: CascadeLevel,
     pub source_order: u32,
+    /// Whether the rule comes from a shadow tree the element is not in,
+    /// i.e. it matched through `:host` or `::slotted()`.
+    pub from_shadow: bool,
+    /// Whether the declaration comes from the element's style attribute.
+    pub inline: bool,
+    /// Position of the declaration's cascade layer, `UNLAYERED` if none.
+    pub layer: u32,
 }
 
 impl CascadedDeclaration {
@@ -69,6 +78,26 @@ impl CascadedDeclaration {
             declaration,
             specificity,
             source_order,
+            from_shadow: false,
+            inline: false,
+            layer: UNLAYERED,
+        }
+    }
+
+    /// Rank of the declaration's shadow-tree context: normal declarations
+    /// of the outer context win, important ones of the inner context.
+    fn context_rank(&self) -> bool {
+        self.from_shadow == self.level.important
+    }
+
+    /// Rank of the declaration's layer: later layers win for normal
+    /// declarations, earlier ones for important declarations, with
+    /// unlayered declarations last.
+    fn layer_rank(&self) -> u32 {
+        if self.level.important {
+            UNLAYERED - self.layer
+        } else {
+            self.layer
         }
     }
 }
@@ -83,6 +112,9 @@ impl Ord for CascadedDeclaration {
     fn cmp(&self, other: &Self) -> Ordering {
         self.level
             .cmp(&other.level)
+            .then_with(|| self.context_rank().cmp(&other.context_rank()))
+            .then_with(|| self.inline.cmp(&other.inline))
+            .then_with(|| self.layer_rank().cmp(&other.layer_rank()))
             .then_with(|| self.specificity.cmp(&other.specificity))
             .then_with(|| self.source_order.cmp(&other.source_order))
     }
@@ -91,6 +123,9 @@ impl Ord for CascadedDeclaration {
 impl PartialEq for CascadedDeclaration {
     fn eq(&self, other: &Self) -> bool {
         self.level == other.level
+            && self.from_shadow == other.from_shadow
+            && self.inline == other.inline
+            && self.layer == other.layer
             && self.specificity == other.specificity
             && self.source_order == other.source_order
     }
@@ -126,222 +161,6 @@ pub fn cascade_styles(
     result
 }
 
-/// Expand shorthand property into longhands.
-pub fn expand_shorthand(
-    property: &PropertyId,
-    value: &css_parser::values::CssValue,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    match property {
-        PropertyId::Margin => expand_four_sides(
-            value,
-            PropertyId::MarginTop,
-            PropertyId::MarginRight,
-            PropertyId::MarginBottom,
-            PropertyId::MarginLeft,
-        ),
-        PropertyId::Padding => expand_four_sides(
-            value,
-            PropertyId::PaddingTop,
-            PropertyId::PaddingRight,
-            PropertyId::PaddingBottom,
-            PropertyId::PaddingLeft,
-        ),
-        PropertyId::BorderWidth => expand_four_sides(
-            value,
-            PropertyId::BorderTopWidth,
-            PropertyId::BorderRightWidth,
-            PropertyId::BorderBottomWidth,
-            PropertyId::BorderLeftWidth,
-        ),
-        PropertyId::BorderStyle => expand_four_sides(
-            value,
-            PropertyId::BorderTopStyle,
-            PropertyId::BorderRightStyle,
-            PropertyId::BorderBottomStyle,
-            PropertyId::BorderLeftStyle,
-        ),
-        PropertyId::BorderColor => expand_four_sides(
-            value,
-            PropertyId::BorderTopColor,
-            PropertyId::BorderRightColor,
-            PropertyId::BorderBottomColor,
-            PropertyId::BorderLeftColor,
-        ),
-        PropertyId::BorderRadius => expand_four_corners(
-            value,
-            PropertyId::BorderTopLeftRadius,
-            PropertyId::BorderTopRightRadius,
-            PropertyId::BorderBottomRightRadius,
-            PropertyId::BorderBottomLeftRadius,
-        ),
-        PropertyId::Background => expand_background(value),
-        PropertyId::Font => expand_font(value),
-        PropertyId::Flex => expand_flex(value),
-        PropertyId::Gap => expand_gap(value),
-        _ => vec![(property.clone(), value.clone())],
-    }
-}
-
-fn expand_four_sides(
-    value: &css_parser::values::CssValue,
-    top: PropertyId,
-    right: PropertyId,
-    bottom: PropertyId,
-    left: PropertyId,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    let values = match value {
-        css_parser::values::CssValue::List(list) => list.clone(),
-        _ => vec![value.clone()],
-    };
-
-    let (t, r, b, l) = match values.len() {
-        1 => (
-            values[0].clone(),
-            values[0].clone(),
-            values[0].clone(),
-            values[0].clone(),
-        ),
-        2 => (
-            values[0].clone(),
-            values[1].clone(),
-            values[0].clone(),
-            values[1].clone(),
-        ),
-        3 => (
-            values[0].clone(),
-            values[1].clone(),
-            values[2].clone(),
-            values[1].clone(),
-        ),
-        _ => (
-            values[0].clone(),
-            values[1].clone(),
-            values[2].clone(),
-            values[3].clone(),
-        ),
-    };
-
-    vec![(top, t), (right, r), (bottom, b), (left, l)]
-}
-
-fn expand_four_corners(
-    value: &css_parser::values::CssValue,
-    top_left: PropertyId,
-    top_right: PropertyId,
-    bottom_right: PropertyId,
-    bottom_left: PropertyId,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    let values = match value {
-        css_parser::values::CssValue::List(list) => list.clone(),
-        _ => vec![value.clone()],
-    };
-
-    let (tl, tr, br, bl) = match values.len() {
-        1 => (
-            values[0].clone(),
-            values[0].clone(),
-            values[0].clone(),
-            values[0].clone(),
-        ),
-        2 => (
-            values[0].clone(),
-            values[1].clone(),
-            values[0].clone(),
-            values[1].clone(),
-        ),
-        3 => (
-            values[0].clone(),
-            values[1].clone(),
-            values[2].clone(),
-            values[1].clone(),
-        ),
-        _ => (
-            values[0].clone(),
-            values[1].clone(),
-            values[2].clone(),
-            values[3].clone(),
-        ),
-    };
-
-    vec![
-        (top_left, tl),
-        (top_right, tr),
-        (bottom_right, br),
-        (bottom_left, bl),
-    ]
-}
-
-fn expand_background(
-    value: &css_parser::values::CssValue,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    // Simplified - real implementation would parse all background components
-    vec![(PropertyId::BackgroundColor, value.clone())]
-}
-
-fn expand_font(
-    value: &css_parser::values::CssValue,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    // Simplified
-    vec![(PropertyId::FontFamily, value.clone())]
-}
-
-fn expand_flex(
-    value: &css_parser::values::CssValue,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    use css_parser::values::CssValue;
-
-    match value {
-        CssValue::Ident(s) if s == "none" => vec![
-            (PropertyId::FlexGrow, CssValue::Number(0.0)),
-            (PropertyId::FlexShrink, CssValue::Number(0.0)),
-            (PropertyId::FlexBasis, CssValue::Ident("auto".to_string())),
-        ],
-        CssValue::Ident(s) if s == "auto" => vec![
-            (PropertyId::FlexGrow, CssValue::Number(1.0)),
-            (PropertyId::FlexShrink, CssValue::Number(1.0)),
-            (PropertyId::FlexBasis, CssValue::Ident("auto".to_string())),
-        ],
-        CssValue::Number(n) => vec![
-            (PropertyId::FlexGrow, CssValue::Number(*n)),
-            (PropertyId::FlexShrink, CssValue::Number(1.0)),
-            (PropertyId::FlexBasis, CssValue::Number(0.0)),
-        ],
-        CssValue::List(list) => {
-            let mut result = vec![];
-            if let Some(grow) = list.first() {
-                result.push((PropertyId::FlexGrow, grow.clone()));
-            }
-            if let Some(shrink) = list.get(1) {
-                result.push((PropertyId::FlexShrink, shrink.clone()));
-            }
-            if let Some(basis) = list.get(2) {
-                result.push((PropertyId::FlexBasis, basis.clone()));
-            }
-            result
-        }
-        _ => vec![
-            (PropertyId::FlexGrow, CssValue::Number(0.0)),
-            (PropertyId::FlexShrink, CssValue::Number(1.0)),
-            (PropertyId::FlexBasis, CssValue::Ident("auto".to_string())),
-        ],
-    }
-}
-
-fn expand_gap(
-    value: &css_parser::values::CssValue,
-) -> Vec<(PropertyId, css_parser::values::CssValue)> {
-    match value {
-        css_parser::values::CssValue::List(list) if list.len() >= 2 => vec![
-            (PropertyId::RowGap, list[0].clone()),
-            (PropertyId::ColumnGap, list[1].clone()),
-        ],
-        _ => vec![
-            (PropertyId::RowGap, value.clone()),
-            (PropertyId::ColumnGap, value.clone()),
-        ],
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -365,6 +184,62 @@ mod tests {
         assert!(author_important > author);
     }
 
+    #[test]
+    fn test_shadow_context_order() {
+        use css_parser::values::CssValue;
+
+        let declaration = |important, from_shadow, specificity| {
+            let mut declaration = CascadedDeclaration::new(
+                PropertyDeclaration {
+                    property: PropertyId::Color,
+                    value: CssValue::Ident("red".to_string()),
+                    important,
+                },
+                Specificity::new(specificity, 0, 0),
+                Origin::Author,
+                0,
+            );
+            declaration.from_shadow = from_shadow;
+            declaration
+        };
+
+        // Normal rules of the outer tree win regardless of specificity,
+        // important rules of the shadow tree win over the outer ones.
+        assert!(declaration(false, false, 0) > declaration(false, true, 1));
+        assert!(declaration(true, true, 0) > declaration(true, false, 1));
+    }
+
+    #[test]
+    fn test_layer_order() {
+        use css_parser::values::CssValue;
+
+        let declaration = |important, layer, specificity| {
+            let mut declaration = CascadedDeclaration::new(
+                PropertyDeclaration {
+                    property: PropertyId::Color,
+                    value: CssValue::Ident("red".to_string()),
+                    important,
+                },
+                Specificity::new(specificity, 0, 0),
+                Origin::Author,
+                0,
+            );
+            declaration.layer = layer;
+            declaration
+        };
+
+        // Later layers win regardless of specificity and unlayered styles
+        // win over all layers, in reverse for important declarations.
+        assert!(declaration(false, 1, 0) > declaration(false, 0, 1));
+        assert!(declaration(false, UNLAYERED, 0) > declaration(false, 1, 1));
+        assert!(declaration(true, 0, 0) > declaration(true, 1, 1));
+        assert!(declaration(true, 1, 0) > declaration(true, UNLAYERED, 1));
+
+        let mut inline = declaration(true, UNLAYERED, 0);
+        inline.inline = true;
+        assert!(inline > declaration(true, 0, 1));
+    }
+
     #[test]
     fn test_expand_margin() {
         use css_parser::values::CssValue;
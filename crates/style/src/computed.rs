@@ -3,12 +3,13 @@
 use common::color::Color;
 use common::geometry::EdgeSizes;
 use common::units::{Length, LengthContext, LengthPercentage};
+use css_parser::calc::CalcNode;
 use css_parser::properties::PropertyId;
 use css_parser::values::CssValue;
 use std::collections::HashMap;
 
 /// Computed style for an element.
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct ComputedStyle {
     /// Display type.
     pub display: Display,
@@ -137,6 +138,14 @@ pub struct ComputedStyle {
     pub backdrop_filter: Vec<Filter>,
     /// Mix blend mode.
     pub mix_blend_mode: BlendMode,
+
+    /// Container queries.
+    pub container_type: ContainerType,
+    pub container_name: Vec<String>,
+
+    /// Custom properties (`--*`) with their substituted values. Missing
+    /// ones have the guaranteed-invalid value.
+    pub custom_properties: HashMap<String, String>,
 }
 
 impl ComputedStyle {
@@ -226,9 +235,17 @@ impl ComputedStyle {
             filter: Vec::new(),
             backdrop_filter: Vec::new(),
             mix_blend_mode: BlendMode::Normal,
+            container_type: ContainerType::Normal,
+            container_name: Vec::new(),
+            custom_properties: HashMap::new(),
         }
     }
 
+    /// Get the computed value of a custom property.
+    pub fn custom_property(&self, name: &str) -> Option<&str> {
+        self.custom_properties.get(name).map(String::as_str)
+    }
+
     /// Check if element creates a new stacking context.
     pub fn creates_stacking_context(&self) -> bool {
         self.position == Position::Fixed
@@ -348,6 +365,8 @@ pub enum SizeValue {
     MaxContent,
     FitContent,
     None, // For max-width/height
+    /// Math function mixing lengths and percentages, resolved at used time.
+    Calc(Box<CalcNode>),
 }
 
 impl SizeValue {
@@ -356,6 +375,7 @@ impl SizeValue {
             SizeValue::Auto | SizeValue::None => 0.0,
             SizeValue::Length(l) => *l,
             SizeValue::Percentage(p) => containing * p / 100.0,
+            SizeValue::Calc(calc) => calc.resolve(containing).unwrap_or(0.0),
             SizeValue::MinContent | SizeValue::MaxContent | SizeValue::FitContent => 0.0,
         }
     }
@@ -938,3 +958,15 @@ pub enum BlendMode {
     Color,
     Luminosity,
 }
+
+/// Container type.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ContainerType {
+    /// Not a query container for size queries.
+    #[default]
+    Normal,
+    /// Size container in both axes.
+    Size,
+    /// Size container in the inline axis.
+    InlineSize,
+}
@@ -0,0 +1,174 @@
+//! Query containers for `@container` rules and container units.
+//!
+//! Whether an element is a query container comes from its computed style,
+//! but its size comes from layout: the embedder lays the document out,
+//! reports container sizes back, and restyles the descendants of the
+//! containers whose size changed. Until a size is known, queries against
+//! the container are unknown and do not match.
+
+use crate::computed::{ComputedStyle, ContainerType};
+use css_parser::container::ContainerSize;
+use dom::node::NodeId;
+use dom::tree::DomTree;
+use std::collections::HashMap;
+
+/// A query container, as recorded when its style was computed.
+#[derive(Clone, Debug)]
+struct QueryContainer {
+    container_type: ContainerType,
+    names: Vec<String>,
+    font_size: f32,
+}
+
+/// The query containers of a document and their sizes.
+#[derive(Clone, Debug, Default)]
+pub struct ContainerQueries {
+    containers: HashMap<NodeId, QueryContainer>,
+    /// Content-box sizes computed by layout.
+    sizes: HashMap<NodeId, (f32, f32)>,
+}
+
+impl ContainerQueries {
+    /// Record whether `node` is a query container after computing its style.
+    pub fn update(&mut self, node: NodeId, style: &ComputedStyle) {
+        if style.container_type == ContainerType::Normal {
+            self.containers.remove(&node);
+            self.sizes.remove(&node);
+        } else {
+            self.containers.insert(
+                node,
+                QueryContainer {
+                    container_type: style.container_type,
+                    names: style.container_name.clone(),
+                    font_size: style.font_size,
+                },
+            );
+        }
+    }
+
+    /// Elements that are query containers.
+    pub fn containers(&self) -> Vec<NodeId> {
+        self.containers.keys().copied().collect()
+    }
+
+    /// Set the content-box size layout computed for a query container.
+    /// Returns whether it changed.
+    pub fn set_size(&mut self, node: NodeId, width: f32, height: f32) -> bool {
+        if !self.containers.contains_key(&node) {
+            return false;
+        }
+        self.sizes.insert(node, (width, height)) != Some((width, height))
+    }
+
+    /// The size of the query container for a query of `node`: its nearest
+    /// flat tree ancestor named `name` that can answer block-axis queries if
+    /// `block_axis` is set. `None` if there is none, or if layout has not
+    /// sized it yet.
+    pub fn query(
+        &self,
+        tree: &DomTree,
+        node: NodeId,
+        name: Option<&str>,
+        block_axis: bool,
+    ) -> Option<ContainerSize> {
+        let (id, container) = self.ancestors(tree, node).find(|(_, container)| {
+            name.map_or(true, |name| container.names.iter().any(|n| n == name))
+                && (!block_axis || container.container_type == ContainerType::Size)
+        })?;
+        let &(width, height) = self.sizes.get(&id)?;
+        Some(ContainerSize {
+            width: Some(width),
+            height: (container.container_type == ContainerType::Size).then_some(height),
+            font_size: container.font_size,
+        })
+    }
+
+    /// The sizes `cq*` units of `node` resolve against: the width of the
+    /// nearest sized container and the height of the nearest sized `size`
+    /// container, each falling back to the viewport.
+    pub fn unit_basis(&self, tree: &DomTree, node: NodeId, viewport: (f32, f32)) -> (f32, f32) {
+        let mut width = None;
+        let mut height = None;
+        for (id, container) in self.ancestors(tree, node) {
+            let Some(&(w, h)) = self.sizes.get(&id) else {
+                continue;
+            };
+            width = width.or(Some(w));
+            if container.container_type == ContainerType::Size {
+                height = height.or(Some(h));
+            }
+            if width.is_some() && height.is_some() {
+                break;
+            }
+        }
+        (width.unwrap_or(viewport.0), height.unwrap_or(viewport.1))
+    }
+
+    fn ancestors<'a>(
+        &'a self,
+        tree: &'a DomTree,
+        node: NodeId,
+    ) -> impl Iterator<Item = (NodeId, &'a QueryContainer)> + 'a {
+        std::iter::successors(tree.flat_parent(node), move |&id| tree.flat_parent(id))
+            .filter_map(move |id| Some((id, self.containers.get(&id)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::element::{ElementData, TagName};
+
+    #[test]
+    fn test_nearest_matching_container() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let outer = tree.create_element(ElementData::new(TagName::div()));
+        let inner = tree.create_element(ElementData::new(TagName::div()));
+        let leaf = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(root, outer);
+        tree.append_child(outer, inner);
+        tree.append_child(inner, leaf);
+
+        let mut queries = ContainerQueries::default();
+        let mut style = ComputedStyle::default_style();
+        style.container_type = ContainerType::Size;
+        style.container_name = vec!["page".to_string()];
+        queries.update(outer, &style);
+        style.container_type = ContainerType::InlineSize;
+        style.container_name = Vec::new();
+        queries.update(inner, &style);
+
+        // Unsized containers cannot answer queries.
+        assert_eq!(queries.query(&tree, leaf, None, false), None);
+        assert!(queries.set_size(outer, 800.0, 600.0));
+        assert!(queries.set_size(inner, 300.0, 50.0));
+        assert!(!queries.set_size(inner, 300.0, 50.0));
+        assert!(!queries.set_size(leaf, 10.0, 10.0));
+
+        assert_eq!(
+            queries.query(&tree, leaf, None, false).unwrap().width,
+            Some(300.0)
+        );
+        assert_eq!(
+            queries.query(&tree, leaf, None, true).unwrap().height,
+            Some(600.0)
+        );
+        assert_eq!(
+            queries
+                .query(&tree, leaf, Some("page"), false)
+                .unwrap()
+                .width,
+            Some(800.0)
+        );
+        assert_eq!(queries.query(&tree, leaf, Some("card"), false), None);
+        assert_eq!(
+            queries.unit_basis(&tree, leaf, (1024.0, 768.0)),
+            (300.0, 600.0)
+        );
+        assert_eq!(
+            queries.unit_basis(&tree, outer, (1024.0, 768.0)),
+            (1024.0, 768.0)
+        );
+    }
+}
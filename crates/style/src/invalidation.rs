@@ -0,0 +1,231 @@
+//! Style and layout invalidation driven by DOM mutations.
+
+use dom::mutation::{Mutation, MutationHook, MutationKind};
+use dom::node::NodeId;
+use dom::tree::DomTree;
+use parking_lot::Mutex;
+
+/// Work left behind by DOM mutations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Invalidation {
+    /// Roots of the subtrees whose styles must be recomputed.
+    pub restyle: Vec<NodeId>,
+    /// Whether layout must run again.
+    pub needs_layout: bool,
+}
+
+impl Invalidation {
+    /// Whether nothing needs to be redone.
+    pub fn is_empty(&self) -> bool {
+        self.restyle.is_empty() && !self.needs_layout
+    }
+
+    /// Add a subtree to restyle, merging it with overlapping roots. A
+    /// shadow root is restyled from its host.
+    fn add_root(&mut self, tree: &DomTree, node: NodeId) {
+        let node = tree.shadow_host(node).unwrap_or(node);
+        if self
+            .restyle
+            .iter()
+            .any(|&root| tree.is_shadow_including_inclusive_ancestor(root, node))
+        {
+            return;
+        }
+        self.restyle
+            .retain(|&root| !tree.is_shadow_including_inclusive_ancestor(node, root));
+        self.restyle.push(node);
+    }
+}
+
+/// Part of the tree to restyle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Restyle {
+    /// An element, with its descendants only if its style changes.
+    Element(NodeId),
+    /// A subtree.
+    Subtree(NodeId),
+}
+
+impl Restyle {
+    /// The element or the root of the subtree.
+    pub fn node(self) -> NodeId {
+        match self {
+            Restyle::Element(node) | Restyle::Subtree(node) => node,
+        }
+    }
+}
+
+/// Mutation hook that collects style and layout invalidations.
+///
+/// Register it with [`DomTree::add_mutation_hook`] and apply what it
+/// collected with [`StyleResolver::apply_invalidation`](crate::StyleResolver::apply_invalidation)
+/// before the next layout.
+#[derive(Debug, Default)]
+pub struct StyleInvalidator {
+    pending: Mutex<Invalidation>,
+}
+
+impl StyleInvalidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the invalidations collected so far.
+    pub fn take(&self) -> Invalidation {
+        std::mem::take(&mut *self.pending.lock())
+    }
+}
+
+impl MutationHook for StyleInvalidator {
+    fn mutated(&self, tree: &DomTree, mutation: &Mutation) {
+        let mut pending = self.pending.lock();
+        match mutation.kind {
+            // Structural pseudo-classes and sibling combinators of the other
+            // children may match differently now.
+            MutationKind::ChildList => pending.add_root(tree, mutation.target),
+            // Following siblings can depend on the element through `+` and `~`.
+            MutationKind::Attributes => {
+                let root = match tree.parent(mutation.target) {
+                    Some(parent) if tree.next_sibling(mutation.target).is_some() => parent,
+                    _ => mutation.target,
+                };
+                pending.add_root(tree, root);
+            }
+            // Text does not affect selector matching.
+            MutationKind::CharacterData => {}
+        }
+        pending.needs_layout = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::computed::ZIndex;
+    use crate::StyleResolver;
+    use dom::element::{ElementData, TagName};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_mutations_invalidate_styles() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let body = tree.create_element(ElementData::new(TagName::body()));
+        let first = tree.create_element(ElementData::new(TagName::p()));
+        let last = tree.create_element(ElementData::new(TagName::p()));
+        let text = tree.create_text("hi".to_string());
+        tree.append_child(root, body);
+        tree.append_child(body, first);
+        tree.append_child(body, last);
+        tree.append_child(last, text);
+
+        let invalidator = Arc::new(StyleInvalidator::new());
+        tree.add_mutation_hook(invalidator.clone());
+
+        tree.set_text_content(text, "hello");
+        assert_eq!(
+            invalidator.take(),
+            Invalidation {
+                restyle: vec![],
+                needs_layout: true
+            }
+        );
+
+        tree.get_element_mut(last)
+            .unwrap()
+            .set_attribute("class", "x");
+        tree.get_element_mut(first)
+            .unwrap()
+            .set_attribute("class", "y");
+        tree.flush_mutations();
+        assert_eq!(invalidator.take().restyle, vec![body]);
+
+        let mut resolver = StyleResolver::new();
+        tree.remove(last);
+        let invalidation = invalidator.take();
+        assert_eq!(invalidation.restyle, vec![body]);
+        assert!(resolver.apply_invalidation(&tree, &invalidation));
+        assert!(resolver.has_style(body) && resolver.has_style(first));
+        assert!(!resolver.has_style(last));
+        assert!(invalidator.take().is_empty());
+    }
+
+    #[test]
+    fn test_has_restyles_anchor() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("class", "card");
+        let card = tree.create_element(data);
+        let body = tree.create_element(ElementData::new(TagName::p()));
+        let img = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(root, card);
+        tree.append_child(card, body);
+        tree.append_child(body, img);
+
+        let mut resolver = StyleResolver::new();
+        resolver.add_stylesheet(css_parser::parse_css(
+            ".card:has(.hero) { opacity: 0.5 }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+        for node in [card, body, img] {
+            resolver.restyle_element(&tree, node);
+        }
+        assert_eq!(resolver.get_style(card).unwrap().opacity, 1.0);
+
+        let invalidator = Arc::new(StyleInvalidator::new());
+        tree.add_mutation_hook(invalidator.clone());
+        tree.get_element_mut(img)
+            .unwrap()
+            .set_attribute("class", "hero");
+        tree.flush_mutations();
+        let invalidation = invalidator.take();
+        assert_eq!(invalidation.restyle, vec![img]);
+        resolver.apply_invalidation(&tree, &invalidation);
+        assert_eq!(resolver.get_style(card).unwrap().opacity, 0.5);
+    }
+
+    #[test]
+    fn test_has_restyles_reachable_anchors_only() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let page = tree.create_element(ElementData::new(TagName::div()));
+        let card = tree.create_element(ElementData::new(TagName::div()));
+        let img = tree.create_element(ElementData::new(TagName::span()));
+        let other = tree.create_element(ElementData::new(TagName::div()));
+        let leaf = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(root, page);
+        tree.append_child(page, card);
+        tree.append_child(card, img);
+        tree.append_child(root, other);
+        tree.append_child(other, leaf);
+
+        let mut resolver = StyleResolver::new();
+        resolver.add_stylesheet(css_parser::parse_css(
+            ":has(.hero) { opacity: 0.5 } :has(> .hero) { z-index: 2 }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+        for node in [page, card, img, other, leaf] {
+            resolver.restyle_element(&tree, node);
+        }
+        let unrelated = [other, leaf].map(|node| resolver.get_style(node).unwrap());
+
+        let invalidator = Arc::new(StyleInvalidator::new());
+        tree.add_mutation_hook(invalidator.clone());
+        tree.get_element_mut(img)
+            .unwrap()
+            .set_attribute("class", "hero");
+        tree.flush_mutations();
+        resolver.apply_invalidation(&tree, &invalidator.take());
+
+        assert_eq!(resolver.get_style(page).unwrap().opacity, 0.5);
+        assert_eq!(resolver.get_style(card).unwrap().opacity, 0.5);
+        assert_eq!(resolver.get_style(img).unwrap().opacity, 1.0);
+        assert_eq!(resolver.get_style(card).unwrap().z_index, ZIndex::Number(2));
+        assert_eq!(resolver.get_style(page).unwrap().z_index, ZIndex::Auto);
+        // A universal `:has()` compound does not restyle other subtrees.
+        for (node, style) in [other, leaf].into_iter().zip(unrelated) {
+            assert!(Arc::ptr_eq(&resolver.get_style(node).unwrap(), &style));
+        }
+    }
+}
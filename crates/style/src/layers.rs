@@ -0,0 +1,130 @@
+//! Cascade layers.
+
+use css_parser::media::MediaContext;
+use css_parser::stylesheet::{CssRule, Stylesheet};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Layer position of unlayered declarations, which come after every layer.
+pub const UNLAYERED: u32 = u32::MAX;
+
+/// The order of the layers of one cascade context: an origin, or the
+/// author styles of a shadow tree.
+#[derive(Clone, Debug, Default)]
+pub struct LayerOrder {
+    /// Sublayers of each layer, by full name, in declaration order.
+    children: HashMap<Vec<String>, Vec<String>>,
+    /// Position of each layer; a layer comes after its sublayers.
+    positions: HashMap<Vec<String>, u32>,
+}
+
+impl LayerOrder {
+    /// Collect the layers declared by `sheets`, skipping conditional rules
+    /// that do not apply.
+    pub fn new(sheets: &[Arc<Stylesheet>], media: &MediaContext) -> Self {
+        let mut order = Self::default();
+        for sheet in sheets {
+            order.collect(&sheet.rules, &[], media);
+        }
+        let mut next = 0;
+        order.assign(&mut Vec::new(), &mut next);
+        order
+    }
+
+    /// Position of the layer with the full name `path`; the empty path is
+    /// unlayered.
+    pub fn position(&self, path: &[String]) -> u32 {
+        self.positions.get(path).copied().unwrap_or(UNLAYERED)
+    }
+
+    /// Declare the layer `name` inside `parent` and return its full name.
+    fn declare(&mut self, parent: &[String], name: &[String]) -> Vec<String> {
+        let mut path = parent.to_vec();
+        for segment in name {
+            let children = self.children.entry(path.clone()).or_default();
+            if !children.contains(segment) {
+                children.push(segment.clone());
+            }
+            path.push(segment.clone());
+        }
+        path
+    }
+
+    fn collect(&mut self, rules: &[CssRule], parent: &[String], media: &MediaContext) {
+        for rule in rules {
+            match rule {
+                CssRule::LayerStatement(statement) => {
+                    for name in &statement.names {
+                        self.declare(parent, &name.0);
+                    }
+                }
+                CssRule::Style(style) => {
+                    self.collect(&style.rules, parent, media);
+                }
+                CssRule::LayerBlock(block) => {
+                    let path = self.declare(parent, &block.name.0);
+                    self.collect(&block.rules, &path, media);
+                }
+                CssRule::Import(import) if import.matches(media) => {
+                    let path = match &import.layer {
+                        Some(layer) => self.declare(parent, &layer.0),
+                        None => parent.to_vec(),
+                    };
+                    if let Some(sheet) = &import.stylesheet {
+                        self.collect(&sheet.rules, &path, media);
+                    }
+                }
+                CssRule::Media(media_rule) if media_rule.matches(media) => {
+                    self.collect(&media_rule.rules, parent, media);
+                }
+                CssRule::Supports(supports) if supports.matches() => {
+                    self.collect(&supports.rules, parent, media);
+                }
+                // Container conditions depend on layout, so the layers they
+                // declare always count.
+                CssRule::Container(container) => {
+                    self.collect(&container.rules, parent, media);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn assign(&mut self, path: &mut Vec<String>, next: &mut u32) {
+        let children = self
+            .children
+            .get(path.as_slice())
+            .cloned()
+            .unwrap_or_default();
+        for child in children {
+            path.push(child);
+            self.assign(path, next);
+            self.positions.insert(path.clone(), *next);
+            *next += 1;
+            path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_order() {
+        let sheet = css_parser::parse_css(
+            "@layer reset, theme; @layer theme.dark { } @layer reset { @layer inner { } } @layer { }",
+            url::Url::parse("about:blank").unwrap(),
+        );
+        let order = LayerOrder::new(&[Arc::new(sheet)], &MediaContext::default());
+        let position =
+            |path: &[&str]| order.position(&path.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        // Sublayers come before their parent's own rules.
+        assert!(position(&["reset", "inner"]) < position(&["reset"]));
+        assert!(position(&["reset"]) < position(&["theme", "dark"]));
+        assert!(position(&["theme", "dark"]) < position(&["theme"]));
+        assert_eq!(order.positions.len(), 5);
+        assert_eq!(position(&[]), UNLAYERED);
+    }
+}
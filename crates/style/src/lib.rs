@@ -8,9 +8,16 @@ pub mod matching;
 pub mod stylist;
 pub mod inheritance;
 pub mod resolver;
+pub mod query;
+pub mod invalidation;
+pub mod variables;
+pub mod layers;
+pub mod container;
 
 pub use cascade::{cascade_styles, Origin, CascadeLevel};
 pub use computed::ComputedStyle;
 pub use matching::match_selectors;
 pub use stylist::Stylist;
 pub use resolver::StyleResolver;
+pub use query::{QueryError, SelectorQuery};
+pub use invalidation::{Invalidation, StyleInvalidator};
@@ -1,10 +1,15 @@
 //! Selector matching.
 
-use css_parser::selector::{AttributeSelector, CaseSensitivity, Combinator, Selector, SelectorList};
+use css_parser::selector::{
+    parse_nth, parse_selector_list, AttributeSelector, CaseSensitivity, Combinator, Selector,
+    SelectorList, PSEUDO_CLASSES,
+};
 use css_parser::stylesheet::StyleRule;
-use dom::element::ElementData;
-use dom::node::{Node, NodeId};
+use dom::element::{CustomElementState, ElementData, ElementFlags};
+use dom::forms;
+use dom::node::{Node, NodeId, NodeType};
 use dom::tree::DomTree;
+use smallvec::SmallVec;
 
 /// Match result with specificity.
 #[derive(Clone, Debug)]
@@ -35,23 +40,364 @@ pub fn match_selector(
     tree: &DomTree,
     node_id: NodeId,
 ) -> bool {
-    // First check the simple selector parts
-    if !match_simple_selector(selector, element) {
-        return false;
+    match_selector_in_scope(selector, element, tree, node_id, None)
+}
+
+/// Match a selector with `:scope` referring to `scope`, or to the root
+/// element when `None`.
+///
+/// Compounds chain left to right, so the last one is the subject and the
+/// combinators are walked right to left from there.
+pub fn match_selector_in_scope(
+    selector: &Selector,
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+) -> bool {
+    match_in_context(selector, element, tree, node_id, scope, None)
+}
+
+/// Match a selector list from the shadow tree of `host`, where `:host`
+/// matches the host and `::slotted()` the elements assigned to its slots.
+pub fn match_shadow_selectors(
+    selector_list: &SelectorList,
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    host: NodeId,
+) -> Option<css_parser::selector::Specificity> {
+    selector_list
+        .selectors
+        .iter()
+        .filter(|s| match_in_context(s, element, tree, node_id, None, Some(host)))
+        .map(|s| s.specificity())
+        .max()
+}
+
+/// Match a selector, from the shadow tree of `host` if given.
+fn match_in_context(
+    selector: &Selector,
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+    host: Option<NodeId>,
+) -> bool {
+    let mut chain: SmallVec<[&Selector; 4]> = SmallVec::new();
+    let mut current = Some(selector);
+    while let Some(compound) = current {
+        chain.push(compound);
+        current = compound.next.as_deref();
     }
+    match_chain(&chain, element, tree, node_id, scope, host)
+}
 
-    // Then check combinator chain
-    match (&selector.combinator, &selector.next) {
-        (None, _) => true,
-        (Some(combinator), Some(next)) => {
-            match_with_combinator(combinator, next.as_ref(), tree, node_id)
+/// Match the last compound of `chain` against the element, then the rest
+/// against its relatives.
+fn match_chain(
+    chain: &[&Selector],
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+    host: Option<NodeId>,
+) -> bool {
+    let (subject, rest) = match chain.split_last() {
+        Some(split) => split,
+        None => return true,
+    };
+
+    // `::slotted(x)` matches an element assigned to a slot of the host by
+    // `x`, with the compound itself and the rest matching the slot.
+    if let Some(argument) = slotted_argument(subject) {
+        let slot = match (host, tree.assigned_slot(node_id)) {
+            (Some(host), Some(slot)) if tree.parent(node_id) == Some(host) => slot,
+            _ => return false,
+        };
+        return parse_selector_list(argument)
+            .is_ok_and(|list| match_compound_list(&list, element, tree, node_id, scope))
+            && tree.get_element(slot).is_some_and(|slot_element| {
+                match_compound(subject, slot_element, tree, slot, scope, host)
+                    && match_relatives(rest, tree, slot, scope, host)
+            });
+    }
+
+    match_compound(subject, element, tree, node_id, scope, host)
+        && match_relatives(rest, tree, node_id, scope, host)
+}
+
+/// Match the compounds of `rest` against the relatives of `node_id`, as
+/// joined by the combinator of the last one.
+fn match_relatives(
+    rest: &[&Selector],
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+    host: Option<NodeId>,
+) -> bool {
+    let previous = match rest.last() {
+        Some(previous) => previous,
+        None => return true,
+    };
+
+    let matches_rest = |id: NodeId| {
+        tree.get_element(id)
+            .map(|elem| match_chain(rest, elem, tree, id, scope, host))
+            .unwrap_or(false)
+    };
+    // Inside a shadow tree the host stands above the shadow root.
+    let parent = |id: NodeId| {
+        let parent = tree.parent(id)?;
+        match tree.shadow_host(parent) {
+            Some(parent_host) if Some(parent_host) == host => Some(parent_host),
+            _ => Some(parent),
+        }
+    };
+    match previous.combinator.unwrap_or(Combinator::Descendant) {
+        Combinator::Descendant => {
+            let mut current = parent(node_id);
+            while let Some(ancestor) = current {
+                if matches_rest(ancestor) {
+                    return true;
+                }
+                current = parent(ancestor);
+            }
+            false
+        }
+        Combinator::Child => parent(node_id).map(matches_rest).unwrap_or(false),
+        Combinator::NextSibling => previous_element_sibling(tree, node_id)
+            .map(matches_rest)
+            .unwrap_or(false),
+        Combinator::SubsequentSibling => {
+            let mut current = previous_element_sibling(tree, node_id);
+            while let Some(sibling) = current {
+                if matches_rest(sibling) {
+                    return true;
+                }
+                current = previous_element_sibling(tree, sibling);
+            }
+            false
+        }
+    }
+}
+
+/// Match the compounds of a relative selector left to right, starting
+/// from the elements related to `anchor` by `combinator`. The search only
+/// covers the anchor's subtree and following siblings, and stops at the
+/// first match; subtrees already searched by a descendant combinator are
+/// not searched again.
+fn match_relative(
+    chain: &[&Selector],
+    combinator: Combinator,
+    tree: &DomTree,
+    anchor: NodeId,
+    scope: Option<NodeId>,
+    host: Option<NodeId>,
+) -> bool {
+    let (first, rest) = match chain.split_first() {
+        Some(split) => split,
+        None => return true,
+    };
+    let next = first.combinator.unwrap_or(Combinator::Descendant);
+    let matches_first = |id: NodeId| {
+        tree.get_element(id)
+            .is_some_and(|elem| match_compound(first, elem, tree, id, scope, host))
+    };
+    let matches = |id: NodeId| {
+        matches_first(id) && match_relative(rest, next, tree, id, scope, host)
+    };
+    match combinator {
+        Combinator::Descendant => {
+            let mut stack: SmallVec<[NodeId; 16]> = tree.children(anchor).collect();
+            while let Some(id) = stack.pop() {
+                if matches_first(id) {
+                    if match_relative(rest, next, tree, id, scope, host) {
+                        return true;
+                    }
+                    if !rest.is_empty() && next == Combinator::Descendant {
+                        continue;
+                    }
+                }
+                stack.extend(tree.children(id));
+            }
+            false
+        }
+        Combinator::Child => tree.children(anchor).any(matches),
+        Combinator::NextSibling => next_element_sibling(tree, anchor).is_some_and(matches),
+        Combinator::SubsequentSibling => {
+            std::iter::successors(next_element_sibling(tree, anchor), |&id| {
+                next_element_sibling(tree, id)
+            })
+            .any(matches)
+        }
+    }
+}
+
+fn next_element_sibling(tree: &DomTree, node_id: NodeId) -> Option<NodeId> {
+    std::iter::successors(tree.next_sibling(node_id), |&id| tree.next_sibling(id))
+        .find(|&id| tree.get_element(id).is_some())
+}
+
+/// Argument of the `::slotted()` pseudo-element of a compound.
+fn slotted_argument(selector: &Selector) -> Option<&str> {
+    selector
+        .pseudo_elements
+        .iter()
+        .find_map(|pseudo| pseudo.strip_prefix("slotted(")?.strip_suffix(')'))
+}
+
+pub(crate) fn previous_element_sibling(tree: &DomTree, node_id: NodeId) -> Option<NodeId> {
+    let mut current = tree.prev_sibling(node_id);
+    while let Some(sibling) = current {
+        if tree.get_element(sibling).is_some() {
+            return Some(sibling);
+        }
+        current = tree.prev_sibling(sibling);
+    }
+    None
+}
+
+/// Whether `node_id` is the `:scope` element: `scope`, or the root element
+/// if there is none.
+fn is_scope(tree: &DomTree, node_id: NodeId, scope: Option<NodeId>) -> bool {
+    match scope {
+        Some(scope) => node_id == scope,
+        None => is_root_element(tree, node_id),
+    }
+}
+
+/// Match one compound selector, pseudo-classes included.
+fn match_compound(
+    selector: &Selector,
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+    host: Option<NodeId>,
+) -> bool {
+    // Seen from its shadow tree, the host is featureless: only `:host`
+    // and `:host-context()` match it.
+    let is_host = host == Some(node_id);
+    if is_host {
+        let names_host = selector
+            .pseudo_classes
+            .iter()
+            .any(|pseudo| matches!(pseudo.name.as_str(), "host" | "host-context"));
+        if !names_host {
+            return false;
         }
-        _ => true,
+    } else if !match_simple_selector(selector, element) {
+        return false;
+    }
+    // Outside a nested rule, `&` behaves like `:scope`.
+    if selector.nesting && !is_scope(tree, node_id, scope) {
+        return false;
     }
+
+    // Sibling positions are only computed if a pseudo-class needs them.
+    let mut context: Option<MatchContext> = None;
+    selector.pseudo_classes.iter().all(|pseudo| match pseudo.name.as_str() {
+        "not" => pseudo.selectors().is_some_and(|list| {
+            !list
+                .selectors
+                .iter()
+                .any(|s| match_in_context(s, element, tree, node_id, scope, host))
+        }),
+        "is" | "where" => pseudo.selectors().is_some_and(|list| {
+            list.selectors
+                .iter()
+                .any(|s| match_in_context(s, element, tree, node_id, scope, host))
+        }),
+        "has" => pseudo.relative_selectors().is_some_and(|list| {
+            list.iter().any(|relative| {
+                let mut chain: SmallVec<[&Selector; 4]> = SmallVec::new();
+                let mut current = Some(&relative.selector);
+                while let Some(compound) = current {
+                    chain.push(compound);
+                    current = compound.next.as_deref();
+                }
+                match_relative(&chain, relative.combinator, tree, node_id, scope, host)
+            })
+        }),
+        "host" => {
+            is_host
+                && (pseudo.args.is_none()
+                    || pseudo
+                        .selectors()
+                        .is_some_and(|list| match_compound_list(list, element, tree, node_id, scope)))
+        }
+        "host-context" => {
+            is_host
+                && pseudo.selectors().is_some_and(|list| {
+                    let mut current = Some(node_id);
+                    while let Some(id) = current {
+                        let matches = tree
+                            .get_element(id)
+                            .is_some_and(|e| match_compound_list(list, e, tree, id, scope));
+                        if matches {
+                            return true;
+                        }
+                        current = tree.parent(id).or_else(|| tree.shadow_host(id));
+                    }
+                    false
+                })
+        }
+        "scope" => is_scope(tree, node_id, scope),
+        "root" => is_root_element(tree, node_id),
+        "link" | "any-link" => {
+            matches!(element.tag_name.as_str(), "a" | "area") && element.has_attribute("href")
+        }
+        // Focus state is mirrored onto the flags by the focus controller.
+        "focus" => element.flags.contains(ElementFlags::FOCUSED),
+        "focus-visible" => element.flags.contains(ElementFlags::FOCUS_VISIBLE),
+        "focus-within" => element.flags.contains(ElementFlags::FOCUS_WITHIN),
+        "defined" => matches!(
+            element.custom_state,
+            CustomElementState::Uncustomized | CustomElementState::Custom
+        ),
+        // Form state, from `dom::forms`; validity needs the whole form.
+        "enabled" => forms::can_be_disabled(element) && !forms::is_disabled(tree, node_id),
+        "disabled" => forms::can_be_disabled(element) && forms::is_disabled(tree, node_id),
+        "checked" => forms::is_checked(tree, node_id),
+        "required" => forms::is_required(element),
+        "optional" => {
+            !forms::is_required(element)
+                && matches!(element.tag_name.as_str(), "input" | "select" | "textarea")
+        }
+        "valid" => forms::validity_match(tree, node_id) == Some(true),
+        "invalid" => forms::validity_match(tree, node_id) == Some(false),
+        name => {
+            let context = context.get_or_insert_with(|| MatchContext::for_element(tree, node_id));
+            match_pseudo_class(name, pseudo.raw_args(), context)
+        }
+    })
+}
+
+/// Whether any compound selector of `list` matches the element, as for
+/// `:host()`, `:host-context()` and `::slotted()`.
+fn match_compound_list(
+    list: &SelectorList,
+    element: &ElementData,
+    tree: &DomTree,
+    node_id: NodeId,
+    scope: Option<NodeId>,
+) -> bool {
+    list.selectors
+        .iter()
+        .any(|s| s.next.is_none() && match_compound(s, element, tree, node_id, scope, None))
+}
+
+/// Whether `node_id` is the document element.
+fn is_root_element(tree: &DomTree, node_id: NodeId) -> bool {
+    tree.parent(node_id)
+        .and_then(|parent| tree.get(parent))
+        .map(|parent| parent.node_type == NodeType::Document)
+        .unwrap_or(false)
 }
 
 /// Match simple selector (without combinators).
-fn match_simple_selector(selector: &Selector, element: &ElementData) -> bool {
+pub(crate) fn match_simple_selector(selector: &Selector, element: &ElementData) -> bool {
     // Universal matches everything
     if selector.universal && selector.tag.is_none() && selector.id.is_none()
         && selector.classes.is_empty() && selector.attributes.is_empty()
@@ -121,69 +467,6 @@ fn match_attribute_selector(selector: &AttributeSelector, element: &ElementData)
     }
 }
 
-/// Match with combinator.
-fn match_with_combinator(
-    combinator: &Combinator,
-    next_selector: &Selector,
-    tree: &DomTree,
-    node_id: NodeId,
-) -> bool {
-    match combinator {
-        Combinator::Descendant => {
-            // Any ancestor must match
-            let mut current = tree.parent(node_id);
-            while let Some(ancestor_id) = current {
-                if let Some(node) = tree.get(ancestor_id) {
-                    if let Some(elem) = node.as_element() {
-                        if match_selector(next_selector, elem, tree, ancestor_id) {
-                            return true;
-                        }
-                    }
-                }
-                current = tree.parent(ancestor_id);
-            }
-            false
-        }
-        Combinator::Child => {
-            // Direct parent must match
-            if let Some(parent_id) = tree.parent(node_id) {
-                if let Some(node) = tree.get(parent_id) {
-                    if let Some(elem) = node.as_element() {
-                        return match_selector(next_selector, elem, tree, parent_id);
-                    }
-                }
-            }
-            false
-        }
-        Combinator::NextSibling => {
-            // Immediately preceding sibling must match
-            if let Some(prev_id) = tree.prev_sibling(node_id) {
-                if let Some(node) = tree.get(prev_id) {
-                    if let Some(elem) = node.as_element() {
-                        return match_selector(next_selector, elem, tree, prev_id);
-                    }
-                }
-            }
-            false
-        }
-        Combinator::SubsequentSibling => {
-            // Any preceding sibling must match
-            let mut current = tree.prev_sibling(node_id);
-            while let Some(sibling_id) = current {
-                if let Some(node) = tree.get(sibling_id) {
-                    if let Some(elem) = node.as_element() {
-                        if match_selector(next_selector, elem, tree, sibling_id) {
-                            return true;
-                        }
-                    }
-                }
-                current = tree.prev_sibling(sibling_id);
-            }
-            false
-        }
-    }
-}
-
 /// Context for pseudo-class matching.
 #[derive(Clone, Debug, Default)]
 pub struct MatchContext {
@@ -295,61 +578,22 @@ pub fn match_pseudo_class(
         "nth-last-child" => match_nth(args, context.nth_last_child),
         "nth-of-type" => match_nth(args, context.nth_of_type),
         "nth-last-of-type" => match_nth(args, context.nth_last_of_type),
-        "enabled" => true, // Would need element state
-        "disabled" => false,
-        "checked" => false,
-        "required" => false,
-        "optional" => true,
-        _ => true, // Unknown pseudo-classes match by default
+        _ => {
+            debug_assert!(!PSEUDO_CLASSES.contains(&name), "unhandled pseudo-class :{name}");
+            false
+        }
     }
 }
 
 /// Match nth-* expression.
 fn match_nth(args: Option<&str>, n: usize) -> bool {
-    let args = match args {
-        Some(a) => a.trim(),
-        None => return false,
+    let Some((a, b)) = args.and_then(parse_nth) else {
+        return false;
     };
-
-    // Parse An+B syntax
-    if args == "odd" {
-        return n % 2 == 1;
-    }
-    if args == "even" {
-        return n % 2 == 0;
-    }
-
-    // Simple number
-    if let Ok(num) = args.parse::<usize>() {
-        return n == num;
-    }
-
-    // An+B form
-    // Simplified parsing - real implementation would be more robust
-    if args.contains('n') {
-        let parts: Vec<&str> = args.split('n').collect();
-        let a: i32 = match parts[0].trim() {
-            "" | "+" => 1,
-            "-" => -1,
-            s => s.parse().unwrap_or(0),
-        };
-        let b: i32 = parts
-            .get(1)
-            .and_then(|s| s.trim().parse().ok())
-            .unwrap_or(0);
-
-        if a == 0 {
-            return n as i32 == b;
-        }
-
-        let diff = n as i32 - b;
-        if a > 0 {
-            diff >= 0 && diff % a == 0
-        } else {
-            diff <= 0 && diff % a == 0
-        }
-    } else {
-        false
+    let diff = n as i32 - b;
+    match a {
+        0 => diff == 0,
+        a => diff % a == 0 && diff / a >= 0,
     }
 }
 
@@ -394,5 +638,144 @@ mod tests {
         assert!(match_nth(Some("3"), 3));
         assert!(match_nth(Some("2n"), 4));
         assert!(match_nth(Some("2n+1"), 3));
+        assert!(match_nth(Some("-n + 3"), 2));
+        assert!(!match_nth(Some("-n + 3"), 4));
+        assert!(!match_nth(Some("2n of .a"), 2));
+    }
+
+    #[test]
+    fn test_every_pseudo_class_is_handled() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        tree.append_child(root, div);
+        // Unhandled names trip the debug assertion in `match_pseudo_class`.
+        for name in PSEUDO_CLASSES {
+            let css = match *name {
+                "is" | "where" | "not" | "has" | "host-context" => format!(":{name}(p)"),
+                name if name.starts_with("nth-") => format!(":{name}(2n+1)"),
+                name => format!(":{name}"),
+            };
+            let list = parse_selector_list(&css).unwrap();
+            match_selectors(&list, tree.get_element(div).unwrap(), &tree, div);
+        }
+    }
+
+    #[test]
+    fn test_match_defined() {
+        let mut tree = DomTree::new();
+        let div = tree.create_element(ElementData::new(TagName::div()));
+        let custom = tree.create_element(ElementData::new(TagName::new("x-widget")));
+        let defined = parse_selector_list(":defined").unwrap();
+
+        let matches = |tree: &DomTree, node| {
+            match_selectors(&defined, tree.get_element(node).unwrap(), tree, node).is_some()
+        };
+        assert!(matches(&tree, div));
+        assert!(!matches(&tree, custom));
+
+        tree.get_element_mut(custom).unwrap().custom_state = CustomElementState::Custom;
+        assert!(matches(&tree, custom));
+    }
+
+    #[test]
+    fn test_match_focus_from_controller() {
+        use dom::focus::{FocusController, FocusOrigin};
+
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let form = tree.create_element(ElementData::new(TagName::new("form")));
+        let input = tree.create_element(ElementData::new(TagName::new("input")));
+        tree.append_child(root, form);
+        tree.append_child(form, input);
+
+        let matches = |tree: &DomTree, selector: &str, node| {
+            let list = parse_selector_list(selector).unwrap();
+            match_selectors(&list, tree.get_element(node).unwrap(), tree, node).is_some()
+        };
+        assert!(!matches(&tree, ":focus", input));
+        assert!(!matches(&tree, ":focus-within", form));
+
+        let mut focus = FocusController::new();
+        focus.focus(&mut tree, input, FocusOrigin::Pointer);
+        assert!(matches(&tree, "input:focus", input));
+        assert!(matches(&tree, ":focus-visible", input));
+        assert!(matches(&tree, "form:focus-within", form));
+        assert!(!matches(&tree, ":focus", form));
+
+        focus.blur(&mut tree, input);
+        assert!(!matches(&tree, ":focus", input));
+        assert!(!matches(&tree, ":focus-within", form));
+    }
+
+    #[test]
+    fn test_match_functional_pseudo_classes() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let mut data = ElementData::new(TagName::div());
+        data.set_attribute("class", "card");
+        let card = tree.create_element(data);
+        let heading = tree.create_element(ElementData::new(TagName::new("h2")));
+        let mut data = ElementData::new(TagName::p());
+        data.set_attribute("class", "note");
+        let note = tree.create_element(data);
+        let img = tree.create_element(ElementData::new(TagName::new("img")));
+        let caption = tree.create_element(ElementData::new(TagName::span()));
+        tree.append_child(root, card);
+        tree.append_child(card, heading);
+        tree.append_child(card, note);
+        tree.append_child(note, img);
+        tree.append_child(card, caption);
+
+        let matches = |selector: &str, node| {
+            let list = parse_selector_list(selector).unwrap();
+            match_selectors(&list, tree.get_element(node).unwrap(), &tree, node).is_some()
+        };
+        assert!(matches(":is(h2, !!, .note) img", img));
+        assert!(matches(":where(.card) > h2", heading));
+        assert!(!matches("p:not(.note, span)", note));
+        assert!(matches(":not(div > h2)", note));
+
+        assert!(matches(".card:has(img)", card));
+        assert!(!matches(".card:has(> img)", card));
+        assert!(matches(".card:has(> .note img, video)", card));
+        assert!(!matches(".card:has(.note video)", card));
+        assert!(matches("h2:has(+ .note)", heading));
+        assert!(matches("h2:has(~ span)", heading));
+        assert!(!matches("h2:has(+ span)", heading));
+        assert!(matches(":has(> :is(h2, h3)) span", caption));
+    }
+
+    #[test]
+    fn test_match_form_state() {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let form = tree.create_element(ElementData::new(TagName::form()));
+        let fieldset = tree.create_element(ElementData::new(TagName::new("fieldset")));
+        let mut data = ElementData::new(TagName::input());
+        data.set_attribute("type", "checkbox");
+        data.set_attribute("required", "");
+        let checkbox = tree.create_element(data);
+        tree.append_child(root, form);
+        tree.append_child(form, fieldset);
+        tree.append_child(fieldset, checkbox);
+
+        let matches = |tree: &DomTree, selector: &str, node| {
+            let list = parse_selector_list(selector).unwrap();
+            match_selectors(&list, tree.get_element(node).unwrap(), tree, node).is_some()
+        };
+        assert!(matches(&tree, "input:required:enabled", checkbox));
+        assert!(matches(&tree, ":invalid", checkbox));
+        assert!(matches(&tree, "form:invalid", form));
+        assert!(!matches(&tree, ":checked", checkbox));
+        assert!(!matches(&tree, ":enabled", form) && !matches(&tree, ":required", form));
+
+        forms::set_checked(&mut tree, checkbox, true);
+        assert!(matches(&tree, "input:checked:valid", checkbox));
+        assert!(matches(&tree, "form:valid", form));
+
+        tree.get_element_mut(fieldset).unwrap().set_attribute("disabled", "");
+        assert!(matches(&tree, "input:disabled", checkbox));
+        assert!(!matches(&tree, ":enabled", checkbox));
     }
 }
@@ -0,0 +1,340 @@
+//! Selector queries: `querySelector`, `querySelectorAll`, `matches` and `closest`.
+
+use crate::matching::match_selector_in_scope;
+use css_parser::selector::{parse_selector_list, Selector, SelectorList};
+use dom::document::Document;
+use dom::node::NodeId;
+use dom::tree::DomTree;
+use thiserror::Error;
+
+/// Errors raised by selector queries.
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    #[error("'{0}' is not a valid selector")]
+    InvalidSelector(String),
+}
+
+/// A parsed selector list, ready to be matched repeatedly.
+#[derive(Clone, Debug)]
+pub struct SelectorQuery {
+    selectors: SelectorList,
+}
+
+impl SelectorQuery {
+    /// Parse `selectors`, e.g. `ul > li:not(.x) a[href^=http]`.
+    pub fn parse(selectors: &str) -> Result<Self, QueryError> {
+        let selectors = parse_selector_list(selectors)
+            .map_err(|_| QueryError::InvalidSelector(selectors.to_string()))?;
+        Ok(Self { selectors })
+    }
+
+    /// Whether the element `node` matches, with `:scope` referring to `scope`.
+    pub fn matches(&self, tree: &DomTree, node: NodeId, scope: Option<NodeId>) -> bool {
+        let element = match tree.get_element(node) {
+            Some(element) => element,
+            None => return false,
+        };
+        self.selectors
+            .selectors
+            .iter()
+            .filter(|selector| can_match_element(selector))
+            .any(|selector| match_selector_in_scope(selector, element, tree, node, scope))
+    }
+
+    /// Matching descendants of `scope`, in tree order.
+    pub fn query_all(&self, tree: &DomTree, scope: NodeId) -> Vec<NodeId> {
+        let scope_element = tree.get_element(scope).map(|_| scope);
+        match self.indexed_candidates(tree) {
+            Some(candidates) => self.matching_candidates(tree, scope, scope_element, candidates),
+            None => tree
+                .descendants(scope)
+                .filter(|&node| self.matches(tree, node, scope_element))
+                .collect(),
+        }
+    }
+
+    /// First matching descendant of `scope` in tree order.
+    pub fn query(&self, tree: &DomTree, scope: NodeId) -> Option<NodeId> {
+        let scope_element = tree.get_element(scope).map(|_| scope);
+        match self.indexed_candidates(tree) {
+            Some(candidates) => self
+                .matching_candidates(tree, scope, scope_element, candidates)
+                .into_iter()
+                .next(),
+            None => tree
+                .descendants(scope)
+                .find(|&node| self.matches(tree, node, scope_element)),
+        }
+    }
+
+    /// Nearest inclusive ancestor of `node` that matches.
+    pub fn closest(&self, tree: &DomTree, node: NodeId) -> Option<NodeId> {
+        std::iter::once(node)
+            .chain(tree.ancestors(node))
+            .find(|&ancestor| self.matches(tree, ancestor, Some(node)))
+    }
+
+    /// The `candidates` inside `scope` that match, in tree order.
+    fn matching_candidates(
+        &self,
+        tree: &DomTree,
+        scope: NodeId,
+        scope_element: Option<NodeId>,
+        candidates: Vec<NodeId>,
+    ) -> Vec<NodeId> {
+        let mut found: Vec<NodeId> = candidates
+            .into_iter()
+            .filter(|&node| node != scope && tree.is_inclusive_ancestor(scope, node))
+            .filter(|&node| self.matches(tree, node, scope_element))
+            .collect();
+        tree.sort_in_tree_order(&mut found);
+        found
+    }
+
+    /// Candidate elements from the id/class index, or `None` if some
+    /// selector has neither an id nor a class in its subject and the tree
+    /// has to be walked.
+    fn indexed_candidates(&self, tree: &DomTree) -> Option<Vec<NodeId>> {
+        let mut candidates = Vec::new();
+        for selector in &self.selectors.selectors {
+            let subject = subject(selector);
+            if let Some(id) = &subject.id {
+                candidates.extend(tree.elements_with_id(id));
+            } else if let Some(class) = subject.classes.first() {
+                candidates.extend(tree.elements_with_class(class));
+            } else {
+                return None;
+            }
+        }
+        Some(candidates)
+    }
+}
+
+/// The rightmost compound, which the matched element itself must satisfy.
+fn subject(selector: &Selector) -> &Selector {
+    let mut subject = selector;
+    while let Some(next) = subject.next.as_deref() {
+        subject = next;
+    }
+    subject
+}
+
+/// Selectors ending in a pseudo-element never match an element.
+fn can_match_element(selector: &Selector) -> bool {
+    subject(selector).pseudo_elements.is_empty()
+}
+
+/// First descendant of `scope` matching `selectors`.
+pub fn query_selector(
+    tree: &DomTree,
+    scope: NodeId,
+    selectors: &str,
+) -> Result<Option<NodeId>, QueryError> {
+    Ok(SelectorQuery::parse(selectors)?.query(tree, scope))
+}
+
+/// All descendants of `scope` matching `selectors`, in tree order.
+pub fn query_selector_all(
+    tree: &DomTree,
+    scope: NodeId,
+    selectors: &str,
+) -> Result<Vec<NodeId>, QueryError> {
+    Ok(SelectorQuery::parse(selectors)?.query_all(tree, scope))
+}
+
+/// Whether the element `node` matches `selectors` (`Element.matches`).
+pub fn matches(tree: &DomTree, node: NodeId, selectors: &str) -> Result<bool, QueryError> {
+    Ok(SelectorQuery::parse(selectors)?.matches(tree, node, Some(node)))
+}
+
+/// Nearest inclusive ancestor of `node` matching `selectors` (`Element.closest`).
+pub fn closest(
+    tree: &DomTree,
+    node: NodeId,
+    selectors: &str,
+) -> Result<Option<NodeId>, QueryError> {
+    Ok(SelectorQuery::parse(selectors)?.closest(tree, node))
+}
+
+/// `querySelector()` and `querySelectorAll()` on a whole tree or document.
+pub trait QuerySelector {
+    /// First element matching `selectors`, in tree order.
+    fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>, QueryError>;
+
+    /// All elements matching `selectors`, in tree order.
+    fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>, QueryError>;
+}
+
+impl QuerySelector for DomTree {
+    fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>, QueryError> {
+        let query = SelectorQuery::parse(selectors)?;
+        Ok(self.root().and_then(|root| query.query(self, root)))
+    }
+
+    fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>, QueryError> {
+        let query = SelectorQuery::parse(selectors)?;
+        Ok(self
+            .root()
+            .map_or_else(Vec::new, |root| query.query_all(self, root)))
+    }
+}
+
+impl QuerySelector for Document {
+    fn query_selector(&self, selectors: &str) -> Result<Option<NodeId>, QueryError> {
+        self.tree.query_selector(selectors)
+    }
+
+    fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeId>, QueryError> {
+        self.tree.query_selector_all(selectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dom::element::{ElementData, TagName};
+
+    fn element(tree: &mut DomTree, parent: NodeId, tag: &str, attrs: &[(&str, &str)]) -> NodeId {
+        let mut data = ElementData::new(TagName::new(tag));
+        for (name, value) in attrs {
+            data.set_attribute(name, value);
+        }
+        let node = tree.create_element(data);
+        tree.append_child(parent, node);
+        node
+    }
+
+    /// `<html><body><ul id=list><li class=x><a href=http://a></a></li>
+    /// <li><a href=http://b></a><a href=/c></a></li></ul><p class=x></p></body></html>`
+    fn sample() -> (DomTree, Vec<NodeId>) {
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let html = element(&mut tree, root, "html", &[]);
+        let body = element(&mut tree, html, "body", &[]);
+        let ul = element(&mut tree, body, "ul", &[("id", "list")]);
+        let li1 = element(&mut tree, ul, "li", &[("class", "x")]);
+        let a1 = element(&mut tree, li1, "a", &[("href", "http://a")]);
+        let li2 = element(&mut tree, ul, "li", &[]);
+        let a2 = element(&mut tree, li2, "a", &[("href", "http://b")]);
+        let a3 = element(&mut tree, li2, "a", &[("href", "/c")]);
+        let p = element(&mut tree, body, "p", &[("class", "x")]);
+        (tree, vec![html, body, ul, li1, a1, li2, a2, a3, p])
+    }
+
+    #[test]
+    fn test_query_selector_all() {
+        let (tree, nodes) = sample();
+        let root = tree.root().unwrap();
+        let (ul, li1, a1, li2, a2, p) =
+            (nodes[2], nodes[3], nodes[4], nodes[5], nodes[6], nodes[8]);
+
+        assert_eq!(
+            query_selector_all(&tree, root, "ul > li:not(.x) a[href^=http]").unwrap(),
+            vec![a2]
+        );
+        assert_eq!(
+            query_selector_all(&tree, root, "li a[href^=http]").unwrap(),
+            vec![a1, a2]
+        );
+        assert_eq!(query_selector_all(&tree, root, ".x").unwrap(), vec![li1, p]);
+        assert_eq!(
+            query_selector_all(&tree, root, "p, #list").unwrap(),
+            vec![ul, p]
+        );
+        assert_eq!(
+            query_selector_all(&tree, root, "li + li").unwrap(),
+            vec![li2]
+        );
+        assert_eq!(query_selector_all(&tree, root, "ul ~ p").unwrap(), vec![p]);
+        assert_eq!(
+            query_selector_all(&tree, root, "li:first-child").unwrap(),
+            vec![li1]
+        );
+        assert_eq!(
+            query_selector(&tree, root, "a:last-child").unwrap(),
+            Some(a1)
+        );
+        assert!(query_selector_all(&tree, root, "a::before")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_document_query_selector_all() {
+        let mut document = Document::new(url::Url::parse("about:blank").unwrap());
+        let root = document.tree.root().unwrap();
+        let ul = element(&mut document.tree, root, "ul", &[]);
+        let li1 = element(&mut document.tree, ul, "li", &[("class", "x")]);
+        let li2 = element(&mut document.tree, ul, "li", &[]);
+        let a1 = element(&mut document.tree, li1, "a", &[]);
+        let a2 = element(&mut document.tree, li2, "a", &[]);
+
+        assert_eq!(
+            document.query_selector_all("ul > li + li > a").unwrap(),
+            vec![a2]
+        );
+        assert_eq!(document.query_selector_all("ul a").unwrap(), vec![a1, a2]);
+        assert_eq!(document.query_selector(".x > a").unwrap(), Some(a1));
+        assert!(document.query_selector_all("li >").is_err());
+    }
+
+    #[test]
+    fn test_query_is_scoped() {
+        let (tree, nodes) = sample();
+        let (ul, li1, li2, a2, a3) = (nodes[2], nodes[3], nodes[5], nodes[6], nodes[7]);
+
+        // Combinators may reach outside the scope, but results may not.
+        assert_eq!(
+            query_selector_all(&tree, li2, "ul a").unwrap(),
+            vec![a2, a3]
+        );
+        assert!(query_selector_all(&tree, li2, "li").unwrap().is_empty());
+        assert_eq!(
+            query_selector_all(&tree, ul, ":scope > .x").unwrap(),
+            vec![li1]
+        );
+    }
+
+    #[test]
+    fn test_matches_and_closest() {
+        let (tree, nodes) = sample();
+        let (html, ul, li1, a1) = (nodes[0], nodes[2], nodes[3], nodes[4]);
+
+        assert!(matches(&tree, a1, "li.x > a").unwrap());
+        assert!(!matches(&tree, a1, "p a").unwrap());
+        assert!(matches(&tree, html, ":root").unwrap());
+        assert_eq!(closest(&tree, a1, "ul").unwrap(), Some(ul));
+        assert_eq!(closest(&tree, a1, "li").unwrap(), Some(li1));
+        assert_eq!(closest(&tree, a1, "a").unwrap(), Some(a1));
+        assert_eq!(closest(&tree, a1, "table").unwrap(), None);
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        let (tree, _) = sample();
+        let root = tree.root().unwrap();
+        assert_eq!(
+            query_selector_all(&tree, root, "ul >").unwrap_err(),
+            QueryError::InvalidSelector("ul >".to_string())
+        );
+        assert!(query_selector(&tree, root, ":bogus").is_err());
+        assert!(matches(&tree, root, "li:not(:bogus)").is_err());
+    }
+
+    #[test]
+    fn test_index_tracks_attribute_changes() {
+        let (mut tree, nodes) = sample();
+        let root = tree.root().unwrap();
+        let (li1, p) = (nodes[3], nodes[8]);
+
+        tree.get_element_mut(li1).unwrap().remove_attribute("class");
+        tree.get_element_mut(p).unwrap().set_attribute("id", "para");
+        assert_eq!(query_selector_all(&tree, root, ".x").unwrap(), vec![p]);
+        assert_eq!(query_selector(&tree, root, "#para").unwrap(), Some(p));
+
+        tree.remove(p);
+        assert!(query_selector_all(&tree, root, ".x, #para")
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 //! Style resolver for full document styling.
 
 use crate::computed::ComputedStyle;
+use crate::invalidation::{Invalidation, Restyle};
 use crate::stylist::Stylist;
 use css_parser::media::MediaContext;
 use css_parser::stylesheet::Stylesheet;
@@ -50,11 +51,37 @@ impl StyleResolver {
         self.styles.write().clear();
 
         if let Some(root) = document.tree.root() {
+            self.collect_shadow_stylesheets(&document.tree, root);
             self.resolve_subtree(&document.tree, root, None);
         }
     }
 
-    /// Resolve styles for a subtree.
+    /// Load the `<style>` elements of every shadow tree below `root` as
+    /// the stylesheets of that tree.
+    pub fn collect_shadow_stylesheets(&mut self, tree: &DomTree, root: NodeId) {
+        let hosts = std::iter::once(root).chain(tree.flat_descendants(root));
+        let shadow_roots: Vec<NodeId> = hosts
+            .filter_map(|node| tree.shadow_root(node).map(|shadow| shadow.root))
+            .collect();
+        for shadow_root in shadow_roots {
+            let sheets = tree
+                .descendants(shadow_root)
+                .filter(|&node| {
+                    tree.get_element(node)
+                        .is_some_and(|e| e.tag_name == "style" && e.namespace.is_none())
+                })
+                .map(|node| {
+                    css_parser::parse_css(
+                        &tree.get_text_content(node),
+                        url::Url::parse("about:blank").unwrap(),
+                    )
+                })
+                .collect();
+            self.stylist.set_shadow_stylesheets(shadow_root, sheets);
+        }
+    }
+
+    /// Resolve styles for a subtree of the flat tree.
     fn resolve_subtree(
         &mut self,
         tree: &DomTree,
@@ -75,9 +102,8 @@ impl StyleResolver {
             None
         };
 
-        // Process children
-        let children: Vec<NodeId> = node.children.iter().copied().collect();
-        for child in children {
+        // Process children, with shadow trees and slotted nodes in place
+        for child in tree.flat_children(node_id) {
             self.resolve_subtree(
                 tree,
                 child,
@@ -91,7 +117,7 @@ impl StyleResolver {
         self.styles.read().get(&node_id).cloned()
     }
 
-    /// Invalidate styles for a subtree.
+    /// Invalidate styles for a subtree, shadow trees included.
     pub fn invalidate_subtree(&mut self, tree: &DomTree, node_id: NodeId) {
         let mut to_remove = vec![node_id];
 
@@ -100,6 +126,7 @@ impl StyleResolver {
         while i < to_remove.len() {
             if let Some(node) = tree.get(to_remove[i]) {
                 to_remove.extend(node.children.iter().copied());
+                to_remove.extend(tree.shadow_root(to_remove[i]).map(|shadow| shadow.root));
             }
             i += 1;
         }
@@ -111,10 +138,97 @@ impl StyleResolver {
         }
     }
 
+    /// Restyle the subtrees invalidated by DOM mutations, dropping styles
+    /// of removed nodes. Returns whether layout has to run again.
+    pub fn apply_invalidation(&mut self, tree: &DomTree, invalidation: &Invalidation) -> bool {
+        if !invalidation.restyle.is_empty() {
+            self.styles.write().retain(|&id, _| tree.get(id).is_some());
+        }
+        for &root in &invalidation.restyle {
+            if tree.get(root).is_none() {
+                continue;
+            }
+            let mut restyles = self.stylist.relative_restyles(tree, root);
+            restyles.push(Restyle::Subtree(root));
+            self.apply_restyles(tree, restyles);
+        }
+        invalidation.needs_layout
+    }
+
+    /// Restyle elements and subtrees, ancestors first, skipping those that
+    /// a subtree restyled before covers.
+    fn apply_restyles(&mut self, tree: &DomTree, mut restyles: Vec<Restyle>) {
+        restyles.sort_by_cached_key(|restyle| {
+            std::iter::successors(Some(restyle.node()), |&id| tree.flat_parent(id)).count()
+        });
+        let mut restyled: Vec<NodeId> = Vec::new();
+        for restyle in restyles {
+            let node = restyle.node();
+            if restyled
+                .iter()
+                .any(|&root| tree.is_shadow_including_inclusive_ancestor(root, node))
+            {
+                continue;
+            }
+            match restyle {
+                Restyle::Subtree(root) => {
+                    self.invalidate_subtree(tree, root);
+                    let parent_style = tree.flat_parent(root).and_then(|p| self.get_style(p));
+                    self.resolve_subtree(tree, root, parent_style.as_deref());
+                }
+                Restyle::Element(element) => {
+                    let old = self.get_style(element);
+                    self.restyle_element(tree, element);
+                    let style = self.get_style(element);
+                    // Descendants only see the element's style through
+                    // inheritance.
+                    let Some(style) = style.filter(|style| old.as_ref() != Some(style)) else {
+                        continue;
+                    };
+                    for child in tree.flat_children(element) {
+                        self.invalidate_subtree(tree, child);
+                        self.resolve_subtree(tree, child, Some(&style));
+                    }
+                }
+            }
+            restyled.push(node);
+        }
+    }
+
+    /// Elements that are query containers, whose sizes layout reports back
+    /// through `update_container_sizes`.
+    pub fn query_containers(&self) -> Vec<NodeId> {
+        self.stylist.query_containers()
+    }
+
+    /// Record the content-box sizes layout computed for query containers
+    /// and restyle the descendants of those whose size changed. Returns
+    /// whether layout has to run again.
+    pub fn update_container_sizes(
+        &mut self,
+        tree: &DomTree,
+        sizes: impl IntoIterator<Item = (NodeId, f32, f32)>,
+    ) -> bool {
+        let changed: Vec<NodeId> = sizes
+            .into_iter()
+            .filter(|&(node, width, height)| self.stylist.set_container_size(node, width, height))
+            .map(|(node, _, _)| node)
+            .collect();
+        for &container in &changed {
+            let Some(style) = self.get_style(container) else {
+                continue;
+            };
+            for child in tree.flat_children(container) {
+                self.resolve_subtree(tree, child, Some(&style));
+            }
+        }
+        !changed.is_empty()
+    }
+
     /// Restyle a single element.
     pub fn restyle_element(&mut self, tree: &DomTree, node_id: NodeId) {
         let parent_style = tree
-            .parent(node_id)
+            .flat_parent(node_id)
             .and_then(|p| self.styles.read().get(&p).cloned());
 
         let computed = self.stylist.compute_style(
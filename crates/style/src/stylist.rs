@@ -2,10 +2,17 @@
 
 use crate::cascade::{cascade_styles, CascadedDeclaration, Origin};
 use crate::computed::ComputedStyle;
-use crate::matching::{match_selectors, MatchContext};
+use crate::container::ContainerQueries;
+use crate::layers::LayerOrder;
+use crate::invalidation::Restyle;
+use crate::matching::{
+    match_selectors, match_shadow_selectors, match_simple_selector, previous_element_sibling,
+    MatchContext,
+};
+use css_parser::calc::{CalcNode, CalcType};
 use css_parser::media::MediaContext;
 use css_parser::properties::{PropertyDeclaration, PropertyId};
-use css_parser::selector::SelectorList;
+use css_parser::selector::{Combinator, Selector, SelectorList};
 use css_parser::stylesheet::{CssRule, MediaRule, StyleRule, Stylesheet, SupportsRule};
 use css_parser::values::CssValue;
 use dom::element::ElementData;
@@ -13,7 +20,7 @@ use dom::node::NodeId;
 use dom::tree::DomTree;
 use indexmap::IndexMap;
 use parking_lot::RwLock;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 /// The Stylist handles style matching and computation.
@@ -24,14 +31,68 @@ pub struct Stylist {
     user_sheets: Vec<Arc<Stylesheet>>,
     /// Author stylesheets.
     author_sheets: Vec<Arc<Stylesheet>>,
+    /// Stylesheets of shadow trees, by shadow root.
+    shadow_sheets: HashMap<NodeId, Vec<Arc<Stylesheet>>>,
+    /// Cascade layers of each origin and shadow tree.
+    ua_layers: LayerOrder,
+    user_layers: LayerOrder,
+    author_layers: LayerOrder,
+    shadow_layers: HashMap<NodeId, LayerOrder>,
     /// Media context.
     media_context: MediaContext,
+    /// Compounds with `:has()`. Elements they may match have styles that
+    /// depend on their descendants or siblings.
+    relative_anchors: Vec<RelativeAnchor>,
+    /// Query containers and their sizes from layout.
+    container_queries: ContainerQueries,
+    /// Container sizes `cq*` units of the element being styled resolve
+    /// against.
+    unit_container: (f32, f32),
     /// Style cache.
     cache: RwLock<HashMap<CacheKey, Arc<ComputedStyle>>>,
     /// Rule source order counter.
     source_order: u32,
 }
 
+/// A compound with `:has()` in the stylesheets.
+#[derive(Clone, Debug)]
+struct RelativeAnchor {
+    /// The compound, without its combinator.
+    compound: Selector,
+    /// Combinators of each relative selector of its `:has()`, the leading
+    /// one first.
+    relative: Vec<Vec<Combinator>>,
+    /// Combinator to the rest of the selector, `None` if the compound is
+    /// the subject.
+    combinator: Option<Combinator>,
+}
+
+/// Where the rules being matched come from.
+#[derive(Clone, Copy, Debug)]
+struct RuleScope<'a> {
+    origin: Origin,
+    /// Host of the shadow tree the stylesheet belongs to.
+    host: Option<NodeId>,
+    /// Whether that shadow tree is not the one the element is in.
+    from_shadow: bool,
+    /// Layer order of the cascade context.
+    layers: &'a LayerOrder,
+    /// Full name of the layer the rules are in.
+    layer: &'a [String],
+}
+
+impl<'a> RuleScope<'a> {
+    fn new(origin: Origin, layers: &'a LayerOrder) -> Self {
+        Self {
+            origin,
+            host: None,
+            from_shadow: false,
+            layers,
+            layer: &[],
+        }
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 struct CacheKey {
     node_id: NodeId,
@@ -44,7 +105,15 @@ impl Stylist {
             ua_sheets: Vec::new(),
             user_sheets: Vec::new(),
             author_sheets: Vec::new(),
+            shadow_sheets: HashMap::new(),
+            ua_layers: LayerOrder::default(),
+            user_layers: LayerOrder::default(),
+            author_layers: LayerOrder::default(),
+            shadow_layers: HashMap::new(),
             media_context: MediaContext::default(),
+            relative_anchors: Vec::new(),
+            container_queries: ContainerQueries::default(),
+            unit_container: (0.0, 0.0),
             cache: RwLock::new(HashMap::new()),
             source_order: 0,
         }
@@ -53,18 +122,37 @@ impl Stylist {
     /// Add user agent stylesheet.
     pub fn add_ua_stylesheet(&mut self, sheet: Stylesheet) {
         self.ua_sheets.push(Arc::new(sheet));
+        self.update_layers();
+        self.update_relative_anchors();
         self.invalidate_cache();
     }
 
     /// Add user stylesheet.
     pub fn add_user_stylesheet(&mut self, sheet: Stylesheet) {
         self.user_sheets.push(Arc::new(sheet));
+        self.update_layers();
+        self.update_relative_anchors();
         self.invalidate_cache();
     }
 
     /// Add author stylesheet.
     pub fn add_author_stylesheet(&mut self, sheet: Stylesheet) {
         self.author_sheets.push(Arc::new(sheet));
+        self.update_layers();
+        self.update_relative_anchors();
+        self.invalidate_cache();
+    }
+
+    /// Set the stylesheets of the shadow tree rooted at `root`.
+    pub fn set_shadow_stylesheets(&mut self, root: NodeId, sheets: Vec<Stylesheet>) {
+        if sheets.is_empty() {
+            self.shadow_sheets.remove(&root);
+        } else {
+            self.shadow_sheets
+                .insert(root, sheets.into_iter().map(Arc::new).collect());
+        }
+        self.update_layers();
+        self.update_relative_anchors();
         self.invalidate_cache();
     }
 
@@ -73,20 +161,137 @@ impl Stylist {
         self.ua_sheets.clear();
         self.user_sheets.clear();
         self.author_sheets.clear();
+        self.shadow_sheets.clear();
+        self.update_layers();
+        self.update_relative_anchors();
         self.invalidate_cache();
     }
 
     /// Set media context.
     pub fn set_media_context(&mut self, context: MediaContext) {
         self.media_context = context;
+        self.update_layers();
         self.invalidate_cache();
     }
 
+    /// Query containers styled so far.
+    pub fn query_containers(&self) -> Vec<NodeId> {
+        self.container_queries.containers()
+    }
+
+    /// Set the content-box size layout computed for a query container.
+    /// Returns whether it changed, in which case the container's
+    /// descendants have to be restyled.
+    pub fn set_container_size(&mut self, node_id: NodeId, width: f32, height: f32) -> bool {
+        let changed = self.container_queries.set_size(node_id, width, height);
+        if changed {
+            self.invalidate_cache();
+        }
+        changed
+    }
+
     /// Invalidate the cache.
     pub fn invalidate_cache(&self) {
         self.cache.write().clear();
     }
 
+    /// Recompute the layer order of every cascade context.
+    fn update_layers(&mut self) {
+        let media = &self.media_context;
+        self.ua_layers = LayerOrder::new(&self.ua_sheets, media);
+        self.user_layers = LayerOrder::new(&self.user_sheets, media);
+        self.author_layers = LayerOrder::new(&self.author_sheets, media);
+        self.shadow_layers = self
+            .shadow_sheets
+            .iter()
+            .map(|(&root, sheets)| (root, LayerOrder::new(sheets, media)))
+            .collect();
+    }
+
+    /// Collect the compounds with `:has()` of every stylesheet.
+    fn update_relative_anchors(&mut self) {
+        let sheets = self
+            .ua_sheets
+            .iter()
+            .chain(&self.user_sheets)
+            .chain(&self.author_sheets)
+            .chain(self.shadow_sheets.values().flatten());
+        let mut anchors = Vec::new();
+        for rule in sheets.flat_map(|sheet| sheet.style_rules()) {
+            for selector in &rule.selectors.selectors {
+                let mut current = Some(selector);
+                while let Some(compound) = current {
+                    let relative: Vec<Vec<Combinator>> = compound
+                        .pseudo_classes
+                        .iter()
+                        .filter(|pseudo| pseudo.name == "has")
+                        .filter_map(|pseudo| pseudo.relative_selectors())
+                        .flatten()
+                        .map(|relative| {
+                            let mut combinators = vec![relative.combinator];
+                            let mut part = &relative.selector;
+                            while let Some(next) = part.next.as_deref() {
+                                combinators.push(part.combinator.unwrap_or(Combinator::Descendant));
+                                part = next;
+                            }
+                            combinators
+                        })
+                        .collect();
+                    if !relative.is_empty() {
+                        anchors.push(RelativeAnchor {
+                            compound: Selector {
+                                combinator: None,
+                                next: None,
+                                ..compound.clone()
+                            },
+                            relative,
+                            combinator: compound
+                                .next
+                                .as_ref()
+                                .map(|_| compound.combinator.unwrap_or(Combinator::Descendant)),
+                        });
+                    }
+                    current = compound.next.as_deref();
+                }
+            }
+        }
+        self.relative_anchors = anchors;
+    }
+
+    /// Whether any stylesheet uses `:has()`.
+    pub fn has_relative_selectors(&self) -> bool {
+        !self.relative_anchors.is_empty()
+    }
+
+    /// Where styles may change through `:has()` after a mutation in the
+    /// subtree of `root`: the anchors outside it that its relative
+    /// selectors can reach, or the subtrees holding the subjects of the
+    /// selectors that do not end at the anchor.
+    pub fn relative_restyles(&self, tree: &DomTree, root: NodeId) -> Vec<Restyle> {
+        let mut restyles = HashSet::new();
+        for anchor in &self.relative_anchors {
+            let candidates: HashSet<NodeId> = anchor
+                .relative
+                .iter()
+                .flat_map(|combinators| relative_anchor_candidates(tree, root, combinators))
+                .collect();
+            for id in candidates {
+                let matches = tree
+                    .get_element(id)
+                    .is_some_and(|element| match_simple_selector(&anchor.compound, element));
+                if !matches {
+                    continue;
+                }
+                restyles.insert(match anchor.combinator {
+                    None => Restyle::Element(id),
+                    Some(Combinator::Child | Combinator::Descendant) => Restyle::Subtree(id),
+                    Some(_) => Restyle::Subtree(tree.parent(id).unwrap_or(id)),
+                });
+            }
+        }
+        restyles.into_iter().collect()
+    }
+
     /// Compute style for an element.
     pub fn compute_style(
         &mut self,
@@ -114,10 +319,11 @@ impl Stylist {
                 element,
                 tree,
                 node_id,
-                Origin::UserAgent,
+                RuleScope::new(Origin::UserAgent, &self.ua_layers),
                 &mut declarations,
                 &mut self.source_order,
                 &self.media_context,
+                &self.container_queries,
             );
         }
 
@@ -128,37 +334,84 @@ impl Stylist {
                 element,
                 tree,
                 node_id,
-                Origin::User,
+                RuleScope::new(Origin::User, &self.user_layers),
                 &mut declarations,
                 &mut self.source_order,
                 &self.media_context,
+                &self.container_queries,
             );
         }
 
-        // Match author rules
-        for sheet in &self.author_sheets {
+        // Match author rules of the tree the element is in: the document,
+        // or a shadow tree.
+        let shadow_root = tree.containing_shadow_root(node_id);
+        let no_layers = LayerOrder::default();
+        let (author_sheets, author_layers) = match shadow_root {
+            Some(root) => (
+                self.shadow_sheets.get(&root).map(Vec::as_slice).unwrap_or_default(),
+                self.shadow_layers.get(&root).unwrap_or(&no_layers),
+            ),
+            None => (self.author_sheets.as_slice(), &self.author_layers),
+        };
+        let scope = RuleScope {
+            host: shadow_root.and_then(|root| tree.shadow_host(root)),
+            ..RuleScope::new(Origin::Author, author_layers)
+        };
+        for sheet in author_sheets {
             Self::collect_matching_rules_from(
                 &sheet.rules,
                 element,
                 tree,
                 node_id,
-                Origin::Author,
+                scope,
                 &mut declarations,
                 &mut self.source_order,
                 &self.media_context,
+                &self.container_queries,
             );
         }
 
+        // Match `:host` rules of the element's own shadow tree and
+        // `::slotted()` rules of the shadow tree it is assigned into.
+        let inner_trees = [
+            tree.shadow_root(node_id).map(|shadow| (shadow.root, node_id)),
+            tree.assigned_slot(node_id).and_then(|slot| {
+                Some((tree.containing_shadow_root(slot)?, tree.parent(node_id)?))
+            }),
+        ];
+        for (root, host) in inner_trees.into_iter().flatten() {
+            let scope = RuleScope {
+                host: Some(host),
+                from_shadow: true,
+                ..RuleScope::new(Origin::Author, self.shadow_layers.get(&root).unwrap_or(&no_layers))
+            };
+            for sheet in self.shadow_sheets.get(&root).into_iter().flatten() {
+                Self::collect_matching_rules_from(
+                    &sheet.rules,
+                    element,
+                    tree,
+                    node_id,
+                    scope,
+                    &mut declarations,
+                    &mut self.source_order,
+                    &self.media_context,
+                    &self.container_queries,
+                );
+            }
+        }
+
         // Add inline styles (highest specificity)
         if let Some(style) = &element.inline_style {
             let inline_decls = css_parser::parse_style_attribute(style);
             for decl in inline_decls {
-                declarations.push(CascadedDeclaration::new(
+                let mut declaration = CascadedDeclaration::new(
                     decl,
                     css_parser::selector::Specificity::new(1, 0, 0),
                     Origin::Author,
                     self.source_order,
-                ));
+                );
+                declaration.inline = true;
+                declarations.push(declaration);
                 self.source_order += 1;
             }
         }
@@ -167,7 +420,11 @@ impl Stylist {
         let cascaded = cascade_styles(declarations);
 
         // Compute final values
-        self.compute_values(cascaded, parent_style)
+        let viewport = (self.media_context.width, self.media_context.height);
+        self.unit_container = self.container_queries.unit_basis(tree, node_id, viewport);
+        let style = self.compute_values(cascaded, parent_style);
+        self.container_queries.update(node_id, &style);
+        style
     }
 
     fn collect_matching_rules_from(
@@ -175,27 +432,48 @@ impl Stylist {
         element: &ElementData,
         tree: &DomTree,
         node_id: NodeId,
-        origin: Origin,
+        scope: RuleScope<'_>,
         declarations: &mut Vec<CascadedDeclaration>,
         source_order: &mut u32,
         media_context: &MediaContext,
+        container_queries: &ContainerQueries,
     ) {
         for rule in rules {
             match rule {
                 CssRule::Style(style_rule) => {
-                    if let Some(specificity) =
-                        match_selectors(&style_rule.selectors, element, tree, node_id)
-                    {
+                    let specificity = match scope.host {
+                        Some(host) => {
+                            match_shadow_selectors(&style_rule.selectors, element, tree, node_id, host)
+                        }
+                        None => match_selectors(&style_rule.selectors, element, tree, node_id),
+                    };
+                    if let Some(specificity) = specificity {
                         for decl in &style_rule.declarations {
-                            declarations.push(CascadedDeclaration::new(
+                            let mut declaration = CascadedDeclaration::new(
                                 decl.clone(),
                                 specificity,
-                                origin,
+                                scope.origin,
                                 *source_order,
-                            ));
+                            );
+                            declaration.from_shadow = scope.from_shadow;
+                            declaration.layer = scope.layers.position(scope.layer);
+                            declarations.push(declaration);
                             *source_order += 1;
                         }
                     }
+                    // Nested rules carry their resolved selectors, so they
+                    // are matched whether or not the parent rule matched.
+                    Self::collect_matching_rules_from(
+                        &style_rule.rules,
+                        element,
+                        tree,
+                        node_id,
+                        scope,
+                        declarations,
+                        source_order,
+                        media_context,
+                        container_queries,
+                    );
                 }
                 CssRule::Media(media_rule) => {
                     if media_rule.media.matches(media_context) {
@@ -204,24 +482,82 @@ impl Stylist {
                             element,
                             tree,
                             node_id,
-                            origin,
+                            scope,
                             declarations,
                             source_order,
                             media_context,
+                            container_queries,
                         );
                     }
                 }
-                CssRule::Supports(supports_rule) => {
-                    // For now, assume @supports conditions are met
+                CssRule::Supports(supports_rule) if supports_rule.matches() => {
                     Self::collect_matching_rules_from(
                         &supports_rule.rules,
                         element,
                         tree,
                         node_id,
-                        origin,
+                        scope,
                         declarations,
                         source_order,
                         media_context,
+                        container_queries,
+                    );
+                }
+                CssRule::Container(container_rule) => {
+                    let size = container_queries.query(
+                        tree,
+                        node_id,
+                        container_rule.name.as_deref(),
+                        container_rule.condition.queries_block_axis(),
+                    );
+                    if size.is_some_and(|size| container_rule.condition.matches(&size)) {
+                        Self::collect_matching_rules_from(
+                            &container_rule.rules,
+                            element,
+                            tree,
+                            node_id,
+                            scope,
+                            declarations,
+                            source_order,
+                            media_context,
+                            container_queries,
+                        );
+                    }
+                }
+                CssRule::LayerBlock(layer_rule) => {
+                    let layer: Vec<String> = scope.layer.iter().chain(&layer_rule.name.0).cloned().collect();
+                    Self::collect_matching_rules_from(
+                        &layer_rule.rules,
+                        element,
+                        tree,
+                        node_id,
+                        RuleScope { layer: &layer, ..scope },
+                        declarations,
+                        source_order,
+                        media_context,
+                        container_queries,
+                    );
+                }
+                CssRule::Import(import) if import.matches(media_context) => {
+                    let Some(sheet) = &import.stylesheet else {
+                        continue;
+                    };
+                    let layer: Vec<String> = scope
+                        .layer
+                        .iter()
+                        .chain(import.layer.iter().flat_map(|name| &name.0))
+                        .cloned()
+                        .collect();
+                    Self::collect_matching_rules_from(
+                        &sheet.rules,
+                        element,
+                        tree,
+                        node_id,
+                        RuleScope { layer: &layer, ..scope },
+                        declarations,
+                        source_order,
+                        media_context,
+                        container_queries,
                     );
                 }
                 _ => {}
@@ -239,8 +575,24 @@ impl Stylist {
             None => ComputedStyle::default_style(),
         };
 
+        style.custom_properties = crate::variables::compute_custom_properties(
+            &cascaded,
+            parent.map(|p| &p.custom_properties),
+        );
+
         // Apply cascaded values
         for (property, value) in cascaded {
+            if property.is_custom_property() {
+                continue;
+            }
+            let value = match value {
+                CssValue::Unparsed(css) => {
+                    // Invalid at computed-value time behaves as `unset`.
+                    crate::variables::substitute(&css, &style.custom_properties)
+                        .unwrap_or(CssValue::Unset)
+                }
+                value => value,
+            };
             self.apply_property(&mut style, &property, &value, parent);
         }
 
@@ -282,20 +634,24 @@ impl Stylist {
         use crate::computed::*;
 
         // Handle CSS-wide keywords
-        if let CssValue::Ident(ident) = value {
-            match ident.as_str() {
-                "inherit" => {
-                    if let Some(p) = parent {
-                        self.inherit_property(style, property, p);
-                    }
-                    return;
-                }
-                "initial" | "unset" | "revert" => {
-                    // Reset to initial value (handled by default style)
-                    return;
+        let keyword = match value {
+            CssValue::Ident(ident) => ident.as_str(),
+            CssValue::Inherit => "inherit",
+            CssValue::Initial | CssValue::Unset | CssValue::Revert => "initial",
+            _ => "",
+        };
+        match keyword {
+            "inherit" => {
+                if let Some(p) = parent {
+                    self.inherit_property(style, property, p);
                 }
-                _ => {}
+                return;
+            }
+            "initial" | "unset" | "revert" => {
+                // Reset to initial value (handled by default style)
+                return;
             }
+            _ => {}
         }
 
         match property {
@@ -492,6 +848,34 @@ impl Stylist {
                 style.overflow_x = overflow;
                 style.overflow_y = overflow;
             }
+            PropertyId::ContainerType => {
+                style.container_type = match value {
+                    CssValue::Ident(s) => match s.as_str() {
+                        "size" => ContainerType::Size,
+                        "inline-size" => ContainerType::InlineSize,
+                        _ => ContainerType::Normal,
+                    },
+                    _ => ContainerType::Normal,
+                };
+            }
+            PropertyId::ContainerName => {
+                let names = match value {
+                    CssValue::List(list) => list.clone(),
+                    value => vec![value.clone()],
+                };
+                style.container_name = names
+                    .into_iter()
+                    .filter_map(|name| match name {
+                        CssValue::Ident(name) if name != "none" => Some(name),
+                        _ => None,
+                    })
+                    .collect();
+            }
+            PropertyId::Container => {
+                for (longhand, value) in crate::cascade::expand_shorthand(property, value) {
+                    self.apply_property(style, &longhand, &value, parent);
+                }
+            }
             PropertyId::Visibility => {
                 style.visibility = match value {
                     CssValue::Ident(s) => match s.as_str() {
@@ -540,6 +924,18 @@ impl Stylist {
                 SizeValue::Length(px)
             }
             CssValue::Percentage(p) => SizeValue::Percentage(*p),
+            CssValue::Calc(calc) => {
+                let calc = calc.compute(&mut |value, unit| Some(self.unit_to_px(value, unit, font_size)));
+                match calc.ty() {
+                    Some(CalcType::Length | CalcType::Percentage | CalcType::LengthPercentage) => {}
+                    _ => return SizeValue::Auto,
+                }
+                match calc {
+                    CalcNode::Dimension(px, _) => SizeValue::Length(px),
+                    CalcNode::Percentage(p) => SizeValue::Percentage(p),
+                    calc => SizeValue::Calc(Box::new(calc)),
+                }
+            }
             _ => SizeValue::Auto,
         }
     }
@@ -564,6 +960,10 @@ impl Stylist {
             CssValue::Number(n) => *n,
             CssValue::Dimension(n, unit) => self.unit_to_px(*n, unit, Some(base_size)),
             CssValue::Percentage(p) => base_size * p / 100.0,
+            CssValue::Calc(calc) => calc
+                .compute(&mut |value, unit| Some(self.unit_to_px(value, unit, Some(base_size))))
+                .resolve(base_size)
+                .unwrap_or(base_size),
             _ => base_size,
         }
     }
@@ -585,6 +985,10 @@ impl Stylist {
             "vh" => value * self.media_context.height / 100.0,
             "vmin" => value * self.media_context.width.min(self.media_context.height) / 100.0,
             "vmax" => value * self.media_context.width.max(self.media_context.height) / 100.0,
+            "cqw" | "cqi" => value * self.unit_container.0 / 100.0,
+            "cqh" | "cqb" => value * self.unit_container.1 / 100.0,
+            "cqmin" => value * self.unit_container.0.min(self.unit_container.1) / 100.0,
+            "cqmax" => value * self.unit_container.0.max(self.unit_container.1) / 100.0,
             "ch" => value * font_size * 0.5, // Approximate
             "ex" => value * font_size * 0.5, // Approximate
             _ => value,
@@ -613,6 +1017,44 @@ impl Default for Stylist {
     }
 }
 
+/// Elements outside the subtree of `root` from which a relative selector
+/// with `combinators`, the leading one first, may reach into it.
+///
+/// The combinators are walked back from the subtree, which stays a
+/// starting point throughout since any of the compounds may match in it.
+fn relative_anchor_candidates(
+    tree: &DomTree,
+    root: NodeId,
+    combinators: &[Combinator],
+) -> HashSet<NodeId> {
+    let mut positions = HashSet::new();
+    for &combinator in combinators.iter().rev() {
+        let mut reached = HashSet::new();
+        for id in std::iter::once(root).chain(positions.iter().copied()) {
+            let step = |id: NodeId| match combinator {
+                Combinator::Child | Combinator::Descendant => tree.parent(id),
+                Combinator::NextSibling | Combinator::SubsequentSibling => {
+                    previous_element_sibling(tree, id)
+                }
+            };
+            let repeat = matches!(
+                combinator,
+                Combinator::Descendant | Combinator::SubsequentSibling
+            );
+            let mut current = step(id);
+            // Walks of one kind meet at the first element already reached.
+            while let Some(next) = current {
+                if !reached.insert(next) || !repeat {
+                    break;
+                }
+                current = step(next);
+            }
+        }
+        positions = reached;
+    }
+    positions
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -622,4 +1064,253 @@ mod tests {
         let stylist = Stylist::new();
         assert!(stylist.ua_sheets.is_empty());
     }
+
+    #[test]
+    fn test_shadow_tree_styles() {
+        use dom::element::{ShadowRootMode, TagName};
+        use dom::ShadowRootInit;
+
+        let url = url::Url::parse("about:blank").unwrap();
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let host = tree.create_element(ElementData::new(TagName::new("my-el")));
+        let mut span = ElementData::new(TagName::span());
+        span.set_attribute("class", "hint");
+        let span = tree.create_element(span);
+        let light_p = tree.create_element(ElementData::new(TagName::p()));
+        tree.append_child(doc, host);
+        tree.append_child(host, span);
+        tree.append_child(doc, light_p);
+
+        let root = tree.attach_shadow(host, ShadowRootInit::new(ShadowRootMode::Open)).unwrap();
+        let inner_p = tree.create_element(ElementData::new(TagName::p()));
+        let slot = tree.create_element(ElementData::new(TagName::new("slot")));
+        tree.append_child(root, inner_p);
+        tree.append_child(root, slot);
+
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            "p { font-size: 11px } my-el { font-size: 12px } span { opacity: 0.5 }",
+            url.clone(),
+        ));
+        stylist.set_shadow_stylesheets(
+            root,
+            vec![css_parser::parse_css(
+                ":host { font-size: 30px; opacity: 0.25 } :host p { font-size: 20px }                  ::slotted(.hint) { opacity: 0.75 } slot::slotted(span) { font-size: 14px }",
+                url,
+            )],
+        );
+
+        let style = |stylist: &mut Stylist, node| stylist.compute_style(&tree, node, None);
+        // Document rules stay out of the shadow tree and vice versa.
+        assert_eq!(style(&mut stylist, inner_p).font_size, 20.0);
+        assert_eq!(style(&mut stylist, light_p).font_size, 11.0);
+        // Outer rules win over `:host` rules, which still fill the gaps.
+        let host_style = style(&mut stylist, host);
+        assert_eq!(host_style.font_size, 12.0);
+        assert_eq!(host_style.opacity, 0.25);
+        // `::slotted()` rules reach assigned light children, again below
+        // the document's own rules.
+        let span_style = style(&mut stylist, span);
+        assert_eq!(span_style.font_size, 14.0);
+        assert_eq!(span_style.opacity, 0.5);
+    }
+
+    #[test]
+    fn test_var_substitution() {
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let mut outer = ElementData::new(dom::element::TagName::div());
+        outer.set_attribute("class", "outer");
+        let outer = tree.create_element(outer);
+        let inner = tree.create_element(ElementData::new(dom::element::TagName::p()));
+        tree.append_child(doc, outer);
+        tree.append_child(outer, inner);
+
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            ".outer { --size: 30px; --loop: var(--loop); width: var(--size); color: red } \
+                 p { --half: 15px; width: var(--half); height: var(--loop); font-size: var(--none, 20px); \
+                 color: var(--loop) }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+
+        let outer_style = stylist.compute_style(&tree, outer, None);
+        assert_eq!(outer_style.width, crate::computed::SizeValue::Length(30.0));
+        assert_eq!(outer_style.custom_property("--size"), Some("30px"));
+        assert_eq!(outer_style.custom_property("--loop"), None);
+
+        let inner_style = stylist.compute_style(&tree, inner, Some(&outer_style));
+        assert_eq!(inner_style.custom_property("--size"), Some("30px"));
+        assert_eq!(inner_style.width, crate::computed::SizeValue::Length(15.0));
+        assert_eq!(inner_style.height, crate::computed::SizeValue::Auto);
+        assert_eq!(inner_style.font_size, 20.0);
+        // Invalid at computed-value time, `color` inherits as `unset` does.
+        assert_eq!(inner_style.color, outer_style.color);
+    }
+
+    #[test]
+    fn test_cascade_layers() {
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let mut div = ElementData::new(dom::element::TagName::div());
+        div.set_attribute("id", "main");
+        let div = tree.create_element(div);
+        tree.append_child(doc, div);
+
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            "@layer base, utilities; \
+             @layer utilities { div { width: 10px; height: 10px !important } } \
+             @layer base { #main { width: 20px; height: 20px !important; font-size: 20px } } \
+             div { font-size: 30px; opacity: 0.5 !important } \
+             @layer { #main { opacity: 0.25 !important } }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+
+        let style = stylist.compute_style(&tree, div, None);
+        // Later layers win over specificity, earlier ones for `!important`.
+        assert_eq!(style.width, crate::computed::SizeValue::Length(10.0));
+        assert_eq!(style.height, crate::computed::SizeValue::Length(20.0));
+        // Unlayered styles win, except over important layered ones.
+        assert_eq!(style.font_size, 30.0);
+        assert_eq!(style.opacity, 0.25);
+    }
+
+    #[test]
+    fn test_nested_rules() {
+        use crate::computed::SizeValue;
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let mut div = ElementData::new(dom::element::TagName::div());
+        div.set_attribute("id", "main");
+        let div = tree.create_element(div);
+        let mut p = ElementData::new(dom::element::TagName::p());
+        p.set_attribute("class", "c");
+        let p = tree.create_element(p);
+        tree.append_child(doc, div);
+        tree.append_child(div, p);
+
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            "#main, .x { width: 1px; .c { width: 10px } } \
+             div .c { width: 20px } \
+             div { > .c { height: 20px } font-size: 30px } \
+             & { opacity: 0.5 }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+
+        let style = stylist.compute_style(&tree, p, None);
+        // `:is(#main, .x) .c` has the specificity of `#main .c`.
+        assert_eq!(style.width, SizeValue::Length(10.0));
+        assert_eq!(style.height, SizeValue::Length(20.0));
+        let style = stylist.compute_style(&tree, div, None);
+        assert_eq!(style.width, SizeValue::Length(1.0));
+        assert_eq!(style.font_size, 30.0);
+        // Outside a nested rule, `&` matches the root element.
+        assert_eq!(style.opacity, 0.5);
+    }
+
+    #[test]
+    fn test_imported_stylesheets() {
+        use crate::computed::SizeValue;
+        use css_parser::import::resolve_imports;
+        use std::collections::HashMap;
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(dom::element::TagName::div()));
+        tree.append_child(doc, div);
+
+        let url = |path: &str| url::Url::parse("https://example.com/").unwrap().join(path).unwrap();
+        let loader = HashMap::from([
+            (url("theme.css"), "div { width: 99px; opacity: 0.5 }".to_string()),
+            (url("print.css"), "div { font-size: 40px }".to_string()),
+            (url("base.css"), "div { width: 10px; height: 50px }".to_string()),
+        ]);
+        let mut sheet = css_parser::parse_css(
+            "@import 'theme.css' layer(theme); @import 'print.css' print; @import 'base.css'; \
+             div { height: 5px }",
+            url("main.css"),
+        );
+        resolve_imports(&mut sheet, &loader);
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(sheet);
+
+        let style = stylist.compute_style(&tree, div, None);
+        // Layered imports lose to unlayered ones, and imported rules come
+        // before the importing sheet's own.
+        assert_eq!(style.width, SizeValue::Length(10.0));
+        assert_eq!(style.height, SizeValue::Length(5.0));
+        assert_eq!(style.opacity, 0.5);
+        assert_ne!(style.font_size, 40.0);
+    }
+
+    #[test]
+    fn test_math_functions() {
+        use crate::computed::SizeValue;
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let div = tree.create_element(ElementData::new(dom::element::TagName::div()));
+        tree.append_child(doc, div);
+
+        let mut stylist = Stylist::new();
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            "div { width: calc(100% - 2rem); height: max(2em, 10px); min-width: calc(1px + 2); \
+                   max-width: clamp(10px, 50%, 200px); font-size: calc(1em + 2px) }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+
+        let style = stylist.compute_style(&tree, div, None);
+        assert_eq!(style.width.resolve(500.0), 468.0);
+        assert_eq!(style.height, SizeValue::Length(32.0));
+        assert!(matches!(style.max_width, SizeValue::Calc(_)));
+        assert_eq!(style.max_width.resolve(100.0), 50.0);
+        assert_eq!(style.max_width.resolve(1000.0), 200.0);
+        // Type errors drop the declaration.
+        assert_eq!(style.min_width, SizeValue::Auto);
+        assert_eq!(style.font_size, 18.0);
+    }
+
+    #[test]
+    fn test_container_queries() {
+        use crate::computed::{ContainerType, SizeValue};
+
+        let mut tree = DomTree::new();
+        let doc = tree.root().unwrap();
+        let card = tree.create_element(ElementData::new(dom::element::TagName::div()));
+        let title = tree.create_element(ElementData::new(dom::element::TagName::p()));
+        tree.append_child(doc, card);
+        tree.append_child(card, title);
+
+        let mut stylist = Stylist::new();
+        stylist.set_media_context(MediaContext { width: 1000.0, height: 800.0, ..MediaContext::default() });
+        stylist.add_author_stylesheet(css_parser::parse_css(
+            "div { container: card / inline-size } p { width: 10cqi; height: 10cqb } \
+             @container card (min-width: 400px) { p { min-width: 1px } } \
+             @container (height > 0) { p { max-width: 1px } }",
+            url::Url::parse("about:blank").unwrap(),
+        ));
+
+        let card_style = stylist.compute_style(&tree, card, None);
+        assert_eq!(card_style.container_type, ContainerType::InlineSize);
+        assert_eq!(card_style.container_name, ["card"]);
+        assert_eq!(stylist.query_containers(), [card]);
+
+        // Before layout sizes the container, queries do not match and
+        // container units use the viewport.
+        let style = stylist.compute_style(&tree, title, Some(&card_style));
+        assert_eq!(style.min_width, SizeValue::Auto);
+        assert_eq!(style.width, SizeValue::Length(100.0));
+
+        assert!(stylist.set_container_size(card, 500.0, 20.0));
+        let style = stylist.compute_style(&tree, title, Some(&card_style));
+        assert_eq!(style.min_width, SizeValue::Length(1.0));
+        assert_eq!(style.width, SizeValue::Length(50.0));
+        // An inline-size container answers neither height queries nor cqb.
+        assert_eq!(style.max_width, SizeValue::None);
+        assert_eq!(style.height, SizeValue::Length(80.0));
+    }
 }
@@ -0,0 +1,159 @@
+//! Custom properties and `var()` substitution.
+
+use css_parser::properties::PropertyId;
+use css_parser::values::CssValue;
+use css_parser::variables;
+use std::collections::{HashMap, HashSet};
+
+/// Compute the custom properties of an element from its cascaded values and
+/// the ones inherited from its parent. Properties in a reference cycle, and
+/// those referencing an invalid property without a fallback, get the
+/// guaranteed-invalid value and are left out.
+pub fn compute_custom_properties(
+    cascaded: &HashMap<PropertyId, CssValue>,
+    inherited: Option<&HashMap<String, String>>,
+) -> HashMap<String, String> {
+    let inherited = inherited.cloned().unwrap_or_default();
+    let mut specified = HashMap::new();
+    for (property, value) in cascaded {
+        let PropertyId::Custom(name) = property else {
+            continue;
+        };
+        if !property.is_custom_property() {
+            continue;
+        }
+        let css = match value {
+            CssValue::Unparsed(css) => css.clone(),
+            value => value.to_css_string(),
+        };
+        match css.to_ascii_lowercase().as_str() {
+            "inherit" | "unset" | "revert" => {}
+            "initial" => {
+                specified.insert(name.clone(), None);
+            }
+            _ => {
+                specified.insert(name.clone(), Some(css));
+            }
+        }
+    }
+
+    let mut resolver = Resolver {
+        specified,
+        inherited,
+        resolved: HashMap::new(),
+        stack: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+    let names: Vec<String> = resolver.specified.keys().cloned().collect();
+    for name in names {
+        resolver.resolve(&name);
+    }
+
+    let mut computed = resolver.inherited;
+    for (name, value) in resolver.resolved {
+        match value {
+            Some(value) => computed.insert(name, value),
+            None => computed.remove(&name),
+        };
+    }
+    computed
+}
+
+/// Substitute the `var()` references of a declaration value and parse the
+/// result. Returns `None` when the declaration is invalid at computed-value
+/// time.
+pub fn substitute(css: &str, custom_properties: &HashMap<String, String>) -> Option<CssValue> {
+    let css = variables::substitute(css, &mut |name| custom_properties.get(name).cloned())?;
+    css_parser::parse_value(&css)
+}
+
+/// Resolves references between the custom properties of one element.
+struct Resolver {
+    /// Specified values, `None` for `initial`.
+    specified: HashMap<String, Option<String>>,
+    inherited: HashMap<String, String>,
+    resolved: HashMap<String, Option<String>>,
+    /// Properties being resolved, to detect cycles.
+    stack: Vec<String>,
+    cyclic: HashSet<String>,
+}
+
+impl Resolver {
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(position) = self.stack.iter().position(|n| n == name) {
+            self.cyclic.extend(self.stack[position..].iter().cloned());
+            return None;
+        }
+        if let Some(value) = self.resolved.get(name) {
+            return value.clone();
+        }
+        let css = match self.specified.get(name) {
+            Some(Some(css)) => css.clone(),
+            Some(None) => {
+                self.resolved.insert(name.to_string(), None);
+                return None;
+            }
+            None => return self.inherited.get(name).cloned(),
+        };
+
+        self.stack.push(name.to_string());
+        let value = if variables::contains_var(&css) {
+            variables::substitute(&css, &mut |name| self.resolve(name))
+        } else {
+            Some(css)
+        };
+        self.stack.pop();
+
+        let value = value.filter(|_| !self.cyclic.contains(name));
+        self.resolved.insert(name.to_string(), value.clone());
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_custom_properties_and_cycles() {
+        let custom = |name: &str, css: &str| {
+            (
+                PropertyId::Custom(name.to_string()),
+                CssValue::Unparsed(css.to_string()),
+            )
+        };
+        let parent = HashMap::from([
+            ("--inherited".to_string(), "red".to_string()),
+            ("--reset".to_string(), "blue".to_string()),
+        ]);
+        let cascaded = HashMap::from([
+            custom("--size", "calc(var(--base) * 2)"),
+            custom("--base", "4px"),
+            custom("--a", "var(--b, 1px)"),
+            custom("--b", "var(--a)"),
+            custom("--c", "var(--a, 3px)"),
+            custom("--d", "var(--missing)"),
+            custom("--color", "var(--inherited)"),
+            custom("--reset", "initial"),
+        ]);
+
+        let computed = compute_custom_properties(&cascaded, Some(&parent));
+        assert_eq!(computed["--size"], "calc(4px * 2)");
+        assert_eq!(computed["--color"], "red");
+        // Properties in a cycle are invalid even with a fallback; the ones
+        // referencing them fall back.
+        assert!(!computed.contains_key("--a"));
+        assert!(!computed.contains_key("--b"));
+        assert_eq!(computed["--c"], "3px");
+        assert!(!computed.contains_key("--d"));
+        assert!(!computed.contains_key("--reset"));
+        assert_eq!(
+            substitute("var(--base) var(--missing, 2px)", &computed),
+            Some(CssValue::List(vec![
+                CssValue::Dimension(4.0, "px".to_string()),
+                CssValue::Dimension(2.0, "px".to_string()),
+            ]))
+        );
+        assert_eq!(substitute("var(--d)", &computed), None);
+    }
+}
@@ -1,5 +1,8 @@
 //! Mutation Observer API implementation.
 
+use dom::mutation::{Mutation, MutationKind};
+use dom::node::NodeId;
+use dom::tree::DomTree;
 use std::collections::VecDeque;
 
 /// Mutation Observer.
@@ -49,6 +52,10 @@ impl MutationObserver {
             return Err("characterDataOldValue requires characterData to be true".into());
         }
 
+        if options.attribute_filter.is_some() && !options.attributes {
+            return Err("attributeFilter requires attributes to be true".into());
+        }
+
         // Remove existing observation of this target
         self.targets.retain(|(t, _)| *t != target);
 
@@ -80,6 +87,11 @@ impl MutationObserver {
         self.targets.iter().any(|(t, _)| *t == target)
     }
 
+    /// Observed targets.
+    pub fn targets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.targets.iter().map(|(target, _)| *target)
+    }
+
     /// Get options for a target.
     pub fn get_options(&self, target: u64) -> Option<&MutationObserverInit> {
         self.targets.iter().find(|(t, _)| *t == target).map(|(_, o)| o)
@@ -94,6 +106,58 @@ impl MutationObserver {
     pub fn callback(&self) -> u64 {
         self.callback
     }
+
+    /// Get the observer ID.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Queue `record` if one of the observed targets is interested in it.
+    ///
+    /// `is_inclusive_ancestor(a, b)` tells whether node `a` contains node `b`,
+    /// for targets observed with `subtree`.
+    fn observe_record(
+        &mut self,
+        record: &MutationRecord,
+        is_inclusive_ancestor: &dyn Fn(u64, u64) -> bool,
+    ) {
+        let mut interested = false;
+        let mut old_value = false;
+        for (target, options) in &self.targets {
+            if *target != record.target
+                && !(options.subtree && is_inclusive_ancestor(*target, record.target))
+            {
+                continue;
+            }
+            match record.mutation_type {
+                MutationType::ChildList if options.child_list => interested = true,
+                MutationType::Attributes if options.attributes => {
+                    // Check attribute filter
+                    let filtered = match (&options.attribute_filter, &record.attribute_name) {
+                        (Some(filter), Some(name)) => !filter.contains(name),
+                        _ => false,
+                    };
+                    if !filtered {
+                        interested = true;
+                        old_value |= options.attribute_old_value;
+                    }
+                }
+                MutationType::CharacterData if options.character_data => {
+                    interested = true;
+                    old_value |= options.character_data_old_value;
+                }
+                _ => {}
+            }
+        }
+
+        if interested {
+            let mut record = record.clone();
+            if !old_value {
+                record.old_value = None;
+            }
+            self.queue_record(record);
+        }
+    }
 }
 
 /// Mutation observer initialization options.
@@ -248,6 +312,23 @@ impl MutationRecord {
     }
 }
 
+impl From<&Mutation> for MutationRecord {
+    fn from(mutation: &Mutation) -> Self {
+        let raw = |nodes: &[NodeId]| nodes.iter().map(|node| node.to_raw()).collect();
+        Self {
+            mutation_type: mutation.kind.into(),
+            target: mutation.target.to_raw(),
+            added_nodes: raw(&mutation.added_nodes),
+            removed_nodes: raw(&mutation.removed_nodes),
+            previous_sibling: mutation.previous_sibling.map(|node| node.to_raw()),
+            next_sibling: mutation.next_sibling.map(|node| node.to_raw()),
+            attribute_name: mutation.attribute_name.clone(),
+            attribute_namespace: None,
+            old_value: mutation.old_value.clone(),
+        }
+    }
+}
+
 /// Mutation type.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MutationType {
@@ -256,6 +337,16 @@ pub enum MutationType {
     CharacterData,
 }
 
+impl From<MutationKind> for MutationType {
+    fn from(kind: MutationKind) -> Self {
+        match kind {
+            MutationKind::ChildList => MutationType::ChildList,
+            MutationKind::Attributes => MutationType::Attributes,
+            MutationKind::CharacterData => MutationType::CharacterData,
+        }
+    }
+}
+
 impl std::fmt::Display for MutationType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -284,56 +375,48 @@ impl MutationObserverController {
         self.observers.push(observer);
     }
 
-    /// Notify observers of a mutation.
-    pub fn notify(&mut self, record: MutationRecord) {
-        for observer in &mut self.observers {
-            if !observer.is_connected() {
-                continue;
-            }
+    /// Unregister an observer, dropping its pending records.
+    pub fn unregister(&mut self, id: u64) {
+        self.observers.retain(|observer| observer.id != id);
+    }
 
-            // Check if this observer is interested in this target
-            let interested = observer.targets.iter().any(|(target, options)| {
-                if *target != record.target {
-                    // Check subtree
-                    if !options.subtree {
-                        return false;
-                    }
-                    // Would need to check if record.target is descendant of target
-                }
+    /// Get a registered observer.
+    pub fn observer_mut(&mut self, id: u64) -> Option<&mut MutationObserver> {
+        self.observers.iter_mut().find(|observer| observer.id == id)
+    }
 
-                match record.mutation_type {
-                    MutationType::ChildList => options.child_list,
-                    MutationType::Attributes => {
-                        if !options.attributes {
-                            return false;
-                        }
-                        // Check attribute filter
-                        if let Some(ref filter) = options.attribute_filter {
-                            if let Some(ref attr_name) = record.attribute_name {
-                                return filter.contains(attr_name);
-                            }
-                        }
-                        true
-                    }
-                    MutationType::CharacterData => options.character_data,
-                }
-            });
+    /// Notify observers of a mutation.
+    ///
+    /// Without the tree, `subtree` observations only see their own target.
+    pub fn notify(&mut self, record: MutationRecord) {
+        self.queue(&record, &|ancestor, node| ancestor == node);
+    }
 
-            if interested {
-                observer.queue_record(record.clone());
-            }
-        }
+    /// Notify observers of a mutation reported by a [`DomTree`] hook.
+    pub fn notify_mutation(&mut self, tree: &DomTree, mutation: &Mutation) {
+        let is_inclusive_ancestor = |ancestor: u64, node: u64| {
+            tree.is_inclusive_ancestor(NodeId::from_raw(ancestor), NodeId::from_raw(node))
+        };
+        self.queue(&MutationRecord::from(mutation), &is_inclusive_ancestor);
     }
 
-    /// Invoke callbacks for pending records.
-    pub fn flush(&mut self) {
+    fn queue(&mut self, record: &MutationRecord, is_inclusive_ancestor: &dyn Fn(u64, u64) -> bool) {
         for observer in &mut self.observers {
-            let records = observer.take_records();
-            if !records.is_empty() {
-                // Would invoke callback with records
+            if observer.is_connected() {
+                observer.observe_record(record, is_inclusive_ancestor);
             }
         }
     }
+
+    /// Take the pending records of every observer that has some, in
+    /// registration order, as `(callback, records)` pairs to invoke.
+    pub fn flush(&mut self) -> Vec<(u64, Vec<MutationRecord>)> {
+        self.observers
+            .iter_mut()
+            .filter(|observer| !observer.pending_records.is_empty())
+            .map(|observer| (observer.callback, observer.take_records()))
+            .collect()
+    }
 }
 
 impl Default for MutationObserverController {
@@ -407,4 +490,50 @@ mod tests {
         let records = observer.take_records();
         assert!(records.is_empty());
     }
+
+    #[test]
+    fn test_controller_delivers_tree_mutations() {
+        use dom::element::{ElementData, TagName};
+        use parking_lot::Mutex;
+        use std::sync::Arc;
+
+        let mut tree = DomTree::new();
+        let root = tree.root().unwrap();
+        let list = tree.create_element(ElementData::new(TagName::ul()));
+        let item = tree.create_element(ElementData::new(TagName::li()));
+        tree.append_child(root, list);
+
+        let mut observer = MutationObserver::new(7);
+        let options = MutationObserverInit::new()
+            .child_list()
+            .subtree()
+            .attribute_filter(vec!["id".into()]);
+        observer.observe(list.to_raw(), options).unwrap();
+        let controller = Arc::new(Mutex::new(MutationObserverController::new()));
+        controller.lock().register(observer);
+        let hook = controller.clone();
+        tree.add_mutation_hook(Arc::new(move |tree: &DomTree, mutation: &Mutation| {
+            hook.lock().notify_mutation(tree, mutation)
+        }));
+
+        tree.append_child(list, item);
+        tree.get_element_mut(item).unwrap().set_attribute("class", "x");
+        tree.get_element_mut(item).unwrap().set_attribute("id", "a");
+        tree.get_element_mut(item).unwrap().set_attribute("id", "b");
+        let outside = tree.create_comment("outside".into());
+        tree.append_child(root, outside);
+        tree.flush_mutations();
+
+        let pending = controller.lock().flush();
+        assert_eq!(pending.len(), 1);
+        let (callback, records) = &pending[0];
+        assert_eq!(*callback, 7);
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].mutation_type, MutationType::ChildList);
+        assert_eq!(records[0].added_nodes, vec![item.to_raw()]);
+        assert_eq!(records[2].attribute_name.as_deref(), Some("id"));
+        // Old values are only kept when asked for.
+        assert_eq!(records[2].old_value, None);
+        assert!(controller.lock().flush().is_empty());
+    }
 }